@@ -0,0 +1,78 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use serde_zero_copy::parse::preamble_len;
+use serde_zero_copy::Value;
+use proxy::tenant;
+use proxy::transform::{Context, Transforms};
+
+/// A tenant [`tenant::Pipeline`] for Python, to run captured payloads through offline what the
+/// proxy would serve them as:
+///
+/// ```python
+/// from hyper_zero_copy import Pipeline
+/// pipeline = Pipeline(open("tenants/acme.json").read())
+/// served = pipeline.apply(payload, query="page=2", accept_language="de")
+/// ```
+///
+/// There's no identity offline, so `claims` steps leave payloads as they are.
+#[pyclass(frozen)]
+pub struct Pipeline {
+    transforms: Transforms,
+}
+
+impl Pipeline {
+    /// What [`Pipeline::apply`] serves, parsed and written as the proxy does.
+    pub fn serve(&self, payload: &[u8], cx: &Context) -> Result<Vec<u8>, serde_json_nostr::Error> {
+        let value: Value = serde_json_nostr::from_slice(&payload[preamble_len(payload)..])?;
+        serde_json_nostr::to_vec(&self.transforms.apply(value, cx))
+    }
+}
+
+#[pymethods]
+impl Pipeline {
+    /// From the JSON of a tenant file, `{"transforms": [...]}`.
+    #[new]
+    fn new(config: &str) -> PyResult<Self> {
+        let pipeline: tenant::Pipeline = serde_json::from_str(config).map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(Pipeline { transforms: pipeline.build() })
+    }
+
+    /// `payload` as it'd be served for a request with these, JSON bytes in and out; the GIL
+    /// is released meanwhile.
+    #[pyo3(signature = (payload, query = None, accept_language = None, origin = None, request_id = None))]
+    fn apply<'py>(
+        &self,
+        py: Python<'py>,
+        payload: &[u8],
+        query: Option<&str>,
+        accept_language: Option<&str>,
+        origin: Option<&str>,
+        request_id: Option<&str>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let cx = Context { query, request_id, identity: None, origin, accept_language };
+        let served = py.allow_threads(|| self.serve(payload, &cx)).map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(PyBytes::new(py, &served))
+    }
+}
+
+#[pymodule]
+fn hyper_zero_copy(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Pipeline>()
+}
+
+#[cfg(test)]
+mod tests {
+    use proxy::tenant;
+    use proxy::transform::Context;
+    use super::Pipeline;
+
+    #[test]
+    fn serves_like_the_proxy() {
+        let config: tenant::Pipeline = serde_json::from_str(r#"{"transforms": [{"mask": ["/email"]}, {"paginate": {"per_page": 1}}]}"#).unwrap();
+        let pipeline = Pipeline { transforms: config.build() };
+        let served = pipeline.serve(b"\xef\xbb\xbf[{\"id\":1,\"email\":\"a@b\"},{\"id\":2}]", &Context::query(Some("page=2"))).unwrap();
+        assert_eq!(String::from_utf8(served).unwrap(), r#"{"data":[{"id":2}],"next":null,"page":2,"total":2}"#);
+        assert!(pipeline.serve(b"{", &Context::default()).is_err());
+    }
+}
@@ -0,0 +1,24 @@
+# Smoke test of the extension module, once it's installed with `maturin develop`:
+#
+#     python -m unittest discover -s tests/python
+import json
+import unittest
+
+from hyper_zero_copy import Pipeline
+
+
+class PipelineTest(unittest.TestCase):
+    def test_serves_like_the_proxy(self):
+        pipeline = Pipeline(json.dumps({"transforms": [{"mask": ["/email"]}, {"paginate": {"per_page": 1}}]}))
+        served = pipeline.apply(b'[{"id":1,"email":"a@b"},{"id":2}]', query="page=2")
+        self.assertEqual(json.loads(served), {"data": [{"id": 2}], "next": None, "page": 2, "total": 2})
+
+    def test_bad_input_raises(self):
+        with self.assertRaises(ValueError):
+            Pipeline("{")
+        with self.assertRaises(ValueError):
+            Pipeline(json.dumps({"transforms": []})).apply(b"{")
+
+
+if __name__ == "__main__":
+    unittest.main()
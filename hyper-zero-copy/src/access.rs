@@ -0,0 +1,180 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use axum::body::{Body, HttpBody};
+use axum::extract::State;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::Serialize;
+use serde_zero_copy::Stats;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::forward::RequestId;
+
+/// Put in a request's extensions by [`log`], so that `/zc` works out its [`ZeroCopyStats`]
+/// only when they're logged.
+#[derive(Debug, Clone, Copy)]
+pub struct CollectStats;
+
+/// How `/zc` got what it served, put in the response's extensions.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ZeroCopyStats {
+    /// Fetching the upstream body, `None` when it came from the cache.
+    pub upstream_ms: Option<f64>,
+    /// Until the first chunk of the body, the rest of `upstream_ms` went to receiving it.
+    pub upstream_first_chunk_ms: Option<f64>,
+    pub upstream_chunks: Option<u64>,
+    pub upstream_bytes: usize,
+    /// Of the document as parsed, before any transform.
+    #[serde(flatten)]
+    pub parsed: Stats,
+    pub borrowed_ratio: f64,
+}
+
+#[derive(Serialize)]
+struct Line<'r> {
+    timestamp_ms: u64,
+    method: &'r str,
+    path: &'r str,
+    status: u16,
+    /// Behind [`crate::forward::headers`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<&'r str>,
+    latency_ms: f64,
+    /// For `/zc` how much serializing wrote, unknown for streamed bodies.
+    response_bytes: Option<u64>,
+    #[serde(flatten)]
+    zero_copy: Option<ZeroCopyStats>,
+}
+
+/// Writes a JSON line per request, e.g. `{"timestamp_ms":…,"method":"GET","path":"/zc",
+/// "status":200,"latency_ms":1.2,"response_bytes":5120,"upstream_ms":0.9,
+/// "upstream_first_chunk_ms":0.4,"upstream_chunks":2,"upstream_bytes":5230,"nodes":310,
+/// "borrowed":120,"owned":3,"borrowed_ratio":0.97}`. Lines are written by a task of their
+/// own, up to `queue` of them wait for it and those beyond are dropped, so that a slow `out`
+/// doesn't hold up requests.
+pub struct AccessLog {
+    tx: mpsc::Sender<Vec<u8>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl AccessLog {
+    pub fn spawn(out: impl Write + Send + 'static, queue: usize) -> (Self, JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel(queue.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let writer = tokio::task::spawn_blocking({
+            let dropped = dropped.clone();
+            move || write_lines(out, rx, dropped)
+        });
+        (AccessLog { tx, dropped }, writer)
+    }
+
+    pub fn stdout(queue: usize) -> (Self, JoinHandle<()>) {
+        AccessLog::spawn(std::io::stdout(), queue)
+    }
+
+    /// Lines dropped so far because the queue was full or they couldn't be written.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn write(&self, line: &Line) {
+        let Ok(mut json) = serde_json::to_vec(line) else { return };
+        json.push(b'\n');
+        // a log that can't be written mustn't fail the request
+        if self.tx.try_send(json).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// writes what's waiting at once, flushed before waiting for more
+fn write_lines(mut out: impl Write, mut rx: mpsc::Receiver<Vec<u8>>, dropped: Arc<AtomicU64>) {
+    while let Some(line) = rx.blocking_recv() {
+        let mut lines = vec![line];
+        while let Ok(line) = rx.try_recv() {
+            lines.push(line);
+        }
+        if lines.iter().try_for_each(|line| out.write_all(line)).and_then(|()| out.flush()).is_err() {
+            dropped.fetch_add(lines.len() as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Middleware for `axum::middleware::from_fn_with_state`.
+pub async fn log(State(log): State<Arc<AccessLog>>, mut request: Request<Body>, next: Next<Body>) -> Response {
+    let start = Instant::now();
+    let (method, path) = (request.method().to_string(), request.uri().path().to_string());
+    request.extensions_mut().insert(CollectStats);
+    let request_id = request.extensions().get::<RequestId>().cloned();
+    let response = next.run(request).await;
+    log.write(&Line {
+        timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64),
+        method: &method,
+        path: &path,
+        status: response.status().as_u16(),
+        request_id: request_id.as_ref().map(|RequestId(id)| id.as_str()),
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        response_bytes: response.body().size_hint().exact(),
+        zero_copy: response.extensions().get::<ZeroCopyStats>().copied(),
+    });
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use hyper::{Client, Uri};
+    use crate::mock::{self, Fixtures};
+    use super::{log, AccessLog};
+
+    #[derive(Clone, Default)]
+    struct Lines(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn access_log_zero_copy_stats() {
+        let body = r#"{"name":"plain","note":"line\nbreak","tags":["a","b"],"n":1}"#;
+        let upstream_addr = mock::spawn(mock::router(Fixtures::default().with("doc", body)));
+
+        let lines = Lines::default();
+        let uri = Uri::try_from(format!("http://{}/doc", upstream_addr)).unwrap();
+        let app = crate::proxy::router(Arc::new(Client::new()), uri)
+            .layer(axum::middleware::from_fn_with_state(Arc::new(AccessLog::spawn(lines.clone(), 16).0), log));
+        let addr = mock::spawn(app);
+
+        for path in ["/zc", "/serde"] {
+            let response = Client::new().get(Uri::try_from(format!("http://{}{}", addr, path)).unwrap()).await.unwrap();
+            hyper::body::to_bytes(response).await.unwrap();
+        }
+        // written as the writer gets to them
+        while lines.0.lock().unwrap().iter().filter(|b| **b == b'\n').count() < 2 {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        let written = String::from_utf8(lines.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = written.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        let zc = &lines[0];
+        assert_eq!((zc["path"].as_str(), zc["status"].as_u64()), (Some("/zc"), Some(200)));
+        assert_eq!((zc["nodes"].as_u64(), zc["borrowed"].as_u64(), zc["owned"].as_u64()), (Some(7), Some(3), Some(1)));
+        assert_eq!(zc["borrowed_ratio"], 0.75);
+        assert_eq!(zc["upstream_bytes"].as_u64(), Some(body.len() as u64));
+        assert_eq!(zc["response_bytes"].as_u64(), Some(body.len() as u64));
+        assert!(zc["upstream_ms"].is_f64());
+        assert!(zc["upstream_chunks"].as_u64().is_some_and(|chunks| chunks > 0));
+        assert_eq!(lines[1]["path"], "/serde");
+        assert!(lines[1].get("nodes").is_none());
+    }
+}
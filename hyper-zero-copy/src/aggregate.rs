@@ -0,0 +1,253 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use axum::body::HttpBody;
+use bytes::{Bytes, BytesMut};
+use hyper::Body;
+use serde::Serialize;
+
+#[derive(Debug)]
+pub enum Error {
+    Hyper(hyper::Error),
+    ChunkTooLarge { len: usize, max: usize },
+    /// At `received` bytes, or announced by `Content-Length` before any were.
+    TooLarge { received: u64, max: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Hyper(err) => write!(f, "{}", err),
+            Error::ChunkTooLarge { len, max } => write!(f, "chunk of {} bytes is over the limit of {}", len, max),
+            Error::TooLarge { received, max } => write!(f, "body of {} bytes is over the limit of {}", received, max),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<hyper::Error> for Error {
+    fn from(err: hyper::Error) -> Self {
+        Error::Hyper(err)
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct AggregateMetrics {
+    /// Bodies being received.
+    pub in_flight: AtomicU64,
+    /// Received so far of the bodies in flight.
+    pub in_flight_bytes: AtomicU64,
+    pub completed: AtomicU64,
+    /// Bodies given up for breaking a limit.
+    pub rejected: AtomicU64,
+}
+
+// takes a body and its bytes off the gauges however aggregating ends, the request may be
+// dropped halfway
+struct InFlight<'m> {
+    metrics: &'m AggregateMetrics,
+    bytes: u64,
+}
+
+impl InFlight<'_> {
+    fn add(&mut self, len: usize) {
+        self.bytes += len as u64;
+        self.metrics.in_flight_bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.metrics.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.metrics.in_flight_bytes.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+/// How far along a body is, passed after each chunk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub received: u64,
+    /// From `Content-Length`.
+    pub expected: Option<u64>,
+    pub chunks: u64,
+    pub first_chunk: Option<Duration>,
+    pub elapsed: Duration,
+}
+
+/// Collects upstream bodies a chunk at a time, the next read only once the last chunk is
+/// taken, so a body over its limits stops being read as soon as that shows, before the rest
+/// is buffered. Added as an `Extension` layer, of bodies of up to 64MiB by default, which is
+/// what `/zc` goes by without one.
+#[derive(Debug, Clone)]
+pub struct Aggregator {
+    pub max_chunk: usize,
+    pub max_total: usize,
+    metrics: Arc<AggregateMetrics>,
+}
+
+impl Default for Aggregator {
+    fn default() -> Self {
+        Aggregator::new(usize::MAX, 64 << 20)
+    }
+}
+
+impl Aggregator {
+    pub fn new(max_chunk: usize, max_total: usize) -> Self {
+        Aggregator { max_chunk, max_total, metrics: Arc::default() }
+    }
+
+    pub fn metrics(&self) -> &AggregateMetrics {
+        &self.metrics
+    }
+
+    /// The whole of `body`, a body of a single chunk without copying it.
+    pub async fn aggregate(&self, mut body: Body, mut on_progress: impl FnMut(&Progress)) -> Result<(Bytes, Progress), Error> {
+        let started = Instant::now();
+        let expected = body.size_hint().exact();
+        if let Some(len) = expected.filter(|&len| len > self.max_total as u64) {
+            return Err(self.reject(Error::TooLarge { received: len, max: self.max_total }));
+        }
+        self.metrics.in_flight.fetch_add(1, Ordering::Relaxed);
+        let mut in_flight = InFlight { metrics: &self.metrics, bytes: 0 };
+        let mut progress = Progress { received: 0, expected, chunks: 0, first_chunk: None, elapsed: Duration::ZERO };
+        let mut first = None;
+        let mut rest = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk?;
+            if chunk.is_empty() {
+                continue;
+            }
+            if chunk.len() > self.max_chunk {
+                return Err(self.reject(Error::ChunkTooLarge { len: chunk.len(), max: self.max_chunk }));
+            }
+            let received = progress.received + chunk.len() as u64;
+            if received > self.max_total as u64 {
+                return Err(self.reject(Error::TooLarge { received, max: self.max_total }));
+            }
+            in_flight.add(chunk.len());
+            match first.take() {
+                None if rest.is_empty() => first = Some(chunk),
+                None => rest.extend_from_slice(&chunk),
+                Some(first) => {
+                    rest.reserve(expected.map_or(0, |len| len as usize).max(received as usize));
+                    rest.extend_from_slice(&first);
+                    rest.extend_from_slice(&chunk);
+                }
+            }
+            progress.received = received;
+            progress.chunks += 1;
+            progress.elapsed = started.elapsed();
+            progress.first_chunk.get_or_insert(progress.elapsed);
+            on_progress(&progress);
+        }
+        self.metrics.completed.fetch_add(1, Ordering::Relaxed);
+        Ok((first.unwrap_or_else(|| rest.freeze()), progress))
+    }
+
+    fn reject(&self, err: Error) -> Error {
+        self.metrics.rejected.fetch_add(1, Ordering::Relaxed);
+        err
+    }
+}
+
+pub(crate) enum Read<B> {
+    Whole(Bytes),
+    // over the limit by its length, as it came
+    Unread(B),
+    // over the limit partway, what was read of it comes first
+    Over(Body),
+}
+
+/// The whole of `body` if it's no longer than `max`, otherwise all of it still to be read.
+pub(crate) async fn read_within<B>(mut body: B, max: usize) -> Result<Read<B>, axum::Error>
+    where
+        B: HttpBody<Data = Bytes> + Send + Unpin + 'static,
+        B::Error: Into<axum::BoxError> + Send,
+{
+    if body.size_hint().lower() > max as u64 {
+        return Ok(Read::Unread(body));
+    }
+    let mut read = BytesMut::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(axum::Error::new)?;
+        if read.len() + chunk.len() <= max {
+            read.extend_from_slice(&chunk);
+            continue;
+        }
+        let (mut tx, over) = Body::channel();
+        let read = read.freeze();
+        tokio::spawn(async move {
+            for chunk in [read, chunk] {
+                if tx.send_data(chunk).await.is_err() {
+                    return;
+                }
+            }
+            while let Some(chunk) = body.data().await {
+                let Ok(chunk) = chunk else {
+                    return tx.abort();
+                };
+                if tx.send_data(chunk).await.is_err() {
+                    return;
+                }
+            }
+        });
+        return Ok(Read::Over(over));
+    }
+    Ok(Read::Whole(read.freeze()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use bytes::Bytes;
+    use hyper::Body;
+    use super::{Aggregator, Error};
+
+    fn chunked(chunks: &[&'static str]) -> Body {
+        let (mut sender, body) = Body::channel();
+        let chunks = chunks.to_vec();
+        tokio::spawn(async move {
+            for chunk in chunks {
+                if sender.send_data(Bytes::from_static(chunk.as_bytes())).await.is_err() {
+                    return;
+                }
+            }
+        });
+        body
+    }
+
+    #[tokio::test]
+    async fn aggregates_with_progress() {
+        let aggregator = Aggregator::default();
+        let mut seen = Vec::new();
+        let (body, progress) = aggregator.aggregate(chunked(&["[1,", "", "2,", "3]"]), |p| seen.push(p.received)).await.unwrap();
+        assert_eq!(body, "[1,2,3]");
+        assert_eq!(seen, [3, 5, 7]);
+        assert_eq!((progress.chunks, progress.expected), (3, None));
+        assert!(progress.first_chunk.is_some_and(|first| first <= progress.elapsed));
+
+        let single = Bytes::from_static(b"{}");
+        let (body, _) = aggregator.aggregate(Body::from(single.clone()), |_| {}).await.unwrap();
+        assert_eq!(body.as_ptr(), single.as_ptr());
+        assert_eq!(aggregator.metrics().completed.load(Ordering::Relaxed), 2);
+        assert_eq!(aggregator.metrics().in_flight.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn enforces_limits() {
+        assert_eq!(Aggregator::default().max_total, 64 << 20);
+        let aggregator = Aggregator::new(4, 6);
+        let err = aggregator.aggregate(chunked(&["[1,", "22222]"]), |_| {}).await.unwrap_err();
+        assert!(matches!(err, Error::ChunkTooLarge { len: 6, max: 4 }));
+        let err = aggregator.aggregate(chunked(&["[1,", "2,", "3]"]), |_| {}).await.unwrap_err();
+        assert!(matches!(err, Error::TooLarge { received: 7, max: 6 }));
+        let err = aggregator.aggregate(Body::from("[1,2,3]"), |_| {}).await.unwrap_err();
+        assert!(matches!(err, Error::TooLarge { received: 7, max: 6 }));
+        let metrics = aggregator.metrics();
+        assert_eq!(metrics.rejected.load(Ordering::Relaxed), 3);
+        assert_eq!((metrics.in_flight.load(Ordering::Relaxed), metrics.in_flight_bytes.load(Ordering::Relaxed)), (0, 0));
+        assert_eq!(serde_json::json!(metrics)["rejected"], 3);
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+pub const X_API_KEY: &str = "x-api-key";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Missing,
+    UnknownKey,
+    Malformed(&'static str),
+    /// Anything but HMAC, `none` included.
+    Algorithm(String),
+    Signature,
+    Expired,
+    NotYetValid,
+    Issuer,
+    Audience,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Missing => write!(f, "no API key or bearer token"),
+            Error::UnknownKey => write!(f, "unknown API key"),
+            Error::Malformed(why) => write!(f, "malformed token: {}", why),
+            Error::Algorithm(alg) => write!(f, "token algorithm {:?} isn't accepted", alg),
+            Error::Signature => write!(f, "token signature doesn't match"),
+            Error::Expired => write!(f, "token expired"),
+            Error::NotYetValid => write!(f, "token not valid yet"),
+            Error::Issuer => write!(f, "token from another issuer"),
+            Error::Audience => write!(f, "token for another audience"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let challenge = match self {
+            Error::Missing => "Bearer",
+            _ => "Bearer error=\"invalid_token\"",
+        };
+        (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, challenge)], self.to_string()).into_response()
+    }
+}
+
+/// Who a request is from, put in its extensions by [`check`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Identity {
+    /// `sub` of a token, or the name an API key was given.
+    pub subject: String,
+    /// Of a token, none for an API key.
+    pub claims: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Identity {
+    /// `sub` is there for API keys too.
+    pub fn claim(&self, name: &str) -> Option<serde_json::Value> {
+        match self.claims.get(name) {
+            Some(value) => Some(value.clone()),
+            None if name == "sub" => Some(serde_json::Value::String(self.subject.clone())),
+            None => None,
+        }
+    }
+}
+
+/// Validates JWTs signed with `secret` by HS256, HS384 or HS512, along with their `exp`, which
+/// they're to have, and `nbf`, and `iss` and `aud` when those are set. Clocks may be `leeway`
+/// apart.
+#[derive(Clone)]
+pub struct Jwt {
+    secret: Vec<u8>,
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    pub leeway: Duration,
+}
+
+impl fmt::Debug for Jwt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Jwt").field("issuer", &self.issuer).field("audience", &self.audience).finish_non_exhaustive()
+    }
+}
+
+fn verify<M: Mac + KeyInit>(secret: &[u8], signed: &[u8], signature: &[u8]) -> bool {
+    let mut mac = <M as KeyInit>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    mac.update(signed);
+    mac.verify_slice(signature).is_ok()
+}
+
+fn decode(part: &str) -> Result<Vec<u8>, Error> {
+    URL_SAFE_NO_PAD.decode(part).map_err(|_| Error::Malformed("not base64url"))
+}
+
+impl Jwt {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Jwt { secret: secret.into(), issuer: None, audience: None, leeway: Duration::from_secs(30) }
+    }
+
+    /// An HS256 token of `claims`.
+    pub fn sign(&self, claims: &serde_json::Value) -> String {
+        let header = URL_SAFE_NO_PAD.encode(br#"{"alg":"HS256","typ":"JWT"}"#);
+        let signed = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(claims.to_string()));
+        let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(&self.secret).expect("HMAC takes keys of any length");
+        mac.update(signed.as_bytes());
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+    }
+
+    pub fn validate(&self, token: &str) -> Result<Identity, Error> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+            return Err(Error::Malformed("not three parts"));
+        };
+        let header: serde_json::Value = serde_json::from_slice(&decode(header)?).map_err(|_| Error::Malformed("header isn't JSON"))?;
+        let signed = &token.as_bytes()[..token.len() - signature.len() - 1];
+        let signature = decode(signature)?;
+        let valid = match header["alg"].as_str().unwrap_or_default() {
+            "HS256" => verify::<Hmac<Sha256>>(&self.secret, signed, &signature),
+            "HS384" => verify::<Hmac<Sha384>>(&self.secret, signed, &signature),
+            "HS512" => verify::<Hmac<Sha512>>(&self.secret, signed, &signature),
+            other => return Err(Error::Algorithm(other.to_string())),
+        };
+        if !valid {
+            return Err(Error::Signature);
+        }
+        let serde_json::Value::Object(claims) = serde_json::from_slice(&decode(payload)?).map_err(|_| Error::Malformed("claims aren't JSON"))? else {
+            return Err(Error::Malformed("claims aren't an object"));
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        // seconds since the epoch, what can't be one is rejected rather than taken for never
+        let at = |name: &str| match claims.get(name) {
+            Some(time) => time.as_f64().and_then(|secs| Duration::try_from_secs_f64(secs).ok()).map(Some).ok_or(Error::Malformed("time isn't seconds since the epoch")),
+            None => Ok(None),
+        };
+        let exp = at("exp")?.ok_or(Error::Malformed("no exp"))?;
+        match exp.checked_add(self.leeway) {
+            Some(until) if now <= until => {}
+            Some(_) => return Err(Error::Expired),
+            None => return Err(Error::Malformed("time isn't seconds since the epoch")),
+        }
+        if at("nbf")?.is_some_and(|nbf| now.saturating_add(self.leeway) < nbf) {
+            return Err(Error::NotYetValid);
+        }
+        if self.issuer.as_ref().is_some_and(|issuer| claims.get("iss").and_then(|iss| iss.as_str()) != Some(issuer)) {
+            return Err(Error::Issuer);
+        }
+        if let Some(audience) = &self.audience {
+            let listed = match claims.get("aud") {
+                Some(serde_json::Value::String(aud)) => aud == audience,
+                Some(serde_json::Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !listed {
+                return Err(Error::Audience);
+            }
+        }
+        let subject = claims.get("sub").and_then(|sub| sub.as_str()).unwrap_or_default().to_string();
+        Ok(Identity { subject, claims })
+    }
+}
+
+/// The token of an `Authorization` value of the `Bearer` scheme, whatever its case.
+pub fn bearer(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.trim_start().split_once(' ')?;
+    Some(token.trim()).filter(|_| scheme.eq_ignore_ascii_case("bearer"))
+}
+
+/// Whether `headers` bear `token` as an `Authorization: Bearer`, as the admin endpoints want,
+/// never when it's empty. Compared by their SHA-256 in constant time, so how long it takes
+/// doesn't tell how much of a guess was right.
+pub fn bears_token(headers: &HeaderMap, token: &str) -> bool {
+    let given = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()).and_then(bearer);
+    let Some(given) = given.filter(|_| !token.is_empty()) else {
+        return false;
+    };
+    let (given, token) = (Sha256::digest(given.as_bytes()), Sha256::digest(token.as_bytes()));
+    given.iter().zip(token.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Lets in requests with a known `X-Api-Key` or a valid `Authorization: Bearer` JWT, and
+/// answers others with 401. Keys are held and looked up by their SHA-256, so neither the
+/// table nor how long a lookup takes gives them away. Selected claims can be sent upstream
+/// as headers, those a client sent of the same name are dropped.
+#[derive(Debug, Default)]
+pub struct Auth {
+    api_keys: HashMap<[u8; 32], String>,
+    jwt: Option<Jwt>,
+    claim_headers: Vec<(String, HeaderName)>,
+}
+
+impl Auth {
+    pub fn with_api_key(mut self, name: &str, key: &str) -> Self {
+        self.api_keys.insert(Sha256::digest(key.as_bytes()).into(), name.to_string());
+        self
+    }
+
+    /// Comma separated `name=key`.
+    pub fn with_api_keys(self, keys: &str) -> Self {
+        keys.split(',')
+            .filter_map(|pair| pair.split_once('='))
+            .fold(self, |auth, (name, key)| auth.with_api_key(name.trim(), key.trim()))
+    }
+
+    pub fn with_jwt(mut self, jwt: Jwt) -> Self {
+        self.jwt = Some(jwt);
+        self
+    }
+
+    /// Sends `claim` as `header`, e.g. `sub` as `X-User`.
+    pub fn with_claim_header(mut self, claim: &str, header: HeaderName) -> Self {
+        self.claim_headers.push((claim.to_string(), header));
+        self
+    }
+
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Identity, Error> {
+        if let Some(key) = headers.get(X_API_KEY) {
+            let digest: [u8; 32] = Sha256::digest(key.as_bytes()).into();
+            let name = self.api_keys.get(&digest).ok_or(Error::UnknownKey)?;
+            return Ok(Identity { subject: name.clone(), ..Identity::default() });
+        }
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(bearer)
+            .ok_or(Error::Missing)?;
+        self.jwt.as_ref().ok_or(Error::Missing)?.validate(token.trim())
+    }
+
+    /// The headers of [`Auth::with_claim_header`] for `identity`, claims it lacks are left out.
+    pub fn claim_headers(&self, identity: &Identity) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (claim, name) in &self.claim_headers {
+            let value = match identity.claim(claim) {
+                Some(serde_json::Value::String(value)) => value,
+                Some(serde_json::Value::Null) | None => continue,
+                Some(other) => other.to_string(),
+            };
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name.clone(), value);
+            }
+        }
+        headers
+    }
+}
+
+/// The headers [`check`] adds to a request for its upstream.
+#[derive(Debug, Clone, Default)]
+pub struct ClaimHeaders(pub HeaderMap);
+
+/// Middleware for `axum::middleware::from_fn_with_state`. Puts the [`Identity`] and its
+/// [`ClaimHeaders`] in the request's extensions.
+pub async fn check(State(auth): State<Arc<Auth>>, mut request: Request<Body>, next: Next<Body>) -> Response {
+    let identity = match auth.authenticate(request.headers()) {
+        Ok(identity) => identity,
+        Err(err) => return err.into_response(),
+    };
+    let claim_headers = auth.claim_headers(&identity);
+    let headers = request.headers_mut();
+    for (_, name) in &auth.claim_headers {
+        headers.remove(name);
+    }
+    headers.extend(claim_headers.clone());
+    request.extensions_mut().insert(identity);
+    request.extensions_mut().insert(ClaimHeaders(claim_headers));
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use axum::http::{header, HeaderMap, HeaderName};
+    use serde_json::json;
+    use super::{bears_token, Auth, Error, Jwt, X_API_KEY};
+
+    fn bearer(token: &str) -> HeaderMap {
+        HeaderMap::from_iter([(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap())])
+    }
+
+    #[test]
+    fn validates_tokens() {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let jwt = Jwt { audience: Some("bff".to_string()), ..Jwt::new("s3cret") };
+        let auth = Auth::default().with_jwt(jwt.clone()).with_claim_header("tenant", HeaderName::from_static("x-tenant"));
+
+        let token = jwt.sign(&json!({"sub": "jane", "tenant": "acme", "aud": ["bff"], "exp": now + 60}));
+        let identity = auth.authenticate(&bearer(&token)).unwrap();
+        assert_eq!(identity.subject, "jane");
+        assert_eq!(auth.claim_headers(&identity)["x-tenant"], "acme");
+
+        let expired = jwt.sign(&json!({"sub": "jane", "aud": "bff", "exp": now - 120}));
+        assert_eq!(auth.authenticate(&bearer(&expired)), Err(Error::Expired));
+        assert_eq!(auth.authenticate(&bearer(&jwt.sign(&json!({"aud": "other", "exp": now + 60})))), Err(Error::Audience));
+        assert_eq!(auth.authenticate(&bearer(&jwt.sign(&json!({"aud": "bff"})))), Err(Error::Malformed("no exp")));
+        for exp in [json!(-1), json!(1e300), json!("soon"), json!(u64::MAX)] {
+            let token = jwt.sign(&json!({"aud": "bff", "exp": exp}));
+            assert_eq!(auth.authenticate(&bearer(&token)), Err(Error::Malformed("time isn't seconds since the epoch")), "{}", exp);
+        }
+        let lower = HeaderMap::from_iter([(header::AUTHORIZATION, format!("bearer {}", token).parse().unwrap())]);
+        assert_eq!(auth.authenticate(&lower).unwrap().subject, "jane");
+        let forged = Jwt::new("guessed").sign(&json!({"sub": "admin", "aud": "bff"}));
+        assert_eq!(auth.authenticate(&bearer(&forged)), Err(Error::Signature));
+        // `{"alg":"none"}`
+        assert_eq!(auth.authenticate(&bearer("eyJhbGciOiJub25lIn0.e30.")), Err(Error::Algorithm("none".to_string())));
+        assert_eq!(auth.authenticate(&bearer("a.b")), Err(Error::Malformed("not three parts")));
+        assert_eq!(auth.authenticate(&HeaderMap::new()), Err(Error::Missing));
+    }
+
+    #[test]
+    fn api_keys() {
+        let auth = Auth::default().with_api_keys("partner=k-123, internal=k-456");
+        let with_key = |key: &str| HeaderMap::from_iter([(HeaderName::from_static(X_API_KEY), key.parse().unwrap())]);
+        assert_eq!(auth.authenticate(&with_key("k-456")).unwrap().subject, "internal");
+        assert_eq!(auth.authenticate(&with_key("k-789")), Err(Error::UnknownKey));
+        assert_eq!(auth.authenticate(&bearer("a.b.c")), Err(Error::Missing));
+    }
+
+    #[test]
+    fn admin_tokens() {
+        assert!(bears_token(&bearer("s3cret"), "s3cret"));
+        assert!(!bears_token(&bearer("s3cre"), "s3cret"));
+        assert!(!bears_token(&HeaderMap::new(), "s3cret"));
+        // an empty token lets no one in
+        assert!(!bears_token(&HeaderMap::from_iter([(header::AUTHORIZATION, "Bearer".parse().unwrap())]), ""));
+        assert!(!bears_token(&bearer(""), ""));
+    }
+}
@@ -0,0 +1,267 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use hyper::client::HttpConnector;
+use hyper::{Client, Uri};
+use tokio::task::{JoinHandle, JoinSet};
+
+#[derive(Debug)]
+pub enum Error {
+    Addr(std::net::AddrParseError),
+    Weight(String),
+    Strategy(String),
+    NoReplicas,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Addr(err) => write!(f, "replica address: {}", err),
+            Error::Weight(weight) => write!(f, "replica weight {:?} isn't a positive integer", weight),
+            Error::Strategy(strategy) => write!(f, "unknown strategy {:?}", strategy),
+            Error::NoReplicas => write!(f, "no replicas"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// In turn, each as often as its weight, spread out rather than in runs.
+    RoundRobin,
+    /// The one with the fewest requests in flight for its weight.
+    LeastRequests,
+}
+
+impl FromStr for Strategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "round_robin" => Ok(Strategy::RoundRobin),
+            "least_requests" => Ok(Strategy::LeastRequests),
+            other => Err(Error::Strategy(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Replica {
+    pub addr: SocketAddr,
+    pub weight: u32,
+    healthy: AtomicBool,
+    in_flight: AtomicU64,
+}
+
+impl Replica {
+    pub fn new(addr: SocketAddr, weight: u32) -> Self {
+        Replica { addr, weight: weight.max(1), healthy: AtomicBool::new(true), in_flight: AtomicU64::new(0) }
+    }
+
+    pub fn healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+}
+
+/// A replica chosen for a request, counted in flight until it's dropped.
+pub struct Picked<'b> {
+    pub replica: &'b Replica,
+}
+
+impl Picked<'_> {
+    /// `uri` sent to this replica instead of its own host.
+    pub fn uri(&self, uri: &Uri) -> Uri {
+        let mut parts = uri.clone().into_parts();
+        parts.scheme.get_or_insert(hyper::http::uri::Scheme::HTTP);
+        parts.authority = self.replica.addr.to_string().parse().ok();
+        parts.path_and_query.get_or_insert_with(|| "/".parse().unwrap());
+        Uri::from_parts(parts).expect("a socket address is a valid authority")
+    }
+}
+
+impl Drop for Picked<'_> {
+    fn drop(&mut self) {
+        self.replica.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Spreads requests for an upstream over its replicas, e.g. from
+/// `10.0.0.1:1080*3,10.0.0.2:1080` where the first takes three times the share of the second.
+/// Replicas a [`Balancer::health_check`] finds down are passed over, unless all of them are.
+/// Added as an `Extension` layer, `/zc` then sends each of its requests to a replica.
+#[derive(Debug)]
+pub struct Balancer {
+    replicas: Vec<Replica>,
+    strategy: Strategy,
+    // smooth weighted round robin, as nginx does it
+    current: Mutex<Vec<i64>>,
+}
+
+impl Balancer {
+    pub fn new(replicas: Vec<Replica>, strategy: Strategy) -> Result<Self, Error> {
+        if replicas.is_empty() {
+            return Err(Error::NoReplicas);
+        }
+        let current = Mutex::new(vec![0; replicas.len()]);
+        Ok(Balancer { replicas, strategy, current })
+    }
+
+    /// Comma separated `addr` or `addr*weight`.
+    pub fn parse(replicas: &str, strategy: Strategy) -> Result<Self, Error> {
+        let replicas = replicas
+            .split(',')
+            .map(str::trim)
+            .filter(|replica| !replica.is_empty())
+            .map(|replica| {
+                let (addr, weight) = replica.split_once('*').unwrap_or((replica, "1"));
+                let weight = weight.parse().ok().filter(|&w| w > 0).ok_or_else(|| Error::Weight(weight.to_string()))?;
+                Ok(Replica::new(addr.parse().map_err(Error::Addr)?, weight))
+            })
+            .collect::<Result<_, Error>>()?;
+        Balancer::new(replicas, strategy)
+    }
+
+    pub fn replicas(&self) -> &[Replica] {
+        &self.replicas
+    }
+
+    pub fn pick(&self) -> Picked<'_> {
+        let any_healthy = self.replicas.iter().any(Replica::healthy);
+        let eligible = |replica: &Replica| replica.healthy() || !any_healthy;
+        let replica = match self.strategy {
+            Strategy::RoundRobin => {
+                let mut current = self.current.lock().unwrap();
+                let mut total = 0;
+                let mut best: Option<usize> = None;
+                for (i, replica) in self.replicas.iter().enumerate().filter(|(_, replica)| eligible(replica)) {
+                    current[i] += replica.weight as i64;
+                    total += replica.weight as i64;
+                    if best.is_none_or(|best| current[i] > current[best]) {
+                        best = Some(i);
+                    }
+                }
+                // there's always an eligible replica
+                let best = best.unwrap();
+                current[best] -= total;
+                &self.replicas[best]
+            }
+            Strategy::LeastRequests => self
+                .replicas
+                .iter()
+                .filter(|replica| eligible(replica))
+                .min_by(|a, b| {
+                    // fewest in flight per weight, compared without dividing
+                    let load = |r: &Replica, other: &Replica| r.in_flight() * other.weight as u64;
+                    load(a, b).cmp(&load(b, a))
+                })
+                .unwrap(),
+        };
+        replica.in_flight.fetch_add(1, Ordering::Relaxed);
+        Picked { replica }
+    }
+
+    /// Every `every`, GETs `path` of all replicas at once, those that don't answer with a
+    /// success within `timeout` are down until they do. Runs until the balancer is dropped.
+    pub fn health_check(
+        self: &Arc<Self>,
+        client: Arc<Client<HttpConnector>>,
+        path: &str,
+        every: Duration,
+        timeout: Duration,
+    ) -> JoinHandle<()> {
+        let balancer = Arc::downgrade(self);
+        let path: Arc<str> = path.into();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            loop {
+                interval.tick().await;
+                let Some(balancer) = balancer.upgrade() else {
+                    return;
+                };
+                let mut checks = JoinSet::new();
+                for i in 0..balancer.replicas.len() {
+                    let (balancer, client, path) = (balancer.clone(), client.clone(), path.clone());
+                    checks.spawn(async move {
+                        let replica = &balancer.replicas[i];
+                        let Ok(uri) = format!("http://{}{}", replica.addr, path).parse::<Uri>() else {
+                            return;
+                        };
+                        let healthy = matches!(
+                            tokio::time::timeout(timeout, client.get(uri)).await,
+                            Ok(Ok(response)) if response.status().is_success()
+                        );
+                        replica.healthy.store(healthy, Ordering::Relaxed);
+                    });
+                }
+                while checks.join_next().await.is_some() {}
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use hyper::{Client, Uri};
+    use crate::mock::{self, Fixtures};
+    use super::{Balancer, Error, Strategy};
+
+    #[test]
+    fn spreads_by_weight() {
+        let balancer = Balancer::parse("127.0.0.1:1*3, 127.0.0.1:2", Strategy::RoundRobin).unwrap();
+        let ports: Vec<_> = (0..8).map(|_| balancer.pick().replica.addr.port()).collect();
+        assert_eq!(ports, [1, 1, 2, 1, 1, 1, 2, 1]);
+
+        let balancer = Balancer::parse("127.0.0.1:1*2,127.0.0.1:2", Strategy::LeastRequests).unwrap();
+        let held: Vec<_> = (0..3).map(|_| balancer.pick()).collect();
+        assert_eq!(held.iter().map(|p| p.replica.addr.port()).collect::<Vec<_>>(), [1, 2, 1]);
+        drop(held);
+        assert!(balancer.replicas().iter().all(|replica| replica.in_flight() == 0));
+
+        let uri: Uri = "http://upstream/hello?x=1".parse().unwrap();
+        assert_eq!(balancer.pick().uri(&uri), "http://127.0.0.1:1/hello?x=1");
+        assert!(matches!(Balancer::parse("127.0.0.1:1*0", Strategy::RoundRobin), Err(Error::Weight(_))));
+        assert!(matches!(Balancer::parse("", Strategy::RoundRobin), Err(Error::NoReplicas)));
+    }
+
+    #[tokio::test]
+    async fn passes_over_replicas_that_are_down() {
+        let up_addr = mock::spawn(mock::router(Fixtures::default()));
+        // bound and dropped, so nothing listens there
+        let down_addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let balancer = Arc::new(Balancer::parse(&format!("{},{}*5", up_addr, down_addr), Strategy::RoundRobin).unwrap());
+        let every = Duration::from_millis(50);
+        let _checker = balancer.health_check(Arc::new(Client::new()), "/hello", every, every);
+        while balancer.replicas()[1].healthy() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(balancer.replicas()[0].healthy());
+        assert!((0..4).all(|_| balancer.pick().replica.addr == up_addr));
+    }
+
+    #[tokio::test]
+    async fn checks_replicas_at_once() {
+        // listening but never accepting, so a check of any of them runs into its timeout
+        let hung: Vec<_> = (0..4).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+        let replicas: Vec<_> = hung.iter().map(|listener| listener.local_addr().unwrap().to_string()).collect();
+        let balancer = Arc::new(Balancer::parse(&replicas.join(","), Strategy::RoundRobin).unwrap());
+        let timeout = Duration::from_millis(300);
+        let started = tokio::time::Instant::now();
+        let _checker = balancer.health_check(Arc::new(Client::new()), "/hello", Duration::from_secs(60), timeout);
+        while balancer.replicas().iter().any(|replica| replica.healthy()) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        // one after another they'd take a timeout each
+        assert!(started.elapsed() < timeout * 2);
+    }
+}
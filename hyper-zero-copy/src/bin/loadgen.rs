@@ -0,0 +1,48 @@
+use std::env;
+use std::net::TcpListener;
+use std::str::FromStr;
+
+use hyper::{Client, Uri};
+use hyper_zero_copy::loadgen::{self, LoadConfig};
+use hyper_zero_copy::mock::{self, Fixtures};
+
+#[tokio::main]
+async fn main() {
+    let target = env::var("target").unwrap_or("http://localhost:2000".to_string());
+    let paths = env::var("paths").unwrap_or("/zc,/serde".to_string());
+    let mut config = LoadConfig::default();
+    if let Some(concurrency) = env::var("concurrency").ok().and_then(|n| n.parse().ok()) {
+        config.concurrency = concurrency;
+    }
+    if let Some(requests) = env::var("requests").ok().and_then(|n| n.parse().ok()) {
+        config.requests = requests;
+    }
+
+    // stands in for the proxy's upstream with a generated payload of this size
+    let payload = match env::var("payload_bytes").ok().and_then(|n| n.parse().ok()) {
+        Some(size) => {
+            let payload = loadgen::payload(size);
+            let addr = format!("0.0.0.0:{}", env::var("upstream_port").unwrap_or("1080".to_string()));
+            let fixtures = Fixtures::default().with("hello", payload.clone());
+            tokio::spawn(mock::serve(TcpListener::bind(addr).unwrap(), fixtures));
+            Some(payload)
+        }
+        None => None,
+    };
+    if env::var("validate").is_ok_and(|v| v == "true") {
+        let expected = match payload {
+            Some(payload) => payload,
+            None => {
+                let upstream = env::var("upstream").unwrap_or("http://localhost:1080/hello".to_string());
+                let response = Client::new().get(Uri::from_str(&upstream).unwrap()).await.unwrap();
+                hyper::body::to_bytes(response).await.unwrap()
+            }
+        };
+        config.expected = Some(serde_json::from_slice(&expected).unwrap());
+    }
+
+    for path in paths.split(',') {
+        let uri = Uri::from_str(&format!("{}{}", target, path)).unwrap();
+        println!("{}", loadgen::run(Client::new(), uri, &config).await);
+    }
+}
@@ -1,25 +1,45 @@
-use std::borrow::Cow;
 use std::env;
 use std::ops::Deref;
 use std::str::FromStr;
-use axum::{
-    Json,
-    extract::{Extension, Path},
-    routing::{get, post},
-    Router,
-};
+use axum::Json;
 use std::sync::Arc;
-use axum::extract::State;
-use axum::http::{header, HeaderValue, StatusCode};
-use axum::response::{IntoResponse, Response};
-use bytes::{Buf, BufMut, Bytes, BytesMut};
-use hyper::{Client, Uri};
-use hyper::body::HttpBody;
+use hyper::{Client, StatusCode, Uri};
 use hyper::client::HttpConnector;
-use serde::{Deserialize, Serialize, Serializer};
-use serde_json::{json, Value};
-use yoke::Yoke;
-use serde_json_nostr::error::Category::Data;
+use serde::Deserialize;
+use axum::Extension;
+use hyper_zero_copy::access::{self, AccessLog};
+use hyper_zero_copy::aggregate::Aggregator;
+use hyper_zero_copy::auth::{self, Auth, Jwt};
+use hyper_zero_copy::balance::{Balancer, Strategy};
+use hyper_zero_copy::branch::{self, Branches};
+use hyper_zero_copy::cache::{Cache, CacheTier, DedupCache, InternedCache, MemoryCache, SpillCache};
+use hyper_zero_copy::capture::{Archive, Capture, CaptureConfig};
+use hyper_zero_copy::compress::Compression;
+use hyper_zero_copy::cors::{self, Cors};
+use hyper_zero_copy::dead_letter::{DeadLetters, Fallback, Sink};
+use hyper_zero_copy::dictionary::Zstd;
+use hyper_zero_copy::forward::{self, Forwarding};
+use hyper_zero_copy::graphql::{self, GraphQlGateway};
+use hyper_zero_copy::hedge::Hedging;
+use hyper_zero_copy::idempotency::{self, Idempotency};
+use hyper_zero_copy::jsonrpc::{self, JsonRpcClient, JsonRpcServer};
+use hyper_zero_copy::locale::Localize;
+use hyper_zero_copy::metrics::{self, Metrics};
+use hyper_zero_copy::offload::Offload;
+use hyper_zero_copy::openapi::{self, OpenApiValidator};
+use hyper_zero_copy::pinned::{self, Pinned};
+use hyper_zero_copy::provenance::Provenance;
+use hyper_zero_copy::proxy;
+use hyper_zero_copy::rewrite::RewriteUrls;
+use hyper_zero_copy::rules::{self, RuleSet};
+use hyper_zero_copy::sample::{self, Sampler};
+use hyper_zero_copy::shadow::{self, Shadow};
+use hyper_zero_copy::static_files;
+use hyper_zero_copy::tenant::Tenants;
+use hyper_zero_copy::transform::{ClaimsField, Compose, Mask, Nulls, Paginate, RequestIdField, Transforms};
+use hyper_zero_copy::unix::{self, Connector};
+use hyper_zero_copy::version::{self, Versions};
+use serde_zero_copy::{Symbols, Template};
 
 struct AppState {
     // ...
@@ -37,122 +57,408 @@ async fn main() {
             .as_str(),
     ).unwrap();
 
-    let app = Router::new()
-        .route(
-            "/zc",
-            get(zero_copy),
-        )
-        .with_state((shared_state.clone(), uri.clone()))
-        .route(
-            "/serde",
-            get(serde_val),
-        )
-        .with_state((shared_state.clone(), uri.clone()))
-        .route(
-            "/simd",
-            get(serde_simd),
-        )
-        .with_state((shared_state.clone(), uri.clone()))
-        ;
-
-
-    // run it with hyper on localhost:3000
-    axum::Server::bind(&"0.0.0.0:2000".parse().unwrap())
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
-}
-
-struct SerializableYok(Yoke<serde_zero_copy::Value<'static>, Arc<Bytes>>);
-
-// impl Serialize for SerializableYok {
-//     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
-//         self.0.get().serialize(serializer)
-//     }
-// }
-
-impl IntoResponse for SerializableYok {
-    fn into_response(self) -> Response {
-
-        // Use a small initial capacity of 128 bytes like serde_json::to_vec
-        // https://docs.rs/serde_json/1.0.82/src/serde_json/ser.rs.html#2189
-        let mut buf = BytesMut::with_capacity(128).writer();
-        match serde_json_nostr::to_writer(&mut buf, &self.0.get()) {
-            Ok(()) => (
-                [(
-                    header::CONTENT_TYPE,
-                    HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
-                )],
-                buf.into_inner().freeze(),
-            )
-                .into_response(),
-            Err(err) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                [(
-                    header::CONTENT_TYPE,
-                    HeaderValue::from_static(mime::TEXT_PLAIN_UTF_8.as_ref()),
-                )],
-                err.to_string(),
-            )
-                .into_response(),
+    // e.g. `upstream_sockets=localhost=/run/upstream.sock`, hosts without a socket are over TCP
+    let upstream_client = Arc::new(Client::builder().build(Connector::parse(&env::var("upstream_sockets").unwrap_or_default())));
+    let mut app = proxy::router(upstream_client.clone(), uri);
+    // what parts keep count of, for `/admin/metrics`
+    let mut metrics = Metrics::new(env::var("admin_token").unwrap_or_default());
+    if let Ok(upstream) = env::var("jsonrpc_upstream") {
+        let client = JsonRpcClient::new(shared_state.clone(), Uri::from_str(&upstream).unwrap());
+        let gateway = JsonRpcServer::default().fallback(move |call| {
+            let client = client.clone();
+            async move { client.forward(&call).await }
+        });
+        app = app.merge(jsonrpc::router("/rpc", gateway));
+    }
+    // e.g. `user=http://localhost:1080/users/{id},stats=http://localhost:1080/stats`
+    if let Ok(fields) = env::var("graphql_fields") {
+        let routes = fields
+            .split(',')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(field, template)| (field.to_string(), template.to_string()))
+            .collect();
+        app = app.merge(graphql::router(GraphQlGateway::new(shared_state.clone(), routes)));
+    }
+    if let Some(ttl) = env::var("cache_ttl_ms").ok().and_then(|ttl| ttl.parse().ok()) {
+        let ttl = std::time::Duration::from_millis(ttl);
+        // e.g. `cache_zstd=3 cache_zstd_dictionary=orders.dict`, see the `train-dictionary` bin
+        let zstd = env::var("cache_zstd").ok().and_then(|level| level.parse().ok()).map(|level| {
+            let mut zstd = Zstd::new(level);
+            if let Some(max_bytes) = env::var("cache_zstd_max_bytes").ok().and_then(|n| n.parse().ok()) {
+                zstd.max_bytes = max_bytes;
+            }
+            match env::var("cache_zstd_dictionary") {
+                Ok(path) => Arc::new(zstd.with_dictionary(&std::fs::read(path).unwrap())),
+                Err(_) => Arc::new(zstd),
+            }
+        });
+        let budget = env::var("cache_budget").ok().and_then(|b| b.parse().ok()).unwrap_or(256 << 20);
+        let memory: Arc<dyn CacheTier> = match env::var("cache_spill_dir") {
+            Ok(dir) => {
+                let spill = SpillCache::new(dir, budget).unwrap();
+                Arc::new(match &zstd {
+                    Some(zstd) => spill.compressed(zstd.clone()),
+                    None => spill,
+                })
+            }
+            Err(_) => Arc::new(MemoryCache::new(budget)),
+        };
+        let memory: Arc<dyn CacheTier> = match env::var("cache_dedup") {
+            Ok(v) if v == "true" => Arc::new(DedupCache::new(memory)),
+            _ => memory,
+        };
+        let memory: Arc<dyn CacheTier> = match env::var("cache_intern_keys") {
+            Ok(v) if v == "true" => Arc::new(InternedCache::new(memory, Symbols::global())),
+            _ => memory,
+        };
+        #[cfg(feature = "redis")]
+        let memory: Arc<dyn CacheTier> = match env::var("redis") {
+            Ok(url) => {
+                use hyper_zero_copy::cache::{RedisCache, TieredCache};
+                let redis = RedisCache::connect(&url, "hyper-zero-copy:").await.unwrap();
+                let redis = match zstd {
+                    Some(zstd) => redis.compressed(zstd),
+                    None => redis,
+                };
+                Arc::new(TieredCache::new(memory, Arc::new(redis), ttl))
+            }
+            Err(_) => memory,
+        };
+        let mut cache = Cache::new(memory, ttl);
+        // `/zc=5000` keeps `/zc` for 5s whatever its upstream says, `/zc=0` doesn't cache it
+        for (route, ms) in env::var("cache_overrides").unwrap_or_default().split(',').filter_map(|pair| pair.split_once('=')) {
+            let Ok(ms) = ms.parse() else {
+                eprintln!("cache_overrides: {}={} isn't a route and milliseconds", route, ms);
+                std::process::exit(1);
+            };
+            cache = cache.with_override(route, std::time::Duration::from_millis(ms));
+        }
+        app = app.layer(Extension(cache));
+    }
+    // e.g. `capture=./captured capture_fields=id,/user/name capture_file_ms=60000`
+    let mut capture_writer = None;
+    if let Ok(dir) = env::var("capture") {
+        let fields = env::var("capture_fields").unwrap_or_default();
+        let fields = fields.split(',').filter(|f| !f.is_empty()).map(str::to_string).collect();
+        let mut config = CaptureConfig::new(dir, fields);
+        if let Some(ms) = env::var("capture_file_ms").ok().and_then(|ms| ms.parse().ok()) {
+            config.file_every = Some(std::time::Duration::from_millis(ms));
+        }
+        let (capture, writer) = Capture::spawn(config);
+        capture_writer = Some(writer);
+        app = app.layer(Extension(capture));
+    }
+    // e.g. `archive=captured.ndjson`, for `replay` to check a pipeline against
+    if let Ok(path) = env::var("archive") {
+        let (archive, _writer) = Archive::spawn(path, 1024).unwrap();
+        let counted = archive.clone();
+        metrics.register("archive", move || serde_json::json!({ "dropped": counted.dropped() }));
+        app = app.layer(Extension(archive));
+    }
+    #[cfg(feature = "kafka")]
+    let mut kafka = None;
+    #[cfg(feature = "kafka")]
+    if let Ok(brokers) = env::var("kafka_brokers") {
+        use hyper_zero_copy::kafka::{KafkaConfig, KafkaPublisher, KafkaSink};
+        // `/zc=zc-events,/other=other-events`, `dead_letters=…` for those of `dead_letters_kafka`
+        let topics = env::var("kafka_topics").unwrap_or_default();
+        let topics = topics
+            .split(',')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(route, topic)| (route.to_string(), topic.to_string()))
+            .collect();
+        let publisher = KafkaPublisher::connect(brokers.split(',').map(str::to_string).collect()).await.unwrap();
+        let (sink, _batcher) = KafkaSink::spawn(publisher, KafkaConfig::new(topics));
+        kafka = Some(sink.clone());
+        app = app.layer(Extension(sink));
+    }
+    if let Some(timeout) = env::var("request_timeout_ms").ok().and_then(|ms| ms.parse().ok()) {
+        app = app.layer(Extension(proxy::RequestTimeout(std::time::Duration::from_millis(timeout))));
+    }
+    if let Some(above) = env::var("yield_above_bytes").ok().and_then(|n| n.parse().ok()) {
+        let every = env::var("yield_every").ok().and_then(|n| n.parse().ok()).unwrap_or(proxy::Yielding::default().every);
+        app = app.layer(Extension(proxy::Yielding { above, every }));
+    }
+    if let Some(above) = env::var("offload_above_bytes").ok().and_then(|n| n.parse().ok()) {
+        let budget = env::var("offload_budget")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get()));
+        let offload = Offload::new(above, budget);
+        let counted = offload.clone();
+        metrics.register("offload", move || serde_json::json!(counted.metrics()));
+        app = app.layer(Extension(offload));
+    }
+    let max_total = env::var("upstream_max_bytes").ok().and_then(|n| n.parse().ok());
+    let max_chunk = env::var("upstream_max_chunk").ok().and_then(|n| n.parse().ok());
+    // without limits set too, for its metrics to be of every request rather than one each
+    let mut aggregator = Aggregator::default();
+    aggregator.max_chunk = max_chunk.unwrap_or(aggregator.max_chunk);
+    aggregator.max_total = max_total.unwrap_or(aggregator.max_total);
+    let counted = aggregator.clone();
+    metrics.register("upstream_bodies", move || serde_json::json!(counted.metrics()));
+    app = app.layer(Extension(aggregator));
+    // e.g. `replicas=10.0.0.1:1080*3,10.0.0.2:1080 balance=least_requests health_path=/hello`
+    if let Ok(replicas) = env::var("replicas") {
+        let strategy = env::var("balance").map_or(Strategy::RoundRobin, |s| s.parse().unwrap());
+        let balancer = Arc::new(Balancer::parse(&replicas, strategy).unwrap());
+        if let Ok(path) = env::var("health_path") {
+            let every = env::var("health_ms").ok().and_then(|ms| ms.parse().ok()).unwrap_or(1000);
+            let timeout = env::var("health_timeout_ms").ok().and_then(|ms| ms.parse().ok()).unwrap_or(every);
+            balancer.health_check(
+                shared_state.clone(),
+                &path,
+                std::time::Duration::from_millis(every),
+                std::time::Duration::from_millis(timeout),
+            );
+        }
+        app = app.layer(Extension(balancer));
+    }
+    // e.g. `hedge_quantile=0.95 hedge_min_ms=20`
+    if let Some(quantile) = env::var("hedge_quantile").ok().and_then(|q| q.parse().ok()) {
+        let min_delay = env::var("hedge_min_ms").ok().and_then(|ms| ms.parse().ok()).unwrap_or(10);
+        app = app.layer(Extension(Hedging::new(quantile, std::time::Duration::from_millis(min_delay))));
+    }
+    // e.g. `method_upstreams=POST=http://localhost:1080/orders,DELETE=http://localhost:1080/orders`
+    if let Ok(upstreams) = env::var("method_upstreams") {
+        app = app.layer(Extension(proxy::MethodUpstreams::parse(&upstreams).unwrap()));
+    }
+    // e.g. `strip_request_fields=/debug,/client/session`
+    let mut request_transforms = Transforms::default();
+    if let Ok(pointers) = env::var("strip_request_fields") {
+        request_transforms = request_transforms.with(Mask { pointers: pointers.split(',').map(str::to_string).collect() });
+    }
+    // e.g. `claims_field=user claims=sub,tenant`, in what's sent to the method upstreams in
+    // place of whatever the client put there
+    if let Ok(key) = env::var("claims_field") {
+        let claims = env::var("claims").unwrap_or("sub".to_string()).split(',').map(str::to_string).collect();
+        let sent = Mask { pointers: vec![format!("/{}", key.replace('~', "~0").replace('/', "~1"))] };
+        request_transforms = request_transforms.with(sent).with(ClaimsField { key, claims });
+    }
+    if !request_transforms.0.is_empty() {
+        app = app.layer(Extension(proxy::RequestTransforms(request_transforms)));
+    }
+    // e.g. `compress=zstd,br,gzip compress_min_bytes=1024`
+    if let Ok(encodings) = env::var("compress") {
+        let encodings = encodings.split(',').map(|encoding| encoding.parse().unwrap()).collect();
+        let min_bytes = env::var("compress_min_bytes").ok().and_then(|n| n.parse().ok()).unwrap_or(Compression::default().min_bytes);
+        app = app.layer(Extension(Compression { encodings, min_bytes }));
+    }
+    if env::var("coalesce").is_ok_and(|v| v == "true") {
+        app = app.layer(Extension(proxy::Coalescing::default()));
+    }
+    let mut transforms = Transforms::default();
+    if let Some(per_page) = env::var("paginate_per_page").ok().and_then(|n| n.parse().ok()) {
+        transforms = transforms.with(Paginate { per_page, ..Paginate::default() });
+    }
+    // after pagination, so that stripping also covers its envelope
+    if env::var("nulls").is_ok_and(|v| v == "true") {
+        let schema = env::var("nulls_schema").ok().map(|path| serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap());
+        transforms = transforms.with(Nulls { schema });
+    }
+    // the template outlives every response, it's read once and kept for good
+    if let Ok(path) = env::var("compose_template") {
+        let template: &'static str = Box::leak(std::fs::read_to_string(path).unwrap().into_boxed_str());
+        transforms = transforms.with(Compose { template: Template::parse(template).unwrap() });
+    }
+    // e.g. `rewrite_urls={"upstreams": ["http://orders.internal:8080"], "pointers": ["/_links/*/href"]}`
+    if let Ok(rewrite) = env::var("rewrite_urls") {
+        transforms = transforms.with(serde_json::from_str::<RewriteUrls>(&rewrite).unwrap());
+    }
+    // e.g. `localize={"fields": [{"pointer": "/total", "currency": "EUR", "decimals": 2}]}`
+    if let Ok(localize) = env::var("localize") {
+        transforms = transforms.with(serde_json::from_str::<Localize>(&localize).unwrap());
+    }
+    // e.g. `request_id_field=request_id`, after the transforms above so that it goes in what
+    // they serve, a page's wrapper included, and no mask drops it
+    if let Ok(key) = env::var("request_id_field") {
+        transforms = transforms.with(RequestIdField { key });
+    }
+    if !transforms.0.is_empty() {
+        app = app.layer(Extension(transforms));
+    }
+    // e.g. `tenants=./tenants tenant_claim=tenant tenants_watch_ms=1000`, needs auth in front
+    if let Ok(dir) = env::var("tenants") {
+        let tenants = Arc::new(Tenants::load(dir, &env::var("tenant_claim").unwrap_or("sub".to_string())).await.unwrap());
+        if let Some(every) = env::var("tenants_watch_ms").ok().and_then(|ms| ms.parse().ok()) {
+            tenants.dir().watch(std::time::Duration::from_millis(every), |err| eprintln!("tenant pipeline not reloaded: {}", err));
+        }
+        app = app.layer(Extension(tenants));
+    }
+    // e.g. `provenance=orders`, what `_meta` calls the upstream
+    if let Ok(upstream) = env::var("provenance") {
+        let mut provenance = Provenance::new(upstream);
+        if let Ok(key) = env::var("provenance_key") {
+            provenance.key = key;
+        }
+        app = app.layer(Extension(provenance));
+    }
+    // e.g. `static_dir=./config static_prefix=/config static_schemas=./config/schemas`
+    if let Ok(dir) = env::var("static_dir") {
+        let schemas = match env::var("static_schemas") {
+            Ok(schemas) => static_files::read_schemas(schemas).unwrap(),
+            Err(_) => Default::default(),
+        };
+        let documents = static_files::load(dir, schemas).await.unwrap();
+        if let Some(every) = env::var("static_watch_ms").ok().and_then(|ms| ms.parse().ok()) {
+            documents.watch(std::time::Duration::from_millis(every), |err| eprintln!("static document not reloaded: {}", err));
+        }
+        app = app.merge(static_files::router(&env::var("static_prefix").unwrap_or("/static".to_string()), documents));
+    }
+    if let Ok(path) = env::var("openapi") {
+        let report_only = env::var("openapi_report_only").is_ok_and(|v| v == "true");
+        let document = std::fs::read_to_string(path).unwrap();
+        let validator = Arc::new(OpenApiValidator::from_json(&document, report_only).unwrap());
+        app = app.layer(axum::middleware::from_fn_with_state(validator, openapi::validate));
+    }
+    // around validation, which sends its letters too
+    let max_letters = env::var("dead_letters_max").ok().and_then(|n| n.parse().ok()).unwrap_or(10_000);
+    let dead_letters = env::var("dead_letters").ok().map(|dir| Sink::Spool { dir: dir.into(), max_letters });
+    #[cfg(feature = "kafka")]
+    let dead_letters = dead_letters.or(kafka.filter(|_| env::var("dead_letters_kafka").is_ok_and(|v| v == "true")).map(Sink::Kafka));
+    if let Some(sink) = dead_letters {
+        let (mut dead_letters, _writer) = DeadLetters::spawn(sink, 1024);
+        // e.g. `dead_letters_fallback={"items":[]}`
+        if let Ok(body) = env::var("dead_letters_fallback") {
+            let status = env::var("dead_letters_fallback_status").map_or(StatusCode::OK, |s| s.parse().unwrap());
+            dead_letters = dead_letters.with_fallback(Fallback::json(status, body));
+        }
+        let counted = dead_letters.clone();
+        metrics.register("dead_letters", move || serde_json::json!({ "dropped": counted.dropped() }));
+        app = app.layer(Extension(dead_letters));
+    }
+    if let Ok(path) = env::var("rules") {
+        let rule_set = Arc::new(RuleSet::from_json(&std::fs::read_to_string(path).unwrap()).unwrap());
+        app = app.layer(axum::middleware::from_fn_with_state(rule_set, rules::check));
+    }
+    let routes = env::var("routes").ok().map(|path| std::fs::read_to_string(path).unwrap());
+    if let Some(routes) = &routes {
+        let branches = Arc::new(Branches::from_json(upstream_client.clone(), routes).unwrap());
+        app = app.layer(axum::middleware::from_fn_with_state(branches, branch::route));
+    }
+    if let Some(versions) = routes.as_ref().map(|routes| Versions::from_json(routes).unwrap()).filter(|versions| !versions.is_empty()) {
+        app = app.layer(axum::middleware::from_fn_with_state(Arc::new(versions), version::select));
+    }
+    // inside auth, keys are per client
+    if let Some(ttl) = env::var("idempotency_ttl_ms").ok().and_then(|ms| ms.parse().ok()) {
+        let ttl = std::time::Duration::from_millis(ttl);
+        let store: Arc<dyn CacheTier> = Arc::new(MemoryCache::default());
+        // shared by replicas, so that a retry is replayed by any of them
+        #[cfg(feature = "redis")]
+        let store: Arc<dyn CacheTier> = match env::var("redis") {
+            Ok(url) => Arc::new(hyper_zero_copy::cache::RedisCache::connect(&url, "hyper-zero-copy:").await.unwrap()),
+            Err(_) => store,
+        };
+        let mut idempotency = Idempotency::new(store, ttl);
+        if let Some(max_body) = env::var("idempotency_max_body").ok().and_then(|n| n.parse().ok()) {
+            idempotency.max_body = max_body;
+        }
+        app = app.layer(axum::middleware::from_fn_with_state(Arc::new(idempotency), idempotency::replay_retries));
+    }
+    // inside auth like the routes they serve, the admin ones ask for `admin_token` as well
+    #[cfg(feature = "pprof")]
+    if env::var("pprof").is_ok_and(|v| v == "true") {
+        app = app.merge(hyper_zero_copy::profile::router());
+    }
+    // e.g. `shadow_upstream=http://orders-v2/orders shadow_percent=5 shadow_ignore=/served_at`,
+    // compared with what the client is served
+    if let Ok(upstream) = env::var("shadow_upstream") {
+        let percent = env::var("shadow_percent").ok().and_then(|n| n.parse().ok()).unwrap_or(100);
+        let capacity = env::var("shadow_capacity").ok().and_then(|n| n.parse().ok()).unwrap_or(100);
+        let mut shadow = Shadow::new(upstream_client.clone(), upstream.parse().unwrap(), percent, capacity, env::var("admin_token").unwrap_or_default());
+        shadow.ignore.pointers = env::var("shadow_ignore").unwrap_or_default().split(',').filter(|p| !p.is_empty()).map(str::to_string).collect();
+        if let Some(max_body) = env::var("shadow_max_body").ok().and_then(|n| n.parse().ok()) {
+            shadow.max_body = max_body;
+        }
+        if let Some(max_in_flight) = env::var("shadow_max_in_flight").ok().and_then(|n| n.parse().ok()) {
+            shadow = shadow.with_max_in_flight(max_in_flight);
+        }
+        let shadow = Arc::new(shadow);
+        app = app
+            .layer(axum::middleware::from_fn_with_state(shadow.clone(), shadow::mirror))
+            .merge(shadow::admin(shadow));
+    }
+    // e.g. `pinned_report_ms=60000`, the report also goes to stdout every so often
+    if env::var("pinned").is_ok_and(|v| v == "true") {
+        let pinned = Arc::new(Pinned::new(env::var("admin_token").unwrap_or_default()));
+        if let Some(every) = env::var("pinned_report_ms").ok().and_then(|ms| ms.parse().ok()) {
+            pinned.report_every(std::time::Duration::from_millis(every), |report| println!("{}", serde_json::json!({ "pinned": report })));
+        }
+        app = app.layer(Extension(pinned.clone())).merge(pinned::admin(pinned));
+    }
+    if !metrics.is_empty() {
+        app = app.merge(metrics::admin(Arc::new(metrics)));
+    }
+    // e.g. `api_keys=partner=k-123 jwt_secret=… jwt_audience=bff claim_headers=sub=x-user`,
+    // in front of whatever looks at requests
+    let api_keys = env::var("api_keys").ok();
+    let jwt_secret = env::var("jwt_secret").ok();
+    if api_keys.is_some() || jwt_secret.is_some() {
+        let mut auth = Auth::default().with_api_keys(&api_keys.unwrap_or_default());
+        if let Some(secret) = jwt_secret {
+            let mut jwt = Jwt::new(secret);
+            jwt.issuer = env::var("jwt_issuer").ok();
+            jwt.audience = env::var("jwt_audience").ok();
+            auth = auth.with_jwt(jwt);
+        }
+        for (claim, header) in env::var("claim_headers").unwrap_or_default().split(',').filter_map(|pair| pair.split_once('=')) {
+            auth = auth.with_claim_header(claim, header.parse().unwrap());
+        }
+        app = app.layer(axum::middleware::from_fn_with_state(Arc::new(auth), auth::check));
+    }
+    // the `cors` of the routes file, around auth for preflights to get through
+    if let Some(cors) = routes.map(|routes| Cors::from_json(&routes).unwrap()).filter(|cors| !cors.is_empty()) {
+        app = app.layer(axum::middleware::from_fn_with_state(Arc::new(cors), cors::handle));
+    }
+    // outermost, so that samples are the exchanges as the client sees them
+    if let Some(percent) = env::var("sample_percent").ok().and_then(|n| n.parse().ok()) {
+        let capacity = env::var("sample_capacity").ok().and_then(|n| n.parse().ok()).unwrap_or(100);
+        let mut sampler = Sampler::new(percent, capacity, env::var("admin_token").unwrap_or_default());
+        if let Some(max_body) = env::var("sample_max_body").ok().and_then(|n| n.parse().ok()) {
+            sampler.max_body = max_body;
+        }
+        let sampler = Arc::new(sampler);
+        app = app
+            .layer(axum::middleware::from_fn_with_state(sampler.clone(), sample::sample))
+            .merge(sample::admin(sampler));
+    }
+    if env::var("access_log").is_ok_and(|v| v == "true") {
+        let (log, _) = AccessLog::stdout(10_000);
+        app = app.layer(axum::middleware::from_fn_with_state(Arc::new(log), access::log));
+    }
+    // around the access log, so that it has the request id
+    let forwarding = Forwarding { trust_forwarded: env::var("trust_forwarded").is_ok_and(|v| v == "true") };
+    app = app.layer(axum::middleware::from_fn_with_state(Arc::new(forwarding), forward::headers));
+
+
+    if let Ok(path) = env::var("listen_socket") {
+        unix::serve_until(unix::bind(path).unwrap(), app, shutdown()).await.unwrap();
+    } else {
+        // run it with hyper on localhost:3000
+        axum::Server::bind(&"0.0.0.0:2000".parse().unwrap())
+            .serve(app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+            .with_graceful_shutdown(shutdown())
+            .await
+            .unwrap();
+    }
+    // the app and the capture handles in it are gone with the server, so the writer closes
+    // the file it has open
+    if let Some(writer) = capture_writer {
+        match tokio::time::timeout(std::time::Duration::from_secs(10), writer).await {
+            Ok(Ok(Ok(()))) => {}
+            Ok(Ok(Err(err))) => eprintln!("{}", err),
+            Ok(Err(err)) => eprintln!("capture writer failed: {}", err),
+            Err(_) => eprintln!("capture writer still busy, its last file isn't closed"),
         }
     }
 }
 
-// async fn root_agg(State(client): State<Arc<Client<HttpConnector>>>, State(uri): State<Uri>) -> Bytes {
-// #[axum_macros::debug_handler]
-async fn zero_copy(State((client, uri)): State<(Arc<Client<HttpConnector>>, Uri)>) -> SerializableYok {
-    let res = client.get(uri).await.unwrap();
-    // let buf = hyper::body::aggregate(res).await.unwrap();
-    let buf = hyper::body::to_bytes(res).await.unwrap();
-    // let val: Value = serde_json::from_slice(buf.as_ref()).unwrap();
-    // let val: serde_zero_copy::Value = serde_json_nostr::from_slice(&buf).unwrap();
-    let buf = Arc::new(buf);
-    let yoked = yoke::Yoke::<serde_zero_copy::Value<'static>, Arc<Bytes>>::attach_to_cart(buf, |b| {
-        let val = serde_json_nostr::from_slice(b).unwrap();
-        val
-    });
-    SerializableYok(yoked)
-    // buf
-    // return to_opaque(buf).unwrap();
-}
-
-// #[axum_macros::debug_handler]
-async fn serde_val(State((client, uri)): State<(Arc<Client<HttpConnector>>, Uri)>) -> Json<Value> {
-    let res = client.get(uri).await.unwrap();
-    let buf = hyper::body::to_bytes(res).await.unwrap();
-    let val: Value = serde_json::from_slice(buf.as_ref()).unwrap();
-    Json(val)
-}
-
-pub struct SimdValue(Value);
-
-
-impl IntoResponse for SimdValue {
-    fn into_response(self) -> Response {
-
-        // Use a small initial capacity of 128 bytes like serde_json::to_vec
-        // https://docs.rs/serde_json/1.0.82/src/serde_json/ser.rs.html#2189
-        let mut buf = BytesMut::with_capacity(128).writer();
-        match simd_json::serde::to_writer(&mut buf, &self.0) {
-            Ok(()) => (
-                [(
-                    header::CONTENT_TYPE,
-                    HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
-                )],
-                buf.into_inner().freeze(),
-            )
-                .into_response(),
-            Err(err) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                [(
-                    header::CONTENT_TYPE,
-                    HeaderValue::from_static(mime::TEXT_PLAIN_UTF_8.as_ref()),
-                )],
-                err.to_string(),
-            )
-                .into_response(),
-        }
+// on Ctrl-C or SIGTERM
+async fn shutdown() {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).unwrap();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
     }
 }
 
@@ -178,22 +484,6 @@ impl <'a> Deref for WrappedValue<'a> {
     }
 }
 
-async fn serde_simd(State((client, uri)): State<(Arc<Client<HttpConnector>>, Uri)>) -> Json<Value> {
-    let mut res = client.get(uri).await.unwrap();
-    let mut buf = hyper::body::to_bytes(res).await.unwrap();
-    let mut buf = buf.to_vec();
-    let val: Value = simd_json::serde::from_slice(&mut buf).unwrap();
-    // let mut value = WrappedValue {
-    //     data: buf,
-    //     value: None,
-    // };
-    // value.parse_to_json();
-    // let yoked = yoke::Yoke::<(), WrappedValue<'static>>::attach_to_cart( value, |b| {
-    //     b
-    // });
-    Json(val)
-}
-
 async fn get_user(state: Arc<Client<HttpConnector>>) {
     // ...
 }
@@ -0,0 +1,28 @@
+use std::env;
+use std::net::TcpListener;
+use std::time::Duration;
+
+use hyper_zero_copy::mock::{self, Fixtures};
+use hyper_zero_copy::reload::WatchedDir;
+use hyper_zero_copy::unix;
+
+#[tokio::main]
+async fn main() {
+    // with watch_ms set, edits to the fixtures are served without a restart
+    let fixtures = match (env::var("fixtures"), env::var("watch_ms")) {
+        (Ok(dir), Ok(every)) => {
+            let watched = WatchedDir::load(dir).await.unwrap();
+            watched.watch(Duration::from_millis(every.parse().unwrap()), |err| eprintln!("fixture not reloaded: {}", err));
+            Fixtures::default().watching(watched)
+        }
+        (Ok(dir), Err(_)) => Fixtures::from_dir(dir).unwrap(),
+        (Err(_), _) => Fixtures::default(),
+    };
+    if let Ok(path) = env::var("socket") {
+        unix::serve(unix::bind(path).unwrap(), mock::router(fixtures)).await.unwrap();
+        return;
+    }
+    let addr = format!("0.0.0.0:{}", env::var("port").unwrap_or("1080".to_string()));
+    let listener = TcpListener::bind(addr).unwrap();
+    mock::serve(listener, fixtures).await.unwrap();
+}
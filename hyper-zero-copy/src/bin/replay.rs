@@ -0,0 +1,19 @@
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+
+use hyper_zero_copy::replay;
+use hyper_zero_copy::tenant::Pipeline;
+
+// e.g. `pipeline=tenants/acme.json archive=captured.ndjson`, of `replay::Record`s as the proxy's
+// own `archive` writes them; exits with 1 when anything's served otherwise or not at all, so that a config
+// change can be checked before it's rolled out
+fn main() {
+    let pipeline: Pipeline = serde_json::from_str(&std::fs::read_to_string(env::var("pipeline").unwrap()).unwrap()).unwrap();
+    let archive = BufReader::new(File::open(env::var("archive").unwrap()).unwrap());
+    let report = replay::replay(&pipeline.build(), archive, |changed| println!("{}", serde_json::to_string(&changed).unwrap())).unwrap();
+    println!("{}", report);
+    if report.changed > 0 || report.failed > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,16 @@
+use std::env;
+
+use hyper_zero_copy::dictionary::{self, DICTIONARY_BYTES};
+
+// e.g. `captured=capture.json out=orders.dict dictionary_bytes=65536`, `captured` being what a
+// sampler's `/admin/capture` served or an archive for `replay`; the dictionary goes to the
+// cache tiers by `cache_zstd_dictionary`
+fn main() {
+    let captured = std::fs::read(env::var("captured").unwrap()).unwrap();
+    let samples = dictionary::samples(&captured);
+    let max_bytes = env::var("dictionary_bytes").ok().and_then(|n| n.parse().ok()).unwrap_or(DICTIONARY_BYTES);
+    let trained = dictionary::train(&samples, max_bytes).unwrap();
+    std::fs::write(env::var("out").unwrap(), &trained).unwrap();
+    let total: usize = samples.iter().map(Vec::len).sum();
+    println!("{} bytes trained on {} documents of {} bytes", trained.len(), samples.len(), total);
+}
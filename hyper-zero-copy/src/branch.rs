@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use axum::body::{boxed, Body};
+use axum::extract::State;
+use axum::http::{Request, StatusCode, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use hyper::client::connect::Connect;
+use hyper::client::HttpConnector;
+use hyper::Client;
+use serde::Deserialize;
+use serde_zero_copy::rules::Rule;
+use serde_zero_copy::Value;
+use crate::openapi::is_json;
+
+/// Where a request goes when its body passes every rule of `when`, e.g.
+/// `{"when": [{"pointer": "/type", "op": "==", "value": "premium"}], "upstream": "http://premium:1080"}`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Branch {
+    #[serde(default)]
+    pub when: Vec<Rule>,
+    #[serde(deserialize_with = "uri")]
+    pub upstream: Uri,
+}
+
+fn uri<'de, D>(deserializer: D) -> Result<Uri, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+{
+    let uri = String::deserialize(deserializer)?;
+    uri.parse().map_err(serde::de::Error::custom)
+}
+
+// a route's branches alone, or along with what else the routes file says of it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Route {
+    Branches(Vec<Branch>),
+    Config {
+        #[serde(default)]
+        branches: Vec<Branch>,
+    },
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+struct Routes(HashMap<String, Route>);
+
+/// Sends requests to other upstreams by the content of their JSON body, from a routes file
+/// of branches by request path, the first that matches wins. Requests no branch matches go
+/// on to the proxy. A route is its list of branches, or an object with them under
+/// `branches` next to its other settings, such as [`crate::cors`] policies.
+#[derive(Clone)]
+pub struct Branches<C = HttpConnector> {
+    client: Arc<Client<C>>,
+    routes: HashMap<String, Vec<Branch>>,
+}
+
+impl<C> Branches<C> {
+    pub fn from_json(client: Arc<Client<C>>, document: &str) -> Result<Self, serde_json::Error> {
+        let Routes(routes) = serde_json::from_str(document)?;
+        let routes = routes
+            .into_iter()
+            .filter_map(|(path, route)| match route {
+                Route::Branches(branches) | Route::Config { branches } => (!branches.is_empty()).then_some((path, branches)),
+            })
+            .collect();
+        Ok(Branches { client, routes })
+    }
+
+    /// The upstream of the first branch for `path` that `value` matches.
+    pub fn select(&self, path: &str, value: &Value) -> Option<&Uri> {
+        self.routes
+            .get(path)?
+            .iter()
+            .find(|branch| branch.when.iter().all(|rule| rule.test(value)))
+            .map(|branch| &branch.upstream)
+    }
+}
+
+// `upstream` with the path and query of the request
+fn target(upstream: &Uri, request: &Uri) -> Result<Uri, axum::http::Error> {
+    let mut parts = upstream.clone().into_parts();
+    parts.path_and_query = request.path_and_query().cloned();
+    Ok(Uri::from_parts(parts)?)
+}
+
+/// Middleware for `axum::middleware::from_fn_with_state`. Bodies that aren't JSON are matched
+/// as null.
+pub async fn route<C>(State(branches): State<Arc<Branches<C>>>, request: Request<Body>, next: Next<Body>) -> Response
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+{
+    if !branches.routes.contains_key(request.uri().path()) {
+        return next.run(request).await;
+    }
+    let (mut parts, body) = request.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    };
+    let upstream = {
+        let value = match is_json(&parts.headers) {
+            true => match serde_json_nostr::from_slice(&body) {
+                Ok(value) => value,
+                Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+            },
+            false => Value::Null,
+        };
+        branches.select(parts.uri.path(), &value).cloned()
+    };
+    let Some(upstream) = upstream else {
+        return next.run(Request::from_parts(parts, Body::from(body))).await;
+    };
+    parts.uri = match target(&upstream, &parts.uri) {
+        Ok(uri) => uri,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    parts.headers.remove(hyper::header::HOST);
+    match branches.client.request(Request::from_parts(parts, Body::from(body))).await {
+        Ok(response) => response.map(boxed),
+        Err(err) => (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use axum::routing::post;
+    use axum::Router;
+    use hyper::{Body, Client, Request};
+    use serde_zero_copy::Value;
+    use crate::mock;
+    use super::{route, Branches};
+
+    #[test]
+    fn branch_select_first_match() {
+        let routes = r#"{"/orders": [
+            {"when": [{"pointer": "/type", "op": "==", "value": "premium"}], "upstream": "http://premium"},
+            {"when": [{"pointer": "/total", "op": ">", "value": 100}], "upstream": "http://large"},
+            {"upstream": "http://rest"}
+        ], "/users": {"branches": [{"upstream": "http://users"}], "cors": {"origins": ["*"]}}, "/zc": {"cors": {"origins": ["*"]}}}"#;
+        let branches = Branches::from_json(Arc::new(Client::new()), routes).unwrap();
+        let select = |body: &str| {
+            let value: Value = serde_json_nostr::from_str(body).unwrap();
+            branches.select("/orders", &value).map(ToString::to_string)
+        };
+        assert_eq!(select(r#"{"type":"premium","total":500}"#).as_deref(), Some("http://premium/"));
+        assert_eq!(select(r#"{"type":"basic","total":500}"#).as_deref(), Some("http://large/"));
+        assert_eq!(select("{}").as_deref(), Some("http://rest/"));
+        assert_eq!(branches.select("/other", &Value::Null), None);
+        assert_eq!(branches.select("/users", &Value::Null).map(ToString::to_string).as_deref(), Some("http://users/"));
+        assert!(!branches.routes.contains_key("/zc"));
+    }
+
+    #[tokio::test]
+    async fn branch_forwards_matching_requests() {
+        let premium = mock::spawn(Router::new().route("/orders", post(|body: String| async move { format!("premium {}", body) })));
+        let routes = format!(
+            r#"{{"/orders": [{{"when": [{{"pointer": "/type", "op": "==", "value": "premium"}}], "upstream": "http://{}"}}]}}"#,
+            premium
+        );
+        let branches = Arc::new(Branches::from_json(Arc::new(Client::new()), &routes).unwrap());
+        let app = Router::new()
+            .route("/orders", post(|| async { "default" }))
+            .layer(axum::middleware::from_fn_with_state(branches, route));
+        let addr = mock::spawn(app);
+
+        let send = |body: &'static str| async move {
+            let request = Request::post(format!("http://{}/orders?x=1", addr))
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            let response = Client::new().request(request).await.unwrap();
+            String::from_utf8(hyper::body::to_bytes(response).await.unwrap().to_vec()).unwrap()
+        };
+        assert_eq!(send(r#"{"type":"premium"}"#).await, r#"premium {"type":"premium"}"#);
+        assert_eq!(send(r#"{"type":"basic"}"#).await, "default");
+    }
+}
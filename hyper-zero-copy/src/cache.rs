@@ -0,0 +1,939 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime};
+use axum::async_trait;
+use axum::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use axum::http::StatusCode;
+use bytes::Bytes;
+#[cfg(feature = "rkyv")]
+use serde_zero_copy::archive;
+use serde_zero_copy::{RawJson, Symbols, Value};
+use yoke::Yoke;
+use crate::dictionary::{self, Zstd};
+
+/// A parsed value together with the buffer it borrows from.
+pub type YokedValue = Yoke<Value<'static>, Arc<Bytes>>;
+
+pub fn yoke(bytes: Bytes) -> Result<YokedValue, serde_json_nostr::Error> {
+    Yoke::try_attach_to_cart(Arc::new(bytes), |b| serde_json_nostr::from_slice(b))
+}
+
+// a spill file, JSON, compressed JSON or an archive
+fn reload(bytes: Bytes, compression: Option<&Zstd>) -> Option<YokedValue> {
+    if dictionary::is_compressed(&bytes) {
+        return yoke(Bytes::from(compression?.decompress(&bytes).ok()?)).ok();
+    }
+    #[cfg(feature = "rkyv")]
+    if archive::is_archive(&bytes) {
+        return Yoke::try_attach_to_cart(Arc::new(bytes), |b| archive::from_archive(b)).ok();
+    }
+    yoke(bytes).ok()
+}
+
+#[derive(Debug)]
+pub enum FileError {
+    Io(std::io::Error),
+    Json(serde_json_nostr::Error),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileError::Io(err) => err.fmt(f),
+            FileError::Json(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+impl From<std::io::Error> for FileError {
+    fn from(err: std::io::Error) -> Self {
+        FileError::Io(err)
+    }
+}
+
+/// A `map_above` for [`from_file_with`], shorter files gain little from being mapped.
+pub const MAP_ABOVE: u64 = 1 << 20;
+
+/// Reads the file at `path` into memory and parses it, the value borrowing from the copy.
+pub async fn from_file(path: impl AsRef<Path>) -> Result<YokedValue, FileError> {
+    let bytes = tokio::fs::read(path).await?;
+    yoke(Bytes::from(bytes)).map_err(FileError::Json)
+}
+
+/// As [`from_file`] for a file shorter than `map_above`, a longer one is mapped so that it's
+/// neither copied nor all resident at once.
+///
+/// # Safety
+///
+/// A mapped file mustn't be truncated or written over for as long as any value borrows from
+/// it, only replaced, the way editors and `rename` do; values would otherwise read what's
+/// changed under them or fault past its end.
+pub async unsafe fn from_file_with(path: impl AsRef<Path>, map_above: u64) -> Result<YokedValue, FileError> {
+    let path = path.as_ref().to_path_buf();
+    let bytes = if tokio::fs::metadata(&path).await?.len() >= map_above {
+        tokio::task::spawn_blocking(move || map(path)).await.map_err(std::io::Error::other)??
+    } else {
+        Bytes::from(tokio::fs::read(&path).await?)
+    };
+    yoke(bytes).map_err(FileError::Json)
+}
+
+/// The body `value` was parsed from, to splice into another document without serializing
+/// it again, e.g. through [`JsonWriter::value`](crate::writer::JsonWriter::value). Only
+/// for values that weren't transformed since, `None` when the body isn't utf-8 or the value
+/// was brought back from an archive.
+pub fn raw_body(value: &YokedValue) -> Option<RawJson<'_>> {
+    #[cfg(feature = "rkyv")]
+    if archive::is_archive(value.backing_cart()) {
+        return None;
+    }
+    std::str::from_utf8(value.backing_cart()).ok().map(RawJson::new_unchecked)
+}
+
+/// Compact with sorted keys, equal values give equal bytes.
+pub fn canonical_bytes(value: &YokedValue) -> Bytes {
+    // compact output is no longer than the source it was parsed from
+    let mut buf = Vec::with_capacity(value.backing_cart().len());
+    match serde_json_nostr::to_writer(&mut buf, value.get()) {
+        Ok(()) => Bytes::from(buf),
+        Err(_) => Bytes::new(),
+    }
+}
+
+#[async_trait]
+pub trait CacheTier: Send + Sync + 'static {
+    async fn get(&self, key: &str) -> Option<Arc<YokedValue>>;
+    async fn put(&self, key: &str, value: Arc<YokedValue>, ttl: Duration);
+}
+
+/// Keeps values in memory up to `budget` bytes of backing buffers, those that expire first
+/// are dropped to make room. Values bigger than that aren't kept.
+pub struct MemoryCache {
+    state: Mutex<MemoryState>,
+    budget: usize,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    entries: HashMap<String, (Instant, Arc<YokedValue>)>,
+    used: usize,
+}
+
+impl Default for MemoryCache {
+    /// Of 256MiB.
+    fn default() -> Self {
+        MemoryCache::new(256 << 20)
+    }
+}
+
+impl MemoryCache {
+    pub fn new(budget: usize) -> Self {
+        MemoryCache { state: Mutex::default(), budget }
+    }
+
+    /// Bytes of backing buffers held.
+    pub fn resident_bytes(&self) -> usize {
+        self.state.lock().unwrap().used
+    }
+}
+
+#[async_trait]
+impl CacheTier for MemoryCache {
+    async fn get(&self, key: &str) -> Option<Arc<YokedValue>> {
+        let mut state = self.state.lock().unwrap();
+        match state.entries.get(key) {
+            Some((expires, value)) if *expires > Instant::now() => Some(value.clone()),
+            Some(_) => {
+                let (_, expired) = state.entries.remove(key).unwrap();
+                state.used -= expired.backing_cart().len();
+                None
+            }
+            None => None,
+        }
+    }
+
+    async fn put(&self, key: &str, value: Arc<YokedValue>, ttl: Duration) {
+        let len = value.backing_cart().len();
+        // one that wouldn't fit on its own doesn't push out those that do
+        if len > self.budget {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if let Some((_, previous)) = state.entries.insert(key.to_string(), (Instant::now() + ttl, value)) {
+            state.used -= previous.backing_cart().len();
+        }
+        state.used += len;
+        while state.used > self.budget {
+            let first = state.entries.iter().min_by_key(|(_, (expires, _))| *expires).map(|(key, _)| key.clone()).unwrap();
+            let (_, dropped) = state.entries.remove(&first).unwrap();
+            state.used -= dropped.backing_cart().len();
+        }
+    }
+}
+
+/// Checks `near` before `far`, values found in `far` are kept in `near` for `promote_ttl`.
+/// Writes go to both.
+pub struct TieredCache {
+    near: Arc<dyn CacheTier>,
+    far: Arc<dyn CacheTier>,
+    promote_ttl: Duration,
+}
+
+impl TieredCache {
+    pub fn new(near: Arc<dyn CacheTier>, far: Arc<dyn CacheTier>, promote_ttl: Duration) -> Self {
+        TieredCache { near, far, promote_ttl }
+    }
+}
+
+#[async_trait]
+impl CacheTier for TieredCache {
+    async fn get(&self, key: &str) -> Option<Arc<YokedValue>> {
+        if let Some(value) = self.near.get(key).await {
+            return Some(value);
+        }
+        let value = self.far.get(key).await?;
+        self.near.put(key, value.clone(), self.promote_ttl).await;
+        Some(value)
+    }
+
+    async fn put(&self, key: &str, value: Arc<YokedValue>, ttl: Duration) {
+        self.near.put(key, value.clone(), ttl).await;
+        self.far.put(key, value, ttl).await;
+    }
+}
+
+/// Shares one value between all entries whose upstream bodies are byte-identical, keyed by a
+/// hash of the raw bytes. Values are only tracked while some entry still holds them.
+pub struct DedupCache {
+    inner: Arc<dyn CacheTier>,
+    seen: Mutex<HashMap<u64, Vec<Weak<YokedValue>>>>,
+    shared: AtomicU64,
+}
+
+impl DedupCache {
+    pub fn new(inner: Arc<dyn CacheTier>) -> Self {
+        DedupCache { inner, seen: Mutex::new(HashMap::new()), shared: AtomicU64::new(0) }
+    }
+
+    /// Puts that reused a value already held instead of their own.
+    pub fn shared(&self) -> u64 {
+        self.shared.load(Ordering::Relaxed)
+    }
+
+    fn intern(&self, value: Arc<YokedValue>) -> Arc<YokedValue> {
+        let bytes = value.backing_cart();
+        let mut hasher = DefaultHasher::new();
+        bytes.as_ref().as_ref().hash(&mut hasher);
+        let mut seen = self.seen.lock().unwrap();
+        let candidates = seen.entry(hasher.finish()).or_default();
+        candidates.retain(|candidate| candidate.strong_count() > 0);
+        // equal hashes are confirmed against the bytes, collisions just don't share
+        let existing = candidates.iter().filter_map(Weak::upgrade).find(|c| c.backing_cart().as_ref() == bytes.as_ref());
+        let interned = match existing {
+            Some(existing) if Arc::ptr_eq(&existing, &value) => existing,
+            Some(existing) => {
+                self.shared.fetch_add(1, Ordering::Relaxed);
+                existing
+            }
+            None => {
+                candidates.push(Arc::downgrade(&value));
+                value
+            }
+        };
+        if seen.len() > 1024 && seen.len().is_power_of_two() {
+            seen.retain(|_, candidates| candidates.iter().any(|c| c.strong_count() > 0));
+        }
+        interned
+    }
+}
+
+#[async_trait]
+impl CacheTier for DedupCache {
+    async fn get(&self, key: &str) -> Option<Arc<YokedValue>> {
+        self.inner.get(key).await
+    }
+
+    async fn put(&self, key: &str, value: Arc<YokedValue>, ttl: Duration) {
+        let value = self.intern(value);
+        self.inner.put(key, value, ttl).await;
+    }
+}
+
+/// Replaces the keys values own, those that had escapes in their upstream bodies, with
+/// `symbols` shared by every entry, before passing them on to `inner`. Borrowed keys are
+/// left as they are, they're part of a body that's held anyway. Such a value is interned in
+/// place if the entry is all that holds it, one that's held elsewhere too costs a copy of
+/// its tree, the body it borrows from isn't copied.
+pub struct InternedCache {
+    inner: Arc<dyn CacheTier>,
+    symbols: &'static Symbols,
+    interned: AtomicU64,
+}
+
+impl InternedCache {
+    pub fn new(inner: Arc<dyn CacheTier>, symbols: &'static Symbols) -> Self {
+        InternedCache { inner, symbols, interned: AtomicU64::new(0) }
+    }
+
+    /// Keys replaced with symbols so far.
+    pub fn interned(&self) -> u64 {
+        self.interned.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl CacheTier for InternedCache {
+    async fn get(&self, key: &str) -> Option<Arc<YokedValue>> {
+        self.inner.get(key).await
+    }
+
+    async fn put(&self, key: &str, value: Arc<YokedValue>, ttl: Duration) {
+        let value = if value.get().has_owned_keys() {
+            let mut interned = 0;
+            let value = Arc::try_unwrap(value).unwrap_or_else(|shared| (*shared).clone());
+            let value = value.map_project(|mut value, _| {
+                interned = value.intern_keys(self.symbols);
+                value
+            });
+            self.interned.fetch_add(interned as u64, Ordering::Relaxed);
+            Arc::new(value)
+        } else {
+            value
+        };
+        self.inner.put(key, value, ttl).await;
+    }
+}
+
+/// Keeps parsed values in memory up to `budget` bytes of backing buffers, least recently
+/// used ones beyond that are written to a directory of their own under `dir` and come back
+/// memory mapped and re-parsed, or with [`SpillCache::archived`] walked through as they're
+/// mapped. Their files are removed once they expire or are replaced, and with their directory
+/// when the cache is dropped.
+pub struct SpillCache {
+    dir: PathBuf,
+    budget: usize,
+    state: Mutex<SpillState>,
+    archived: bool,
+    compression: Option<Arc<Zstd>>,
+}
+
+#[derive(Default)]
+struct SpillState {
+    memory: HashMap<String, Resident>,
+    // with the number of the file, the later of two spills of a key to finish is the newer
+    spilled: HashMap<String, (Instant, u64, PathBuf)>,
+    used: usize,
+    clock: u64,
+    files: u64,
+}
+
+struct Resident {
+    expires: Instant,
+    value: Arc<YokedValue>,
+    last_used: u64,
+}
+
+impl SpillCache {
+    pub fn new(dir: impl Into<PathBuf>, budget: usize) -> std::io::Result<SpillCache> {
+        let parent = dir.into();
+        std::fs::create_dir_all(&parent)?;
+        // so that instances sharing `dir`, in this process or another, never map each other's
+        let mut n = 0;
+        let dir = loop {
+            let dir = parent.join(format!("{}-{}", std::process::id(), n));
+            match std::fs::create_dir(&dir) {
+                Ok(()) => break dir,
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => n += 1,
+                Err(err) => return Err(err),
+            }
+        };
+        Ok(SpillCache { dir, budget, state: Mutex::new(SpillState::default()), archived: false, compression: None })
+    }
+
+    /// Spills values compressed, they come back decompressed into memory rather than mapped.
+    /// Archives are spilled as they are, to be walked through as they're mapped.
+    pub fn compressed(mut self, compression: Arc<Zstd>) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Spills values as rkyv archives rather than their JSON, so that they come back without
+    /// being parsed. Their bodies aren't kept then, see [`raw_body`].
+    #[cfg(feature = "rkyv")]
+    pub fn archived(mut self) -> Self {
+        self.archived = true;
+        self
+    }
+
+    // what's written of a value that's spilled
+    fn spilled(archived: bool, compression: Option<&Zstd>, value: &YokedValue) -> Option<Bytes> {
+        #[cfg(feature = "rkyv")]
+        if archived {
+            return archive::to_archive(value.get()).ok().map(|archive| Bytes::from(archive.into_vec()));
+        }
+        let _ = archived;
+        match compression {
+            Some(compression) => compression.compress(value.backing_cart()).ok().map(Bytes::from),
+            None => Some(value.backing_cart().as_ref().clone()),
+        }
+    }
+
+    /// Bytes of backing buffers currently held in memory.
+    pub fn resident_bytes(&self) -> usize {
+        self.state.lock().unwrap().used
+    }
+
+    fn size(value: &YokedValue) -> usize {
+        value.backing_cart().len()
+    }
+}
+
+// the file stays mapped for as long as any value borrows from it
+fn map(path: PathBuf) -> std::io::Result<Bytes> {
+    let file = File::open(path)?;
+    // SAFETY: spill files are written once before being mapped and never modified after, the
+    // callers of `from_file_with` promise the same of theirs
+    let mmap = unsafe { memmap2::Mmap::map(&file)? };
+    Ok(Bytes::from_owner(mmap))
+}
+
+#[async_trait]
+impl CacheTier for SpillCache {
+    async fn get(&self, key: &str) -> Option<Arc<YokedValue>> {
+        let path = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            state.clock += 1;
+            let clock = state.clock;
+            if let Some(resident) = state.memory.get_mut(key) {
+                if resident.expires > now {
+                    resident.last_used = clock;
+                    return Some(resident.value.clone());
+                }
+                let expired = state.memory.remove(key).unwrap();
+                state.used -= Self::size(&expired.value);
+                return None;
+            }
+            match state.spilled.get(key) {
+                Some((expires, _, path)) if *expires > now => path.clone(),
+                Some(_) => {
+                    let (_, _, path) = state.spilled.remove(key).unwrap();
+                    let _ = std::fs::remove_file(path);
+                    return None;
+                }
+                None => return None,
+            }
+        };
+        // decompressing and parsing included
+        let compression = self.compression.clone();
+        tokio::task::spawn_blocking(move || reload(map(path).ok()?, compression.as_deref()).map(Arc::new)).await.ok()?
+    }
+
+    async fn put(&self, key: &str, value: Arc<YokedValue>, ttl: Duration) {
+        let expires = Instant::now() + ttl;
+        let evicted = {
+            let mut state = self.state.lock().unwrap();
+            state.clock += 1;
+            let clock = state.clock;
+            if let Some((_, _, path)) = state.spilled.remove(key) {
+                let _ = std::fs::remove_file(path);
+            }
+            if let Some(previous) = state.memory.insert(key.to_string(), Resident { expires, value: value.clone(), last_used: clock }) {
+                state.used -= Self::size(&previous.value);
+            }
+            state.used += Self::size(&value);
+            let mut evicted = Vec::new();
+            while state.used > self.budget {
+                let oldest = state
+                    .memory
+                    .iter()
+                    .min_by_key(|(_, resident)| resident.last_used)
+                    .map(|(key, _)| key.clone())
+                    .unwrap();
+                let resident = state.memory.remove(&oldest).unwrap();
+                state.used -= Self::size(&resident.value);
+                state.files += 1;
+                let extension = match (self.archived, &self.compression) {
+                    (true, _) => "rkyv",
+                    (false, Some(_)) => "json.zst",
+                    (false, None) => "json",
+                };
+                evicted.push((oldest, resident, state.files, self.dir.join(format!("{}.{}", state.files, extension))));
+            }
+            // files of entries that expired without being asked for again go as others come
+            if !evicted.is_empty() {
+                let now = Instant::now();
+                state.spilled.retain(|_, (expires, _, path)| {
+                    let live = *expires > now;
+                    if !live {
+                        let _ = std::fs::remove_file(path);
+                    }
+                    live
+                });
+            }
+            evicted
+        };
+        // written outside the lock, a get in the meantime is a miss rather than a wait
+        for (key, resident, file, path) in evicted {
+            let (archived, compression, value, written) = (self.archived, self.compression.clone(), resident.value.clone(), path.clone());
+            let result = tokio::task::spawn_blocking(move || {
+                let spilled = Self::spilled(archived, compression.as_deref(), &value).ok_or(())?;
+                std::fs::write(written, spilled).map_err(drop)
+            })
+                .await;
+            if let Ok(Ok(())) = result {
+                let mut state = self.state.lock().unwrap();
+                // put again or spilled again since, whichever is older goes
+                let replaced = match state.spilled.get(&key) {
+                    _ if state.memory.contains_key(&key) => Some(path),
+                    Some((_, newer, _)) if *newer > file => Some(path),
+                    _ => state.spilled.insert(key, (resident.expires, file, path)).map(|(_, _, old)| old),
+                };
+                if let Some(path) = replaced {
+                    let _ = std::fs::remove_file(path);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for SpillCache {
+    fn drop(&mut self) {
+        // values mapped from them stay readable, the mappings outlive the names
+        let Ok(state) = self.state.get_mut() else {
+            return;
+        };
+        for (_, (_, _, path)) in state.spilled.drain() {
+            let _ = std::fs::remove_file(path);
+        }
+        let _ = std::fs::remove_dir(&self.dir);
+    }
+}
+
+/// Shared between proxy instances. Values are stored as their canonical bytes and fetched
+/// bytes become the cart of the re-parsed value as they are, without copying. Redis errors
+/// are treated as misses, the upstream stays the source of truth. With
+/// [`RedisCache::compressed`] they're stored compressed and decompressed into a cart.
+#[cfg(feature = "redis")]
+pub struct RedisCache {
+    connection: redis::aio::ConnectionManager,
+    prefix: String,
+    compression: Option<Arc<Zstd>>,
+}
+
+#[cfg(feature = "redis")]
+impl RedisCache {
+    pub async fn connect(url: &str, prefix: impl Into<String>) -> redis::RedisResult<RedisCache> {
+        let connection = redis::Client::open(url)?.get_connection_manager().await?;
+        Ok(RedisCache { connection, prefix: prefix.into(), compression: None })
+    }
+
+    pub fn compressed(mut self, compression: Arc<Zstd>) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl CacheTier for RedisCache {
+    async fn get(&self, key: &str) -> Option<Arc<YokedValue>> {
+        let bytes: Option<Vec<u8>> = redis::cmd("GET")
+            .arg(format!("{}{}", self.prefix, key))
+            .query_async(&mut self.connection.clone())
+            .await
+            .ok()?;
+        let bytes = bytes?;
+        let bytes = match self.compression.clone() {
+            Some(compression) if dictionary::is_compressed(&bytes) => tokio::task::spawn_blocking(move || compression.decompress(&bytes)).await.ok()?.ok()?,
+            _ => bytes,
+        };
+        yoke(Bytes::from(bytes)).ok().map(Arc::new)
+    }
+
+    async fn put(&self, key: &str, value: Arc<YokedValue>, ttl: Duration) {
+        let bytes = canonical_bytes(&value);
+        let bytes = match self.compression.clone() {
+            Some(compression) => match tokio::task::spawn_blocking(move || compression.compress(&bytes)).await {
+                Ok(Ok(compressed)) => Bytes::from(compressed),
+                _ => return,
+            },
+            None => bytes,
+        };
+        let _: redis::RedisResult<()> = redis::cmd("SET")
+            .arg(format!("{}{}", self.prefix, key))
+            .arg(bytes.as_ref())
+            .arg("PX")
+            .arg(ttl.as_millis().max(1) as u64)
+            .query_async(&mut self.connection.clone())
+            .await;
+    }
+}
+
+/// What an upstream response says about keeping it, from its `Cache-Control`, `Expires`,
+/// `Age`, `Vary` and `ETag` headers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Directives {
+    /// From now, `None` when the response doesn't say.
+    pub fresh_for: Option<Duration>,
+    /// `no-store`, `no-cache`, `private` or `Vary: *`. Nothing is revalidated, so responses
+    /// that would need it aren't kept at all.
+    pub no_store: bool,
+    /// The request headers the response depends on.
+    pub vary: Vec<HeaderName>,
+    pub etag: Option<HeaderValue>,
+}
+
+impl Directives {
+    pub fn from_headers(headers: &HeaderMap, now: SystemTime) -> Self {
+        let values = |name| headers.get_all(name).iter().filter_map(|v| v.to_str().ok()).flat_map(|v| v.split(','));
+        let mut directives = Directives { etag: headers.get(header::ETAG).cloned(), ..Directives::default() };
+        let (mut max_age, mut s_maxage) = (None, None);
+        for directive in values(header::CACHE_CONTROL) {
+            let (name, value) = directive.split_once('=').unwrap_or((directive, ""));
+            let seconds = value.trim().trim_matches('"').parse::<u64>().ok();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "no-store" | "no-cache" | "private" => directives.no_store = true,
+                "max-age" => max_age = seconds,
+                "s-maxage" => s_maxage = seconds,
+                _ => {}
+            }
+        }
+        let age = headers.get(header::AGE).and_then(|v| v.to_str().ok()?.parse().ok()).unwrap_or(0);
+        directives.fresh_for = match s_maxage.or(max_age) {
+            Some(seconds) => Some(Duration::from_secs(u64::saturating_sub(seconds, age))),
+            // a date that doesn't parse, such as `0`, is in the past
+            None => headers.get(header::EXPIRES).map(|expires| {
+                let at = expires.to_str().ok().and_then(|at| httpdate::parse_http_date(at).ok());
+                at.and_then(|at| at.duration_since(now).ok()).unwrap_or(Duration::ZERO)
+            }),
+        };
+        for name in values(header::VARY).map(str::trim).filter(|name| !name.is_empty()) {
+            match HeaderName::from_bytes(name.as_bytes()) {
+                Ok(name) if name != "*" => directives.vary.push(name),
+                _ => directives.no_store = true,
+            }
+        }
+        directives
+    }
+}
+
+/// What the proxy consults before going upstream, added as an `Extension` layer. Entries are
+/// kept as long as the upstream response says they're fresh, `ttl` when it doesn't say, and
+/// per request header it `Vary`s by. Only responses of a status that's cacheable by default,
+/// such as 200 or 404, are kept. A miss forgets what's known of the uri's `Vary` and the
+/// entry's `ETag`, the response fetched next tells again.
+#[derive(Clone)]
+pub struct Cache {
+    pub tier: Arc<dyn CacheTier>,
+    pub ttl: Duration,
+    /// By route path, used whatever responses say, zero to not cache the route.
+    pub overrides: HashMap<String, Duration>,
+    // the headers the last response for a uri varied by, and the etags of entries, both no
+    // more than one per uri or entry and for no longer than it's a hit
+    vary: Arc<Mutex<HashMap<String, Vec<HeaderName>>>>,
+    etags: Arc<Mutex<HashMap<String, HeaderValue>>>,
+}
+
+impl Cache {
+    pub fn new(tier: Arc<dyn CacheTier>, ttl: Duration) -> Self {
+        Cache { tier, ttl, overrides: HashMap::new(), vary: Arc::default(), etags: Arc::default() }
+    }
+
+    pub fn with_override(mut self, route: &str, ttl: Duration) -> Self {
+        self.overrides.insert(route.to_string(), ttl);
+        self
+    }
+
+    /// The entry for `uri` that a request with `request` headers gets.
+    pub fn key(&self, uri: &str, request: &HeaderMap) -> String {
+        let mut key = uri.to_string();
+        if let Some(names) = self.vary.lock().unwrap().get(uri) {
+            for name in names {
+                let value = request.get(name).and_then(|v| v.to_str().ok()).unwrap_or_default();
+                key.push_str(&format!("\n{}: {}", name, value));
+            }
+        }
+        key
+    }
+
+    /// How long to keep a response of `route`, `None` for not at all.
+    pub fn ttl(&self, route: &str, directives: &Directives) -> Option<Duration> {
+        let ttl = match self.overrides.get(route) {
+            Some(ttl) => *ttl,
+            None if directives.no_store => return None,
+            None => directives.fresh_for.unwrap_or(self.ttl),
+        };
+        Some(ttl).filter(|ttl| !ttl.is_zero())
+    }
+
+    /// The value and the upstream `ETag` it came with, of the entry for `uri` at `key`.
+    pub async fn get(&self, uri: &str, key: &str) -> Option<(Arc<YokedValue>, Option<HeaderValue>)> {
+        let Some(value) = self.tier.get(key).await else {
+            self.etags.lock().unwrap().remove(key);
+            self.vary.lock().unwrap().remove(uri);
+            return None;
+        };
+        Some((value, self.etags.lock().unwrap().get(key).cloned()))
+    }
+
+    /// Keeps `value`, fetched from `uri` for a request of `route`, as its `status` and
+    /// `response` headers say.
+    pub async fn put(&self, route: &str, uri: &str, request: &HeaderMap, status: StatusCode, response: &HeaderMap, value: Arc<YokedValue>) {
+        // RFC 9110's heuristically cacheable ones
+        if !matches!(status.as_u16(), 200 | 203 | 204 | 206 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501) {
+            return;
+        }
+        let directives = Directives::from_headers(response, SystemTime::now());
+        if directives.vary.is_empty() {
+            self.vary.lock().unwrap().remove(uri);
+        } else {
+            self.vary.lock().unwrap().insert(uri.to_string(), directives.vary.clone());
+        }
+        let Some(ttl) = self.ttl(route, &directives) else {
+            return;
+        };
+        let key = self.key(uri, request);
+        match directives.etag {
+            Some(etag) => self.etags.lock().unwrap().insert(key.clone(), etag),
+            None => self.etags.lock().unwrap().remove(&key),
+        };
+        self.tier.put(&key, value, ttl).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use bytes::Bytes;
+    use std::time::SystemTime;
+    use axum::http::{HeaderMap, HeaderValue, StatusCode};
+    use super::{canonical_bytes, from_file, from_file_with, raw_body, yoke, Cache, CacheTier, DedupCache, Directives, FileError, InternedCache, MemoryCache, SpillCache, Symbols, TieredCache, Value, YokedValue, Zstd};
+
+    #[tokio::test]
+    async fn from_file_reads_or_maps() {
+        let path = "../serde-zero-copy/src/sample.json";
+        let expected: serde_zero_copy::Value = serde_json_nostr::from_slice(crate::mock::SAMPLE_JSON).unwrap();
+        let read = from_file(path).await.unwrap();
+        // SAFETY: a source file of the repository, nothing writes to it
+        let mapped = unsafe { from_file_with(path, 0) }.await.unwrap();
+        assert_eq!(read.get(), &expected);
+        assert_eq!(mapped.get(), &expected);
+        assert!(matches!(from_file("missing.json").await, Err(FileError::Io(_))));
+        assert!(matches!(from_file("Cargo.toml").await, Err(FileError::Json(_))));
+    }
+
+    #[test]
+    fn raw_bodies_splice_verbatim() {
+        let users = yoke(Bytes::from_static(br#"[{"id": 1}, {"id": 2}]"#)).unwrap();
+        let orders = yoke(Bytes::from_static(br#"{"total": 3.50}"#)).unwrap();
+        let mut out = crate::writer::JsonWriter::default();
+        out.begin_object();
+        out.key("users").value(&raw_body(&users).unwrap()).unwrap();
+        out.key("orders").value(&raw_body(&orders).unwrap()).unwrap();
+        out.key("count").num(2).end();
+        assert_eq!(out.finish(), r#"{"users":[{"id": 1}, {"id": 2}],"orders":{"total": 3.50},"count":2}"#);
+        assert!(raw_body(&yoke(Bytes::from_static(b"\"\xff\"")).unwrap()).is_none());
+    }
+
+    #[test]
+    fn canonical_bytes_reyoke() {
+        let value = yoke(Bytes::from_static(br#"{ "b": [1, "x\ny"], "a": null }"#)).unwrap();
+        let canonical = canonical_bytes(&value);
+        assert_eq!(canonical.as_ref(), br#"{"a":null,"b":[1,"x\ny"]}"#);
+        let again = yoke(canonical.clone()).unwrap();
+        assert_eq!(again.get(), value.get());
+        assert_eq!(again.backing_cart().as_ptr(), canonical.as_ptr());
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs.iter().map(|(name, value)| (name.parse().unwrap(), HeaderValue::from_static(value))).collect()
+    }
+
+    #[test]
+    fn directives_from_upstream_headers() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let directives = Directives::from_headers(&headers(&[("cache-control", "public, max-age=60, s-maxage=\"30\""), ("age", "10"), ("vary", "Accept-Language"), ("etag", "\"v1\"")]), now);
+        assert_eq!(directives.fresh_for, Some(Duration::from_secs(20)));
+        assert_eq!(directives.vary, ["accept-language"]);
+        assert_eq!(directives.etag, Some(HeaderValue::from_static("\"v1\"")));
+        assert!(!directives.no_store);
+
+        let expires = headers(&[("expires", "Tue, 14 Nov 2023 22:14:20 GMT")]);
+        assert_eq!(Directives::from_headers(&expires, now).fresh_for, Some(Duration::from_secs(60)));
+        assert_eq!(Directives::from_headers(&headers(&[("expires", "0")]), now).fresh_for, Some(Duration::ZERO));
+        assert!(Directives::from_headers(&headers(&[("cache-control", "No-Store")]), now).no_store);
+        assert!(Directives::from_headers(&headers(&[("vary", "*")]), now).no_store);
+        assert_eq!(Directives::from_headers(&HeaderMap::new(), now), Directives::default());
+    }
+
+    #[tokio::test]
+    async fn cache_keeps_what_upstreams_allow() {
+        let cache = Cache::new(Arc::new(MemoryCache::default()), Duration::from_secs(60)).with_override("/fixed", Duration::from_secs(5));
+        let value = Arc::new(yoke(Bytes::from_static(b"[1]")).unwrap());
+        let (english, french) = (headers(&[("accept-language", "en")]), headers(&[("accept-language", "fr")]));
+        let varying = headers(&[("vary", "accept-language"), ("etag", "\"en\"")]);
+        cache.put("/zc", "http://up/a", &english, StatusCode::OK, &varying, value.clone()).await;
+        let (hit, etag) = cache.get("http://up/a", &cache.key("http://up/a", &english)).await.unwrap();
+        assert!(Arc::ptr_eq(&hit, &value));
+        assert_eq!(etag, Some(HeaderValue::from_static("\"en\"")));
+        assert!(cache.get("http://up/a", &cache.key("http://up/a", &french)).await.is_none());
+        assert!(cache.vary.lock().unwrap().is_empty() && cache.etags.lock().unwrap().len() == 1);
+
+        let private = headers(&[("cache-control", "private")]);
+        cache.put("/zc", "http://up/b", &HeaderMap::new(), StatusCode::OK, &private, value.clone()).await;
+        assert!(cache.get("http://up/b", "http://up/b").await.is_none());
+        cache.put("/fixed", "http://up/b", &HeaderMap::new(), StatusCode::OK, &private, value.clone()).await;
+        assert!(cache.get("http://up/b", "http://up/b").await.is_some());
+        cache.put("/zc", "http://up/c", &HeaderMap::new(), StatusCode::SERVICE_UNAVAILABLE, &HeaderMap::new(), value.clone()).await;
+        assert!(cache.get("http://up/c", "http://up/c").await.is_none());
+        assert_eq!(cache.ttl("/zc", &Directives::default()), Some(Duration::from_secs(60)));
+    }
+
+    #[tokio::test]
+    async fn tiered_cache_promotes_far_hits() {
+        let near = Arc::new(MemoryCache::default());
+        let far = Arc::new(MemoryCache::default());
+        let tiered = TieredCache::new(near.clone(), far.clone(), Duration::from_secs(60));
+        let value = Arc::new(yoke(Bytes::from_static(b"[1]")).unwrap());
+
+        far.put("k", value.clone(), Duration::from_secs(60)).await;
+        assert!(near.get("k").await.is_none());
+        assert!(Arc::ptr_eq(&tiered.get("k").await.unwrap(), &value));
+        assert!(near.get("k").await.is_some());
+
+        tiered.put("short", value, Duration::ZERO).await;
+        assert!(tiered.get("short").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn memory_cache_keeps_to_its_budget() {
+        let cache = MemoryCache::new(8);
+        let value = |json: &'static [u8]| Arc::new(yoke(Bytes::from_static(json)).unwrap());
+        cache.put("a", value(b"[1,2]"), Duration::from_secs(60)).await;
+        cache.put("b", value(b"[3]"), Duration::from_secs(30)).await;
+        assert_eq!(cache.resident_bytes(), 8);
+        // what expires first makes room
+        cache.put("c", value(b"[4]"), Duration::from_secs(90)).await;
+        assert!(cache.get("b").await.is_none() && cache.get("a").await.is_some());
+        cache.put("d", value(b"[1,2,3,4,5]"), Duration::from_secs(60)).await;
+        assert!(cache.get("d").await.is_none());
+        assert_eq!(cache.resident_bytes(), 8);
+    }
+
+    #[tokio::test]
+    async fn dedup_cache_shares_identical_bodies() {
+        let cache = DedupCache::new(Arc::new(MemoryCache::default()));
+        let ttl = Duration::from_secs(60);
+        let catalog = br#"{"items":[1,2,3]}"#;
+        let first = Arc::new(yoke(Bytes::copy_from_slice(catalog)).unwrap());
+        cache.put("/catalog?page=1", first.clone(), ttl).await;
+        cache.put("/catalog?sort=asc", Arc::new(yoke(Bytes::copy_from_slice(catalog)).unwrap()), ttl).await;
+        cache.put("/catalog?page=1", first.clone(), ttl).await;
+        cache.put("/other", Arc::new(yoke(Bytes::from_static(b"[]")).unwrap()), ttl).await;
+
+        assert!(Arc::ptr_eq(&cache.get("/catalog?sort=asc").await.unwrap(), &first));
+        assert!(!Arc::ptr_eq(&cache.get("/other").await.unwrap(), &first));
+        assert_eq!(cache.shared(), 1);
+    }
+
+    #[tokio::test]
+    async fn spill_cache_maps_evicted_values() {
+        let dir = std::env::temp_dir().join(format!("hyper-zero-copy-spill-{}", std::process::id()));
+        let cache = SpillCache::new(&dir, 16).unwrap();
+        let files = cache.dir.clone();
+        let ttl = Duration::from_secs(60);
+        let a = Arc::new(yoke(Bytes::from_static(br#"{"name":"aaaaaa"}"#)).unwrap());
+        let b = Arc::new(yoke(Bytes::from_static(br#"[1, 2, 3]"#)).unwrap());
+
+        cache.put("b", b.clone(), ttl).await;
+        cache.put("a", a.clone(), ttl).await;
+        // `a` alone is over budget, `b` was used least recently so both spill
+        assert_eq!(cache.resident_bytes(), 0);
+        assert_eq!(std::fs::read_dir(&files).unwrap().count(), 2);
+        let spilled = cache.get("a").await.unwrap();
+        assert_eq!(spilled.get(), a.get());
+        assert_ne!(spilled.backing_cart().as_ptr(), a.backing_cart().as_ptr());
+
+        cache.put("b", b.clone(), ttl).await;
+        assert_eq!(cache.resident_bytes(), 9);
+        assert!(Arc::ptr_eq(&cache.get("b").await.unwrap(), &b));
+        assert_eq!(std::fs::read_dir(&files).unwrap().count(), 1);
+
+        // `a` expires spilled, its file goes with the next to spill
+        cache.put("a", a.clone(), Duration::ZERO).await;
+        cache.put("c", a.clone(), ttl).await;
+        assert_eq!(std::fs::read_dir(&files).unwrap().count(), 2);
+        // another instance spilling to the same directory keeps to its own files
+        let other = SpillCache::new(&dir, 0).unwrap();
+        other.put("a", b.clone(), ttl).await;
+        assert_eq!(std::fs::read_dir(&files).unwrap().count(), 2);
+        assert_eq!(cache.get("c").await.unwrap().get(), a.get());
+        drop((cache, other));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        // what was mapped stays readable
+        assert_eq!(spilled.get(), a.get());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn interned_entries_share_their_keys() {
+        let memory = Arc::new(MemoryCache::default());
+        let cache = InternedCache::new(memory.clone(), Symbols::global());
+        for key in ["a", "b"] {
+            cache.put(key, Arc::new(yoke(Bytes::from_static(br#"{"x\u0079":1,"z":[{"q\"":2}]}"#)).unwrap()), Duration::from_secs(60)).await;
+        }
+        assert_eq!(cache.interned(), 4);
+        let key = |value: &YokedValue| match value.get() {
+            Value::Object(map) => map.keys().next().unwrap().as_ptr(),
+            _ => panic!(),
+        };
+        let (a, b) = (memory.get("a").await.unwrap(), memory.get("b").await.unwrap());
+        assert_eq!(key(&a), key(&b));
+        assert!(!a.get().has_owned_keys());
+        assert_eq!(serde_json_nostr::to_string(a.get()).unwrap(), r#"{"xy":1,"z":[{"q\"":2}]}"#);
+        // one that's held elsewhere is copied, what's held stays as it was
+        let held = Arc::new(yoke(Bytes::from_static(br#"{"x\u0079":1}"#)).unwrap());
+        cache.put("c", held.clone(), Duration::from_secs(60)).await;
+        assert!(held.get().has_owned_keys() && !memory.get("c").await.unwrap().get().has_owned_keys());
+    }
+
+    #[tokio::test]
+    async fn spill_cache_compresses_evicted_values() {
+        let dir = std::env::temp_dir().join(format!("hyper-zero-copy-zstd-{}", std::process::id()));
+        let cache = SpillCache::new(&dir, 0).unwrap().compressed(Arc::new(Zstd::new(3)));
+        let a = Arc::new(yoke(Bytes::from(format!("[{}]", vec![r#"{"name":"a"}"#; 100].join(",")))).unwrap());
+        cache.put("a", a.clone(), Duration::from_secs(60)).await;
+
+        let file = std::fs::read_dir(&cache.dir).unwrap().next().unwrap().unwrap().path();
+        assert!(file.to_str().unwrap().ends_with(".json.zst"));
+        assert!(std::fs::metadata(&file).unwrap().len() < a.backing_cart().len() as u64 / 4);
+        assert_eq!(cache.get("a").await.unwrap().get(), a.get());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[tokio::test]
+    async fn spill_cache_archives_evicted_values() {
+        let dir = std::env::temp_dir().join(format!("hyper-zero-copy-archive-{}", std::process::id()));
+        let cache = SpillCache::new(&dir, 0).unwrap().archived();
+        let a = Arc::new(yoke(Bytes::from_static(br#"{"name":"a \"b\"","n":[1,2.5]}"#)).unwrap());
+        cache.put("a", a.clone(), Duration::from_secs(60)).await;
+        assert_eq!(cache.resident_bytes(), 0);
+
+        let spilled = cache.get("a").await.unwrap();
+        assert_eq!(serde_json_nostr::to_string(spilled.get()).unwrap(), serde_json_nostr::to_string(a.get()).unwrap());
+        assert!(matches!(spilled.get().pointer("/name"), Some(serde_zero_copy::Value::Str("a \"b\""))));
+        assert!(raw_body(&spilled).is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
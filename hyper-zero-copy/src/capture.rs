@@ -0,0 +1,482 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use arrow_schema::SchemaRef;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use serde::Serialize;
+use serde_zero_copy::arrow::{self as arrow, ArrowOptions};
+use serde_zero_copy::Value;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::transform::Context;
+
+#[derive(Debug, Clone)]
+pub struct CaptureConfig {
+    pub dir: PathBuf,
+    /// Top level keys or JSON pointers, e.g. `id` or `/user/name`, each becomes a column.
+    pub fields: Vec<String>,
+    /// Rows waiting for the writer, once full further rows are dropped rather than waited for.
+    pub queue: usize,
+    /// Rows per row group.
+    pub batch_rows: usize,
+    /// Rows per file before rotating to the next one.
+    pub file_rows: usize,
+    /// How long after its first row a file is closed at the latest, the rows still waiting
+    /// for a full batch written to it first. Until it's closed a file can't be read.
+    pub file_every: Option<Duration>,
+}
+
+impl CaptureConfig {
+    /// Closing files a minute after they're started.
+    pub fn new(dir: impl Into<PathBuf>, fields: Vec<String>) -> Self {
+        CaptureConfig {
+            dir: dir.into(),
+            fields,
+            queue: 1024,
+            batch_rows: 1024,
+            file_rows: 1 << 20,
+            file_every: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Parquet(ParquetError),
+    Arrow(arrow::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "capture io error: {}", err),
+            Error::Parquet(err) => write!(f, "capture parquet error: {}", err),
+            Error::Arrow(err) => write!(f, "capture conversion error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<ParquetError> for Error {
+    fn from(err: ParquetError) -> Self {
+        Error::Parquet(err)
+    }
+}
+
+struct Row {
+    route: String,
+    timestamp_ms: u64,
+    // one per configured field, in order
+    values: Vec<Value<'static>>,
+}
+
+/// Handle for recording proxied values, cheap to clone into handlers. The writer task exits
+/// and closes the current file once every handle is dropped, which is to be awaited on the
+/// way out so that the file gets its footer. A batch it fails to write is dropped, and the
+/// file it went to closed as far as it got.
+#[derive(Clone)]
+pub struct Capture {
+    tx: mpsc::Sender<Row>,
+    fields: Arc<[String]>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Capture {
+    pub fn spawn(config: CaptureConfig) -> (Capture, JoinHandle<Result<(), Error>>) {
+        let (tx, rx) = mpsc::channel(config.queue.max(1));
+        let capture = Capture {
+            tx,
+            fields: config.fields.clone().into(),
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+        let dropped = capture.dropped.clone();
+        let writer = tokio::task::spawn_blocking(move || write_rows(config, rx, dropped));
+        (capture, writer)
+    }
+
+    /// Queues the selected fields of `value` without waiting, the row is dropped if the
+    /// writer has fallen behind.
+    pub fn record(&self, route: &str, value: &Value) {
+        let row = Row {
+            route: route.to_string(),
+            timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64),
+            values: self.fields.iter().map(|field| select(value, field).map_or(Value::Null, to_column)).collect(),
+        };
+        if self.tx.try_send(row).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Rows dropped so far because the queue was full, the writer had stopped or failed to
+    /// write them.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+fn select<'v, 'a>(value: &'v Value<'a>, field: &str) -> Option<&'v Value<'a>> {
+    if field.starts_with('/') {
+        return value.pointer(field);
+    }
+    match value {
+        Value::Object(map) => map.get(field),
+        _ => None,
+    }
+}
+
+// Owned and flat, nested values are kept as their JSON text.
+fn to_column(value: &Value) -> Value<'static> {
+    match value {
+        Value::Null => Value::Null,
+        Value::Bool(b) => Value::Bool(*b),
+        Value::Number(n) => Value::Number(n.clone()),
+        Value::Bytes(b) => Value::String(String::from_utf8_lossy(b).into_owned()),
+        Value::Str(s) => Value::String(s.to_string()),
+        Value::String(s) => Value::String(s.clone()),
+        nested => Value::String(serde_json_nostr::to_string(nested).unwrap_or_default()),
+    }
+}
+
+// a line of the archive, as read back as a `crate::replay::Record`
+#[derive(Serialize)]
+struct Exchange<'r, 'u, 's> {
+    path: &'r str,
+    upstream: &'r Value<'u>,
+    served: &'r Value<'s>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<&'r str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    origin: Option<&'r str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accept_language: Option<&'r str>,
+}
+
+/// Handle for recording what a route fetched and served, whole, for [`crate::replay`] to go
+/// over: a line of NDJSON an exchange, appended to a file. Cheap to clone into handlers and
+/// added as an `Extension` layer. Lines wait for the writer up to `queue`, further ones are
+/// dropped, as are those it fails to write. The writer exits once every handle is dropped.
+#[derive(Clone)]
+pub struct Archive {
+    tx: mpsc::Sender<Vec<u8>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Archive {
+    pub fn spawn(path: impl Into<PathBuf>, queue: usize) -> std::io::Result<(Archive, JoinHandle<()>)> {
+        let path = path.into();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let (tx, rx) = mpsc::channel(queue.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let writer = tokio::task::spawn_blocking({
+            let dropped = dropped.clone();
+            move || write_lines(path, file, rx, dropped)
+        });
+        Ok((Archive { tx, dropped }, writer))
+    }
+
+    /// Queues the exchange without waiting, `route` with the query of `cx` as its path.
+    pub fn record(&self, route: &str, cx: &Context, upstream: &Value, served: &Value) {
+        let path = match cx.query {
+            Some(query) => format!("{}?{}", route, query),
+            None => route.to_string(),
+        };
+        let exchange = Exchange {
+            path: &path,
+            upstream,
+            served,
+            request_id: cx.request_id,
+            origin: cx.origin,
+            accept_language: cx.accept_language,
+        };
+        let sent = serde_json_nostr::to_vec(&exchange).ok().is_some_and(|mut line| {
+            line.push(b'\n');
+            self.tx.try_send(line).is_ok()
+        });
+        if !sent {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Exchanges dropped so far because the queue was full, the writer had stopped or failed
+    /// to write them.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+// writes what's waiting at once, flushed before waiting for more
+fn write_lines(path: PathBuf, file: File, mut rx: mpsc::Receiver<Vec<u8>>, dropped: Arc<AtomicU64>) {
+    let mut file = BufWriter::new(file);
+    while let Some(line) = rx.blocking_recv() {
+        let mut lines = vec![line];
+        while let Ok(line) = rx.try_recv() {
+            lines.push(line);
+        }
+        let written = lines.iter().try_for_each(|line| file.write_all(line)).and_then(|()| file.flush());
+        if let Err(err) = written {
+            eprintln!("archive of {} exchanges to {} dropped: {}", lines.len(), path.display(), err);
+            dropped.fetch_add(lines.len() as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+struct Sink {
+    writer: ArrowWriter<File>,
+    schema: SchemaRef,
+    rows: usize,
+}
+
+fn write_rows(config: CaptureConfig, mut rx: mpsc::Receiver<Row>, dropped: Arc<AtomicU64>) -> Result<(), Error> {
+    let runtime = tokio::runtime::Handle::current();
+    let mut sink: Option<Sink> = None;
+    let mut sequence = 0;
+    let mut pending = Vec::with_capacity(config.batch_rows);
+    // of the first row since the last file was closed by the clock
+    let mut since: Option<Instant> = None;
+    loop {
+        let due = since.zip(config.file_every).map(|(since, every)| tokio::time::Instant::from_std(since + every));
+        // `None` once it's due, `Some(None)` once every handle is dropped
+        let received = match due {
+            Some(due) => runtime.block_on(tokio::time::timeout_at(due, rx.recv())).ok(),
+            None => Some(rx.blocking_recv()),
+        };
+        let due = received.is_none();
+        let row = received.flatten();
+        let closed = !due && row.is_none();
+        if row.is_some() && since.is_none() {
+            since = Some(Instant::now());
+        }
+        pending.extend(row);
+        if !pending.is_empty() && (closed || due || pending.len() >= config.batch_rows) {
+            let batch = std::mem::take(&mut pending);
+            if let Err(err) = write_batch(&config, &mut sink, &mut sequence, &batch) {
+                eprintln!("capture of {} rows to {} dropped: {}", batch.len(), config.dir.display(), err);
+                dropped.fetch_add(batch.len() as u64, Ordering::Relaxed);
+                // the next batch starts a file of its own
+                if let Some(failed) = sink.take() {
+                    let _ = failed.writer.close();
+                }
+            }
+        }
+        if due {
+            since = None;
+            if let Some(Err(err)) = sink.take().map(|current| current.writer.close()) {
+                eprintln!("capture file in {} not closed: {}", config.dir.display(), err);
+            }
+        }
+        if closed {
+            break;
+        }
+    }
+    if let Some(sink) = sink {
+        sink.writer.close()?;
+    }
+    Ok(())
+}
+
+fn write_batch(config: &CaptureConfig, sink: &mut Option<Sink>, sequence: &mut usize, rows: &[Row]) -> Result<(), Error> {
+    let objects = rows
+        .iter()
+        .map(|row| {
+            let mut object: BTreeMap<Cow<str>, Value> = config
+                .fields
+                .iter()
+                .map(|field| Cow::Borrowed(field.as_str()))
+                .zip(row.values.iter().cloned())
+                .collect();
+            object.insert(Cow::Borrowed("route"), Value::Str(&row.route));
+            object.insert(Cow::Borrowed("timestamp_ms"), Value::Number(row.timestamp_ms.into()));
+            Value::Object(object)
+        })
+        .collect();
+    let objects = Value::Array(objects);
+
+    // a file keeps the schema of its first batch, rows that don't fit it start the next file
+    let batches = match sink.as_ref() {
+        Some(current) if current.rows < config.file_rows => {
+            arrow::to_record_batches_with(&objects, current.schema.clone(), rows.len()).ok()
+        }
+        _ => None,
+    };
+    let batches = match batches {
+        Some(batches) => batches,
+        None => {
+            if let Some(previous) = sink.take() {
+                previous.writer.close()?;
+            }
+            let batches = arrow::to_record_batches(&objects, ArrowOptions { infer_rows: rows.len(), batch_size: rows.len() })
+                .map_err(Error::Arrow)?;
+            let timestamp_ms = rows[0].timestamp_ms;
+            std::fs::create_dir_all(&config.dir)?;
+            let path = config.dir.join(format!("capture-{}-{}.parquet", timestamp_ms, sequence));
+            *sequence += 1;
+            let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+            let schema = batches[0].schema();
+            let writer = ArrowWriter::try_new(File::create(path)?, schema.clone(), Some(properties))?;
+            *sink = Some(Sink { writer, schema, rows: 0 });
+            batches
+        }
+    };
+    let current = sink.as_mut().unwrap();
+    for batch in &batches {
+        current.writer.write(batch)?;
+        current.rows += batch.num_rows();
+    }
+    current.writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_zero_copy::Value;
+    use crate::transform::{Context, Paginate, Transforms};
+    use super::{Archive, Capture, CaptureConfig};
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("hyper-zero-copy-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn read_rows(dir: &std::path::Path) -> Vec<(usize, Vec<String>)> {
+        let mut paths: Vec<_> = std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect();
+        paths.sort();
+        paths
+            .iter()
+            .map(|path| {
+                let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap();
+                let columns = reader.schema().fields().iter().map(|f| f.name().clone()).collect();
+                let rows = reader.build().unwrap().map(|batch| batch.unwrap().num_rows()).sum();
+                (rows, columns)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn capture_writes_rotating_files() {
+        let dir = temp_dir("capture");
+        let mut config = CaptureConfig::new(&dir, vec!["id".to_string(), "/user/name".to_string(), "tags".to_string()]);
+        config.batch_rows = 2;
+        config.file_rows = 4;
+        let (capture, writer) = Capture::spawn(config);
+        let payload: Value = serde_json_nostr::from_str(r#"{"id":7,"user":{"name":"Jane"},"tags":["a","b"]}"#).unwrap();
+        for _ in 0..5 {
+            capture.record("/zc", &payload);
+        }
+        assert_eq!(capture.dropped(), 0);
+        drop(capture);
+        writer.await.unwrap().unwrap();
+
+        let files = read_rows(&dir);
+        let columns: Vec<String> = ["/user/name", "id", "route", "tags", "timestamp_ms"].iter().map(|c| c.to_string()).collect();
+        assert_eq!(files, vec![(4, columns.clone()), (1, columns)]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn capture_closes_files_on_time() {
+        let dir = temp_dir("capture-timed");
+        let mut config = CaptureConfig::new(&dir, vec!["id".to_string()]);
+        config.file_every = Some(std::time::Duration::from_millis(20));
+        let (capture, writer) = Capture::spawn(config);
+        let payload: Value = serde_json_nostr::from_str(r#"{"id":1}"#).unwrap();
+        capture.record("/zc", &payload);
+        // well short of a batch, readable all the same while the writer still runs
+        let readable = || {
+            let mut files = std::fs::read_dir(&dir).into_iter().flatten().flatten();
+            files.any(|file| File::open(file.path()).is_ok_and(|file| ParquetRecordBatchReaderBuilder::try_new(file).is_ok()))
+        };
+        while !readable() {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        assert_eq!(read_rows(&dir)[0].0, 1);
+        capture.record("/zc", &payload);
+        drop(capture);
+        writer.await.unwrap().unwrap();
+        assert_eq!(read_rows(&dir).iter().map(|(rows, _)| rows).collect::<Vec<_>>(), [&1, &1]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn capture_drops_when_queue_is_full() {
+        let dir = temp_dir("capture-full");
+        let mut config = CaptureConfig::new(&dir, vec!["id".to_string()]);
+        config.queue = 1;
+        config.batch_rows = 1;
+        let (capture, writer) = Capture::spawn(config);
+        let payload: Value = serde_json_nostr::from_str(r#"{"id":1}"#).unwrap();
+        // the writer can't keep up with a tight loop on a single slot queue
+        for _ in 0..10_000 {
+            capture.record("/zc", &payload);
+        }
+        let dropped = capture.dropped();
+        assert!(dropped > 0);
+        drop(capture);
+        writer.await.unwrap().unwrap();
+        let written: usize = read_rows(&dir).iter().map(|(rows, _)| rows).sum();
+        assert_eq!(written as u64 + dropped, 10_000);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn capture_keeps_going_past_failed_batches() {
+        let dir = temp_dir("capture-failing");
+        // where the directory should be
+        std::fs::write(&dir, b"").unwrap();
+        let mut config = CaptureConfig::new(&dir, vec!["id".to_string()]);
+        config.batch_rows = 1;
+        let (capture, writer) = Capture::spawn(config);
+        let payload: Value = serde_json_nostr::from_str(r#"{"id":1}"#).unwrap();
+        capture.record("/zc", &payload);
+        while capture.dropped() == 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        std::fs::remove_file(&dir).unwrap();
+        capture.record("/zc", &payload);
+        drop(capture);
+        writer.await.unwrap().unwrap();
+        assert_eq!(read_rows(&dir), vec![(1, vec!["id".to_string(), "route".to_string(), "timestamp_ms".to_string()])]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn archives_replay_as_they_were_served() {
+        let path = temp_dir("archive");
+        let (archive, writer) = Archive::spawn(&path, 8).unwrap();
+        let transforms = Transforms::default().with(Paginate { per_page: 10, max_per_page: 10 });
+        for query in [None, Some("page=2&per_page=1")] {
+            let upstream: Value = serde_json_nostr::from_str(r#"[{"id":1},{"id":"two"}]"#).unwrap();
+            let cx = Context { request_id: Some("r-1"), ..Context::query(query) };
+            archive.record("/zc", &cx, &upstream, &transforms.apply(upstream.clone(), &cx));
+        }
+        drop(archive);
+        writer.await.unwrap();
+
+        let archived = std::fs::read(&path).unwrap();
+        assert_eq!(archived.split(|b| *b == b'\n').filter(|line| !line.is_empty()).count(), 2);
+        let report = crate::replay::replay(&transforms, &archived[..], |changed| panic!("{:?}", changed)).unwrap();
+        assert_eq!((report.compared, report.unparsable), (2, 0));
+        let report = crate::replay::replay(&Transforms::default(), &archived[..], |_| {}).unwrap();
+        assert_eq!(report.changed, 2);
+        std::fs::remove_file(path).unwrap();
+    }
+}
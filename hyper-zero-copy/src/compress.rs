@@ -0,0 +1,189 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use flate2::write::GzEncoder;
+
+#[derive(Debug)]
+pub enum Error {
+    Encoding(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Encoding(encoding) => write!(f, "unknown encoding {:?}", encoding),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+impl Encoding {
+    /// As in `Accept-Encoding` and `Content-Encoding`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "gzip" => Ok(Encoding::Gzip),
+            "br" => Ok(Encoding::Brotli),
+            "zstd" => Ok(Encoding::Zstd),
+            other => Err(Error::Encoding(other.to_string())),
+        }
+    }
+}
+
+/// What `/zc` serves compressed by the request's `Accept-Encoding`, when the upstream's body
+/// is at least `min_bytes` long. Where the client weighs encodings the same the first of
+/// `encodings` goes. Added as an `Extension` layer on a route.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    pub encodings: Vec<Encoding>,
+    pub min_bytes: usize,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression { encodings: vec![Encoding::Zstd, Encoding::Brotli, Encoding::Gzip], min_bytes: 1024 }
+    }
+}
+
+impl Compression {
+    /// The encoding to serve a body parsed from `len` bytes in, `None` for none.
+    pub fn negotiate(&self, accept_encoding: &str, len: usize) -> Option<Encoding> {
+        if len < self.min_bytes {
+            return None;
+        }
+        let mut named = Vec::new();
+        let mut wildcard = None;
+        for coding in accept_encoding.split(',') {
+            let mut params = coding.split(';');
+            let name = params.next().unwrap_or_default().trim();
+            let q: f32 = match params.find_map(|param| param.trim().strip_prefix("q=")) {
+                Some(q) => q.trim().parse().unwrap_or(0.0),
+                None => 1.0,
+            };
+            match name {
+                "*" => wildcard = Some(q),
+                name => named.push((name, q)),
+            }
+        }
+        let mut best: Option<(f32, Encoding)> = None;
+        for &encoding in &self.encodings {
+            let q = named.iter().find(|(name, _)| name.eq_ignore_ascii_case(encoding.name())).map(|(_, q)| *q).or(wildcard);
+            match (q, best) {
+                (Some(q), Some((best_q, _))) if best_q >= q => {}
+                (Some(q), _) if q > 0.0 => best = Some((q, encoding)),
+                _ => {}
+            }
+        }
+        best.map(|(_, encoding)| encoding)
+    }
+}
+
+/// Compresses what's written on its way to `W`, or passes it on as it is.
+pub enum Encoder<W: Write> {
+    Identity(W),
+    Gzip(GzEncoder<W>),
+    Brotli(Box<brotli::CompressorWriter<W>>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(encoding: Option<Encoding>, writer: W) -> io::Result<Self> {
+        // levels for output compressed as it's served rather than once for good
+        Ok(match encoding {
+            None => Encoder::Identity(writer),
+            Some(Encoding::Gzip) => Encoder::Gzip(GzEncoder::new(writer, flate2::Compression::fast())),
+            Some(Encoding::Brotli) => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(writer, 4096, 4, 22))),
+            Some(Encoding::Zstd) => Encoder::Zstd(zstd::Encoder::new(writer, 3)?),
+        })
+    }
+
+    /// Writes what's left of the compressed stream, the end of it included.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Identity(writer) => Ok(writer),
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Brotli(encoder) => Ok(encoder.into_inner()),
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Identity(writer) => writer.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Brotli(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Identity(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Brotli(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use super::{Compression, Encoder, Encoding};
+
+    #[test]
+    fn negotiates_by_weight_then_preference() {
+        let compression = Compression::default();
+        assert_eq!(compression.negotiate("gzip, deflate, br", 2048), Some(Encoding::Brotli));
+        assert_eq!(compression.negotiate("gzip;q=1, br;q=0.5", 2048), Some(Encoding::Gzip));
+        assert_eq!(compression.negotiate("*", 2048), Some(Encoding::Zstd));
+        assert_eq!(compression.negotiate("*, zstd;q=0", 2048), Some(Encoding::Brotli));
+        assert_eq!(compression.negotiate("identity", 2048), None);
+        assert_eq!(compression.negotiate("gzip", 100), None);
+        let gzip_only = Compression { encodings: vec![Encoding::Gzip], min_bytes: 0 };
+        assert_eq!(gzip_only.negotiate("zstd, br", 0), None);
+    }
+
+    #[test]
+    fn encoders_round_trip() {
+        let input = br#"{"items":[1,2,3],"name":"abc"}"#.repeat(100);
+        for encoding in [None, Some(Encoding::Gzip), Some(Encoding::Brotli), Some(Encoding::Zstd)] {
+            let mut encoder = Encoder::new(encoding, Vec::new()).unwrap();
+            encoder.write_all(&input).unwrap();
+            let compressed = encoder.finish().unwrap();
+            let mut output = Vec::new();
+            match encoding {
+                None => output = compressed,
+                Some(Encoding::Gzip) => {
+                    flate2::read::GzDecoder::new(&compressed[..]).read_to_end(&mut output).unwrap();
+                }
+                Some(Encoding::Brotli) => {
+                    brotli::Decompressor::new(&compressed[..], 4096).read_to_end(&mut output).unwrap();
+                }
+                Some(Encoding::Zstd) => output = zstd::decode_all(&compressed[..]).unwrap(),
+            }
+            assert_eq!(output, input, "{:?}", encoding);
+        }
+    }
+}
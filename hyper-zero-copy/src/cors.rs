@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+
+fn default_methods() -> Vec<String> {
+    vec!["GET".to_string(), "HEAD".to_string()]
+}
+
+/// Which browser origins may call a route, e.g.
+/// `{"origins": ["https://app.example.com"], "headers": ["authorization"], "max_age": 600}`.
+/// `*` in `origins` or `headers` allows any, though `origins` of `*` can't go with `credentials`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    pub origins: Vec<String>,
+    #[serde(default = "default_methods")]
+    pub methods: Vec<String>,
+    /// Request headers beyond those browsers always allow.
+    #[serde(default)]
+    pub headers: Vec<String>,
+    /// Response headers scripts may read beyond the basic ones.
+    #[serde(default)]
+    pub expose: Vec<String>,
+    /// Cookies and `Authorization`, the origin is then always named rather than `*`.
+    #[serde(default)]
+    pub credentials: bool,
+    /// How long, in seconds, browsers may keep a preflight's answer.
+    pub max_age: Option<u64>,
+}
+
+impl Policy {
+    fn allows_origin(&self, origin: &str) -> bool {
+        self.origins.iter().any(|allowed| allowed == "*" || allowed == origin)
+    }
+
+    fn allows_method(&self, method: &str) -> bool {
+        self.methods.iter().any(|allowed| allowed.eq_ignore_ascii_case(method))
+    }
+
+    fn allows_headers(&self, requested: &str) -> bool {
+        requested
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .all(|name| self.headers.iter().any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(name)))
+    }
+
+    fn allow_origin(&self, origin: &HeaderValue) -> HeaderValue {
+        if self.origins.iter().any(|allowed| allowed == "*") && !self.credentials {
+            return HeaderValue::from_static("*");
+        }
+        origin.clone()
+    }
+}
+
+/// CORS policies by request path, from the `cors` of each route of a routes file (see
+/// [`crate::branch::Branches`]), e.g. `{"/zc": {"cors": {"origins": ["*"]}}}`. A route `*`
+/// covers paths without a policy of their own, requests to others are left alone.
+#[derive(Debug, Clone, Default)]
+pub struct Cors {
+    policies: HashMap<String, Policy>,
+}
+
+impl Cors {
+    pub fn from_json(document: &str) -> Result<Self, serde_json::Error> {
+        let routes: HashMap<String, serde_json::Value> = serde_json::from_str(document)?;
+        let mut policies = HashMap::new();
+        // routes that are only a list of branches have none
+        for (path, mut route) in routes {
+            if let Some(policy) = route.get_mut("cors").map(serde_json::Value::take) {
+                let policy: Policy = serde_json::from_value(policy)?;
+                // browsers wouldn't take `*` with credentials, naming every origin instead
+                // would let any page read what's behind a user's cookies
+                if policy.credentials && policy.origins.iter().any(|allowed| allowed == "*") {
+                    return Err(serde::de::Error::custom(format!("cors of {}: origins of * with credentials", path)));
+                }
+                policies.insert(path, policy);
+            }
+        }
+        Ok(Cors { policies })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    pub fn policy(&self, path: &str) -> Option<&Policy> {
+        self.policies.get(path).or_else(|| self.policies.get("*"))
+    }
+}
+
+fn join(values: &[String]) -> Option<HeaderValue> {
+    HeaderValue::from_str(&values.join(", ")).ok()
+}
+
+// answers a preflight whole, it doesn't go on to the route
+fn preflight(policy: &Policy, origin: &HeaderValue, headers: &HeaderMap) -> Response {
+    let method = headers.get(header::ACCESS_CONTROL_REQUEST_METHOD).and_then(|m| m.to_str().ok()).unwrap_or_default();
+    let requested = headers.get(header::ACCESS_CONTROL_REQUEST_HEADERS).and_then(|h| h.to_str().ok()).unwrap_or_default();
+    if !policy.allows_method(method) || !policy.allows_headers(requested) {
+        return (StatusCode::FORBIDDEN, "CORS preflight not allowed").into_response();
+    }
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    let out = response.headers_mut();
+    out.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, policy.allow_origin(origin));
+    if let Some(methods) = join(&policy.methods) {
+        out.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods);
+    }
+    // echoed, `*` isn't taken along with credentials
+    if let Some(requested) = HeaderValue::from_str(requested).ok().filter(|h| !h.is_empty()) {
+        out.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, requested);
+    }
+    if policy.credentials {
+        out.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+    }
+    if let Some(max_age) = policy.max_age {
+        out.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.into());
+    }
+    out.insert(header::VARY, HeaderValue::from_static("origin, access-control-request-method, access-control-request-headers"));
+    response
+}
+
+/// Middleware for `axum::middleware::from_fn_with_state`, in front of authentication since
+/// browsers send preflights without credentials. A preflight from an origin or for a
+/// method or headers the policy doesn't allow gets a 403, other requests from such an origin
+/// go through without CORS headers and so stay unreadable to the page.
+pub async fn handle(State(cors): State<Arc<Cors>>, request: Request<Body>, next: Next<Body>) -> Response {
+    let Some(policy) = cors.policy(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    // what's served differs by origin, with one or without any, for caches not to mix them up
+    let vary = |mut response: Response| {
+        response.headers_mut().append(header::VARY, HeaderValue::from_static("origin"));
+        response
+    };
+    let Some(origin) = request.headers().get(header::ORIGIN).cloned() else {
+        return vary(next.run(request).await);
+    };
+    let allowed = origin.to_str().is_ok_and(|origin| policy.allows_origin(origin));
+    if request.method() == Method::OPTIONS && request.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD) {
+        if !allowed {
+            return vary((StatusCode::FORBIDDEN, "CORS preflight not allowed").into_response());
+        }
+        return preflight(policy, &origin, request.headers());
+    }
+    let mut response = vary(next.run(request).await);
+    let out = response.headers_mut();
+    if !allowed {
+        return response;
+    }
+    out.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, policy.allow_origin(&origin));
+    if policy.credentials {
+        out.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+    }
+    if let Some(expose) = join(&policy.expose).filter(|_| !policy.expose.is_empty()) {
+        out.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, expose);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use axum::http::header;
+    use axum::routing::get;
+    use axum::Router;
+    use hyper::{Body, Client, Method, Request, StatusCode};
+    use crate::mock;
+    use super::{handle, Cors};
+
+    const ROUTES: &str = r#"{
+        "/orders": [{"upstream": "http://orders"}],
+        "/zc": {"cors": {"origins": ["https://app.example.com"], "headers": ["authorization"], "expose": ["etag"], "credentials": true, "max_age": 600}},
+        "*": {"cors": {"origins": ["*"]}}
+    }"#;
+
+    #[test]
+    fn policies_from_routes_file() {
+        let cors = Cors::from_json(ROUTES).unwrap();
+        assert_eq!(cors.policy("/zc").unwrap().max_age, Some(600));
+        assert_eq!(cors.policy("/orders").unwrap().origins, ["*"]);
+        assert_eq!(cors.policy("/other").unwrap().methods, ["GET", "HEAD"]);
+        assert!(Cors::from_json(r#"{"/zc": {"cors": {"origin": ["*"]}}}"#).is_err());
+        assert!(Cors::from_json(r#"{"/zc": {"cors": {"origins": ["*"], "credentials": true}}}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn preflights_and_requests() {
+        let app = Router::new()
+            .route("/zc", get(|| async { "[]" }))
+            .layer(axum::middleware::from_fn_with_state(Arc::new(Cors::from_json(ROUTES).unwrap()), handle));
+        let addr = mock::spawn(app);
+        let send = |method: Method, origin: &str, headers: &[(&str, &str)]| {
+            let mut request = Request::builder().method(method).uri(format!("http://{}/zc", addr)).header(header::ORIGIN, origin);
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            Client::new().request(request.body(Body::empty()).unwrap())
+        };
+
+        let preflight = [("access-control-request-method", "GET"), ("access-control-request-headers", "Authorization")];
+        let response = send(Method::OPTIONS, "https://app.example.com", &preflight).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS], "Authorization");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        let response = send(Method::OPTIONS, "https://evil.example.com", &preflight).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = send(Method::OPTIONS, "https://app.example.com", &[("access-control-request-method", "DELETE")]).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = send(Method::GET, "https://app.example.com", &[]).await.unwrap();
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS], "etag");
+        assert_eq!(response.headers()[header::VARY], "origin");
+        let response = send(Method::GET, "https://evil.example.com", &[]).await.unwrap();
+        assert!(response.status().is_success());
+        assert!(!response.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert_eq!(response.headers()[header::VARY], "origin");
+        let response = Client::new().get(format!("http://{}/zc", addr).parse().unwrap()).await.unwrap();
+        assert_eq!(response.headers()[header::VARY], "origin");
+    }
+}
@@ -0,0 +1,253 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use axum::body::{boxed, Body};
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::Response;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+#[cfg(feature = "kafka")]
+use crate::kafka::KafkaSink;
+
+/// The route dead letters are published as to a [`KafkaSink`], give it a topic.
+pub const DEAD_LETTERS_ROUTE: &str = "dead_letters";
+
+// how often a full spool is looked at again for letters that were taken away
+const RECOUNT: Duration = Duration::from_secs(1);
+
+/// Where on the way a body was turned down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// An upstream's body that didn't parse.
+    UpstreamBody,
+    /// A request's body, on its way to a method upstream, that didn't parse.
+    RequestBody,
+    RequestValidation,
+    ResponseValidation,
+}
+
+impl Stage {
+    // the client's request was fine, what it's answered with may stand in for what failed
+    fn upstream(self) -> bool {
+        matches!(self, Stage::UpstreamBody | Stage::ResponseValidation)
+    }
+}
+
+/// A body that failed, as it came, with what was wrong with it.
+#[derive(Debug, Clone)]
+pub struct Letter {
+    pub route: String,
+    pub stage: Stage,
+    pub error: String,
+    pub request_id: Option<String>,
+    pub body: Bytes,
+}
+
+impl Letter {
+    // `{"route", "stage", "error", "request_id", "at_ms", "body"}`, a body that isn't utf-8
+    // in base64 under `body_base64`
+    fn to_json(&self, at_ms: u64) -> Vec<u8> {
+        let mut letter = serde_json::json!({
+            "route": self.route,
+            "stage": self.stage,
+            "error": self.error,
+            "request_id": self.request_id,
+            "at_ms": at_ms,
+        });
+        match std::str::from_utf8(&self.body) {
+            Ok(text) => letter["body"] = text.into(),
+            Err(_) => letter["body_base64"] = STANDARD.encode(&self.body).into(),
+        }
+        serde_json::to_vec(&letter).unwrap_or_default()
+    }
+}
+
+/// Where letters go.
+pub enum Sink {
+    /// A file a letter, `{at_ms}-{n}.json`, up to `max_letters` in `dir`. Once there are as
+    /// many further letters are dropped, until some are taken away.
+    Spool { dir: PathBuf, max_letters: usize },
+    /// As [`DEAD_LETTERS_ROUTE`].
+    #[cfg(feature = "kafka")]
+    Kafka(KafkaSink),
+}
+
+/// Answers requests in place of an upstream's body that failed, e.g. a 200 with an empty
+/// list, rather than with the 502 they'd get. Failed request bodies keep their 400, so that
+/// the client can tell what to fix.
+#[derive(Debug, Clone)]
+pub struct Fallback {
+    pub status: StatusCode,
+    pub content_type: HeaderValue,
+    pub body: Bytes,
+}
+
+impl Fallback {
+    pub fn json(status: StatusCode, body: impl Into<Bytes>) -> Self {
+        Fallback { status, content_type: HeaderValue::from_static("application/json"), body: body.into() }
+    }
+}
+
+/// Handle for keeping bodies that failed to parse or validate, cheap to clone into handlers
+/// and added as an `Extension` layer. `/zc` and [`crate::openapi::validate`] send letters
+/// here. The writer exits once every handle is dropped, a letter it fails to write is
+/// dropped.
+#[derive(Clone)]
+pub struct DeadLetters {
+    tx: mpsc::Sender<Letter>,
+    fallback: Option<Arc<Fallback>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl DeadLetters {
+    /// `queue` letters wait for the writer, once full further letters are dropped.
+    pub fn spawn(sink: Sink, queue: usize) -> (DeadLetters, JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel(queue.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let writer = tokio::spawn(write(sink, rx, dropped.clone()));
+        (DeadLetters { tx, fallback: None, dropped }, writer)
+    }
+
+    pub fn with_fallback(mut self, fallback: Fallback) -> Self {
+        self.fallback = Some(Arc::new(fallback));
+        self
+    }
+
+    /// Queues `letter` without waiting.
+    pub fn send(&self, letter: Letter) {
+        if self.tx.try_send(letter).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Sends `letter` and answers with the fallback in place of `response` when there is one
+    /// and the letter is of the upstream.
+    pub fn capture(&self, letter: Letter, response: Response) -> Response {
+        let stage = letter.stage;
+        self.send(letter);
+        match self.fallback.as_deref().filter(|_| stage.upstream()) {
+            Some(fallback) => Response::builder()
+                .status(fallback.status)
+                .header(header::CONTENT_TYPE, fallback.content_type.clone())
+                .body(boxed(Body::from(fallback.body.clone())))
+                .expect("a status and a header value make a response"),
+            None => response,
+        }
+    }
+
+    /// Letters dropped so far because the queue was full, the spool was, or they couldn't be
+    /// written.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+// the letters in `dir`, none if it isn't there yet
+async fn spooled(dir: &Path) -> usize {
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+        return 0;
+    };
+    let mut letters = 0;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        letters += usize::from(entry.path().extension().is_some_and(|extension| extension == "json"));
+    }
+    letters
+}
+
+// written whole under a name that readers skip before it's in place
+async fn spool(dir: &Path, name: &str, json: &[u8]) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+    let path = dir.join(name);
+    let partial = path.with_extension("json.partial");
+    tokio::fs::write(&partial, json).await?;
+    tokio::fs::rename(&partial, &path).await
+}
+
+async fn write(sink: Sink, mut rx: mpsc::Receiver<Letter>, dropped: Arc<AtomicU64>) {
+    let mut letters = match &sink {
+        Sink::Spool { dir, .. } => spooled(dir).await,
+        #[cfg(feature = "kafka")]
+        Sink::Kafka(_) => 0,
+    };
+    let mut counted = Instant::now();
+    let mut sequence = 0u64;
+    while let Some(letter) = rx.recv().await {
+        let at_ms = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        let json = letter.to_json(at_ms);
+        match &sink {
+            Sink::Spool { dir, max_letters } => {
+                if letters >= *max_letters && counted.elapsed() >= RECOUNT {
+                    letters = spooled(dir).await;
+                    counted = Instant::now();
+                }
+                if letters >= *max_letters {
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                match spool(dir, &format!("{}-{}.json", at_ms, sequence), &json).await {
+                    Ok(()) => letters += 1,
+                    Err(err) => {
+                        eprintln!("dead letter for {} not spooled to {}: {}", letter.route, dir.display(), err);
+                        dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+            #[cfg(feature = "kafka")]
+            Sink::Kafka(kafka) => {
+                if let Ok(value) = serde_json_nostr::from_slice::<serde_zero_copy::Value>(&json) {
+                    kafka.publish(DEAD_LETTERS_ROUTE, &value);
+                }
+            }
+        }
+        sequence += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use bytes::Bytes;
+    use super::{DeadLetters, Fallback, Letter, Sink, Stage};
+
+    fn letter(stage: Stage, body: &'static [u8]) -> Letter {
+        Letter { route: "/zc".to_string(), stage, error: "EOF while parsing".to_string(), request_id: Some("r-1".to_string()), body: Bytes::from_static(body) }
+    }
+
+    #[tokio::test]
+    async fn spools_letters_and_falls_back() {
+        let dir = std::env::temp_dir().join(format!("dead-letters-{}", std::process::id()));
+        let (letters, writer) = DeadLetters::spawn(Sink::Spool { dir: dir.clone(), max_letters: 2 }, 8);
+        let letters = letters.with_fallback(Fallback::json(StatusCode::OK, "[]"));
+        let response = letters.capture(letter(Stage::UpstreamBody, br#"{"items":["#), StatusCode::BAD_GATEWAY.into_response());
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hyper::body::to_bytes(response.into_body()).await.unwrap(), "[]");
+        // the client's own mistakes are still told to it
+        let response = letters.capture(letter(Stage::RequestBody, b"\xff"), StatusCode::BAD_REQUEST.into_response());
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        // over `max_letters`
+        letters.send(letter(Stage::RequestValidation, b"{}"));
+        let dropped = letters.dropped.clone();
+        drop(letters);
+        writer.await.unwrap();
+        assert_eq!(dropped.load(Ordering::Relaxed), 1);
+
+        let mut spooled: Vec<serde_json::Value> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| serde_json::from_slice(&std::fs::read(entry.unwrap().path()).unwrap()).unwrap())
+            .collect();
+        spooled.sort_by_key(|letter| letter["stage"].as_str().unwrap().to_string());
+        assert_eq!(spooled[0]["stage"], "request_body");
+        assert_eq!(spooled[0]["body_base64"], "/w==");
+        assert_eq!(spooled[1]["body"], r#"{"items":["#);
+        assert_eq!(spooled[1]["request_id"], "r-1");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+use std::io::{self, Read};
+use serde_zero_copy::Value;
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+// what every zstd frame starts with
+const MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// zstd's default for a trained dictionary.
+pub const DICTIONARY_BYTES: usize = 112_640;
+
+pub fn is_compressed(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// Compresses what a cache tier stores with zstd, with a dictionary trained by [`train`] on
+/// documents like the ones it holds if there is one. Small documents of the same shape repeat
+/// each other's keys and values, compressed on their own there's little to go by but the
+/// dictionary. What's compressed with one dictionary only comes back with the same. Frames
+/// that decompress to more than `max_bytes` don't come back at all.
+pub struct Zstd {
+    level: i32,
+    dictionary: Option<(EncoderDictionary<'static>, DecoderDictionary<'static>)>,
+    pub max_bytes: u64,
+}
+
+impl Zstd {
+    /// Of up to 64MiB decompressed.
+    pub fn new(level: i32) -> Self {
+        Zstd { level, dictionary: None, max_bytes: 64 << 20 }
+    }
+
+    pub fn with_dictionary(mut self, dictionary: &[u8]) -> Self {
+        self.dictionary = Some((EncoderDictionary::copy(dictionary, self.level), DecoderDictionary::copy(dictionary)));
+        self
+    }
+
+    pub fn compress(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        match &self.dictionary {
+            Some((dictionary, _)) => zstd::bulk::Compressor::with_prepared_dictionary(dictionary)?.compress(bytes),
+            None => zstd::bulk::compress(bytes, self.level),
+        }
+    }
+
+    pub fn decompress(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decompressed = Vec::with_capacity(bytes.len() * 4);
+        // a byte over, to tell a frame of `max_bytes` from a longer one
+        let limit = self.max_bytes.saturating_add(1);
+        match &self.dictionary {
+            Some((_, dictionary)) => zstd::Decoder::with_prepared_dictionary(bytes, dictionary)?.take(limit).read_to_end(&mut decompressed)?,
+            None => zstd::Decoder::new(bytes)?.take(limit).read_to_end(&mut decompressed)?,
+        };
+        if decompressed.len() as u64 > self.max_bytes {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("decompresses to over {} bytes", self.max_bytes)));
+        }
+        Ok(decompressed)
+    }
+}
+
+/// The documents of captured traffic, compact, to [`train`] on: those of a sampler's
+/// `/admin/capture`, a JSON array of exchanges that are trained on by their `response`, or
+/// of an NDJSON archive of [`crate::replay::Record`]s, by their `upstream`. Exchanges
+/// without one are skipped.
+pub fn samples(captured: &[u8]) -> Vec<Vec<u8>> {
+    fn document(exchange: &Value, pointer: &str) -> Option<Vec<u8>> {
+        let value = exchange.pointer(pointer).filter(|value| !matches!(value, Value::Null))?;
+        serde_json_nostr::to_vec(value).ok()
+    }
+    if let Ok(Value::Array(exchanges)) = serde_json_nostr::from_slice(captured) {
+        return exchanges.iter().filter_map(|exchange| document(exchange, "/response")).collect();
+    }
+    captured
+        .split(|b| *b == b'\n')
+        .filter_map(|line| serde_json_nostr::from_slice::<Value>(line).ok())
+        .filter_map(|record| document(&record, "/upstream"))
+        .collect()
+}
+
+/// A dictionary of at most `max_bytes` for documents like `samples`. zstd wants a hundred
+/// or so samples and about a hundred times the dictionary's size of them.
+pub fn train(samples: &[Vec<u8>], max_bytes: usize) -> io::Result<Vec<u8>> {
+    zstd::dict::from_samples(samples, max_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_compressed, samples, train, Zstd};
+
+    #[test]
+    fn trained_dictionaries_shrink_similar_documents() {
+        let document = |i: usize| format!(r#"{{"id":{},"status":"shipped","customer":{{"name":"customer {}","country":"NL"}},"items":[{{"sku":"SKU-{}","quantity":{}}}]}}"#, i, i % 7, i % 13, i % 3);
+        let archive: String = (0..1000).map(|i| format!("{{\"path\": \"/orders/{}\", \"upstream\": {}}}\n", i, document(i))).collect();
+        let captured = samples(archive.as_bytes());
+        assert_eq!(captured.len(), 1000);
+        let dictionary = train(&captured, 4096).unwrap();
+
+        let plain = Zstd::new(3);
+        let trained = Zstd::new(3).with_dictionary(&dictionary);
+        let document = document(5000);
+        let compressed = trained.compress(document.as_bytes()).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() * 3 < plain.compress(document.as_bytes()).unwrap().len() * 2);
+        assert_eq!(trained.decompress(&compressed).unwrap(), document.as_bytes());
+        assert!(plain.decompress(&compressed).is_err());
+        assert_eq!(samples(br#"[{"response": {"a": 1}}, {"response": null}]"#), [br#"{"a":1}"#.to_vec()]);
+    }
+
+    #[test]
+    fn frames_over_the_limit_dont_come_back() {
+        let mut zstd = Zstd::new(3);
+        let compressed = zstd.compress(&[b' '; 4096]).unwrap();
+        zstd.max_bytes = 4096;
+        assert_eq!(zstd.decompress(&compressed).unwrap().len(), 4096);
+        zstd.max_bytes = 4095;
+        assert!(zstd.decompress(&compressed).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, State};
+use axum::http::uri::Authority;
+use axum::http::{header, HeaderMap, HeaderValue, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+
+pub const X_REQUEST_ID: &str = "x-request-id";
+pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
+pub const X_FORWARDED_HOST: &str = "x-forwarded-host";
+pub const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+// what's sent on to upstreams of a request
+const PROPAGATED: [&str; 5] = [X_REQUEST_ID, X_FORWARDED_FOR, X_FORWARDED_HOST, X_FORWARDED_PROTO, "forwarded"];
+
+/// The id of a request, the client's `X-Request-Id` when it sent a usable one and made up
+/// otherwise. Put in the request's extensions by [`headers`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// 32 hex digits, unlikely to be made up twice by any number of proxies.
+    pub fn generate() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        // each `RandomState` is keyed afresh from a random seed
+        let random = || {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+            hasher.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos()));
+            hasher.finish()
+        };
+        RequestId(format!("{:016x}{:016x}", random(), random()))
+    }
+
+    // printable and short, others are replaced rather than passed on to upstreams and logs
+    fn from_client(headers: &HeaderMap) -> Option<Self> {
+        let id = headers.get(X_REQUEST_ID)?.to_str().ok()?;
+        let usable = !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic());
+        usable.then(|| RequestId(id.to_string()))
+    }
+}
+
+/// How far what clients say about themselves is believed. Put in the request's extensions
+/// by [`headers`].
+#[derive(Debug, Clone, Default)]
+pub struct Forwarding {
+    /// Add to the `X-Forwarded-*` and `Forwarded` headers a request came with rather than
+    /// replace them, for a proxy only reached through others that set them.
+    pub trust_forwarded: bool,
+}
+
+/// Middleware for `axum::middleware::from_fn_with_state`. Sets `X-Request-Id` on the request
+/// and its response, and adds the client to `X-Forwarded-For` and `Forwarded` along with
+/// the host and protocol it asked for. The client's address is there when the app is served
+/// `into_make_service_with_connect_info::<SocketAddr>`, over a socket path it's `unknown`.
+pub async fn headers(State(forwarding): State<Arc<Forwarding>>, mut request: Request<Body>, next: Next<Body>) -> Response {
+    let id = RequestId::from_client(request.headers()).unwrap_or_else(RequestId::generate);
+    let client = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip());
+    let headers = request.headers_mut();
+    if !forwarding.trust_forwarded {
+        for name in [X_FORWARDED_FOR, X_FORWARDED_HOST, X_FORWARDED_PROTO, "forwarded"] {
+            headers.remove(name);
+        }
+    }
+    let host = headers.get(header::HOST).and_then(|host| host.to_str().ok()).map(str::to_string);
+    if let Some(ip) = client {
+        append(headers, X_FORWARDED_FOR, &ip.to_string());
+    }
+    append(headers, "forwarded", &forwarded(client, host.as_deref()));
+    if let Some(host) = host.and_then(|host| HeaderValue::from_str(&host).ok()) {
+        headers.entry(X_FORWARDED_HOST).or_insert(host);
+    }
+    headers.entry(X_FORWARDED_PROTO).or_insert(HeaderValue::from_static("http"));
+    let value = HeaderValue::from_str(&id.0).expect("request ids are printable ASCII");
+    headers.insert(X_REQUEST_ID, value.clone());
+    request.extensions_mut().insert(id);
+    request.extensions_mut().insert(forwarding);
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(X_REQUEST_ID, value);
+    response
+}
+
+/// `scheme://host` of the proxy as the client addressed it, by `Host` over http or, where
+/// `forwarding` trusts them, by the first of `X-Forwarded-Proto` and `X-Forwarded-Host`.
+/// `None` unless the host is a plain `host[:port]`.
+pub fn origin(headers: &HeaderMap, forwarding: Option<&Forwarding>) -> Option<String> {
+    let first = |name| headers.get(name)?.to_str().ok()?.split(',').next().map(str::trim).filter(|value| !value.is_empty());
+    let trusted = forwarding.is_some_and(|forwarding| forwarding.trust_forwarded);
+    let host = first(X_FORWARDED_HOST).filter(|_| trusted).or_else(|| first(header::HOST.as_str()))?;
+    let proto = first(X_FORWARDED_PROTO).filter(|_| trusted).unwrap_or("http");
+    (is_authority(host) && matches!(proto, "http" | "https")).then(|| format!("{}://{}", proto, host))
+}
+
+// a host or `[address]` and a port, without the user info, path, query or fragment an
+// `Authority` would otherwise let through
+fn is_authority(host: &str) -> bool {
+    let allowed = |b: u8| b.is_ascii_alphanumeric() || b"-._~%!$&'()*+,;=:[]".contains(&b);
+    host.bytes().all(allowed) && host.parse::<Authority>().is_ok_and(|authority| !authority.host().is_empty())
+}
+
+/// The headers of `headers` that [`headers`] set, to send on with a request of one's own.
+pub fn propagated(headers: &HeaderMap) -> HeaderMap {
+    let mut sent = HeaderMap::new();
+    for name in PROPAGATED {
+        for value in headers.get_all(name) {
+            sent.append(name, value.clone());
+        }
+    }
+    sent
+}
+
+/// Removes the headers that are only for one connection, RFC 9110's and those `Connection`
+/// names, from headers about to go on over another.
+pub fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let named: Vec<String> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect();
+    for name in named {
+        headers.remove(name.as_str());
+    }
+    for name in ["connection", "keep-alive", "proxy-connection", "te", "trailer", "transfer-encoding", "upgrade"] {
+        headers.remove(name);
+    }
+}
+
+// an element of RFC 7239, addresses with colons and the host are quoted
+fn forwarded(client: Option<IpAddr>, host: Option<&str>) -> String {
+    let mut element = match client {
+        Some(IpAddr::V4(ip)) => format!("for={}", ip),
+        Some(IpAddr::V6(ip)) => format!("for=\"[{}]\"", ip),
+        None => "for=unknown".to_string(),
+    };
+    if let Some(host) = host.filter(|host| !host.contains(['"', '\\'])) {
+        element.push_str(&format!(";host=\"{}\"", host));
+    }
+    element.push_str(";proto=http");
+    element
+}
+
+// as one value after those already there, which proxies further on may not join up
+fn append(headers: &mut HeaderMap, name: &'static str, value: &str) {
+    let mut joined: Vec<&str> = headers.get_all(name).iter().filter_map(|value| value.to_str().ok()).collect();
+    joined.push(value);
+    if let Ok(value) = HeaderValue::from_str(&joined.join(", ")) {
+        headers.insert(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use axum::http::{HeaderMap, Request};
+    use axum::routing::get;
+    use axum::Router;
+    use hyper::{Body, Client};
+    use crate::mock;
+    use super::{headers, origin, propagated, Forwarding, RequestId, X_FORWARDED_FOR, X_FORWARDED_HOST, X_FORWARDED_PROTO, X_REQUEST_ID};
+
+    async fn echo(request: Request<Body>) -> String {
+        let id = request.extensions().get::<RequestId>().unwrap();
+        let sent = propagated(request.headers());
+        let header = |name: &str| sent.get(name).map_or("", |v| v.to_str().unwrap()).to_string();
+        format!("{}|{}|{}|{}", id.0, header(X_REQUEST_ID), header(X_FORWARDED_FOR), header("forwarded"))
+    }
+
+    async fn serve(forwarding: Forwarding) -> SocketAddr {
+        let app = Router::new()
+            .route("/", get(echo))
+            .layer(axum::middleware::from_fn_with_state(Arc::new(forwarding), headers));
+        mock::spawn_with_peers(app)
+    }
+
+    async fn get_with(addr: SocketAddr, headers: &[(&str, &str)]) -> (HeaderMap, Vec<String>) {
+        let mut request = Request::get(format!("http://{}/", addr));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let response = Client::new().request(request.body(Body::empty()).unwrap()).await.unwrap();
+        let headers = response.headers().clone();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (headers, String::from_utf8(body.to_vec()).unwrap().split('|').map(str::to_string).collect())
+    }
+
+    #[tokio::test]
+    async fn sets_forwarding_headers_and_ids() {
+        let addr = serve(Forwarding::default()).await;
+        let (response, seen) = get_with(addr, &[(X_FORWARDED_FOR, "6.6.6.6")]).await;
+        assert_eq!(seen[0].len(), 32);
+        assert_eq!(seen[0], seen[1]);
+        assert_eq!(response[X_REQUEST_ID], seen[0].as_str());
+        // a client isn't believed about who it is
+        assert_eq!(seen[2], "127.0.0.1");
+        assert_eq!(seen[3], format!("for=127.0.0.1;host=\"{}\";proto=http", addr));
+
+        let (response, seen) = get_with(addr, &[(X_REQUEST_ID, "trace-42")]).await;
+        assert_eq!(seen[0], "trace-42");
+        assert_eq!(response[X_REQUEST_ID], "trace-42");
+        let (_, seen) = get_with(addr, &[(X_REQUEST_ID, "")]).await;
+        assert_eq!(seen[0].len(), 32);
+        assert_ne!(RequestId::generate(), RequestId::generate());
+    }
+
+    #[tokio::test]
+    async fn adds_to_trusted_chains() {
+        let addr = serve(Forwarding { trust_forwarded: true }).await;
+        let (_, seen) = get_with(addr, &[(X_FORWARDED_FOR, "203.0.113.7"), ("forwarded", "for=203.0.113.7")]).await;
+        assert_eq!(seen[2], "203.0.113.7, 127.0.0.1");
+        assert!(seen[3].starts_with("for=203.0.113.7, for=127.0.0.1;"));
+    }
+
+    #[test]
+    fn origins_by_trusted_headers_only() {
+        let headers = |host: &str| {
+            HeaderMap::from_iter([
+                (axum::http::header::HOST, host.parse().unwrap()),
+                (X_FORWARDED_HOST.parse().unwrap(), "evil.example".parse().unwrap()),
+                (X_FORWARDED_PROTO.parse().unwrap(), "https".parse().unwrap()),
+            ])
+        };
+        let trusted = Forwarding { trust_forwarded: true };
+        assert_eq!(origin(&headers("api.example.com:8080"), None).as_deref(), Some("http://api.example.com:8080"));
+        assert_eq!(origin(&headers("api.example.com"), Some(&Forwarding::default())).as_deref(), Some("http://api.example.com"));
+        assert_eq!(origin(&headers("api.example.com"), Some(&trusted)).as_deref(), Some("https://evil.example"));
+        for host in ["user@api.example.com", "api.example.com?x", "api.example.com#x", "api.example.com/x", ":80"] {
+            assert_eq!(origin(&headers(host), None), None, "{}", host);
+        }
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use axum::extract::State;
+use axum::http::{header, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use bytes::Bytes;
+use hyper::client::HttpConnector;
+use hyper::{Client, Uri};
+use serde::Deserialize;
+use serde_zero_copy::mask::{project, FieldMask, MaskField};
+use crate::cache::{yoke, YokedValue};
+use crate::writer::JsonWriter;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Syntax { offset: usize, reason: &'static str },
+    /// Valid GraphQL outside the supported subset, e.g. fragments or variables.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Syntax { offset, reason } => write!(f, "syntax error at {}: {}", offset, reason),
+            Error::Unsupported(what) => write!(f, "unsupported: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A root field of the query, answered by one upstream request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootField {
+    pub name: String,
+    pub alias: Option<String>,
+    /// Literal arguments in their query spelling, strings unquoted.
+    pub arguments: Vec<(String, String)>,
+    pub mask: FieldMask,
+}
+
+impl RootField {
+    pub fn key(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+}
+
+struct Parser<'q> {
+    query: &'q str,
+    pos: usize,
+}
+
+impl<'q> Parser<'q> {
+    fn error(&self, reason: &'static str) -> Error {
+        Error::Syntax { offset: self.pos, reason }
+    }
+
+    // whitespace, commas and comments are all insignificant
+    fn skip(&mut self) {
+        let bytes = self.query.as_bytes();
+        while let Some(&b) = bytes.get(self.pos) {
+            match b {
+                b' ' | b'\t' | b'\n' | b'\r' | b',' => self.pos += 1,
+                b'#' => {
+                    while bytes.get(self.pos).is_some_and(|&b| b != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip();
+        self.query.as_bytes().get(self.pos).copied()
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, b: u8, reason: &'static str) -> Result<(), Error> {
+        if self.eat(b) { Ok(()) } else { Err(self.error(reason)) }
+    }
+
+    fn name(&mut self) -> Result<&'q str, Error> {
+        self.skip();
+        let bytes = self.query.as_bytes();
+        let start = self.pos;
+        while bytes.get(self.pos).is_some_and(|&b| b == b'_' || b.is_ascii_alphanumeric()) {
+            self.pos += 1;
+        }
+        if self.pos == start || bytes[start].is_ascii_digit() {
+            return Err(self.error("expected a name"));
+        }
+        Ok(&self.query[start..self.pos])
+    }
+
+    fn value(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(b'$') => Err(Error::Unsupported("variables")),
+            Some(b'[') | Some(b'{') => Err(Error::Unsupported("list and object arguments")),
+            Some(b'"') => {
+                self.pos += 1;
+                let mut out = String::new();
+                let mut chars = self.query[self.pos..].char_indices();
+                loop {
+                    match chars.next() {
+                        None => return Err(self.error("unterminated string")),
+                        Some((i, '"')) => {
+                            self.pos += i + 1;
+                            return Ok(out);
+                        }
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c @ ('"' | '\\' | '/'))) => out.push(c),
+                            Some((_, 'n')) => out.push('\n'),
+                            Some((_, 't')) => out.push('\t'),
+                            _ => return Err(self.error("unsupported escape")),
+                        },
+                        Some((_, c)) => out.push(c),
+                    }
+                }
+            }
+            Some(b) if b == b'-' || b.is_ascii_digit() => {
+                let start = self.pos;
+                let bytes = self.query.as_bytes();
+                self.pos += 1;
+                while bytes.get(self.pos).is_some_and(|&b| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'+' | b'-')) {
+                    self.pos += 1;
+                }
+                Ok(self.query[start..self.pos].to_string())
+            }
+            // true, false, null and enum values
+            _ => self.name().map(str::to_string),
+        }
+    }
+
+    fn arguments(&mut self) -> Result<Vec<(String, String)>, Error> {
+        let mut arguments = Vec::new();
+        if self.eat(b'(') {
+            while !self.eat(b')') {
+                let name = self.name()?.to_string();
+                self.expect(b':', "expected ':' after argument name")?;
+                arguments.push((name, self.value()?));
+            }
+        }
+        Ok(arguments)
+    }
+
+    // `alias: name(args) { ... }`
+    fn field(&mut self) -> Result<(MaskField, Vec<(String, String)>), Error> {
+        if self.query[self.pos..].starts_with("...") {
+            return Err(Error::Unsupported("fragments"));
+        }
+        let first = self.name()?;
+        let (alias, name) = if self.eat(b':') { (Some(first.to_string()), self.name()?) } else { (None, first) };
+        let arguments = self.arguments()?;
+        if self.peek() == Some(b'@') {
+            return Err(Error::Unsupported("directives"));
+        }
+        let mask = if self.peek() == Some(b'{') { self.selection_set()? } else { FieldMask::default() };
+        Ok((MaskField { name: name.to_string(), alias, mask }, arguments))
+    }
+
+    fn selection_set(&mut self) -> Result<FieldMask, Error> {
+        self.expect(b'{', "expected '{'")?;
+        let mut mask = FieldMask::default();
+        while !self.eat(b'}') {
+            if self.peek().is_none() {
+                return Err(self.error("unterminated selection set"));
+            }
+            let (field, arguments) = self.field()?;
+            if !arguments.is_empty() {
+                return Err(Error::Unsupported("arguments on nested fields"));
+            }
+            mask.fields.push(field);
+        }
+        Ok(mask)
+    }
+}
+
+/// Parses an anonymous query or a named `query` operation without fragments, variables or
+/// directives, arguments are only allowed on root fields.
+pub fn parse_query(query: &str) -> Result<Vec<RootField>, Error> {
+    let mut parser = Parser { query, pos: 0 };
+    if parser.peek() != Some(b'{') {
+        match parser.name()? {
+            "query" => {}
+            "mutation" | "subscription" => return Err(Error::Unsupported("mutations and subscriptions")),
+            _ => return Err(parser.error("expected an operation")),
+        }
+        if parser.peek() != Some(b'{') {
+            parser.name()?;
+        }
+        if parser.peek() == Some(b'(') {
+            return Err(Error::Unsupported("variables"));
+        }
+    }
+    parser.expect(b'{', "expected '{'")?;
+    let mut roots = Vec::new();
+    while !parser.eat(b'}') {
+        if parser.peek().is_none() {
+            return Err(parser.error("unterminated selection set"));
+        }
+        let (field, arguments) = parser.field()?;
+        roots.push(RootField { name: field.name, alias: field.alias, arguments, mask: field.mask });
+    }
+    if parser.peek().is_some() {
+        return Err(Error::Unsupported("multiple operations"));
+    }
+    Ok(roots)
+}
+
+// the unreserved characters of RFC 3986 stay, everything else is percent encoded
+fn encode(argument: &str) -> String {
+    argument
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Answers root fields from REST upstreams. Each root field maps to a URL template whose
+/// `{argument}` placeholders are filled from the field's arguments.
+#[derive(Clone)]
+pub struct GraphQlGateway {
+    client: Arc<Client<HttpConnector>>,
+    routes: Arc<HashMap<String, String>>,
+}
+
+impl GraphQlGateway {
+    pub fn new(client: Arc<Client<HttpConnector>>, routes: HashMap<String, String>) -> Self {
+        GraphQlGateway { client, routes: Arc::new(routes) }
+    }
+
+    fn uri(&self, field: &RootField) -> Result<Uri, String> {
+        let template = self.routes.get(&field.name).ok_or_else(|| format!("unknown field {}", field.name))?;
+        let mut uri = template.clone();
+        for (name, value) in &field.arguments {
+            uri = uri.replace(&format!("{{{}}}", name), &encode(value));
+        }
+        if let Some(start) = uri.find('{') {
+            let end = uri[start..].find('}').map_or(uri.len(), |end| start + end + 1);
+            return Err(format!("missing argument {} for field {}", &uri[start + 1..end - 1], field.name));
+        }
+        Uri::try_from(uri).map_err(|err| err.to_string())
+    }
+
+    async fn fetch(client: Arc<Client<HttpConnector>>, uri: Uri) -> Result<YokedValue, String> {
+        let response = client.get(uri).await.map_err(|err| err.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("upstream responded with {}", response.status()));
+        }
+        let body = hyper::body::to_bytes(response).await.map_err(|err| err.to_string())?;
+        yoke(body).map_err(|err| err.to_string())
+    }
+
+    /// The response body for `query`, root fields are fetched concurrently and each is written
+    /// straight from the projection of its upstream value.
+    pub async fn execute(&self, query: &str) -> Bytes {
+        let roots = match parse_query(query) {
+            Ok(roots) => roots,
+            Err(err) => return errors_only(&err.to_string()),
+        };
+        let fetches: Vec<_> = roots
+            .iter()
+            .map(|root| self.uri(root).map(|uri| tokio::spawn(Self::fetch(self.client.clone(), uri))))
+            .collect();
+
+        let mut out = JsonWriter::with_capacity(128);
+        let mut errors = Vec::new();
+        out.begin_object().key("data").begin_object();
+        for (root, fetch) in roots.iter().zip(fetches) {
+            out.key(root.key());
+            let fetched = match fetch {
+                Ok(running) => running.await.unwrap_or_else(|err| Err(err.to_string())),
+                Err(err) => Err(err),
+            };
+            let written = match fetched {
+                Ok(value) => out.value(&project(value.get(), &root.mask)).map(|_| ()).map_err(|err| err.to_string()),
+                Err(message) => Err(message),
+            };
+            if let Err(message) = written {
+                out.null();
+                errors.push(serde_json::json!({"message": message, "path": [root.key()]}));
+            }
+        }
+        out.end();
+        if !errors.is_empty() {
+            let _ = out.key("errors").value(&errors);
+        }
+        out.end();
+        out.finish()
+    }
+}
+
+fn errors_only(message: &str) -> Bytes {
+    Bytes::from(serde_json::json!({"errors": [{"message": message}]}).to_string())
+}
+
+#[derive(Deserialize)]
+struct GraphQlRequest {
+    query: String,
+}
+
+async fn serve(State(gateway): State<GraphQlGateway>, Json(request): Json<GraphQlRequest>) -> Response {
+    (
+        [(header::CONTENT_TYPE, HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()))],
+        gateway.execute(&request.query).await,
+    )
+        .into_response()
+}
+
+/// Serves `gateway` on `POST /graphql`.
+pub fn router(gateway: GraphQlGateway) -> Router {
+    Router::new().route("/graphql", post(serve)).with_state(gateway)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use bytes::Bytes;
+    use hyper::Client;
+    use crate::mock::{self, Fixtures};
+    use super::{parse_query, Error, GraphQlGateway};
+
+    #[test]
+    fn graphql_parses_the_subset() {
+        let roots = parse_query(r#"query Profile {
+            me: user(id: 1, name: "a b") { name posts { title } }  # trailing comment
+            stats
+        }"#)
+        .unwrap();
+        assert_eq!(roots.len(), 2);
+        assert_eq!(roots[0].key(), "me");
+        assert_eq!(roots[0].arguments, vec![("id".to_string(), "1".to_string()), ("name".to_string(), "a b".to_string())]);
+        assert_eq!(roots[0].mask.fields[1].mask.fields[0].name, "title");
+        assert!(roots[1].mask.is_empty());
+
+        assert_eq!(parse_query("{ user { ...F } }"), Err(Error::Unsupported("fragments")));
+        assert_eq!(parse_query("query Q($id: Int) { user }"), Err(Error::Unsupported("variables")));
+        assert_eq!(parse_query("{ user { posts(first: 1) { id } } }"), Err(Error::Unsupported("arguments on nested fields")));
+        assert!(matches!(parse_query("{ user "), Err(Error::Syntax { .. })));
+    }
+
+    #[tokio::test]
+    async fn graphql_projects_upstream_values() {
+        let fixtures = Fixtures::default()
+            .with("user-1", Bytes::from_static(br#"{"id":1,"name":"Jane","email":"j@x","posts":[{"title":"a","body":"..."}]}"#))
+            .with("stats", Bytes::from_static(br#"{"users":10,"posts":42}"#));
+        let addr = mock::spawn(mock::router(fixtures));
+
+        let routes = HashMap::from([
+            ("user".to_string(), format!("http://{}/user-{{id}}", addr)),
+            ("stats".to_string(), format!("http://{}/stats", addr)),
+        ]);
+        let gateway = GraphQlGateway::new(Arc::new(Client::new()), routes);
+        let response = gateway.execute("{ me: user(id: 1) { name posts { title } } stats { posts } missing: user(id: 2) { name } }").await;
+        assert_eq!(
+            std::str::from_utf8(&response).unwrap(),
+            concat!(
+                r#"{"data":{"me":{"name":"Jane","posts":[{"title":"a"}]},"stats":{"posts":42},"missing":null},"#,
+                r#""errors":[{"message":"upstream responded with 404 Not Found","path":["missing"]}]}"#,
+            )
+        );
+        let response = gateway.execute("{ user { name } }").await;
+        assert_eq!(std::str::from_utf8(&response).unwrap(), r#"{"data":{"user":null},"errors":[{"message":"missing argument id for field user","path":["user"]}]}"#);
+    }
+}
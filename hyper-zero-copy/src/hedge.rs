@@ -0,0 +1,210 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+// latencies the quantile is taken over, and how many it takes before it's trusted
+const WINDOW: usize = 256;
+const WARM_UP: usize = 20;
+
+#[derive(Debug, Default)]
+pub struct HedgeMetrics {
+    /// Requests that went out a second time.
+    pub hedged: AtomicU64,
+    /// Hedged requests where the second one answered first.
+    pub won: AtomicU64,
+}
+
+/// Sends an idempotent request a second time when the first hasn't answered within the
+/// `quantile` of recent latencies, and takes whichever succeeds first, dropping the other.
+/// Until enough requests are seen, and never below it, the wait is `min_delay`. Added as an
+/// `Extension` layer; behind [`SingleFlight`] only one request per flight is hedged.
+#[derive(Debug, Clone)]
+pub struct Hedging {
+    pub quantile: f64,
+    pub min_delay: Duration,
+    latencies: Arc<Mutex<VecDeque<Duration>>>,
+    metrics: Arc<HedgeMetrics>,
+}
+
+impl Hedging {
+    pub fn new(quantile: f64, min_delay: Duration) -> Self {
+        Hedging { quantile, min_delay, latencies: Arc::default(), metrics: Arc::default() }
+    }
+
+    pub fn metrics(&self) -> &HedgeMetrics {
+        &self.metrics
+    }
+
+    /// How long the first request has before the second goes out.
+    pub fn delay(&self) -> Duration {
+        let mut latencies: Vec<_> = self.latencies.lock().unwrap().iter().copied().collect();
+        if latencies.len() < WARM_UP {
+            return self.min_delay;
+        }
+        latencies.sort_unstable();
+        let at = ((latencies.len() - 1) as f64 * self.quantile.clamp(0.0, 1.0)).round() as usize;
+        latencies[at].max(self.min_delay)
+    }
+
+    /// Runs `attempt`, and runs it again past [`Hedging::delay`]. A failure that comes first
+    /// waits for the other attempt, if there is one.
+    pub async fn run<T, E, F, Fut>(&self, attempt: F) -> Result<T, E>
+        where
+            F: Fn() -> Fut,
+            Fut: Future<Output = Result<T, E>>,
+    {
+        let started = Instant::now();
+        let first = attempt();
+        tokio::pin!(first);
+        let result = tokio::select! {
+            result = &mut first => result,
+            _ = tokio::time::sleep(self.delay()) => {
+                self.metrics.hedged.fetch_add(1, Ordering::Relaxed);
+                let second = attempt();
+                tokio::pin!(second);
+                tokio::select! {
+                    result = &mut first => match result {
+                        Ok(value) => Ok(value),
+                        Err(_) => second.await,
+                    },
+                    result = &mut second => match result {
+                        Ok(value) => {
+                            self.metrics.won.fetch_add(1, Ordering::Relaxed);
+                            Ok(value)
+                        }
+                        Err(_) => first.await,
+                    },
+                }
+            }
+        };
+        if result.is_ok() {
+            self.record(started.elapsed());
+        }
+        result
+    }
+
+    fn record(&self, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        if latencies.len() == WINDOW {
+            latencies.pop_front();
+        }
+        latencies.push_back(latency);
+    }
+}
+
+/// Concurrent calls for the same key share the work of the first, so that a burst of misses
+/// sends one request upstream rather than one each. Those who join get what the first gets,
+/// should it be dropped one of them carries on.
+pub struct SingleFlight<T> {
+    flights: Flights<T>,
+}
+
+type Flights<T> = Mutex<HashMap<String, Arc<OnceCell<T>>>>;
+
+// a call's part in a flight, given up when it's done or dropped: a flight that's landed, or
+// that no call is waiting on any more, is forgotten
+struct Boarded<'f, T> {
+    flights: &'f Flights<T>,
+    key: &'f str,
+    flight: Arc<OnceCell<T>>,
+}
+
+impl<T> Drop for Boarded<'_, T> {
+    fn drop(&mut self) {
+        let mut flights = self.flights.lock().unwrap();
+        let Some(current) = flights.get(self.key).filter(|current| Arc::ptr_eq(current, &self.flight)) else {
+            return;
+        };
+        // the map's and this call's
+        if current.initialized() || Arc::strong_count(current) == 2 {
+            flights.remove(self.key);
+        }
+    }
+}
+
+impl<T> Default for SingleFlight<T> {
+    fn default() -> Self {
+        SingleFlight { flights: Mutex::default() }
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub async fn run<F, Fut>(&self, key: &str, work: F) -> T
+        where
+            F: FnOnce() -> Fut,
+            Fut: Future<Output = T>,
+    {
+        let flight = self.flights.lock().unwrap().entry(key.to_string()).or_default().clone();
+        let boarded = Boarded { flights: &self.flights, key, flight };
+        boarded.flight.get_or_init(work).await.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use super::{Hedging, SingleFlight};
+
+    #[tokio::test]
+    async fn hedges_slow_attempts() {
+        let hedging = Hedging::new(0.9, Duration::from_millis(20));
+        let attempts = AtomicU64::new(0);
+        // the first attempt hangs, the second answers at once
+        let slow_first = || {
+            let n = attempts.fetch_add(1, Ordering::Relaxed);
+            async move {
+                if n == 0 {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+                Ok::<_, ()>(n)
+            }
+        };
+        assert_eq!(hedging.run(slow_first).await, Ok(1));
+        assert_eq!(hedging.run(|| async { Err::<u64, _>("down") }).await, Err("down"));
+        assert_eq!(hedging.run(|| async { Ok::<_, ()>(7) }).await, Ok(7));
+        assert_eq!(hedging.metrics().hedged.load(Ordering::Relaxed), 1);
+        assert_eq!(hedging.metrics().won.load(Ordering::Relaxed), 1);
+
+        for _ in 0..30 {
+            hedging.record(Duration::from_millis(100));
+        }
+        assert_eq!(hedging.delay(), Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn concurrent_calls_share_a_flight() {
+        let flights = Arc::new(SingleFlight::default());
+        let runs = Arc::new(AtomicU64::new(0));
+        let calls = (0..8).map(|_| {
+            let (flights, runs) = (flights.clone(), runs.clone());
+            tokio::spawn(async move {
+                flights
+                    .run("k", || async move {
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        runs.fetch_add(1, Ordering::Relaxed)
+                    })
+                    .await
+            })
+        });
+        for call in calls.collect::<Vec<_>>() {
+            assert_eq!(call.await.unwrap(), 0);
+        }
+        assert_eq!(runs.load(Ordering::Relaxed), 1);
+        assert_eq!(flights.run("k", || async { 5 }).await, 5);
+    }
+
+    #[tokio::test]
+    async fn abandoned_flights_are_forgotten() {
+        let flights = SingleFlight::default();
+        let hung = flights.run("k", std::future::pending::<u64>);
+        assert!(tokio::time::timeout(Duration::from_millis(10), hung).await.is_err());
+        assert!(flights.flights.lock().unwrap().is_empty());
+        assert_eq!(flights.run("k", || async { 5 }).await, 5);
+        assert!(flights.flights.lock().unwrap().is_empty());
+    }
+}
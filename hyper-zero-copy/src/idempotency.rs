@@ -0,0 +1,326 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use axum::body::{boxed, Body};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use serde_zero_copy::Value;
+use sha2::{Digest, Sha256};
+use crate::aggregate::{self, read_within, Aggregator, Read};
+use crate::auth::Identity;
+use crate::cache::{self, canonical_bytes, CacheTier, YokedValue};
+use crate::openapi::is_json;
+
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+/// Set on a response that's a replay of the one to the first request with its key.
+pub const IDEMPOTENT_REPLAYED: &str = "idempotent-replayed";
+
+// the body's hash, a JSON body's of its canonical form so that retries needn't send it
+// byte for byte
+fn fingerprint(body: &Bytes, json: bool) -> String {
+    let canonical = json
+        .then(|| cache::yoke(body.clone()).ok())
+        .flatten()
+        .map_or_else(|| body.clone(), |value| canonical_bytes(&value));
+    Sha256::digest(&canonical).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// a stored response, `{"status", "headers", "fingerprint", "body"}` with a body that isn't
+// utf-8 in base64 under `body_base64`
+fn entry(status: StatusCode, headers: &HeaderMap, fingerprint: &str, body: &Bytes) -> Option<YokedValue> {
+    let headers: Vec<_> = headers
+        .iter()
+        .filter(|(name, _)| *name != header::CONTENT_LENGTH && *name != header::TRANSFER_ENCODING)
+        .filter_map(|(name, value)| Some([name.as_str(), value.to_str().ok()?]))
+        .collect();
+    let mut entry = serde_json::json!({"status": status.as_u16(), "headers": headers, "fingerprint": fingerprint});
+    match std::str::from_utf8(body) {
+        Ok(text) => entry["body"] = text.into(),
+        Err(_) => entry["body_base64"] = STANDARD.encode(body).into(),
+    }
+    cache::yoke(Bytes::from(serde_json::to_vec(&entry).ok()?)).ok()
+}
+
+// strings parsed by `serde_json_nostr` borrow as bytes
+fn text<'v>(value: Option<&'v Value>) -> Option<&'v str> {
+    match value? {
+        Value::Bytes(text) => std::str::from_utf8(text).ok(),
+        Value::Str(text) => Some(text),
+        Value::String(text) => Some(text),
+        _ => None,
+    }
+}
+
+fn replay(entry: &YokedValue) -> Option<Response> {
+    let value = entry.get();
+    let status = match value.pointer("/status")? {
+        Value::Number(status) => StatusCode::from_u16(status.as_u64()? as u16).ok()?,
+        _ => return None,
+    };
+    // a body that took no escaping is a slice of the entry, which it keeps alive until sent
+    let body = match (value.pointer("/body"), value.pointer("/body_base64")) {
+        (Some(Value::Bytes(text)), _) => entry.backing_cart().slice_ref(text),
+        (Some(Value::Str(text)), _) => entry.backing_cart().slice_ref(text.as_bytes()),
+        (Some(Value::String(text)), _) => Bytes::from(text.clone()),
+        (None, encoded) => Bytes::from(STANDARD.decode(text(encoded)?).ok()?),
+        _ => return None,
+    };
+    let mut response = Response::builder().status(status);
+    if let Some(Value::Array(headers)) = value.pointer("/headers") {
+        for pair in headers {
+            if let (Some(name), Some(value)) = (text(pair.pointer("/0")), text(pair.pointer("/1"))) {
+                response = response.header(name, value);
+            }
+        }
+    }
+    let mut response = response.body(boxed(Body::from(body))).ok()?;
+    response.headers_mut().insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+    Some(response)
+}
+
+/// Answers a POST retried with the `Idempotency-Key` of an earlier one with the response the
+/// earlier one got, rather than running it twice. Keys are per path and per client when
+/// there's an [`Identity`]. A retry with the key but another body gets a 422, one while the
+/// first is still being handled a 409. Responses are kept in `store` for `ttl`, those with
+/// a server error or a body over `max_body` aren't, so they can be retried for real. A
+/// request with a body over `max_body` gets a 413.
+///
+/// Which keys are being handled is known to this process only, with a `store` shared by
+/// replicas a retry that reaches another one before the first request is done runs again.
+pub struct Idempotency {
+    store: Arc<dyn CacheTier>,
+    pub ttl: Duration,
+    pub max_body: usize,
+    in_flight: Mutex<HashSet<String>>,
+}
+
+// releases a key however handling the first request ends
+struct InFlight<'i> {
+    idempotency: &'i Idempotency,
+    key: String,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.idempotency.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl Idempotency {
+    pub fn new(store: Arc<dyn CacheTier>, ttl: Duration) -> Self {
+        Idempotency { store, ttl, max_body: 1 << 20, in_flight: Mutex::default() }
+    }
+
+    fn start(&self, key: &str) -> Option<InFlight<'_>> {
+        self.in_flight.lock().unwrap().insert(key.to_string()).then(|| InFlight { idempotency: self, key: key.to_string() })
+    }
+}
+
+async fn stored(idempotency: &Idempotency, key: &str, fingerprint: &str) -> Option<Response> {
+    let entry = idempotency.store.get(key).await?;
+    if text(entry.get().pointer("/fingerprint")) != Some(fingerprint) {
+        return Some((StatusCode::UNPROCESSABLE_ENTITY, "Idempotency-Key was used for another request").into_response());
+    }
+    replay(&entry)
+}
+
+/// Middleware for `axum::middleware::from_fn_with_state`, behind authentication so that
+/// clients don't share keys.
+pub async fn replay_retries(State(idempotency): State<Arc<Idempotency>>, request: Request<Body>, next: Next<Body>) -> Response {
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY).filter(|_| request.method() == Method::POST).cloned() else {
+        return next.run(request).await;
+    };
+    let Ok(key) = key.to_str() else {
+        return (StatusCode::BAD_REQUEST, "Idempotency-Key isn't ASCII").into_response();
+    };
+    let client = request.extensions().get::<Identity>().map_or("", |identity| identity.subject.as_str());
+    let key = format!("idempotency:{}:{}:{}", client, request.uri().path(), key);
+    let (parts, body) = request.into_parts();
+    let body = match Aggregator::new(usize::MAX, idempotency.max_body).aggregate(body, |_| ()).await {
+        Ok((body, _)) => body,
+        Err(err @ aggregate::Error::TooLarge { .. }) => return (StatusCode::PAYLOAD_TOO_LARGE, err.to_string()).into_response(),
+        Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    };
+    let fingerprint = fingerprint(&body, is_json(&parts.headers));
+    if let Some(response) = stored(&idempotency, &key, &fingerprint).await {
+        return response;
+    }
+    let Some(_in_flight) = idempotency.start(&key) else {
+        return (StatusCode::CONFLICT, "a request with this Idempotency-Key is in progress").into_response();
+    };
+    // the first request may have been stored and let go of the key in between
+    if let Some(response) = stored(&idempotency, &key, &fingerprint).await {
+        return response;
+    }
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if response.status().is_server_error() {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let body = match read_within(body, idempotency.max_body).await {
+        Ok(Read::Whole(body)) => body,
+        Ok(Read::Unread(body)) => return Response::from_parts(parts, body),
+        Ok(Read::Over(body)) => return Response::from_parts(parts, boxed(body)),
+        Err(err) => return (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+    };
+    if let Some(entry) = entry(parts.status, &parts.headers, &fingerprint, &body) {
+        idempotency.store.put(&key, Arc::new(entry), idempotency.ttl).await;
+    }
+    Response::from_parts(parts, boxed(Body::from(body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use axum::async_trait;
+    use axum::http::header;
+    use axum::routing::post;
+    use axum::Router;
+    use hyper::{Body, Client, Request, StatusCode};
+    use tokio::sync::oneshot;
+    use crate::cache::{CacheTier, MemoryCache, YokedValue};
+    use crate::mock;
+    use super::{replay_retries, Idempotency, IDEMPOTENCY_KEY, IDEMPOTENT_REPLAYED};
+
+    // a store whose first lookup answers late with what it found when asked, as a remote
+    // one's may
+    struct Late {
+        inner: MemoryCache,
+        stall: Mutex<Option<(oneshot::Sender<()>, oneshot::Receiver<()>)>>,
+    }
+
+    #[async_trait]
+    impl CacheTier for Late {
+        async fn get(&self, key: &str) -> Option<Arc<YokedValue>> {
+            let found = self.inner.get(key).await;
+            let stall = self.stall.lock().unwrap().take();
+            if let Some((asked, answer)) = stall {
+                let _ = asked.send(());
+                let _ = answer.await;
+            }
+            found
+        }
+
+        async fn put(&self, key: &str, value: Arc<YokedValue>, ttl: Duration) {
+            self.inner.put(key, value, ttl).await
+        }
+    }
+
+    async fn serve(orders: Arc<AtomicUsize>) -> SocketAddr {
+        serve_from(orders, Arc::new(MemoryCache::default())).await
+    }
+
+    async fn serve_from(orders: Arc<AtomicUsize>, store: Arc<dyn CacheTier>) -> SocketAddr {
+        let app = Router::new()
+            .route(
+                "/orders",
+                post(move || async move {
+                    let n = orders.fetch_add(1, Ordering::SeqCst) + 1;
+                    (StatusCode::CREATED, [(header::LOCATION, format!("/orders/{}", n))], format!(r#"{{"order":{}}}"#, n))
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(Idempotency::new(store, Duration::from_secs(60))),
+                replay_retries,
+            ));
+        mock::spawn(app)
+    }
+
+    async fn order(addr: SocketAddr, key: Option<&str>, body: &'static str) -> (StatusCode, Option<String>, bool, String) {
+        let mut request = Request::post(format!("http://{}/orders", addr)).header(header::CONTENT_TYPE, "application/json");
+        if let Some(key) = key {
+            request = request.header(IDEMPOTENCY_KEY, key);
+        }
+        let response = Client::new().request(request.body(Body::from(body)).unwrap()).await.unwrap();
+        let status = response.status();
+        let location = response.headers().get(header::LOCATION).map(|l| l.to_str().unwrap().to_string());
+        let replayed = response.headers().contains_key(IDEMPOTENT_REPLAYED);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, location, replayed, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn replays_retries() {
+        let orders = Arc::new(AtomicUsize::new(0));
+        let addr = serve(orders.clone()).await;
+        let first = order(addr, Some("k-1"), r#"{"sku":"a","qty":2}"#).await;
+        assert_eq!(first, (StatusCode::CREATED, Some("/orders/1".to_string()), false, r#"{"order":1}"#.to_string()));
+        // the same request, for all its keys being in another order
+        let retry = order(addr, Some("k-1"), r#"{ "qty": 2, "sku": "a" }"#).await;
+        assert_eq!(retry, (StatusCode::CREATED, Some("/orders/1".to_string()), true, r#"{"order":1}"#.to_string()));
+        assert_eq!(orders.load(Ordering::SeqCst), 1);
+
+        let (status, ..) = order(addr, Some("k-1"), r#"{"sku":"b","qty":2}"#).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let (_, location, replayed, _) = order(addr, None, r#"{"sku":"a","qty":2}"#).await;
+        assert_eq!((location.as_deref(), replayed), (Some("/orders/2"), false));
+        let (_, location, ..) = order(addr, Some("k-2"), r#"{"sku":"a","qty":2}"#).await;
+        assert_eq!(location.as_deref(), Some("/orders/3"));
+    }
+
+    #[tokio::test]
+    async fn retries_that_missed_the_store_look_again() {
+        let orders = Arc::new(AtomicUsize::new(0));
+        let ((asked, was_asked), (answer, answered)) = (oneshot::channel(), oneshot::channel());
+        let store = Late { inner: MemoryCache::default(), stall: Mutex::new(Some((asked, answered))) };
+        let addr = serve_from(orders.clone(), Arc::new(store)).await;
+        // the retry misses, then the first is handled and stored before it goes on
+        let retry = tokio::spawn(order(addr, Some("k-1"), r#"{"sku":"a"}"#));
+        was_asked.await.unwrap();
+        let first = order(addr, Some("k-1"), r#"{"sku":"a"}"#).await;
+        answer.send(()).unwrap();
+        let retry = retry.await.unwrap();
+        assert_eq!((first.2, retry.2), (false, true));
+        assert_eq!(retry.3, first.3);
+        assert_eq!(orders.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn bodies_over_the_limit_arent_kept() {
+        let reports = Arc::new(AtomicUsize::new(0));
+        let counted = reports.clone();
+        let mut idempotency = Idempotency::new(Arc::new(MemoryCache::default()), Duration::from_secs(60));
+        idempotency.max_body = 16;
+        let app = Router::new()
+            .route(
+                "/reports",
+                post(move || {
+                    counted.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        // of no known length, it's over the limit halfway
+                        let (mut tx, body) = Body::channel();
+                        tokio::spawn(async move {
+                            for chunk in ["[1,2,3,4,5,", "6,7,8,9,10,", "11,12]"] {
+                                tx.send_data(chunk.into()).await.unwrap();
+                            }
+                        });
+                        axum::response::Response::new(axum::body::boxed(body))
+                    }
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(Arc::new(idempotency), replay_retries));
+        let addr = mock::spawn(app);
+        let report = |key: &'static str, body: &'static str| {
+            let request = Request::post(format!("http://{}/reports", addr)).header(IDEMPOTENCY_KEY, key).body(Body::from(body)).unwrap();
+            Client::new().request(request)
+        };
+
+        for _ in 0..2 {
+            let response = report("k-1", "{}").await.unwrap();
+            assert!(!response.headers().contains_key(IDEMPOTENT_REPLAYED));
+            assert_eq!(hyper::body::to_bytes(response.into_body()).await.unwrap(), "[1,2,3,4,5,6,7,8,9,10,11,12]");
+        }
+        assert_eq!(reports.load(Ordering::SeqCst), 2);
+        let response = report("k-2", r#"{"over":"the limit"}"#).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(reports.load(Ordering::SeqCst), 2);
+    }
+}
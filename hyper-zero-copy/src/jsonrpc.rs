@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use axum::extract::State;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use bytes::Bytes;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Request, Uri};
+use serde_zero_copy::Value;
+use crate::cache::{yoke, YokedValue};
+use crate::writer::JsonWriter;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError { code, message: message.into() }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "json-rpc error {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// What a handler answers with.
+pub enum Reply {
+    /// A value borrowing from its own buffer, e.g. the result of an upstream call.
+    Value(YokedValue),
+    /// Already serialized JSON.
+    Raw(Bytes),
+}
+
+// Strings without escapes are parsed as bytes.
+fn as_str<'v>(value: &'v Value) -> Option<&'v str> {
+    match value {
+        Value::Str(s) => Some(s),
+        Value::String(s) => Some(s),
+        Value::Bytes(b) => std::str::from_utf8(b).ok(),
+        _ => None,
+    }
+}
+
+fn member<'v, 'a>(value: &'v Value<'a>, key: &str) -> Option<&'v Value<'a>> {
+    match value {
+        Value::Object(map) => map.get(key),
+        _ => None,
+    }
+}
+
+fn validate(request: &Value) -> Result<(), RpcError> {
+    let invalid = |message| Err(RpcError::new(INVALID_REQUEST, message));
+    if !matches!(request, Value::Object(_)) {
+        return invalid("request is not an object");
+    }
+    if member(request, "jsonrpc").and_then(as_str) != Some("2.0") {
+        return invalid("jsonrpc must be \"2.0\"");
+    }
+    if member(request, "method").and_then(as_str).is_none() {
+        return invalid("method must be a string");
+    }
+    if !matches!(member(request, "params"), None | Some(Value::Array(_)) | Some(Value::Object(_))) {
+        return invalid("params must be an array or an object");
+    }
+    if !matches!(member(request, "id"), None | Some(Value::Null | Value::Number(_) | Value::Str(_) | Value::String(_) | Value::Bytes(_))) {
+        return invalid("id must be a string, a number or null");
+    }
+    Ok(())
+}
+
+/// One request of a possibly batched body, its fields borrow from the shared body.
+#[derive(Clone)]
+pub struct Call {
+    body: Arc<YokedValue>,
+    index: Option<usize>,
+}
+
+impl Call {
+    fn request(&self) -> &Value<'_> {
+        match (self.body.get(), self.index) {
+            (Value::Array(batch), Some(i)) => &batch[i],
+            (request, _) => request,
+        }
+    }
+
+    pub fn method(&self) -> &str {
+        member(self.request(), "method").and_then(as_str).unwrap_or_default()
+    }
+
+    pub fn params(&self) -> Option<&Value<'_>> {
+        member(self.request(), "params")
+    }
+
+    /// `None` for notifications, which get no response.
+    pub fn id(&self) -> Option<&Value<'_>> {
+        member(self.request(), "id")
+    }
+}
+
+pub type HandlerFuture = Pin<Box<dyn Future<Output = Result<Reply, RpcError>> + Send>>;
+
+type Handler = Arc<dyn Fn(Call) -> HandlerFuture + Send + Sync>;
+
+/// Dispatches requests to handlers by method name.
+#[derive(Clone, Default)]
+pub struct JsonRpcServer {
+    handlers: HashMap<String, Handler>,
+    fallback: Option<Handler>,
+}
+
+impl JsonRpcServer {
+    pub fn method<F, Fut>(mut self, name: &str, handler: F) -> Self
+        where
+            F: Fn(Call) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Result<Reply, RpcError>> + Send + 'static,
+    {
+        self.handlers.insert(name.to_string(), Arc::new(move |call| Box::pin(handler(call))));
+        self
+    }
+
+    /// Handles methods without a handler of their own, e.g. to forward them upstream.
+    pub fn fallback<F, Fut>(mut self, handler: F) -> Self
+        where
+            F: Fn(Call) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Result<Reply, RpcError>> + Send + 'static,
+    {
+        self.fallback = Some(Arc::new(move |call| Box::pin(handler(call))));
+        self
+    }
+
+    /// Handles `body`, batches run concurrently. `None` if there is nothing to respond with,
+    /// i.e. only notifications.
+    pub async fn handle(&self, body: Bytes) -> Option<Bytes> {
+        let body = match yoke(body) {
+            Ok(body) => Arc::new(body),
+            Err(err) => return Some(error_response(&Value::Null, &RpcError::new(PARSE_ERROR, err.to_string()))),
+        };
+        let batch = match body.get() {
+            Value::Array(batch) if batch.is_empty() => {
+                return Some(error_response(&Value::Null, &RpcError::new(INVALID_REQUEST, "empty batch")));
+            }
+            Value::Array(batch) => Some(batch.len()),
+            _ => None,
+        };
+        let calls: Vec<Call> = match batch {
+            Some(len) => (0..len).map(|i| Call { body: body.clone(), index: Some(i) }).collect(),
+            None => vec![Call { body: body.clone(), index: None }],
+        };
+
+        let pending: Vec<_> = calls
+            .into_iter()
+            .map(|call| {
+                let handler = self.handlers.get(call.method()).or(self.fallback.as_ref()).cloned();
+                let running = validate(call.request())
+                    .and_then(|()| {
+                        handler.ok_or_else(|| RpcError::new(METHOD_NOT_FOUND, format!("unknown method {}", call.method())))
+                    })
+                    .map(|handler| tokio::spawn(handler(call.clone())));
+                (call, running)
+            })
+            .collect();
+
+        let mut responses = Vec::new();
+        for (call, running) in pending {
+            let outcome = match running {
+                Ok(running) => running.await.unwrap_or_else(|_| Err(RpcError::new(INTERNAL_ERROR, "handler panicked"))),
+                Err(err) => Err(err),
+            };
+            // invalid requests are answered even without an id, notifications aren't
+            let invalid = matches!(&outcome, Err(err) if err.code == INVALID_REQUEST);
+            let id = match (call.id(), invalid) {
+                (Some(id), _) => id,
+                (None, true) => &Value::Null,
+                (None, false) => continue,
+            };
+            responses.push(match outcome {
+                Ok(reply) => result_response(id, &reply),
+                Err(err) => error_response(id, &err),
+            });
+        }
+
+        match (batch, responses.len()) {
+            (_, 0) => None,
+            (None, _) => responses.pop(),
+            (Some(_), _) => {
+                let mut out = JsonWriter::with_capacity(responses.iter().map(|r| r.len() + 1).sum::<usize>() + 1);
+                out.begin_array();
+                for response in &responses {
+                    out.raw(response);
+                }
+                out.end();
+                Some(out.finish())
+            }
+        }
+    }
+}
+
+// Responses are written member by member so results and ids aren't copied into a new tree.
+fn result_response(id: &Value, reply: &Reply) -> Bytes {
+    let mut out = JsonWriter::with_capacity(128);
+    out.begin_object().key("jsonrpc").str("2.0").key("result");
+    let written = match reply {
+        Reply::Value(value) => out.value(value.get()).map(|_| ()),
+        Reply::Raw(raw) => {
+            out.raw(raw);
+            Ok(())
+        }
+    };
+    if written.is_err() {
+        return error_response(id, &RpcError::new(INTERNAL_ERROR, "result is not serializable"));
+    }
+    id_and_end(out.key("id"), id);
+    out.finish()
+}
+
+fn error_response(id: &Value, err: &RpcError) -> Bytes {
+    let mut out = JsonWriter::with_capacity(128);
+    out.begin_object().key("jsonrpc").str("2.0").key("error");
+    out.begin_object().key("code").num(err.code).key("message").str(&err.message).end();
+    id_and_end(out.key("id"), id);
+    out.finish()
+}
+
+// an id that can't be serialized, e.g. bytes that aren't utf-8, is answered as null
+fn id_and_end(out: &mut JsonWriter, id: &Value) {
+    if out.value(id).is_err() {
+        out.null();
+    }
+    out.end();
+}
+
+async fn serve(State(server): State<Arc<JsonRpcServer>>, body: Bytes) -> Response {
+    match server.handle(body).await {
+        Some(response) => (
+            [(header::CONTENT_TYPE, HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()))],
+            response,
+        )
+            .into_response(),
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+/// Serves `server` on `POST path`.
+pub fn router(path: &str, server: JsonRpcServer) -> Router {
+    Router::new().route(path, post(serve)).with_state(Arc::new(server))
+}
+
+/// Calls methods on an upstream JSON-RPC endpoint.
+#[derive(Clone)]
+pub struct JsonRpcClient {
+    client: Arc<Client<HttpConnector>>,
+    uri: Uri,
+}
+
+impl JsonRpcClient {
+    pub fn new(client: Arc<Client<HttpConnector>>, uri: Uri) -> Self {
+        JsonRpcClient { client, uri }
+    }
+
+    /// The upstream's `result`, borrowing from the upstream response.
+    pub async fn call(&self, method: &str, params: Option<&Value<'_>>) -> Result<YokedValue, RpcError> {
+        let mut out = JsonWriter::with_capacity(128);
+        out.begin_object().key("jsonrpc").str("2.0").key("method").str(method);
+        if let Some(params) = params {
+            out.key("params").value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))?;
+        }
+        out.key("id").num(1).end();
+
+        let upstream_error = |err: &dyn fmt::Display| RpcError::new(INTERNAL_ERROR, format!("upstream: {}", err));
+        let request = Request::post(self.uri.clone())
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(out.finish()))
+            .map_err(|err| upstream_error(&err))?;
+        let response = self.client.request(request).await.map_err(|err| upstream_error(&err))?;
+        let body = hyper::body::to_bytes(response).await.map_err(|err| upstream_error(&err))?;
+        let response = yoke(body).map_err(|err| upstream_error(&err))?;
+
+        if let Some(error) = member(response.get(), "error") {
+            let code = match member(error, "code") {
+                Some(Value::Number(code)) => code.as_i64().unwrap_or(INTERNAL_ERROR),
+                _ => INTERNAL_ERROR,
+            };
+            let message = member(error, "message").and_then(as_str).unwrap_or_default();
+            return Err(RpcError::new(code, message));
+        }
+        response.try_map_project(|value, _| match value {
+            Value::Object(mut map) => map.remove("result").ok_or_else(|| upstream_error(&"response without a result")),
+            _ => Err(upstream_error(&"response is not an object")),
+        })
+    }
+
+    /// Passes `call` upstream as it is, its params are written straight from the request body.
+    pub async fn forward(&self, call: &Call) -> Result<Reply, RpcError> {
+        self.call(call.method(), call.params()).await.map(Reply::Value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use bytes::Bytes;
+    use hyper::{Client, Uri};
+    use serde_zero_copy::Value;
+    use crate::mock;
+    use super::{router, JsonRpcClient, JsonRpcServer, Reply, RpcError, INVALID_PARAMS};
+
+    fn sum_server() -> JsonRpcServer {
+        JsonRpcServer::default().method("sum", |call| async move {
+            let total = match call.params() {
+                Some(Value::Array(items)) => items.iter().map(|item| match item {
+                    Value::Number(n) => n.as_i64().ok_or(()),
+                    _ => Err(()),
+                }).sum::<Result<i64, ()>>(),
+                _ => Err(()),
+            };
+            total
+                .map(|total| Reply::Raw(Bytes::from(total.to_string())))
+                .map_err(|_| RpcError::new(INVALID_PARAMS, "expected an array of integers"))
+        })
+    }
+
+    #[tokio::test]
+    async fn jsonrpc_batches_and_errors() {
+        let server = sum_server();
+        let response = server
+            .handle(Bytes::from_static(br#"[
+                {"jsonrpc":"2.0","method":"sum","params":[1,2],"id":"a"},
+                {"jsonrpc":"2.0","method":"sum","params":[1]},
+                {"jsonrpc":"2.0","method":"nope","id":2},
+                {"jsonrpc":"2.0","method":"sum","params":["x"],"id":3},
+                {"method":"sum","id":4},
+                5
+            ]"#))
+            .await
+            .unwrap();
+        assert_eq!(
+            std::str::from_utf8(&response).unwrap(),
+            concat!(
+                r#"[{"jsonrpc":"2.0","result":3,"id":"a"},"#,
+                r#"{"jsonrpc":"2.0","error":{"code":-32601,"message":"unknown method nope"},"id":2},"#,
+                r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"expected an array of integers"},"id":3},"#,
+                r#"{"jsonrpc":"2.0","error":{"code":-32600,"message":"jsonrpc must be \"2.0\""},"id":4},"#,
+                r#"{"jsonrpc":"2.0","error":{"code":-32600,"message":"request is not an object"},"id":null}]"#,
+            )
+        );
+        assert!(server.handle(Bytes::from_static(br#"{"jsonrpc":"2.0","method":"sum","params":[]}"#)).await.is_none());
+        let parse_error = server.handle(Bytes::from_static(b"{")).await.unwrap();
+        assert!(parse_error.starts_with(br#"{"jsonrpc":"2.0","error":{"code":-32700"#));
+    }
+
+    #[tokio::test]
+    async fn jsonrpc_forwards_upstream() {
+        let addr = mock::spawn(router("/rpc", sum_server()));
+
+        let client = JsonRpcClient::new(Arc::new(Client::new()), Uri::try_from(format!("http://{}/rpc", addr)).unwrap());
+        let gateway = JsonRpcServer::default().fallback(move |call| {
+            let client = client.clone();
+            async move { client.forward(&call).await }
+        });
+        let response = gateway
+            .handle(Bytes::from_static(br#"[{"jsonrpc":"2.0","method":"sum","params":[40,2],"id":7},{"jsonrpc":"2.0","method":"sum","params":{},"id":8}]"#))
+            .await
+            .unwrap();
+        assert_eq!(
+            std::str::from_utf8(&response).unwrap(),
+            r#"[{"jsonrpc":"2.0","result":42,"id":7},{"jsonrpc":"2.0","error":{"code":-32602,"message":"expected an array of integers"},"id":8}]"#
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use axum::async_trait;
+use rskafka::chrono::Utc;
+use rskafka::client::partition::{Compression, PartitionClient, UnknownTopicHandling};
+use rskafka::client::{Client, ClientBuilder};
+use rskafka::record::Record;
+use serde_zero_copy::{AvroSchema, Value};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+
+/// How payloads are serialized onto the topic.
+#[derive(Debug, Clone)]
+pub enum PayloadFormat {
+    Json,
+    /// A single Avro datum per record, consumers are expected to know the schema.
+    Avro(Arc<AvroSchema>),
+}
+
+impl PayloadFormat {
+    fn encode(&self, value: &Value) -> Option<Vec<u8>> {
+        match self {
+            PayloadFormat::Json => serde_json_nostr::to_vec(value).ok(),
+            PayloadFormat::Avro(schema) => serde_zero_copy::to_avro(value, schema).ok(),
+        }
+    }
+}
+
+/// Somewhere to deliver batches of payloads, [`KafkaPublisher`] outside of tests.
+#[async_trait]
+pub trait Publish: Send + Sync + 'static {
+    async fn publish(&self, topic: &str, payloads: Vec<Vec<u8>>) -> Result<(), String>;
+}
+
+/// Publishes to partition 0 of each topic, creating partition clients as topics are first used.
+pub struct KafkaPublisher {
+    client: Client,
+    partitions: Mutex<HashMap<String, Arc<PartitionClient>>>,
+}
+
+impl KafkaPublisher {
+    pub async fn connect(brokers: Vec<String>) -> Result<KafkaPublisher, rskafka::client::error::Error> {
+        Ok(KafkaPublisher { client: ClientBuilder::new(brokers).build().await?, partitions: Mutex::new(HashMap::new()) })
+    }
+
+    async fn partition(&self, topic: &str) -> Result<Arc<PartitionClient>, rskafka::client::error::Error> {
+        let mut partitions = self.partitions.lock().await;
+        if let Some(partition) = partitions.get(topic) {
+            return Ok(partition.clone());
+        }
+        let partition = Arc::new(self.client.partition_client(topic, 0, UnknownTopicHandling::Retry).await?);
+        partitions.insert(topic.to_string(), partition.clone());
+        Ok(partition)
+    }
+}
+
+#[async_trait]
+impl Publish for KafkaPublisher {
+    async fn publish(&self, topic: &str, payloads: Vec<Vec<u8>>) -> Result<(), String> {
+        let timestamp = Utc::now();
+        let records = payloads
+            .into_iter()
+            .map(|payload| Record { key: None, value: Some(payload), headers: BTreeMap::new(), timestamp })
+            .collect();
+        let partition = self.partition(topic).await.map_err(|err| err.to_string())?;
+        partition.produce(records, Compression::NoCompression).await.map(|_| ()).map_err(|err| err.to_string())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KafkaConfig {
+    /// Route to topic, routes without a topic aren't published.
+    pub topics: HashMap<String, String>,
+    pub format: PayloadFormat,
+    /// Records waiting to be batched, once full further records are dropped.
+    pub queue: usize,
+    pub batch_size: usize,
+    /// How long a partial batch waits for more records.
+    pub linger: Duration,
+}
+
+impl KafkaConfig {
+    pub fn new(topics: HashMap<String, String>) -> Self {
+        KafkaConfig { topics, format: PayloadFormat::Json, queue: 4096, batch_size: 256, linger: Duration::from_millis(50) }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct KafkaMetrics {
+    pub delivered: AtomicU64,
+    /// Records in batches the publisher returned an error for.
+    pub failed: AtomicU64,
+    /// Records that didn't fit the queue.
+    pub dropped: AtomicU64,
+    /// Values the payload format couldn't serialize, e.g. not matching the Avro schema.
+    pub unencodable: AtomicU64,
+}
+
+/// Handle for publishing proxied values, cheap to clone into handlers. The batching task
+/// flushes and exits once every handle is dropped.
+#[derive(Clone)]
+pub struct KafkaSink {
+    tx: mpsc::Sender<(String, Vec<u8>)>,
+    topics: Arc<HashMap<String, String>>,
+    format: PayloadFormat,
+    metrics: Arc<KafkaMetrics>,
+}
+
+impl KafkaSink {
+    pub fn spawn<P: Publish>(publisher: P, config: KafkaConfig) -> (KafkaSink, JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel(config.queue.max(1));
+        let metrics = Arc::new(KafkaMetrics::default());
+        let sink = KafkaSink { tx, topics: Arc::new(config.topics), format: config.format, metrics: metrics.clone() };
+        let batcher = tokio::spawn(batch(publisher, rx, config.batch_size.max(1), config.linger, metrics));
+        (sink, batcher)
+    }
+
+    /// Serializes `value` and queues it for the route's topic without waiting.
+    pub fn publish(&self, route: &str, value: &Value) {
+        let topic = match self.topics.get(route) {
+            Some(topic) => topic,
+            None => return,
+        };
+        let payload = match self.format.encode(value) {
+            Some(payload) => payload,
+            None => {
+                self.metrics.unencodable.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        if self.tx.try_send((topic.clone(), payload)).is_err() {
+            self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn metrics(&self) -> &KafkaMetrics {
+        &self.metrics
+    }
+}
+
+async fn batch<P: Publish>(
+    publisher: P,
+    mut rx: mpsc::Receiver<(String, Vec<u8>)>,
+    batch_size: usize,
+    linger: Duration,
+    metrics: Arc<KafkaMetrics>,
+) {
+    let mut pending: HashMap<String, Vec<Vec<u8>>> = HashMap::new();
+    let mut count = 0;
+    loop {
+        let received = if count == 0 {
+            rx.recv().await.map(Some)
+        } else {
+            match tokio::time::timeout(linger, rx.recv()).await {
+                Ok(received) => received.map(Some),
+                Err(_) => Some(None),
+            }
+        };
+        let closed = received.is_none();
+        let lingered = matches!(received, Some(None));
+        if let Some(Some((topic, payload))) = received {
+            pending.entry(topic).or_default().push(payload);
+            count += 1;
+        }
+        if count >= batch_size || lingered || closed {
+            for (topic, payloads) in pending.drain() {
+                let n = payloads.len() as u64;
+                match publisher.publish(&topic, payloads).await {
+                    Ok(()) => metrics.delivered.fetch_add(n, Ordering::Relaxed),
+                    Err(_) => metrics.failed.fetch_add(n, Ordering::Relaxed),
+                };
+            }
+            count = 0;
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::Ordering;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use axum::async_trait;
+    use serde_zero_copy::{AvroSchema, Value};
+    use super::{KafkaConfig, KafkaSink, PayloadFormat, Publish};
+
+    type Published = Vec<(String, Vec<Vec<u8>>)>;
+
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Published>>);
+
+    #[async_trait]
+    impl Publish for Recorder {
+        async fn publish(&self, topic: &str, payloads: Vec<Vec<u8>>) -> Result<(), String> {
+            if topic == "broken" {
+                return Err("unavailable".to_string());
+            }
+            self.0.lock().unwrap().push((topic.to_string(), payloads));
+            Ok(())
+        }
+    }
+
+    fn topics(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(route, topic)| (route.to_string(), topic.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn kafka_batches_per_topic() {
+        let recorder = Recorder::default();
+        let mut config = KafkaConfig::new(topics(&[("/zc", "events"), ("/down", "broken")]));
+        config.batch_size = 2;
+        config.linger = Duration::from_secs(60);
+        let (sink, batcher) = KafkaSink::spawn(recorder.clone(), config);
+        let value: Value = serde_json_nostr::from_str(r#"{"id":1}"#).unwrap();
+        for _ in 0..3 {
+            sink.publish("/zc", &value);
+        }
+        sink.publish("/down", &value);
+        sink.publish("/unrouted", &value);
+        let metrics = sink.metrics.clone();
+        drop(sink);
+        batcher.await.unwrap();
+
+        let published = recorder.0.lock().unwrap().clone();
+        let sizes: Vec<usize> = published.iter().map(|(_, payloads)| payloads.len()).collect();
+        assert_eq!(sizes.iter().sum::<usize>(), 3);
+        assert!(published.iter().all(|(topic, _)| topic == "events"));
+        assert_eq!(published[0].1[0], br#"{"id":1}"#);
+        assert_eq!(metrics.delivered.load(Ordering::Relaxed), 3);
+        assert_eq!(metrics.failed.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn kafka_avro_and_linger() {
+        let recorder = Recorder::default();
+        let schema = AvroSchema::parse_str(r#"{"type":"record","name":"E","fields":[{"name":"id","type":"long"}]}"#).unwrap();
+        let mut config = KafkaConfig::new(topics(&[("/zc", "events")]));
+        config.format = PayloadFormat::Avro(Arc::new(schema));
+        config.linger = Duration::from_millis(10);
+        let (sink, _batcher) = KafkaSink::spawn(recorder.clone(), config);
+        sink.publish("/zc", &serde_json_nostr::from_str(r#"{"id":-1}"#).unwrap());
+        sink.publish("/zc", &serde_json_nostr::from_str(r#"{"id":"x"}"#).unwrap());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // flushed by the linger timeout while the sink is still alive
+        assert_eq!(recorder.0.lock().unwrap().clone(), vec![("events".to_string(), vec![vec![1u8]])]);
+        assert_eq!(sink.metrics().unencodable.load(Ordering::Relaxed), 1);
+    }
+}
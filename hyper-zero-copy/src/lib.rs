@@ -1,13 +1,97 @@
+pub mod access;
+pub mod aggregate;
+pub mod auth;
+pub mod balance;
+pub mod branch;
+pub mod cache;
+pub mod capture;
+pub mod compress;
+pub mod cors;
+pub mod dead_letter;
+pub mod dictionary;
+pub mod forward;
+pub mod graphql;
+pub mod hedge;
+pub mod idempotency;
+pub mod jsonrpc;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod loadgen;
+pub mod locale;
+pub mod metrics;
+pub mod mock;
+pub mod multipart;
+pub mod offload;
+pub mod openapi;
+pub mod pinned;
+pub mod pool;
+#[cfg(feature = "pprof")]
+pub mod profile;
+pub mod provenance;
+pub mod proxy;
+pub mod reload;
+pub mod replay;
+pub mod rewrite;
+pub mod rules;
+pub mod sample;
+pub mod shadow;
+pub mod static_files;
+pub mod tenant;
+pub mod transform;
+pub mod unix;
+pub mod version;
+pub mod writer;
+
 #[cfg(test)]
 mod tests {
     use hyper::{Body, Uri};
     use hyper::client::Client;
+    use crate::mock::{self, Fixtures};
 
     #[tokio::test]
     async fn hyper_getting_started() {
+        let addr = mock::spawn(mock::router(Fixtures::default()));
         let client = Client::new();
-        let res = client.get(Uri::from_static("http://localhost:1080/hello")).await.unwrap();
+        let uri = Uri::try_from(format!("http://{}/hello", addr)).unwrap();
+        let res = client.get(uri).await.unwrap();
         let buf = hyper::body::to_bytes(res).await.unwrap();
         println!("body: {:?}", buf);
+        assert_eq!(buf.as_ref(), mock::SAMPLE_JSON);
+    }
+
+    #[tokio::test]
+    async fn mock_upstream_chunked_gzip() {
+        let addr = mock::spawn(mock::router(Fixtures::default()));
+        let client = Client::new();
+        let uri = Uri::try_from(format!("http://{}/hello?chunk_size=256&gzip=true&delay_ms=10", addr)).unwrap();
+        let res = client.get(uri).await.unwrap();
+        assert_eq!(res.headers()[hyper::header::CONTENT_ENCODING], "gzip");
+        assert_eq!(res.headers()[hyper::header::TRANSFER_ENCODING], "chunked");
+        let buf = hyper::body::to_bytes(res).await.unwrap();
+
+        use std::io::Read;
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(buf.as_ref()).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, mock::SAMPLE_JSON);
+    }
+
+    #[tokio::test]
+    async fn mock_upstream_refused_gzip() {
+        let addr = mock::spawn(mock::router(Fixtures::default()));
+        let request = |accept_encoding: &str| hyper::Request::get(format!("http://{}/hello", addr)).header(hyper::header::ACCEPT_ENCODING, accept_encoding).body(Body::empty()).unwrap();
+        let res = Client::new().request(request("br, gzip;q=0")).await.unwrap();
+        assert!(!res.headers().contains_key(hyper::header::CONTENT_ENCODING));
+        let res = Client::new().request(request("GZIP;q=0.5")).await.unwrap();
+        assert_eq!(res.headers()[hyper::header::CONTENT_ENCODING], "gzip");
+    }
+
+    #[tokio::test]
+    async fn mock_upstream_unknown_fixture() {
+        let addr = mock::spawn(mock::router(Fixtures::default()));
+        let res = Client::new()
+            .request(hyper::Request::get(format!("http://{}/missing", addr)).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::NOT_FOUND);
     }
 }
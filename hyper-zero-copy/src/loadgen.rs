@@ -0,0 +1,190 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use hyper::client::HttpConnector;
+use hyper::{Client, Uri};
+
+#[derive(Debug, Clone)]
+pub struct LoadConfig {
+    /// Requests in flight at once.
+    pub concurrency: usize,
+    /// Requests per path.
+    pub requests: usize,
+    /// What every response must parse to, checked as JSON so that key order and formatting
+    /// don't matter. `None` only checks the status.
+    pub expected: Option<serde_json::Value>,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        LoadConfig { concurrency: 16, requests: 10_000, expected: None }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub uri: Uri,
+    /// Failed requests and responses other than 2xx.
+    pub errors: usize,
+    /// Responses that didn't match [`LoadConfig::expected`].
+    pub invalid: usize,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// Of the requests that got a response, sorted.
+    pub latencies: Vec<Duration>,
+}
+
+impl Report {
+    /// The latency `p` of the way up, e.g. `0.99`.
+    pub fn percentile(&self, p: f64) -> Duration {
+        match self.latencies.len() {
+            0 => Duration::ZERO,
+            n => self.latencies[((n as f64 * p).ceil() as usize).clamp(1, n) - 1],
+        }
+    }
+
+    pub fn requests_per_second(&self) -> f64 {
+        (self.latencies.len() + self.errors) as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{}: {} requests in {:.2}s, {:.0} req/s, {:.1} MB/s, p50 {:.2}ms p90 {:.2}ms p99 {:.2}ms max {:.2}ms, {} errors, {} invalid",
+            self.uri,
+            self.latencies.len() + self.errors,
+            self.elapsed.as_secs_f64(),
+            self.requests_per_second(),
+            self.bytes as f64 / self.elapsed.as_secs_f64() / 1e6,
+            ms(self.percentile(0.5)),
+            ms(self.percentile(0.9)),
+            ms(self.percentile(0.99)),
+            ms(self.percentile(1.0)),
+            self.errors,
+            self.invalid,
+        )
+    }
+}
+
+#[derive(Default)]
+struct Worker {
+    errors: usize,
+    invalid: usize,
+    bytes: u64,
+    latencies: Vec<Duration>,
+}
+
+async fn request(client: &Client<HttpConnector>, uri: &Uri) -> Result<Bytes, ()> {
+    let response = client.get(uri.clone()).await.map_err(drop)?;
+    if !response.status().is_success() {
+        return Err(());
+    }
+    hyper::body::to_bytes(response).await.map_err(drop)
+}
+
+/// Sends `config.requests` GETs to `uri`, `config.concurrency` at a time.
+pub async fn run(client: Client<HttpConnector>, uri: Uri, config: &LoadConfig) -> Report {
+    let remaining = Arc::new(AtomicUsize::new(config.requests));
+    let expected = Arc::new(config.expected.clone());
+    let start = Instant::now();
+    let workers: Vec<_> = (0..config.concurrency.max(1))
+        .map(|_| {
+            let (client, uri, remaining, expected) = (client.clone(), uri.clone(), remaining.clone(), expected.clone());
+            tokio::spawn(async move {
+                let mut worker = Worker::default();
+                while remaining.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1)).is_ok() {
+                    let sent = Instant::now();
+                    let Ok(body) = request(&client, &uri).await else {
+                        worker.errors += 1;
+                        continue;
+                    };
+                    worker.latencies.push(sent.elapsed());
+                    worker.bytes += body.len() as u64;
+                    if let Some(expected) = expected.as_ref() {
+                        if serde_json::from_slice::<serde_json::Value>(&body).ok().as_ref() != Some(expected) {
+                            worker.invalid += 1;
+                        }
+                    }
+                }
+                worker
+            })
+        })
+        .collect();
+    let mut report = Report { uri, errors: 0, invalid: 0, bytes: 0, elapsed: Duration::ZERO, latencies: Vec::new() };
+    for worker in workers {
+        let worker = worker.await.unwrap_or_default();
+        report.errors += worker.errors;
+        report.invalid += worker.invalid;
+        report.bytes += worker.bytes;
+        report.latencies.extend(worker.latencies);
+    }
+    report.elapsed = start.elapsed();
+    report.latencies.sort();
+    report
+}
+
+/// A JSON array of objects of about `size` bytes, to stand in for an upstream's payload.
+pub fn payload(size: usize) -> Bytes {
+    let mut out = String::from("[");
+    let mut id = 0;
+    while out.len() + 1 < size || id == 0 {
+        if id > 0 {
+            out.push(',');
+        }
+        out.push_str(&format!(r#"{{"id":{},"name":"item {}","tags":["a","b\n"],"price":{}.5,"active":true}}"#, id, id, id % 100));
+        id += 1;
+    }
+    out.push(']');
+    Bytes::from(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use axum::routing::get;
+    use axum::Router;
+    use hyper::{Client, Uri};
+    use crate::mock;
+    use super::{payload, run, LoadConfig, Report};
+
+    #[test]
+    fn report_percentiles() {
+        let report = Report {
+            uri: Uri::from_static("http://localhost/zc"),
+            errors: 0,
+            invalid: 0,
+            bytes: 0,
+            elapsed: Duration::from_secs(1),
+            latencies: (1..=100).map(Duration::from_millis).collect(),
+        };
+        assert_eq!(report.percentile(0.5), Duration::from_millis(50));
+        assert_eq!(report.percentile(0.99), Duration::from_millis(99));
+        assert_eq!(report.percentile(1.0), Duration::from_millis(100));
+        assert_eq!(report.requests_per_second(), 100.0);
+        let body = payload(4096);
+        assert!(body.len() >= 4096 && body.len() < 4096 + 100);
+        assert!(serde_json::from_slice::<serde_json::Value>(&body).unwrap().is_array());
+    }
+
+    #[tokio::test]
+    async fn loadgen_validates_responses() {
+        let app = Router::new()
+            .route("/good", get(|| async { r#"{"b": [1, 2], "a": "x"}"# }))
+            .route("/bad", get(|| async { r#"{"a": "y"}"# }));
+        let addr = mock::spawn(app);
+
+        let config = LoadConfig { concurrency: 4, requests: 50, expected: Some(serde_json::json!({"a": "x", "b": [1, 2]})) };
+        let uri = |path: &str| Uri::try_from(format!("http://{}{}", addr, path)).unwrap();
+        let good = run(Client::new(), uri("/good"), &config).await;
+        assert_eq!((good.latencies.len(), good.errors, good.invalid), (50, 0, 0));
+        let bad = run(Client::new(), uri("/bad"), &config).await;
+        assert_eq!((bad.latencies.len(), bad.errors, bad.invalid), (50, 0, 50));
+        let missing = run(Client::new(), uri("/missing"), &config).await;
+        assert_eq!((missing.latencies.len(), missing.errors), (0, 50));
+    }
+}
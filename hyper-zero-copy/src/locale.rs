@@ -0,0 +1,246 @@
+use serde::Deserialize;
+use serde_zero_copy::{CompiledPath, Value};
+use crate::transform::{query_value, Context, Transform};
+
+/// How a language writes numbers, for the few [`Locale::parse`] knows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locale {
+    pub group: &'static str,
+    pub decimal: char,
+    /// `1.234,50 €` rather than `€1,234.50`.
+    pub currency_after: bool,
+}
+
+impl Locale {
+    /// By a language tag such as `fr` or `pt-BR`; regions go by their language but for `de-CH`.
+    pub fn parse(tag: &str) -> Option<Locale> {
+        let tag = tag.trim().to_ascii_lowercase();
+        let language = tag.split(['-', '_']).next()?;
+        let (group, decimal, currency_after) = match (language, tag.as_str()) {
+            (_, "de-ch") => ("\u{2019}", '.', false),
+            ("en" | "ja" | "zh" | "ko" | "he" | "th", _) => (",", '.', false),
+            ("de" | "es" | "it" | "nl" | "pt" | "id" | "tr" | "da", _) => (".", ',', true),
+            ("fr" | "ru" | "pl" | "cs" | "sv" | "fi" | "nb" | "uk", _) => ("\u{202f}", ',', true),
+            _ => return None,
+        };
+        Some(Locale { group, decimal, currency_after })
+    }
+
+    /// The first of an `Accept-Language` header's languages by their `q` that's known.
+    pub fn negotiate(accept_language: &str) -> Option<Locale> {
+        let mut languages: Vec<(f32, &str)> = accept_language
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim();
+                let q = parts.find_map(|p| p.trim().strip_prefix("q=")).map_or(Some(1.0), |q| q.parse().ok())?;
+                Some((q, tag))
+            })
+            .collect();
+        languages.sort_by(|a, b| b.0.total_cmp(&a.0));
+        languages.into_iter().filter(|(q, _)| *q > 0.0).find_map(|(_, tag)| Locale::parse(tag))
+    }
+
+    /// `number` as written in JSON, e.g. `-1234.5`, to `decimals` places when they're given.
+    pub fn format(&self, number: &str, decimals: Option<usize>) -> Option<String> {
+        let rounded;
+        let number = match decimals {
+            Some(decimals) => {
+                rounded = round(number, decimals)?;
+                rounded.as_str()
+            }
+            None => number,
+        };
+        let (sign, digits) = number.strip_prefix('-').map_or(("", number), |digits| ("-", digits));
+        let (int, fraction) = digits.split_once('.').map_or((digits, None), |(int, fraction)| (int, Some(fraction)));
+        if int.is_empty() || !int.bytes().all(|b| b.is_ascii_digit()) || fraction.is_some_and(|f| !f.bytes().all(|b| b.is_ascii_digit())) {
+            return None;
+        }
+        let mut formatted = sign.to_string();
+        for (i, digit) in int.chars().enumerate() {
+            if i > 0 && (int.len() - i) % 3 == 0 {
+                formatted.push_str(self.group);
+            }
+            formatted.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            formatted.push(self.decimal);
+            formatted.push_str(fraction);
+        }
+        Some(formatted)
+    }
+
+    pub fn currency(&self, formatted: String, code: &str) -> String {
+        let symbol = match code {
+            "USD" => "$",
+            "EUR" => "\u{20ac}",
+            "GBP" => "\u{a3}",
+            "JPY" | "CNY" => "\u{a5}",
+            "INR" => "\u{20b9}",
+            code => code,
+        };
+        if self.currency_after {
+            format!("{}\u{a0}{}", formatted, symbol)
+        } else {
+            format!("{}{}", symbol, formatted)
+        }
+    }
+}
+
+// `number` as written in JSON to `decimals` places, half away from zero, without an exponent.
+// By its digits rather than as a float, which integers past 2^53 don't survive.
+fn round(number: &str, decimals: usize) -> Option<String> {
+    let (sign, number) = number.strip_prefix('-').map_or(("", number), |digits| ("-", digits));
+    let (mantissa, exponent) = match number.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>().ok().filter(|e| e.abs() <= 1024)?),
+        None => (number, 0),
+    };
+    let (int, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if int.is_empty() || !int.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let mut digits: Vec<u8> = int.bytes().chain(fraction.bytes()).collect();
+    // where the point goes among the digits
+    let mut point = int.len() as i64 + exponent;
+    if point < 0 {
+        digits.splice(0..0, std::iter::repeat_n(b'0', point.unsigned_abs() as usize));
+        point = 0;
+    }
+    let mut point = point as usize;
+    let kept = point + decimals;
+    // a digit past those kept to round by
+    digits.resize(digits.len().max(kept + 1), b'0');
+    let up = digits[kept] >= b'5';
+    digits.truncate(kept);
+    if up {
+        match digits.iter().rposition(|&digit| digit != b'9') {
+            Some(i) => {
+                digits[i] += 1;
+                digits[i + 1..].fill(b'0');
+            }
+            None => {
+                digits.fill(b'0');
+                digits.insert(0, b'1');
+                point += 1;
+            }
+        }
+    }
+    let (int, fraction) = digits.split_at(point);
+    let int = match int.iter().position(|&digit| digit != b'0') {
+        Some(i) => &int[i..],
+        None => b"0",
+    };
+    let mut rounded = format!("{}{}", sign, std::str::from_utf8(int).ok()?);
+    if !fraction.is_empty() {
+        rounded.push('.');
+        rounded.push_str(std::str::from_utf8(fraction).ok()?);
+    }
+    Some(rounded)
+}
+
+/// A number [`Localize`] writes out, e.g. `{"pointer": "/items/*/price", "currency": "EUR",
+/// "decimals": 2}`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Field {
+    pub pointer: CompiledPath,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub decimals: Option<usize>,
+}
+
+fn default_param() -> String {
+    "locale".to_string()
+}
+
+/// Serves the numbers at `fields` as strings the way the client's language writes them, e.g.
+/// `1234.5` as `"1.234,50 €"` for `de`. The language is the `param` query parameter's, else
+/// the best of `Accept-Language` that's known, else `default`; with none the numbers stay
+/// numbers. What isn't a number is left as it is, as is everything but the fields.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Localize {
+    pub fields: Vec<Field>,
+    #[serde(default = "default_param")]
+    pub param: String,
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+impl Localize {
+    fn locale(&self, cx: &Context) -> Option<Locale> {
+        query_value(cx.query, &self.param)
+            .and_then(Locale::parse)
+            .or_else(|| cx.accept_language.and_then(Locale::negotiate))
+            .or_else(|| self.default.as_deref().and_then(Locale::parse))
+    }
+}
+
+impl Transform for Localize {
+    fn apply<'a>(&self, mut value: Value<'a>, cx: &Context) -> Value<'a> {
+        let Some(locale) = self.locale(cx) else {
+            return value;
+        };
+        for field in &self.fields {
+            field.pointer.for_each_mut(&mut value, &mut |target| {
+                let Value::Number(n) = target else {
+                    return;
+                };
+                if let Some(formatted) = locale.format(&n.to_string(), field.decimals) {
+                    *target = Value::String(match &field.currency {
+                        Some(code) => locale.currency(formatted, code),
+                        None => formatted,
+                    });
+                }
+            });
+        }
+        value
+    }
+
+    fn vary(&self) -> &'static [&'static str] {
+        &["accept-language"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_zero_copy::Value;
+    use crate::transform::{Context, Transform};
+    use super::{Locale, Localize};
+
+    #[test]
+    fn formats_by_language() {
+        let localize: Localize = serde_json::from_str(
+            r#"{"fields": [{"pointer": "/items/*/price", "currency": "EUR", "decimals": 2}, {"pointer": "/count"}]}"#,
+        )
+        .unwrap();
+        let input = br#"{"items":[{"price":1234.5,"name":"A"},{"price":"n/a"}],"count":-1234567,"id":1234}"#;
+        let served = |cx: &Context| serde_json_nostr::to_string(&localize.apply(serde_json_nostr::from_slice(input).unwrap(), cx)).unwrap();
+
+        let german = Context { accept_language: Some("fr;q=0.5, de-DE, en;q=0.8"), ..Context::default() };
+        assert_eq!(served(&german), "{\"count\":\"-1.234.567\",\"id\":1234,\"items\":[{\"name\":\"A\",\"price\":\"1.234,50\u{a0}\u{20ac}\"},{\"price\":\"n/a\"}]}");
+        let english = Context { query: Some("locale=en-US"), ..german };
+        assert_eq!(served(&english), r#"{"count":"-1,234,567","id":1234,"items":[{"name":"A","price":"€1,234.50"},{"price":"n/a"}]}"#);
+        let unknown = Context { accept_language: Some("xx, tlh"), ..Context::default() };
+        assert_eq!(served(&unknown), serde_json_nostr::to_string(&serde_json_nostr::from_slice::<Value>(input).unwrap()).unwrap());
+
+        assert_eq!(Locale::parse("de-CH").unwrap().format("1234567.891", None).as_deref(), Some("1\u{2019}234\u{2019}567.891"));
+        assert_eq!(Locale::parse("en").unwrap().format("999", Some(0)).as_deref(), Some("999"));
+        assert_eq!(Locale::negotiate("de;q=0"), None);
+        assert_eq!(localize.vary(), ["accept-language"]);
+    }
+
+    #[test]
+    fn rounds_by_digits() {
+        let en = Locale::parse("en").unwrap();
+        let format = |number: &str, decimals: usize| en.format(number, Some(decimals));
+        assert_eq!(format("9007199254740993", 2).as_deref(), Some("9,007,199,254,740,993.00"));
+        assert_eq!(format("2.675", 2).as_deref(), Some("2.68"));
+        assert_eq!(format("-999.996", 2).as_deref(), Some("-1,000.00"));
+        assert_eq!(format("1.5e3", 0).as_deref(), Some("1,500"));
+        assert_eq!(format("12E-4", 3).as_deref(), Some("0.001"));
+        assert_eq!(format("0.04", 1).as_deref(), Some("0.0"));
+        assert_eq!(format("1e99999", 2), None);
+    }
+}
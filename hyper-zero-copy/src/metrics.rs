@@ -0,0 +1,69 @@
+use std::sync::Arc;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use crate::auth;
+
+type Source = Box<dyn Fn() -> serde_json::Value + Send + Sync>;
+
+/// The metrics of the parts of the proxy that keep them, each under the name it's registered
+/// with, e.g. `{"dead_letters": {"dropped": 0}, "offload": {"queued": 2, …}}`. They're read
+/// as they are at the time of the report.
+#[derive(Default)]
+pub struct Metrics {
+    sources: Vec<(String, Source)>,
+    token: String,
+}
+
+impl Metrics {
+    /// `token` is what `/admin/metrics` is to be requested with, none if it's empty.
+    pub fn new(token: impl Into<String>) -> Self {
+        Metrics { sources: Vec::new(), token: token.into() }
+    }
+
+    pub fn register<F>(&mut self, name: impl Into<String>, source: F)
+        where
+            F: Fn() -> serde_json::Value + Send + Sync + 'static,
+    {
+        self.sources.push((name.into(), Box::new(source)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    pub fn report(&self) -> serde_json::Value {
+        self.sources.iter().map(|(name, source)| (name.clone(), source())).collect::<serde_json::Map<_, _>>().into()
+    }
+}
+
+async fn report(State(metrics): State<Arc<Metrics>>, headers: HeaderMap) -> Response {
+    if !auth::bears_token(&headers, &metrics.token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    axum::Json(metrics.report()).into_response()
+}
+
+/// `GET /admin/metrics`, the report, for requests bearing the token.
+pub fn admin(metrics: Arc<Metrics>) -> Router {
+    Router::new().route("/admin/metrics", get(report)).with_state(metrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use super::Metrics;
+
+    #[test]
+    fn reports_what_sources_read_now() {
+        let dropped = Arc::new(AtomicU64::new(0));
+        let mut metrics = Metrics::new("t");
+        let source = dropped.clone();
+        metrics.register("dead_letters", move || serde_json::json!({ "dropped": source.load(Ordering::Relaxed) }));
+        dropped.store(3, Ordering::Relaxed);
+        assert_eq!(metrics.report(), serde_json::json!({ "dead_letters": { "dropped": 3 } }));
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{Path as UrlPath, Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hyper::Body;
+use serde::Deserialize;
+use crate::reload::WatchedDir;
+
+/// Fixture served under `/hello` when nothing else is configured.
+pub const SAMPLE_JSON: &[u8] = include_bytes!("../../serde-zero-copy/src/sample.json");
+
+/// JSON documents served by the mock upstream, keyed by their url name.
+#[derive(Clone)]
+pub struct Fixtures {
+    fixed: Arc<HashMap<String, Bytes>>,
+    watched: Option<Arc<WatchedDir>>,
+}
+
+impl Default for Fixtures {
+    fn default() -> Self {
+        let mut fixtures = HashMap::new();
+        fixtures.insert("hello".to_string(), Bytes::from_static(SAMPLE_JSON));
+        Fixtures { fixed: Arc::new(fixtures), watched: None }
+    }
+}
+
+impl Fixtures {
+    /// Every `*.json` file in `dir` is served under its file stem, on top of the defaults.
+    pub fn from_dir(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut fixtures = Fixtures::default().fixed.as_ref().clone();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                fixtures.insert(name.to_string(), Bytes::from(std::fs::read(&path)?));
+            }
+        }
+        Ok(Fixtures { fixed: Arc::new(fixtures), watched: None })
+    }
+
+    pub fn with(self, name: &str, body: impl Into<Bytes>) -> Self {
+        let mut fixtures = self.fixed.as_ref().clone();
+        fixtures.insert(name.to_string(), body.into());
+        Fixtures { fixed: Arc::new(fixtures), ..self }
+    }
+
+    /// Serves the documents of `dir` as they are at the time of each request, ahead of the
+    /// others. Start [`WatchedDir::watch`] to pick up edits without a restart.
+    pub fn watching(self, dir: Arc<WatchedDir>) -> Self {
+        Fixtures { watched: Some(dir), ..self }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Bytes> {
+        let watched = self.watched.as_ref().and_then(|dir| dir.get(name));
+        match watched {
+            Some(value) => Some(Bytes::clone(value.backing_cart())),
+            None => self.fixed.get(name).cloned(),
+        }
+    }
+}
+
+/// Per-request knobs, passed as query parameters, e.g. `/hello?delay_ms=50&chunk_size=512&gzip=true`.
+#[derive(Deserialize, Default, Debug)]
+pub struct ResponseOptions {
+    /// Wait before sending the response head.
+    pub delay_ms: Option<u64>,
+    /// Send the body with chunked transfer encoding, in chunks of this size.
+    pub chunk_size: Option<usize>,
+    /// Wait between chunks, only meaningful with `chunk_size`.
+    pub chunk_delay_ms: Option<u64>,
+    /// Gzip the body even if the client didn't send `Accept-Encoding: gzip`.
+    #[serde(default)]
+    pub gzip: bool,
+}
+
+pub fn router(fixtures: Fixtures) -> Router {
+    Router::new()
+        .route("/:name", get(fixture))
+        .with_state(fixtures)
+}
+
+/// Serves `fixtures` on an already bound listener, use port 0 to get an ephemeral one.
+pub async fn serve(listener: TcpListener, fixtures: Fixtures) -> hyper::Result<()> {
+    axum::Server::from_tcp(listener)?
+        .serve(router(fixtures).into_make_service())
+        .await
+}
+
+/// Serves `app` on an ephemeral localhost port for tests, returns where.
+#[cfg(test)]
+pub(crate) fn spawn(app: Router) -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+    addr
+}
+
+/// Like [`spawn`], with each request's peer address for handlers taking `ConnectInfo`.
+#[cfg(test)]
+pub(crate) fn spawn_with_peers(app: Router) -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = axum::Server::from_tcp(listener).unwrap();
+    tokio::spawn(server.serve(app.into_make_service_with_connect_info::<std::net::SocketAddr>()));
+    addr
+}
+
+async fn fixture(
+    State(fixtures): State<Fixtures>,
+    UrlPath(name): UrlPath<String>,
+    Query(options): Query<ResponseOptions>,
+    headers: HeaderMap,
+) -> Response {
+    let body = match fixtures.get(&name) {
+        Some(body) => body,
+        None => return (StatusCode::NOT_FOUND, format!("no fixture named {}", name)).into_response(),
+    };
+
+    if let Some(delay) = options.delay_ms {
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+    }
+
+    let gzip = options.gzip || accepts_gzip(&headers);
+    let body = if gzip {
+        let mut encoder = GzEncoder::new(Vec::with_capacity(body.len() / 4), Compression::fast());
+        match encoder.write_all(&body).and_then(|_| encoder.finish()) {
+            Ok(compressed) => Bytes::from(compressed),
+            Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+        }
+    } else {
+        body
+    };
+
+    let mut response = match options.chunk_size {
+        Some(chunk_size) if chunk_size > 0 => {
+            let (mut sender, stream) = Body::channel();
+            let chunk_delay = options.chunk_delay_ms.map(Duration::from_millis);
+            tokio::spawn(async move {
+                let mut offset = 0;
+                while offset < body.len() {
+                    let end = usize::min(offset + chunk_size, body.len());
+                    if sender.send_data(body.slice(offset..end)).await.is_err() {
+                        return;
+                    }
+                    offset = end;
+                    if let Some(delay) = chunk_delay {
+                        tokio::time::sleep(delay).await;
+                    }
+                }
+            });
+            Response::new(axum::body::boxed(stream))
+        }
+        _ => Response::new(axum::body::boxed(Body::from(body))),
+    };
+
+    let response_headers = response.headers_mut();
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
+    );
+    if gzip {
+        response_headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    }
+    response
+}
+
+// unless it's weighed `q=0`, a refusal
+fn accepts_gzip(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut params = coding.split(';');
+            let name = params.next().unwrap_or_default().trim();
+            let q: f32 = params.find_map(|param| param.trim().strip_prefix("q=")).map_or(1.0, |q| q.trim().parse().unwrap_or(0.0));
+            name.eq_ignore_ascii_case("gzip") && q > 0.0
+        })
+}
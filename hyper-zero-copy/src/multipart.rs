@@ -0,0 +1,262 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use axum::async_trait;
+use axum::extract::FromRequest;
+use axum::http::{header, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use hyper::Body;
+use serde_zero_copy::{Value, ValueBuilder};
+use yoke::Yoke;
+use crate::aggregate::{self, Aggregator};
+
+/// A `multipart/form-data` body parsed into an object keyed by field name, borrowing from the
+/// aggregated body. Text fields are strings, `application/json` fields are parsed values and
+/// file fields are `{"filename", "content_type", "data"}` objects with `data` as a `Bytes`
+/// slice of the body. Repeated field names collect into an array. The body is read within the
+/// limits of the [`Aggregator`] extension, or of the default one without it.
+pub struct MultipartForm(pub Yoke<Value<'static>, Arc<Bytes>>);
+
+impl MultipartForm {
+    pub fn get(&self) -> &Value<'_> {
+        self.0.get()
+    }
+}
+
+#[derive(Debug)]
+pub enum MultipartRejection {
+    NotMultipart,
+    Body(hyper::Error),
+    TooLarge(aggregate::Error),
+    Malformed(&'static str),
+    Json(serde_json_nostr::Error),
+}
+
+impl fmt::Display for MultipartRejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultipartRejection::NotMultipart => f.write_str("expected a multipart/form-data body with a boundary"),
+            MultipartRejection::Body(err) => write!(f, "failed to read body: {}", err),
+            MultipartRejection::TooLarge(err) => write!(f, "{}", err),
+            MultipartRejection::Malformed(reason) => write!(f, "malformed multipart body: {}", reason),
+            MultipartRejection::Json(err) => write!(f, "invalid json part: {}", err),
+        }
+    }
+}
+
+impl IntoResponse for MultipartRejection {
+    fn into_response(self) -> Response {
+        let status = match self {
+            MultipartRejection::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S, Body> for MultipartForm
+    where
+        S: Send + Sync,
+{
+    type Rejection = MultipartRejection;
+
+    async fn from_request(req: Request<Body>, _state: &S) -> Result<Self, Self::Rejection> {
+        let boundary = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(boundary)
+            .ok_or(MultipartRejection::NotMultipart)?;
+        let aggregator = req.extensions().get::<Aggregator>().cloned().unwrap_or_default();
+        let body = match aggregator.aggregate(req.into_body(), |_| {}).await {
+            Ok((body, _)) => body,
+            Err(aggregate::Error::Hyper(err)) => return Err(MultipartRejection::Body(err)),
+            Err(err) => return Err(MultipartRejection::TooLarge(err)),
+        };
+        let yoked = Yoke::try_attach_to_cart(Arc::new(body), |b| parse(b, boundary.as_bytes()))?;
+        Ok(MultipartForm(yoked))
+    }
+}
+
+fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    if !params.next()?.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim_matches('"').to_string())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+// `name="value"` out of a Content-Disposition header
+fn disposition_param<'a>(disposition: &'a str, param: &str) -> Option<&'a str> {
+    disposition
+        .split(';')
+        .filter_map(|part| part.trim().split_once('='))
+        .find(|(name, _)| name.eq_ignore_ascii_case(param))
+        .map(|(_, value)| value.trim_matches('"'))
+}
+
+fn parse<'a>(body: &'a [u8], boundary: &[u8]) -> Result<Value<'a>, MultipartRejection> {
+    let delimiter = [b"--", boundary].concat();
+    let mut fields: BTreeMap<Cow<'a, str>, Value<'a>> = BTreeMap::new();
+
+    let start = find(body, &delimiter).ok_or(MultipartRejection::Malformed("missing boundary"))?;
+    let mut rest = &body[start + delimiter.len()..];
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(Value::Object(fields));
+        }
+        // transport padding may follow a delimiter
+        let padding = rest.iter().take_while(|&&b| b == b' ' || b == b'\t').count();
+        rest = rest[padding..].strip_prefix(b"\r\n").ok_or(MultipartRejection::Malformed("expected CRLF after boundary"))?;
+        let headers_end = find(rest, b"\r\n\r\n").ok_or(MultipartRejection::Malformed("unterminated part headers"))?;
+        let headers = std::str::from_utf8(&rest[..headers_end])
+            .map_err(|_| MultipartRejection::Malformed("part headers are not utf-8"))?;
+        rest = &rest[headers_end + 4..];
+        let data_end = find(rest, &[b"\r\n", delimiter.as_slice()].concat())
+            .ok_or(MultipartRejection::Malformed("unterminated part"))?;
+        let data = &rest[..data_end];
+        rest = &rest[data_end + 2 + delimiter.len()..];
+
+        let mut disposition = None;
+        let mut content_type = None;
+        for line in headers.split("\r\n") {
+            match line.split_once(':') {
+                Some((name, value)) if name.eq_ignore_ascii_case("content-disposition") => disposition = Some(value.trim()),
+                Some((name, value)) if name.eq_ignore_ascii_case("content-type") => content_type = Some(value.trim()),
+                _ => {}
+            }
+        }
+        let disposition = disposition.ok_or(MultipartRejection::Malformed("part without content-disposition"))?;
+        let name = disposition_param(disposition, "name").ok_or(MultipartRejection::Malformed("part without a name"))?;
+
+        let value = match (disposition_param(disposition, "filename"), content_type) {
+            (Some(filename), _) => ValueBuilder::new()
+                .object()
+                .key("filename")
+                .str(filename)
+                .key("content_type")
+                .value(content_type.map_or(Value::Null, Value::Str))
+                .key("data")
+                .value(Value::Bytes(data))
+                .end()
+                .finish(),
+            (None, Some(content_type)) if content_type.starts_with(mime::APPLICATION_JSON.as_ref()) => {
+                serde_json_nostr::from_slice(data).map_err(MultipartRejection::Json)?
+            }
+            (None, _) => match std::str::from_utf8(data) {
+                Ok(text) => Value::Str(text),
+                Err(_) => Value::Bytes(data),
+            },
+        };
+
+        match fields.get_mut(name) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => {
+                let first = std::mem::replace(existing, Value::Null);
+                *existing = Value::Array(vec![first, value]);
+            }
+            None => {
+                fields.insert(Cow::Borrowed(name), value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::FromRequest;
+    use axum::http::{header, Request, StatusCode};
+    use axum::response::IntoResponse;
+    use hyper::Body;
+    use serde_zero_copy::Value;
+    use crate::aggregate::Aggregator;
+    use super::MultipartForm;
+
+    const BODY: &str = "preamble\r\n--XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+        holiday\r\n--XyZ\r\n\
+        Content-Disposition: form-data; name=\"meta\"\r\nContent-Type: application/json\r\n\r\n\
+        {\"tags\":[\"sea\"],\"id\":7}\r\n--XyZ\r\n\
+        Content-Disposition: form-data; name=\"photo\"; filename=\"a.png\"\r\nContent-Type: image/png\r\n\r\n\
+        \u{1}PNG\r\n--XyZ\r\n\
+        Content-Disposition: form-data; name=\"photo\"; filename=\"b.png\"\r\n\r\n\
+        second\r\n--XyZ--\r\n";
+
+    fn request(content_type: &str, body: &'static str) -> Request<Body> {
+        Request::post("/upload")
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn multipart_parts_borrow_from_body() {
+        let form = MultipartForm::from_request(request("multipart/form-data; boundary=XyZ", BODY), &())
+            .await
+            .unwrap();
+        let body_range = form.0.backing_cart().as_ptr_range();
+        let fields = match form.get() {
+            Value::Object(fields) => fields,
+            _ => panic!(),
+        };
+        assert!(fields["title"].identical(&Value::Str("holiday")));
+        assert_eq!(
+            serde_json_nostr::to_string(&fields["meta"]).unwrap(),
+            r#"{"id":7,"tags":["sea"]}"#
+        );
+        let photos = match &fields["photo"] {
+            Value::Array(photos) => photos,
+            _ => panic!(),
+        };
+        match &photos[0] {
+            Value::Object(file) => {
+                assert!(file["filename"].identical(&Value::Str("a.png")));
+                assert!(file["content_type"].identical(&Value::Str("image/png")));
+                match file["data"] {
+                    Value::Bytes(data) => {
+                        assert_eq!(data, b"\x01PNG");
+                        assert!(body_range.contains(&data.as_ptr()));
+                    }
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+        match &photos[1] {
+            Value::Object(file) => assert_eq!(file["content_type"], Value::Null),
+            _ => panic!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn multipart_rejections() {
+        assert!(MultipartForm::from_request(request("application/json", BODY), &()).await.is_err());
+        assert!(MultipartForm::from_request(request("multipart/form-data; boundary=nope", BODY), &()).await.is_err());
+        let truncated = &BODY[..BODY.len() - 20];
+        assert!(MultipartForm::from_request(request("multipart/form-data; boundary=XyZ", truncated), &()).await.is_err());
+        let mut over = request("multipart/form-data; boundary=XyZ", BODY);
+        over.extensions_mut().insert(Aggregator::new(usize::MAX, BODY.len() - 1));
+        let rejection = MultipartForm::from_request(over, &()).await.err().unwrap();
+        assert_eq!(rejection.into_response().status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn multipart_padding_after_boundaries() {
+        let body = "--XyZ \t\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nholiday\r\n--XyZ-- \r\n";
+        let form = MultipartForm::from_request(request("multipart/form-data; boundary=XyZ", body), &()).await.unwrap();
+        match form.get() {
+            Value::Object(fields) => assert!(fields["title"].identical(&Value::Str("holiday"))),
+            _ => panic!(),
+        }
+    }
+}
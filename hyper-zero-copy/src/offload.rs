@@ -0,0 +1,129 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use serde::Serialize;
+use tokio::sync::Semaphore;
+
+#[derive(Debug, Default, Serialize)]
+pub struct OffloadMetrics {
+    /// Jobs waiting for one of the `budget` slots.
+    pub queued: AtomicU64,
+    pub running: AtomicU64,
+    pub completed: AtomicU64,
+}
+
+// takes a job off `queued` however the wait for a slot ends, the request may be dropped
+// while it waits
+struct Queued<'m>(&'m OffloadMetrics);
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Parsing and serializing of bodies longer than `above` bytes run on tokio's blocking pool,
+/// at most `budget` at a time, rather than on the worker serving the request.
+/// [`Offload::metrics`] shows how many wait for a slot, to size the budget by, e.g. under
+/// [`crate::metrics`].
+#[derive(Debug, Clone)]
+pub struct Offload {
+    pub above: usize,
+    slots: Arc<Semaphore>,
+    metrics: Arc<OffloadMetrics>,
+}
+
+impl Offload {
+    pub fn new(above: usize, budget: usize) -> Self {
+        Offload { above, slots: Arc::new(Semaphore::new(budget.max(1))), metrics: Arc::default() }
+    }
+
+    pub fn applies(&self, len: usize) -> bool {
+        len > self.above
+    }
+
+    pub fn metrics(&self) -> &OffloadMetrics {
+        &self.metrics
+    }
+
+    /// Runs `job` on the blocking pool once a slot is free. A job that started runs to the
+    /// end even if its caller stops waiting for it.
+    pub async fn run<T, F>(&self, job: F) -> T
+        where
+            T: Send + 'static,
+            F: FnOnce() -> T + Send + 'static,
+    {
+        self.metrics.queued.fetch_add(1, Ordering::Relaxed);
+        let queued = Queued(&self.metrics);
+        // the semaphore is never closed
+        let slot = self.slots.clone().acquire_owned().await.unwrap();
+        drop(queued);
+        self.metrics.running.fetch_add(1, Ordering::Relaxed);
+        let metrics = self.metrics.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let output = job();
+            drop(slot);
+            metrics.running.fetch_sub(1, Ordering::Relaxed);
+            metrics.completed.fetch_add(1, Ordering::Relaxed);
+            output
+        })
+            .await;
+        match result {
+            Ok(output) => output,
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use axum::Extension;
+    use hyper::{Client, Uri};
+    use crate::mock::{self, Fixtures};
+    use crate::proxy;
+    use super::Offload;
+
+    #[tokio::test]
+    async fn offload_queues_beyond_budget() {
+        let offload = Offload::new(0, 1);
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let first = tokio::spawn({
+            let offload = offload.clone();
+            async move { offload.run(move || rx.recv().unwrap()).await }
+        });
+        let second = tokio::spawn({
+            let offload = offload.clone();
+            async move { offload.run(|| 2).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(offload.metrics().running.load(Ordering::Relaxed), 1);
+        assert_eq!(offload.metrics().queued.load(Ordering::Relaxed), 1);
+
+        tx.send(()).unwrap();
+        first.await.unwrap();
+        assert_eq!(second.await.unwrap(), 2);
+        assert_eq!(offload.metrics().queued.load(Ordering::Relaxed), 0);
+        assert_eq!(offload.metrics().running.load(Ordering::Relaxed), 0);
+        assert_eq!(offload.metrics().completed.load(Ordering::Relaxed), 2);
+        assert!(!offload.applies(0));
+    }
+
+    #[tokio::test]
+    async fn offload_proxied_body() {
+        let upstream_addr = mock::spawn(mock::router(Fixtures::default()));
+        let uri = Uri::try_from(format!("http://{}/hello", upstream_addr)).unwrap();
+        let offload = Offload::new(0, 2);
+        let app = proxy::router(Arc::new(Client::new()), uri).layer(Extension(offload.clone()));
+        let addr = mock::spawn(app);
+
+        let res = Client::new().get(Uri::try_from(format!("http://{}/zc", addr)).unwrap()).await.unwrap();
+        let body = hyper::body::to_bytes(res).await.unwrap();
+        let expected: serde_zero_copy::Value = serde_json_nostr::from_slice(mock::SAMPLE_JSON).unwrap();
+        assert_eq!(body, serde_json_nostr::to_vec(&expected).unwrap());
+        // the parse and the serialization
+        assert_eq!(offload.metrics().completed.load(Ordering::Relaxed), 2);
+        assert_eq!(serde_json::json!(offload.metrics()), serde_json::json!({"queued": 0, "running": 0, "completed": 2}));
+    }
+}
@@ -0,0 +1,356 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use axum::body::{boxed, Body};
+use axum::extract::State;
+use axum::http::{header, HeaderValue, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use serde_zero_copy::{validate as validate_value, Value, Violation};
+use crate::dead_letter::{DeadLetters, Letter, Stage};
+use crate::forward::RequestId;
+
+#[derive(Debug)]
+pub enum Error {
+    Json(serde_json::Error),
+    /// Not an OpenAPI 3 document, e.g. a Swagger 2 one.
+    NotOpenApi3,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Json(err) => write!(f, "{}", err),
+            Error::NotOpenApi3 => write!(f, "not an OpenAPI 3 document"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+const METHODS: [&str; 8] = ["get", "put", "post", "delete", "options", "head", "patch", "trace"];
+
+struct Operation {
+    method: Method,
+    /// Path segments, `None` for a `{parameter}`.
+    segments: Vec<Option<String>>,
+    /// JSON pointers into the document.
+    request: Option<(String, bool)>,
+    responses: Vec<(String, String)>,
+}
+
+impl Operation {
+    fn matches(&self, method: &Method, segments: &[&str]) -> bool {
+        self.method == method
+            && self.segments.len() == segments.len()
+            && self.segments.iter().zip(segments).all(|(expected, actual)| match expected {
+                Some(literal) => literal == actual,
+                None => !actual.is_empty(),
+            })
+    }
+
+    fn parameters(&self) -> usize {
+        self.segments.iter().filter(|s| s.is_none()).count()
+    }
+
+    // the exact status first, then its range like `2XX`, then `default`
+    fn response(&self, status: StatusCode) -> Option<&str> {
+        let exact = status.as_str().to_string();
+        let range = format!("{}XX", &exact[..1]);
+        [exact.as_str(), range.as_str(), "default"]
+            .iter()
+            .find_map(|key| self.responses.iter().find(|(status, _)| status.eq_ignore_ascii_case(key)))
+            .map(|(_, pointer)| pointer.as_str())
+    }
+}
+
+fn escape(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+// the schema of the JSON media type under `content`, if any
+fn json_schema(content: Option<&serde_json::Value>, pointer: &str) -> Option<String> {
+    let (media, _) = content?
+        .as_object()?
+        .iter()
+        .find(|(media, entry)| (*media == "application/json" || media.ends_with("+json")) && entry.get("schema").is_some())?;
+    Some(format!("{}/content/{}/schema", pointer, escape(media)))
+}
+
+#[derive(Debug, Default)]
+pub struct OpenApiMetrics {
+    pub invalid_requests: AtomicU64,
+    pub invalid_responses: AtomicU64,
+}
+
+/// Validates JSON request and response bodies of the operations in an OpenAPI 3 document.
+/// Requests for paths the document doesn't describe pass through untouched. Enforcing,
+/// invalid requests are answered with 400 and invalid responses replaced by 502, both
+/// listing the violations. In report-only mode the exchange goes through unchanged and
+/// only gains an `x-openapi-violations` header with the number of violations.
+pub struct OpenApiValidator {
+    document: serde_json::Value,
+    operations: Vec<Operation>,
+    report_only: bool,
+    metrics: OpenApiMetrics,
+}
+
+impl OpenApiValidator {
+    pub fn from_json(document: &str, report_only: bool) -> Result<OpenApiValidator, Error> {
+        Self::new(serde_json::from_str(document).map_err(Error::Json)?, report_only)
+    }
+
+    pub fn new(document: serde_json::Value, report_only: bool) -> Result<OpenApiValidator, Error> {
+        if !document.get("openapi").and_then(serde_json::Value::as_str).is_some_and(|v| v.starts_with("3.")) {
+            return Err(Error::NotOpenApi3);
+        }
+        let mut operations = Vec::new();
+        let paths = document.get("paths").and_then(serde_json::Value::as_object).into_iter().flatten();
+        for (path, item) in paths {
+            let segments = path
+                .trim_start_matches('/')
+                .split('/')
+                .map(|s| if s.starts_with('{') && s.ends_with('}') { None } else { Some(s.to_string()) })
+                .collect::<Vec<_>>();
+            for method in METHODS {
+                let operation = match item.get(method) {
+                    Some(operation) => operation,
+                    None => continue,
+                };
+                let pointer = format!("/paths/{}/{}", escape(path), method);
+                let request = operation.get("requestBody").and_then(|body| {
+                    let required = body.get("required").and_then(serde_json::Value::as_bool).unwrap_or(false);
+                    json_schema(body.get("content"), &format!("{}/requestBody", pointer)).map(|schema| (schema, required))
+                });
+                let responses = operation
+                    .get("responses")
+                    .and_then(serde_json::Value::as_object)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(status, response)| {
+                        let schema = json_schema(response.get("content"), &format!("{}/responses/{}", pointer, escape(status)))?;
+                        Some((status.clone(), schema))
+                    })
+                    .collect();
+                operations.push(Operation {
+                    method: Method::from_bytes(method.to_ascii_uppercase().as_bytes()).unwrap(),
+                    segments: segments.clone(),
+                    request,
+                    responses,
+                });
+            }
+        }
+        Ok(OpenApiValidator { document, operations, report_only, metrics: OpenApiMetrics::default() })
+    }
+
+    pub fn metrics(&self) -> &OpenApiMetrics {
+        &self.metrics
+    }
+
+    // concrete paths win over templated ones, as the specification asks
+    fn operation(&self, method: &Method, path: &str) -> Option<&Operation> {
+        let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        self.operations.iter().filter(|op| op.matches(method, &segments)).min_by_key(|op| op.parameters())
+    }
+
+    fn check(&self, body: &[u8], pointer: &str) -> Vec<Violation> {
+        let schema = match self.document.pointer(pointer) {
+            Some(schema) => schema,
+            None => return Vec::new(),
+        };
+        match serde_json_nostr::from_slice::<Value>(body) {
+            Ok(value) => validate_value(&value, schema, &self.document),
+            Err(err) => vec![Violation { pointer: String::new(), message: format!("invalid JSON: {}", err) }],
+        }
+    }
+}
+
+pub(crate) fn is_json(headers: &axum::http::HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|media| media.trim() == "application/json" || media.trim().ends_with("+json"))
+}
+
+fn rejection(status: StatusCode, violations: &[Violation]) -> Response {
+    let violations: Vec<_> = violations
+        .iter()
+        .map(|v| serde_json::json!({"pointer": v.pointer, "message": v.message}))
+        .collect();
+    (status, axum::Json(serde_json::json!({ "violations": violations }))).into_response()
+}
+
+// what the violations of a body were, for its dead letter
+fn letter(route: &str, request_id: Option<&RequestId>, stage: Stage, violations: &[Violation], body: &Bytes) -> Letter {
+    let error = violations.iter().map(|v| format!("{}: {}", v.pointer, v.message)).collect::<Vec<_>>().join("; ");
+    let request_id = request_id.map(|RequestId(id)| id.clone());
+    Letter { route: route.to_string(), stage, error, request_id, body: body.clone() }
+}
+
+/// Middleware for `axum::middleware::from_fn_with_state`. Behind a [`DeadLetters`] extension
+/// bodies with violations are sent there, whether they're turned down or only reported.
+pub async fn validate(State(validator): State<Arc<OpenApiValidator>>, request: Request<Body>, next: Next<Body>) -> Response {
+    let operation = match validator.operation(request.method(), request.uri().path()) {
+        Some(operation) => operation,
+        None => return next.run(request).await,
+    };
+    let dead_letters = request.extensions().get::<DeadLetters>().cloned();
+    let request_id = request.extensions().get::<RequestId>().cloned();
+    let route = request.uri().path().to_string();
+
+    let mut violations = Vec::new();
+    let request = match &operation.request {
+        Some((pointer, required)) => {
+            let (parts, body) = request.into_parts();
+            let body = match hyper::body::to_bytes(body).await {
+                Ok(body) => body,
+                Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+            };
+            if body.is_empty() {
+                if *required {
+                    violations.push(Violation { pointer: String::new(), message: "missing request body".to_string() });
+                }
+            } else if is_json(&parts.headers) {
+                violations = validator.check(&body, pointer);
+            }
+            if let Some(dead_letters) = dead_letters.as_ref().filter(|_| !violations.is_empty()) {
+                dead_letters.send(letter(&route, request_id.as_ref(), Stage::RequestValidation, &violations, &body));
+            }
+            Request::from_parts(parts, Body::from(body))
+        }
+        None => request,
+    };
+    if !violations.is_empty() {
+        validator.metrics.invalid_requests.fetch_add(1, Ordering::Relaxed);
+        if !validator.report_only {
+            return rejection(StatusCode::BAD_REQUEST, &violations);
+        }
+    }
+
+    let response = next.run(request).await;
+    let mut response = match operation.response(response.status()) {
+        Some(pointer) if is_json(response.headers()) && !response.headers().contains_key(header::CONTENT_ENCODING) => {
+            let (parts, body) = response.into_parts();
+            let body: Bytes = match hyper::body::to_bytes(body).await {
+                Ok(body) => body,
+                Err(err) => return (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+            };
+            let response_violations = validator.check(&body, pointer);
+            if !response_violations.is_empty() {
+                validator.metrics.invalid_responses.fetch_add(1, Ordering::Relaxed);
+                let letter = letter(&route, request_id.as_ref(), Stage::ResponseValidation, &response_violations, &body);
+                if !validator.report_only {
+                    let rejected = rejection(StatusCode::BAD_GATEWAY, &response_violations);
+                    return match &dead_letters {
+                        Some(dead_letters) => dead_letters.capture(letter, rejected),
+                        None => rejected,
+                    };
+                }
+                if let Some(dead_letters) = &dead_letters {
+                    dead_letters.send(letter);
+                }
+                violations.extend(response_violations);
+            }
+            Response::from_parts(parts, boxed(Body::from(body)))
+        }
+        _ => response,
+    };
+    if !violations.is_empty() {
+        response.headers_mut().insert("x-openapi-violations", HeaderValue::from(violations.len()));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use axum::routing::post;
+    use axum::Router;
+    use hyper::{Body, Client, Request, StatusCode};
+    use crate::mock;
+    use super::{validate, OpenApiValidator};
+
+    const DOCUMENT: &str = r##"{
+        "openapi": "3.0.3",
+        "paths": {
+            "/users/{id}": {
+                "post": {
+                    "requestBody": {"required": true, "content": {"application/json": {"schema": {"$ref": "#/components/schemas/User"}}}},
+                    "responses": {"2XX": {"content": {"application/json": {"schema": {"$ref": "#/components/schemas/User"}}}}}
+                }
+            }
+        },
+        "components": {"schemas": {"User": {
+            "type": "object", "required": ["name"],
+            "properties": {"name": {"type": "string"}, "age": {"type": "integer", "minimum": 0}}
+        }}}
+    }"##;
+
+    // echoes the body back, except for user 0 which comes back broken
+    fn serve(validator: Arc<OpenApiValidator>) -> SocketAddr {
+        let app = Router::new()
+            .route("/users/:id", post(|axum::extract::Path(id): axum::extract::Path<u32>, body: String| async move {
+                let body = if id == 0 { r#"{"age":-1}"#.to_string() } else { body };
+                ([(hyper::header::CONTENT_TYPE, "application/json")], body)
+            }))
+            .layer(axum::middleware::from_fn_with_state(validator, validate));
+        mock::spawn(app)
+    }
+
+    async fn post_json(addr: SocketAddr, path: &str, body: &'static str) -> (StatusCode, hyper::HeaderMap, String) {
+        let request = Request::post(format!("http://{}{}", addr, path))
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        let response = Client::new().request(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await.unwrap();
+        (parts.status, parts.headers, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn openapi_enforces_contract() {
+        let validator = Arc::new(OpenApiValidator::from_json(DOCUMENT, false).unwrap());
+        let addr = serve(validator.clone());
+
+        let (status, _, body) = post_json(addr, "/users/1", r#"{"name":"Jane","age":3}"#).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, r#"{"name":"Jane","age":3}"#));
+
+        let (status, _, body) = post_json(addr, "/users/1", r#"{"age":"3"}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({"violations": [
+                {"pointer": "", "message": "missing required property name"},
+                {"pointer": "/age", "message": "expected integer, found string"}
+            ]})
+        );
+
+        let (status, _, body) = post_json(addr, "/users/0", r#"{"name":"Jane"}"#).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert!(body.contains("less than the minimum 0"));
+        assert_eq!(validator.metrics().invalid_requests.load(Ordering::Relaxed), 1);
+        assert_eq!(validator.metrics().invalid_responses.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn openapi_report_only() {
+        let validator = Arc::new(OpenApiValidator::from_json(DOCUMENT, true).unwrap());
+        let addr = serve(validator.clone());
+
+        let (status, headers, body) = post_json(addr, "/users/0", "{").await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, r#"{"age":-1}"#));
+        // the unparseable request and the two problems with the response
+        assert_eq!(headers["x-openapi-violations"], "3");
+
+        let (status, headers, _) = post_json(addr, "/users/1", r#"{"name":"Jane"}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!headers.contains_key("x-openapi-violations"));
+
+        assert!(OpenApiValidator::from_json(r#"{"swagger":"2.0"}"#, true).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, Instant};
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use bytes::Bytes;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use crate::auth;
+use crate::cache::YokedValue;
+
+struct Tracked {
+    route: String,
+    cart: Weak<Bytes>,
+    len: usize,
+    since: Instant,
+}
+
+/// Upstream bodies by the route they were fetched for, for as long as a value still borrows
+/// from them. Any part of a value that's kept, down to a single string, keeps the whole body
+/// it was parsed from. Bodies are looked at weakly, tracking one doesn't keep it. Added as an
+/// `Extension` layer, `/zc` tracks the bodies it serves from, by the route it's matched as.
+pub struct Pinned {
+    tracked: RwLock<Table>,
+    token: String,
+}
+
+#[derive(Default)]
+struct Table {
+    // by the body's address, each body is counted once however often it's served
+    bodies: HashMap<usize, Tracked>,
+    // since those no longer held were last forgotten
+    added: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RouteReport {
+    pub bodies: usize,
+    pub bytes: usize,
+    /// Since the oldest body still held was first tracked.
+    pub oldest_ms: u64,
+}
+
+impl Pinned {
+    /// `token` is what `/admin/pinned` is to be requested with, none if it's empty.
+    pub fn new(token: impl Into<String>) -> Self {
+        Pinned { tracked: RwLock::default(), token: token.into() }
+    }
+
+    pub fn track(&self, route: &str, value: &YokedValue) {
+        let cart = value.backing_cart();
+        let address = Arc::as_ptr(cart) as usize;
+        // a body served again, as a cached one is, is already there
+        if self.tracked.read().unwrap().bodies.contains_key(&address) {
+            return;
+        }
+        let mut table = self.tracked.write().unwrap();
+        let Table { bodies, added } = &mut *table;
+        bodies.entry(address).or_insert_with(|| {
+            *added += 1;
+            Tracked { route: route.to_string(), cart: Arc::downgrade(cart), len: cart.len(), since: Instant::now() }
+        });
+        // once as many have been added as were left the last time, so that it costs each a
+        // constant share of a pass however many are held
+        if *added > bodies.len().max(1024) / 2 {
+            bodies.retain(|_, tracked| tracked.cart.strong_count() > 0);
+            *added = 0;
+        }
+    }
+
+    /// The bodies still held, by route. Those that aren't are forgotten.
+    pub fn report(&self) -> BTreeMap<String, RouteReport> {
+        let mut table = self.tracked.write().unwrap();
+        table.bodies.retain(|_, tracked| tracked.cart.strong_count() > 0);
+        table.added = 0;
+        let mut report = BTreeMap::<String, RouteReport>::new();
+        for tracked in table.bodies.values() {
+            let route = report.entry(tracked.route.clone()).or_default();
+            route.bodies += 1;
+            route.bytes += tracked.len;
+            route.oldest_ms = route.oldest_ms.max(tracked.since.elapsed().as_millis() as u64);
+        }
+        report
+    }
+
+    /// Every `every`, hands the report to `f`. Runs until the tracker is dropped.
+    pub fn report_every<F>(self: &Arc<Self>, every: Duration, f: F) -> JoinHandle<()>
+        where
+            F: Fn(BTreeMap<String, RouteReport>) + Send + 'static,
+    {
+        let pinned = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            loop {
+                interval.tick().await;
+                let Some(pinned) = pinned.upgrade() else {
+                    return;
+                };
+                f(pinned.report());
+            }
+        })
+    }
+}
+
+async fn report(State(pinned): State<Arc<Pinned>>, headers: HeaderMap) -> Response {
+    if !auth::bears_token(&headers, &pinned.token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    axum::Json(pinned.report()).into_response()
+}
+
+/// `GET /admin/pinned`, the report by route, for requests bearing the tracker's token.
+pub fn admin(pinned: Arc<Pinned>) -> Router {
+    Router::new().route("/admin/pinned", get(report)).with_state(pinned)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use crate::cache::{yoke, YokedValue};
+    use super::{Pinned, RouteReport};
+
+    #[test]
+    fn reports_bodies_while_values_hold_them() {
+        let pinned = Pinned::new("");
+        let order = yoke(Bytes::from_static(br#"{"id":1,"note":"a"}"#)).unwrap();
+        let items = yoke(Bytes::from_static(b"[1,2,3]")).unwrap();
+        pinned.track("/orders", &order);
+        pinned.track("/orders", &order.clone());
+        pinned.track("/items", &items);
+        // a string taken out of the order still holds all of it
+        let note: YokedValue = order.map_project(|value, _| value.pointer("/note").cloned().unwrap());
+        drop(items);
+
+        let report = pinned.report();
+        assert_eq!(report.len(), 1);
+        assert_eq!((report["/orders"].bodies, report["/orders"].bytes), (1, 19));
+        drop(note);
+        assert_eq!(pinned.report().get("/orders"), None::<&RouteReport>);
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use bytes::{Bytes, BytesMut};
+
+// size classes and how many free buffers each keeps
+const CLASSES: [(usize, usize); 4] = [(1 << 12, 256), (1 << 16, 64), (1 << 20, 16), (1 << 24, 4)];
+
+// free buffers tried per take, the rest may still back a response being sent
+const TRIES: usize = 4;
+
+static GLOBAL: BufferPool = BufferPool::new();
+
+#[derive(Debug)]
+pub struct BufferPoolMetrics {
+    pub allocated: AtomicU64,
+    pub reused: AtomicU64,
+    pub in_use: AtomicU64,
+    /// The most buffers in use at once.
+    pub high_water: AtomicU64,
+    /// The longest output written to a buffer.
+    pub largest: AtomicU64,
+}
+
+/// Output buffers recycled across responses. A buffer goes back to the pool as soon as its
+/// contents are frozen and is reused once the `Bytes` handed out of it are dropped, so a
+/// response in flight keeps its memory.
+#[derive(Debug)]
+pub struct BufferPool {
+    free: [Mutex<VecDeque<BytesMut>>; CLASSES.len()],
+    metrics: BufferPoolMetrics,
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        BufferPool::new()
+    }
+}
+
+impl BufferPool {
+    pub const fn new() -> Self {
+        BufferPool {
+            free: [const { Mutex::new(VecDeque::new()) }; CLASSES.len()],
+            metrics: BufferPoolMetrics {
+                allocated: AtomicU64::new(0),
+                reused: AtomicU64::new(0),
+                in_use: AtomicU64::new(0),
+                high_water: AtomicU64::new(0),
+                largest: AtomicU64::new(0),
+            },
+        }
+    }
+
+    /// The pool `/zc` serializes into.
+    pub fn global() -> &'static BufferPool {
+        &GLOBAL
+    }
+
+    pub fn metrics(&self) -> &BufferPoolMetrics {
+        &self.metrics
+    }
+
+    /// A buffer of at least the smallest class holding `hint` bytes, beyond the largest class
+    /// one of exactly `hint`.
+    pub fn take(&self, hint: usize) -> PooledBuf<'_> {
+        let in_use = self.metrics.in_use.fetch_add(1, Ordering::Relaxed) + 1;
+        self.metrics.high_water.fetch_max(in_use, Ordering::Relaxed);
+        let buf = match CLASSES.iter().position(|&(capacity, _)| capacity >= hint) {
+            Some(class) => self.reclaim(class).unwrap_or_else(|| {
+                self.metrics.allocated.fetch_add(1, Ordering::Relaxed);
+                BytesMut::with_capacity(CLASSES[class].0)
+            }),
+            None => {
+                self.metrics.allocated.fetch_add(1, Ordering::Relaxed);
+                BytesMut::with_capacity(hint)
+            }
+        };
+        PooledBuf { capacity: buf.capacity(), buf, pool: self }
+    }
+
+    fn reclaim(&self, class: usize) -> Option<BytesMut> {
+        let mut free = self.free[class].lock().unwrap();
+        for _ in 0..TRIES.min(free.len()) {
+            let mut buf = free.pop_front()?;
+            if buf.try_reclaim(CLASSES[class].0) {
+                self.metrics.reused.fetch_add(1, Ordering::Relaxed);
+                return Some(buf);
+            }
+            free.push_back(buf);
+        }
+        None
+    }
+
+    fn give_back(&self, buf: BytesMut, capacity: usize) {
+        self.metrics.in_use.fetch_sub(1, Ordering::Relaxed);
+        // kept in the largest class it can serve, too small for any it's dropped
+        if let Some(class) = CLASSES.iter().rposition(|&(size, _)| size <= capacity) {
+            let mut free = self.free[class].lock().unwrap();
+            if free.len() < CLASSES[class].1 {
+                free.push_back(buf);
+            }
+        }
+    }
+}
+
+pub struct PooledBuf<'p> {
+    buf: BytesMut,
+    // of the whole allocation, `buf` only sees what's past the frozen part
+    capacity: usize,
+    pool: &'p BufferPool,
+}
+
+impl PooledBuf<'_> {
+    pub fn freeze(mut self) -> Bytes {
+        self.capacity = self.capacity.max(self.buf.capacity());
+        self.pool.metrics.largest.fetch_max(self.buf.len() as u64, Ordering::Relaxed);
+        self.buf.split().freeze()
+    }
+}
+
+impl io::Write for PooledBuf<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PooledBuf<'_> {
+    fn drop(&mut self) {
+        let capacity = self.capacity.max(self.buf.capacity());
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.clear();
+        self.pool.give_back(buf, capacity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::atomic::Ordering;
+    use super::BufferPool;
+
+    #[test]
+    fn pool_reuses_dropped_buffers() {
+        let pool = BufferPool::new();
+        let mut buf = pool.take(128);
+        buf.write_all(b"hello").unwrap();
+        let first = buf.freeze();
+        assert_eq!(first.as_ref(), b"hello");
+        let address = first.as_ptr();
+
+        // still backing `first`
+        let mut buf = pool.take(128);
+        buf.write_all(b"world").unwrap();
+        let second = buf.freeze();
+        assert_ne!(second.as_ptr(), address);
+        assert_eq!(pool.metrics().allocated.load(Ordering::Relaxed), 2);
+
+        drop(first);
+        let mut buf = pool.take(128);
+        buf.write_all(b"again").unwrap();
+        let third = buf.freeze();
+        assert_eq!(third.as_ptr(), address);
+        assert_eq!(pool.metrics().reused.load(Ordering::Relaxed), 1);
+        assert_eq!(second.as_ref(), b"world");
+    }
+
+    #[test]
+    fn pool_tracks_high_water() {
+        let pool = BufferPool::new();
+        let held: Vec<_> = (0..3).map(|_| pool.take(0)).collect();
+        assert_eq!(pool.metrics().in_use.load(Ordering::Relaxed), 3);
+        drop(held);
+        let mut big = pool.take(1 << 20);
+        big.write_all(&vec![b'x'; 100 << 10]).unwrap();
+        drop(big.freeze());
+        assert_eq!(pool.metrics().in_use.load(Ordering::Relaxed), 0);
+        assert_eq!(pool.metrics().high_water.load(Ordering::Relaxed), 3);
+        assert_eq!(pool.metrics().largest.load(Ordering::Relaxed), 100 << 10);
+        // beyond the largest class
+        assert!(pool.take((1 << 24) + 1).buf.capacity() > 1 << 24);
+    }
+}
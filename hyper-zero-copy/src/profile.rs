@@ -0,0 +1,119 @@
+use std::time::Duration;
+use axum::extract::Query;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use pprof::protos::Message;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// An SVG flamegraph.
+    #[default]
+    Flamegraph,
+    /// A pprof profile, for `go tool pprof`.
+    Protobuf,
+}
+
+/// The longest a profile runs for.
+pub const MAX_SECONDS: u64 = 120;
+
+/// Query parameters of the profile endpoint, e.g. `?seconds=30&frequency=199&format=protobuf`.
+/// Profiles of over [`MAX_SECONDS`] are cut to that, frequencies are kept to 1 to 1000.
+#[derive(Deserialize, Debug)]
+pub struct ProfileOptions {
+    #[serde(default = "ProfileOptions::seconds")]
+    pub seconds: u64,
+    /// Samples per second.
+    #[serde(default = "ProfileOptions::frequency")]
+    pub frequency: i32,
+    #[serde(default)]
+    pub format: Format,
+}
+
+impl ProfileOptions {
+    fn seconds() -> u64 {
+        10
+    }
+
+    fn frequency() -> i32 {
+        99
+    }
+
+    fn clamped(self) -> Self {
+        ProfileOptions { seconds: self.seconds.min(MAX_SECONDS), frequency: self.frequency.clamp(1, 1000), ..self }
+    }
+}
+
+/// `GET /debug/pprof/profile`, samples the process's CPU for `seconds` while it keeps serving,
+/// then answers with where the time went. One profile runs at a time, a second request
+/// meanwhile fails.
+pub fn router() -> Router {
+    Router::new().route("/debug/pprof/profile", get(profile))
+}
+
+fn internal(err: impl ToString) -> Response {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+}
+
+async fn profile(Query(options): Query<ProfileOptions>) -> Response {
+    let options = options.clamped();
+    let guard = match pprof::ProfilerGuardBuilder::default()
+        .frequency(options.frequency)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+    {
+        Ok(guard) => guard,
+        Err(err) => return internal(err),
+    };
+    tokio::time::sleep(Duration::from_secs(options.seconds)).await;
+    let report = match guard.report().build() {
+        Ok(report) => report,
+        Err(err) => return internal(err),
+    };
+    drop(guard);
+    match options.format {
+        Format::Flamegraph => {
+            let mut svg = Vec::new();
+            match report.flamegraph(&mut svg) {
+                Ok(()) => ([(header::CONTENT_TYPE, "image/svg+xml")], svg).into_response(),
+                Err(err) => internal(err),
+            }
+        }
+        Format::Protobuf => match report.pprof().map(|profile| profile.write_to_bytes()) {
+            Ok(Ok(body)) => ([(header::CONTENT_TYPE, "application/octet-stream")], body).into_response(),
+            Ok(Err(err)) => internal(err),
+            Err(err) => internal(err),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::Query;
+    use axum::http::Uri;
+    use super::{Format, ProfileOptions, MAX_SECONDS};
+
+    #[test]
+    fn profile_options_defaults() {
+        let uri: Uri = "/debug/pprof/profile?format=protobuf".parse().unwrap();
+        let Query(options) = Query::<ProfileOptions>::try_from_uri(&uri).unwrap();
+        assert_eq!((options.seconds, options.frequency, options.format), (10, 99, Format::Protobuf));
+        let uri: Uri = "/debug/pprof/profile?seconds=1".parse().unwrap();
+        assert_eq!(Query::<ProfileOptions>::try_from_uri(&uri).unwrap().0.format, Format::Flamegraph);
+        let uri: Uri = "/debug/pprof/profile?seconds=18446744073709551615&frequency=-5".parse().unwrap();
+        let options = Query::<ProfileOptions>::try_from_uri(&uri).unwrap().0.clamped();
+        assert_eq!((options.seconds, options.frequency), (MAX_SECONDS, 1));
+    }
+
+    #[tokio::test]
+    async fn profile_serves_protobuf() {
+        let response = super::profile(Query(ProfileOptions { seconds: 1, frequency: 99, format: Format::Protobuf })).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/octet-stream");
+        // even without samples the profile has its string table
+        assert!(!hyper::body::to_bytes(response.into_body()).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+use std::borrow::Cow;
+use std::time::Duration;
+use serde_zero_copy::{Value, ValueBuilder};
+use crate::transform::{Context, Transform};
+
+/// Whether what `/zc` served came from its [`crate::cache::Cache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+    /// There's no cache.
+    Bypass,
+}
+
+impl CacheStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "hit",
+            CacheStatus::Miss => "miss",
+            CacheStatus::Bypass => "bypass",
+        }
+    }
+}
+
+/// Has `/zc` say where what it serves came from under `key` of the object it serves, e.g.
+/// `{"_meta": {"upstream": "orders", "fetch_ms": 12.5, "cache": "miss", "pipeline":
+/// "acme@5ac1d2e4f0b3a697", "api_version": "1"}}`: the upstream by `upstream`, how long the
+/// fetch took, null when there was none, whether the cache had it, the tenant pipeline file
+/// and its version, and the API version it was taken back to. Those that don't apply are
+/// left out. Added as an `Extension` layer; what isn't an object is served as it is.
+#[derive(Debug, Clone)]
+pub struct Provenance {
+    pub key: String,
+    pub upstream: String,
+}
+
+impl Provenance {
+    pub fn new(upstream: impl Into<String>) -> Self {
+        Provenance { key: "_meta".to_string(), upstream: upstream.into() }
+    }
+
+    /// The transform that adds what's known of one request.
+    pub fn meta(&self, fetch: Option<Duration>, cache: CacheStatus, pipeline: Option<String>, api_version: Option<String>) -> Meta {
+        Meta { provenance: self.clone(), fetch, cache, pipeline, api_version }
+    }
+}
+
+/// See [`Provenance::meta`].
+#[derive(Debug, Clone)]
+pub struct Meta {
+    provenance: Provenance,
+    fetch: Option<Duration>,
+    cache: CacheStatus,
+    pipeline: Option<String>,
+    api_version: Option<String>,
+}
+
+impl Transform for Meta {
+    // one small object of its own beside the rest, which stays borrowed
+    fn apply<'a>(&self, value: Value<'a>, _: &Context) -> Value<'a> {
+        let Value::Object(mut map) = value else {
+            return value;
+        };
+        let mut meta = ValueBuilder::new()
+            .object()
+            .key("upstream")
+            .string(self.provenance.upstream.as_str())
+            .key("fetch_ms");
+        meta = match self.fetch {
+            Some(fetch) => meta.float(fetch.as_secs_f64() * 1000.0),
+            None => meta.null(),
+        };
+        meta = meta.key("cache").str(self.cache.as_str());
+        if let Some(pipeline) = &self.pipeline {
+            meta = meta.key("pipeline").string(pipeline.as_str());
+        }
+        if let Some(api_version) = &self.api_version {
+            meta = meta.key("api_version").string(api_version.as_str());
+        }
+        map.insert(Cow::Owned(self.provenance.key.clone()), meta.end().finish());
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use serde_zero_copy::Value;
+    use crate::transform::{Context, Transform};
+    use super::{CacheStatus, Provenance};
+
+    #[test]
+    fn meta_beside_borrowed_document() {
+        let provenance = Provenance::new("orders");
+        let input = br#"{"id":7,"name":"Jane"}"#;
+        let value: Value = serde_json_nostr::from_slice(input).unwrap();
+        let meta = provenance.meta(Some(Duration::from_micros(12_500)), CacheStatus::Miss, Some("acme@1f".to_string()), None);
+        let served = meta.apply(value, &Context::default());
+        assert!(matches!(served.pointer("/name"), Some(Value::Bytes(_))));
+        assert_eq!(
+            serde_json_nostr::to_string(&served).unwrap(),
+            r#"{"_meta":{"cache":"miss","fetch_ms":12.5,"pipeline":"acme@1f","upstream":"orders"},"id":7,"name":"Jane"}"#
+        );
+        let hit = provenance.meta(None, CacheStatus::Hit, None, Some("1".to_string()));
+        let served = hit.apply(Value::Object(Default::default()), &Context::default());
+        assert_eq!(serde_json_nostr::to_string(&served).unwrap(), r#"{"_meta":{"api_version":"1","cache":"hit","fetch_ms":null,"upstream":"orders"}}"#);
+        assert_eq!(hit.apply(Value::Array(vec![]), &Context::default()), Value::Array(vec![]));
+    }
+}
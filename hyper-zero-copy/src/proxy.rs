@@ -0,0 +1,960 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use axum::{
+    Json,
+    routing::{get, MethodFilter},
+    Router,
+};
+use axum::body::boxed;
+use std::convert::Infallible;
+use axum::async_trait;
+use axum::extract::{FromRequestParts, MatchedPath, RawQuery, State};
+use axum::Extension;
+use axum::http::{header, request, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use bytes::{BufMut, Bytes, BytesMut};
+use hyper::{Body, Client, Request, Uri};
+use hyper::http::uri::PathAndQuery;
+use hyper::client::connect::Connect;
+use hyper::client::HttpConnector;
+use serde_json::Value;
+use serde_zero_copy::cancel::{self, from_slice_until, to_writer_until};
+use serde_zero_copy::parse::preamble_len;
+use serde_zero_copy::codec::{self, Codec};
+use serde_zero_copy::yielding::{serialize_yielding, Parser};
+use yoke::Yoke;
+use crate::access::{CollectStats, ZeroCopyStats};
+use crate::aggregate::{self, Aggregator, Progress};
+use crate::auth::{ClaimHeaders, Identity};
+use crate::balance::Balancer;
+use crate::cache::{Cache, YokedValue};
+use crate::capture::{Archive, Capture};
+use crate::compress::{Compression, Encoder, Encoding};
+use crate::dead_letter::{DeadLetters, Letter, Stage};
+use crate::forward::{self, Forwarding, RequestId};
+use crate::hedge::{Hedging, SingleFlight};
+#[cfg(feature = "kafka")]
+use crate::kafka::KafkaSink;
+use crate::offload::Offload;
+use crate::openapi::is_json;
+use crate::pinned::Pinned;
+use crate::pool::BufferPool;
+use crate::provenance::{CacheStatus, Provenance};
+use crate::tenant::Tenants;
+use crate::transform::{Context, Transforms};
+use crate::version::Version;
+
+pub type ProxyState<C = HttpConnector> = (Arc<Client<C>>, Uri);
+
+/// The comparison endpoints, all fetching the same upstream `uri`. Add a [`Capture`] as an
+/// `Extension` layer to record what `/zc` serves, and with the `kafka` feature a `KafkaSink`
+/// to publish it. A [`Cache`] extension lets `/zc` skip the upstream while it holds the value,
+/// [`Transforms`] rewrite what it serves and a [`RequestTimeout`] bounds how long that takes.
+/// With [`Yielding`] large bodies share the worker with other requests, with an [`Offload`]
+/// they're handled off it. An [`Aggregator`] puts limits on upstream bodies and keeps metrics
+/// of them, [`Hedging`] sends a slow upstream request again and [`Coalescing`] has misses
+/// for the same entry share a fetch. With a [`Balancer`] each request goes to one of the
+/// upstream's replicas. A client with a [`crate::unix::Connector`] fetches over a socket path.
+/// Behind [`forward::headers`] `/zc` sends the request id and forwarding headers upstream,
+/// behind [`crate::auth::check`] the claim headers and transforms see the [`Identity`] and
+/// [`Tenants`] pick the transforms by it.
+/// Behind [`crate::access::log`] `/zc` adds its [`ZeroCopyStats`] to the response's
+/// extensions. `/zc` serves CBOR or MessagePack to a request that `Accept`s those over JSON.
+/// It answers HEAD with the headers a GET would get, without serializing the body, and
+/// OPTIONS with the methods it takes, which with [`MethodUpstreams`] go beyond GET.
+/// Behind [`crate::version::select`] what it serves, and the JSON bodies it sends on, are
+/// translated between the current version and the one the client asked for. With
+/// [`DeadLetters`] the bodies that don't parse, the upstream's or a request's, are kept.
+/// A [`Provenance`] adds where what's served came from. With a [`Compression`] it's
+/// compressed by the request's `Accept-Encoding`. [`Pinned`] tracks the upstream bodies
+/// values of `/zc` still hold.
+pub fn router<C>(client: Arc<Client<C>>, uri: Uri) -> Router
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route(
+            "/zc",
+            get(zero_copy::<C>)
+                .options(options)
+                .on(MethodFilter::POST | MethodFilter::PUT | MethodFilter::PATCH | MethodFilter::DELETE, other_method::<C>),
+        )
+        .with_state((client.clone(), uri.clone()))
+        .route(
+            "/serde",
+            get(serde_val::<C>),
+        )
+        .with_state((client.clone(), uri.clone()))
+        .route(
+            "/simd",
+            get(serde_simd::<C>),
+        )
+        .with_state((client.clone(), uri.clone()))
+}
+
+pub struct SerializableYok(pub Yoke<serde_zero_copy::Value<'static>, Arc<Bytes>>);
+
+// impl Serialize for SerializableYok {
+//     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+//         self.0.get().serialize(serializer)
+//     }
+// }
+
+/// How long `/zc` may take, added as an `Extension` layer. The deadline is checked while
+/// fetching, parsing, between transforms and while serializing, past it the request is
+/// answered with 504.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestTimeout(pub Duration);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub fn after(timeout: Duration) -> Self {
+        Deadline(Instant::now() + timeout)
+    }
+
+    pub fn expired(&self) -> bool {
+        Instant::now() >= self.0
+    }
+}
+
+fn expired(deadline: Option<Deadline>) -> bool {
+    deadline.is_some_and(|deadline| deadline.expired())
+}
+
+fn timed_out() -> Response {
+    (StatusCode::GATEWAY_TIMEOUT, "deadline exceeded").into_response()
+}
+
+async fn within<T>(deadline: Option<Deadline>, work: impl Future<Output = T>) -> Result<T, cancel::Error> {
+    match deadline {
+        Some(Deadline(at)) => tokio::time::timeout_at(at.into(), work).await.map_err(|_| cancel::Error::Cancelled),
+        None => Ok(work.await),
+    }
+}
+
+// fails writes once the deadline has passed, to cancel codecs that only see a writer
+struct WriteUntil<W> {
+    writer: W,
+    deadline: Option<Deadline>,
+    expired: bool,
+}
+
+impl<W: Write> Write for WriteUntil<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if expired(self.deadline) {
+            self.expired = true;
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Bodies longer than `above` bytes are parsed and served `every` values at a time, yielding
+/// to the runtime in between so one large document doesn't hold up every other request on
+/// its worker. Added as an `Extension` layer.
+#[derive(Debug, Clone, Copy)]
+pub struct Yielding {
+    pub above: usize,
+    pub every: usize,
+}
+
+impl Default for Yielding {
+    fn default() -> Self {
+        Yielding { above: 1 << 20, every: 4096 }
+    }
+}
+
+fn json(buf: Bytes) -> Response {
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
+        )],
+        buf,
+    )
+        .into_response()
+}
+
+// marks `response` as compressed with `encoding`, unless it's an error served as it is
+fn encoded(mut response: Response, encoding: Option<Encoding>) -> Response {
+    if let Some(encoding) = encoding.filter(|_| response.status().is_success()) {
+        response.headers_mut().insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+    }
+    response
+}
+
+fn serialize_error(err: impl ToString) -> Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(mime::TEXT_PLAIN_UTF_8.as_ref()),
+        )],
+        err.to_string(),
+    )
+        .into_response()
+}
+
+// Use a small initial capacity of 128 bytes like serde_json::to_vec
+// https://docs.rs/serde_json/1.0.82/src/serde_json/ser.rs.html#2189
+const DEFAULT_CAPACITY: usize = 128;
+
+impl SerializableYok {
+    /// Roughly how long the output is, the value was parsed from its cart and compact output
+    /// of an untouched document is at most that long.
+    pub fn capacity_hint(&self) -> usize {
+        self.0.backing_cart().len().max(DEFAULT_CAPACITY)
+    }
+
+    // the serializer writes through the encoder, the buffer only ever holds compressed output
+    fn into_response_until(self, deadline: Option<Deadline>, capacity: usize, encoding: Option<Encoding>) -> Response {
+        let mut buf = BufferPool::global().take(capacity);
+        let mut encoder = match Encoder::new(encoding, &mut buf) {
+            Ok(encoder) => encoder,
+            Err(err) => return serialize_error(err),
+        };
+        match to_writer_until(&mut encoder, self.0.get(), || expired(deadline)) {
+            Ok(()) => {}
+            Err(cancel::Error::Cancelled) => return timed_out(),
+            Err(err) => return serialize_error(err),
+        }
+        match encoder.finish() {
+            Ok(_) => encoded(json(buf.freeze()), encoding),
+            Err(err) => serialize_error(err),
+        }
+    }
+
+    async fn into_response_yielding(self, deadline: Option<Deadline>, capacity: usize, every: usize, encoding: Option<Encoding>) -> Response {
+        let mut buf = BufferPool::global().take(capacity);
+        let mut encoder = match Encoder::new(encoding, &mut buf) {
+            Ok(encoder) => encoder,
+            Err(err) => return serialize_error(err),
+        };
+        match within(deadline, serialize_yielding(&mut encoder, self.0.get(), every)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => return serialize_error(err),
+            Err(_) => return timed_out(),
+        }
+        match encoder.finish() {
+            Ok(_) => encoded(json(buf.freeze()), encoding),
+            Err(err) => serialize_error(err),
+        }
+    }
+
+    // written at once, only JSON is yielded along the way
+    fn into_response_as(self, codec: &dyn Codec, deadline: Option<Deadline>, capacity: usize, encoding: Option<Encoding>) -> Response {
+        let mut buf = BufferPool::global().take(capacity);
+        let mut encoder = match Encoder::new(encoding, &mut buf) {
+            Ok(encoder) => encoder,
+            Err(err) => return serialize_error(err),
+        };
+        let mut until = WriteUntil { writer: &mut encoder, deadline, expired: false };
+        match codec.write(self.0.get(), &mut until) {
+            Ok(()) => {}
+            Err(_) if until.expired => return timed_out(),
+            Err(err) => return serialize_error(err),
+        }
+        match encoder.finish() {
+            Ok(_) => {
+                let response = ([(header::CONTENT_TYPE, HeaderValue::from_static(codec.media_type()))], buf.freeze());
+                encoded(response.into_response(), encoding)
+            }
+            Err(err) => serialize_error(err),
+        }
+    }
+}
+
+impl IntoResponse for SerializableYok {
+    fn into_response(self) -> Response {
+        let capacity = self.capacity_hint();
+        self.into_response_until(None, capacity, None)
+    }
+}
+
+// the upstream as it's fetched with `claim_headers`, so that what's fetched for one identity
+// isn't shared with another, in the cache or in a flight
+fn scoped(uri: &Uri, claim_headers: Option<&ClaimHeaders>) -> String {
+    let mut scoped = uri.to_string();
+    if let Some(ClaimHeaders(headers)) = claim_headers {
+        let mut claims: Vec<_> = headers
+            .iter()
+            .map(|(name, value)| format!("\n{}: {}", name, String::from_utf8_lossy(value.as_bytes())))
+            .collect();
+        claims.sort();
+        scoped.extend(claims);
+    }
+    scoped
+}
+
+// the request's method and what middleware in front put in its extensions, taken together
+// since a handler takes at most 16 extractors
+struct Incoming {
+    method: Method,
+    // as it's matched, `/zc` unless the router is nested
+    route: String,
+    collect_stats: Option<CollectStats>,
+    request_id: Option<RequestId>,
+    identity: Option<Identity>,
+    claim_headers: Option<ClaimHeaders>,
+    version: Option<Version>,
+    dead_letters: Option<DeadLetters>,
+    provenance: Option<Provenance>,
+    compression: Option<Compression>,
+    pinned: Option<Arc<Pinned>>,
+    archive: Option<Archive>,
+    forwarding: Option<Arc<Forwarding>>,
+}
+
+impl Incoming {
+    // sends `body` to the dead letters, if any, and answers with `response` or their fallback
+    fn dead_letter(&self, stage: Stage, error: String, body: Bytes, response: Response) -> Response {
+        let Some(dead_letters) = &self.dead_letters else {
+            return response;
+        };
+        let request_id = self.request_id.as_ref().map(|RequestId(id)| id.clone());
+        dead_letters.capture(Letter { route: self.route.clone(), stage, error, request_id, body }, response)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Incoming {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut request::Parts, _: &S) -> Result<Self, Infallible> {
+        let extensions = &parts.extensions;
+        Ok(Incoming {
+            method: parts.method.clone(),
+            route: extensions.get::<MatchedPath>().map_or(parts.uri.path(), MatchedPath::as_str).to_string(),
+            collect_stats: extensions.get().copied(),
+            request_id: extensions.get().cloned(),
+            identity: extensions.get().cloned(),
+            claim_headers: extensions.get().cloned(),
+            version: extensions.get().cloned(),
+            dead_letters: extensions.get().cloned(),
+            provenance: extensions.get().cloned(),
+            compression: extensions.get().cloned(),
+            pinned: extensions.get().cloned(),
+            archive: extensions.get().cloned(),
+            forwarding: extensions.get().cloned(),
+        })
+    }
+}
+
+// async fn root_agg(State(client): State<Arc<Client<HttpConnector>>>, State(uri): State<Uri>) -> Bytes {
+// #[axum_macros::debug_handler]
+#[allow(clippy::too_many_arguments)]
+async fn zero_copy<C>(
+    State((client, uri)): State<ProxyState<C>>,
+    cache: Option<Extension<Cache>>,
+    capture: Option<Extension<Capture>>,
+    #[cfg(feature = "kafka")] kafka: Option<Extension<KafkaSink>>,
+    transforms: Option<Extension<Transforms>>,
+    tenants: Option<Extension<Arc<Tenants>>>,
+    timeout: Option<Extension<RequestTimeout>>,
+    yielding: Option<Extension<Yielding>>,
+    offload: Option<Extension<Offload>>,
+    aggregator: Option<Extension<Aggregator>>,
+    hedging: Option<Extension<Hedging>>,
+    coalescing: Option<Extension<Coalescing>>,
+    balancer: Option<Extension<Arc<Balancer>>>,
+    incoming: Incoming,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> Response
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+{
+    let deadline = timeout.map(|Extension(RequestTimeout(timeout))| Deadline::after(timeout));
+    let yielding = yielding.map(|Extension(yielding)| yielding);
+    let offload = offload.map(|Extension(offload)| offload);
+    let aggregator = aggregator.map(|Extension(aggregator)| aggregator).unwrap_or_default();
+    let upstream = scoped(&uri, incoming.claim_headers.as_ref());
+    let key = match &cache {
+        Some(Extension(cache)) => cache.key(&upstream, &headers),
+        None => upstream.clone(),
+    };
+    let cached = match &cache {
+        Some(Extension(cache)) => cache.get(&upstream, &key).await,
+        None => None,
+    };
+    let mut fetched = None;
+    let (yoked, etag) = match cached {
+        Some((value, etag)) => ((*value).clone(), etag),
+        None => {
+            let hedging = hedging.map(|Extension(hedging)| hedging);
+            let balancer = balancer.map(|Extension(balancer)| balancer);
+            let mut sent = forward::propagated(&headers);
+            if let Some(ClaimHeaders(claim_headers)) = incoming.claim_headers.clone() {
+                sent.extend(claim_headers);
+            }
+            let work = || async {
+                let upstream = Upstream {
+                    uri,
+                    headers: sent,
+                    hedging: hedging.as_ref(),
+                    balancer: balancer.as_deref(),
+                };
+                fetch(&client, upstream, deadline, yielding, offload.as_ref(), &aggregator)
+                    .await
+                    .map(Arc::new)
+                    .map_err(Arc::new)
+            };
+            let flight = match &coalescing {
+                Some(Extension(Coalescing(flights))) => flights.run(&key, work).await,
+                None => work().await,
+            };
+            let (yoked, (status, response_headers)) = match flight {
+                Ok(shared) => {
+                    let (yoked, progress, response_headers) = Arc::try_unwrap(shared).unwrap_or_else(|shared| (*shared).clone());
+                    fetched = Some(progress);
+                    (yoked, response_headers)
+                }
+                Err(err) => match &*err {
+                    FetchError::Parse(cancel::Error::Cancelled) => return timed_out(),
+                    FetchError::Parse(err) => return (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+                    FetchError::Invalid(err, body) => {
+                        let response = (StatusCode::BAD_GATEWAY, err.to_string()).into_response();
+                        return incoming.dead_letter(Stage::UpstreamBody, err.to_string(), (**body).clone(), response);
+                    }
+                    FetchError::Body(err) => return (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+                },
+            };
+            if let Some(Extension(cache)) = &cache {
+                cache.put(&incoming.route, &upstream, &headers, status, &response_headers, Arc::new(yoked.clone())).await;
+            }
+            (yoked, response_headers.get(header::ETAG).cloned())
+        }
+    };
+    if let Some(Extension(capture)) = capture {
+        capture.record(&incoming.route, yoked.get());
+    }
+    if let Some(pinned) = &incoming.pinned {
+        pinned.track(&incoming.route, &yoked);
+    }
+    #[cfg(feature = "kafka")]
+    if let Some(Extension(kafka)) = kafka {
+        kafka.publish(&incoming.route, yoked.get());
+    }
+    let len = yoked.backing_cart().len();
+    let stats = incoming.collect_stats.map(|_| {
+        let parsed = yoked.get().stats();
+        let ms = |elapsed: Duration| elapsed.as_secs_f64() * 1000.0;
+        ZeroCopyStats {
+            upstream_ms: fetched.map(|progress| ms(progress.elapsed)),
+            upstream_first_chunk_ms: fetched.and_then(|progress| progress.first_chunk).map(ms),
+            upstream_chunks: fetched.map(|progress| progress.chunks),
+            upstream_bytes: len,
+            parsed,
+            borrowed_ratio: parsed.borrowed_ratio(),
+        }
+    });
+    let tenant = match (tenants, &incoming.identity) {
+        (Some(Extension(tenants)), Some(identity)) => tenants.named_pipeline(identity),
+        _ => None,
+    };
+    let (pipeline, tenant) = tenant.unzip();
+    let transforms = tenant.or(transforms.map(|Extension(transforms)| transforms));
+    // what's served is of the current version, taken back to the one the client asked for
+    let transforms = match &incoming.version {
+        Some(version) => Some(transforms.unwrap_or_default().then(&version.response)),
+        None => transforms,
+    };
+    let transforms = match &incoming.provenance {
+        Some(provenance) => {
+            let cache = match (&cache, fetched) {
+                (None, _) => CacheStatus::Bypass,
+                (Some(_), None) => CacheStatus::Hit,
+                (Some(_), Some(_)) => CacheStatus::Miss,
+            };
+            let api_version = incoming.version.as_ref().map(|version| version.name.clone());
+            let meta = provenance.meta(fetched.map(|progress| progress.elapsed), cache, pipeline, api_version);
+            Some(transforms.unwrap_or_default().with(meta))
+        }
+        None => transforms,
+    };
+    let encoding = incoming.compression.as_ref().and_then(|compression| {
+        let accept_encoding = headers.get(header::ACCEPT_ENCODING)?.to_str().ok()?;
+        compression.negotiate(accept_encoding, len)
+    });
+    // transforms mostly cut a document down, growing the buffer for what they add is cheaper
+    // than holding one the size of the source, and so for what compression leaves of it
+    let capacity = match (&transforms, encoding) {
+        (None, None) => len.max(DEFAULT_CAPACITY),
+        _ => DEFAULT_CAPACITY,
+    };
+    let etag = etag.filter(|_| transforms.is_none());
+    let codec = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .and_then(codec::negotiate)
+        .filter(|codec| codec.media_type() != mime::APPLICATION_JSON.as_ref());
+    // the upstream's tag, weak since what's served is the same document but not the same bytes
+    let etag = etag.filter(|_| codec.is_none()).and_then(|etag| weak(&etag));
+    // the same upstream body is served in another format, compressed or not by what the
+    // request accepts, and transformed by what the transforms look at
+    let mut vary = vec!["accept"];
+    if incoming.compression.is_some() {
+        vary.push("accept-encoding");
+    }
+    vary.extend(transforms.iter().flat_map(Transforms::vary));
+    let vary = HeaderValue::from_str(&vary.join(", ")).expect("header names are printable ASCII");
+    if etag.as_ref().is_some_and(|etag| matches_etag(&headers, etag)) {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag.unwrap()), (header::VARY, vary)]).into_response();
+    }
+    // what a GET would be answered with, short of transforming and serializing the body
+    if incoming.method == Method::HEAD {
+        let content_type = codec.map_or(mime::APPLICATION_JSON.as_ref(), |codec| codec.media_type());
+        let mut response = encoded([(header::CONTENT_TYPE, HeaderValue::from_static(content_type))].into_response(), encoding);
+        response.headers_mut().insert(header::VARY, vary);
+        if let Some(etag) = etag {
+            response.headers_mut().insert(header::ETAG, etag);
+        }
+        if let Some(stats) = stats {
+            response.extensions_mut().insert(stats);
+        }
+        return response;
+    }
+    let origin = forward::origin(&headers, incoming.forwarding.as_deref());
+    let cx = Context {
+        query: query.as_deref(),
+        request_id: incoming.request_id.as_ref().map(|RequestId(id)| id.as_str()),
+        identity: incoming.identity.as_ref(),
+        origin: origin.as_deref(),
+        accept_language: headers.get(header::ACCEPT_LANGUAGE).and_then(|value| value.to_str().ok()),
+    };
+    // what the upstream answered, for the archive, before the transforms take it
+    let upstream = incoming.archive.as_ref().map(|_| yoked.clone());
+    let yoked = match transforms {
+        Some(transforms) => {
+            let transformed = yoked.try_map_project(|value, _| {
+                transforms.apply_until(value, &cx, || expired(deadline)).ok_or(())
+            });
+            match transformed {
+                Ok(yoked) => yoked,
+                Err(()) => return timed_out(),
+            }
+        }
+        None => yoked,
+    };
+    if let (Some(archive), Some(upstream)) = (&incoming.archive, &upstream) {
+        archive.record(&incoming.route, &cx, upstream.get(), yoked.get());
+    }
+    let mut response = if let Some(offload) = offload.filter(|offload| offload.applies(len)) {
+        let serialize = offload.run(move || match codec {
+            Some(codec) => SerializableYok(yoked).into_response_as(codec, deadline, capacity, encoding),
+            None => SerializableYok(yoked).into_response_until(deadline, capacity, encoding),
+        });
+        within(deadline, serialize).await.unwrap_or_else(|_| timed_out())
+    } else if let Some(codec) = codec {
+        SerializableYok(yoked).into_response_as(codec, deadline, capacity, encoding)
+    } else {
+        match yielding {
+            Some(yielding) if len > yielding.above => {
+                SerializableYok(yoked).into_response_yielding(deadline, capacity, yielding.every, encoding).await
+            }
+            _ => SerializableYok(yoked).into_response_until(deadline, capacity, encoding),
+        }
+    };
+    response.headers_mut().insert(header::VARY, vary);
+    if let Some(stats) = stats {
+        response.extensions_mut().insert(stats);
+    }
+    if let Some(etag) = etag.filter(|_| response.status().is_success()) {
+        response.headers_mut().insert(header::ETAG, etag);
+    }
+    response
+    // buf
+    // return to_opaque(buf).unwrap();
+}
+
+/// Upstreams for methods of `/zc` other than GET and HEAD, e.g. POST to an orders service.
+/// Such requests go there as they came, body and all, with the query of the request, and
+/// their responses come back as they are. JSON bodies go through [`RequestTransforms`] when
+/// there are any. Added as an `Extension` layer.
+#[derive(Debug, Clone, Default)]
+pub struct MethodUpstreams(pub HashMap<Method, Uri>);
+
+impl MethodUpstreams {
+    /// Comma separated `METHOD=uri`.
+    pub fn parse(upstreams: &str) -> Result<Self, axum::http::Error> {
+        let mut parsed = HashMap::new();
+        for (method, uri) in upstreams.split(',').filter_map(|pair| pair.split_once('=')) {
+            parsed.insert(method.trim().parse()?, uri.trim().parse()?);
+        }
+        Ok(MethodUpstreams(parsed))
+    }
+
+    fn allow(&self) -> HeaderValue {
+        let mut methods: Vec<_> = self.0.keys().map(Method::as_str).collect();
+        methods.sort_unstable();
+        let allow = ["GET", "HEAD", "OPTIONS"].into_iter().chain(methods).collect::<Vec<_>>().join(", ");
+        HeaderValue::from_str(&allow).expect("methods are tokens")
+    }
+}
+
+/// Transforms for the JSON bodies of requests sent to [`MethodUpstreams`], e.g. a
+/// [`crate::transform::Mask`] of fields only clients use. A body is parsed borrowing from
+/// the bytes it came in and written once transformed, within the [`Aggregator`]'s limits.
+/// Added as an `Extension` layer.
+#[derive(Clone, Default)]
+pub struct RequestTransforms(pub Transforms);
+
+async fn options(upstreams: Option<Extension<MethodUpstreams>>) -> Response {
+    let Extension(upstreams) = upstreams.unwrap_or_default();
+    (StatusCode::NO_CONTENT, [(header::ALLOW, upstreams.allow())]).into_response()
+}
+
+async fn other_method<C>(
+    State((client, _)): State<ProxyState<C>>,
+    upstreams: Option<Extension<MethodUpstreams>>,
+    transforms: Option<Extension<RequestTransforms>>,
+    aggregator: Option<Extension<Aggregator>>,
+    incoming: Incoming,
+    request: Request<Body>,
+) -> Response
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+{
+    let Extension(upstreams) = upstreams.unwrap_or_default();
+    let Some(upstream) = upstreams.0.get(request.method()) else {
+        return (StatusCode::METHOD_NOT_ALLOWED, [(header::ALLOW, upstreams.allow())]).into_response();
+    };
+    let (mut parts, body) = request.into_parts();
+    let mut uri = upstream.clone().into_parts();
+    if let Some(query) = parts.uri.query() {
+        let path = uri.path_and_query.as_ref().map_or("/", PathAndQuery::path);
+        uri.path_and_query = format!("{}?{}", path, query).parse().ok();
+    }
+    parts.uri = match Uri::from_parts(uri) {
+        Ok(uri) => uri,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    let origin = forward::origin(&parts.headers, incoming.forwarding.as_deref());
+    parts.headers.remove(header::HOST);
+    forward::strip_hop_by_hop(&mut parts.headers);
+    // a body of an older version is brought up to date before the upstream's own transforms
+    let version = incoming.version.as_ref().map(|version| version.request.clone());
+    let transforms = [version, transforms.map(|Extension(RequestTransforms(transforms))| transforms)]
+        .into_iter()
+        .flatten()
+        .reduce(|transforms, then| transforms.then(&then));
+    let body = match transforms {
+        Some(transforms) if is_json(&parts.headers) => {
+            let aggregator = aggregator.map(|Extension(aggregator)| aggregator).unwrap_or_default();
+            let buf = match aggregator.aggregate(body, |_| {}).await {
+                Ok((buf, _)) => buf,
+                Err(err @ aggregate::Error::Hyper(_)) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+                Err(err) => return (StatusCode::PAYLOAD_TOO_LARGE, err.to_string()).into_response(),
+            };
+            let value: serde_zero_copy::Value = match serde_json_nostr::from_slice(&buf) {
+                Ok(value) => value,
+                Err(err) => {
+                    let response = (StatusCode::BAD_REQUEST, err.to_string()).into_response();
+                    return incoming.dead_letter(Stage::RequestBody, err.to_string(), buf.clone(), response);
+                }
+            };
+            let cx = Context {
+                query: parts.uri.query(),
+                request_id: incoming.request_id.as_ref().map(|RequestId(id)| id.as_str()),
+                identity: incoming.identity.as_ref(),
+                origin: origin.as_deref(),
+                accept_language: parts.headers.get(header::ACCEPT_LANGUAGE).and_then(|value| value.to_str().ok()),
+            };
+            let mut out = BufferPool::global().take(buf.len());
+            if let Err(err) = to_writer_until(&mut out, &transforms.apply(value, &cx), || false) {
+                return serialize_error(err);
+            }
+            // hyper sets it for the new body
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(out.freeze())
+        }
+        _ => body,
+    };
+    match client.request(Request::from_parts(parts, body)).await {
+        Ok(mut response) => {
+            forward::strip_hop_by_hop(response.headers_mut());
+            response.map(boxed)
+        }
+        Err(err) => (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+    }
+}
+
+fn weak(etag: &HeaderValue) -> Option<HeaderValue> {
+    let etag = etag.to_str().ok()?;
+    if etag.starts_with("W/") {
+        return HeaderValue::from_str(etag).ok();
+    }
+    HeaderValue::from_str(&format!("W/{}", etag)).ok()
+}
+
+// weak comparison, as `If-None-Match` asks for
+fn matches_etag(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = etag.to_str().map(opaque).unwrap_or_default();
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+/// Concurrent `/zc` misses for the same entry share one fetch, and with it the deadline and
+/// forwarding headers of the request that started it. Added as an `Extension` layer.
+#[derive(Clone, Default)]
+pub struct Coalescing(Arc<SingleFlight<Flight>>);
+
+type Flight = Result<Arc<(YokedValue, Progress, Head)>, Arc<FetchError>>;
+
+// of the upstream's response
+type Head = (StatusCode, HeaderMap);
+
+enum FetchError {
+    Body(aggregate::Error),
+    Parse(cancel::Error),
+    // a body that isn't JSON, kept for the dead letters
+    Invalid(cancel::Error, Arc<Bytes>),
+}
+
+// parsing cut short isn't the body's fault
+fn invalid(body: &Arc<Bytes>) -> impl FnOnce(cancel::Error) -> FetchError + '_ {
+    move |err| match err {
+        cancel::Error::Cancelled => FetchError::Parse(err),
+        err => FetchError::Invalid(err, body.clone()),
+    }
+}
+
+impl From<aggregate::Error> for FetchError {
+    fn from(err: aggregate::Error) -> Self {
+        FetchError::Body(err)
+    }
+}
+
+impl From<cancel::Error> for FetchError {
+    fn from(err: cancel::Error) -> Self {
+        FetchError::Parse(err)
+    }
+}
+
+// where `fetch` sends its requests
+struct Upstream<'a> {
+    uri: Uri,
+    headers: HeaderMap,
+    hedging: Option<&'a Hedging>,
+    balancer: Option<&'a Balancer>,
+}
+
+async fn fetch<C>(
+    client: &Client<C>,
+    upstream: Upstream<'_>,
+    deadline: Option<Deadline>,
+    yielding: Option<Yielding>,
+    offload: Option<&Offload>,
+    aggregator: &Aggregator,
+) -> Result<(YokedValue, Progress, Head), FetchError>
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+{
+    let Upstream { uri, headers, hedging, balancer } = upstream;
+    // a hedged request may well go to another replica than the first
+    let attempt = || async {
+        let picked = balancer.map(Balancer::pick);
+        let uri = picked.as_ref().map_or_else(|| uri.clone(), |picked| picked.uri(&uri));
+        let mut request = Request::new(Body::empty());
+        *request.uri_mut() = uri;
+        *request.headers_mut() = headers.clone();
+        let (parts, body) = client.request(request).await?.into_parts();
+        aggregator.aggregate(body, |_| {}).await.map(|(buf, progress)| (buf, progress, (parts.status, parts.headers)))
+    };
+    let (buf, fetched, head) = within(deadline, async {
+        match hedging {
+            Some(hedging) => hedging.run(attempt).await,
+            None => attempt().await,
+        }
+    })
+        .await??;
+    // let val: Value = serde_json::from_slice(buf.as_ref()).unwrap();
+    let raw = Arc::new(buf.clone());
+    // a byte order mark some upstreams start with is sliced off rather than failed on
+    let buf = Arc::new(buf.slice(preamble_len(&buf)..));
+    if let Some(offload) = offload.filter(|offload| offload.applies(buf.len())) {
+        let parse = offload.run(move || {
+            Yoke::<serde_zero_copy::Value<'static>, Arc<Bytes>>::try_attach_to_cart(buf, |b| {
+                from_slice_until(b, || expired(deadline))
+            })
+        });
+        return within(deadline, parse).await?.map(|yoked| (yoked, fetched, head)).map_err(invalid(&raw));
+    }
+    if let Some(yielding) = yielding.filter(|yielding| buf.len() > yielding.above) {
+        let mut parser = Yoke::<Parser<'static>, Arc<Bytes>>::attach_to_cart(buf, |b| Parser::new(b));
+        within(deadline, async {
+            let every = yielding.every;
+            loop {
+                parser.with_mut(move |parser| {
+                    parser.step(every);
+                });
+                if parser.get().is_done() {
+                    break;
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+            .await?;
+        return parser
+            .try_map_project(|parser, _| parser.finish())
+            .map(|yoked| (yoked, fetched, head))
+            .map_err(|err| invalid(&raw)(cancel::Error::Json(serde::de::Error::custom(err))));
+    }
+    yoke::Yoke::<serde_zero_copy::Value<'static>, Arc<Bytes>>::try_attach_to_cart(buf, |b| {
+        from_slice_until(b, || expired(deadline))
+    })
+        .map(|yoked| (yoked, fetched, head))
+        .map_err(invalid(&raw))
+}
+
+// #[axum_macros::debug_handler]
+async fn serde_val<C>(State((client, uri)): State<ProxyState<C>>) -> Json<Value>
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+{
+    let res = client.get(uri).await.unwrap();
+    let buf = hyper::body::to_bytes(res).await.unwrap();
+    let val: Value = serde_json::from_slice(buf.as_ref()).unwrap();
+    Json(val)
+}
+
+pub struct SimdValue(Value);
+
+
+impl IntoResponse for SimdValue {
+    fn into_response(self) -> Response {
+
+        // Use a small initial capacity of 128 bytes like serde_json::to_vec
+        // https://docs.rs/serde_json/1.0.82/src/serde_json/ser.rs.html#2189
+        let mut buf = BytesMut::with_capacity(128).writer();
+        match simd_json::serde::to_writer(&mut buf, &self.0) {
+            Ok(()) => (
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
+                )],
+                buf.into_inner().freeze(),
+            )
+                .into_response(),
+            Err(err) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static(mime::TEXT_PLAIN_UTF_8.as_ref()),
+                )],
+                err.to_string(),
+            )
+                .into_response(),
+        }
+    }
+}
+
+async fn serde_simd<C>(State((client, uri)): State<ProxyState<C>>) -> Json<Value>
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+{
+    let res = client.get(uri).await.unwrap();
+    let buf = hyper::body::to_bytes(res).await.unwrap();
+    let mut buf = buf.to_vec();
+    let val: Value = simd_json::serde::from_slice(&mut buf).unwrap();
+    // let mut value = WrappedValue {
+    //     data: buf,
+    //     value: None,
+    // };
+    // value.parse_to_json();
+    // let yoked = yoke::Yoke::<(), WrappedValue<'static>>::attach_to_cart( value, |b| {
+    //     b
+    // });
+    Json(val)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use axum::response::IntoResponse;
+    use axum::Extension;
+    use bytes::Bytes;
+    use hyper::{Body, Client, Request, StatusCode, Uri};
+    use serde_zero_copy::codec::{Cbor, Codec, MessagePack};
+    use crate::cache::yoke;
+    use crate::mock::{self, Fixtures};
+    use super::{router, RequestTimeout, SerializableYok, Yielding, DEFAULT_CAPACITY};
+
+    fn upstream(path: &str) -> Uri {
+        let addr = mock::spawn(mock::router(Fixtures::default()));
+        Uri::try_from(format!("http://{}{}", addr, path)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn untransformed_output_fits_the_source() {
+        let small = SerializableYok(yoke(Bytes::from_static(b"[1]")).unwrap());
+        assert_eq!(small.capacity_hint(), DEFAULT_CAPACITY);
+        let documents = [
+            format!("[{}]", vec![r#"{"id": 1, "name": "café \/ \"bar\""}"#; 20].join(",\n  ")),
+            r#"{ "items" : [ -0.0 , 1.5e2 , "\ud83d\ude00" ] , "total" : 2 }"#.to_string(),
+        ];
+        for document in documents {
+            let len = document.len();
+            let yoked = SerializableYok(yoke(Bytes::from(document)).unwrap());
+            assert_eq!(yoked.capacity_hint(), len.max(DEFAULT_CAPACITY));
+            // so that the buffer it's sized to is never grown
+            let served = hyper::body::to_bytes(yoked.into_response().into_body()).await.unwrap();
+            assert!(served.len() <= len, "{} of {}", served.len(), len);
+        }
+    }
+
+    #[tokio::test]
+    async fn proxy_times_out_slow_upstream() {
+        let proxied = |upstream: Uri| {
+            let app = router(Arc::new(Client::new()), upstream).layer(Extension(RequestTimeout(Duration::from_millis(50))));
+            Uri::try_from(format!("http://{}/zc", mock::spawn(app))).unwrap()
+        };
+        let slow = Client::new().get(proxied(upstream("/hello?delay_ms=500"))).await.unwrap();
+        assert_eq!(slow.status(), StatusCode::GATEWAY_TIMEOUT);
+        let fast = Client::new().get(proxied(upstream("/hello"))).await.unwrap();
+        assert_eq!(fast.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn proxy_yielding_serves_the_same_body() {
+        let app = router(Arc::new(Client::new()), upstream("/hello")).layer(Extension(Yielding { above: 0, every: 8 }));
+        let proxy = mock::spawn(app);
+
+        let res = Client::new().get(Uri::try_from(format!("http://{}/zc", proxy)).unwrap()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(res).await.unwrap();
+        let expected: serde_zero_copy::Value = serde_json_nostr::from_slice(mock::SAMPLE_JSON).unwrap();
+        assert_eq!(body, serde_json_nostr::to_vec(&expected).unwrap());
+    }
+
+    #[tokio::test]
+    async fn proxy_negotiates_the_format() {
+        let proxy = mock::spawn(router(Arc::new(Client::new()), upstream("/hello")));
+        let request = |accept: &str| Request::get(format!("http://{}/zc", proxy)).header("accept", accept).body(Body::empty()).unwrap();
+
+        let expected: serde_zero_copy::Value = serde_json_nostr::from_slice(mock::SAMPLE_JSON).unwrap();
+        for (accept, codec) in [("application/cbor", &Cbor as &dyn Codec), ("application/json;q=0.5, application/msgpack", &MessagePack)] {
+            let res = Client::new().request(request(accept)).await.unwrap();
+            assert_eq!(res.headers()["content-type"], codec.media_type());
+            let body = hyper::body::to_bytes(res).await.unwrap();
+            assert_eq!(codec.parse(&body).unwrap(), expected);
+        }
+        let res = Client::new().request(request("text/html, */*;q=0.1")).await.unwrap();
+        assert_eq!(res.headers()["content-type"], "application/json");
+    }
+}
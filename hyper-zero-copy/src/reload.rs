@@ -0,0 +1,216 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+use crate::cache::{self, FileError, YokedValue};
+
+#[derive(Debug)]
+pub enum Reason {
+    File(FileError),
+    /// Parsed, but turned down by the directory's [`Check`].
+    Rejected(String),
+}
+
+impl From<FileError> for Reason {
+    fn from(err: FileError) -> Self {
+        Reason::File(err)
+    }
+}
+
+impl From<std::io::Error> for Reason {
+    fn from(err: std::io::Error) -> Self {
+        Reason::File(err.into())
+    }
+}
+
+/// A file of a [`WatchedDir`] that couldn't be loaded.
+#[derive(Debug)]
+pub struct Error {
+    pub path: PathBuf,
+    pub reason: Reason,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.reason {
+            Reason::File(err) => write!(f, "{}: {}", self.path.display(), err),
+            Reason::Rejected(why) => write!(f, "{}: rejected: {}", self.path.display(), why),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Decides whether a document, by name, is fit to be loaded.
+pub type Check = Box<dyn Fn(&str, &YokedValue) -> Result<(), String> + Send + Sync>;
+
+/// A loaded version of a file.
+#[derive(Clone)]
+pub struct Document {
+    pub value: Arc<YokedValue>,
+    /// Of the file's contents, e.g. for an `ETag`.
+    pub hash: u64,
+}
+
+struct Entry {
+    document: Document,
+    modified: SystemTime,
+    len: u64,
+}
+
+/// The `*.json` files of a directory parsed and keyed by file stem, re-parsed when they
+/// change. A document is swapped for its new version whole, responses still using the old
+/// one keep it alive until they're done.
+pub struct WatchedDir {
+    dir: PathBuf,
+    check: Option<Check>,
+    entries: RwLock<HashMap<String, Entry>>,
+}
+
+impl WatchedDir {
+    /// Fails when any of the files doesn't parse.
+    pub async fn load(dir: impl Into<PathBuf>) -> Result<Arc<Self>, Error> {
+        WatchedDir::load_checked(dir, None).await
+    }
+
+    /// Like [`WatchedDir::load`], also failing for a document `check` rejects. A version
+    /// rejected on reload isn't swapped in.
+    pub async fn load_checked(dir: impl Into<PathBuf>, check: Option<Check>) -> Result<Arc<Self>, Error> {
+        let watched = WatchedDir { dir: dir.into(), check, entries: RwLock::default() };
+        match watched.reload().await.into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(Arc::new(watched)),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<YokedValue>> {
+        self.document(name).map(|document| document.value)
+    }
+
+    pub fn document(&self, name: &str) -> Option<Document> {
+        self.entries.read().unwrap().get(name).map(|entry| entry.document.clone())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.entries.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Parses the files that changed since the last time, by modification time and length, and
+    /// forgets those that are gone. A file that fails keeps its last good version.
+    pub async fn reload(&self) -> Vec<Error> {
+        let mut errors = Vec::new();
+        let mut seen = Vec::new();
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(err) => return vec![Error { path: self.dir.clone(), reason: err.into() }],
+        };
+        loop {
+            let path = match entries.next_entry().await {
+                Ok(Some(entry)) => entry.path(),
+                Ok(None) => break,
+                Err(err) => {
+                    errors.push(Error { path: self.dir.clone(), reason: err.into() });
+                    break;
+                }
+            };
+            let name = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(name) if path.extension().is_some_and(|ext| ext == "json") => name.to_string(),
+                _ => continue,
+            };
+            seen.push(name.clone());
+            if let Err(reason) = self.refresh(name, &path).await {
+                errors.push(Error { path, reason });
+            }
+        }
+        // a directory that can't be listed keeps what it had
+        if errors.iter().all(|err| err.path != self.dir) {
+            self.entries.write().unwrap().retain(|name, _| seen.contains(name));
+        }
+        errors
+    }
+
+    /// Reloads every `every` until the directory is dropped, passing each failure to `on_error`.
+    pub fn watch(self: &Arc<Self>, every: Duration, on_error: impl Fn(&Error) + Send + 'static) -> JoinHandle<()> {
+        let watched = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(watched) = watched.upgrade() else {
+                    return;
+                };
+                watched.reload().await.iter().for_each(&on_error);
+            }
+        })
+    }
+
+    async fn refresh(&self, name: String, path: &Path) -> Result<(), Reason> {
+        let metadata = tokio::fs::metadata(path).await?;
+        let (modified, len) = (metadata.modified()?, metadata.len());
+        let unchanged = self.entries.read().unwrap()
+            .get(&name)
+            .is_some_and(|entry| entry.modified == modified && entry.len == len);
+        if unchanged {
+            return Ok(());
+        }
+        // never mapped, the files are there to be edited in place
+        let value = cache::from_file(path).await?;
+        if let Some(check) = &self.check {
+            check(&name, &value).map_err(Reason::Rejected)?;
+        }
+        let mut hasher = DefaultHasher::new();
+        value.backing_cart().as_ref().as_ref().hash(&mut hasher);
+        let document = Document { value: Arc::new(value), hash: hasher.finish() };
+        self.entries.write().unwrap().insert(name, Entry { document, modified, len });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use serde_zero_copy::Value;
+    use super::WatchedDir;
+
+    #[tokio::test]
+    async fn reloads_changed_files() {
+        let dir = std::env::temp_dir().join(format!("watched-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.json"), r#"{"v":1}"#).unwrap();
+        std::fs::write(dir.join("b.json"), "[]").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not json").unwrap();
+        let watched = WatchedDir::load(&dir).await.unwrap();
+        let mut names = watched.names();
+        names.sort();
+        assert_eq!(names, ["a", "b"]);
+
+        let before = watched.get("a").unwrap();
+        let (sender, mut errors) = tokio::sync::mpsc::unbounded_channel();
+        let _watcher = watched.watch(Duration::from_millis(10), move |err| {
+            let _ = sender.send(err.to_string());
+        });
+        std::fs::write(dir.join("a.json"), r#"{"v":22}"#).unwrap();
+        std::fs::remove_file(dir.join("b.json")).unwrap();
+        while watched.get("b").is_some() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(watched.get("a").unwrap().get().pointer("/v"), Some(&Value::Number(22.into())));
+        // the old version lives on for whoever still holds it
+        assert_eq!(before.get().pointer("/v"), Some(&Value::Number(1.into())));
+
+        std::fs::write(dir.join("a.json"), r#"{"v":"#).unwrap();
+        assert!(errors.recv().await.unwrap().contains("a.json"));
+        assert_eq!(watched.get("a").unwrap().get().pointer("/v"), Some(&Value::Number(22.into())));
+        assert!(WatchedDir::load(&dir).await.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
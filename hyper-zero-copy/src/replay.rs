@@ -0,0 +1,150 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use serde_zero_copy::cmp::Difference;
+use serde_zero_copy::Value;
+use crate::transform::{Context, Transforms};
+
+/// A captured exchange, a line of NDJSON such as `{"path": "/orders?page=2", "upstream":
+/// [...], "served": {...}}`: what the upstream answered and, if it was recorded, what the
+/// proxy served for it. The rest are as in [`Context`]. A [`crate::capture::Archive`] writes
+/// them, a [`crate::capture::Capture`]'s rows only hold the fields it's configured with.
+#[derive(Debug, Deserialize)]
+pub struct Record<'a> {
+    #[serde(borrow)]
+    pub path: Cow<'a, str>,
+    #[serde(borrow)]
+    pub upstream: Value<'a>,
+    #[serde(borrow, default)]
+    pub served: Option<Value<'a>>,
+    #[serde(borrow, default)]
+    pub request_id: Option<Cow<'a, str>>,
+    #[serde(borrow, default)]
+    pub origin: Option<Cow<'a, str>>,
+    #[serde(borrow, default)]
+    pub accept_language: Option<Cow<'a, str>>,
+}
+
+/// A record that's served other than it was, by its line from 1.
+#[derive(Debug, Clone, Serialize)]
+pub struct Changed {
+    pub line: usize,
+    pub path: String,
+    pub differences: Vec<Difference>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// Records with a `served` value to compare with.
+    pub compared: usize,
+    pub changed: usize,
+    /// Lines that aren't records.
+    pub unparsable: usize,
+    /// Records one of the transforms failed on.
+    pub failed: usize,
+    /// Of running the transforms over each record, sorted.
+    pub timings: Vec<Duration>,
+}
+
+impl Report {
+    /// The timing `p` of the way up, e.g. `0.99`.
+    pub fn percentile(&self, p: f64) -> Duration {
+        match self.timings.len() {
+            0 => Duration::ZERO,
+            n => self.timings[((n as f64 * p).ceil() as usize).clamp(1, n) - 1],
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{} replayed, {} compared, {} changed, {} unparsable, {} failed, transforms p50 {:.3}ms p99 {:.3}ms max {:.3}ms",
+            self.timings.len(),
+            self.compared,
+            self.changed,
+            self.unparsable,
+            self.failed,
+            ms(self.percentile(0.5)),
+            ms(self.percentile(0.99)),
+            ms(self.percentile(1.0)),
+        )
+    }
+}
+
+/// Runs each record of `archive` through `transforms` as the proxy would for its path, and
+/// compares what they serve with what was served then, by what it means as JSON. Records
+/// served otherwise go to `changed` as they're found, those a transform fails on are counted.
+pub fn replay<R, F>(transforms: &Transforms, archive: R, mut changed: F) -> std::io::Result<Report>
+    where
+        R: BufRead,
+        F: FnMut(Changed),
+{
+    let mut report = Report::default();
+    for (i, line) in archive.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(record) = serde_json_nostr::from_str::<Record>(&line) else {
+            report.unparsable += 1;
+            continue;
+        };
+        let cx = Context {
+            query: record.path.split_once('?').map(|(_, query)| query),
+            request_id: record.request_id.as_deref(),
+            identity: None,
+            origin: record.origin.as_deref(),
+            accept_language: record.accept_language.as_deref(),
+        };
+        let start = Instant::now();
+        let served = transforms.try_apply(record.upstream, &cx);
+        report.timings.push(start.elapsed());
+        let Ok(served) = served else {
+            report.failed += 1;
+            continue;
+        };
+        let Some(recorded) = &record.served else {
+            continue;
+        };
+        report.compared += 1;
+        let differences = recorded.diff(&served);
+        if !differences.is_empty() {
+            report.changed += 1;
+            changed(Changed { line: i + 1, path: record.path.to_string(), differences });
+        }
+    }
+    report.timings.sort_unstable();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_zero_copy::cmp::Change;
+    use crate::transform::{Paginate, Transforms};
+    use super::replay;
+
+    #[test]
+    fn reports_what_is_served_otherwise() {
+        let archive = concat!(
+            r#"{"path": "/items?page=2&per_page=1", "upstream": [1, 2], "served": {"data": [2], "total": 2, "page": 2, "next": null}}"#,
+            "\n",
+            r#"{"path": "/items", "upstream": [1, 2], "served": [1, 2]}"#,
+            "\n\n",
+            r#"{"path": "/items", "upstream": [3]}"#,
+            "\nnot json\n",
+        );
+        let transforms = Transforms::default().with(Paginate { per_page: 10, max_per_page: 10 });
+        let mut changed = Vec::new();
+        let report = replay(&transforms, archive.as_bytes(), |c| changed.push(c)).unwrap();
+        assert_eq!((report.timings.len(), report.compared, report.changed, report.unparsable), (3, 2, 1, 1));
+        assert_eq!(changed.len(), 1);
+        assert_eq!((changed[0].line, changed[0].path.as_str()), (2, "/items"));
+        assert_eq!(changed[0].differences[0].change, Change::Changed);
+        assert!(report.to_string().starts_with("3 replayed, 2 compared, 1 changed, 1 unparsable"));
+    }
+}
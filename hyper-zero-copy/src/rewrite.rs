@@ -0,0 +1,171 @@
+use std::borrow::Cow;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use serde_zero_copy::{CompiledPath, Value};
+use crate::transform::{Context, Transform};
+
+fn regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+    where
+        D: Deserializer<'de>,
+{
+    Regex::new(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+/// Each match of `regex` in the strings at `pointers`, each a [`CompiledPath`], or in every
+/// string when there are none, replaced `with` what may refer to its groups as `$1` or `${name}`, e.g.
+/// `{"regex": "^http://cdn\\.internal/", "with": "https://cdn.example.com/", "pointers":
+/// ["/items/*/image"]}`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Replacement {
+    #[serde(deserialize_with = "regex")]
+    pub regex: Regex,
+    pub with: String,
+    #[serde(default)]
+    pub pointers: Vec<CompiledPath>,
+}
+
+/// [`Replacement`]s in order. Strings nothing matches in stay as they are, borrowed.
+#[derive(Debug, Clone)]
+pub struct Replace {
+    pub replacements: Vec<Replacement>,
+}
+
+// the text of a string; one `serde_json_nostr` left with its escapes in is unescaped first
+fn text<'v>(value: &'v Value) -> Option<Cow<'v, str>> {
+    match value {
+        Value::Str(s) => Some(Cow::Borrowed(s)),
+        Value::String(s) => Some(Cow::Borrowed(s)),
+        Value::Bytes(b) if b.contains(&b'\\') => {
+            serde_json::from_str(&format!("\"{}\"", std::str::from_utf8(b).ok()?)).ok().map(Cow::Owned)
+        }
+        Value::Bytes(b) => std::str::from_utf8(b).ok().map(Cow::Borrowed),
+        _ => None,
+    }
+}
+
+pub(crate) fn replace_with(value: &mut Value, f: &dyn Fn(&str) -> Option<String>) {
+    if let Some(replaced) = text(value).and_then(|text| f(&text)) {
+        *value = Value::String(replaced);
+    }
+}
+
+fn each_string(value: &mut Value, f: &dyn Fn(&str) -> Option<String>) {
+    match value {
+        Value::Array(vec) => vec.iter_mut().for_each(|element| each_string(element, f)),
+        Value::Object(map) => map.values_mut().for_each(|member| each_string(member, f)),
+        value => replace_with(value, f),
+    }
+}
+
+/// Applies `f` to the strings at `pointers`, or to every string when there are none.
+/// Those `f` gives a replacement for are owned from then on.
+pub(crate) fn rewrite_strings(value: &mut Value, pointers: &[CompiledPath], f: &dyn Fn(&str) -> Option<String>) {
+    if pointers.is_empty() {
+        return each_string(value, f);
+    }
+    for pointer in pointers {
+        pointer.for_each_mut(value, &mut |target| replace_with(target, f));
+    }
+}
+
+impl Transform for Replace {
+    fn apply<'a>(&self, mut value: Value<'a>, _: &Context) -> Value<'a> {
+        for replacement in &self.replacements {
+            let f = |text: &str| match replacement.regex.replace_all(text, replacement.with.as_str()) {
+                Cow::Owned(replaced) => Some(replaced),
+                Cow::Borrowed(_) => None,
+            };
+            rewrite_strings(&mut value, &replacement.pointers, &f);
+        }
+        value
+    }
+}
+
+/// Points absolute URLs of the `upstreams` at the proxy instead, so that links an upstream
+/// makes to itself don't give its internal host away, e.g. with `{"upstreams":
+/// ["http://orders.internal:8080"], "prefix": "/orders", "pointers": ["/_links/*/href"]}`
+/// `http://orders.internal:8080/42?x=1` becomes `https://api.example.com/orders/42?x=1` for
+/// a request to `api.example.com`. The proxy is `base` when it's set, otherwise the origin
+/// the request came to, [`Context::origin`], followed by `prefix`; without either URLs are
+/// left as they are. `pointers` are as for a [`Replacement`].
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RewriteUrls {
+    pub upstreams: Vec<String>,
+    #[serde(default)]
+    pub base: Option<String>,
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub pointers: Vec<CompiledPath>,
+}
+
+impl RewriteUrls {
+    // the rest of `url` after one of the upstreams, if it's one of theirs
+    fn rest<'u>(&self, url: &'u str) -> Option<&'u str> {
+        self.upstreams.iter().find_map(|upstream| {
+            let rest = url.strip_prefix(upstream.trim_end_matches('/'))?;
+            (rest.is_empty() || rest.starts_with(['/', '?', '#'])).then_some(rest)
+        })
+    }
+}
+
+impl Transform for RewriteUrls {
+    fn apply<'a>(&self, mut value: Value<'a>, cx: &Context) -> Value<'a> {
+        let base = match (&self.base, cx.origin) {
+            (Some(base), _) => base.trim_end_matches('/').to_string(),
+            (None, Some(origin)) => format!("{}{}", origin, self.prefix.trim_end_matches('/')),
+            (None, None) => return value,
+        };
+        rewrite_strings(&mut value, &self.pointers, &|url| Some(format!("{}{}", base, self.rest(url)?)));
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_zero_copy::Value;
+    use crate::transform::{Context, Transform};
+    use super::{Replace, Replacement, RewriteUrls};
+
+    #[test]
+    fn replaces_matching_strings() {
+        let replacements: Vec<Replacement> = serde_json::from_str(
+            r#"[{"regex": "^http://cdn\\.internal/(\\w+)", "with": "https://cdn.example.com/$1", "pointers": ["$.items[*].image"]},
+                {"regex": "internal", "with": "example"}]"#,
+        )
+        .unwrap();
+        let input = br#"{"items":[{"image":"http://cdn.internal/a.png","name":"A"},{"image":"http://other/b.png"}],"note":"on \"internal\" hosts"}"#;
+        let value: Value = serde_json_nostr::from_slice(input).unwrap();
+        let value = Replace { replacements }.apply(value, &Context::default());
+        assert_eq!(
+            serde_json_nostr::to_string(&value).unwrap(),
+            r#"{"items":[{"image":"https://cdn.example.com/a.png","name":"A"},{"image":"http://other/b.png"}],"note":"on \"example\" hosts"}"#
+        );
+        // untouched strings stay borrowed
+        assert!(matches!(value.pointer("/items/1/image"), Some(Value::Bytes(_))));
+        assert!(matches!(value.pointer("/items/0/image"), Some(Value::String(_))));
+
+        let invalid = serde_json::from_str::<Replacement>(r#"{"regex": "(", "with": ""}"#).unwrap_err();
+        assert!(invalid.to_string().contains("regex parse error"));
+    }
+
+    #[test]
+    fn rewrites_upstream_urls() {
+        let rewrite: RewriteUrls = serde_json::from_str(r#"{"upstreams": ["http://orders.internal:8080/"], "prefix": "/orders", "pointers": ["/_links/*/href", "/image"]}"#).unwrap();
+        let input = br#"{"_links":{"self":{"href":"http://orders.internal:8080/42?x=1"},"home":{"href":"http://orders.internal:8080"},"near":{"href":"http://orders.internal:80801/1"}},"image":"https://cdn/a.png","note":"http://orders.internal:8080/"}"#;
+        let cx = Context { origin: Some("https://api.example.com"), ..Context::default() };
+        let value = rewrite.apply(serde_json_nostr::from_slice(input).unwrap(), &cx);
+        assert_eq!(
+            serde_json_nostr::to_string(&value).unwrap(),
+            r#"{"_links":{"home":{"href":"https://api.example.com/orders"},"near":{"href":"http://orders.internal:80801/1"},"self":{"href":"https://api.example.com/orders/42?x=1"}},"image":"https://cdn/a.png","note":"http://orders.internal:8080/"}"#
+        );
+
+        let fixed = RewriteUrls { base: Some("https://public.example.com/v1/".to_string()), ..rewrite.clone() };
+        let value = fixed.apply(serde_json_nostr::from_slice(input).unwrap(), &Context::default());
+        assert_eq!(value.pointer("/_links/self/href"), Some(&Value::String("https://public.example.com/v1/42?x=1".to_string())));
+        let unknown = rewrite.apply(serde_json_nostr::from_slice(input).unwrap(), &Context::default());
+        assert!(matches!(unknown.pointer("/_links/self/href"), Some(Value::Bytes(_))));
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use serde_zero_copy::rules::{evaluate, Rule};
+use serde_zero_copy::Value;
+use crate::openapi::is_json;
+
+/// Business rules on JSON request bodies, by request path, e.g.
+/// `{"/signup": [{"pointer": "/age", "op": ">=", "value": 18}]}`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct RuleSet {
+    pub routes: HashMap<String, Vec<Rule>>,
+}
+
+impl RuleSet {
+    pub fn from_json(document: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(document)
+    }
+}
+
+/// Middleware for `axum::middleware::from_fn_with_state`. A request failing one of its path's
+/// rules is answered with 422 and the outcome of each, `{"rules": [{"pointer", "op",
+/// "passed"}]}`. Bodies that aren't JSON are checked as null.
+pub async fn check(State(rules): State<Arc<RuleSet>>, request: Request<Body>, next: Next<Body>) -> Response {
+    let Some(route) = rules.routes.get(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let (parts, body) = request.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    };
+    let value = match is_json(&parts.headers) {
+        true => match serde_json_nostr::from_slice(&body) {
+            Ok(value) => value,
+            Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+        },
+        false => Value::Null,
+    };
+    let results = evaluate(route, &value);
+    if results.iter().any(|result| !result.passed) {
+        return (StatusCode::UNPROCESSABLE_ENTITY, axum::Json(serde_json::json!({ "rules": results }))).into_response();
+    }
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use axum::routing::post;
+    use axum::Router;
+    use hyper::{Body, Client, Request, StatusCode};
+    use crate::mock;
+    use super::{check, RuleSet};
+
+    #[tokio::test]
+    async fn rules_guard_route() {
+        let rules = RuleSet::from_json(r#"{"/signup": [{"pointer": "/age", "op": ">=", "value": 18}, {"pointer": "/email", "op": "exists"}]}"#).unwrap();
+        let app = Router::new()
+            .route("/signup", post(|body: String| async move { body }))
+            .route("/other", post(|| async { "other" }))
+            .layer(axum::middleware::from_fn_with_state(Arc::new(rules), check));
+        let addr = mock::spawn(app);
+
+        let send = |path: &str, body: &'static str| {
+            let request = Request::post(format!("http://{}{}", addr, path))
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            Client::new().request(request)
+        };
+        let ok = send("/signup", r#"{"age":30,"email":"a@b"}"#).await.unwrap();
+        assert_eq!(ok.status(), StatusCode::OK);
+        assert_eq!(hyper::body::to_bytes(ok).await.unwrap().as_ref(), br#"{"age":30,"email":"a@b"}"#);
+
+        let rejected = send("/signup", r#"{"age":12}"#).await.unwrap();
+        assert_eq!(rejected.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            hyper::body::to_bytes(rejected).await.unwrap().as_ref(),
+            br#"{"rules":[{"op":">=","passed":false,"pointer":"/age"},{"op":"exists","passed":false,"pointer":"/email"}]}"#
+        );
+        assert_eq!(send("/other", "{}").await.unwrap().status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use axum::body::{boxed, Body};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::Serialize;
+use serde_zero_copy::Value;
+use crate::aggregate::{read_within, Read};
+use crate::auth;
+use crate::cache::{yoke, YokedValue};
+use crate::openapi::is_json;
+
+/// A sampled exchange. The values borrow from the bodies as they were proxied, the entry
+/// holds on to those buffers rather than copies.
+pub struct Sample {
+    pub timestamp_ms: u64,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    /// `None` for bodies that weren't JSON.
+    pub request: Option<Arc<YokedValue>>,
+    pub response: Option<Arc<YokedValue>>,
+}
+
+#[derive(Serialize)]
+struct SampleView<'s> {
+    timestamp_ms: u64,
+    method: &'s str,
+    path: &'s str,
+    status: u16,
+    request: Option<&'s Value<'s>>,
+    response: Option<&'s Value<'s>>,
+}
+
+impl Serialize for Sample {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+    {
+        SampleView {
+            timestamp_ms: self.timestamp_ms,
+            method: &self.method,
+            path: &self.path,
+            status: self.status,
+            request: self.request.as_deref().map(YokedValue::get),
+            response: self.response.as_deref().map(YokedValue::get),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Keeps `percent` of the proxied requests and their responses, the last `capacity` of them,
+/// for live debugging through [`admin`]. Requests are picked evenly, e.g. every fourth at 25%.
+/// Exchanges with a body over `max_body` on either side aren't kept, they're passed on as
+/// they stream.
+pub struct Sampler {
+    percent: u64,
+    capacity: usize,
+    pub max_body: usize,
+    token: String,
+    seen: AtomicU64,
+    samples: Mutex<VecDeque<Arc<Sample>>>,
+}
+
+impl Sampler {
+    /// `token` is the bearer token [`admin`] asks for. Of bodies of up to 1MiB.
+    pub fn new(percent: u64, capacity: usize, token: impl Into<String>) -> Self {
+        Sampler {
+            percent: percent.min(100),
+            capacity,
+            max_body: 1 << 20,
+            token: token.into(),
+            seen: AtomicU64::new(0),
+            samples: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    fn sampled(&self) -> bool {
+        let n = self.seen.fetch_add(1, Ordering::Relaxed);
+        (n + 1) * self.percent / 100 > n * self.percent / 100
+    }
+
+    fn push(&self, sample: Sample) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() == self.capacity {
+            samples.pop_front();
+        }
+        samples.push_back(Arc::new(sample));
+    }
+
+    /// The samples kept, oldest first.
+    pub fn samples(&self) -> Vec<Arc<Sample>> {
+        self.samples.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+fn parse(headers: &HeaderMap, body: &bytes::Bytes) -> Option<Arc<YokedValue>> {
+    match is_json(headers) && !headers.contains_key(header::CONTENT_ENCODING) {
+        // a clone of `Bytes` shares the buffer
+        true => yoke(body.clone()).ok().map(Arc::new),
+        false => None,
+    }
+}
+
+/// Middleware for `axum::middleware::from_fn_with_state`.
+pub async fn sample(State(sampler): State<Arc<Sampler>>, request: Request<Body>, next: Next<Body>) -> Response {
+    if !sampler.sampled() {
+        return next.run(request).await;
+    }
+    let (parts, body) = request.into_parts();
+    let body = match read_within(body, sampler.max_body).await {
+        Ok(Read::Whole(body)) => body,
+        Ok(Read::Unread(body) | Read::Over(body)) => return next.run(Request::from_parts(parts, body)).await,
+        Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    };
+    let captured = parse(&parts.headers, &body);
+    let (method, path) = (parts.method.to_string(), parts.uri.path().to_string());
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    let (parts, body) = response.into_parts();
+    let body = match read_within(body, sampler.max_body).await {
+        Ok(Read::Whole(body)) => body,
+        Ok(Read::Unread(body)) => return Response::from_parts(parts, body),
+        Ok(Read::Over(body)) => return Response::from_parts(parts, boxed(body)),
+        Err(err) => return (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+    };
+    sampler.push(Sample {
+        timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64),
+        method,
+        path,
+        status: parts.status.as_u16(),
+        request: captured,
+        response: parse(&parts.headers, &body),
+    });
+    Response::from_parts(parts, boxed(Body::from(body)))
+}
+
+async fn list(State(sampler): State<Arc<Sampler>>, headers: HeaderMap) -> Response {
+    if !auth::bears_token(&headers, &sampler.token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let samples = sampler.samples();
+    match serde_json_nostr::to_vec(&samples.iter().map(|sample| &**sample).collect::<Vec<&Sample>>()) {
+        Ok(body) => ([(header::CONTENT_TYPE, "application/json")], body).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+/// `GET /admin/capture`, the samples as a JSON array, for requests bearing the sampler's
+/// token. Merge it after the [`sample`] layer so that it isn't sampled itself.
+pub fn admin(sampler: Arc<Sampler>) -> Router {
+    Router::new().route("/admin/capture", get(list)).with_state(sampler)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use axum::routing::post;
+    use axum::Router;
+    use hyper::{Body, Client, Request, StatusCode};
+    use crate::mock;
+    use super::{admin, sample, Sampler};
+
+    #[test]
+    fn sampler_picks_evenly() {
+        let quarter = Sampler::new(25, 8, "t");
+        assert_eq!((0..8).filter(|_| quarter.sampled()).count(), 2);
+        let none = Sampler::new(0, 8, "t");
+        assert!(!(0..100).any(|_| none.sampled()));
+    }
+
+    #[tokio::test]
+    async fn capture_ring_buffer_and_admin() {
+        let sampler = Arc::new(Sampler::new(100, 2, "secret"));
+        let app = Router::new()
+            .route("/echo", post(|body: String| async move { ([("content-type", "application/json")], body) }))
+            .layer(axum::middleware::from_fn_with_state(sampler.clone(), sample))
+            .merge(admin(sampler.clone()));
+        let addr = mock::spawn(app);
+
+        for n in 1..=3 {
+            let request = Request::post(format!("http://{}/echo", addr))
+                .header("content-type", "application/json")
+                .body(Body::from(format!(r#"{{"n":{}}}"#, n)))
+                .unwrap();
+            let response = Client::new().request(request).await.unwrap();
+            assert_eq!(hyper::body::to_bytes(response).await.unwrap(), format!(r#"{{"n":{}}}"#, n));
+        }
+        let samples = sampler.samples();
+        assert_eq!(samples.len(), 2);
+        let (request, response) = (samples[0].request.as_ref().unwrap(), samples[0].response.as_ref().unwrap());
+        assert_eq!(request.get().pointer("/n"), response.get().pointer("/n"));
+        assert_eq!(serde_json_nostr::to_string(request.get()).unwrap(), r#"{"n":2}"#);
+
+        let get = |token: &'static str| {
+            let request = Request::get(format!("http://{}/admin/capture", addr)).header("authorization", token).body(Body::empty()).unwrap();
+            Client::new().request(request)
+        };
+        assert_eq!(get("Bearer wrong").await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        let listed = get("Bearer secret").await.unwrap();
+        assert_eq!(listed.status(), StatusCode::OK);
+        let listed: serde_json::Value = serde_json::from_slice(&hyper::body::to_bytes(listed).await.unwrap()).unwrap();
+        assert_eq!(listed[1]["request"], serde_json::json!({"n": 3}));
+        assert_eq!(listed[1]["path"], "/echo");
+        assert_eq!(sampler.samples().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn bodies_over_the_limit_stream_through_unsampled() {
+        let mut sampler = Sampler::new(100, 8, "secret");
+        sampler.max_body = 8;
+        let sampler = Arc::new(sampler);
+        let app = Router::new()
+            .route("/echo", post(|body: String| async move { body }))
+            .route("/big", post(|| async { "[1,2,3,4,5,6,7,8,9]" }))
+            .layer(axum::middleware::from_fn_with_state(sampler.clone(), sample));
+        let addr = mock::spawn(app);
+
+        let post = |path: &str, body: Body| {
+            Client::new().request(Request::post(format!("http://{}{}", addr, path)).body(body).unwrap())
+        };
+        // of no length known up front, it's found over the limit partway
+        let (mut tx, streamed) = Body::channel();
+        tokio::spawn(async move {
+            for chunk in ["[1,2,3,", "4,5,6]"] {
+                tx.send_data(chunk.into()).await.unwrap();
+            }
+        });
+        let echoed = post("/echo", streamed).await.unwrap();
+        assert_eq!(hyper::body::to_bytes(echoed).await.unwrap(), "[1,2,3,4,5,6]");
+        let big = post("/big", Body::from("[]")).await.unwrap();
+        assert_eq!(hyper::body::to_bytes(big).await.unwrap(), "[1,2,3,4,5,6,7,8,9]");
+        assert!(sampler.samples().is_empty());
+        post("/echo", Body::from("[1]")).await.unwrap();
+        assert_eq!(sampler.samples().len(), 1);
+    }
+}
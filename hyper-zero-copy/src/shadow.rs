@@ -0,0 +1,349 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use axum::body::{boxed, Body, HttpBody};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use bytes::Bytes;
+use hyper::client::connect::Connect;
+use hyper::client::HttpConnector;
+use hyper::http::uri::PathAndQuery;
+use hyper::{Client, Uri};
+use serde::Serialize;
+use tokio::sync::Semaphore;
+use serde_zero_copy::cmp::{Change, Difference};
+use serde_zero_copy::Value;
+use crate::aggregate::Aggregator;
+use crate::auth;
+use crate::forward::{strip_hop_by_hop, RequestId};
+use crate::transform::{Context, Mask, Transform};
+
+/// A request the shadow answered otherwise than the route did.
+#[derive(Debug, Serialize)]
+pub struct Mismatch {
+    pub timestamp_ms: u64,
+    pub path: String,
+    pub request_id: Option<String>,
+    /// The route's and the shadow's.
+    pub status: (u16, u16),
+    pub differences: Vec<Difference>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ShadowMetrics {
+    pub compared: AtomicU64,
+    pub mismatched: AtomicU64,
+    /// Shadow requests that got no response, or one over `max_body`.
+    pub failed: AtomicU64,
+    /// Requests that weren't shadowed for `max_in_flight` others being, or a body of the
+    /// route's that's over `max_body`, of unknown length or compressed.
+    pub skipped: AtomicU64,
+}
+
+/// Sends `percent` of the GET requests a route takes to `upstream` as well, with their query,
+/// and compares what comes back with what the route answered: JSON bodies by what they mean,
+/// so that key order and how numbers are written don't count, others byte for byte. What's
+/// at the `ignore` pointers, e.g. timestamps, isn't compared. The last `capacity` mismatches
+/// are kept for [`admin`]. The client gets the route's response, the shadow's is only
+/// compared, after it has. Other methods aren't sent, they'd have their effects twice.
+/// Neither are the request's credentials and cookies, the shadow isn't the route's upstream.
+/// At most `max_in_flight` shadow requests are out at a time, of `max_body` bytes on either
+/// side, others are skipped, as are those the route serves compressed.
+///
+/// A migrated service is one `upstream`, `/serde` of the proxy itself another, to check the
+/// zero-copy path against `serde_json`'s.
+pub struct Shadow<C = HttpConnector> {
+    client: Arc<Client<C>>,
+    upstream: Uri,
+    percent: u64,
+    pub ignore: Mask,
+    capacity: usize,
+    pub max_body: usize,
+    token: String,
+    seen: AtomicU64,
+    in_flight: Arc<Semaphore>,
+    mismatches: Mutex<VecDeque<Arc<Mismatch>>>,
+    metrics: ShadowMetrics,
+}
+
+impl<C> Shadow<C> {
+    /// `token` is the bearer token [`admin`] asks for. Up to 64 shadow requests of 1MiB
+    /// bodies, see [`Shadow::with_max_in_flight`].
+    pub fn new(client: Arc<Client<C>>, upstream: Uri, percent: u64, capacity: usize, token: impl Into<String>) -> Self {
+        Shadow {
+            client,
+            upstream,
+            percent: percent.min(100),
+            ignore: Mask { pointers: Vec::new() },
+            capacity,
+            max_body: 1 << 20,
+            token: token.into(),
+            seen: AtomicU64::new(0),
+            in_flight: Arc::new(Semaphore::new(64)),
+            mismatches: Mutex::new(VecDeque::with_capacity(capacity)),
+            metrics: ShadowMetrics::default(),
+        }
+    }
+
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.in_flight = Arc::new(Semaphore::new(max_in_flight));
+        self
+    }
+
+    fn skip(&self) {
+        self.metrics.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // evenly, like a `crate::sample::Sampler`
+    fn shadowed(&self) -> bool {
+        let n = self.seen.fetch_add(1, Ordering::Relaxed);
+        (n + 1) * self.percent / 100 > n * self.percent / 100
+    }
+
+    /// The mismatches kept, oldest first.
+    pub fn mismatches(&self) -> Vec<Arc<Mismatch>> {
+        self.mismatches.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn metrics(&self) -> &ShadowMetrics {
+        &self.metrics
+    }
+
+    fn uri(&self, query: Option<&str>) -> Uri {
+        let Some(query) = query else {
+            return self.upstream.clone();
+        };
+        let mut uri = self.upstream.clone().into_parts();
+        let path = uri.path_and_query.as_ref().map_or("/", PathAndQuery::path);
+        uri.path_and_query = format!("{}?{}", path, query).parse().ok();
+        Uri::from_parts(uri).unwrap_or_else(|_| self.upstream.clone())
+    }
+
+    fn parse<'b>(&self, body: &'b [u8]) -> Option<Value<'b>> {
+        let value = serde_json_nostr::from_slice(body).ok()?;
+        Some(self.ignore.apply(value, &Context::default()))
+    }
+
+    fn differences(&self, served: &Bytes, shadowed: &Bytes) -> Vec<Difference> {
+        match (self.parse(served), self.parse(shadowed)) {
+            (Some(served), Some(shadowed)) => served.diff(&shadowed),
+            _ if served == shadowed => Vec::new(),
+            _ => vec![Difference { pointer: String::new(), change: Change::Changed }],
+        }
+    }
+
+    fn compare(&self, path: String, request_id: Option<String>, served: (StatusCode, Bytes), shadowed: (StatusCode, Bytes)) {
+        self.metrics.compared.fetch_add(1, Ordering::Relaxed);
+        let differences = self.differences(&served.1, &shadowed.1);
+        if served.0 == shadowed.0 && differences.is_empty() {
+            return;
+        }
+        self.metrics.mismatched.fetch_add(1, Ordering::Relaxed);
+        if self.capacity == 0 {
+            return;
+        }
+        let mismatch = Mismatch {
+            timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64),
+            path,
+            request_id,
+            status: (served.0.as_u16(), shadowed.0.as_u16()),
+            differences,
+        };
+        let mut mismatches = self.mismatches.lock().unwrap();
+        if mismatches.len() == self.capacity {
+            mismatches.pop_front();
+        }
+        mismatches.push_back(Arc::new(mismatch));
+    }
+}
+
+/// Middleware for `axum::middleware::from_fn_with_state`.
+pub async fn mirror<C>(State(shadow): State<Arc<Shadow<C>>>, request: Request<Body>, next: Next<Body>) -> Response
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+{
+    if request.method() != Method::GET || !shadow.shadowed() {
+        return next.run(request).await;
+    }
+    let Ok(permit) = shadow.in_flight.clone().try_acquire_owned() else {
+        shadow.skip();
+        return next.run(request).await;
+    };
+    let mut mirrored = Request::new(Body::empty());
+    *mirrored.uri_mut() = shadow.uri(request.uri().query());
+    *mirrored.headers_mut() = request.headers().clone();
+    let headers = mirrored.headers_mut();
+    strip_hop_by_hop(headers);
+    // the shadow answers uncompressed, to be compared with what's served so
+    for name in [header::HOST, header::AUTHORIZATION, header::PROXY_AUTHORIZATION, header::COOKIE, header::ACCEPT_ENCODING] {
+        headers.remove(name);
+    }
+    let path = request.uri().path().to_string();
+    let request_id = request.extensions().get::<RequestId>().map(|RequestId(id)| id.clone());
+
+    let response = next.run(request).await;
+    let encoded = response.headers().contains_key(header::CONTENT_ENCODING);
+    if encoded || response.body().size_hint().exact().is_none_or(|len| len > shadow.max_body as u64) {
+        shadow.skip();
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(err) => return (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+    };
+    let served = (parts.status, body.clone());
+    tokio::spawn(async move {
+        let shadowed = match shadow.client.request(mirrored).await {
+            Ok(response) => {
+                let status = response.status();
+                let aggregator = Aggregator::new(usize::MAX, shadow.max_body);
+                aggregator.aggregate(response.into_body(), |_| {}).await.ok().map(|(body, _)| (status, body))
+            }
+            Err(_) => None,
+        };
+        drop(permit);
+        match shadowed {
+            Some(shadowed) => shadow.compare(path, request_id, served, shadowed),
+            None => {
+                shadow.metrics.failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+    Response::from_parts(parts, boxed(Body::from(body)))
+}
+
+async fn report<C>(State(shadow): State<Arc<Shadow<C>>>, headers: HeaderMap) -> Response
+    where
+        C: Send + Sync + 'static,
+{
+    if !auth::bears_token(&headers, &shadow.token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let mismatches = shadow.mismatches();
+    let report = serde_json::json!({
+        "metrics": shadow.metrics(),
+        "mismatches": mismatches.iter().map(|mismatch| &**mismatch).collect::<Vec<&Mismatch>>(),
+    });
+    axum::Json(report).into_response()
+}
+
+/// `GET /admin/shadow`, the metrics and the mismatches kept, for requests bearing the shadow's
+/// token. Merge it after the [`mirror`] layer so that it isn't shadowed itself.
+pub fn admin<C>(shadow: Arc<Shadow<C>>) -> Router
+    where
+        C: Send + Sync + 'static,
+{
+    Router::new().route("/admin/shadow", get(report::<C>)).with_state(shadow)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use axum::extract::RawQuery;
+    use axum::http::HeaderMap;
+    use axum::routing::get;
+    use axum::Router;
+    use hyper::{Client, Uri};
+    use serde_zero_copy::cmp::Change;
+    use crate::mock;
+    use super::{admin, mirror, Shadow};
+
+    #[tokio::test]
+    async fn compares_with_shadow() {
+        // the migrated service writes numbers its own way, renamed a field and dates its answers
+        let migrated = mock::spawn(Router::new().route(
+            "/orders",
+            get(|RawQuery(query): RawQuery| async move {
+                match query.as_deref() {
+                    Some("id=2") => r#"{"at":"tomorrow","total":2.0,"state":"open"}"#,
+                    _ => r#"{"at":"tomorrow","total":1.0,"status":"open"}"#,
+                }
+            }),
+        ));
+        let uri = Uri::try_from(format!("http://{}/orders", migrated)).unwrap();
+        let mut shadow = Shadow::new(Arc::new(Client::new()), uri, 100, 8, "secret");
+        shadow.ignore.pointers = vec!["/at".to_string()];
+        let shadow = Arc::new(shadow);
+        let app = Router::new()
+            .route("/orders", get(|RawQuery(query): RawQuery| async move { format!(r#"{{"status":"open","total":{},"at":"now"}}"#, &query.unwrap()[3..]) }))
+            .layer(axum::middleware::from_fn_with_state(shadow.clone(), mirror))
+            .merge(admin(shadow.clone()));
+        let addr = mock::spawn(app);
+
+        for id in [1, 2] {
+            let served = Client::new().get(Uri::try_from(format!("http://{}/orders?id={}", addr, id)).unwrap()).await.unwrap();
+            let body = hyper::body::to_bytes(served.into_body()).await.unwrap();
+            assert_eq!(body, format!(r#"{{"status":"open","total":{},"at":"now"}}"#, id));
+        }
+        for _ in 0..100 {
+            if shadow.metrics().compared.load(std::sync::atomic::Ordering::Relaxed) == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let mismatches = shadow.mismatches();
+        assert_eq!(mismatches.len(), 1);
+        let differences: Vec<_> = mismatches[0].differences.iter().map(|d| (d.pointer.as_str(), d.change)).collect();
+        assert_eq!(differences, [("/state", Change::Added), ("/status", Change::Removed)]);
+
+        let report = Client::new()
+            .request(hyper::Request::get(format!("http://{}/admin/shadow", addr)).header("authorization", "Bearer secret").body(hyper::Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&hyper::body::to_bytes(report.into_body()).await.unwrap()).unwrap();
+        assert_eq!(report["metrics"]["mismatched"], 1);
+        assert_eq!(report["mismatches"][0]["differences"][0]["change"], "added");
+    }
+
+    #[tokio::test]
+    async fn mirrors_without_credentials_within_bounds() {
+        let credentials = |headers: HeaderMap| async move {
+            let sent: Vec<_> = ["authorization", "cookie", "accept-encoding", "x-secret", "x-kept"].into_iter().filter(|name| headers.contains_key(*name)).collect();
+            format!("{:?}", sent)
+        };
+        let migrated = mock::spawn(Router::new().route("/who", get(credentials)));
+        let uri = Uri::try_from(format!("http://{}/who", migrated)).unwrap();
+        let shadow = Arc::new(Shadow::new(Arc::new(Client::new()), uri.clone(), 100, 8, ""));
+        let full = Arc::new(Shadow::new(Arc::new(Client::new()), uri, 100, 8, "").with_max_in_flight(0));
+        let route = || {
+            Router::new()
+                .route("/who", get(|| async { r#"["x-kept"]"# }))
+                .route("/gzip", get(|| async { ([("content-encoding", "gzip")], "compressed") }))
+        };
+        let addr = mock::spawn(route().layer(axum::middleware::from_fn_with_state(shadow.clone(), mirror)));
+        let full_addr = mock::spawn(route().layer(axum::middleware::from_fn_with_state(full.clone(), mirror)));
+
+        for addr in [addr, full_addr] {
+            let request = hyper::Request::get(format!("http://{}/who", addr))
+                .header("authorization", "Bearer k")
+                .header("cookie", "session=1")
+                .header("accept-encoding", "gzip")
+                .header("connection", "x-secret")
+                .header("x-secret", "1")
+                .header("x-kept", "1")
+                .body(hyper::Body::empty())
+                .unwrap();
+            assert!(Client::new().request(request).await.unwrap().status().is_success());
+        }
+        for _ in 0..100 {
+            if shadow.metrics().compared.load(Ordering::Relaxed) == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(shadow.metrics().compared.load(Ordering::Relaxed), 1);
+        assert!(shadow.mismatches().is_empty(), "{:?}", shadow.mismatches());
+        // not to be compared with the shadow's uncompressed one
+        Client::new().get(Uri::try_from(format!("http://{}/gzip", addr)).unwrap()).await.unwrap();
+        assert_eq!(shadow.metrics().skipped.load(Ordering::Relaxed), 1);
+        assert_eq!((full.metrics().skipped.load(Ordering::Relaxed), full.metrics().compared.load(Ordering::Relaxed)), (1, 0));
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use axum::extract::{Path as UrlPath, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use bytes::Bytes;
+use serde_zero_copy::validate;
+use crate::reload::{self, WatchedDir};
+
+/// JSON schemas by the name of the document they're for, from the `*.json` files of `dir`.
+pub fn read_schemas(dir: impl AsRef<Path>) -> std::io::Result<HashMap<String, serde_json::Value>> {
+    let mut schemas = HashMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+            schemas.insert(name.to_string(), serde_json::from_slice(&std::fs::read(&path)?)?);
+        }
+    }
+    Ok(schemas)
+}
+
+/// Loads the documents of `dir`, together with the schemas for those that have one. A document
+/// that breaks its schema fails the load, and isn't swapped in when it's reloaded.
+pub async fn load(dir: impl Into<PathBuf>, schemas: HashMap<String, serde_json::Value>) -> Result<Arc<WatchedDir>, reload::Error> {
+    if schemas.is_empty() {
+        return WatchedDir::load(dir).await;
+    }
+    let check: reload::Check = Box::new(move |name, value| {
+        let Some(schema) = schemas.get(name) else {
+            return Ok(());
+        };
+        let violations = validate(value.get(), schema, schema);
+        if violations.is_empty() {
+            return Ok(());
+        }
+        Err(violations.iter().map(|v| format!("{} {}", v.pointer, v.message)).collect::<Vec<_>>().join(", "))
+    });
+    WatchedDir::load_checked(dir, Some(check)).await
+}
+
+/// Serves the documents of `dir` under `{prefix}/{name}`, as they were written and parsed at
+/// load. Each response carries an `ETag` of the document's version, `If-None-Match` with it
+/// gets a 304.
+pub fn router(prefix: &str, dir: Arc<WatchedDir>) -> Router {
+    Router::new()
+        .route(&format!("{}/:name", prefix.trim_end_matches('/')), get(document))
+        .with_state(dir)
+}
+
+async fn document(State(dir): State<Arc<WatchedDir>>, UrlPath(name): UrlPath<String>, headers: HeaderMap) -> Response {
+    let Some(document) = dir.document(&name) else {
+        return (StatusCode::NOT_FOUND, format!("no document named {}", name)).into_response();
+    };
+    let etag = format!("\"{:016x}\"", document.hash);
+    let fresh = headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+    let etag = HeaderValue::from_str(&etag).expect("hex in quotes is a valid header value");
+    if fresh {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+    let body = Bytes::clone(document.value.backing_cart());
+    let content_type = HeaderValue::from_static(mime::APPLICATION_JSON.as_ref());
+    ([(header::CONTENT_TYPE, content_type), (header::ETAG, etag)], body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use axum::http::header;
+    use hyper::{Body, Client, Request, StatusCode};
+    use crate::reload::Reason;
+    use crate::mock;
+    use super::{load, router};
+
+    #[tokio::test]
+    async fn serves_with_etags() {
+        let dir = std::env::temp_dir().join(format!("static-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("config.json"), r#"{"flags": {"beta": true}}"#).unwrap();
+        let schema = serde_json::json!({"type": "object", "required": ["flags"]});
+        let app = router("/static/", load(&dir, HashMap::from([("config".to_string(), schema.clone())])).await.unwrap());
+        let addr = mock::spawn(app);
+
+        let get = |etag: Option<&str>| {
+            let mut request = Request::get(format!("http://{}/static/config", addr));
+            if let Some(etag) = etag {
+                request = request.header(header::IF_NONE_MATCH, etag);
+            }
+            Client::new().request(request.body(Body::empty()).unwrap())
+        };
+        let response = get(None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, r#"{"flags": {"beta": true}}"#);
+        assert_eq!(get(Some(&etag)).await.unwrap().status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(get(Some("\"other\"")).await.unwrap().status(), StatusCode::OK);
+
+        std::fs::write(dir.join("config.json"), r#"{"version": 2}"#).unwrap();
+        let err = load(&dir, HashMap::from([("config".to_string(), schema)])).await.err().unwrap();
+        assert!(matches!(err.reason, Reason::Rejected(why) if why.contains("flags")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use serde::Deserialize;
+use crate::auth::Identity;
+use crate::locale::Localize;
+use crate::reload::{self, WatchedDir};
+use crate::rewrite::{Replace, Replacement, RewriteUrls};
+use crate::transform::{ClaimsField, Mask, Nulls, Paginate, RequestIdField, Transforms};
+use crate::version::Adapter;
+
+/// A transform of a [`Pipeline`], e.g. `{"paginate": {"per_page": 10}}` or
+/// `{"mask": ["/email"]}`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Step {
+    Paginate {
+        per_page: Option<usize>,
+        max_per_page: Option<usize>,
+    },
+    Nulls {
+        #[serde(default)]
+        schema: Option<serde_json::Value>,
+    },
+    Mask(Vec<String>),
+    RequestId(String),
+    Claims {
+        key: String,
+        claims: Vec<String>,
+    },
+    Adapt(Adapter),
+    Replace(Vec<Replacement>),
+    RewriteUrls(RewriteUrls),
+    Localize(Localize),
+}
+
+/// What a tenant is served, `{"transforms": [...]}` of [`Step`]s applied in order.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+    pub transforms: Vec<Step>,
+}
+
+impl Pipeline {
+    pub fn build(&self) -> Transforms {
+        self.transforms.iter().cloned().fold(Transforms::default(), |transforms, step| match step {
+            Step::Paginate { per_page, max_per_page } => {
+                let default = Paginate::default();
+                transforms.with(Paginate {
+                    per_page: per_page.unwrap_or(default.per_page),
+                    max_per_page: max_per_page.unwrap_or(default.max_per_page),
+                })
+            }
+            Step::Nulls { schema } => transforms.with(Nulls { schema }),
+            Step::Mask(pointers) => transforms.with(Mask { pointers }),
+            Step::RequestId(key) => transforms.with(RequestIdField { key }),
+            Step::Claims { key, claims } => transforms.with(ClaimsField { key, claims }),
+            Step::Adapt(adapter) => transforms.with(adapter),
+            Step::Replace(replacements) => transforms.with(Replace { replacements }),
+            Step::RewriteUrls(rewrite) => transforms.with(rewrite),
+            Step::Localize(localize) => transforms.with(localize),
+        })
+    }
+}
+
+/// Pipelines by who a request is from, from a directory of them: `{tenant}.json` for the
+/// tenant named by the identity's `claim`, `scope.{scope}.json` for each of its `scope`s in
+/// turn and `default.json` for everyone else. Requests they don't cover are served the
+/// route's own [`Transforms`]. Added as an `Extension` layer in an `Arc`; a pipeline is built
+/// once per version of its file, and [`Tenants::dir`] can be watched to pick up edits.
+pub struct Tenants {
+    dir: Arc<WatchedDir>,
+    pub claim: String,
+    // by name, with the hash of the version they're built from
+    built: RwLock<HashMap<String, (u64, Transforms)>>,
+}
+
+impl Tenants {
+    /// Fails, and later reloads are turned down, for a file that isn't a [`Pipeline`].
+    pub async fn load(dir: impl Into<PathBuf>, claim: &str) -> Result<Self, reload::Error> {
+        let check: reload::Check = Box::new(|_, value| {
+            serde_json::from_slice::<Pipeline>(value.backing_cart()).map(|_| ()).map_err(|err| err.to_string())
+        });
+        let dir = WatchedDir::load_checked(dir, Some(check)).await?;
+        Ok(Tenants { dir, claim: claim.to_string(), built: RwLock::default() })
+    }
+
+    pub fn dir(&self) -> &Arc<WatchedDir> {
+        &self.dir
+    }
+
+    /// The pipeline for `identity`, `None` when no file covers it.
+    pub fn pipeline(&self, identity: &Identity) -> Option<Transforms> {
+        self.named_pipeline(identity).map(|(_, transforms)| transforms)
+    }
+
+    /// Like [`Tenants::pipeline`], along with the file's name and version, e.g.
+    /// `scope.partner@5ac1d2e4f0b3a697`.
+    pub fn named_pipeline(&self, identity: &Identity) -> Option<(String, Transforms)> {
+        let tenant = identity.claim(&self.claim).and_then(|claim| claim.as_str().map(str::to_string));
+        let scopes = identity.claim("scope").and_then(|scope| scope.as_str().map(str::to_string)).unwrap_or_default();
+        tenant
+            .into_iter()
+            .chain(scopes.split_whitespace().map(|scope| format!("scope.{}", scope)))
+            .chain(["default".to_string()])
+            .find_map(|name| Some((name.clone(), self.built(&name)?)))
+            .map(|(name, (hash, transforms))| (format!("{}@{:016x}", name, hash), transforms))
+    }
+
+    fn built(&self, name: &str) -> Option<(u64, Transforms)> {
+        let document = self.dir.document(name)?;
+        if let Some((hash, transforms)) = self.built.read().unwrap().get(name) {
+            if *hash == document.hash {
+                return Some((*hash, transforms.clone()));
+            }
+        }
+        // checked when it was loaded
+        let pipeline: Pipeline = serde_json::from_slice(document.value.backing_cart()).ok()?;
+        let transforms = pipeline.build();
+        self.built.write().unwrap().insert(name.to_string(), (document.hash, transforms.clone()));
+        Some((document.hash, transforms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_zero_copy::Value;
+    use crate::auth::Identity;
+    use crate::transform::Context;
+    use super::Tenants;
+
+    fn identity(claims: serde_json::Value) -> Identity {
+        Identity { subject: "someone".to_string(), claims: claims.as_object().cloned().unwrap() }
+    }
+
+    fn served(tenants: &Tenants, identity: &Identity) -> Option<String> {
+        let value: Value = serde_json_nostr::from_slice(br#"{"id":1,"email":"j@example.com","cost":3}"#).unwrap();
+        let transforms = tenants.pipeline(identity)?;
+        Some(serde_json_nostr::to_string(&transforms.apply(value, &Context::default())).unwrap())
+    }
+
+    #[tokio::test]
+    async fn pipelines_by_tenant_and_scope() {
+        let dir = std::env::temp_dir().join(format!("tenants-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("acme.json"), r#"{"transforms": [{"mask": ["/cost"]}]}"#).unwrap();
+        std::fs::write(dir.join("scope.partner.json"), r#"{"transforms": [{"mask": ["/email", "/cost"]}]}"#).unwrap();
+        let tenants = Tenants::load(&dir, "tenant").await.unwrap();
+
+        let acme = identity(serde_json::json!({"tenant": "acme", "scope": "partner"}));
+        assert_eq!(served(&tenants, &acme).unwrap(), r#"{"email":"j@example.com","id":1}"#);
+        let partner = identity(serde_json::json!({"tenant": "globex", "scope": "read partner"}));
+        assert_eq!(served(&tenants, &partner).unwrap(), r#"{"id":1}"#);
+        assert_eq!(served(&tenants, &identity(serde_json::json!({}))), None);
+
+        std::fs::write(dir.join("acme.json"), r#"{"transforms": [{"mask": ["/id"]}]}"#).unwrap();
+        std::fs::write(dir.join("default.json"), r#"{"transforms": [{"mask": ["/email"]}]}"#).unwrap();
+        assert!(tenants.dir().reload().await.is_empty());
+        assert_eq!(served(&tenants, &acme).unwrap(), r#"{"cost":3,"email":"j@example.com"}"#);
+        assert_eq!(served(&tenants, &identity(serde_json::json!({}))).unwrap(), r#"{"cost":3,"id":1}"#);
+
+        std::fs::write(dir.join("acme.json"), r#"{"transforms": [{"redact": []}]}"#).unwrap();
+        assert_eq!(tenants.dir().reload().await.len(), 1);
+        assert_eq!(served(&tenants, &acme).unwrap(), r#"{"cost":3,"email":"j@example.com"}"#);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,443 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use bytes::Bytes;
+use serde::Deserialize;
+use serde_zero_copy::{NullPolicy, Template, Value, ValueBuilder};
+use yoke::Yoke;
+use crate::auth::Identity;
+use crate::cache::YokedValue;
+
+/// What a transform knows of the request it runs for.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Context<'r> {
+    pub query: Option<&'r str>,
+    /// Set behind [`crate::forward::headers`].
+    pub request_id: Option<&'r str>,
+    /// Set behind [`crate::auth::check`].
+    pub identity: Option<&'r Identity>,
+    /// See [`crate::forward::origin`].
+    pub origin: Option<&'r str>,
+    /// The request's `Accept-Language`.
+    pub accept_language: Option<&'r str>,
+}
+
+impl<'r> Context<'r> {
+    pub fn query(query: Option<&'r str>) -> Self {
+        Context { query, ..Context::default() }
+    }
+}
+
+/// A rewrite of the value a route serves, given the request it's for. Transforms move and
+/// drop parts of the borrowed tree, they don't need to copy what they keep.
+pub trait Transform: Send + Sync + 'static {
+    fn apply<'a>(&self, value: Value<'a>, cx: &Context) -> Value<'a>;
+
+    /// For transforms that can fail on a value, where `apply` passes it through as it is.
+    fn try_apply<'a>(&self, value: Value<'a>, cx: &Context) -> Result<Value<'a>, String> {
+        Ok(self.apply(value, cx))
+    }
+
+    /// The request headers what it serves depends on, for `Vary`.
+    fn vary(&self) -> &'static [&'static str] {
+        &[]
+    }
+}
+
+/// Transforms applied in order, added as an `Extension` layer. They run after capture and
+/// publishing, which see the upstream value, and their output isn't cached.
+#[derive(Clone, Default)]
+pub struct Transforms(pub Vec<Arc<dyn Transform>>);
+
+impl Transforms {
+    pub fn with(mut self, transform: impl Transform) -> Self {
+        self.0.push(Arc::new(transform));
+        self
+    }
+
+    /// These, then those of `then`.
+    pub fn then(mut self, then: &Transforms) -> Self {
+        self.0.extend(then.0.iter().cloned());
+        self
+    }
+
+    pub fn apply<'a>(&self, value: Value<'a>, cx: &Context) -> Value<'a> {
+        self.0.iter().fold(value, |value, transform| transform.apply(value, cx))
+    }
+
+    /// What these vary by, each header once.
+    pub fn vary(&self) -> Vec<&'static str> {
+        let mut vary: Vec<&'static str> = Vec::new();
+        for name in self.0.iter().flat_map(|transform| transform.vary()) {
+            if !vary.contains(name) {
+                vary.push(name);
+            }
+        }
+        vary
+    }
+
+    /// Like [`Transforms::apply`] but the error of the first of them to fail, if one does.
+    pub fn try_apply<'a>(&self, value: Value<'a>, cx: &Context) -> Result<Value<'a>, String> {
+        self.0.iter().try_fold(value, |value, transform| transform.try_apply(value, cx))
+    }
+
+    /// Like [`Transforms::apply`] but `None` if `stop` says so before one of the transforms.
+    pub fn apply_until<'a, F>(&self, value: Value<'a>, cx: &Context, stop: F) -> Option<Value<'a>>
+        where
+            F: Fn() -> bool,
+    {
+        self.0.iter().try_fold(value, |value, transform| (!stop()).then(|| transform.apply(value, cx)))
+    }
+
+    /// Applies these to each of `values` in place, on rayon's pool with the `rayon` feature.
+    /// A value one of them fails on is left null and its error collected, the others are
+    /// transformed all the same.
+    pub fn apply_batch(&self, values: &mut [YokedValue], cx: &Context) -> Vec<BatchError> {
+        let apply = |(index, slot): (usize, &mut YokedValue)| {
+            self.apply_yoked(slot, cx).err().map(|message| BatchError { index, message })
+        };
+        #[cfg(feature = "rayon")]
+        let errors = {
+            use rayon::prelude::*;
+            values.par_iter_mut().enumerate().filter_map(apply).collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let errors = values.iter_mut().enumerate().filter_map(apply).collect();
+        errors
+    }
+
+    fn apply_yoked(&self, slot: &mut YokedValue, cx: &Context) -> Result<(), String> {
+        let yoked = std::mem::replace(slot, Yoke::attach_to_cart(Arc::new(Bytes::new()), |_| Value::Null));
+        *slot = yoked.try_map_project(|value, _| self.try_apply(value, cx))?;
+        Ok(())
+    }
+}
+
+/// A value of [`Transforms::apply_batch`] that couldn't be transformed, by its index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchError {
+    pub index: usize,
+    pub message: String,
+}
+
+/// Slices an array by the `page` (from 1) and `per_page` query parameters and wraps it as
+/// `{"data": [...], "total": n, "page": p, "next": p + 1 or null}`. Missing or invalid
+/// parameters fall back to the first page of `per_page` elements, `per_page` is capped at
+/// `max_per_page`. Anything but an array passes through.
+#[derive(Debug, Clone)]
+pub struct Paginate {
+    pub per_page: usize,
+    pub max_per_page: usize,
+}
+
+impl Default for Paginate {
+    fn default() -> Self {
+        Paginate { per_page: 20, max_per_page: 100 }
+    }
+}
+
+pub(crate) fn query_value<'q>(query: Option<&'q str>, name: &str) -> Option<&'q str> {
+    query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn param(query: Option<&str>, name: &str) -> Option<usize> {
+    query_value(query, name).and_then(|value| value.parse().ok())
+}
+
+impl Transform for Paginate {
+    fn apply<'a>(&self, value: Value<'a>, cx: &Context) -> Value<'a> {
+        let mut vec = match value {
+            Value::Array(vec) => vec,
+            other => return other,
+        };
+        let page = param(cx.query, "page").filter(|&p| p > 0).unwrap_or(1);
+        let per_page = param(cx.query, "per_page").filter(|&n| n > 0).unwrap_or(self.per_page).min(self.max_per_page);
+        let total = vec.len();
+        let start = (page - 1).saturating_mul(per_page).min(total);
+        let end = start.saturating_add(per_page).min(total);
+        vec.truncate(end);
+        let data: Vec<Value<'a>> = vec.drain(start..).collect();
+        let next = if end < total { Value::Number((page + 1).into()) } else { Value::Null };
+        ValueBuilder::new()
+            .object()
+            .key("data")
+            .value(Value::Array(data))
+            .key("total")
+            .num(total)
+            .key("page")
+            .num(page)
+            .key("next")
+            .value(next)
+            .end()
+            .finish()
+    }
+}
+
+/// Applies a [`NullPolicy`] picked by the `nulls` query parameter, a comma separated list of
+/// `strip`, `defaults` and `collapse`, e.g. `?nulls=defaults,strip` for clients that take no
+/// nulls at all. Defaults come from `schema`, without a parameter the value passes through.
+#[derive(Debug, Clone, Default)]
+pub struct Nulls {
+    pub schema: Option<serde_json::Value>,
+}
+
+impl Transform for Nulls {
+    fn apply<'a>(&self, value: Value<'a>, cx: &Context) -> Value<'a> {
+        let Some(policies) = query_value(cx.query, "nulls") else {
+            return value;
+        };
+        let mut policy = NullPolicy::default();
+        for name in policies.split(',') {
+            match name {
+                "strip" => policy.strip_nulls = true,
+                "defaults" => policy.defaults = self.schema.as_ref(),
+                "collapse" => policy.collapse_empty = true,
+                _ => {}
+            }
+        }
+        value.apply_nulls(policy)
+    }
+}
+
+/// Serves `template` filled in from the value, see [`Template`].
+#[derive(Debug, Clone)]
+pub struct Compose {
+    pub template: Template<'static>,
+}
+
+impl Transform for Compose {
+    fn apply<'a>(&self, value: Value<'a>, _: &Context) -> Value<'a> {
+        self.template.render(&value)
+    }
+}
+
+/// Drops what's at each of `pointers`, where a `*` segment stands for every element or member,
+/// e.g. `/orders/*/card` for clients that mustn't see cards, and one ending in `*` for the
+/// members whose keys start with what's before it, e.g. `/meta/ext_*`. Pointers to nothing
+/// are skipped.
+#[derive(Debug, Clone)]
+pub struct Mask {
+    pub pointers: Vec<String>,
+}
+
+fn unescape(segment: &str) -> Cow<'_, str> {
+    if segment.contains('~') {
+        Cow::Owned(segment.replace("~1", "/").replace("~0", "~"))
+    } else {
+        Cow::Borrowed(segment)
+    }
+}
+
+fn remove(value: &mut Value, segments: &[&str]) {
+    let Some((&segment, rest)) = segments.split_first() else {
+        return;
+    };
+    if let Some(prefix) = segment.strip_suffix('*').filter(|prefix| !prefix.is_empty()) {
+        let prefix = unescape(prefix);
+        if rest.is_empty() {
+            value.remove_with_prefix(&prefix);
+        } else {
+            value.keys_with_prefix_mut(&prefix).for_each(|(_, member)| remove(member, rest));
+        }
+        return;
+    }
+    match value {
+        Value::Object(map) if segment == "*" && rest.is_empty() => map.clear(),
+        Value::Object(map) if segment == "*" => map.values_mut().for_each(|member| remove(member, rest)),
+        Value::Object(map) if rest.is_empty() => {
+            map.remove(unescape(segment).as_ref());
+        }
+        Value::Object(map) => {
+            if let Some(member) = map.get_mut(unescape(segment).as_ref()) {
+                remove(member, rest);
+            }
+        }
+        Value::Array(vec) if segment == "*" && rest.is_empty() => vec.clear(),
+        Value::Array(vec) if segment == "*" => vec.iter_mut().for_each(|element| remove(element, rest)),
+        Value::Array(vec) => match segment.parse::<usize>() {
+            Ok(i) if i < vec.len() && rest.is_empty() => {
+                vec.remove(i);
+            }
+            Ok(i) if i < vec.len() => remove(&mut vec[i], rest),
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+impl Transform for Mask {
+    fn apply<'a>(&self, mut value: Value<'a>, _: &Context) -> Value<'a> {
+        for pointer in &self.pointers {
+            if let Some(pointer) = pointer.strip_prefix('/') {
+                remove(&mut value, &pointer.split('/').collect::<Vec<_>>());
+            }
+        }
+        value
+    }
+}
+
+/// Adds the id of the request under `key` of an object, so that a client can quote it when
+/// something's wrong with what it got. Anything but an object, or without an id, passes through.
+#[derive(Debug, Clone)]
+pub struct RequestIdField {
+    pub key: String,
+}
+
+impl Transform for RequestIdField {
+    fn apply<'a>(&self, value: Value<'a>, cx: &Context) -> Value<'a> {
+        match (value, cx.request_id) {
+            (Value::Object(mut map), Some(id)) => {
+                map.insert(Cow::Owned(self.key.clone()), Value::String(id.to_string()));
+                Value::Object(map)
+            }
+            (value, _) => value,
+        }
+    }
+}
+
+/// Adds the `claims` of who the request is from as an object under `key` of an object, claims
+/// they lack are left out. Anything but an object, or without an identity, passes through.
+#[derive(Debug, Clone)]
+pub struct ClaimsField {
+    pub key: String,
+    pub claims: Vec<String>,
+}
+
+impl Transform for ClaimsField {
+    fn apply<'a>(&self, value: Value<'a>, cx: &Context) -> Value<'a> {
+        let (mut map, identity) = match (value, cx.identity) {
+            (Value::Object(map), Some(identity)) => (map, identity),
+            (value, _) => return value,
+        };
+        let claims: BTreeMap<_, _> = self
+            .claims
+            .iter()
+            .filter_map(|name| {
+                let claim = Value::deserialize(identity.claim(name)?).ok()?;
+                Some((Cow::Owned(name.clone()), claim))
+            })
+            .collect();
+        map.insert(Cow::Owned(self.key.clone()), Value::Object(claims));
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use axum::Extension;
+    use hyper::{Client, Uri};
+    use serde_zero_copy::Value;
+    use crate::mock::{self, Fixtures};
+    use crate::proxy;
+    use serde_zero_copy::Template;
+    use crate::auth::Identity;
+    use super::{BatchError, ClaimsField, Compose, Context, Mask, Nulls, Paginate, RequestIdField, Transform, Transforms};
+
+    #[test]
+    fn paginate_slices_and_wraps() {
+        let input = br#"[{"id":1},{"id":2},{"id":3},{"id":4},{"id":5}]"#;
+        let paginate = Paginate { per_page: 2, max_per_page: 3 };
+        let page = |query: Option<&str>| {
+            let value: Value = serde_json_nostr::from_slice(input).unwrap();
+            serde_json_nostr::to_string(&paginate.apply(value, &Context::query(query))).unwrap()
+        };
+        assert_eq!(page(None), r#"{"data":[{"id":1},{"id":2}],"next":2,"page":1,"total":5}"#);
+        assert_eq!(page(Some("page=2&per_page=3")), r#"{"data":[{"id":4},{"id":5}],"next":null,"page":2,"total":5}"#);
+        assert_eq!(page(Some("per_page=50&page=0")), r#"{"data":[{"id":1},{"id":2},{"id":3}],"next":2,"page":1,"total":5}"#);
+        assert_eq!(page(Some("page=9")), r#"{"data":[],"next":null,"page":9,"total":5}"#);
+        assert_eq!(paginate.apply(Value::Bool(true), &Context::default()), Value::Bool(true));
+    }
+
+    #[test]
+    fn batches_collect_errors_per_item() {
+        struct Strict;
+        impl Transform for Strict {
+            fn apply<'a>(&self, value: Value<'a>, _: &Context) -> Value<'a> {
+                value
+            }
+
+            fn try_apply<'a>(&self, value: Value<'a>, _: &Context) -> Result<Value<'a>, String> {
+                value.pointer("/id").is_some().then_some(value).ok_or_else(|| "no id".to_string())
+            }
+        }
+        let transforms = Transforms::default().with(Mask { pointers: vec!["/secret".to_string()] }).with(Strict);
+        let mut values: Vec<_> = [&br#"{"id":1,"secret":"a"}"#[..], br#"{"secret":"b"}"#, br#"{"id":3}"#]
+            .into_iter()
+            .map(|json| crate::cache::yoke(bytes::Bytes::from_static(json)).unwrap())
+            .collect();
+        let errors = transforms.apply_batch(&mut values, &Context::default());
+        assert_eq!(errors, [BatchError { index: 1, message: "no id".to_string() }]);
+        let served: Vec<_> = values.iter().map(|value| serde_json_nostr::to_string(value.get()).unwrap()).collect();
+        assert_eq!(served, [r#"{"id":1}"#, "null", r#"{"id":3}"#]);
+    }
+
+    #[test]
+    fn nulls_by_query() {
+        let input = br#"{"name":null,"tags":[],"id":1}"#;
+        let nulls = Nulls { schema: Some(serde_json::json!({"properties": {"name": {"type": "string"}}})) };
+        let apply = |query: Option<&str>| {
+            let value: Value = serde_json_nostr::from_slice(input).unwrap();
+            serde_json_nostr::to_string(&nulls.apply(value, &Context::query(query))).unwrap()
+        };
+        assert_eq!(apply(None), r#"{"id":1,"name":null,"tags":[]}"#);
+        assert_eq!(apply(Some("nulls=strip,collapse")), r#"{"id":1}"#);
+        assert_eq!(apply(Some("page=1&nulls=defaults")), r#"{"id":1,"name":"","tags":[]}"#);
+    }
+
+    #[test]
+    fn compose_fills_template() {
+        let compose = Compose { template: Template::parse(r#"{"greeting":"Hello ${/name}!","id":"${/id}"}"#).unwrap() };
+        let value: Value = serde_json_nostr::from_slice(br#"{"name":"Jane","id":7}"#).unwrap();
+        assert_eq!(serde_json_nostr::to_string(&compose.apply(value, &Context::default())).unwrap(), r#"{"greeting":"Hello Jane!","id":7}"#);
+    }
+
+    #[test]
+    fn mask_drops_by_pointer() {
+        let input = br#"{"name":"Jane","a/b":1,"orders":[{"id":1,"card":"4111"},{"id":2,"card":"5500"}],"tags":["x","y"],"ext_a":1,"ext_b":{"x":1,"y":2},"extra":3}"#;
+        let mask = Mask { pointers: ["/orders/*/card", "/a~1b", "/tags/0", "/missing/x", "/tags/9", "/ext_b*/x", "/ext_*"].map(str::to_string).to_vec() };
+        let value: Value = serde_json_nostr::from_slice(input).unwrap();
+        let masked = serde_json_nostr::to_string(&mask.apply(value, &Context::default())).unwrap();
+        assert_eq!(masked, r#"{"extra":3,"name":"Jane","orders":[{"id":1},{"id":2}],"tags":["y"]}"#);
+        let mask = Mask { pointers: vec!["/ext_b*/x".to_string()] };
+        let masked = mask.apply(serde_json_nostr::from_slice(input).unwrap(), &Context::default());
+        assert_eq!(serde_json_nostr::to_string(&masked.pointer("/ext_b")).unwrap(), r#"{"y":2}"#);
+    }
+
+    #[test]
+    fn request_id_into_objects() {
+        let field = RequestIdField { key: "request_id".to_string() };
+        let cx = Context { request_id: Some("abc"), ..Context::default() };
+        let value: Value = serde_json_nostr::from_slice(br#"{"id":7}"#).unwrap();
+        assert_eq!(serde_json_nostr::to_string(&field.apply(value, &cx)).unwrap(), r#"{"id":7,"request_id":"abc"}"#);
+        assert_eq!(field.apply(Value::Array(vec![]), &cx), Value::Array(vec![]));
+        assert_eq!(field.apply(Value::Object(Default::default()), &Context::default()), Value::Object(Default::default()));
+    }
+
+    #[test]
+    fn claims_into_objects() {
+        let field = ClaimsField { key: "user".to_string(), claims: vec!["sub".to_string(), "tier".to_string(), "org".to_string()] };
+        let claims = serde_json::json!({"tier": 2, "scope": "read"}).as_object().cloned().unwrap();
+        let identity = Identity { subject: "jane".to_string(), claims };
+        let cx = Context { identity: Some(&identity), ..Context::default() };
+        let value: Value = serde_json_nostr::from_slice(br#"{"id":7}"#).unwrap();
+        assert_eq!(serde_json_nostr::to_string(&field.apply(value, &cx)).unwrap(), r#"{"id":7,"user":{"sub":"jane","tier":2}}"#);
+    }
+
+    #[tokio::test]
+    async fn paginate_proxied_list() {
+        let upstream_addr = mock::spawn(mock::router(Fixtures::default().with("hello", "[1,2,3]")));
+        let uri = Uri::try_from(format!("http://{}/hello", upstream_addr)).unwrap();
+        let app = proxy::router(Arc::new(Client::new()), uri)
+            .layer(Extension(Transforms::default().with(Paginate { per_page: 2, max_per_page: 2 })));
+        let addr = mock::spawn(app);
+
+        let res = Client::new().get(Uri::try_from(format!("http://{}/zc?page=2", addr)).unwrap()).await.unwrap();
+        let body = hyper::body::to_bytes(res).await.unwrap();
+        assert_eq!(body.as_ref(), br#"{"data":[3],"next":null,"page":2,"total":3}"#);
+    }
+}
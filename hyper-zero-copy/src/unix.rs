@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use axum::Router;
+use hyper::client::connect::{Connected, Connection};
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper::Uri;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpStream, UnixListener, UnixStream};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Connects to the hosts given a socket path over it, and to every other over TCP, e.g. with
+/// `Connector::default().with_socket("billing", "/run/billing.sock")` a request for
+/// `http://billing/invoices` goes to the socket.
+#[derive(Clone)]
+pub struct Connector {
+    http: HttpConnector,
+    sockets: Arc<HashMap<String, PathBuf>>,
+}
+
+impl Default for Connector {
+    fn default() -> Self {
+        Connector { http: HttpConnector::new(), sockets: Arc::default() }
+    }
+}
+
+impl Connector {
+    pub fn with_socket(mut self, host: &str, path: impl Into<PathBuf>) -> Self {
+        Arc::make_mut(&mut self.sockets).insert(host.to_string(), path.into());
+        self
+    }
+
+    /// Comma separated `host=path`.
+    pub fn parse(sockets: &str) -> Self {
+        sockets
+            .split(',')
+            .filter_map(|pair| pair.split_once('='))
+            .fold(Connector::default(), |connector, (host, path)| connector.with_socket(host.trim(), path.trim()))
+    }
+}
+
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Service<Uri> for Connector {
+    type Response = Stream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Stream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        match uri.host().and_then(|host| self.sockets.get(host)).cloned() {
+            Some(path) => Box::pin(async move { Ok(Stream::Unix(UnixStream::connect(path).await?)) }),
+            None => {
+                let connecting = self.http.call(uri);
+                Box::pin(async move { Ok(Stream::Tcp(connecting.await?)) })
+            }
+        }
+    }
+}
+
+impl Connection for Stream {
+    fn connected(&self) -> Connected {
+        match self {
+            Stream::Tcp(stream) => stream.connected(),
+            Stream::Unix(_) => Connected::new(),
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            Stream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Binds `path`, replacing a socket left there by an earlier run.
+pub fn bind(path: impl AsRef<Path>) -> io::Result<UnixListener> {
+    match std::fs::remove_file(&path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    UnixListener::bind(path)
+}
+
+/// Like `axum::Server::from_tcp`, for a listener on a socket path.
+pub async fn serve(listener: UnixListener, app: Router) -> hyper::Result<()> {
+    serve_until(listener, app, std::future::pending()).await
+}
+
+/// [`serve`] until `signal`, then until the connections it has are done.
+pub async fn serve_until(listener: UnixListener, app: Router, signal: impl Future<Output = ()>) -> hyper::Result<()> {
+    let accept = hyper::server::accept::poll_fn(move |cx| match listener.poll_accept(cx) {
+        Poll::Ready(accepted) => Poll::Ready(Some(accepted.map(|(stream, _)| stream))),
+        Poll::Pending => Poll::Pending,
+    });
+    axum::Server::builder(accept).serve(app.into_make_service()).with_graceful_shutdown(signal).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use hyper::{Client, Uri};
+    use crate::mock::{self, Fixtures};
+    use super::{bind, serve, Connector};
+
+    #[tokio::test]
+    async fn proxies_between_sockets() {
+        let dir = std::env::temp_dir().join(format!("unix-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (upstream, proxy) = (dir.join("upstream.sock"), dir.join("proxy.sock"));
+        tokio::spawn(serve(bind(&upstream).unwrap(), mock::router(Fixtures::default().with("hello", "[1,2]"))));
+
+        let connector = Connector::parse(&format!("upstream={},proxy={}", upstream.display(), proxy.display()));
+        let client = Arc::new(Client::builder().build::<_, hyper::Body>(connector.clone()));
+        let app = crate::proxy::router(client, Uri::from_static("http://upstream/hello"));
+        tokio::spawn(serve(bind(&proxy).unwrap(), app));
+
+        let client = Client::builder().build::<_, hyper::Body>(connector);
+        let response = client.get(Uri::from_static("http://proxy/zc")).await.unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(hyper::body::to_bytes(response).await.unwrap(), "[1,2]");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,333 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use serde_json::Number;
+use serde_zero_copy::Value;
+use crate::transform::{Context, Transform, Transforms};
+
+pub const API_VERSION: &str = "api-version";
+
+/// What a field can be converted between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    String,
+    Number,
+    Boolean,
+}
+
+/// A change of an [`Adapter`], e.g. `{"rename": {"pointer": "/items/*/sku", "to": "code"}}`,
+/// `{"move": {"from": "/customer/email", "to": "/email"}}` or
+/// `{"convert": {"pointer": "/total", "from": "number", "to": "string"}}`. Pointers of renames
+/// and conversions may have `*` segments, those of moves may not.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Change {
+    Rename {
+        pointer: String,
+        to: String,
+    },
+    Move {
+        from: String,
+        to: String,
+    },
+    Convert {
+        pointer: String,
+        from: Kind,
+        to: Kind,
+    },
+}
+
+impl Change {
+    fn inverse(&self) -> Change {
+        match self {
+            Change::Rename { pointer, to } => {
+                let (parent, name) = pointer.rsplit_once('/').unwrap_or_default();
+                Change::Rename { pointer: format!("{}/{}", parent, escape(to)), to: unescape(name).into_owned() }
+            }
+            Change::Move { from, to } => Change::Move { from: to.clone(), to: from.clone() },
+            Change::Convert { pointer, from, to } => Change::Convert { pointer: pointer.clone(), from: *to, to: *from },
+        }
+    }
+
+    fn apply(&self, value: &mut Value) {
+        match self {
+            Change::Rename { pointer, to } => {
+                let Some((parent, name)) = pointer.rsplit_once('/') else {
+                    return;
+                };
+                let name = unescape(name);
+                visit(value, &segments(parent), &mut |parent| {
+                    if let Value::Object(map) = parent {
+                        if let Some(member) = map.remove(name.as_ref()) {
+                            map.insert(Cow::Owned(to.clone()), member);
+                        }
+                    }
+                });
+            }
+            Change::Move { from, to } => {
+                if let Some(moved) = take(value, &segments(from)) {
+                    put(value, &segments(to), moved);
+                }
+            }
+            Change::Convert { pointer, from, to } => {
+                visit(value, &segments(pointer), &mut |target| convert(target, *from, *to));
+            }
+        }
+    }
+}
+
+fn escape(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
+fn unescape(segment: &str) -> Cow<'_, str> {
+    if segment.contains('~') {
+        Cow::Owned(segment.replace("~1", "/").replace("~0", "~"))
+    } else {
+        Cow::Borrowed(segment)
+    }
+}
+
+pub(crate) fn segments(pointer: &str) -> Vec<&str> {
+    pointer.strip_prefix('/').map_or_else(Vec::new, |pointer| pointer.split('/').collect())
+}
+
+// each value `segments` points to, through every element or member for a `*`
+pub(crate) fn visit(value: &mut Value, segments: &[&str], f: &mut dyn FnMut(&mut Value)) {
+    let Some((&segment, rest)) = segments.split_first() else {
+        return f(value);
+    };
+    match value {
+        Value::Object(map) if segment == "*" => map.values_mut().for_each(|member| visit(member, rest, f)),
+        Value::Object(map) => {
+            if let Some(member) = map.get_mut(unescape(segment).as_ref()) {
+                visit(member, rest, f);
+            }
+        }
+        Value::Array(vec) if segment == "*" => vec.iter_mut().for_each(|element| visit(element, rest, f)),
+        Value::Array(vec) => {
+            if let Some(element) = segment.parse().ok().and_then(|i: usize| vec.get_mut(i)) {
+                visit(element, rest, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+// removed rather than left null, the way an older payload wouldn't have it
+fn take<'a>(value: &mut Value<'a>, segments: &[&str]) -> Option<Value<'a>> {
+    let (&last, parents) = segments.split_last()?;
+    let parent = parents.iter().try_fold(value, |value, &segment| match value {
+        Value::Object(map) => map.get_mut(unescape(segment).as_ref()),
+        Value::Array(vec) => vec.get_mut(segment.parse::<usize>().ok()?),
+        _ => None,
+    })?;
+    match parent {
+        Value::Object(map) => map.remove(unescape(last).as_ref()),
+        Value::Array(vec) => last.parse().ok().filter(|&i| i < vec.len()).map(|i| vec.remove(i)),
+        _ => None,
+    }
+}
+
+// objects missing on the way are added, anything else in the way is left alone and the
+// value dropped
+fn put<'a>(value: &mut Value<'a>, segments: &[&str], moved: Value<'a>) {
+    let Some((&first, rest)) = segments.split_first() else {
+        return;
+    };
+    let Value::Object(map) = value else {
+        return;
+    };
+    let key = Cow::Owned(unescape(first).into_owned());
+    if rest.is_empty() {
+        map.insert(key, moved);
+        return;
+    }
+    put(map.entry(key).or_insert_with(|| Value::Object(Default::default())), rest, moved);
+}
+
+// values that don't convert are left as they are
+fn convert(value: &mut Value, from: Kind, to: Kind) {
+    let text = match &*value {
+        Value::Bytes(bytes) => std::str::from_utf8(bytes).ok(),
+        Value::Str(text) => Some(*text),
+        Value::String(text) => Some(text.as_str()),
+        _ => None,
+    };
+    let converted = match (from, to, &*value) {
+        (Kind::Number, Kind::String, Value::Number(n)) => Value::String(n.to_string()),
+        (Kind::Boolean, Kind::String, Value::Bool(b)) => Value::String(b.to_string()),
+        (Kind::String, Kind::Number, _) => match text.and_then(|text| text.parse::<Number>().ok()) {
+            Some(n) => Value::Number(n),
+            None => return,
+        },
+        (Kind::String, Kind::Boolean, _) => match text.and_then(|text| text.parse().ok()) {
+            Some(b) => Value::Bool(b),
+            None => return,
+        },
+        (Kind::Boolean, Kind::Number, Value::Bool(b)) => Value::Number(u8::from(*b).into()),
+        (Kind::Number, Kind::Boolean, Value::Number(n)) => match n.as_u64() {
+            Some(0) => Value::Bool(false),
+            Some(1) => Value::Bool(true),
+            _ => return,
+        },
+        _ => return,
+    };
+    *value = converted;
+}
+
+/// [`Change`]s applied in order, a bundle to translate payloads between two versions of an
+/// API. As a [`Transform`] it can go into any pipeline, e.g. a tenant's.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Adapter {
+    pub changes: Vec<Change>,
+}
+
+impl Adapter {
+    /// Undoes this one, for payloads going the other way. Values that were dropped, or didn't
+    /// convert, aren't brought back.
+    pub fn inverse(&self) -> Adapter {
+        Adapter { changes: self.changes.iter().rev().map(Change::inverse).collect() }
+    }
+}
+
+impl Transform for Adapter {
+    fn apply<'a>(&self, mut value: Value<'a>, _: &Context) -> Value<'a> {
+        for change in &self.changes {
+            change.apply(&mut value);
+        }
+        value
+    }
+}
+
+/// An older version a request asked for, put in its extensions by [`select`]. `response`
+/// takes what's served back to that version, `request` brings a body in it up to date.
+#[derive(Clone)]
+pub struct Version {
+    pub name: String,
+    pub response: Transforms,
+    pub request: Transforms,
+}
+
+// a route's `versions`, `{"current": "2", "adapters": {"1": [...]}}` with an adapter from
+// the current version to each older one
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Route {
+    current: String,
+    #[serde(default)]
+    adapters: HashMap<String, Adapter>,
+}
+
+/// API versions by request path, from the `versions` of each route of a routes file (see
+/// [`crate::branch::Branches`]), e.g. `{"/zc": {"versions": {"current": "2", "adapters":
+/// {"1": [{"rename": {"pointer": "/total", "to": "amount"}}]}}}}`. A client picks one with
+/// `Api-Version`, and is served the current one without. Routes without `versions` are left
+/// alone.
+#[derive(Clone, Default)]
+pub struct Versions {
+    routes: HashMap<String, (String, HashMap<String, Version>)>,
+}
+
+impl Versions {
+    pub fn from_json(document: &str) -> Result<Self, serde_json::Error> {
+        let routes: HashMap<String, serde_json::Value> = serde_json::from_str(document)?;
+        let mut versions = HashMap::new();
+        for (path, mut route) in routes {
+            let Some(route) = route.get_mut("versions").map(serde_json::Value::take) else {
+                continue;
+            };
+            let Route { current, adapters } = serde_json::from_value(route)?;
+            let adapters = adapters
+                .into_iter()
+                .map(|(name, adapter)| {
+                    let request = Transforms::default().with(adapter.inverse());
+                    let version = Version { name: name.clone(), response: Transforms::default().with(adapter), request };
+                    (name, version)
+                })
+                .collect();
+            versions.insert(path, (current, adapters));
+        }
+        Ok(Versions { routes: versions })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+/// Middleware for `axum::middleware::from_fn_with_state`. Puts the [`Version`] a request asks
+/// for in its extensions, for `/zc` to translate what it serves and the JSON bodies it sends
+/// on, and names the version served in the response's `Api-Version`. Versions a route
+/// doesn't have get a 400.
+pub async fn select(State(versions): State<Arc<Versions>>, mut request: Request<Body>, next: Next<Body>) -> Response {
+    let Some((current, adapters)) = versions.routes.get(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let asked = request.headers().get(API_VERSION).map(|version| version.to_str().unwrap_or_default());
+    let served = match asked {
+        None => current,
+        Some(asked) if asked == current => current,
+        Some(asked) => match adapters.get(asked) {
+            Some(version) => {
+                request.extensions_mut().insert(version.clone());
+                &version.name
+            }
+            None => return (StatusCode::BAD_REQUEST, format!("unsupported API version {:?}", asked)).into_response(),
+        },
+    };
+    let served = HeaderValue::from_str(served);
+    let mut response = next.run(request).await;
+    if let Ok(served) = served {
+        response.headers_mut().insert(API_VERSION, served);
+    }
+    response.headers_mut().append(header::VARY, HeaderValue::from_static(API_VERSION));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_zero_copy::Value;
+    use crate::transform::{Context, Transform};
+    use super::{Adapter, Versions};
+
+    const CHANGES: &str = r#"[
+        {"rename": {"pointer": "/items/*/sku", "to": "code"}},
+        {"move": {"from": "/customer/email", "to": "/email"}},
+        {"convert": {"pointer": "/total", "from": "number", "to": "string"}}
+    ]"#;
+
+    fn adapt(adapter: &Adapter, input: &str) -> String {
+        let value: Value = serde_json_nostr::from_slice(input.as_bytes()).unwrap();
+        serde_json_nostr::to_string(&adapter.apply(value, &Context::default())).unwrap()
+    }
+
+    #[test]
+    fn adapts_and_back() {
+        let adapter: Adapter = serde_json::from_str(CHANGES).unwrap();
+        let v2 = r#"{"customer":{"email":"j@example.com","name":"Jane"},"items":[{"qty":1,"sku":"a"},{"qty":2,"sku":"b"}],"total":12.5}"#;
+        let v1 = r#"{"customer":{"name":"Jane"},"email":"j@example.com","items":[{"code":"a","qty":1},{"code":"b","qty":2}],"total":"12.5"}"#;
+        assert_eq!(adapt(&adapter, v2), v1);
+        assert_eq!(adapt(&adapter.inverse(), v1), v2);
+        // missing fields and values of other kinds are left as they are
+        assert_eq!(adapt(&adapter, r#"{"items":[1],"total":"n/a"}"#), r#"{"items":[1],"total":"n/a"}"#);
+    }
+
+    #[test]
+    fn versions_from_routes_file() {
+        let routes = format!(r#"{{"/zc": {{"versions": {{"current": "2", "adapters": {{"1": {}}}}}}}, "/orders": []}}"#, CHANGES);
+        let versions = Versions::from_json(&routes).unwrap();
+        let (current, adapters) = &versions.routes["/zc"];
+        assert_eq!((current.as_str(), adapters.len()), ("2", 1));
+        assert!(!versions.routes.contains_key("/orders"));
+        assert!(Versions::from_json(r#"{"/zc": {"versions": {"current": "2", "adapters": {"1": [{"drop": "/x"}]}}}}"#).is_err());
+    }
+}
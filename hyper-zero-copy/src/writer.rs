@@ -0,0 +1,188 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::Serialize;
+use serde_json::Number;
+
+/// JSON written straight into a buffer as it's produced, commas and colons put in for you, so
+/// that a synthesized response needs no tree of its own. Parts of other documents go in as
+/// they are with [`JsonWriter::value`], or already serialized with [`JsonWriter::raw`].
+/// Misuse, such as a key outside an object, panics like it does for `ValueBuilder`.
+#[derive(Debug, Default)]
+pub struct JsonWriter {
+    out: BytesMut,
+    /// Per open array or object, its closing bracket and whether anything is in it yet.
+    open: Vec<(u8, bool)>,
+    after_key: bool,
+}
+
+impl JsonWriter {
+    pub fn with_capacity(capacity: usize) -> Self {
+        JsonWriter { out: BytesMut::with_capacity(capacity), ..JsonWriter::default() }
+    }
+
+    pub fn begin_object(&mut self) -> &mut Self {
+        self.separate();
+        self.out.put_u8(b'{');
+        self.open.push((b'}', false));
+        self
+    }
+
+    pub fn begin_array(&mut self) -> &mut Self {
+        self.separate();
+        self.out.put_u8(b'[');
+        self.open.push((b']', false));
+        self
+    }
+
+    /// Ends the innermost array or object.
+    pub fn end(&mut self) -> &mut Self {
+        assert!(!self.after_key, "end of an object after a key without its value");
+        let (close, _) = self.open.pop().expect("end without an array or object to end");
+        self.out.put_u8(close);
+        self
+    }
+
+    pub fn key(&mut self, key: &str) -> &mut Self {
+        assert!(matches!(self.open.last(), Some((b'}', _))) && !self.after_key, "key {:?} outside an object", key);
+        self.comma();
+        self.string(key);
+        self.out.put_u8(b':');
+        self.after_key = true;
+        self
+    }
+
+    pub fn str(&mut self, s: &str) -> &mut Self {
+        self.separate();
+        self.string(s);
+        self
+    }
+
+    pub fn num(&mut self, n: impl Into<Number>) -> &mut Self {
+        self.separate();
+        self.out.put_slice(n.into().to_string().as_bytes());
+        self
+    }
+
+    pub fn bool(&mut self, b: bool) -> &mut Self {
+        self.separate();
+        self.out.put_slice(if b { b"true" } else { b"false" });
+        self
+    }
+
+    pub fn null(&mut self) -> &mut Self {
+        self.separate();
+        self.out.put_slice(b"null");
+        self
+    }
+
+    /// Serializes `value`, e.g. a subtree of a yoked document. When that fails nothing of it
+    /// is written.
+    pub fn value(&mut self, value: &impl Serialize) -> Result<&mut Self, serde_json_nostr::Error> {
+        let (len, after_key, open) = (self.out.len(), self.after_key, self.open.last().copied());
+        self.separate();
+        if let Err(err) = serde_json_nostr::to_writer((&mut self.out).writer(), value) {
+            self.out.truncate(len);
+            self.after_key = after_key;
+            if let (Some(last), Some(open)) = (self.open.last_mut(), open) {
+                *last = open;
+            }
+            return Err(err);
+        }
+        Ok(self)
+    }
+
+    /// Writes `json` as it is, it has to be a single valid JSON value.
+    pub fn raw(&mut self, json: &[u8]) -> &mut Self {
+        self.separate();
+        self.out.put_slice(json);
+        self
+    }
+
+    pub fn finish(self) -> Bytes {
+        assert!(self.open.is_empty(), "finished with {} arrays or objects not ended", self.open.len());
+        self.out.freeze()
+    }
+
+    // a comma before anything but the first value of an array or object, and a value after a key
+    fn separate(&mut self) {
+        if std::mem::take(&mut self.after_key) {
+            return;
+        }
+        match self.open.last() {
+            Some((b'}', _)) => panic!("a value in an object without a key"),
+            Some(_) => self.comma(),
+            None => assert!(self.out.is_empty(), "a value after the one written"),
+        }
+    }
+
+    fn comma(&mut self) {
+        if let Some((_, written)) = self.open.last_mut() {
+            if *written {
+                self.out.put_u8(b',');
+            }
+            *written = true;
+        }
+    }
+
+    fn string(&mut self, s: &str) {
+        // writing to memory can't fail, nor can serializing a str
+        let _ = serde_json::to_writer((&mut self.out).writer(), s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_zero_copy::Value;
+    use super::JsonWriter;
+
+    #[test]
+    fn writes_with_separators() {
+        let doc: Value = serde_json_nostr::from_str(r#"{"items":[1,{"a":"b"}],"note":"x"}"#).unwrap();
+        let mut out = JsonWriter::with_capacity(64);
+        out.begin_object().key("data").begin_array();
+        for item in ["a\"b", "c"] {
+            out.str(item);
+        }
+        out.raw(br#"{"pre":true}"#).end();
+        out.key("items").value(doc.pointer("/items").unwrap()).unwrap();
+        out.key("empty").begin_object().end();
+        out.key("n").num(-3).key("ok").bool(false).key("none").null().end();
+        assert_eq!(
+            out.finish(),
+            r#"{"data":["a\"b","c",{"pre":true}],"items":[1,{"a":"b"}],"empty":{},"n":-3,"ok":false,"none":null}"#
+        );
+    }
+
+    #[test]
+    fn failed_value_writes_nothing() {
+        struct Failing;
+        impl serde::Serialize for Failing {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::{Error, SerializeSeq};
+                let mut seq = serializer.serialize_seq(None)?;
+                seq.serialize_element(&1)?;
+                Err(S::Error::custom("no"))
+            }
+        }
+        let mut out = JsonWriter::default();
+        out.begin_array().num(1);
+        assert!(out.value(&Failing).is_err());
+        out.num(2).end();
+        assert_eq!(out.finish(), "[1,2]");
+        let panics = |write: fn(&mut JsonWriter)| std::panic::catch_unwind(|| write(&mut JsonWriter::default())).is_err();
+        assert!(panics(|out| {
+            out.begin_object().null();
+        }));
+        assert!(panics(|out| {
+            out.begin_array().key("a");
+        }));
+        assert!(panics(|out| {
+            out.null().null();
+        }));
+        assert!(panics(|out| {
+            out.begin_object().key("a").end();
+        }));
+        assert!(panics(|out| {
+            out.end();
+        }));
+    }
+}
@@ -0,0 +1,396 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+
+use axum::extract::RawQuery;
+use axum::http::{header, HeaderMap};
+use axum::routing::post;
+use axum::Extension;
+use hyper::{Body, Client, Method, Request, StatusCode, Uri};
+use hyper_zero_copy::auth::{self, Auth, X_API_KEY};
+use hyper_zero_copy::cache::{Cache, MemoryCache};
+use hyper_zero_copy::compress::Compression;
+use hyper_zero_copy::dead_letter::{DeadLetters, Fallback, Sink};
+use hyper_zero_copy::forward::{self, Forwarding};
+use hyper_zero_copy::mock::{self, Fixtures};
+use hyper_zero_copy::provenance::Provenance;
+use hyper_zero_copy::proxy::{self, MethodUpstreams, RequestTransforms};
+use hyper_zero_copy::rewrite::RewriteUrls;
+use hyper_zero_copy::transform::{ClaimsField, Mask, Transforms};
+use hyper_zero_copy::version::{self, Versions, API_VERSION};
+
+// Counts per thread: `#[tokio::test]` runs the proxy, the mock upstream and the client on the
+// test's own thread, so concurrently running tests don't pollute each other's numbers.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + layout.size()));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let grown = new_size.saturating_sub(layout.size());
+        let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + grown));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn serve(app: axum::Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+    addr
+}
+
+fn allocated() -> usize {
+    ALLOCATED.with(|allocated| allocated.get())
+}
+
+struct Harness {
+    proxy: SocketAddr,
+    client: Client<hyper::client::HttpConnector>,
+}
+
+impl Harness {
+    fn start(fixtures: Fixtures) -> Harness {
+        let upstream_addr = serve(mock::router(fixtures));
+
+        let uri = Uri::try_from(format!("http://{}/hello", upstream_addr)).unwrap();
+        let app = proxy::router(Arc::new(Client::new()), uri);
+        let proxy = serve(app);
+
+        Harness { proxy, client: Client::new() }
+    }
+
+    async fn get(&self, path: &str) -> bytes::Bytes {
+        let uri = Uri::try_from(format!("http://{}{}", self.proxy, path)).unwrap();
+        let res = self.client.get(uri).await.unwrap();
+        assert!(res.status().is_success(), "{} returned {}", path, res.status());
+        hyper::body::to_bytes(res).await.unwrap()
+    }
+
+    /// Bytes allocated on this thread while serving `path`, client side included.
+    async fn allocated_for(&self, path: &str) -> usize {
+        let before = allocated();
+        self.get(path).await;
+        allocated() - before
+    }
+}
+
+fn as_json(bytes: &[u8]) -> serde_json::Value {
+    serde_json::from_slice(bytes).unwrap()
+}
+
+#[tokio::test]
+async fn zero_copy_matches_upstream() {
+    let harness = Harness::start(Fixtures::default());
+    let body = harness.get("/zc").await;
+    assert_json_diff::assert_json_eq!(as_json(&body), as_json(mock::SAMPLE_JSON));
+}
+
+#[tokio::test]
+async fn serde_matches_upstream() {
+    let harness = Harness::start(Fixtures::default());
+    let body = harness.get("/serde").await;
+    assert_json_diff::assert_json_eq!(as_json(&body), as_json(mock::SAMPLE_JSON));
+}
+
+#[tokio::test]
+async fn zero_copy_and_serde_are_byte_identical() {
+    // Both trees keep keys sorted, so without escapes in the fixture the outputs are identical.
+    let harness = Harness::start(Fixtures::default().with("hello", r#"{"b":[1,2.5,null],"a":{"y":"x","x":true}}"#));
+    let zc = harness.get("/zc").await;
+    let serde = harness.get("/serde").await;
+    assert_eq!(zc, serde);
+    assert_eq!(zc.as_ref(), br#"{"a":{"x":true,"y":"x"},"b":[1,2.5,null]}"#);
+}
+
+#[tokio::test]
+async fn zero_copy_does_not_copy_strings() {
+    let harness = Harness::start(Fixtures::default());
+    // Warm up both paths first, the very first request pays for pool and buffer growth.
+    harness.get("/zc").await;
+    harness.get("/serde").await;
+    let zc = harness.allocated_for("/zc").await;
+    let serde = harness.allocated_for("/serde").await;
+    println!("allocated per request: zc={} serde={}", zc, serde);
+    assert!(zc < serde, "zero copy path allocated {} bytes, serde path {}", zc, serde);
+}
+
+#[tokio::test]
+async fn head_and_options_without_a_body() {
+    let harness = Harness::start(Fixtures::default());
+    let request = |method: Method| {
+        let request = Request::builder().method(method).uri(format!("http://{}/zc", harness.proxy));
+        harness.client.request(request.body(Body::empty()).unwrap())
+    };
+    let head = request(Method::HEAD).await.unwrap();
+    assert_eq!(head.status(), StatusCode::OK);
+    assert_eq!(head.headers()[header::CONTENT_TYPE], "application/json");
+    assert!(hyper::body::to_bytes(head).await.unwrap().is_empty());
+    let options = request(Method::OPTIONS).await.unwrap();
+    assert_eq!(options.status(), StatusCode::NO_CONTENT);
+    assert_eq!(options.headers()[header::ALLOW], "GET, HEAD, OPTIONS");
+    assert_eq!(request(Method::POST).await.unwrap().status(), StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[tokio::test]
+async fn other_methods_go_to_their_upstream() {
+    let echo = |query: RawQuery, headers: HeaderMap, body: String| async move {
+        // those the client meant for the proxy alone don't come this far
+        assert!(!headers.contains_key("x-hop") && !headers.contains_key("keep-alive"));
+        format!("created {} {}", query.0.unwrap_or_default(), body)
+    };
+    let app = axum::Router::new().route("/orders", post(echo));
+    let orders_addr = serve(app);
+
+    let upstreams = MethodUpstreams::parse(&format!("POST=http://{}/orders", orders_addr)).unwrap();
+    let strip = RequestTransforms(Transforms::default().with(Mask { pointers: vec!["/debug".to_string()] }));
+    let app = proxy::router(Arc::new(Client::new()), Uri::from_static("http://127.0.0.1:1/hello"))
+        .layer(Extension(upstreams))
+        .layer(Extension(strip));
+    let addr = serve(app);
+
+    let send = |method: Method, content_type: &str| {
+        let request = Request::builder()
+            .method(method)
+            .uri(format!("http://{}/zc?dry_run=1", addr))
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONNECTION, "x-hop")
+            .header("x-hop", "1")
+            .header("keep-alive", "timeout=5");
+        Client::new().request(request.body(Body::from(r#"{"sku":"a","debug":{"trace":true}}"#)).unwrap())
+    };
+    let created = send(Method::POST, "application/json").await.unwrap();
+    assert_eq!(hyper::body::to_bytes(created).await.unwrap(), r#"created dry_run=1 {"sku":"a"}"#);
+    // only JSON is transformed
+    let created = send(Method::POST, "text/plain").await.unwrap();
+    assert_eq!(hyper::body::to_bytes(created).await.unwrap(), r#"created dry_run=1 {"sku":"a","debug":{"trace":true}}"#);
+    let deleted = send(Method::DELETE, "application/json").await.unwrap();
+    assert_eq!(deleted.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(deleted.headers()[header::ALLOW], "GET, HEAD, OPTIONS, POST");
+}
+
+#[tokio::test]
+async fn upstreams_are_sent_the_claims() {
+    let app = axum::Router::new().route("/orders", post(|body: String| async move { body }));
+    let orders_addr = serve(app);
+
+    let upstreams = MethodUpstreams::parse(&format!("POST=http://{}/orders", orders_addr)).unwrap();
+    let claims = ClaimsField { key: "user".to_string(), claims: vec!["sub".to_string()] };
+    let transforms = RequestTransforms(Transforms::default().with(Mask { pointers: vec!["/user".to_string()] }).with(claims));
+    let auth = Auth::default().with_api_keys("acme=k-a");
+    let app = proxy::router(Arc::new(Client::new()), Uri::from_static("http://127.0.0.1:1/hello"))
+        .layer(Extension(upstreams))
+        .layer(Extension(transforms))
+        .layer(axum::middleware::from_fn_with_state(Arc::new(auth), auth::check));
+    let addr = serve(app);
+
+    let request = Request::post(format!("http://{}/zc", addr))
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(X_API_KEY, "k-a")
+        .body(Body::from(r#"{"sku":"a","user":{"sub":"globex"}}"#))
+        .unwrap();
+    let created = Client::new().request(request).await.unwrap();
+    // what the upstream got, the client's own claim replaced by the verified one
+    assert_eq!(hyper::body::to_bytes(created).await.unwrap(), r#"{"sku":"a","user":{"sub":"acme"}}"#);
+}
+
+#[tokio::test]
+async fn older_versions_are_translated() {
+    let upstream_addr = serve(mock::router(Fixtures::default().with("hello", r#"{"id":1,"total":12.5}"#)));
+    let app = axum::Router::new().route("/orders", post(|body: String| async move { body }));
+    let orders_addr = serve(app);
+
+    let routes = r#"{"/zc": {"versions": {"current": "2", "adapters": {"1": [{"rename": {"pointer": "/total", "to": "amount"}}]}}}}"#;
+    let upstreams = MethodUpstreams::parse(&format!("POST=http://{}/orders", orders_addr)).unwrap();
+    let app = proxy::router(Arc::new(Client::new()), Uri::try_from(format!("http://{}/hello", upstream_addr)).unwrap())
+        .layer(Extension(upstreams))
+        .layer(axum::middleware::from_fn_with_state(Arc::new(Versions::from_json(routes).unwrap()), version::select));
+    let addr = serve(app);
+
+    let send = |method: Method, version: Option<&str>, body: &'static str| {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("http://{}/zc", addr))
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(version) = version {
+            request = request.header(API_VERSION, version);
+        }
+        Client::new().request(request.body(Body::from(body)).unwrap())
+    };
+    let v1 = send(Method::GET, Some("1"), "").await.unwrap();
+    assert_eq!(v1.headers()[API_VERSION], "1");
+    assert_eq!(hyper::body::to_bytes(v1).await.unwrap(), r#"{"amount":12.5,"id":1}"#);
+    let current = send(Method::GET, None, "").await.unwrap();
+    assert_eq!(current.headers()[API_VERSION], "2");
+    assert_eq!(hyper::body::to_bytes(current).await.unwrap(), r#"{"id":1,"total":12.5}"#);
+    assert_eq!(send(Method::GET, Some("3"), "").await.unwrap().status(), StatusCode::BAD_REQUEST);
+    // what an older client sends is brought up to date for the upstream
+    let created = send(Method::POST, Some("1"), r#"{"amount":3}"#).await.unwrap();
+    assert_eq!(hyper::body::to_bytes(created).await.unwrap(), r#"{"total":3}"#);
+}
+
+#[tokio::test]
+async fn unparsable_upstream_bodies_are_dead_lettered() {
+    let upstream_addr = serve(mock::router(Fixtures::default().with("hello", r#"{"items":[1,"#)));
+    let dir = std::env::temp_dir().join(format!("end-to-end-dead-letters-{}", std::process::id()));
+    let (letters, writer) = DeadLetters::spawn(Sink::Spool { dir: dir.clone(), max_letters: 8 }, 8);
+    let app = proxy::router(Arc::new(Client::new()), Uri::try_from(format!("http://{}/hello", upstream_addr)).unwrap())
+        .layer(Extension(letters.with_fallback(Fallback::json(StatusCode::OK, r#"{"items":[]}"#))));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service());
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(server.with_graceful_shutdown(async { stopped.await.unwrap_or_default() }));
+
+    let response = Client::new().get(Uri::try_from(format!("http://{}/zc", addr)).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(hyper::body::to_bytes(response).await.unwrap(), r#"{"items":[]}"#);
+    // the writer is done once the app, and the handle in it, is gone
+    stop.send(()).unwrap();
+    server.await.unwrap().unwrap();
+    writer.await.unwrap();
+    let spooled: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| std::fs::read(entry.unwrap().path()).unwrap()).collect();
+    assert_eq!(spooled.len(), 1);
+    let letter = as_json(&spooled[0]);
+    assert_eq!((letter["stage"].as_str(), letter["route"].as_str()), (Some("upstream_body"), Some("/zc")));
+    assert_eq!(letter["body"], r#"{"items":[1,"#);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn provenance_says_where_responses_came_from() {
+    let upstream_addr = serve(mock::router(Fixtures::default().with("hello", r#"{"id":1}"#)));
+    let cache = Cache::new(Arc::new(MemoryCache::default()), std::time::Duration::from_secs(60));
+    let app = proxy::router(Arc::new(Client::new()), Uri::try_from(format!("http://{}/hello", upstream_addr)).unwrap())
+        .layer(Extension(cache))
+        .layer(Extension(Provenance::new("hello")));
+    let addr = serve(app);
+
+    let get = || async {
+        let response = Client::new().get(Uri::try_from(format!("http://{}/zc", addr)).unwrap()).await.unwrap();
+        as_json(&hyper::body::to_bytes(response).await.unwrap())
+    };
+    let miss = get().await;
+    assert_eq!((miss["id"].as_u64(), miss["_meta"]["cache"].as_str()), (Some(1), Some("miss")));
+    assert_eq!(miss["_meta"]["upstream"], "hello");
+    assert!(miss["_meta"]["fetch_ms"].is_f64());
+    let hit = get().await;
+    assert_eq!(hit["_meta"]["cache"], "hit");
+    assert!(hit["_meta"]["fetch_ms"].is_null());
+}
+
+#[tokio::test]
+async fn upstream_links_point_at_the_proxy() {
+    let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    let order = format!(r#"{{"id":42,"_links":{{"self":{{"href":"http://{}/orders/42"}}}}}}"#, upstream_addr);
+    tokio::spawn(mock::serve(upstream, Fixtures::default().with("orders", order)));
+    let rewrite = RewriteUrls { upstreams: vec![format!("http://{}", upstream_addr)], prefix: "/api".to_string(), ..RewriteUrls::default() };
+    let proxied = |forwarding: Forwarding| {
+        let app = proxy::router(Arc::new(Client::new()), Uri::try_from(format!("http://{}/orders", upstream_addr)).unwrap())
+            .layer(Extension(Transforms::default().with(rewrite.clone())))
+            .layer(axum::middleware::from_fn_with_state(Arc::new(forwarding), forward::headers));
+        serve(app)
+    };
+    let href = |addr: SocketAddr, forwarded: bool| async move {
+        let mut request = Request::get(format!("http://{}/zc", addr));
+        if forwarded {
+            request = request.header("x-forwarded-proto", "https").header("x-forwarded-host", "api.example.com");
+        }
+        let response = Client::new().request(request.body(Body::empty()).unwrap()).await.unwrap();
+        as_json(&hyper::body::to_bytes(response).await.unwrap())["_links"]["self"]["href"].clone()
+    };
+
+    let trusting = proxied(Forwarding { trust_forwarded: true });
+    assert_eq!(href(trusting, true).await, "https://api.example.com/api/orders/42");
+    assert_eq!(href(trusting, false).await, format!("http://{}/api/orders/42", trusting));
+    // what a client says of the host it asked for isn't believed unless it's trusted
+    let untrusting = proxied(Forwarding::default());
+    assert_eq!(href(untrusting, true).await, format!("http://{}/api/orders/42", untrusting));
+}
+
+#[tokio::test]
+async fn byte_order_marks_are_skipped() {
+    let upstream_addr = serve(mock::router(Fixtures::default().with("legacy", &b"\xef\xbb\xbf{\"id\":1}"[..])));
+    let app = proxy::router(Arc::new(Client::new()), Uri::try_from(format!("http://{}/legacy", upstream_addr)).unwrap());
+    let addr = serve(app);
+
+    let response = Client::new().get(Uri::try_from(format!("http://{}/zc", addr)).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::VARY], "accept");
+    assert_eq!(hyper::body::to_bytes(response).await.unwrap(), r#"{"id":1}"#);
+}
+
+#[tokio::test]
+async fn responses_are_compressed_by_accept_encoding() {
+    let items = format!("[{}]", vec![r#"{"name":"widget","price":1}"#; 100].join(","));
+    let upstream_addr = serve(mock::router(Fixtures::default().with("items", items.clone()).with("small", "[1]")));
+    let app = |path: &str| {
+        let uri = Uri::try_from(format!("http://{}/{}", upstream_addr, path)).unwrap();
+        proxy::router(Arc::new(Client::new()), uri).layer(Extension(Compression { min_bytes: 64, ..Compression::default() }))
+    };
+    let mut addrs = Vec::new();
+    for path in ["items", "small"] {
+        addrs.push(serve(app(path)));
+    }
+
+    let get = |addr: SocketAddr, accept_encoding: &'static str| async move {
+        let request = Request::get(format!("http://{}/zc", addr)).header(header::ACCEPT_ENCODING, accept_encoding).body(Body::empty()).unwrap();
+        let response = Client::new().request(request).await.unwrap();
+        assert_eq!(response.headers()[header::VARY], "accept, accept-encoding");
+        let encoding = response.headers().get(header::CONTENT_ENCODING).map(|value| value.to_str().unwrap().to_string());
+        (encoding, hyper::body::to_bytes(response).await.unwrap())
+    };
+    let (encoding, body) = get(addrs[0], "gzip, br;q=0.9").await;
+    assert_eq!(encoding.as_deref(), Some("gzip"));
+    let mut served = Vec::new();
+    std::io::Read::read_to_end(&mut flate2::read::GzDecoder::new(&body[..]), &mut served).unwrap();
+    assert_eq!(served, items.as_bytes());
+    let (encoding, body) = get(addrs[0], "zstd").await;
+    assert_eq!(encoding.as_deref(), Some("zstd"));
+    assert_eq!(zstd::decode_all(&body[..]).unwrap(), items.as_bytes());
+    // under the threshold it goes as it is
+    assert_eq!(get(addrs[1], "gzip").await, (None, bytes::Bytes::from_static(b"[1]")));
+}
+
+#[tokio::test]
+async fn identities_share_neither_flights_nor_entries() {
+    // slow enough for both requests to be in flight at once
+    let echo = axum::Router::new().route("/me", axum::routing::get(|headers: axum::http::HeaderMap| async move {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        format!(r#"{{"user":"{}"}}"#, headers["x-user"].to_str().unwrap())
+    }));
+    let upstream_addr = serve(echo);
+    let auth = Auth::default().with_api_keys("acme=k-a,globex=k-b").with_claim_header("sub", header::HeaderName::from_static("x-user"));
+    let cache = Cache::new(Arc::new(MemoryCache::default()), std::time::Duration::from_secs(60));
+    let app = proxy::router(Arc::new(Client::new()), Uri::try_from(format!("http://{}/me", upstream_addr)).unwrap())
+        .layer(Extension(cache))
+        .layer(Extension(proxy::Coalescing::default()))
+        .layer(axum::middleware::from_fn_with_state(Arc::new(auth), auth::check));
+    let addr = serve(app);
+
+    let get = |key: &'static str| async move {
+        let request = Request::get(format!("http://{}/zc", addr)).header(X_API_KEY, key).body(Body::empty()).unwrap();
+        as_json(&hyper::body::to_bytes(Client::new().request(request).await.unwrap()).await.unwrap())
+    };
+    for _ in 0..2 {
+        // the first time both are fetched at once, the second both are cached
+        let (a, b) = tokio::join!(get("k-a"), get("k-b"));
+        assert_eq!((a["user"].as_str(), b["user"].as_str()), (Some("acme"), Some("globex")));
+    }
+}
@@ -0,0 +1,83 @@
+// Webhook deliveries of two shapes, told apart the way `#[serde(untagged)]` and
+// `#[serde(tag = "type")]` would, with every string borrowing the request body.
+//
+//     cargo run --example polymorphic
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use serde_zero_copy::{BorrowedContent, Value};
+
+#[derive(Deserialize)]
+struct Push<'a> {
+    repository: &'a str,
+    commits: Vec<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct Issue<'a> {
+    title: &'a str,
+    #[serde(borrow)]
+    labels: Value<'a>,
+}
+
+// internally tagged by "type"
+enum Event<'a> {
+    Push(Push<'a>),
+    Issue(Issue<'a>),
+}
+
+impl<'de> Deserialize<'de> for Event<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+    {
+        let content = BorrowedContent::deserialize(deserializer)?;
+        match content.tag("type") {
+            Some("push") => content.deserialize_as().map(Event::Push).map_err(D::Error::custom),
+            Some("issue") => content.deserialize_as().map(Event::Issue).map_err(D::Error::custom),
+            Some(other) => Err(D::Error::unknown_variant(other, &["push", "issue"])),
+            None => Err(D::Error::missing_field("type")),
+        }
+    }
+}
+
+// untagged: a single event or a batch of them
+enum Delivery<'a> {
+    One(Event<'a>),
+    Batch(Vec<Event<'a>>),
+}
+
+impl<'de> Deserialize<'de> for Delivery<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+    {
+        let content = BorrowedContent::deserialize(deserializer)?;
+        content
+            .deserialize_as()
+            .map(Delivery::One)
+            .or_else(|_| content.deserialize_as().map(Delivery::Batch))
+            .map_err(|_| D::Error::custom("data did not match any variant of Delivery"))
+    }
+}
+
+fn describe(event: &Event) -> String {
+    match event {
+        Event::Push(push) => format!("push of {} commits to {}", push.commits.len(), push.repository),
+        Event::Issue(issue) => format!("issue {:?} labelled {}", issue.title, serde_json_nostr::to_string(&issue.labels).unwrap_or_default()),
+    }
+}
+
+fn main() {
+    let bodies = [
+        r#"{"type":"push","repository":"trial-n-error","commits":["ce55592","70ca584"]}"#,
+        r#"[{"type":"issue","title":"flatten","labels":["serde"]},{"type":"push","repository":"r","commits":[]}]"#,
+        r#"{"type":"deploy"}"#,
+    ];
+    for body in bodies {
+        match serde_json_nostr::from_str::<Delivery>(body) {
+            Ok(Delivery::One(event)) => println!("{}", describe(&event)),
+            Ok(Delivery::Batch(events)) => println!("batch: {}", events.iter().map(describe).collect::<Vec<_>>().join(", ")),
+            Err(err) => println!("rejected: {}", err),
+        }
+    }
+}
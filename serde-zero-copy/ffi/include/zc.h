@@ -0,0 +1,59 @@
+/* The zero-copy parser over a C ABI, link against libzc.
+ *
+ * Values are handles, each yoked to the buffer it was parsed from; a handle got from another
+ * shares its buffer, which is freed with the last of them. Every handle is freed on its own,
+ * in any order. Handles can be read from several threads at once. */
+#ifndef ZC_H
+#define ZC_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct ZcValue ZcValue;
+
+typedef enum ZcStatus {
+    ZC_OK = 0,
+    ZC_NULL_ARGUMENT = 1,
+    ZC_INVALID_UTF8 = 2,
+    ZC_PARSE_ERROR = 3,
+    ZC_NOT_FOUND = 4,
+    ZC_SERIALIZE_ERROR = 5,
+} ZcStatus;
+
+/* Bytes the library allocated, to be given back to zc_buffer_free. */
+typedef struct ZcBuffer {
+    uint8_t *data;
+    size_t len;
+} ZcBuffer;
+
+/* What the last call on this thread that didn't return ZC_OK failed with, valid until the
+ * next one fails. */
+const char *zc_last_error(void);
+
+/* Parses `len` bytes at `input` into a new handle at `out`. The bytes are copied. */
+ZcStatus zc_parse(const uint8_t *input, size_t len, ZcValue **out);
+
+/* The value at the JSON pointer `pointer` into `value` as a new handle at `out`, sharing
+ * `value`'s buffer. ZC_NOT_FOUND if there's none. */
+ZcStatus zc_pointer_get(const ZcValue *value, const char *pointer, ZcValue **out);
+
+/* `value` as JSON into `out`, to be freed with zc_buffer_free. */
+ZcStatus zc_serialize(const ZcValue *value, ZcBuffer *out);
+
+/* Another handle to `value`, NULL for NULL. */
+ZcValue *zc_value_clone(const ZcValue *value);
+
+/* Frees a handle, NULL is ignored. */
+void zc_value_free(ZcValue *value);
+
+void zc_buffer_free(ZcBuffer buffer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
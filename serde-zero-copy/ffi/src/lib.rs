@@ -0,0 +1,181 @@
+//! A C ABI over the parser, see `include/zc.h`. Values are handles, each yoked to the buffer
+//! it was parsed from; a handle got from another shares its buffer, which is freed with the
+//! last of them.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+use std::sync::Arc;
+use serde_zero_copy::Value;
+use yoke::Yoke;
+
+pub struct ZcValue(Yoke<Value<'static>, Arc<[u8]>>);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZcStatus {
+    Ok = 0,
+    NullArgument = 1,
+    InvalidUtf8 = 2,
+    ParseError = 3,
+    NotFound = 4,
+    SerializeError = 5,
+}
+
+/// Bytes the library allocated, to be given back to [`zc_buffer_free`].
+#[repr(C)]
+pub struct ZcBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn fail(status: ZcStatus, message: impl ToString) -> ZcStatus {
+    let message = CString::new(message.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    status
+}
+
+/// What the last call on this thread that didn't return `ZC_OK` failed with, valid until the
+/// next one fails.
+#[no_mangle]
+pub extern "C" fn zc_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// Parses `len` bytes at `input` into a new handle at `out`. The bytes are copied, `input` can
+/// be reused once this returns.
+///
+/// # Safety
+///
+/// `input` is to be valid for `len` bytes and `out` for a write.
+#[no_mangle]
+pub unsafe extern "C" fn zc_parse(input: *const u8, len: usize, out: *mut *mut ZcValue) -> ZcStatus {
+    if input.is_null() || out.is_null() {
+        return fail(ZcStatus::NullArgument, "null argument to zc_parse");
+    }
+    let cart: Arc<[u8]> = Arc::from(std::slice::from_raw_parts(input, len));
+    match Yoke::try_attach_to_cart(cart, |bytes| serde_json_nostr::from_slice(bytes)) {
+        Ok(yoke) => {
+            *out = Box::into_raw(Box::new(ZcValue(yoke)));
+            ZcStatus::Ok
+        }
+        Err(err) => fail(ZcStatus::ParseError, err),
+    }
+}
+
+/// The value at the JSON pointer `pointer` into `value` as a new handle at `out`, sharing
+/// `value`'s buffer. `ZC_NOT_FOUND` if there's none.
+///
+/// # Safety
+///
+/// `value` is to be a live handle, `pointer` a NUL terminated string and `out` valid for a
+/// write.
+#[no_mangle]
+pub unsafe extern "C" fn zc_pointer_get(value: *const ZcValue, pointer: *const c_char, out: *mut *mut ZcValue) -> ZcStatus {
+    if value.is_null() || pointer.is_null() || out.is_null() {
+        return fail(ZcStatus::NullArgument, "null argument to zc_pointer_get");
+    }
+    let Ok(pointer) = CStr::from_ptr(pointer).to_str() else {
+        return fail(ZcStatus::InvalidUtf8, "pointer isn't utf-8");
+    };
+    let found = (*value).0.try_map_project_cloned(|value, _| value.pointer(pointer).cloned().ok_or(()));
+    match found {
+        Ok(yoke) => {
+            *out = Box::into_raw(Box::new(ZcValue(yoke)));
+            ZcStatus::Ok
+        }
+        Err(()) => fail(ZcStatus::NotFound, format!("nothing at {}", pointer)),
+    }
+}
+
+/// `value` as JSON into `out`.
+///
+/// # Safety
+///
+/// `value` is to be a live handle and `out` valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn zc_serialize(value: *const ZcValue, out: *mut ZcBuffer) -> ZcStatus {
+    if value.is_null() || out.is_null() {
+        return fail(ZcStatus::NullArgument, "null argument to zc_serialize");
+    }
+    match serde_json_nostr::to_vec((*value).0.get()) {
+        Ok(json) => {
+            let json = Box::into_raw(json.into_boxed_slice());
+            *out = ZcBuffer { data: json.cast(), len: json.len() };
+            ZcStatus::Ok
+        }
+        Err(err) => fail(ZcStatus::SerializeError, err),
+    }
+}
+
+/// Another handle to `value`, to be freed on its own.
+///
+/// # Safety
+///
+/// `value` is to be a live handle or null, which gives null.
+#[no_mangle]
+pub unsafe extern "C" fn zc_value_clone(value: *const ZcValue) -> *mut ZcValue {
+    match value.as_ref() {
+        Some(value) => Box::into_raw(Box::new(ZcValue(value.0.clone()))),
+        None => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `value` is to be a live handle or null; it isn't to be used after.
+#[no_mangle]
+pub unsafe extern "C" fn zc_value_free(value: *mut ZcValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+/// # Safety
+///
+/// `buffer` is to be one [`zc_serialize`] gave, not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn zc_buffer_free(buffer: ZcBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::ptr;
+    use super::*;
+
+    #[test]
+    fn handles_share_the_parsed_buffer() {
+        unsafe {
+            let input = br#"{"user":{"name":"Jane","tags":["a","b"]}}"#;
+            let mut doc = ptr::null_mut();
+            assert_eq!(zc_parse(input.as_ptr(), input.len(), &mut doc), ZcStatus::Ok);
+            let mut tags = ptr::null_mut();
+            assert_eq!(zc_pointer_get(doc, c"/user/tags".as_ptr(), &mut tags), ZcStatus::Ok);
+            // the document can go first, its buffer stays for `tags`
+            zc_value_free(doc);
+
+            let mut json = ZcBuffer { data: ptr::null_mut(), len: 0 };
+            assert_eq!(zc_serialize(tags, &mut json), ZcStatus::Ok);
+            assert_eq!(std::slice::from_raw_parts(json.data, json.len), br#"["a","b"]"#);
+            zc_buffer_free(json);
+
+            let mut missing = ptr::null_mut();
+            assert_eq!(zc_pointer_get(tags, c"/5".as_ptr(), &mut missing), ZcStatus::NotFound);
+            assert_eq!(CStr::from_ptr(zc_last_error()).to_str().unwrap(), "nothing at /5");
+            assert!(missing.is_null());
+            zc_value_free(tags);
+
+            assert_eq!(zc_parse(b"{".as_ptr(), 1, &mut doc), ZcStatus::ParseError);
+            let mut unused = ZcBuffer { data: ptr::null_mut(), len: 0 };
+            assert_eq!(zc_serialize(ptr::null(), &mut unused), ZcStatus::NullArgument);
+        }
+    }
+}
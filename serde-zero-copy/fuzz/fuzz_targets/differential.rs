@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde_zero_copy::Value;
+
+// Whenever both parsers accept the input they must agree on what it means.
+fuzz_target!(|data: &[u8]| {
+    let zero_copy: Value = match serde_json_nostr::from_slice(data) {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+    let reference: serde_json::Value = match serde_json::from_slice(data) {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+    let reparsed: serde_json::Value = serde_json::from_slice(&serde_json_nostr::to_vec(&zero_copy).unwrap()).unwrap();
+    assert_eq!(reparsed, reference);
+});
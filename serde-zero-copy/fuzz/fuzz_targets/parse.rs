@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde_zero_copy::Value;
+
+// Arbitrary input must be rejected with an error, never a panic.
+fuzz_target!(|data: &[u8]| {
+    let _ = serde_json_nostr::from_slice::<Value>(data);
+    let _ = serde_json::from_slice::<Value>(data);
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde_zero_copy::Value;
+
+// parse -> serialize -> parse -> serialize must reproduce the first serialization. The trees
+// themselves may differ in representation, an escaped string comes back borrowed the second time.
+fuzz_target!(|data: &[u8]| {
+    let first: Value = match serde_json_nostr::from_slice(data) {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+    let serialized = serde_json_nostr::to_vec(&first).unwrap();
+    let second: Value = serde_json_nostr::from_slice(&serialized).unwrap();
+    assert_eq!(serialized, serde_json_nostr::to_vec(&second).unwrap());
+});
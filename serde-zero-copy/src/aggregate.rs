@@ -0,0 +1,175 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use serde_json::Number;
+use crate::array::compare_numbers;
+use crate::Value;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    NotAnArray,
+    /// The group key of element `index` isn't a string, a boolean or null, so it can't
+    /// become an object key.
+    InvalidKey { index: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotAnArray => write!(f, "not an array"),
+            Error::InvalidKey { index } => write!(f, "group key of element {} isn't a string, boolean or null", index),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    /// Elements with a value other than null.
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+fn group_key<'a>(value: Option<&Value<'a>>) -> Option<Cow<'a, str>> {
+    match value {
+        None | Some(Value::Null) => Some(Cow::Borrowed("null")),
+        Some(Value::Bool(true)) => Some(Cow::Borrowed("true")),
+        Some(Value::Bool(false)) => Some(Cow::Borrowed("false")),
+        Some(Value::Str(s)) => Some(Cow::Borrowed(s)),
+        Some(Value::Bytes(b)) => std::str::from_utf8(b).ok().map(Cow::Borrowed),
+        Some(Value::String(s)) => Some(Cow::Owned(s.clone())),
+        _ => None,
+    }
+}
+
+impl<'a> Value<'a> {
+    /// Splits an array into an object of arrays keyed by the value at `pointer` in each
+    /// element, keeping their order. Elements without one are grouped under `null`.
+    pub fn group_by_pointer(self, pointer: &str) -> Result<Value<'a>, Error> {
+        let vec = match self {
+            Value::Array(vec) => vec,
+            _ => return Err(Error::NotAnArray),
+        };
+        let mut groups: BTreeMap<Cow<'a, str>, Vec<Value<'a>>> = BTreeMap::new();
+        for (index, element) in vec.into_iter().enumerate() {
+            let key = group_key(element.pointer(pointer)).ok_or(Error::InvalidKey { index })?;
+            groups.entry(key).or_default().push(element);
+        }
+        Ok(Value::Object(groups.into_iter().map(|(k, v)| (k, Value::Array(v))).collect()))
+    }
+
+    /// Aggregates the numbers at `pointer` across the elements of an array, others are
+    /// skipped. Sums of integers stay integers unless they overflow. The average, minimum and
+    /// maximum of no numbers are null.
+    pub fn aggregate(&self, pointer: &str, aggregate: Aggregate) -> Value<'static> {
+        let vec = match self {
+            Value::Array(vec) => vec,
+            _ => return Value::Null,
+        };
+        let values: Vec<&Value> = vec.iter().filter_map(|element| element.pointer(pointer)).collect();
+        let numbers: Vec<&Number> = values
+            .iter()
+            .filter_map(|v| match v {
+                Value::Number(n) => Some(n),
+                _ => None,
+            })
+            .collect();
+        let floats = || numbers.iter().filter_map(|n| n.as_f64());
+        let float = Value::from_f64;
+        let extreme = match aggregate {
+            Aggregate::Count => return Value::Number(values.iter().filter(|v| !matches!(v, Value::Null)).count().into()),
+            Aggregate::Sum => {
+                let ints: Option<i64> = numbers.iter().try_fold(0i64, |sum, n| sum.checked_add(n.as_i64()?));
+                return ints.map_or_else(|| float(floats().sum()), |sum| Value::Number(sum.into()));
+            }
+            Aggregate::Avg if numbers.is_empty() => return Value::Null,
+            Aggregate::Avg => return float(floats().sum::<f64>() / numbers.len() as f64),
+            Aggregate::Min => numbers.iter().min_by(|a, b| compare_numbers(a, b)),
+            Aggregate::Max => numbers.iter().max_by(|a, b| compare_numbers(a, b)),
+        };
+        extreme.map_or(Value::Null, |n| Value::Number((*n).clone()))
+    }
+
+    /// [`Value::aggregate`] over each group of an object, as made by
+    /// [`Value::group_by_pointer`].
+    pub fn aggregate_groups(&self, pointer: &str, aggregate: Aggregate) -> Value<'a> {
+        match self {
+            Value::Object(groups) => Value::Object(groups.iter().map(|(k, v)| (k.clone(), v.aggregate(pointer, aggregate))).collect()),
+            _ => Value::Null,
+        }
+    }
+
+    pub fn count(&self, pointer: &str) -> Value<'static> {
+        self.aggregate(pointer, Aggregate::Count)
+    }
+
+    pub fn sum(&self, pointer: &str) -> Value<'static> {
+        self.aggregate(pointer, Aggregate::Sum)
+    }
+
+    pub fn avg(&self, pointer: &str) -> Value<'static> {
+        self.aggregate(pointer, Aggregate::Avg)
+    }
+
+    // not `min`, which `Ord` would shadow
+    pub fn minimum(&self, pointer: &str) -> Value<'static> {
+        self.aggregate(pointer, Aggregate::Min)
+    }
+
+    pub fn maximum(&self, pointer: &str) -> Value<'static> {
+        self.aggregate(pointer, Aggregate::Max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{Aggregate, Error};
+
+    const ITEMS: &str = r#"[
+        {"category":"fruit","name":"apple","price":3},
+        {"category":"veg","name":"leek","price":2.5},
+        {"category":"fruit","name":"pear","price":4},
+        {"name":"mystery"}
+    ]"#;
+
+    #[test]
+    fn group_by_category() {
+        let value: Value = serde_json_nostr::from_str(ITEMS).unwrap();
+        let groups = value.clone().group_by_pointer("/category").unwrap();
+        assert_eq!(
+            serde_json_nostr::to_string(&groups.pointer("/fruit").unwrap()).unwrap(),
+            r#"[{"category":"fruit","name":"apple","price":3},{"category":"fruit","name":"pear","price":4}]"#
+        );
+        assert!(groups.pointer("/null/0/name").is_some_and(|name| name.identical(&Value::Bytes(b"mystery"))));
+        assert_eq!(
+            serde_json_nostr::to_string(&groups.aggregate_groups("/price", Aggregate::Sum)).unwrap(),
+            r#"{"fruit":7,"null":0,"veg":2.5}"#
+        );
+        assert_eq!(value.group_by_pointer("/price"), Err(Error::InvalidKey { index: 0 }));
+        assert_eq!(Value::Null.group_by_pointer(""), Err(Error::NotAnArray));
+    }
+
+    #[test]
+    fn aggregate_numbers() {
+        let value: Value = serde_json_nostr::from_str(ITEMS).unwrap();
+        let json = |v: Value| serde_json_nostr::to_string(&v).unwrap();
+        assert_eq!(json(value.count("/price")), "3");
+        assert_eq!(json(value.count("/name")), "4");
+        assert_eq!(json(value.sum("/price")), "9.5");
+        assert_eq!(json(value.avg("/price")), "3.1666666666666665");
+        assert_eq!(json(value.minimum("/price")), "2.5");
+        assert_eq!(json(value.maximum("/price")), "4");
+        assert_eq!(json(value.avg("/missing")), "null");
+        let big: Value = serde_json_nostr::from_str(r#"[{"n":9223372036854775807},{"n":1}]"#).unwrap();
+        #[cfg(not(feature = "decimal"))]
+        assert_eq!(json(big.sum("/n")), "9.223372036854776e18");
+        // arbitrary precision numbers write floats with an explicit exponent sign
+        #[cfg(feature = "decimal")]
+        assert_eq!(json(big.sum("/n")), "9.223372036854776e+18");
+    }
+}
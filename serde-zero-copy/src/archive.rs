@@ -0,0 +1,152 @@
+use std::borrow::Cow;
+use std::fmt;
+use rkyv::rancor;
+use rkyv::util::AlignedVec;
+use serde_json::Number;
+use crate::{NonFinite, Value};
+
+/// What archives start with, so that they're told apart from JSON at a glance; 16 bytes so
+/// that what follows stays aligned.
+pub const MAGIC: &[u8; 16] = b"\0serde-zero-copy";
+
+#[derive(Debug)]
+pub enum Error {
+    /// It doesn't start with [`MAGIC`].
+    NotAnArchive,
+    Invalid(rancor::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotAnArchive => f.write_str("not an archived value"),
+            Error::Invalid(err) => write!(f, "invalid archived value: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<rancor::Error> for Error {
+    fn from(err: rancor::Error) -> Self {
+        Error::Invalid(err)
+    }
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize)]
+#[rkyv(serialize_bounds(__S: rkyv::ser::Writer + rkyv::ser::Allocator, __S::Error: rancor::Source))]
+#[rkyv(bytecheck(bounds(__C: rkyv::validation::ArchiveContext)))]
+enum Node {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    /// A number as written, with `decimal`.
+    Numeral(String),
+    BigInt(String),
+    NonFinite(u8),
+    String(String),
+    Array(#[rkyv(omit_bounds)] Vec<Node>),
+    Object(#[rkyv(omit_bounds)] Vec<(String, Node)>),
+}
+
+fn number(n: &Number) -> Node {
+    if cfg!(feature = "decimal") {
+        return Node::Numeral(n.to_string());
+    }
+    match (n.as_i64(), n.as_u64(), n.as_f64()) {
+        (Some(i), _, _) => Node::Int(i),
+        (_, Some(u), _) => Node::Uint(u),
+        (_, _, Some(f)) => Node::Float(f),
+        _ => Node::Numeral(n.to_string()),
+    }
+}
+
+fn node(value: &Value) -> Node {
+    match value {
+        Value::Null => Node::Null,
+        Value::Bool(b) => Node::Bool(*b),
+        Value::Number(n) => number(n),
+        Value::BigInt(n) => Node::BigInt(n.to_string()),
+        Value::NonFinite(f) => Node::NonFinite(*f as u8),
+        // what `serde_json_nostr` left escaped is archived as the text it stands for
+        Value::Bytes(b) if b.contains(&b'\\') => {
+            let text = std::str::from_utf8(b).ok().and_then(|s| serde_json::from_str(&format!("\"{}\"", s)).ok());
+            Node::String(text.unwrap_or_else(|| String::from_utf8_lossy(b).into_owned()))
+        }
+        Value::Bytes(b) => Node::String(String::from_utf8_lossy(b).into_owned()),
+        Value::Str(s) => Node::String(s.to_string()),
+        Value::String(s) => Node::String(s.clone()),
+        Value::Array(vec) => Node::Array(vec.iter().map(node).collect()),
+        Value::Object(map) => Node::Object(map.iter().map(|(key, member)| (key.to_string(), node(member))).collect()),
+    }
+}
+
+fn value(node: &ArchivedNode) -> Value<'_> {
+    match node {
+        ArchivedNode::Null => Value::Null,
+        ArchivedNode::Bool(b) => Value::Bool(*b),
+        ArchivedNode::Int(i) => Value::Number(i.to_native().into()),
+        ArchivedNode::Uint(u) => Value::Number(u.to_native().into()),
+        ArchivedNode::Float(f) => Value::from_f64(f.to_native()),
+        ArchivedNode::Numeral(n) => n.as_str().parse().map_or(Value::Null, Value::Number),
+        ArchivedNode::BigInt(n) => Value::BigInt(Cow::Borrowed(n.as_str())),
+        ArchivedNode::NonFinite(f) => Value::NonFinite(match f {
+            0 => NonFinite::NaN,
+            1 => NonFinite::Infinity,
+            _ => NonFinite::NegInfinity,
+        }),
+        ArchivedNode::String(s) => Value::Str(s.as_str()),
+        ArchivedNode::Array(vec) => Value::Array(vec.iter().map(value).collect()),
+        ArchivedNode::Object(members) => {
+            Value::Object(members.iter().map(|entry| (Cow::Borrowed(entry.0.as_str()), value(&entry.1))).collect())
+        }
+    }
+}
+
+/// `value` archived with rkyv behind [`MAGIC`], to be persisted and brought back by
+/// [`from_archive`] without parsing.
+pub fn to_archive(value: &Value) -> Result<AlignedVec, Error> {
+    let mut out = AlignedVec::new();
+    out.extend_from_slice(MAGIC);
+    Ok(rkyv::api::high::to_bytes_in::<_, rancor::Error>(&node(value), out)?)
+}
+
+pub fn is_archive(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// The value `bytes` archive, its strings and keys borrowed from them. The archive is checked
+/// rather than trusted, but only walked through, nothing's parsed. `bytes` are to be aligned
+/// to 16 like an [`AlignedVec`] or a mapped file are.
+pub fn from_archive(bytes: &[u8]) -> Result<Value<'_>, Error> {
+    let archived = bytes.strip_prefix(MAGIC.as_slice()).ok_or(Error::NotAnArchive)?;
+    Ok(value(rkyv::access::<ArchivedNode, rancor::Error>(archived)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{from_archive, is_archive, to_archive, Error};
+
+    #[test]
+    fn round_trip_borrows_from_archive() {
+        let input = br#"{"name":"Jane \"J\"","tags":["a",null,true],"n":-3,"u":18446744073709551615,"f":1.5,"big":123456789012345678901234}"#;
+        let parsed: Value = serde_json_nostr::from_slice(input).unwrap();
+        let archive = to_archive(&parsed).unwrap();
+        assert!(is_archive(&archive));
+        let loaded = from_archive(&archive).unwrap();
+        assert_eq!(serde_json_nostr::to_string(&loaded).unwrap(), serde_json_nostr::to_string(&parsed).unwrap());
+        match loaded.pointer("/name") {
+            Some(Value::Str(name)) => {
+                assert_eq!(*name, "Jane \"J\"");
+                assert!(archive.as_ptr_range().contains(&name.as_ptr()));
+            }
+            other => panic!("{:?}", other),
+        }
+
+        assert!(matches!(from_archive(input), Err(Error::NotAnArchive)));
+        assert!(matches!(from_archive(&archive[..archive.len() - 3]), Err(Error::Invalid(_))));
+    }
+}
@@ -0,0 +1,279 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use serde_json::Number;
+use crate::Value;
+
+pub(crate) fn as_bytes<'v>(value: &'v Value) -> Option<&'v [u8]> {
+    match value {
+        Value::Bytes(b) => Some(b),
+        Value::Str(s) => Some(s.as_bytes()),
+        Value::String(s) => Some(s.as_bytes()),
+        _ => None,
+    }
+}
+
+// bytes that are text are a string like any other
+pub(crate) fn rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) | Value::BigInt(_) | Value::NonFinite(_) => 2,
+        Value::Bytes(b) if std::str::from_utf8(b).is_err() => 4,
+        Value::Bytes(_) | Value::Str(_) | Value::String(_) => 3,
+        Value::Array(_) => 5,
+        Value::Object(_) => 6,
+    }
+}
+
+pub(crate) fn compare_numbers(a: &Number, b: &Number) -> Ordering {
+    let integer = |n: &Number| n.as_i64().map(i128::from).or_else(|| n.as_u64().map(i128::from));
+    let float = |n: &Number| n.as_f64().unwrap_or(f64::NAN);
+    match (integer(a), integer(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(a), None) => compare_integer_float(a, float(b)),
+        (None, Some(b)) => compare_integer_float(b, float(a)).reverse(),
+        (None, None) => float(a).total_cmp(&float(b)),
+    }
+}
+
+// exactly, where `a as f64` would round and make the order intransitive, and with `-0.0`
+// below `0` as `total_cmp` has it below `0.0`
+fn compare_integer_float(a: i128, b: f64) -> Ordering {
+    if b.is_nan() {
+        return if b.is_sign_negative() { Ordering::Greater } else { Ordering::Less };
+    }
+    // beyond every i64 and u64, infinities included
+    if b >= 18446744073709551616.0 {
+        return Ordering::Less;
+    }
+    if b < -9223372036854775808.0 {
+        return Ordering::Greater;
+    }
+    let whole = b.trunc();
+    a.cmp(&(whole as i128)).then_with(|| match b - whole {
+        fraction if fraction > 0.0 => Ordering::Less,
+        fraction if fraction < 0.0 => Ordering::Greater,
+        _ if b.is_sign_negative() && a == 0 => Ordering::Greater,
+        _ => Ordering::Equal,
+    })
+}
+
+// integers beyond 64 bits, by sign then length then digits, JSON has no leading zeros
+fn compare_numerals(a: &str, b: &str) -> Ordering {
+    let (a_negative, b_negative) = (a.starts_with('-'), b.starts_with('-'));
+    let (a, b) = (a.trim_start_matches('-'), b.trim_start_matches('-'));
+    match (a_negative, b_negative) {
+        (false, true) => Ordering::Greater,
+        (true, false) => Ordering::Less,
+        (negative, _) => {
+            let magnitude = a.len().cmp(&b.len()).then_with(|| a.cmp(b));
+            if negative { magnitude.reverse() } else { magnitude }
+        }
+    }
+}
+
+// exactly too: every float that isn't whole is within 64 bits, those beyond are whole and
+// written out in full
+fn compare_numeral_float(a: &str, b: f64) -> Ordering {
+    if b.is_nan() {
+        return if b.is_sign_negative() { Ordering::Greater } else { Ordering::Less };
+    }
+    if b.is_infinite() {
+        return if b > 0.0 { Ordering::Less } else { Ordering::Greater };
+    }
+    let whole = b.trunc();
+    // `-0` for a negative fraction and `-0.0` both, which puts `0` above them
+    compare_numerals(a, &format!("{:.0}", whole)).then_with(|| match b - whole {
+        fraction if fraction > 0.0 => Ordering::Less,
+        fraction if fraction < 0.0 => Ordering::Greater,
+        _ => Ordering::Equal,
+    })
+}
+
+fn compare_numeral_number(a: &str, b: &Number) -> Ordering {
+    match (b.as_i64(), b.as_u64()) {
+        (Some(b), _) => compare_numerals(a, &b.to_string()),
+        (_, Some(b)) => compare_numerals(a, &b.to_string()),
+        _ => compare_numeral_float(a, b.as_f64().unwrap_or(f64::NAN)),
+    }
+}
+
+pub(crate) fn big_int_f64(numeral: &str) -> f64 {
+    numeral.parse().unwrap_or(f64::NAN)
+}
+
+/// A total order over values: null, booleans, numbers, strings, bytes that aren't utf-8,
+/// arrays then objects. Numbers compare by value, strings bytewise whichever way they are
+/// held, containers element by element. It's the order of [`Ord`] for `Value`.
+pub fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => compare_numbers(a, b),
+        (Value::BigInt(a), Value::BigInt(b)) => compare_numerals(a, b),
+        (Value::BigInt(a), Value::Number(b)) => compare_numeral_number(a, b),
+        (Value::Number(a), Value::BigInt(b)) => compare_numeral_number(b, a).reverse(),
+        (Value::NonFinite(a), Value::NonFinite(b)) => a.as_f64().total_cmp(&b.as_f64()),
+        (Value::NonFinite(a), Value::Number(b)) => a.as_f64().total_cmp(&b.as_f64().unwrap_or(f64::NAN)),
+        (Value::Number(a), Value::NonFinite(b)) => a.as_f64().unwrap_or(f64::NAN).total_cmp(&b.as_f64()),
+        (Value::NonFinite(a), Value::BigInt(b)) => compare_numeral_float(b, a.as_f64()).reverse(),
+        (Value::BigInt(a), Value::NonFinite(b)) => compare_numeral_float(a, b.as_f64()),
+        (Value::Array(a), Value::Array(b)) => {
+            a.iter().zip(b).map(|(a, b)| compare(a, b)).find(|o| o.is_ne()).unwrap_or_else(|| a.len().cmp(&b.len()))
+        }
+        (Value::Object(a), Value::Object(b)) => a
+            .iter()
+            .zip(b)
+            .map(|((ka, va), (kb, vb))| ka.cmp(kb).then_with(|| compare(va, vb)))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (a, b) => rank(a).cmp(&rank(b)).then_with(|| as_bytes(a).cmp(&as_bytes(b))),
+    }
+}
+
+/// Combinators for list-shaped values. They rearrange the elements in place without copying
+/// them, on anything but an array they do nothing.
+impl<'a> Value<'a> {
+    fn elements(&mut self) -> Option<&mut Vec<Value<'a>>> {
+        match self {
+            Value::Array(vec) => Some(vec),
+            _ => None,
+        }
+    }
+
+    /// Stable sort by the value at `pointer` in each element, elements without one go last.
+    pub fn sort_by_pointer(&mut self, pointer: &str) -> &mut Self {
+        if let Some(vec) = self.elements() {
+            vec.sort_by(|a, b| match (a.pointer(pointer), b.pointer(pointer)) {
+                (Some(a), Some(b)) => compare(a, b),
+                (a, b) => b.is_some().cmp(&a.is_some()),
+            });
+        }
+        self
+    }
+
+    /// Like [`Value::sort_by_pointer`] but largest first, elements without one still go last.
+    pub fn sort_by_pointer_desc(&mut self, pointer: &str) -> &mut Self {
+        if let Some(vec) = self.elements() {
+            vec.sort_by(|a, b| match (a.pointer(pointer), b.pointer(pointer)) {
+                (Some(a), Some(b)) => compare(b, a),
+                (a, b) => b.is_some().cmp(&a.is_some()),
+            });
+        }
+        self
+    }
+
+    pub fn filter<F>(&mut self, mut f: F) -> &mut Self
+        where
+            F: FnMut(&Value<'a>) -> bool,
+    {
+        if let Some(vec) = self.elements() {
+            vec.retain(|v| f(v));
+        }
+        self
+    }
+
+    pub fn take(&mut self, n: usize) -> &mut Self {
+        if let Some(vec) = self.elements() {
+            vec.truncate(n);
+        }
+        self
+    }
+
+    pub fn skip(&mut self, n: usize) -> &mut Self {
+        if let Some(vec) = self.elements() {
+            vec.drain(..n.min(vec.len()));
+        }
+        self
+    }
+
+    /// Keeps the first element for each distinct value at `pointer`, elements without one
+    /// count as having null.
+    pub fn distinct_by_pointer(&mut self, pointer: &str) -> &mut Self {
+        if let Some(vec) = self.elements() {
+            let mut seen = HashSet::new();
+            vec.retain(|v| {
+                let key = v.pointer(pointer).map_or_else(|| b"null".to_vec(), |v| serde_json_nostr::to_vec(v).unwrap_or_default());
+                seen.insert(key)
+            });
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+
+    fn names(value: &Value) -> Vec<String> {
+        match value {
+            Value::Array(vec) => vec.iter().map(|v| serde_json_nostr::to_string(v.pointer("/name").unwrap_or(&Value::Null)).unwrap()).collect(),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn array_sort_and_slice() {
+        let input = br#"[{"name":"a","price":3},{"name":"b","price":1.5},{"name":"c"},{"name":"d","price":10},{"name":"e","price":1.5}]"#;
+        let mut value: Value = serde_json_nostr::from_slice(input).unwrap();
+        value.sort_by_pointer("/price");
+        assert_eq!(names(&value), vec![r#""b""#, r#""e""#, r#""a""#, r#""d""#, r#""c""#]);
+        value.sort_by_pointer_desc("/price").skip(1).take(2);
+        assert_eq!(names(&value), vec![r#""a""#, r#""b""#]);
+        match &value {
+            Value::Array(vec) => match vec[0].pointer("/name") {
+                Some(Value::Bytes(name)) => assert!(input.as_ptr_range().contains(&name.as_ptr())),
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+        assert_eq!(value.clone().skip(10), &Value::Array(vec![]));
+        assert_eq!(Value::Bool(true).take(0), &Value::Bool(true));
+    }
+
+    #[test]
+    fn array_filter_and_distinct() {
+        let input = r#"[{"name":"a","tag":"x"},{"name":"b","tag":"y"},{"name":"c","tag":"x"},{"name":"d"},{"name":"e","tag":null}]"#;
+        let mut value: Value = serde_json_nostr::from_str(input).unwrap();
+        value.distinct_by_pointer("/tag");
+        assert_eq!(names(&value), vec![r#""a""#, r#""b""#, r#""d""#]);
+        value.filter(|v| v.pointer("/tag").is_some());
+        assert_eq!(names(&value), vec![r#""a""#, r#""b""#]);
+    }
+
+    #[test]
+    fn integers_and_floats_compare_exactly() {
+        let (above, at): (Value, Value) = (Value::Number(9007199254740993i64.into()), Value::Number(9007199254740992i64.into()));
+        let float = |f: f64| Value::Number(serde_json::Number::from_f64(f).unwrap());
+        assert!(above > float(9007199254740992.0));
+        assert_eq!(at, float(9007199254740992.0));
+        assert!(above > at);
+        assert!(Value::Number(u64::MAX.into()) < float(18446744073709551616.0));
+        assert!(Value::Number(i64::MIN.into()) == float(-9223372036854775808.0));
+        assert!(Value::Number(1.into()) < float(1.5) && Value::Number((-1).into()) > float(-1.5));
+        assert!(Value::Number(0.into()) > float(-0.0) && float(-0.0) < float(0.0));
+    }
+
+    #[test]
+    fn big_integers_compare_exactly() {
+        let big = |numeral: &'static str| Value::BigInt(numeral.into());
+        let float = |f: f64| Value::Number(serde_json::Number::from_f64(f).unwrap());
+        // all three round to the same float
+        let (max, below) = (Value::Number(u64::MAX.into()), Value::Number((u64::MAX - 1).into()));
+        assert!(below < max && max < big("18446744073709551616"));
+        assert!(big("-9223372036854775809") < Value::Number(i64::MIN.into()));
+        assert_eq!(big("100000000000000000000"), float(1e20));
+        assert!(big("100000000000000000001") > float(1e20));
+        assert!(big("99999999999999999999") < float(1e20));
+        assert!(big("-100000000000000000001") < float(-1e20));
+        assert!(big("18446744073709551616") > float(1.5) && big("-18446744073709551616") < float(-1.5));
+        // past the largest float there's still an infinity above
+        let huge = Value::BigInt(format!("1{}", "0".repeat(400)).into());
+        assert!(huge > float(f64::MAX) && huge < Value::NonFinite(crate::NonFinite::Infinity));
+        assert!(big("-18446744073709551616") > Value::NonFinite(crate::NonFinite::NegInfinity));
+        let mut values = [big("100000000000000000001"), float(1e20), big("100000000000000000000"), big("99999999999999999999")];
+        values.sort();
+        assert!(values.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(values[0], big("99999999999999999999"));
+        assert_eq!(values[3], big("100000000000000000001"));
+    }
+}
@@ -0,0 +1,289 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use arrow_array::builder::{BinaryBuilder, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder, UInt64Builder};
+use arrow_array::{ArrayRef, NullArray, RecordBatch};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use crate::Value;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ArrowOptions {
+    /// How many leading rows the schema is inferred from.
+    pub infer_rows: usize,
+    pub batch_size: usize,
+}
+
+impl Default for ArrowOptions {
+    fn default() -> Self {
+        ArrowOptions { infer_rows: 100, batch_size: 8192 }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Arrow(ArrowError),
+    NotAnArray,
+    NotAnObject { row: usize },
+    /// Only flat objects convert, nested arrays and objects don't have a column type.
+    Nested { row: usize, field: String },
+    /// The field holds values of incompatible types within the inferred rows.
+    MixedTypes { field: String },
+    /// A row after the inferred ones doesn't fit the schema.
+    Mismatch { row: usize, field: String, expected: DataType },
+    UnknownField { row: usize, field: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Arrow(err) => err.fmt(f),
+            Error::NotAnArray => f.write_str("expected an array of objects"),
+            Error::NotAnObject { row } => write!(f, "row {} is not an object", row),
+            Error::Nested { row, field } => write!(f, "row {} has a nested value in field {}", row, field),
+            Error::MixedTypes { field } => write!(f, "field {} has values of incompatible types", field),
+            Error::Mismatch { row, field, expected } => {
+                write!(f, "row {} field {} doesn't match the inferred type {}", row, field, expected)
+            }
+            Error::UnknownField { row, field } => write!(f, "row {} has field {} which isn't in the schema", row, field),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ArrowError> for Error {
+    fn from(err: ArrowError) -> Self {
+        Error::Arrow(err)
+    }
+}
+
+fn rows<'v, 'a>(value: &'v Value<'a>) -> Result<&'v [Value<'a>], Error> {
+    match value {
+        Value::Array(rows) => Ok(rows),
+        _ => Err(Error::NotAnArray),
+    }
+}
+
+fn object<'v, 'a>(row: &'v Value<'a>, i: usize) -> Result<&'v BTreeMap<Cow<'a, str>, Value<'a>>, Error> {
+    match row {
+        Value::Object(map) => Ok(map),
+        _ => Err(Error::NotAnObject { row: i }),
+    }
+}
+
+fn data_type(value: &Value, row: usize, field: &str) -> Result<DataType, Error> {
+    Ok(match value {
+        Value::Null => DataType::Null,
+        Value::Bool(_) => DataType::Boolean,
+        Value::Number(n) if n.is_i64() => DataType::Int64,
+        Value::Number(n) if n.is_u64() => DataType::UInt64,
+        Value::Number(_) | Value::NonFinite(_) => DataType::Float64,
+        // too wide for any integer column, kept exact as its numeral
+        Value::BigInt(_) => DataType::Utf8,
+        // strings parsed without escapes come out as bytes
+        Value::Bytes(b) if std::str::from_utf8(b).is_ok() => DataType::Utf8,
+        Value::Bytes(_) => DataType::Binary,
+        Value::Str(_) | Value::String(_) => DataType::Utf8,
+        Value::Array(_) | Value::Object(_) => return Err(Error::Nested { row, field: field.to_string() }),
+    })
+}
+
+// integers widen to floats and strings to binary, anything else has to agree
+fn merge(a: DataType, b: DataType, field: &str) -> Result<DataType, Error> {
+    use DataType::*;
+    Ok(match (a, b) {
+        (a, b) if a == b => a,
+        (Null, t) | (t, Null) => t,
+        (Int64 | UInt64 | Float64, Int64 | UInt64 | Float64) => Float64,
+        (Utf8 | Binary, Utf8 | Binary) => Binary,
+        _ => return Err(Error::MixedTypes { field: field.to_string() }),
+    })
+}
+
+/// Infers the schema of an array of flat objects from its first `infer_rows` rows. Columns
+/// are sorted by name and always nullable, a field missing from a row is a null.
+pub fn infer_schema(value: &Value, infer_rows: usize) -> Result<Schema, Error> {
+    let mut columns: BTreeMap<&str, DataType> = BTreeMap::new();
+    for (i, row) in rows(value)?.iter().take(infer_rows).enumerate() {
+        for (field, value) in object(row, i)? {
+            let field = field.as_ref();
+            let t = data_type(value, i, field)?;
+            let merged = match columns.remove(field) {
+                Some(existing) => merge(existing, t, field)?,
+                None => t,
+            };
+            columns.insert(field, merged);
+        }
+    }
+    Ok(Schema::new(
+        columns
+            .into_iter()
+            .map(|(name, t)| Field::new(name, t, true))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+enum Column {
+    Null(usize),
+    Bool(BooleanBuilder),
+    Int(Int64Builder),
+    UInt(UInt64Builder),
+    Float(Float64Builder),
+    Utf8(StringBuilder),
+    Binary(BinaryBuilder),
+}
+
+impl Column {
+    fn new(t: &DataType, capacity: usize) -> Column {
+        match t {
+            DataType::Boolean => Column::Bool(BooleanBuilder::with_capacity(capacity)),
+            DataType::Int64 => Column::Int(Int64Builder::with_capacity(capacity)),
+            DataType::UInt64 => Column::UInt(UInt64Builder::with_capacity(capacity)),
+            DataType::Float64 => Column::Float(Float64Builder::with_capacity(capacity)),
+            DataType::Utf8 => Column::Utf8(StringBuilder::new()),
+            DataType::Binary => Column::Binary(BinaryBuilder::new()),
+            _ => Column::Null(0),
+        }
+    }
+
+    // false if the value doesn't fit the column
+    fn append(&mut self, value: Option<&Value>) -> bool {
+        match (self, value) {
+            (Column::Null(len), None | Some(Value::Null)) => *len += 1,
+            (Column::Bool(b), None | Some(Value::Null)) => b.append_null(),
+            (Column::Int(b), None | Some(Value::Null)) => b.append_null(),
+            (Column::UInt(b), None | Some(Value::Null)) => b.append_null(),
+            (Column::Float(b), None | Some(Value::Null)) => b.append_null(),
+            (Column::Utf8(b), None | Some(Value::Null)) => b.append_null(),
+            (Column::Binary(b), None | Some(Value::Null)) => b.append_null(),
+            (Column::Bool(b), Some(Value::Bool(v))) => b.append_value(*v),
+            (Column::Int(b), Some(Value::Number(n))) if n.is_i64() => b.append_option(n.as_i64()),
+            (Column::UInt(b), Some(Value::Number(n))) if n.is_u64() => b.append_option(n.as_u64()),
+            (Column::Float(b), Some(Value::Number(n))) => b.append_option(n.as_f64()),
+            (Column::Float(b), Some(Value::NonFinite(f))) => b.append_value(f.as_f64()),
+            (Column::Utf8(b), Some(Value::Str(s))) => b.append_value(s),
+            (Column::Utf8(b), Some(Value::String(s))) => b.append_value(s),
+            (Column::Utf8(b), Some(Value::BigInt(n))) => b.append_value(n),
+            (Column::Utf8(b), Some(Value::Bytes(v))) => match std::str::from_utf8(v) {
+                Ok(s) => b.append_value(s),
+                Err(_) => return false,
+            },
+            (Column::Binary(b), Some(Value::Bytes(v))) => b.append_value(v),
+            (Column::Binary(b), Some(Value::Str(s))) => b.append_value(s),
+            (Column::Binary(b), Some(Value::String(s))) => b.append_value(s),
+            _ => return false,
+        }
+        true
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            Column::Null(len) => Arc::new(NullArray::new(std::mem::take(len))),
+            Column::Bool(b) => Arc::new(b.finish()),
+            Column::Int(b) => Arc::new(b.finish()),
+            Column::UInt(b) => Arc::new(b.finish()),
+            Column::Float(b) => Arc::new(b.finish()),
+            Column::Utf8(b) => Arc::new(b.finish()),
+            Column::Binary(b) => Arc::new(b.finish()),
+        }
+    }
+}
+
+/// Converts an array of flat objects into record batches of at most `batch_size` rows each,
+/// sharing a schema inferred by [`infer_schema`].
+pub fn to_record_batches(value: &Value, options: ArrowOptions) -> Result<Vec<RecordBatch>, Error> {
+    let schema: SchemaRef = Arc::new(infer_schema(value, options.infer_rows)?);
+    to_record_batches_with(value, schema, options.batch_size)
+}
+
+/// Same as [`to_record_batches`] with a known schema, e.g. to keep later payloads consistent
+/// with the batches written for earlier ones.
+pub fn to_record_batches_with(value: &Value, schema: SchemaRef, batch_size: usize) -> Result<Vec<RecordBatch>, Error> {
+    let batch_size = batch_size.max(1);
+    let mut columns: Vec<Column> = schema
+        .fields()
+        .iter()
+        .map(|field| Column::new(field.data_type(), batch_size))
+        .collect();
+    let mut batches = Vec::new();
+    let flush = |columns: &mut Vec<Column>, batches: &mut Vec<RecordBatch>| -> Result<(), Error> {
+        let arrays = columns.iter_mut().map(Column::finish).collect();
+        batches.push(RecordBatch::try_new(schema.clone(), arrays)?);
+        Ok(())
+    };
+
+    let mut pending = 0;
+    for (i, row) in rows(value)?.iter().enumerate() {
+        let map = object(row, i)?;
+        if let Some(field) = map.keys().find(|k| schema.index_of(k).is_err()) {
+            return Err(Error::UnknownField { row: i, field: field.to_string() });
+        }
+        for (field, column) in schema.fields().iter().zip(columns.iter_mut()) {
+            if !column.append(map.get(field.name().as_str())) {
+                return Err(Error::Mismatch { row: i, field: field.name().clone(), expected: field.data_type().clone() });
+            }
+        }
+        pending += 1;
+        if pending == batch_size {
+            flush(&mut columns, &mut batches)?;
+            pending = 0;
+        }
+    }
+    if pending > 0 || batches.is_empty() {
+        flush(&mut columns, &mut batches)?;
+    }
+    Ok(batches)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, Int64Type};
+    use arrow_schema::DataType;
+    use crate::Value;
+    use super::{to_record_batches, ArrowOptions, Error};
+
+    #[test]
+    fn arrow_infers_columns() {
+        let input = br#"[
+            {"id": 1, "name": "John", "price": 10, "tags": null},
+            {"id": 2, "name": "Jane", "price": 2.5},
+            {"id": 3, "name": "Ja\"ne", "price": null, "tags": null}
+        ]"#;
+        let value: Value = serde_json_nostr::from_slice(input).unwrap();
+        let batches = to_record_batches(&value, ArrowOptions { infer_rows: 3, batch_size: 2 }).unwrap();
+        assert_eq!(batches.len(), 2);
+        let schema = batches[0].schema();
+        let types: Vec<(&str, &DataType)> = schema.fields().iter().map(|f| (f.name().as_str(), f.data_type())).collect();
+        assert_eq!(
+            types,
+            vec![("id", &DataType::Int64), ("name", &DataType::Utf8), ("price", &DataType::Float64), ("tags", &DataType::Null)]
+        );
+        assert_eq!(batches[0].num_rows(), 2);
+        assert_eq!(batches[1].num_rows(), 1);
+        let prices = batches[0].column(2).as_primitive::<Float64Type>();
+        assert_eq!((prices.value(0), prices.value(1)), (10.0, 2.5));
+        assert_eq!(batches[1].column(0).as_primitive::<Int64Type>().value(0), 3);
+        assert_eq!(batches[1].column(1).as_string::<i32>().value(0), "Ja\"ne");
+        assert_eq!(batches[1].column(3).logical_null_count(), 1);
+    }
+
+    #[test]
+    fn arrow_errors() {
+        let parse = |s: &'static str| -> Value<'static> { serde_json_nostr::from_str(s).unwrap() };
+        let options = ArrowOptions { infer_rows: 1, batch_size: 10 };
+        assert!(matches!(to_record_batches(&parse("{}"), options), Err(Error::NotAnArray)));
+        assert!(matches!(to_record_batches(&parse("[1]"), options), Err(Error::NotAnObject { row: 0 })));
+        assert!(matches!(to_record_batches(&parse(r#"[{"a":[1]}]"#), options), Err(Error::Nested { row: 0, .. })));
+        assert!(matches!(
+            to_record_batches(&parse(r#"[{"a":1},{"a":"x"}]"#), ArrowOptions::default()),
+            Err(Error::MixedTypes { .. })
+        ));
+        assert!(matches!(
+            to_record_batches(&parse(r#"[{"a":1},{"a":"x"}]"#), options),
+            Err(Error::Mismatch { row: 1, expected: DataType::Int64, .. })
+        ));
+        assert!(matches!(to_record_batches(&parse(r#"[{"a":1},{"b":1}]"#), options), Err(Error::UnknownField { row: 1, .. })));
+    }
+}
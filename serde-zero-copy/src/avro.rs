@@ -0,0 +1,510 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use crate::Value;
+
+/// An Avro schema, named types are inlined where they are referenced so recursive types
+/// aren't supported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AvroSchema {
+    Null,
+    Boolean,
+    Int,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    String,
+    Array(Box<AvroSchema>),
+    Map(Box<AvroSchema>),
+    Record { name: String, fields: Vec<(String, AvroSchema)> },
+    Enum { name: String, symbols: Vec<String> },
+    Fixed { name: String, size: usize },
+    Union(Vec<AvroSchema>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The schema JSON isn't valid or uses something unsupported.
+    Schema(String),
+    /// The value at `pointer` doesn't fit the schema, `expected` names the Avro type.
+    Mismatch { pointer: String, expected: String },
+    MissingField { pointer: String },
+    UnknownSymbol { pointer: String },
+    /// Input ended while decoding the value at `pointer`.
+    Eof { pointer: String },
+    InvalidUtf8 { pointer: String },
+    /// A union branch or enum index out of range, or a negative length.
+    InvalidIndex { pointer: String },
+    TrailingBytes,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Schema(reason) => write!(f, "invalid avro schema: {}", reason),
+            Error::Mismatch { pointer, expected } => write!(f, "expected {} at '{}'", expected, pointer),
+            Error::MissingField { pointer } => write!(f, "missing record field '{}'", pointer),
+            Error::UnknownSymbol { pointer } => write!(f, "unknown enum symbol at '{}'", pointer),
+            Error::Eof { pointer } => write!(f, "unexpected end of input at '{}'", pointer),
+            Error::InvalidUtf8 { pointer } => write!(f, "invalid utf-8 string at '{}'", pointer),
+            Error::InvalidIndex { pointer } => write!(f, "invalid index or length at '{}'", pointer),
+            Error::TrailingBytes => f.write_str("trailing bytes after avro datum"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl AvroSchema {
+    /// Parses a schema from its JSON form, e.g. `{"type": "array", "items": "long"}`.
+    pub fn parse_str(schema: &str) -> Result<AvroSchema, Error> {
+        let json: serde_json::Value = serde_json::from_str(schema).map_err(|err| Error::Schema(err.to_string()))?;
+        AvroSchema::from_json(&json, &mut HashMap::new())
+    }
+
+    fn from_json(json: &serde_json::Value, named: &mut HashMap<String, AvroSchema>) -> Result<AvroSchema, Error> {
+        use serde_json::Value as Json;
+        let invalid = |reason: &str| Error::Schema(reason.to_string());
+        let object = match json {
+            Json::String(name) => return match name.as_str() {
+                "null" => Ok(AvroSchema::Null),
+                "boolean" => Ok(AvroSchema::Boolean),
+                "int" => Ok(AvroSchema::Int),
+                "long" => Ok(AvroSchema::Long),
+                "float" => Ok(AvroSchema::Float),
+                "double" => Ok(AvroSchema::Double),
+                "bytes" => Ok(AvroSchema::Bytes),
+                "string" => Ok(AvroSchema::String),
+                name => named.get(name).cloned().ok_or_else(|| Error::Schema(format!("unknown type {}", name))),
+            },
+            Json::Array(branches) => {
+                return branches
+                    .iter()
+                    .map(|branch| AvroSchema::from_json(branch, named))
+                    .collect::<Result<_, _>>()
+                    .map(AvroSchema::Union);
+            }
+            Json::Object(object) => object,
+            _ => return Err(invalid("expected a type name, union or object")),
+        };
+        let name = || {
+            object
+                .get("name")
+                .and_then(Json::as_str)
+                .map(str::to_string)
+                .ok_or_else(|| invalid("named type without a name"))
+        };
+        let schema = match object.get("type").ok_or_else(|| invalid("object without a type"))? {
+            Json::String(t) if t == "array" => {
+                AvroSchema::Array(Box::new(AvroSchema::from_json(object.get("items").ok_or_else(|| invalid("array without items"))?, named)?))
+            }
+            Json::String(t) if t == "map" => {
+                AvroSchema::Map(Box::new(AvroSchema::from_json(object.get("values").ok_or_else(|| invalid("map without values"))?, named)?))
+            }
+            Json::String(t) if t == "record" => {
+                let name = name()?;
+                let fields = object.get("fields").and_then(Json::as_array).ok_or_else(|| invalid("record without fields"))?;
+                let fields = fields
+                    .iter()
+                    .map(|field| {
+                        let field_name = field.get("name").and_then(Json::as_str).ok_or_else(|| invalid("field without a name"))?;
+                        let field_type = field.get("type").ok_or_else(|| invalid("field without a type"))?;
+                        Ok((field_name.to_string(), AvroSchema::from_json(field_type, named)?))
+                    })
+                    .collect::<Result<_, Error>>()?;
+                AvroSchema::Record { name, fields }
+            }
+            Json::String(t) if t == "enum" => {
+                let symbols = object.get("symbols").and_then(Json::as_array).ok_or_else(|| invalid("enum without symbols"))?;
+                let symbols = symbols
+                    .iter()
+                    .map(|s| s.as_str().map(str::to_string).ok_or_else(|| invalid("non-string enum symbol")))
+                    .collect::<Result<_, _>>()?;
+                AvroSchema::Enum { name: name()?, symbols }
+            }
+            Json::String(t) if t == "fixed" => {
+                let size = object.get("size").and_then(Json::as_u64).ok_or_else(|| invalid("fixed without a size"))?;
+                AvroSchema::Fixed { name: name()?, size: size as usize }
+            }
+            // primitives spelled as objects, possibly with a logicalType we don't interpret
+            other => AvroSchema::from_json(other, named)?,
+        };
+        if let AvroSchema::Record { name, .. } | AvroSchema::Enum { name, .. } | AvroSchema::Fixed { name, .. } = &schema {
+            named.insert(name.clone(), schema.clone());
+        }
+        Ok(schema)
+    }
+
+    fn type_name(&self) -> String {
+        match self {
+            AvroSchema::Null => "null".to_string(),
+            AvroSchema::Boolean => "boolean".to_string(),
+            AvroSchema::Int => "int".to_string(),
+            AvroSchema::Long => "long".to_string(),
+            AvroSchema::Float => "float".to_string(),
+            AvroSchema::Double => "double".to_string(),
+            AvroSchema::Bytes => "bytes".to_string(),
+            AvroSchema::String => "string".to_string(),
+            AvroSchema::Array(_) => "array".to_string(),
+            AvroSchema::Map(_) => "map".to_string(),
+            AvroSchema::Record { name, .. } | AvroSchema::Enum { name, .. } | AvroSchema::Fixed { name, .. } => name.clone(),
+            AvroSchema::Union(branches) => {
+                let names: Vec<String> = branches.iter().map(AvroSchema::type_name).collect();
+                format!("one of [{}]", names.join(", "))
+            }
+        }
+    }
+}
+
+// `/a/0/b~1c`, as in RFC 6901
+fn child(pointer: &str, token: &str) -> String {
+    format!("{}/{}", pointer, token.replace('~', "~0").replace('/', "~1"))
+}
+
+fn write_long(out: &mut Vec<u8>, n: i64) {
+    let mut zigzag = ((n << 1) ^ (n >> 63)) as u64;
+    while zigzag >= 0x80 {
+        out.push(zigzag as u8 | 0x80);
+        zigzag >>= 7;
+    }
+    out.push(zigzag as u8);
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_long(out, bytes.len() as i64);
+    out.extend_from_slice(bytes);
+}
+
+fn encode(out: &mut Vec<u8>, value: &Value, schema: &AvroSchema, pointer: &str) -> Result<(), Error> {
+    let mismatch = || Error::Mismatch { pointer: pointer.to_string(), expected: schema.type_name() };
+    match (schema, value) {
+        (AvroSchema::Null, Value::Null) => {}
+        (AvroSchema::Boolean, Value::Bool(b)) => out.push(*b as u8),
+        (AvroSchema::Int, Value::Number(n)) => {
+            let n = n.as_i64().filter(|n| i32::try_from(*n).is_ok()).ok_or_else(mismatch)?;
+            write_long(out, n);
+        }
+        (AvroSchema::Long, Value::Number(n)) => write_long(out, n.as_i64().ok_or_else(mismatch)?),
+        (AvroSchema::Float, Value::Number(n)) => out.extend_from_slice(&(n.as_f64().ok_or_else(mismatch)? as f32).to_le_bytes()),
+        (AvroSchema::Double, Value::Number(n)) => out.extend_from_slice(&n.as_f64().ok_or_else(mismatch)?.to_le_bytes()),
+        (AvroSchema::Float, Value::NonFinite(f)) => out.extend_from_slice(&(f.as_f64() as f32).to_le_bytes()),
+        (AvroSchema::Double, Value::NonFinite(f)) => out.extend_from_slice(&f.as_f64().to_le_bytes()),
+        (AvroSchema::Bytes, Value::Bytes(b)) => write_bytes(out, b),
+        (AvroSchema::Bytes | AvroSchema::String, Value::Str(s)) => write_bytes(out, s.as_bytes()),
+        (AvroSchema::Bytes | AvroSchema::String, Value::String(s)) => write_bytes(out, s.as_bytes()),
+        // strings parsed without escapes come out as bytes
+        (AvroSchema::String, Value::Bytes(b)) if std::str::from_utf8(b).is_ok() => write_bytes(out, b),
+        (AvroSchema::Fixed { size, .. }, Value::Bytes(b)) if b.len() == *size => out.extend_from_slice(b),
+        (AvroSchema::Array(items), Value::Array(vec)) => {
+            if !vec.is_empty() {
+                write_long(out, vec.len() as i64);
+                for (i, item) in vec.iter().enumerate() {
+                    encode(out, item, items, &child(pointer, &i.to_string()))?;
+                }
+            }
+            write_long(out, 0);
+        }
+        (AvroSchema::Map(values), Value::Object(map)) => {
+            if !map.is_empty() {
+                write_long(out, map.len() as i64);
+                for (k, v) in map {
+                    write_bytes(out, k.as_bytes());
+                    encode(out, v, values, &child(pointer, k))?;
+                }
+            }
+            write_long(out, 0);
+        }
+        (AvroSchema::Record { fields, .. }, Value::Object(map)) => {
+            for (name, field) in fields {
+                let pointer = child(pointer, name);
+                match map.get(name.as_str()) {
+                    Some(v) => encode(out, v, field, &pointer)?,
+                    // an absent field is fine where null is
+                    None => encode(out, &Value::Null, field, &pointer).map_err(|_| Error::MissingField { pointer })?,
+                }
+            }
+        }
+        (AvroSchema::Enum { symbols, .. }, value) => {
+            let symbol = match value {
+                Value::Str(s) => *s,
+                Value::String(s) => s.as_str(),
+                Value::Bytes(b) => std::str::from_utf8(b).map_err(|_| mismatch())?,
+                _ => return Err(mismatch()),
+            };
+            let index = symbols
+                .iter()
+                .position(|s| s == symbol)
+                .ok_or_else(|| Error::UnknownSymbol { pointer: pointer.to_string() })?;
+            write_long(out, index as i64);
+        }
+        (AvroSchema::Union(branches), value) => {
+            // the first branch the value fits, errors inside a single candidate are more useful
+            // than a generic mismatch so they are kept when nothing else fits
+            let mut last = None;
+            for (i, branch) in branches.iter().enumerate() {
+                let mut buf = Vec::new();
+                write_long(&mut buf, i as i64);
+                match encode(&mut buf, value, branch, pointer) {
+                    Ok(()) => {
+                        out.extend_from_slice(&buf);
+                        return Ok(());
+                    }
+                    Err(err @ Error::Mismatch { .. }) if err_pointer(&err) == pointer => {}
+                    Err(err) => last = Some(err),
+                }
+            }
+            return Err(last.unwrap_or_else(mismatch));
+        }
+        _ => return Err(mismatch()),
+    }
+    Ok(())
+}
+
+fn err_pointer(err: &Error) -> &str {
+    match err {
+        Error::Mismatch { pointer, .. } => pointer,
+        _ => "",
+    }
+}
+
+/// Serializes `value` as a single Avro datum, without container or schema prefix.
+pub fn to_avro(value: &Value, schema: &AvroSchema) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    encode(&mut out, value, schema, "")?;
+    Ok(out)
+}
+
+// the fewest bytes a value of `schema` is encoded in
+fn min_len(schema: &AvroSchema) -> usize {
+    match schema {
+        AvroSchema::Null => 0,
+        AvroSchema::Float => 4,
+        AvroSchema::Double => 8,
+        AvroSchema::Fixed { size, .. } => *size,
+        AvroSchema::Record { fields, .. } => fields.iter().map(|(_, field)| min_len(field)).fold(0, usize::saturating_add),
+        _ => 1,
+    }
+}
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize, pointer: &str) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .input
+            .get(self.pos..self.pos.saturating_add(n))
+            .ok_or_else(|| Error::Eof { pointer: pointer.to_string() })?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn long(&mut self, pointer: &str) -> Result<i64, Error> {
+        let mut zigzag = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1, pointer)?[0];
+            zigzag |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64));
+            }
+        }
+        Err(Error::InvalidIndex { pointer: pointer.to_string() })
+    }
+
+    fn index(&mut self, pointer: &str) -> Result<usize, Error> {
+        usize::try_from(self.long(pointer)?).map_err(|_| Error::InvalidIndex { pointer: pointer.to_string() })
+    }
+
+    fn bytes(&mut self, pointer: &str) -> Result<&'a [u8], Error> {
+        let len = self.index(pointer)?;
+        self.take(len, pointer)
+    }
+
+    fn str(&mut self, pointer: &str) -> Result<&'a str, Error> {
+        std::str::from_utf8(self.bytes(pointer)?).map_err(|_| Error::InvalidUtf8 { pointer: pointer.to_string() })
+    }
+
+    // array and map blocks, a negative count is followed by the block's byte size. Items
+    // of `item_len` bytes at least have to fit the bytes left, so that a count doesn't make
+    // for more work than the input pays for; those of none are counted a byte each
+    fn block_len(&mut self, item_len: usize, pointer: &str) -> Result<usize, Error> {
+        let count = self.long(pointer)?;
+        if count < 0 {
+            self.long(pointer)?;
+        }
+        usize::try_from(count.unsigned_abs())
+            .ok()
+            .filter(|&count| count.saturating_mul(item_len.max(1)) <= self.input.len() - self.pos)
+            .ok_or_else(|| Error::InvalidIndex { pointer: pointer.to_string() })
+    }
+
+    fn value<'s: 'a>(&mut self, schema: &'s AvroSchema, pointer: &str) -> Result<Value<'a>, Error> {
+        Ok(match schema {
+            AvroSchema::Null => Value::Null,
+            AvroSchema::Boolean => Value::Bool(self.take(1, pointer)?[0] != 0),
+            AvroSchema::Int => {
+                let n = i32::try_from(self.long(pointer)?).map_err(|_| Error::Mismatch { pointer: pointer.to_string(), expected: "int".to_string() })?;
+                Value::Number(n.into())
+            }
+            AvroSchema::Long => Value::Number(self.long(pointer)?.into()),
+            AvroSchema::Float => {
+                let bytes = self.take(4, pointer)?.try_into().unwrap();
+                Value::from_f64(f32::from_le_bytes(bytes) as f64)
+            }
+            AvroSchema::Double => {
+                let bytes = self.take(8, pointer)?.try_into().unwrap();
+                Value::from_f64(f64::from_le_bytes(bytes))
+            }
+            AvroSchema::Bytes => Value::Bytes(self.bytes(pointer)?),
+            AvroSchema::String => Value::Str(self.str(pointer)?),
+            AvroSchema::Fixed { size, .. } => Value::Bytes(self.take(*size, pointer)?),
+            AvroSchema::Array(items) => {
+                let mut vec = Vec::new();
+                loop {
+                    let len = self.block_len(min_len(items), pointer)?;
+                    if len == 0 {
+                        break Value::Array(vec);
+                    }
+                    for _ in 0..len {
+                        let item = self.value(items, &child(pointer, &vec.len().to_string()))?;
+                        vec.push(item);
+                    }
+                }
+            }
+            AvroSchema::Map(values) => {
+                let mut map = BTreeMap::new();
+                loop {
+                    let len = self.block_len(1 + min_len(values), pointer)?;
+                    if len == 0 {
+                        break Value::Object(map);
+                    }
+                    for _ in 0..len {
+                        let key = self.str(pointer)?;
+                        let value = self.value(values, &child(pointer, key))?;
+                        map.insert(Cow::Borrowed(key), value);
+                    }
+                }
+            }
+            AvroSchema::Record { fields, .. } => {
+                let mut map = BTreeMap::new();
+                for (name, field) in fields {
+                    let value = self.value(field, &child(pointer, name))?;
+                    map.insert(Cow::Borrowed(name.as_str()), value);
+                }
+                Value::Object(map)
+            }
+            AvroSchema::Enum { symbols, .. } => {
+                let symbol = symbols.get(self.index(pointer)?).ok_or_else(|| Error::InvalidIndex { pointer: pointer.to_string() })?;
+                Value::Str(symbol)
+            }
+            AvroSchema::Union(branches) => {
+                let branch = branches.get(self.index(pointer)?).ok_or_else(|| Error::InvalidIndex { pointer: pointer.to_string() })?;
+                self.value(branch, pointer)?
+            }
+        })
+    }
+}
+
+/// Decodes a single Avro datum. Strings and bytes borrow from `input`, record field names
+/// and enum symbols from `schema`.
+pub fn from_avro_slice<'a>(input: &'a [u8], schema: &'a AvroSchema) -> Result<Value<'a>, Error> {
+    let mut decoder = Decoder { input, pos: 0 };
+    let value = decoder.value(schema, "")?;
+    if decoder.pos != input.len() {
+        return Err(Error::TrailingBytes);
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{from_avro_slice, to_avro, AvroSchema, Error};
+
+    const SCHEMA: &str = r#"{
+        "type": "record", "name": "User",
+        "fields": [
+            {"name": "id", "type": "long"},
+            {"name": "name", "type": "string"},
+            {"name": "email", "type": ["null", "string"]},
+            {"name": "role", "type": {"type": "enum", "name": "Role", "symbols": ["admin", "user"]}},
+            {"name": "scores", "type": {"type": "array", "items": "double"}},
+            {"name": "labels", "type": {"type": "map", "values": "string"}},
+            {"name": "manager", "type": ["null", "Role"]}
+        ]
+    }"#;
+
+    #[test]
+    fn avro_round_trip_borrows_strings() {
+        let schema = AvroSchema::parse_str(SCHEMA).unwrap();
+        let value: Value = serde_json_nostr::from_str(
+            r#"{"id":-3,"name":"Jane","role":"user","scores":[1.5,2],"labels":{"team":"core"},"manager":"admin"}"#,
+        )
+        .unwrap();
+        let encoded = to_avro(&value, &schema).unwrap();
+        let decoded = from_avro_slice(&encoded, &schema).unwrap();
+        assert_eq!(
+            serde_json_nostr::to_string(&decoded).unwrap(),
+            r#"{"email":null,"id":-3,"labels":{"team":"core"},"manager":"admin","name":"Jane","role":"user","scores":[1.5,2.0]}"#
+        );
+        let encoded_range = encoded.as_ptr_range();
+        match &decoded {
+            Value::Object(map) => match (&map["name"], &map["labels"]) {
+                (Value::Str(name), Value::Object(labels)) => {
+                    assert!(encoded_range.contains(&name.as_ptr()));
+                    assert!(encoded_range.contains(&labels.keys().next().unwrap().as_ptr()));
+                }
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn avro_errors_point_at_the_value() {
+        let schema = AvroSchema::parse_str(SCHEMA).unwrap();
+        let parse = |s: &'static str| -> Value<'static> { serde_json_nostr::from_str(s).unwrap() };
+        assert_eq!(
+            to_avro(&parse(r#"{"id":1,"name":"x","role":"user","scores":[1,"2"],"labels":{}}"#), &schema),
+            Err(Error::Mismatch { pointer: "/scores/1".to_string(), expected: "double".to_string() })
+        );
+        assert_eq!(
+            to_avro(&parse(r#"{"id":1,"name":"x","role":"guest","scores":[],"labels":{}}"#), &schema),
+            Err(Error::UnknownSymbol { pointer: "/role".to_string() })
+        );
+        assert_eq!(
+            to_avro(&parse(r#"{"id":1,"role":"user","scores":[],"labels":{}}"#), &schema),
+            Err(Error::MissingField { pointer: "/name".to_string() })
+        );
+        assert_eq!(
+            to_avro(&parse(r#"{"id":1,"name":"x","role":"user","scores":[],"labels":{"a/b":1}}"#), &schema),
+            Err(Error::Mismatch { pointer: "/labels/a~1b".to_string(), expected: "string".to_string() })
+        );
+        let encoded = to_avro(&parse(r#"{"id":1,"name":"x","role":"user","scores":[],"labels":{}}"#), &schema).unwrap();
+        assert_eq!(from_avro_slice(&encoded[..2], &schema), Err(Error::Eof { pointer: "/name".to_string() }));
+        assert!(matches!(AvroSchema::parse_str(r#"{"type": "record"}"#), Err(Error::Schema(_))));
+    }
+
+    #[test]
+    fn avro_decoding_is_bounded_by_the_input() {
+        let long = |n: i64| to_avro(&Value::Number(n.into()), &AvroSchema::Long).unwrap();
+        // a million nulls would take no bytes at all, as many doubles 8MB
+        for items in [AvroSchema::Null, AvroSchema::Double] {
+            let array = AvroSchema::Array(Box::new(items));
+            assert_eq!(from_avro_slice(&long(1_000_000), &array), Err(Error::InvalidIndex { pointer: String::new() }));
+        }
+        let doubles = AvroSchema::Array(Box::new(AvroSchema::Double));
+        let mut two = long(2);
+        two.extend([0; 16]);
+        two.extend(long(0));
+        assert_eq!(from_avro_slice(&two, &doubles), Ok(Value::Array(vec![Value::from_f64(0.0), Value::from_f64(0.0)])));
+        assert_eq!(
+            from_avro_slice(&long(1 << 40), &AvroSchema::Int),
+            Err(Error::Mismatch { pointer: String::new(), expected: "int".to_string() })
+        );
+        assert_eq!(from_avro_slice(&long(-1 << 31), &AvroSchema::Int), Ok(Value::Number((-1i64 << 31).into())));
+    }
+}
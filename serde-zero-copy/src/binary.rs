@@ -0,0 +1,109 @@
+use serde::ser::{Error, SerializeMap, SerializeSeq};
+use serde::{Serialize, Serializer};
+use crate::Value;
+
+/// How [`Value::Bytes`] is written, see [`Value::bytes`].
+///
+/// `serde_json_nostr` parses strings as `Bytes` of what's between their quotes, which
+/// `Raw` writes back as they were; the others are for values whose bytes are binary, e.g.
+/// fields deserialized with `serde_bytes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BytesPolicy {
+    /// By `serialize_bytes`, like a plain `serialize` does: `serde_json_nostr` writes them
+    /// as they are between quotes, `serde_json` as an array of numbers.
+    #[default]
+    Raw,
+    /// As a string in standard, padded base64.
+    Base64,
+    /// As an array of numbers, whichever the serializer.
+    Array,
+    /// Fail the serialization.
+    Error,
+}
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+        for _ in chunk.len()..3 {
+            encoded.push('=');
+        }
+    }
+    encoded
+}
+
+impl<'a> Value<'a> {
+    /// The value, to serialize with its bytes written as `policy` says.
+    pub fn bytes(&self, policy: BytesPolicy) -> WithBytes<'_, 'a> {
+        WithBytes { value: self, policy }
+    }
+}
+
+/// A value serializing its bytes by a [`BytesPolicy`], made by [`Value::bytes`].
+#[derive(Debug, Clone, Copy)]
+pub struct WithBytes<'v, 'a> {
+    value: &'v Value<'a>,
+    policy: BytesPolicy,
+}
+
+impl Serialize for WithBytes<'_, '_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+    {
+        let wrap = |value| WithBytes { value, policy: self.policy };
+        match (self.value, self.policy) {
+            (Value::Bytes(b), BytesPolicy::Raw) => serializer.serialize_bytes(b),
+            (Value::Bytes(b), BytesPolicy::Base64) => serializer.serialize_str(&base64(b)),
+            (Value::Bytes(b), BytesPolicy::Array) => {
+                let mut seq = serializer.serialize_seq(Some(b.len()))?;
+                for byte in *b {
+                    seq.serialize_element(byte)?;
+                }
+                seq.end()
+            }
+            (Value::Bytes(b), BytesPolicy::Error) => Err(S::Error::custom(format!("{} bytes have no JSON representation", b.len()))),
+            (Value::Array(vec), _) => {
+                let mut seq = serializer.serialize_seq(Some(vec.len()))?;
+                for element in vec {
+                    seq.serialize_element(&wrap(element))?;
+                }
+                seq.end()
+            }
+            (Value::Object(map), _) => {
+                let mut out = serializer.serialize_map(Some(map.len()))?;
+                for (key, member) in map {
+                    out.serialize_entry(key, &wrap(member))?;
+                }
+                out.end()
+            }
+            (value, _) => value.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{base64, BytesPolicy};
+
+    #[test]
+    fn bytes_policies() {
+        assert_eq!([base64(b""), base64(b"f"), base64(b"fo"), base64(b"foo"), base64(b"\xff\xfe\xfd\x00")], ["", "Zg==", "Zm8=", "Zm9v", "//79AA=="]);
+        let value = Value::Object([("blob".into(), Value::Bytes(b"\x01\xff")), ("n".into(), Value::Array(vec![Value::Bytes(b"hi")]))].into_iter().collect());
+        assert_eq!(serde_json::to_string(&value).unwrap(), r#"{"blob":[1,255],"n":[[104,105]]}"#);
+        assert_eq!(serde_json_nostr::to_string(&value.bytes(BytesPolicy::Base64)).unwrap(), r#"{"blob":"Af8=","n":["aGk="]}"#);
+        assert_eq!(serde_json_nostr::to_string(&value.bytes(BytesPolicy::Array)).unwrap(), r#"{"blob":[1,255],"n":[[104,105]]}"#);
+        let err = serde_json::to_string(&value.bytes(BytesPolicy::Error)).unwrap_err();
+        assert_eq!(err.to_string(), "2 bytes have no JSON representation");
+
+        // strings parsed by `serde_json_nostr` are bytes, written back as they came
+        let parsed: Value = serde_json_nostr::from_str(r#"{"name":"Jane \"J\""}"#).unwrap();
+        assert_eq!(serde_json_nostr::to_string(&parsed.bytes(BytesPolicy::Raw)).unwrap(), r#"{"name":"Jane \"J\""}"#);
+    }
+}
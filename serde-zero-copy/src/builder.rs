@@ -0,0 +1,168 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use serde_json::Number;
+use crate::Value;
+
+#[derive(Debug)]
+enum Open<'a> {
+    Array(Vec<Value<'a>>),
+    /// With the key of the member that comes next.
+    Object(BTreeMap<Cow<'a, str>, Value<'a>>, Option<Cow<'a, str>>),
+}
+
+/// Builds a value in the order it's written out, e.g.
+/// `ValueBuilder::new().object().key("id").num(1).key("tags").array().str("a").end().end().finish()`.
+/// Misuse, such as a key outside an object or a container left open, is a bug in the code
+/// doing the building and panics.
+#[derive(Debug, Default)]
+pub struct ValueBuilder<'a> {
+    open: Vec<Open<'a>>,
+    root: Option<Value<'a>>,
+}
+
+impl<'a> ValueBuilder<'a> {
+    pub fn new() -> Self {
+        ValueBuilder::default()
+    }
+
+    pub fn object(self) -> Self {
+        self.start(Open::Object(BTreeMap::new(), None))
+    }
+
+    pub fn array(self) -> Self {
+        self.start(Open::Array(Vec::new()))
+    }
+
+    /// Ends the innermost array or object.
+    pub fn end(mut self) -> Self {
+        let value = match self.open.pop() {
+            Some(Open::Array(vec)) => Value::Array(vec),
+            Some(Open::Object(map, None)) => Value::Object(map),
+            Some(Open::Object(_, Some(key))) => panic!("end of an object after key {:?} without its value", key),
+            None => panic!("end without an array or object to end"),
+        };
+        self.value(value)
+    }
+
+    pub fn key(mut self, key: impl Into<Cow<'a, str>>) -> Self {
+        let key = key.into();
+        match self.open.last_mut() {
+            Some(Open::Object(_, pending @ None)) => *pending = Some(key),
+            Some(Open::Object(_, Some(pending))) => panic!("key {:?} after key {:?} without its value", key, pending),
+            _ => panic!("key {:?} outside an object", key),
+        }
+        self
+    }
+
+    pub fn value(mut self, value: Value<'a>) -> Self {
+        self.check_slot();
+        match self.open.last_mut() {
+            Some(Open::Array(vec)) => vec.push(value),
+            Some(Open::Object(map, key)) => {
+                map.insert(key.take().unwrap_or_default(), value);
+            }
+            None => self.root = Some(value),
+        }
+        self
+    }
+
+    pub fn null(self) -> Self {
+        self.value(Value::Null)
+    }
+
+    pub fn bool(self, b: bool) -> Self {
+        self.value(Value::Bool(b))
+    }
+
+    pub fn num(self, n: impl Into<Number>) -> Self {
+        self.value(Value::Number(n.into()))
+    }
+
+    /// See [`Value::from_f64`] for NaN and the infinities.
+    pub fn float(self, f: f64) -> Self {
+        self.value(Value::from_f64(f))
+    }
+
+    pub fn str(self, s: &'a str) -> Self {
+        self.value(Value::Str(s))
+    }
+
+    pub fn string(self, s: impl Into<String>) -> Self {
+        self.value(Value::String(s.into()))
+    }
+
+    pub fn finish(self) -> Value<'a> {
+        assert!(self.open.is_empty(), "finished with {} arrays or objects not ended", self.open.len());
+        self.root.expect("finished before anything was built")
+    }
+
+    fn start(mut self, open: Open<'a>) -> Self {
+        self.check_slot();
+        self.open.push(open);
+        self
+    }
+
+    // there's somewhere for a value to go
+    fn check_slot(&self) {
+        match self.open.last() {
+            Some(Open::Object(_, None)) => panic!("a value in an object without a key"),
+            None if self.root.is_some() => panic!("a value after the one built"),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::ValueBuilder;
+
+    #[test]
+    fn builds_in_order() {
+        let input = String::from("borrowed");
+        let name = "n";
+        let value = ValueBuilder::new()
+            .object()
+            .key("id")
+            .num(1)
+            .key("tags")
+            .array()
+            .str(&input)
+            .string("owned")
+            .null()
+            .end()
+            .key(format!("{}_total", name))
+            .float(2.5)
+            .key("nested")
+            .object()
+            .key("ok")
+            .bool(true)
+            .key("empty")
+            .array()
+            .end()
+            .end()
+            .key("inf")
+            .float(f64::INFINITY)
+            .end()
+            .finish();
+        assert_eq!(
+            serde_json_nostr::to_string(&value).unwrap(),
+            r#"{"id":1,"inf":null,"n_total":2.5,"nested":{"empty":[],"ok":true},"tags":["borrowed","owned",null]}"#
+        );
+        assert!(matches!(value.pointer("/tags/0"), Some(Value::Str(s)) if s.as_ptr() == input.as_ptr()));
+        assert_eq!(ValueBuilder::new().str("x").finish(), Value::Str("x"));
+    }
+
+    #[test]
+    fn misuse_panics() {
+        let panics = |build: fn() -> Value<'static>| std::panic::catch_unwind(build).is_err();
+        assert!(panics(|| ValueBuilder::new().key("a").null().finish()));
+        assert!(panics(|| ValueBuilder::new().object().null().end().finish()));
+        assert!(panics(|| ValueBuilder::new().object().key("a").key("b").finish()));
+        assert!(panics(|| ValueBuilder::new().object().key("a").end().finish()));
+        assert!(panics(|| ValueBuilder::new().array().finish()));
+        assert!(panics(|| ValueBuilder::new().null().end().finish()));
+        assert!(panics(|| ValueBuilder::new().null().null().finish()));
+        assert!(panics(|| ValueBuilder::new().finish()));
+    }
+}
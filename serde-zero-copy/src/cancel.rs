@@ -0,0 +1,240 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::fmt;
+use std::io;
+use serde::de::{DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use serde::Deserializer;
+use serde_json::Number;
+use crate::Value;
+
+// how often `stop` is consulted, in values parsed or writes made
+const CHECK_EVERY: u32 = 1024;
+
+#[derive(Debug)]
+pub enum Error {
+    /// `stop` asked to stop before the work was done.
+    Cancelled,
+    Json(serde_json_nostr::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Cancelled => write!(f, "cancelled"),
+            Error::Json(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+struct Check<F> {
+    stop: F,
+    ticks: Cell<u32>,
+    cancelled: Cell<bool>,
+}
+
+impl<F: Fn() -> bool> Check<F> {
+    fn new(stop: F) -> Self {
+        Check { stop, ticks: Cell::new(0), cancelled: Cell::new(false) }
+    }
+
+    // true once `stop` has said so, checked every CHECK_EVERY ticks to keep the clock off the
+    // hot path
+    fn tick(&self) -> bool {
+        let ticks = self.ticks.get() + 1;
+        self.ticks.set(ticks);
+        if ticks.is_multiple_of(CHECK_EVERY) && (self.stop)() {
+            self.cancelled.set(true);
+        }
+        self.cancelled.get()
+    }
+}
+
+const CANCELLED: &str = "cancelled";
+
+struct Until<'c, F>(&'c Check<F>);
+
+impl<'de, 'c, F: Fn() -> bool> DeserializeSeed<'de> for Until<'c, F> {
+    type Value = Value<'de>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Value<'de>, D::Error>
+        where
+            D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'c, F: Fn() -> bool> Visitor<'de> for Until<'c, F> {
+    type Value = Value<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any valid JSON value")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Value<'de>, E> {
+        Ok(Value::Bool(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Value<'de>, E> {
+        Ok(Value::Number(value.into()))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Value<'de>, E> {
+        Ok(Value::Number(value.into()))
+    }
+
+    fn visit_i128<E>(self, value: i128) -> Result<Value<'de>, E> {
+        Ok(crate::from_i128(value))
+    }
+
+    fn visit_u128<E>(self, value: u128) -> Result<Value<'de>, E> {
+        Ok(crate::from_u128(value))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Value<'de>, E> {
+        Ok(Number::from_f64(value).map_or(Value::Null, Value::Number))
+    }
+
+    fn visit_borrowed_str<E>(self, value: &'de str) -> Result<Value<'de>, E> {
+        Ok(Value::Str(value))
+    }
+
+    fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<Value<'de>, E> {
+        Ok(Value::Bytes(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Value<'de>, E> {
+        Ok(Value::String(String::from_utf8_lossy(v).into_owned()))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value<'de>, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<V>(self, mut visitor: V) -> Result<Value<'de>, V::Error>
+        where
+            V: SeqAccess<'de>,
+    {
+        let mut vec = Vec::new();
+        while let Some(elem) = visitor.next_element_seed(Until(self.0))? {
+            if self.0.tick() {
+                return Err(serde::de::Error::custom(CANCELLED));
+            }
+            vec.push(elem);
+        }
+        Ok(Value::Array(vec))
+    }
+
+    fn visit_map<V>(self, mut visitor: V) -> Result<Value<'de>, V::Error>
+        where
+            V: MapAccess<'de>,
+    {
+        let mut map = std::collections::BTreeMap::new();
+        while let Some(key) = visitor.next_key_seed(crate::KeySeed)? {
+            #[cfg(feature = "decimal")]
+            if key == crate::NUMBER_TOKEN {
+                return crate::next_number(&mut visitor).map(Value::Number);
+            }
+            if key == serde_json_nostr::de::BIG_INT_TOKEN {
+                return visitor.next_value().map(|numeral| Value::BigInt(Cow::Borrowed(numeral)));
+            }
+            let value = visitor.next_value_seed(Until(self.0))?;
+            if self.0.tick() {
+                return Err(serde::de::Error::custom(CANCELLED));
+            }
+            map.insert(key, value);
+        }
+        Ok(Value::Object(map))
+    }
+}
+
+/// Parses like `serde_json_nostr::from_slice`, giving up with [`Error::Cancelled`] once
+/// `stop` returns true. `stop` is called every so many values, e.g. to check a deadline.
+pub fn from_slice_until<F>(input: &[u8], stop: F) -> Result<Value<'_>, Error>
+    where
+        F: Fn() -> bool,
+{
+    let check = Check::new(stop);
+    let mut deserializer = serde_json_nostr::Deserializer::from_slice(input);
+    let parsed = Until(&check).deserialize(&mut deserializer).and_then(|value| deserializer.end().map(|()| value));
+    match parsed {
+        Ok(value) => Ok(value),
+        Err(_) if check.cancelled.get() => Err(Error::Cancelled),
+        Err(err) => Err(Error::Json(err)),
+    }
+}
+
+struct UntilWriter<'c, W, F> {
+    inner: W,
+    check: &'c Check<F>,
+}
+
+impl<'c, W: io::Write, F: Fn() -> bool> io::Write for UntilWriter<'c, W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.check.tick() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, CANCELLED));
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Serializes like `serde_json_nostr::to_writer`, giving up with [`Error::Cancelled`] once
+/// `stop` returns true. `stop` is called every so many writes, what was written up to then
+/// stays written.
+pub fn to_writer_until<W, F>(writer: W, value: &Value, stop: F) -> Result<(), Error>
+    where
+        W: io::Write,
+        F: Fn() -> bool,
+{
+    let check = Check::new(stop);
+    match serde_json_nostr::to_writer(UntilWriter { inner: writer, check: &check }, value) {
+        Ok(()) => Ok(()),
+        Err(_) if check.cancelled.get() => Err(Error::Cancelled),
+        Err(err) => Err(Error::Json(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use crate::Value;
+    use super::{from_slice_until, to_writer_until, Error};
+
+    fn large() -> String {
+        format!("[{}]", (0..10_000).map(|i| format!(r#"{{"id":{},"name":"n{}"}}"#, i, i)).collect::<Vec<_>>().join(","))
+    }
+
+    #[test]
+    fn parse_until_stops() {
+        let input = large();
+        let parsed = from_slice_until(input.as_bytes(), || false).unwrap();
+        assert_eq!(parsed, serde_json_nostr::from_str::<Value>(&input).unwrap());
+        let calls = Cell::new(0);
+        let stopped = from_slice_until(input.as_bytes(), || {
+            calls.set(calls.get() + 1);
+            calls.get() > 2
+        });
+        assert!(matches!(stopped, Err(Error::Cancelled)));
+        assert_eq!(calls.get(), 3);
+        assert!(matches!(from_slice_until(b"[1,", || false), Err(Error::Json(_))));
+        assert!(matches!(from_slice_until(b"[1] x", || false), Err(Error::Json(_))));
+    }
+
+    #[test]
+    fn serialize_until_stops() {
+        let input = large();
+        let value: Value = serde_json_nostr::from_str(&input).unwrap();
+        let mut out = Vec::new();
+        to_writer_until(&mut out, &value, || false).unwrap();
+        assert_eq!(out, input.as_bytes());
+        let mut partial = Vec::new();
+        assert!(matches!(to_writer_until(&mut partial, &value, || true), Err(Error::Cancelled)));
+        assert!(partial.len() < input.len());
+    }
+}
@@ -0,0 +1,230 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use serde::Serialize;
+use crate::array::{as_bytes, big_int_f64, compare, rank};
+use crate::Value;
+
+/// How a value differs from another at a [`Difference`]'s pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    /// Only the first value has something there.
+    Removed,
+    /// Only the other value has something there.
+    Added,
+    /// Both have, but not the same, or not of the same kind.
+    Changed,
+}
+
+/// A difference found by [`Value::diff`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Difference {
+    /// A JSON pointer into both values.
+    pub pointer: String,
+    pub change: Change,
+}
+
+impl<'a> Value<'a> {
+    /// Equality by what the values mean as JSON rather than how they're held: strings compare
+    /// by content whether borrowed, owned or bytes, numbers by value, e.g. `1` and `1.0`, and
+    /// objects regardless of key order. This is what `==` does.
+    pub fn semantic_eq(&self, other: &Value) -> bool {
+        compare(self, other).is_eq()
+    }
+
+    /// Equality of how the values are held as well as what they mean: a borrowed string
+    /// isn't an owned one, nor bytes, nor is `1` the same as `1.0`. For checking that parsing
+    /// borrowed what it could.
+    pub fn identical(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::BigInt(a), Value::BigInt(b)) => a == b,
+            (Value::NonFinite(a), Value::NonFinite(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.identical(b)),
+            (Value::Object(a), Value::Object(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|((ka, va), (kb, vb))| ka == kb && va.identical(vb))
+            }
+            _ => false,
+        }
+    }
+
+    /// Where `other` differs from this value by [`Value::semantic_eq`], the innermost
+    /// members and elements that do, in key and then index order. Arrays compare element by
+    /// element, so an element put in front shows up as all of them changing.
+    pub fn diff(&self, other: &Value) -> Vec<Difference> {
+        let mut differences = Vec::new();
+        diff(self, other, &mut String::new(), &mut differences);
+        differences
+    }
+}
+
+fn diff(left: &Value, right: &Value, pointer: &mut String, differences: &mut Vec<Difference>) {
+    match (left, right) {
+        (Value::Object(left), Value::Object(right)) => {
+            let mut keys: Vec<_> = left.keys().chain(right.keys()).collect();
+            keys.sort_unstable();
+            keys.dedup();
+            for key in keys {
+                member(left.get(key), right.get(key), &key.replace('~', "~0").replace('/', "~1"), pointer, differences);
+            }
+        }
+        (Value::Array(left), Value::Array(right)) => {
+            for i in 0..left.len().max(right.len()) {
+                member(left.get(i), right.get(i), &i.to_string(), pointer, differences);
+            }
+        }
+        (left, right) if !left.semantic_eq(right) => differences.push(Difference { pointer: pointer.clone(), change: Change::Changed }),
+        _ => {}
+    }
+}
+
+fn member(left: Option<&Value>, right: Option<&Value>, segment: &str, pointer: &mut String, differences: &mut Vec<Difference>) {
+    let len = pointer.len();
+    pointer.push('/');
+    pointer.push_str(segment);
+    match (left, right) {
+        (Some(left), Some(right)) => diff(left, right, pointer, differences),
+        (Some(_), None) => differences.push(Difference { pointer: pointer.clone(), change: Change::Removed }),
+        (None, _) => differences.push(Difference { pointer: pointer.clone(), change: Change::Added }),
+    }
+    pointer.truncate(len);
+}
+
+impl<'a> PartialEq for Value<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.semantic_eq(other)
+    }
+}
+
+impl<'a> Eq for Value<'a> {}
+
+/// See [`array::compare`](crate::array::compare) for the order.
+impl<'a> Ord for Value<'a> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(self, other)
+    }
+}
+
+impl<'a> PartialOrd for Value<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Consistent with `==`: numbers hash by their value as a float and strings by their bytes,
+/// so a value can key a `HashMap` however it was parsed.
+impl<'a> Hash for Value<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        rank(self).hash(state);
+        let number = match self {
+            Value::Null => return,
+            Value::Bool(b) => return b.hash(state),
+            Value::Number(n) => n.as_f64().unwrap_or(f64::NAN),
+            Value::BigInt(n) => big_int_f64(n),
+            Value::NonFinite(f) => f.as_f64(),
+            Value::Array(vec) => return vec.hash(state),
+            Value::Object(map) => return map.hash(state),
+            text => return as_bytes(text).hash(state),
+        };
+        number.to_bits().hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::{BTreeMap, HashMap};
+    use std::hash::{Hash, Hasher};
+    use crate::Value;
+    use super::Change;
+
+    #[test]
+    fn semantic_eq_ignores_representation() {
+        let bytes: Value = serde_json_nostr::from_str(r#"{"name":"a\"b","n":1,"tags":["x"]}"#).unwrap();
+        let mut strs: Value = serde_json::from_str(r#"{"tags":["x"],"n":1.0,"name":null}"#).unwrap();
+        *strs.pointer_mut("/name").unwrap() = Value::Str("a\"b");
+        assert!(matches!(bytes.pointer("/tags/0"), Some(Value::Bytes(_))));
+        assert!(matches!(bytes.pointer("/name"), Some(Value::String(_))));
+        assert!(matches!(strs.pointer("/tags/0"), Some(Value::Str(_))));
+        assert_eq!(bytes, strs);
+        assert!(bytes.semantic_eq(&strs));
+        assert_eq!(Value::Str("x"), Value::String("x".to_string()));
+        assert_eq!(Value::Bytes(b"x"), Value::Str("x"));
+        assert_ne!(Value::Str("1"), Value::Number(1.into()));
+        assert_ne!(Value::Null, Value::Bool(false));
+        assert_ne!(Value::Array(vec![Value::Null]), Value::Array(vec![Value::Null, Value::Null]));
+    }
+
+    #[test]
+    fn identical_tells_representations_apart() {
+        let bytes: Value = serde_json_nostr::from_str(r#"{"name":"x","n":1}"#).unwrap();
+        let strs: Value = serde_json::from_str(r#"{"name":"x","n":1.0}"#).unwrap();
+        assert_eq!(bytes, strs);
+        assert!(!bytes.identical(&strs));
+        assert!(bytes.identical(&bytes.clone()));
+        assert!(!Value::Str("x").identical(&Value::String("x".to_string())));
+        assert!(Value::Array(vec![Value::Str("x")]).identical(&Value::Array(vec![Value::Str("x")])));
+    }
+
+    #[test]
+    fn diff_by_pointer() {
+        let left: Value = serde_json_nostr::from_str(r#"{"id":1,"a/b":{"x":true},"tags":["x","y"],"gone":null}"#).unwrap();
+        let right: Value = serde_json::from_str(r#"{"tags":["x","z","w"],"id":1.0,"a/b":{"x":"true"},"new":0}"#).unwrap();
+        let differences: Vec<_> = left.diff(&right).into_iter().map(|d| (d.pointer, d.change)).collect();
+        let expected = [("/a~1b/x", Change::Changed), ("/gone", Change::Removed), ("/new", Change::Added), ("/tags/1", Change::Changed), ("/tags/2", Change::Added)];
+        assert_eq!(differences, expected.map(|(pointer, change)| (pointer.to_string(), change)));
+        assert!(left.diff(&left).is_empty());
+        assert_eq!(Value::Null.diff(&Value::Bool(false))[0].pointer, "");
+    }
+
+    #[test]
+    fn hash_matches_semantic_eq() {
+        let hash = |value: &Value| {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        };
+        let parsed: Value = serde_json_nostr::from_str(r#"{"q":{"term":"a\tb","page":1},"tags":["x","y"]}"#).unwrap();
+        let mut built: Value = serde_json::from_str(r#"{"tags":["x","y"],"q":{"page":1.0,"term":null}}"#).unwrap();
+        *built.pointer_mut("/q/term").unwrap() = Value::Str("a\tb");
+        assert_eq!(hash(&parsed), hash(&built));
+        let mut cache = HashMap::new();
+        cache.insert(parsed, "cached");
+        assert_eq!(cache.get(&built), Some(&"cached"));
+        assert_eq!(hash(&Value::Bytes(b"x")), hash(&Value::String("x".to_string())));
+        assert_ne!(hash(&Value::Str("1")), hash(&Value::Number(1.into())));
+        assert_ne!(hash(&Value::Null), hash(&Value::Array(Vec::new())));
+    }
+
+    #[test]
+    fn total_order_across_variants() {
+        let mut values: Vec<Value> = serde_json_nostr::from_str(r#"[{"a":1},[2],"b",2.5,true,null,"a",[1,2],-1,false,{}]"#).unwrap();
+        values.push(Value::Bytes(&[0xff]));
+        values.push(Value::String("a".to_string()));
+        values.push(Value::BigInt("-100000000000000000000".into()));
+        values.push(Value::NonFinite(crate::NonFinite::Infinity));
+        values.sort();
+        let json: Vec<_> = values
+            .iter()
+            .map(|v| match v {
+                Value::Bytes(&[0xff]) => "0xff".to_string(),
+                v => serde_json_nostr::to_string(v).unwrap(),
+            })
+            .collect();
+        assert_eq!(
+            json,
+            vec!["null", "false", "true", "-100000000000000000000", "-1", "2.5", "null", r#""a""#, r#""a""#, r#""b""#, "0xff", "[1,2]", "[2]", "{}", r#"{"a":1}"#]
+        );
+        let mut counts = BTreeMap::new();
+        for value in &values {
+            *counts.entry(value).or_insert(0) += 1;
+        }
+        assert_eq!(counts[&Value::Str("a")], 2);
+        assert_eq!(counts.len(), values.len() - 1);
+    }
+}
@@ -0,0 +1,582 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use crate::{from_i128, from_u128, Value};
+
+#[derive(Debug)]
+pub enum Error {
+    Json(serde_json_nostr::Error),
+    Io(std::io::Error),
+    /// Input ended inside a value.
+    Eof,
+    /// What's at `offset` isn't something a [`Value`] can hold, e.g. a map key that isn't a
+    /// string or a MessagePack extension type.
+    Unsupported { offset: usize, what: &'static str },
+    InvalidUtf8 { offset: usize },
+    TooDeep,
+    TrailingBytes,
+    /// A string, array or object longer than the format can say.
+    TooLong,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Json(err) => err.fmt(f),
+            Error::Io(err) => err.fmt(f),
+            Error::Eof => f.write_str("unexpected end of input"),
+            Error::Unsupported { offset, what } => write!(f, "unsupported {} at byte {}", what, offset),
+            Error::InvalidUtf8 { offset } => write!(f, "invalid utf-8 string at byte {}", offset),
+            Error::TooDeep => write!(f, "nested more than {} deep", MAX_DEPTH),
+            Error::TrailingBytes => f.write_str("trailing bytes after value"),
+            Error::TooLong => f.write_str("value too long for the format"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// A format a [`Value`] can be read from and written to. Strings are borrowed from the input
+/// where the format allows it.
+pub trait Codec: Send + Sync {
+    /// e.g. `application/cbor`
+    fn media_type(&self) -> &'static str;
+
+    fn parse<'a>(&self, input: &'a [u8]) -> Result<Value<'a>, Error>;
+
+    fn write(&self, value: &Value, out: &mut dyn Write) -> Result<(), Error>;
+
+    fn to_vec(&self, value: &Value) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::new();
+        self.write(value, &mut out)?;
+        Ok(out)
+    }
+}
+
+pub struct Json;
+
+/// RFC 8949. Integers wider than 64 bits are bignums, byte strings that are valid utf-8 are
+/// written as text since that's what borrowed JSON strings are.
+pub struct Cbor;
+
+/// Integers wider than 64 bits are written as strings, there being no bignums.
+pub struct MessagePack;
+
+impl Codec for Json {
+    fn media_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn parse<'a>(&self, input: &'a [u8]) -> Result<Value<'a>, Error> {
+        serde_json_nostr::from_slice(input).map_err(Error::Json)
+    }
+
+    fn write(&self, value: &Value, out: &mut dyn Write) -> Result<(), Error> {
+        serde_json_nostr::to_writer(out, value).map_err(Error::Json)
+    }
+}
+
+impl Codec for Cbor {
+    fn media_type(&self) -> &'static str {
+        "application/cbor"
+    }
+
+    fn parse<'a>(&self, input: &'a [u8]) -> Result<Value<'a>, Error> {
+        let mut reader = Reader { input, pos: 0, depth: 0 };
+        let value = read_cbor(&mut reader)?;
+        reader.finish().map(|()| value)
+    }
+
+    fn write(&self, value: &Value, out: &mut dyn Write) -> Result<(), Error> {
+        write_cbor(value, out)
+    }
+}
+
+impl Codec for MessagePack {
+    fn media_type(&self) -> &'static str {
+        "application/msgpack"
+    }
+
+    fn parse<'a>(&self, input: &'a [u8]) -> Result<Value<'a>, Error> {
+        let mut reader = Reader { input, pos: 0, depth: 0 };
+        let value = read_msgpack(&mut reader)?;
+        reader.finish().map(|()| value)
+    }
+
+    fn write(&self, value: &Value, out: &mut dyn Write) -> Result<(), Error> {
+        write_msgpack(value, out)
+    }
+}
+
+/// The codec for a media type like `application/cbor`, parameters such as `charset` aside.
+pub fn for_media_type(media_type: &str) -> Option<&'static dyn Codec> {
+    let essence = media_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    match essence.as_str() {
+        "application/json" => Some(&Json),
+        "application/cbor" => Some(&Cbor),
+        "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => Some(&MessagePack),
+        _ => None,
+    }
+}
+
+/// The codec an `Accept` header prefers by its `q` weights, JSON for a wildcard and `None`
+/// when nothing it accepts is known.
+pub fn negotiate(accept: &str) -> Option<&'static dyn Codec> {
+    let mut best: Option<(f32, &'static dyn Codec)> = None;
+    for range in accept.split(',') {
+        let mut params = range.split(';');
+        let media_type = params.next().unwrap_or_default().trim();
+        let q = match params.find_map(|param| param.trim().strip_prefix("q=")) {
+            Some(q) => q.trim().parse().unwrap_or(0.0),
+            None => 1.0,
+        };
+        let codec = match media_type {
+            "*/*" | "application/*" => Some(&Json as &dyn Codec),
+            media_type => for_media_type(media_type),
+        };
+        match (codec, best) {
+            (Some(_), Some((best_q, _))) if best_q >= q => {}
+            (Some(codec), _) if q > 0.0 => best = Some((q, codec)),
+            _ => {}
+        }
+    }
+    best.map(|(_, codec)| codec)
+}
+
+// deep enough for any real document, shallow enough not to overflow the stack
+const MAX_DEPTH: usize = 128;
+
+struct Reader<'a> {
+    input: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: u64) -> Result<&'a [u8], Error> {
+        let end = usize::try_from(len).ok().and_then(|len| self.pos.checked_add(len)).filter(|end| *end <= self.input.len());
+        let end = end.ok_or(Error::Eof)?;
+        let bytes = &self.input[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        self.take(1).map(|b| b[0])
+    }
+
+    // a big-endian unsigned integer of `len` bytes
+    fn uint(&mut self, len: u64) -> Result<u64, Error> {
+        Ok(self.take(len)?.iter().fold(0, |n, b| n << 8 | *b as u64))
+    }
+
+    fn str(&mut self, len: u64) -> Result<&'a str, Error> {
+        let offset = self.pos;
+        std::str::from_utf8(self.take(len)?).map_err(|_| Error::InvalidUtf8 { offset })
+    }
+
+    // a break ending an indefinite-length item, consumed when it's there
+    fn at_break(&mut self) -> Result<bool, Error> {
+        match self.input.get(self.pos) {
+            Some(0xff) => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(_) => Ok(false),
+            None => Err(Error::Eof),
+        }
+    }
+
+    // room for `len` items without trusting a length the input can't back
+    fn capacity(&self, len: u64) -> usize {
+        usize::try_from(len).unwrap_or(usize::MAX).min(self.input.len() - self.pos)
+    }
+
+    fn nested<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(Error::TooDeep);
+        }
+        self.depth += 1;
+        let value = read(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        match self.pos == self.input.len() {
+            true => Ok(()),
+            false => Err(Error::TrailingBytes),
+        }
+    }
+}
+
+fn unsupported<T>(offset: usize, what: &'static str) -> Result<T, Error> {
+    Err(Error::Unsupported { offset, what })
+}
+
+fn key(offset: usize, key: Value) -> Result<Cow<str>, Error> {
+    match key {
+        Value::Str(s) => Ok(Cow::Borrowed(s)),
+        Value::String(s) => Ok(Cow::Owned(s)),
+        _ => unsupported(offset, "map key that isn't a string"),
+    }
+}
+
+fn f16(bits: u16) -> f64 {
+    let (exponent, fraction) = ((bits >> 10) & 0x1f, (bits & 0x3ff) as f64);
+    let magnitude = match exponent {
+        0 => fraction * 2f64.powi(-24),
+        31 if fraction == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + fraction / 1024.0) * 2f64.powi(exponent as i32 - 15),
+    };
+    if bits & 0x8000 == 0 { magnitude } else { -magnitude }
+}
+
+fn read_cbor<'a>(r: &mut Reader<'a>) -> Result<Value<'a>, Error> {
+    let offset = r.pos;
+    let initial = r.byte()?;
+    let (major, info) = (initial >> 5, initial & 0x1f);
+    let argument = match info {
+        0..=23 => Some(info as u64),
+        24..=27 => Some(r.uint(1 << (info - 24))?),
+        31 => None,
+        _ => return unsupported(offset, "reserved additional information"),
+    };
+    match (major, argument) {
+        (0, Some(n)) => Ok(Value::Number(n.into())),
+        (1, Some(n)) => Ok(from_i128(-1 - n as i128)),
+        (2, Some(len)) => Ok(Value::Bytes(r.take(len)?)),
+        (3, Some(len)) => Ok(Value::Str(r.str(len)?)),
+        // chunks copied together, there being nothing to borrow them as one
+        (3, None) => {
+            let mut text = String::new();
+            while !r.at_break()? {
+                match r.nested(read_cbor)? {
+                    Value::Str(chunk) => text.push_str(chunk),
+                    _ => return unsupported(offset, "chunk of a text string that isn't text"),
+                }
+            }
+            Ok(Value::String(text))
+        }
+        (4, len) => r.nested(|r| {
+            let mut vec = Vec::with_capacity(len.map_or(0, |len| r.capacity(len)));
+            let mut remaining = len;
+            while remaining != Some(0) && !(remaining.is_none() && r.at_break()?) {
+                vec.push(read_cbor(r)?);
+                remaining = remaining.map(|n| n - 1);
+            }
+            Ok(Value::Array(vec))
+        }),
+        (5, len) => r.nested(|r| {
+            let mut map = BTreeMap::new();
+            let mut remaining = len;
+            while remaining != Some(0) && !(remaining.is_none() && r.at_break()?) {
+                let offset = r.pos;
+                let key = key(offset, read_cbor(r)?)?;
+                map.insert(key, read_cbor(r)?);
+                remaining = remaining.map(|n| n - 1);
+            }
+            Ok(Value::Object(map))
+        }),
+        // positive and negative bignums
+        (6, Some(tag @ (2 | 3))) => {
+            let Value::Bytes(digits) = r.nested(read_cbor)? else { return unsupported(offset, "bignum that isn't a byte string") };
+            if digits.iter().skip_while(|b| **b == 0).count() > 16 {
+                return unsupported(offset, "bignum wider than 128 bits");
+            }
+            let n = digits.iter().fold(0u128, |n, b| n << 8 | *b as u128);
+            match (tag, i128::try_from(n)) {
+                (2, _) => Ok(from_u128(n)),
+                (_, Ok(n)) => Ok(from_i128(-1 - n)),
+                _ => unsupported(offset, "bignum wider than 128 bits"),
+            }
+        }
+        // the tagged item as it is for any other tag
+        (6, Some(_)) => r.nested(read_cbor),
+        (7, Some(n)) => match info {
+            20 => Ok(Value::Bool(false)),
+            21 => Ok(Value::Bool(true)),
+            // undefined too
+            22 | 23 => Ok(Value::Null),
+            25 => Ok(Value::from_f64(f16(n as u16))),
+            26 => Ok(Value::from_f64(f32::from_bits(n as u32) as f64)),
+            27 => Ok(Value::from_f64(f64::from_bits(n))),
+            _ => unsupported(offset, "simple value"),
+        },
+        _ => unsupported(offset, "indefinite length"),
+    }
+}
+
+fn cbor_head(out: &mut dyn Write, major: u8, n: u64) -> std::io::Result<()> {
+    let major = major << 5;
+    match n {
+        0..=23 => out.write_all(&[major | n as u8]),
+        24..=0xff => out.write_all(&[major | 24, n as u8]),
+        0x100..=0xffff => out.write_all(&[major | 25]).and_then(|()| out.write_all(&(n as u16).to_be_bytes())),
+        0x1_0000..=0xffff_ffff => out.write_all(&[major | 26]).and_then(|()| out.write_all(&(n as u32).to_be_bytes())),
+        _ => out.write_all(&[major | 27]).and_then(|()| out.write_all(&n.to_be_bytes())),
+    }
+}
+
+fn cbor_text(out: &mut dyn Write, s: &str) -> std::io::Result<()> {
+    cbor_head(out, 3, s.len() as u64)?;
+    out.write_all(s.as_bytes())
+}
+
+fn write_cbor(value: &Value, out: &mut dyn Write) -> Result<(), Error> {
+    match value {
+        Value::Null => out.write_all(&[0xf6])?,
+        Value::Bool(b) => out.write_all(&[if *b { 0xf5 } else { 0xf4 }])?,
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(n), _) => cbor_head(out, 0, n)?,
+            (_, Some(n)) => cbor_head(out, 1, (-1 - n) as u64)?,
+            _ => cbor_float(out, n.as_f64().unwrap_or(f64::NAN))?,
+        },
+        Value::BigInt(numeral) => {
+            let bignum = match (numeral.parse::<u128>(), numeral.parse::<i128>()) {
+                (Ok(n), _) => Some((0xc2, n)),
+                (_, Ok(n)) => Some((0xc3, (-1 - n) as u128)),
+                _ => None,
+            };
+            match bignum {
+                Some((tag, n)) => {
+                    let digits = n.to_be_bytes();
+                    let digits = &digits[(n.leading_zeros() / 8) as usize..];
+                    out.write_all(&[tag])?;
+                    cbor_head(out, 2, digits.len() as u64)?;
+                    out.write_all(digits)?;
+                }
+                None => cbor_text(out, numeral)?,
+            }
+        }
+        Value::NonFinite(f) => cbor_float(out, f.as_f64())?,
+        Value::Bytes(b) => match std::str::from_utf8(b) {
+            Ok(s) => cbor_text(out, s)?,
+            Err(_) => {
+                cbor_head(out, 2, b.len() as u64)?;
+                out.write_all(b)?;
+            }
+        },
+        Value::Str(s) => cbor_text(out, s)?,
+        Value::String(s) => cbor_text(out, s)?,
+        Value::Array(vec) => {
+            cbor_head(out, 4, vec.len() as u64)?;
+            vec.iter().try_for_each(|element| write_cbor(element, out))?;
+        }
+        Value::Object(map) => {
+            cbor_head(out, 5, map.len() as u64)?;
+            for (key, member) in map {
+                cbor_text(out, key)?;
+                write_cbor(member, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cbor_float(out: &mut dyn Write, f: f64) -> std::io::Result<()> {
+    out.write_all(&[0xfb])?;
+    out.write_all(&f.to_bits().to_be_bytes())
+}
+
+fn read_msgpack<'a>(r: &mut Reader<'a>) -> Result<Value<'a>, Error> {
+    let offset = r.pos;
+    let marker = r.byte()?;
+    match marker {
+        0x00..=0x7f => Ok(Value::Number(marker.into())),
+        0x80..=0x8f => read_msgpack_map(r, (marker & 0x0f) as u64),
+        0x90..=0x9f => read_msgpack_array(r, (marker & 0x0f) as u64),
+        0xa0..=0xbf => Ok(Value::Str(r.str((marker & 0x1f) as u64)?)),
+        0xc0 => Ok(Value::Null),
+        0xc2 => Ok(Value::Bool(false)),
+        0xc3 => Ok(Value::Bool(true)),
+        0xc4..=0xc6 => {
+            let len = r.uint(1 << (marker - 0xc4))?;
+            Ok(Value::Bytes(r.take(len)?))
+        }
+        0xca => Ok(Value::from_f64(f32::from_bits(r.uint(4)? as u32) as f64)),
+        0xcb => Ok(Value::from_f64(f64::from_bits(r.uint(8)?))),
+        0xcc..=0xcf => Ok(Value::Number(r.uint(1 << (marker - 0xcc))?.into())),
+        0xd0..=0xd3 => {
+            let shift = 64 - 8 * (1 << (marker - 0xd0));
+            let n = ((r.uint(1 << (marker - 0xd0))? << shift) as i64) >> shift;
+            Ok(Value::Number(n.into()))
+        }
+        0xd9..=0xdb => {
+            let len = r.uint(1 << (marker - 0xd9))?;
+            Ok(Value::Str(r.str(len)?))
+        }
+        0xdc | 0xdd => {
+            let len = r.uint(2 << (marker - 0xdc))?;
+            read_msgpack_array(r, len)
+        }
+        0xde | 0xdf => {
+            let len = r.uint(2 << (marker - 0xde))?;
+            read_msgpack_map(r, len)
+        }
+        0xe0..=0xff => Ok(Value::Number((marker as i8).into())),
+        _ => unsupported(offset, "extension type"),
+    }
+}
+
+fn read_msgpack_array<'a>(r: &mut Reader<'a>, len: u64) -> Result<Value<'a>, Error> {
+    r.nested(|r| {
+        let mut vec = Vec::with_capacity(r.capacity(len));
+        for _ in 0..len {
+            vec.push(read_msgpack(r)?);
+        }
+        Ok(Value::Array(vec))
+    })
+}
+
+fn read_msgpack_map<'a>(r: &mut Reader<'a>, len: u64) -> Result<Value<'a>, Error> {
+    r.nested(|r| {
+        let mut map = BTreeMap::new();
+        for _ in 0..len {
+            let offset = r.pos;
+            let key = key(offset, read_msgpack(r)?)?;
+            map.insert(key, read_msgpack(r)?);
+        }
+        Ok(Value::Object(map))
+    })
+}
+
+// the marker for a length of `len`, from the fixed form when there is one up to 32 bits
+fn msgpack_len(out: &mut dyn Write, fixed: Option<(u8, u64)>, markers: [u8; 3], len: usize) -> Result<(), Error> {
+    match (fixed, len) {
+        (Some((marker, max)), len) if len as u64 <= max => out.write_all(&[marker | len as u8])?,
+        (_, 0..=0xff) if markers[0] != 0 => out.write_all(&[markers[0], len as u8])?,
+        (_, 0..=0xffff) => out.write_all(&[markers[1]]).and_then(|()| out.write_all(&(len as u16).to_be_bytes()))?,
+        (_, len) => {
+            let len = u32::try_from(len).map_err(|_| Error::TooLong)?;
+            out.write_all(&[markers[2]])?;
+            out.write_all(&len.to_be_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn msgpack_str(out: &mut dyn Write, s: &str) -> Result<(), Error> {
+    msgpack_len(out, Some((0xa0, 31)), [0xd9, 0xda, 0xdb], s.len())?;
+    Ok(out.write_all(s.as_bytes())?)
+}
+
+fn write_msgpack(value: &Value, out: &mut dyn Write) -> Result<(), Error> {
+    match value {
+        Value::Null => out.write_all(&[0xc0])?,
+        Value::Bool(b) => out.write_all(&[if *b { 0xc3 } else { 0xc2 }])?,
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(n @ 0..=0x7f), _) => out.write_all(&[n as u8])?,
+            (Some(n @ 0..=0xff), _) => out.write_all(&[0xcc, n as u8])?,
+            (Some(n @ 0..=0xffff), _) => out.write_all(&[0xcd]).and_then(|()| out.write_all(&(n as u16).to_be_bytes()))?,
+            (Some(n @ 0..=0xffff_ffff), _) => out.write_all(&[0xce]).and_then(|()| out.write_all(&(n as u32).to_be_bytes()))?,
+            (Some(n), _) => out.write_all(&[0xcf]).and_then(|()| out.write_all(&n.to_be_bytes()))?,
+            (_, Some(n @ -32..=-1)) => out.write_all(&[n as i8 as u8])?,
+            (_, Some(n @ -0x80..=-1)) => out.write_all(&[0xd0, n as i8 as u8])?,
+            (_, Some(n @ -0x8000..=-1)) => out.write_all(&[0xd1]).and_then(|()| out.write_all(&(n as i16).to_be_bytes()))?,
+            (_, Some(n @ -0x8000_0000..=-1)) => out.write_all(&[0xd2]).and_then(|()| out.write_all(&(n as i32).to_be_bytes()))?,
+            (_, Some(n)) => out.write_all(&[0xd3]).and_then(|()| out.write_all(&n.to_be_bytes()))?,
+            _ => msgpack_float(out, n.as_f64().unwrap_or(f64::NAN))?,
+        },
+        Value::BigInt(numeral) => msgpack_str(out, numeral)?,
+        Value::NonFinite(f) => msgpack_float(out, f.as_f64())?,
+        Value::Bytes(b) => match std::str::from_utf8(b) {
+            Ok(s) => msgpack_str(out, s)?,
+            Err(_) => {
+                msgpack_len(out, None, [0xc4, 0xc5, 0xc6], b.len())?;
+                out.write_all(b)?;
+            }
+        },
+        Value::Str(s) => msgpack_str(out, s)?,
+        Value::String(s) => msgpack_str(out, s)?,
+        Value::Array(vec) => {
+            msgpack_len(out, Some((0x90, 15)), [0, 0xdc, 0xdd], vec.len())?;
+            vec.iter().try_for_each(|element| write_msgpack(element, out))?;
+        }
+        Value::Object(map) => {
+            msgpack_len(out, Some((0x80, 15)), [0, 0xde, 0xdf], map.len())?;
+            for (key, member) in map {
+                msgpack_str(out, key)?;
+                write_msgpack(member, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn msgpack_float(out: &mut dyn Write, f: f64) -> std::io::Result<()> {
+    out.write_all(&[0xcb])?;
+    out.write_all(&f.to_bits().to_be_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{negotiate, Cbor, Codec, Error, Json, MessagePack};
+
+    const INPUT: &str = r#"{"a":1,"b":[true,null],"c":-2,"d":1.5,"e":"tab\there","f":{"g":"plain","h":-300,"i":70000}}"#;
+
+    #[test]
+    fn round_trips_borrow_from_the_encoding() {
+        let parsed = Json.parse(INPUT.as_bytes()).unwrap();
+        let mut value = parsed.clone();
+        if let Value::Object(map) = &mut value {
+            map.insert("big".into(), Value::BigInt("-100000000000000000000".into()));
+            map.insert("inf".into(), Value::from_f64(f64::INFINITY));
+        }
+        for codec in [&Cbor as &dyn Codec, &MessagePack] {
+            let encoded = codec.to_vec(&value).unwrap();
+            let decoded = codec.parse(&encoded).unwrap();
+            let borrowed = |s: &str| encoded.as_ptr_range().contains(&s.as_ptr());
+            assert_eq!(decoded.pointer("/f"), parsed.pointer("/f"), "{}", codec.media_type());
+            assert!(matches!(decoded.pointer("/f/g"), Some(Value::Str(s)) if borrowed(s)));
+            assert!(matches!(decoded.pointer("/e"), Some(Value::Str(s)) if borrowed(s) && *s == "tab\there"));
+            assert!(matches!(decoded.pointer("/inf"), Some(Value::NonFinite(_))));
+            match &decoded {
+                Value::Object(map) => assert!(map.keys().all(|key| matches!(key, std::borrow::Cow::Borrowed(k) if borrowed(k)))),
+                _ => panic!(),
+            }
+        }
+        assert_eq!(Cbor.parse(&Cbor.to_vec(&value).unwrap()).unwrap(), value);
+        assert_eq!(MessagePack.parse(&MessagePack.to_vec(&value).unwrap()).unwrap().pointer("/big"), Some(&Value::Str("-100000000000000000000")));
+        assert_eq!(Json.to_vec(&parsed).unwrap(), INPUT.as_bytes());
+    }
+
+    #[test]
+    fn known_encodings() {
+        let value = Json.parse(br#"{"a":1,"b":[true,null],"c":-2,"d":1.5}"#).unwrap();
+        let cbor = b"\xa4\x61a\x01\x61b\x82\xf5\xf6\x61c\x21\x61d\xfb\x3f\xf8\0\0\0\0\0\0";
+        let msgpack = b"\x84\xa1a\x01\xa1b\x92\xc3\xc0\xa1c\xfe\xa1d\xcb\x3f\xf8\0\0\0\0\0\0";
+        assert_eq!(Cbor.to_vec(&value).unwrap(), cbor);
+        assert_eq!(MessagePack.to_vec(&value).unwrap(), msgpack);
+        // a half float, an indefinite-length array and text string, and a bignum
+        let value = Cbor.parse(b"\x83\xf9\x3e\x00\x9f\x01\x7f\x61a\x61b\xff\xff\xc2\x49\x01\0\0\0\0\0\0\0\0").unwrap();
+        assert_eq!(serde_json_nostr::to_string(&value).unwrap(), r#"[1.5,[1,"ab"],18446744073709551616]"#);
+        assert!(matches!(Cbor.parse(b"\x82\x01"), Err(Error::Eof)));
+        assert!(matches!(Cbor.parse(b"\x01\x02"), Err(Error::TrailingBytes)));
+        assert!(matches!(Cbor.parse(b"\xa1\x01\x02"), Err(Error::Unsupported { offset: 1, .. })));
+        assert!(matches!(MessagePack.parse(b"\xd4\x01\x02"), Err(Error::Unsupported { offset: 0, .. })));
+        assert!(matches!(MessagePack.parse(b"\xa2\xff\xfe"), Err(Error::InvalidUtf8 { offset: 1 })));
+        assert!(matches!(Cbor.parse(&[0x81; 200]), Err(Error::TooDeep)));
+        assert!(matches!(Cbor.parse(&[0xc6; 200]), Err(Error::TooDeep)));
+        assert!(matches!(MessagePack.parse(b"\xdd\xff\xff\xff\xff"), Err(Error::Eof)));
+    }
+
+    #[test]
+    fn negotiates_by_weight() {
+        let media_type = |accept| negotiate(accept).map(|codec| codec.media_type());
+        assert_eq!(media_type("application/cbor"), Some("application/cbor"));
+        assert_eq!(media_type("application/json;q=0.5, application/x-msgpack"), Some("application/msgpack"));
+        assert_eq!(media_type("application/cbor;q=0.2, */*;q=0.8"), Some("application/json"));
+        assert_eq!(media_type("text/html, application/CBOR; q=0.1"), Some("application/cbor"));
+        assert_eq!(media_type("text/html, application/cbor;q=0"), None);
+    }
+}
@@ -0,0 +1,77 @@
+use std::borrow::Cow;
+use crate::schema::as_str;
+use crate::Value;
+
+// an integer, or a float without a fraction that fits
+fn integral(f: f64) -> Option<i64> {
+    (f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64).then_some(f as i64)
+}
+
+/// Lenient accessors, for upstreams that send the same field as `1` one time and `"1"` the
+/// next. A value of neither kind, e.g. an object, is `None` rather than guessed at.
+impl<'a> Value<'a> {
+    /// A string as it is, borrowed; a number, big integer or boolean as it'd be written.
+    pub fn as_str_coerced(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Value::Number(n) => Some(Cow::Owned(n.to_string())),
+            Value::BigInt(n) => Some(Cow::Borrowed(n)),
+            Value::Bool(b) => Some(Cow::Borrowed(if *b { "true" } else { "false" })),
+            value => as_str(value).map(Cow::Borrowed),
+        }
+    }
+
+    /// An integer, or a string of one such as `"123"` or `" -4 "`. Floats and strings of them
+    /// count when they've no fraction, e.g. `2.0`, so that an upstream writing integers as
+    /// floats is read alike.
+    pub fn as_i64_coerced(&self) -> Option<i64> {
+        match self {
+            Value::Number(n) => n.as_i64().or_else(|| integral(n.as_f64()?)),
+            value => {
+                let s = as_str(value)?.trim();
+                s.parse().ok().or_else(|| integral(s.parse().ok()?))
+            }
+        }
+    }
+
+    /// A boolean, `"true"` or `"false"`, or `0` or `1` as numbers or strings.
+    pub fn as_bool_coerced(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            value => match self.as_i64_coerced() {
+                Some(0) => Some(false),
+                Some(1) => Some(true),
+                _ => as_str(value)?.trim().parse().ok(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use crate::Value;
+
+    #[test]
+    fn coerces_between_representations() {
+        let value: Value = serde_json_nostr::from_str(
+            r#"{"id":"123","qty":2.0,"code":1,"big":123456789012345678901,"flag":"true","off":0,"name":"Jane","half":"1.5","tags":["a"]}"#,
+        )
+        .unwrap();
+        let at = |pointer| value.pointer(pointer).unwrap();
+        assert_eq!(at("/id").as_i64_coerced(), Some(123));
+        assert_eq!(at("/qty").as_i64_coerced(), Some(2));
+        assert_eq!(at("/code").as_str_coerced(), Some(Cow::Owned("1".to_string())));
+        assert!(matches!(at("/name").as_str_coerced(), Some(Cow::Borrowed("Jane"))));
+        assert_eq!(at("/big").as_str_coerced().as_deref(), Some("123456789012345678901"));
+        assert_eq!((at("/flag").as_bool_coerced(), at("/off").as_bool_coerced()), (Some(true), Some(false)));
+        assert_eq!(Value::Bool(false).as_str_coerced().as_deref(), Some("false"));
+
+        // nothing is made up
+        assert_eq!(at("/half").as_i64_coerced(), None);
+        assert_eq!(at("/big").as_i64_coerced(), None);
+        assert_eq!(at("/name").as_bool_coerced(), None);
+        assert_eq!(at("/code").as_bool_coerced(), Some(true));
+        assert_eq!(at("/tags").as_str_coerced(), None);
+        assert_eq!(Value::Null.as_i64_coerced(), None);
+    }
+}
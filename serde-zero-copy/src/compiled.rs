@@ -0,0 +1,253 @@
+use std::fmt;
+use serde::{Deserialize, Deserializer};
+use crate::Value;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Neither a pointer nor a JSONPath, or one that doesn't go on as it should.
+    Invalid(String),
+    /// JSONPath that's more than members, indices and wildcards, e.g. `..` or a filter.
+    Unsupported(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Invalid(path) => write!(f, "invalid path {:?}", path),
+            Error::Unsupported(part) => write!(f, "unsupported {:?} in a path", part),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// a member or element, resolved once: a pointer's `0` is either, a JSONPath's `.0` only a
+// member and its `[0]` only an element
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+    key: Option<String>,
+    index: Option<usize>,
+}
+
+impl Token {
+    fn pointer(token: &str) -> Token {
+        let key = token.replace("~1", "/").replace("~0", "~");
+        Token { index: key.parse().ok(), key: Some(key) }
+    }
+
+    fn get<'v, 'a>(&self, value: &'v Value<'a>) -> Option<&'v Value<'a>> {
+        match value {
+            Value::Object(map) => map.get(self.key.as_deref()?),
+            Value::Array(vec) => vec.get(self.index?),
+            _ => None,
+        }
+    }
+
+    fn get_mut<'v, 'a>(&self, value: &'v mut Value<'a>) -> Option<&'v mut Value<'a>> {
+        match value {
+            Value::Object(map) => map.get_mut(self.key.as_deref()?),
+            Value::Array(vec) => vec.get_mut(self.index?),
+            _ => None,
+        }
+    }
+}
+
+/// A JSON pointer parsed once, to be looked up in many values without unescaping and parsing
+/// its tokens again each time. Looks up what [`Value::pointer`] does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledPointer {
+    tokens: Vec<Token>,
+}
+
+impl CompiledPointer {
+    pub fn new(pointer: &str) -> Result<Self, Error> {
+        if pointer.is_empty() {
+            return Ok(CompiledPointer { tokens: Vec::new() });
+        }
+        let tokens = pointer.strip_prefix('/').ok_or_else(|| Error::Invalid(pointer.to_string()))?;
+        Ok(CompiledPointer { tokens: tokens.split('/').map(Token::pointer).collect() })
+    }
+
+    pub fn get<'v, 'a>(&self, value: &'v Value<'a>) -> Option<&'v Value<'a>> {
+        self.tokens.iter().try_fold(value, |value, token| token.get(value))
+    }
+
+    pub fn get_mut<'v, 'a>(&self, value: &'v mut Value<'a>) -> Option<&'v mut Value<'a>> {
+        self.tokens.iter().try_fold(value, |value, token| token.get_mut(value))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Token(Token),
+    /// Every element or member.
+    Wildcard,
+}
+
+/// A path to any number of values parsed once: a JSON pointer where a `*` token stands for
+/// every element or member, e.g. `/items/*/price`, or the JSONPath of members, indices and
+/// wildcards, e.g. `$.items[*].price` or `$['a b'][0]`. Deserializes from either.
+#[derive(Debug, Clone)]
+pub struct CompiledPath {
+    path: String,
+    steps: Vec<Step>,
+}
+
+fn jsonpath(path: &str) -> Result<Vec<Step>, Error> {
+    let invalid = || Error::Invalid(path.to_string());
+    let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if rest.starts_with("..") {
+            return Err(Error::Unsupported("..".to_string()));
+        }
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            steps.push(match &after[..end] {
+                "" => return Err(invalid()),
+                "*" => Step::Wildcard,
+                name => Step::Token(Token { key: Some(name.to_string()), index: None }),
+            });
+            rest = &after[end..];
+            continue;
+        }
+        let after = rest.strip_prefix('[').ok_or_else(invalid)?;
+        let (step, after) = match after.chars().next() {
+            Some(quote @ ('\'' | '"')) => {
+                let (name, after) = after[1..].split_once(quote).ok_or_else(invalid)?;
+                (Step::Token(Token { key: Some(name.to_string()), index: None }), after.strip_prefix(']').ok_or_else(invalid)?)
+            }
+            _ => {
+                let (inner, after) = after.split_once(']').ok_or_else(invalid)?;
+                let step = match inner.trim() {
+                    "*" => Step::Wildcard,
+                    inner => match inner.parse() {
+                        Ok(index) => Step::Token(Token { key: None, index: Some(index) }),
+                        Err(_) => return Err(Error::Unsupported(format!("[{}]", inner))),
+                    },
+                };
+                (step, after)
+            }
+        };
+        steps.push(step);
+        rest = after;
+    }
+    Ok(steps)
+}
+
+impl CompiledPath {
+    pub fn new(path: &str) -> Result<Self, Error> {
+        let steps = match path.chars().next() {
+            None => Vec::new(),
+            Some('$') => jsonpath(path)?,
+            Some('/') => path[1..].split('/').map(|token| if token == "*" { Step::Wildcard } else { Step::Token(Token::pointer(token)) }).collect(),
+            Some(_) => return Err(Error::Invalid(path.to_string())),
+        };
+        Ok(CompiledPath { path: path.to_string(), steps })
+    }
+
+    /// As it was written.
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    /// Calls `f` with each value the path is to, in document order.
+    pub fn for_each<'v, 'a>(&self, value: &'v Value<'a>, f: &mut dyn FnMut(&'v Value<'a>)) {
+        fn each<'v, 'a>(steps: &[Step], value: &'v Value<'a>, f: &mut dyn FnMut(&'v Value<'a>)) {
+            let Some((step, rest)) = steps.split_first() else {
+                return f(value);
+            };
+            match (step, value) {
+                (Step::Wildcard, Value::Object(map)) => map.values().for_each(|member| each(rest, member, f)),
+                (Step::Wildcard, Value::Array(vec)) => vec.iter().for_each(|element| each(rest, element, f)),
+                (Step::Token(token), value) => {
+                    if let Some(next) = token.get(value) {
+                        each(rest, next, f);
+                    }
+                }
+                _ => {}
+            }
+        }
+        each(&self.steps, value, f)
+    }
+
+    pub fn for_each_mut(&self, value: &mut Value, f: &mut dyn FnMut(&mut Value)) {
+        fn each(steps: &[Step], value: &mut Value, f: &mut dyn FnMut(&mut Value)) {
+            let Some((step, rest)) = steps.split_first() else {
+                return f(value);
+            };
+            match (step, value) {
+                (Step::Wildcard, Value::Object(map)) => map.values_mut().for_each(|member| each(rest, member, f)),
+                (Step::Wildcard, Value::Array(vec)) => vec.iter_mut().for_each(|element| each(rest, element, f)),
+                (Step::Token(token), value) => {
+                    if let Some(next) = token.get_mut(value) {
+                        each(rest, next, f);
+                    }
+                }
+                _ => {}
+            }
+        }
+        each(&self.steps, value, f)
+    }
+
+    /// The first value the path is to.
+    pub fn first<'v, 'a>(&self, value: &'v Value<'a>) -> Option<&'v Value<'a>> {
+        let mut first = None;
+        self.for_each(value, &mut |found| {
+            first.get_or_insert(found);
+        });
+        first
+    }
+}
+
+impl fmt::Display for CompiledPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+impl<'de> Deserialize<'de> for CompiledPath {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+    {
+        CompiledPath::new(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{CompiledPath, CompiledPointer, Error};
+
+    #[test]
+    fn compiled_lookups() {
+        let input = br#"{"items":[{"price":1,"a/b":{"~":2}},{"price":3}],"0":"zero","a b":[4]}"#;
+        let mut value: Value = serde_json_nostr::from_slice(input).unwrap();
+        for pointer in ["", "/items/0/a~1b/~0", "/items/1", "/0", "/missing", "/items/x"] {
+            assert_eq!(CompiledPointer::new(pointer).unwrap().get(&value), value.pointer(pointer), "{}", pointer);
+        }
+        *CompiledPointer::new("/items/1/price").unwrap().get_mut(&mut value).unwrap() = Value::Null;
+        assert_eq!(value.pointer("/items/1/price"), Some(&Value::Null));
+        assert_eq!(CompiledPointer::new("items"), Err(Error::Invalid("items".to_string())));
+
+        let found = |path: &str| {
+            let mut found = Vec::new();
+            CompiledPath::new(path).unwrap().for_each(&value, &mut |v| found.push(serde_json_nostr::to_string(v).unwrap()));
+            found
+        };
+        assert_eq!(found("/items/*/price"), ["1", "null"]);
+        assert_eq!(found("$.items[*].price"), ["1", "null"]);
+        assert_eq!(found("$['a b'][0]"), ["4"]);
+        assert_eq!(found("$[0]"), Vec::<String>::new());
+        assert_eq!(found("$.0"), [r#""zero""#]);
+        assert_eq!(found("$.*").len(), 3);
+
+        CompiledPath::new("$.items[*]").unwrap().for_each_mut(&mut value, &mut |item| *item = Value::Bool(true));
+        assert_eq!(serde_json_nostr::to_string(&CompiledPath::new("/items").unwrap().first(&value)).unwrap(), "[true,true]");
+        assert!(matches!(CompiledPath::new("$..price"), Err(Error::Unsupported(_))));
+        assert!(matches!(CompiledPath::new("$[?(@.price)]"), Err(Error::Unsupported(_))));
+        assert!(matches!(CompiledPath::new("$['a"), Err(Error::Invalid(_))));
+        assert!(serde_json::from_str::<CompiledPath>(r#""items""#).unwrap_err().to_string().contains("invalid path"));
+    }
+}
@@ -0,0 +1,302 @@
+use std::borrow::Cow;
+use serde::de::value::{BorrowedStrDeserializer, MapAccessDeserializer, MapDeserializer, SeqDeserializer};
+use serde::de::{self, Deserialize, IntoDeserializer, Unexpected, Visitor};
+use serde::forward_to_deserialize_any;
+use serde_json_nostr::Error;
+use crate::Value;
+
+fn key(key: Cow<str>) -> Value {
+    match key {
+        Cow::Borrowed(s) => Value::Str(s),
+        Cow::Owned(s) => Value::String(s),
+    }
+}
+
+fn unexpected<'v>(value: &'v Value) -> Unexpected<'v> {
+    match value {
+        Value::Null => Unexpected::Unit,
+        Value::Bool(b) => Unexpected::Bool(*b),
+        Value::Number(n) => n.as_f64().map_or(Unexpected::Other("number"), Unexpected::Float),
+        Value::BigInt(n) => Unexpected::Other(n),
+        Value::NonFinite(f) => Unexpected::Float(f.as_f64()),
+        Value::Bytes(b) => std::str::from_utf8(b).map_or(Unexpected::Bytes(b), Unexpected::Str),
+        Value::Str(s) => Unexpected::Str(s),
+        Value::String(s) => Unexpected::Str(s),
+        Value::Array(_) => Unexpected::Seq,
+        Value::Object(_) => Unexpected::Map,
+    }
+}
+
+/// A parsed value handed to a `Deserialize` type in place of the input, like serde's own
+/// buffer for `#[serde(flatten)]` but lending bytes that are utf-8 out as `&'de str`.
+impl<'de> de::Deserializer<'de> for Value<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+        where
+            V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(b),
+            Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+                (Some(n), _) => visitor.visit_u64(n),
+                (_, Some(n)) => visitor.visit_i64(n),
+                _ => visitor.visit_f64(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::BigInt(n) => match (n.parse::<i128>(), n.parse::<u128>()) {
+                (Ok(n), _) => visitor.visit_i128(n),
+                (_, Ok(n)) => visitor.visit_u128(n),
+                _ => match n {
+                    Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+                    Cow::Owned(s) => visitor.visit_string(s),
+                },
+            },
+            Value::NonFinite(f) => visitor.visit_f64(f.as_f64()),
+            Value::Bytes(b) => match std::str::from_utf8(b) {
+                Ok(s) => visitor.visit_borrowed_str(s),
+                Err(_) => visitor.visit_borrowed_bytes(b),
+            },
+            Value::Str(s) => visitor.visit_borrowed_str(s),
+            Value::String(s) => visitor.visit_string(s),
+            Value::Array(vec) => {
+                let mut seq = SeqDeserializer::new(vec.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Object(map) => {
+                let mut map = MapDeserializer::new(map.into_iter().map(|(k, v)| (key(k), v)));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+        where
+            V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_none(),
+            value => visitor.visit_some(value),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error>
+        where
+            V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // a unit variant as a string, any other as an object with the variant as its one key
+    fn deserialize_enum<V>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Error>
+        where
+            V: Visitor<'de>,
+    {
+        match self {
+            Value::Bytes(b) => match std::str::from_utf8(b) {
+                Ok(s) => visitor.visit_enum(BorrowedStrDeserializer::new(s)),
+                Err(_) => Err(de::Error::invalid_type(Unexpected::Bytes(b), &"a string or an object with one key")),
+            },
+            Value::Str(s) => visitor.visit_enum(BorrowedStrDeserializer::new(s)),
+            Value::String(s) => visitor.visit_enum(s.into_deserializer()),
+            Value::Object(map) if map.len() == 1 => {
+                visitor.visit_enum(MapAccessDeserializer::new(MapDeserializer::new(map.into_iter().map(|(k, v)| (key(k), v)))))
+            }
+            value => Err(de::Error::invalid_type(unexpected(&value), &"a string or an object with one key")),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// `T` from a value parsed before, borrowing from the same input.
+pub fn from_value<'de, T>(value: Value<'de>) -> Result<T, Error>
+    where
+        T: Deserialize<'de>,
+{
+    T::deserialize(value)
+}
+
+/// Deserializes a `T` that has `#[serde(flatten)]` fields through a [`Value`] of `input`
+/// rather than serde's buffer: strings `serde_json_nostr` lends as bytes reach the flattened
+/// fields as `&str`, so types that only take a string, such as `IpAddr`, keep working and
+/// `&str`, `Cow<str>` and `Value` fields keep borrowing.
+pub fn flatten_borrowed<'de, T>(input: &'de [u8]) -> Result<T, Error>
+    where
+        T: Deserialize<'de>,
+{
+    from_value(serde_json_nostr::from_slice(input)?)
+}
+
+/// The buffer for an untagged or internally tagged enum of borrowed types, in place of
+/// serde's: deserialize one, look at it, then deserialize the variant from it as with
+/// [`from_value`], see `examples/polymorphic.rs`. Trying a variant clones the value, which
+/// copies only the strings that couldn't be borrowed in the first place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BorrowedContent<'de>(Value<'de>);
+
+impl<'de> BorrowedContent<'de> {
+    /// The string member `field` of an object, e.g. the tag of an internally tagged enum.
+    pub fn tag(&self, field: &str) -> Option<&str> {
+        let Value::Object(map) = &self.0 else { return None };
+        match map.get(field)? {
+            Value::Bytes(b) => std::str::from_utf8(b).ok(),
+            Value::Str(s) => Some(s),
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn deserialize_as<T>(&self) -> Result<T, Error>
+        where
+            T: Deserialize<'de>,
+    {
+        from_value(self.0.clone())
+    }
+
+    pub fn value(&self) -> &Value<'de> {
+        &self.0
+    }
+
+    pub fn into_value(self) -> Value<'de> {
+        self.0
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for BorrowedContent<'a> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: de::Deserializer<'de>,
+    {
+        Value::deserialize(deserializer).map(BorrowedContent)
+    }
+}
+
+impl<'de> From<Value<'de>> for BorrowedContent<'de> {
+    fn from(value: Value<'de>) -> Self {
+        BorrowedContent(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::net::IpAddr;
+    use serde::Deserialize;
+    use crate::Value;
+    use super::{flatten_borrowed, from_value, BorrowedContent};
+
+    #[derive(Deserialize)]
+    struct Event<'a> {
+        id: &'a str,
+        #[serde(borrow, flatten)]
+        source: Source<'a>,
+        #[serde(borrow, flatten)]
+        rest: Value<'a>,
+    }
+
+    #[derive(Deserialize)]
+    struct Source<'a> {
+        ip: IpAddr,
+        #[serde(borrow)]
+        agent: Cow<'a, str>,
+        #[serde(borrow)]
+        tags: Vec<&'a str>,
+    }
+
+    #[test]
+    fn flattened_fields_borrow() {
+        let input = r#"{"id":"e1","ip":"10.0.0.1","agent":"curl","tags":["a","b"],"extra":{"n":1,"s":"x"},"note":"tab\there"}"#;
+        let borrowed = |s: &str| input.as_bytes().as_ptr_range().contains(&s.as_ptr());
+        assert!(serde_json_nostr::from_str::<Event>(input).is_err());
+        let event: Event = flatten_borrowed(input.as_bytes()).unwrap();
+        assert!(borrowed(event.id));
+        assert_eq!(event.source.ip, IpAddr::from([10, 0, 0, 1]));
+        assert!(matches!(event.source.agent, Cow::Borrowed(s) if borrowed(s)));
+        assert!(event.source.tags.iter().all(|s| borrowed(s)));
+        assert_eq!(serde_json_nostr::to_string(&event.rest).unwrap(), r#"{"extra":{"n":1,"s":"x"},"note":"tab\there"}"#);
+        assert!(matches!(event.rest.pointer("/extra/s"), Some(Value::Str(s)) if borrowed(s)));
+        assert!(matches!(event.rest.pointer("/note"), Some(Value::String(_))));
+    }
+
+    #[test]
+    fn from_value_types() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        enum Kind<'a> {
+            Plain,
+            Named(&'a str),
+            Pair { a: i64, b: Option<u128> },
+        }
+        let value: Value = serde_json_nostr::from_str(r#"["Plain",{"Named":"x"},{"Pair":{"a":-1,"b":null}},{"Pair":{"a":2,"b":3}}]"#).unwrap();
+        let kinds: Vec<Kind> = from_value(value).unwrap();
+        assert_eq!(kinds, vec![Kind::Plain, Kind::Named("x"), Kind::Pair { a: -1, b: None }, Kind::Pair { a: 2, b: Some(3) }]);
+        assert_eq!(from_value::<u128>(Value::BigInt("100000000000000000000".into())).unwrap(), 100000000000000000000);
+        let value: Value = serde_json_nostr::from_str(r#"[1,2]"#).unwrap();
+        assert!(from_value::<(u8,)>(value).is_err());
+        assert!(from_value::<Kind>(Value::Null).is_err());
+    }
+
+    #[derive(Debug)]
+    enum Shape<'a> {
+        Circle { r: f64, label: &'a str },
+        Poly { points: Vec<(i32, i32)>, style: Value<'a> },
+        Named(&'a str),
+    }
+
+    // internally tagged by "kind", and a bare string for a named shape
+    impl<'de> Deserialize<'de> for Shape<'de> {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+        {
+            #[derive(Deserialize)]
+            struct Circle<'a> {
+                r: f64,
+                label: &'a str,
+            }
+            #[derive(Deserialize)]
+            struct Poly<'a> {
+                points: Vec<(i32, i32)>,
+                #[serde(borrow)]
+                style: Value<'a>,
+            }
+            let content = BorrowedContent::deserialize(deserializer)?;
+            let shape = match content.tag("kind") {
+                Some("circle") => content.deserialize_as().map(|c: Circle| Shape::Circle { r: c.r, label: c.label }),
+                Some("poly") => content.deserialize_as().map(|p: Poly| Shape::Poly { points: p.points, style: p.style }),
+                Some(kind) => return Err(serde::de::Error::unknown_variant(kind, &["circle", "poly"])),
+                None => content.deserialize_as().map(Shape::Named),
+            };
+            shape.map_err(serde::de::Error::custom)
+        }
+    }
+
+    #[test]
+    fn borrowed_content_picks_variants() {
+        let input = r#"[{"kind":"circle","r":1.5,"label":"c"},{"points":[[0,0],[1,2]],"kind":"poly","style":{"fill":"red"}},"star"]"#;
+        let borrowed = |s: &str| input.as_bytes().as_ptr_range().contains(&s.as_ptr());
+        let shapes: Vec<Shape> = serde_json_nostr::from_str(input).unwrap();
+        assert!(matches!(shapes[0], Shape::Circle { r, label } if r == 1.5 && borrowed(label)));
+        assert!(matches!(&shapes[1], Shape::Poly { points, style } if points == &[(0, 0), (1, 2)]
+            && matches!(style.pointer("/fill"), Some(Value::Str(s)) if borrowed(s))));
+        assert!(matches!(&shapes[2], Shape::Named(s) if borrowed(s)));
+        let err = serde_json_nostr::from_str::<Shape>(r#"{"kind":"line"}"#).unwrap_err();
+        assert!(err.to_string().contains("unknown variant `line`"));
+        assert!(serde_json_nostr::from_str::<Shape>(r#"{"kind":"circle","r":"x"}"#).is_err());
+    }
+}
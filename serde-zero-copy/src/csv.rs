@@ -0,0 +1,269 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use serde_json::Number;
+use crate::Value;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    /// Turn `true`/`false`, numbers and empty fields into booleans, numbers and nulls instead
+    /// of keeping every field a string.
+    pub infer_types: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions { delimiter: b',', infer_types: false }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    UnterminatedQuote { line: usize },
+    /// A quote in the middle of an unquoted field, or garbage after a closing quote.
+    UnexpectedQuote { line: usize },
+    DuplicateHeader(String),
+    FieldCount { line: usize, expected: usize, found: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnterminatedQuote { line } => write!(f, "unterminated quoted field starting on line {}", line),
+            Error::UnexpectedQuote { line } => write!(f, "unexpected quote on line {}", line),
+            Error::DuplicateHeader(header) => write!(f, "duplicate header field: {}", header),
+            Error::FieldCount { line, expected, found } => {
+                write!(f, "line {} has {} fields, the header has {}", line, found, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+struct Reader<'a> {
+    input: &'a str,
+    pos: usize,
+    line: usize,
+    delimiter: u8,
+}
+
+impl<'a> Reader<'a> {
+    fn at_end(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    // Returns the field and whether it was the last one of its record.
+    fn field(&mut self) -> Result<(Cow<'a, str>, bool), Error> {
+        let bytes = self.input.as_bytes();
+        if bytes.get(self.pos) == Some(&b'"') {
+            let start_line = self.line;
+            let start = self.pos + 1;
+            let mut i = start;
+            loop {
+                match bytes.get(i) {
+                    None => return Err(Error::UnterminatedQuote { line: start_line }),
+                    Some(b'"') if bytes.get(i + 1) == Some(&b'"') => {
+                        self.line = start_line;
+                        return self.quoted_owned(start, start_line);
+                    }
+                    Some(b'"') => break,
+                    Some(b'\n') => self.line += 1,
+                    _ => {}
+                }
+                i += 1;
+            }
+            self.pos = i + 1;
+            let last = self.separator(start_line)?;
+            Ok((Cow::Borrowed(&self.input[start..i]), last))
+        } else {
+            let start = self.pos;
+            let mut i = start;
+            while i < bytes.len() && bytes[i] != self.delimiter && bytes[i] != b'\n' && bytes[i] != b'\r' {
+                if bytes[i] == b'"' {
+                    return Err(Error::UnexpectedQuote { line: self.line });
+                }
+                i += 1;
+            }
+            self.pos = i;
+            let last = self.separator(self.line)?;
+            Ok((Cow::Borrowed(&self.input[start..i]), last))
+        }
+    }
+
+    // Rescans a quoted field starting at `start` once it is known to contain `""` escapes.
+    fn quoted_owned(&mut self, start: usize, start_line: usize) -> Result<(Cow<'a, str>, bool), Error> {
+        let bytes = self.input.as_bytes();
+        let mut buf = String::new();
+        let mut i = start;
+        let mut chunk = start;
+        loop {
+            match bytes.get(i) {
+                None => return Err(Error::UnterminatedQuote { line: start_line }),
+                Some(b'"') if bytes.get(i + 1) == Some(&b'"') => {
+                    buf.push_str(&self.input[chunk..i + 1]);
+                    i += 2;
+                    chunk = i;
+                    continue;
+                }
+                Some(b'"') => break,
+                Some(b'\n') => self.line += 1,
+                _ => {}
+            }
+            i += 1;
+        }
+        buf.push_str(&self.input[chunk..i]);
+        self.pos = i + 1;
+        let last = self.separator(start_line)?;
+        Ok((Cow::Owned(buf), last))
+    }
+
+    // Consumes the delimiter or line ending after a field.
+    fn separator(&mut self, line: usize) -> Result<bool, Error> {
+        let bytes = self.input.as_bytes();
+        match bytes.get(self.pos) {
+            None => Ok(true),
+            Some(&b) if b == self.delimiter => {
+                self.pos += 1;
+                Ok(false)
+            }
+            Some(b'\r') if bytes.get(self.pos + 1) == Some(&b'\n') => {
+                self.pos += 2;
+                self.line += 1;
+                Ok(true)
+            }
+            Some(b'\n') | Some(b'\r') => {
+                self.pos += 1;
+                self.line += 1;
+                Ok(true)
+            }
+            Some(_) => Err(Error::UnexpectedQuote { line }),
+        }
+    }
+
+    fn record(&mut self) -> Result<Vec<Cow<'a, str>>, Error> {
+        let mut fields = Vec::new();
+        loop {
+            let (field, last) = self.field()?;
+            fields.push(field);
+            if last {
+                return Ok(fields);
+            }
+        }
+    }
+}
+
+fn infer(field: Cow<str>) -> Value {
+    match field.as_ref() {
+        "" => return Value::Null,
+        "true" => return Value::Bool(true),
+        "false" => return Value::Bool(false),
+        _ => {}
+    }
+    if let Ok(n) = field.parse::<i64>() {
+        return Value::Number(n.into());
+    }
+    if let Ok(n) = field.parse::<u64>() {
+        return Value::Number(n.into());
+    }
+    if let Some(n) = field.parse::<f64>().ok().and_then(Number::from_f64) {
+        // "inf", "NaN" and friends parse as f64 but aren't numbers in the JSON sense
+        if field.bytes().all(|b| b.is_ascii_digit() || matches!(b, b'-' | b'+' | b'.' | b'e' | b'E')) {
+            return Value::Number(n);
+        }
+    }
+    match field {
+        Cow::Borrowed(s) => Value::Str(s),
+        Cow::Owned(s) => Value::String(s),
+    }
+}
+
+/// Parses a CSV document with a header line into an array of objects, one per record. Keys
+/// and values borrow the input unless they contain `""` escapes.
+pub fn from_csv(input: &str, options: CsvOptions) -> Result<Value<'_>, Error> {
+    let mut reader = Reader { input, pos: 0, line: 1, delimiter: options.delimiter };
+    if reader.at_end() {
+        return Ok(Value::Array(Vec::new()));
+    }
+    let mut headers = Vec::new();
+    for header in reader.record()? {
+        if headers.contains(&header) {
+            return Err(Error::DuplicateHeader(header.into_owned()));
+        }
+        headers.push(header);
+    }
+
+    let mut records = Vec::new();
+    while !reader.at_end() {
+        let line = reader.line;
+        let fields = reader.record()?;
+        // tolerate blank lines, e.g. a trailing one
+        if fields.len() == 1 && fields[0].is_empty() {
+            continue;
+        }
+        if fields.len() != headers.len() {
+            return Err(Error::FieldCount { line, expected: headers.len(), found: fields.len() });
+        }
+        let record: BTreeMap<Cow<str>, Value> = headers
+            .iter()
+            .cloned()
+            .zip(fields.into_iter().map(|field| match field {
+                field if options.infer_types => infer(field),
+                Cow::Borrowed(s) => Value::Str(s),
+                Cow::Owned(s) => Value::String(s),
+            }))
+            .collect();
+        records.push(Value::Object(record));
+    }
+    Ok(Value::Array(records))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{from_csv, CsvOptions, Error};
+
+    #[test]
+    fn csv_borrows_headers_and_fields() {
+        let input = "id,name,\"note\"\"\"\r\n1,John Doe,\"says \"\"hi\"\"\"\n2,\"Jane, Q\",\"multi\nline\"\n";
+        let value = from_csv(input, CsvOptions::default()).unwrap();
+        let records = match &value {
+            Value::Array(records) => records,
+            _ => panic!(),
+        };
+        assert_eq!(records.len(), 2);
+        match &records[0] {
+            Value::Object(record) => {
+                let (k, v) = record.get_key_value("id").unwrap();
+                assert_eq!(k.as_ptr(), input.as_ptr());
+                assert!(v.identical(&Value::Str("1")));
+                assert!(record["note\""].identical(&Value::String("says \"hi\"".to_string())));
+            }
+            _ => panic!(),
+        }
+        assert_eq!(
+            serde_json_nostr::to_string(&records[1]).unwrap(),
+            r#"{"id":"2","name":"Jane, Q","note\"":"multi\nline"}"#
+        );
+    }
+
+    #[test]
+    fn csv_infers_types() {
+        let options = CsvOptions { delimiter: b';', infer_types: true };
+        let value = from_csv("a;b;c;d;e\n-1;2.5;true;;1e3x\n", options).unwrap();
+        assert_eq!(
+            serde_json_nostr::to_string(&value).unwrap(),
+            r#"[{"a":-1,"b":2.5,"c":true,"d":null,"e":"1e3x"}]"#
+        );
+    }
+
+    #[test]
+    fn csv_errors() {
+        let options = CsvOptions::default();
+        assert_eq!(from_csv("a,b\n1\n", options), Err(Error::FieldCount { line: 2, expected: 2, found: 1 }));
+        assert_eq!(from_csv("a,b\n\"1,2\n", options), Err(Error::UnterminatedQuote { line: 2 }));
+        assert_eq!(from_csv("a,a\n", options), Err(Error::DuplicateHeader("a".to_string())));
+        assert_eq!(from_csv("a\nx\"y\n", options), Err(Error::UnexpectedQuote { line: 2 }));
+    }
+}
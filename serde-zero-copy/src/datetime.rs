@@ -0,0 +1,106 @@
+use std::borrow::Cow;
+use chrono::{DateTime, FixedOffset, NaiveTime, SecondsFormat, Timelike};
+use crate::Value;
+
+/// The unit [`truncate`] drops everything below, in the timestamp's own offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Truncate {
+    Second,
+    Minute,
+    Hour,
+    Day,
+}
+
+fn parse(s: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(s).ok()
+}
+
+/// RFC 3339, with `Z` for UTC and as many fraction digits as a second needs.
+fn format(datetime: &DateTime<FixedOffset>) -> String {
+    datetime.to_rfc3339_opts(SecondsFormat::AutoSi, datetime.offset().local_minus_utc() == 0)
+}
+
+impl<'a> Value<'a> {
+    /// The RFC 3339 timestamp in a string, e.g. `"2024-05-01T12:30:00+02:00"`.
+    pub fn as_datetime(&self) -> Option<DateTime<FixedOffset>> {
+        match self {
+            Value::Str(s) => parse(s),
+            Value::Bytes(b) => parse(std::str::from_utf8(b).ok()?),
+            Value::String(s) => parse(s),
+            _ => None,
+        }
+    }
+
+    /// Replaces every string that is an RFC 3339 timestamp with `f` of it, see [`to_offset`]
+    /// and [`truncate`]. Strings that aren't, and timestamps `f` leaves as they were, stay
+    /// borrowed.
+    pub fn map_datetimes<F>(self, mut f: F) -> Value<'a>
+        where
+            F: FnMut(DateTime<FixedOffset>) -> DateTime<FixedOffset>,
+    {
+        self.map_strings(|s| match parse(s) {
+            Some(datetime) => {
+                let mapped = f(datetime);
+                match mapped == datetime && mapped.offset() == datetime.offset() {
+                    true => Cow::Borrowed(s),
+                    false => Cow::Owned(format(&mapped)),
+                }
+            }
+            None => Cow::Borrowed(s),
+        })
+    }
+}
+
+/// The same instant at `offset`.
+pub fn to_offset(offset: FixedOffset) -> impl Fn(DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+    move |datetime| datetime.with_timezone(&offset)
+}
+
+pub fn truncate(unit: Truncate) -> impl Fn(DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+    move |datetime| {
+        let (hour, minute, second) = (datetime.hour(), datetime.minute(), datetime.second());
+        let time = match unit {
+            Truncate::Second => NaiveTime::from_hms_opt(hour, minute, second),
+            Truncate::Minute => NaiveTime::from_hms_opt(hour, minute, 0),
+            Truncate::Hour => NaiveTime::from_hms_opt(hour, 0, 0),
+            Truncate::Day => Some(NaiveTime::MIN),
+        };
+        time.and_then(|time| datetime.with_time(time).single()).unwrap_or(datetime)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, TimeZone};
+    use crate::Value;
+    use super::{to_offset, truncate, Truncate};
+
+    #[test]
+    fn datetime_accessor() {
+        let value: Value = serde_json_nostr::from_str(r#"{"at":"2024-05-01T12:30:05.250+02:00","name":"2024"}"#).unwrap();
+        let utc = FixedOffset::east_opt(0).unwrap();
+        assert_eq!(
+            value.pointer("/at").and_then(Value::as_datetime),
+            Some(utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 5).unwrap() + chrono::Duration::milliseconds(250))
+        );
+        assert_eq!(value.pointer("/name").and_then(Value::as_datetime), None);
+        assert_eq!(Value::Null.as_datetime(), None);
+    }
+
+    #[test]
+    fn datetime_transforms() {
+        let input = r#"{"created":"2024-05-01T23:30:05.250+02:00","updated":"2024-05-02T01:00:00Z","note":"soon"}"#;
+        let value: Value = serde_json_nostr::from_str(input).unwrap();
+        let utc = value.clone().map_datetimes(to_offset(FixedOffset::east_opt(0).unwrap()));
+        assert_eq!(
+            serde_json_nostr::to_string(&utc).unwrap(),
+            r#"{"created":"2024-05-01T21:30:05.250Z","note":"soon","updated":"2024-05-02T01:00:00Z"}"#
+        );
+        assert!(matches!(utc.pointer("/updated"), Some(Value::Bytes(b)) if input.as_bytes().as_ptr_range().contains(&b.as_ptr())));
+        let days = value.map_datetimes(truncate(Truncate::Day));
+        assert_eq!(
+            serde_json_nostr::to_string(&days).unwrap(),
+            r#"{"created":"2024-05-01T00:00:00+02:00","note":"soon","updated":"2024-05-02T00:00:00Z"}"#
+        );
+    }
+}
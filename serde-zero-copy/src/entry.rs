@@ -0,0 +1,115 @@
+use std::borrow::Cow;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeSet;
+use crate::Value;
+
+impl<'a> Value<'a> {
+    /// The member `key` of an object to look at or fill in, `None` for anything else. Keys can
+    /// be borrowed or owned, e.g. `value.entry(format!("{}_total", name))`.
+    pub fn entry(&mut self, key: impl Into<Cow<'a, str>>) -> Option<Entry<'_, Cow<'a, str>, Value<'a>>> {
+        match self {
+            Value::Object(map) => Some(map.entry(key.into())),
+            _ => None,
+        }
+    }
+
+    /// Removes the member `key` of an object, handing back its key and value.
+    pub fn remove_entry(&mut self, key: &str) -> Option<(Cow<'a, str>, Value<'a>)> {
+        match self {
+            Value::Object(map) => map.remove_entry(key),
+            _ => None,
+        }
+    }
+}
+
+/// Key names computed at runtime, to insert into many objects without allocating the key for
+/// each of them: every name is kept here once and the objects borrow it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyInterner(BTreeSet<String>);
+
+impl KeyInterner {
+    pub fn new() -> Self {
+        KeyInterner::default()
+    }
+
+    pub fn intern(&mut self, key: impl Into<String>) {
+        self.0.insert(key.into());
+    }
+
+    /// `key` borrowed from here when it was interned, owned otherwise.
+    pub fn get<'k>(&'k self, key: &str) -> Cow<'k, str> {
+        match self.0.get(key) {
+            Some(key) => Cow::Borrowed(key),
+            None => Cow::Owned(key.to_string()),
+        }
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for KeyInterner {
+    fn from_iter<I: IntoIterator<Item = S>>(keys: I) -> Self {
+        KeyInterner(keys.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use crate::Value;
+    use super::KeyInterner;
+
+    #[test]
+    fn entry_borrowed_and_owned_keys() {
+        let mut value: Value = serde_json_nostr::from_str(r#"{"status":"ok","n":1}"#).unwrap();
+        value.entry("status").unwrap().or_insert(Value::Null);
+        value.entry("missing").unwrap().or_insert(Value::Null);
+        let name = String::from("n");
+        value.entry(format!("{}_total", name)).unwrap().or_insert(Value::Number(2.into()));
+        assert_eq!(serde_json_nostr::to_string(&value).unwrap(), r#"{"missing":null,"n":1,"n_total":2,"status":"ok"}"#);
+        assert!(Value::Null.entry("a").is_none());
+    }
+
+    #[test]
+    fn interned_keys_are_shared() {
+        let input = r#"[{"price":3},{"price":4}]"#;
+        let mut interner: KeyInterner = ["price_cents"].into_iter().collect();
+        interner.intern("price_cents");
+        let mut value: Value = serde_json_nostr::from_str(input).unwrap();
+        if let Value::Array(items) = &mut value {
+            for item in items {
+                let cents = match item.pointer("/price") {
+                    Some(Value::Number(n)) => n.as_i64().map(|n| n * 100),
+                    _ => None,
+                };
+                item.entry(interner.get("price_cents")).unwrap().or_insert(cents.map_or(Value::Null, |n| Value::Number(n.into())));
+            }
+        }
+        assert_eq!(serde_json_nostr::to_string(&value).unwrap(), r#"[{"price":3,"price_cents":300},{"price":4,"price_cents":400}]"#);
+        let keys: Vec<_> = match &value {
+            Value::Array(items) => items.iter().filter_map(|item| match item {
+                Value::Object(map) => map.keys().find(|key| *key == "price_cents"),
+                _ => None,
+            }).collect(),
+            _ => panic!(),
+        };
+        assert!(keys.iter().all(|key| matches!(key, Cow::Borrowed(k) if k.as_ptr() == keys[0].as_ptr())));
+        assert!(matches!(interner.get("other"), Cow::Owned(_)));
+    }
+
+    #[test]
+    fn subtrees_move_between_documents() {
+        let input = r#"{"order":{"items":[{"sku":"a"},{"sku":"b"}],"note":"x"},"meta":{}}"#;
+        let mut source: Value = serde_json_nostr::from_str(input).unwrap();
+        let mut target: Value = serde_json_nostr::from_str(r#"{"lines":null}"#).unwrap();
+        let items = source.take_pointer("/order/items").unwrap();
+        let previous = std::mem::replace(target.pointer_mut("/lines").unwrap(), items);
+        assert_eq!(previous, Value::Null);
+        let (key, note) = source.pointer_mut("/order").unwrap().remove_entry("note").unwrap();
+        assert!(matches!(key, Cow::Borrowed(k) if input.as_bytes().as_ptr_range().contains(&k.as_ptr())));
+        target.entry(key).unwrap().or_insert(note);
+        assert_eq!(serde_json_nostr::to_string(&source).unwrap(), r#"{"meta":{},"order":{"items":null}}"#);
+        assert_eq!(serde_json_nostr::to_string(&target).unwrap(), r#"{"lines":[{"sku":"a"},{"sku":"b"}],"note":"x"}"#);
+        assert!(matches!(target.pointer("/lines/1/sku"), Some(Value::Bytes(b)) if input.as_bytes().as_ptr_range().contains(&b.as_ptr())));
+        assert_eq!(Value::Null.remove_entry("a"), None);
+        assert_eq!(source.take_pointer("/order/missing"), None);
+    }
+}
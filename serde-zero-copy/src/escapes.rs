@@ -0,0 +1,227 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use serde::de::IgnoredAny;
+use crate::Value;
+
+#[derive(Debug, Clone, Copy)]
+pub struct EscapeOptions {
+    /// Report escaped object keys too, which parsing allocates like strings.
+    pub keys: bool,
+    /// Report array elements under `*` instead of their index, e.g. `/items/*/name`, so a
+    /// field counts once for all of them.
+    pub wildcard_indices: bool,
+}
+
+impl Default for EscapeOptions {
+    fn default() -> Self {
+        EscapeOptions { keys: true, wildcard_indices: false }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Counts {
+    strings: u64,
+    escapes: u64,
+    surrogate_pairs: u64,
+}
+
+/// Which strings of JSON documents parsing couldn't borrow, because of escapes in them, to
+/// tell from captured traffic how much of it would parse without allocating. Documents are
+/// added with [`EscapeAnalysis::scan`], [`EscapeAnalysis::report`] sums them up.
+#[derive(Debug, Clone, Default)]
+pub struct EscapeAnalysis {
+    options: EscapeOptions,
+    documents: u64,
+    strings: u64,
+    owned: u64,
+    fields: BTreeMap<(String, bool), Counts>,
+}
+
+/// `(escapes, surrogate pairs)` in the string between `input[at]`'s quote and the next one,
+/// and the offset past it. A high surrogate without a low one after it is an escape.
+fn string(input: &[u8], mut at: usize) -> (bool, bool, usize) {
+    // `\uD8`..`\uDB` for a high surrogate, `\uDC`..`\uDF` for a low one
+    let surrogate = |at: usize, seconds: &[u8]| {
+        input.get(at..at + 4).is_some_and(|u| matches!(u, [b'\\', b'u', b'd' | b'D', n] if seconds.contains(n)))
+    };
+    let (mut escape, mut pair) = (false, false);
+    at += 1;
+    while let Some(&b) = input.get(at) {
+        match b {
+            b'"' => break,
+            b'\\' if surrogate(at, b"89abAB") && surrogate(at + 6, b"cdefCDEF") => {
+                pair = true;
+                at += 12;
+            }
+            b'\\' => {
+                escape = true;
+                at += if input.get(at + 1) == Some(&b'u') { 6 } else { 2 };
+            }
+            _ => at += 1,
+        }
+    }
+    (escape, pair, (at + 1).min(input.len()))
+}
+
+fn skip_whitespace(input: &[u8], mut at: usize) -> usize {
+    while input.get(at).is_some_and(u8::is_ascii_whitespace) {
+        at += 1;
+    }
+    at
+}
+
+fn push_token(pointer: &mut String, token: &str) {
+    pointer.push('/');
+    pointer.push_str(&token.replace('~', "~0").replace('/', "~1"));
+}
+
+impl EscapeAnalysis {
+    pub fn new(options: EscapeOptions) -> Self {
+        EscapeAnalysis { options, ..Default::default() }
+    }
+
+    /// Adds a document, which must be valid JSON.
+    pub fn scan(&mut self, input: &[u8]) -> Result<(), serde_json_nostr::Error> {
+        serde_json_nostr::from_slice::<IgnoredAny>(input)?;
+        self.documents += 1;
+        self.value(input, skip_whitespace(input, 0), &mut String::new());
+        Ok(())
+    }
+
+    fn string(&mut self, input: &[u8], at: usize, pointer: &str, key: bool) -> usize {
+        let (escape, surrogate, end) = string(input, at);
+        self.strings += 1;
+        if escape || surrogate {
+            self.owned += 1;
+        }
+        if (escape || surrogate) && (!key || self.options.keys) {
+            let counts = self.fields.entry((pointer.to_string(), key)).or_default();
+            counts.strings += 1;
+            counts.escapes += escape as u64;
+            counts.surrogate_pairs += surrogate as u64;
+        }
+        end
+    }
+
+    // the offset past the value at `at`
+    fn value(&mut self, input: &[u8], at: usize, pointer: &mut String) -> usize {
+        let len = pointer.len();
+        match input[at] {
+            b'"' => self.string(input, at, pointer, false),
+            b'[' => {
+                let mut at = skip_whitespace(input, at + 1);
+                let mut index = 0;
+                while input[at] != b']' {
+                    match self.options.wildcard_indices {
+                        true => push_token(pointer, "*"),
+                        false => push_token(pointer, &index.to_string()),
+                    }
+                    at = skip_whitespace(input, self.value(input, at, pointer));
+                    pointer.truncate(len);
+                    at = skip_whitespace(input, at + (input[at] == b',') as usize);
+                    index += 1;
+                }
+                at + 1
+            }
+            b'{' => {
+                let mut at = skip_whitespace(input, at + 1);
+                while input[at] != b'}' {
+                    let end = string(input, at).2;
+                    let key: String = serde_json_nostr::from_slice(&input[at..end]).unwrap_or_default();
+                    push_token(pointer, &key);
+                    self.string(input, at, pointer, true);
+                    // past the colon
+                    at = skip_whitespace(input, skip_whitespace(input, end) + 1);
+                    at = skip_whitespace(input, self.value(input, at, pointer));
+                    pointer.truncate(len);
+                    at = skip_whitespace(input, at + (input[at] == b',') as usize);
+                }
+                at + 1
+            }
+            _ => at + input[at..].iter().position(|b| matches!(b, b',' | b']' | b'}') || b.is_ascii_whitespace()).unwrap_or(input.len() - at),
+        }
+    }
+
+    /// `{"documents", "strings", "owned", "fields": [{"pointer", "key", "strings", "escapes",
+    /// "surrogate_pairs"}]}`, by how many strings a field had to own, most first. `escapes`
+    /// and `surrogate_pairs` count the strings with any.
+    pub fn report(&self) -> Value<'static> {
+        let number = |n: u64| Value::Number(n.into());
+        let mut fields: Vec<_> = self.fields.iter().collect();
+        fields.sort_by_key(|(_, counts)| std::cmp::Reverse(counts.strings));
+        let fields = fields
+            .into_iter()
+            .map(|((pointer, key), counts)| {
+                Value::Object(BTreeMap::from([
+                    (Cow::Borrowed("pointer"), Value::String(pointer.clone())),
+                    (Cow::Borrowed("key"), Value::Bool(*key)),
+                    (Cow::Borrowed("strings"), number(counts.strings)),
+                    (Cow::Borrowed("escapes"), number(counts.escapes)),
+                    (Cow::Borrowed("surrogate_pairs"), number(counts.surrogate_pairs)),
+                ]))
+            })
+            .collect();
+        Value::Object(BTreeMap::from([
+            (Cow::Borrowed("documents"), number(self.documents)),
+            (Cow::Borrowed("strings"), number(self.strings)),
+            (Cow::Borrowed("owned"), number(self.owned)),
+            (Cow::Borrowed("fields"), Value::Array(fields)),
+        ]))
+    }
+}
+
+/// [`EscapeAnalysis::report`] of a single document.
+pub fn analyze_escapes(input: &[u8], options: EscapeOptions) -> Result<Value<'static>, serde_json_nostr::Error> {
+    let mut analysis = EscapeAnalysis::new(options);
+    analysis.scan(input)?;
+    Ok(analysis.report())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze_escapes, EscapeAnalysis, EscapeOptions};
+
+    #[test]
+    fn escapes_report_fields() {
+        let input = br#" {"plain": "a", "quote": "say \"hi\"", "emoji": "\ud83d\ude00", "a/b\n": [1, "x", "\u00e9"], "n": [true, null, -1.5e3]} "#;
+        let report = analyze_escapes(input, EscapeOptions::default()).unwrap();
+        assert_eq!(
+            serde_json_nostr::to_string(&report).unwrap(),
+            concat!(
+                r#"{"documents":1,"fields":["#,
+                r#"{"escapes":1,"key":true,"pointer":"/a~1b\n","strings":1,"surrogate_pairs":0},"#,
+                r#"{"escapes":1,"key":false,"pointer":"/a~1b\n/2","strings":1,"surrogate_pairs":0},"#,
+                r#"{"escapes":0,"key":false,"pointer":"/emoji","strings":1,"surrogate_pairs":1},"#,
+                r#"{"escapes":1,"key":false,"pointer":"/quote","strings":1,"surrogate_pairs":0}"#,
+                r#"],"owned":4,"strings":10}"#
+            )
+        );
+        assert!(analyze_escapes(br#"{"a": "\"#, EscapeOptions::default()).is_err());
+    }
+
+    #[test]
+    fn escapes_across_documents() {
+        let mut analysis = EscapeAnalysis::new(EscapeOptions { keys: false, wildcard_indices: true });
+        analysis.scan(br#"{"items":[{"name":"a\tb"},{"name":"c"}],"k\"":1}"#).unwrap();
+        analysis.scan(br#"{"items":[{"name":"d\\e"}]}"#).unwrap();
+        let report = analysis.report();
+        assert_eq!(
+            serde_json_nostr::to_string(&report.pointer("/fields").unwrap()).unwrap(),
+            r#"[{"escapes":2,"key":false,"pointer":"/items/*/name","strings":2,"surrogate_pairs":0}]"#
+        );
+        assert_eq!(serde_json_nostr::to_string(&report.pointer("/owned").unwrap()).unwrap(), "3");
+    }
+
+    #[test]
+    fn lone_surrogates_are_escapes() {
+        let report = analyze_escapes(br#"["\ud83d\n", "\ud83d\u0041", "\uDBFF\uDFFF"]"#, EscapeOptions::default()).unwrap();
+        assert_eq!(
+            serde_json_nostr::to_string(&report.pointer("/fields").unwrap()).unwrap(),
+            concat!(
+                r#"[{"escapes":1,"key":false,"pointer":"/0","strings":1,"surrogate_pairs":0},"#,
+                r#"{"escapes":1,"key":false,"pointer":"/1","strings":1,"surrogate_pairs":0},"#,
+                r#"{"escapes":0,"key":false,"pointer":"/2","strings":1,"surrogate_pairs":1}]"#
+            )
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::Value;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Syntax { path: String, offset: usize },
+    /// The path goes through a leaf or mixes indices and keys at the same level.
+    Conflict { path: String },
+    /// An index more than [`MAX_INDEX_GAP`] past the end of its array so far.
+    IndexOutOfRange { path: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Syntax { path, offset } => write!(f, "invalid path {:?} at {}", path, offset),
+            Error::Conflict { path } => write!(f, "{:?} conflicts with an earlier path", path),
+            Error::IndexOutOfRange { path } => write!(f, "index out of range in {:?}", path),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// How many nulls an index can leave before it in an array.
+pub const MAX_INDEX_GAP: usize = 64;
+
+// keys made of these are written bare, anything else is quoted like `["a.b"]`
+fn is_bare(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-' || b == b'$')
+}
+
+fn push_key(path: &mut String, key: &str) {
+    if is_bare(key) {
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(key);
+    } else {
+        path.push('[');
+        path.push_str(&serde_json::to_string(key).unwrap());
+        path.push(']');
+    }
+}
+
+impl<'a> Value<'a> {
+    /// Every leaf by its path, like `product.allergens[0].name`. Empty arrays and objects
+    /// are leaves, a scalar root is found under the empty path.
+    pub fn flatten(&self) -> BTreeMap<String, &Value<'a>> {
+        let mut flat = BTreeMap::new();
+        self.flatten_into(&mut String::new(), &mut flat);
+        flat
+    }
+
+    fn flatten_into<'v>(&'v self, path: &mut String, flat: &mut BTreeMap<String, &'v Value<'a>>) {
+        let len = path.len();
+        match self {
+            Value::Array(vec) if !vec.is_empty() => {
+                for (i, item) in vec.iter().enumerate() {
+                    path.push_str(&format!("[{}]", i));
+                    item.flatten_into(path, flat);
+                    path.truncate(len);
+                }
+            }
+            Value::Object(map) if !map.is_empty() => {
+                for (key, item) in map {
+                    push_key(path, key);
+                    item.flatten_into(path, flat);
+                    path.truncate(len);
+                }
+            }
+            leaf => {
+                flat.insert(path.clone(), leaf);
+            }
+        }
+    }
+
+    /// The inverse of [`Value::flatten`], keys borrow from the paths unless quoted with
+    /// escapes. Gaps in arrays are filled with nulls, up to [`MAX_INDEX_GAP`] of them.
+    pub fn unflatten<I>(pairs: I) -> Result<Value<'a>, Error>
+        where
+            I: IntoIterator<Item = (&'a str, Value<'a>)>,
+    {
+        let mut root = None;
+        for (path, value) in pairs {
+            let segments = parse(path)?;
+            place(&mut root, &segments, value, path)?;
+        }
+        Ok(root.map_or(Value::Null, Node::into_value))
+    }
+}
+
+enum Segment<'a> {
+    Key(Cow<'a, str>),
+    Index(usize),
+}
+
+fn parse(path: &str) -> Result<Vec<Segment<'_>>, Error> {
+    let syntax = |offset| Error::Syntax { path: path.to_string(), offset };
+    let bytes = path.as_bytes();
+    let mut segments = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'[' if bytes.get(pos + 1) == Some(&b'"') => {
+                let start = pos + 2;
+                let mut end = start;
+                let mut escaped = false;
+                while end < bytes.len() && bytes[end] != b'"' {
+                    if bytes[end] == b'\\' {
+                        escaped = true;
+                        end += 1;
+                    }
+                    end += 1;
+                }
+                if bytes.get(end + 1) != Some(&b']') {
+                    return Err(syntax(pos));
+                }
+                let key = match escaped {
+                    true => Cow::Owned(serde_json::from_str(&path[start - 1..=end]).map_err(|_| syntax(start))?),
+                    false => Cow::Borrowed(&path[start..end]),
+                };
+                segments.push(Segment::Key(key));
+                pos = end + 2;
+            }
+            b'[' => {
+                let end = path[pos..].find(']').map(|i| pos + i).ok_or_else(|| syntax(pos))?;
+                segments.push(Segment::Index(path[pos + 1..end].parse().map_err(|_| syntax(pos + 1))?));
+                pos = end + 1;
+            }
+            b'.' if !segments.is_empty() && bytes.get(pos + 1).is_some_and(|&b| b != b'.' && b != b'[') => pos += 1,
+            _ if pos == 0 || bytes[pos - 1] == b'.' => {
+                let end = path[pos..].find(['.', '[']).map_or(path.len(), |i| pos + i);
+                if !is_bare(&path[pos..end]) {
+                    return Err(syntax(pos));
+                }
+                segments.push(Segment::Key(Cow::Borrowed(&path[pos..end])));
+                pos = end;
+            }
+            _ => return Err(syntax(pos)),
+        }
+    }
+    Ok(segments)
+}
+
+enum Node<'a> {
+    Leaf(Value<'a>),
+    Array(Vec<Option<Node<'a>>>),
+    Object(BTreeMap<Cow<'a, str>, Node<'a>>),
+}
+
+impl<'a> Node<'a> {
+    fn into_value(self) -> Value<'a> {
+        match self {
+            Node::Leaf(value) => value,
+            Node::Array(vec) => Value::Array(vec.into_iter().map(|n| n.map_or(Value::Null, Node::into_value)).collect()),
+            Node::Object(map) => Value::Object(map.into_iter().map(|(k, n)| (k, n.into_value())).collect()),
+        }
+    }
+}
+
+fn place<'a>(slot: &mut Option<Node<'a>>, segments: &[Segment<'a>], value: Value<'a>, path: &str) -> Result<(), Error> {
+    let conflict = || Error::Conflict { path: path.to_string() };
+    let (first, rest) = match segments.split_first() {
+        Some(split) => split,
+        None if slot.is_none() => {
+            *slot = Some(Node::Leaf(value));
+            return Ok(());
+        }
+        None => return Err(conflict()),
+    };
+    match (first, slot.get_or_insert_with(|| match first {
+        Segment::Key(_) => Node::Object(BTreeMap::new()),
+        Segment::Index(_) => Node::Array(Vec::new()),
+    })) {
+        (Segment::Key(key), Node::Object(map)) => {
+            let mut child = map.remove(key);
+            let placed = place(&mut child, rest, value, path);
+            if let Some(child) = child {
+                map.insert(key.clone(), child);
+            }
+            placed
+        }
+        (Segment::Index(i), Node::Array(vec)) => {
+            if *i > vec.len() + MAX_INDEX_GAP {
+                return Err(Error::IndexOutOfRange { path: path.to_string() });
+            }
+            if vec.len() <= *i {
+                vec.resize_with(i + 1, || None);
+            }
+            place(&mut vec[*i], rest, value, path)
+        }
+        _ => Err(conflict()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::Error;
+
+    #[test]
+    fn flatten_paths() {
+        let input = r#"{"product":{"allergens":[{"name":"nuts"},{"name":"milk"}],"tags":[],"a.b":1,"a\"b":2},"ok":true}"#;
+        let value: Value = serde_json_nostr::from_str(input).unwrap();
+        let flat = value.flatten();
+        let paths: Vec<_> = flat.iter().map(|(path, v)| format!("{} = {}", path, serde_json_nostr::to_string(v).unwrap())).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "ok = true",
+                r#"product.allergens[0].name = "nuts""#,
+                r#"product.allergens[1].name = "milk""#,
+                "product.tags = []",
+                r#"product["a.b"] = 1"#,
+                r#"product["a\"b"] = 2"#,
+            ]
+        );
+        let back = Value::unflatten(flat.iter().map(|(path, v)| (path.as_str(), (*v).clone()))).unwrap();
+        assert_eq!(back, value);
+        assert_eq!(Value::Bool(true).flatten().keys().collect::<Vec<_>>(), vec![""]);
+    }
+
+    #[test]
+    fn unflatten_errors_and_gaps() {
+        let pairs = [("a[2]", Value::Bool(true)), ("b", Value::Null)];
+        assert_eq!(
+            serde_json_nostr::to_string(&Value::unflatten(pairs.iter().map(|(p, v)| (*p, v.clone()))).unwrap()).unwrap(),
+            r#"{"a":[null,null,true],"b":null}"#
+        );
+        let conflict = [("a", Value::Null), ("a.b", Value::Null)];
+        assert_eq!(Value::unflatten(conflict.iter().map(|(p, v)| (*p, v.clone()))), Err(Error::Conflict { path: "a.b".to_string() }));
+        let mixed = [("a[0]", Value::Null), ("a.b", Value::Null)];
+        assert!(matches!(Value::unflatten(mixed.iter().map(|(p, v)| (*p, v.clone()))), Err(Error::Conflict { .. })));
+        assert!(matches!(Value::unflatten([("a..b", Value::Null)]), Err(Error::Syntax { .. })));
+        assert!(matches!(Value::unflatten([(r#"["a\u"]"#, Value::Null)]), Err(Error::Syntax { .. })));
+    }
+
+    #[test]
+    fn unflatten_rejects_huge_indices() {
+        let path = "a[18446744073709551615]";
+        assert_eq!(Value::unflatten([(path, Value::Null)]), Err(Error::IndexOutOfRange { path: path.to_string() }));
+        assert!(matches!(Value::unflatten([("a[65]", Value::Null)]), Err(Error::IndexOutOfRange { .. })));
+        let grown = Value::unflatten([("a[64]", Value::Null), ("a[129].b", Value::Bool(true))]).unwrap();
+        assert_eq!(grown.pointer("/a/129/b"), Some(&Value::Bool(true)));
+    }
+}
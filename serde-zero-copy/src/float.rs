@@ -0,0 +1,136 @@
+use std::fmt;
+use serde::ser::{Error, SerializeMap, SerializeSeq, SerializeStruct};
+use serde::{Serialize, Serializer};
+use serde_json::Number;
+use crate::{Value, RAW_TOKEN};
+
+/// A float JSON has no number for, see [`Value::from_f64`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NonFinite {
+    NaN,
+    Infinity,
+    NegInfinity,
+}
+
+impl NonFinite {
+    pub fn as_f64(self) -> f64 {
+        match self {
+            NonFinite::NaN => f64::NAN,
+            NonFinite::Infinity => f64::INFINITY,
+            NonFinite::NegInfinity => f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl fmt::Display for NonFinite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NonFinite::NaN => "NaN",
+            NonFinite::Infinity => "Infinity",
+            NonFinite::NegInfinity => "-Infinity",
+        })
+    }
+}
+
+/// How [`Value::NonFinite`] is written, see [`Value::non_finite`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonFinitePolicy {
+    /// Fail the serialization.
+    Error,
+    /// As `null`, like a plain `serialize` does and like parsing makes of them.
+    #[default]
+    Null,
+    /// As the bare `NaN`, `Infinity` and `-Infinity` tokens that JSON5 and Python's `json`
+    /// accept. That isn't JSON, only for consumers that want them.
+    Literal,
+}
+
+impl<'a> Value<'a> {
+    /// A number, or for NaN and infinities the [`Value::NonFinite`] that keeps which it was.
+    pub fn from_f64(f: f64) -> Value<'a> {
+        match Number::from_f64(f) {
+            Some(n) => Value::Number(n),
+            None if f.is_nan() => Value::NonFinite(NonFinite::NaN),
+            None if f > 0.0 => Value::NonFinite(NonFinite::Infinity),
+            None => Value::NonFinite(NonFinite::NegInfinity),
+        }
+    }
+
+    /// The value, to serialize with non-finite floats written as `policy` says.
+    pub fn non_finite(&self, policy: NonFinitePolicy) -> WithNonFinite<'_, 'a> {
+        WithNonFinite { value: self, policy }
+    }
+}
+
+/// A value serializing its non-finite floats by a [`NonFinitePolicy`], made by
+/// [`Value::non_finite`].
+#[derive(Debug, Clone, Copy)]
+pub struct WithNonFinite<'v, 'a> {
+    value: &'v Value<'a>,
+    policy: NonFinitePolicy,
+}
+
+impl Serialize for WithNonFinite<'_, '_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+    {
+        let wrap = |value| WithNonFinite { value, policy: self.policy };
+        match (self.value, self.policy) {
+            (Value::NonFinite(f), NonFinitePolicy::Error) => Err(S::Error::custom(format!("{} is not a JSON number", f))),
+            (Value::NonFinite(_), NonFinitePolicy::Null) => serializer.serialize_unit(),
+            (Value::NonFinite(f), NonFinitePolicy::Literal) => {
+                let mut raw = serializer.serialize_struct(RAW_TOKEN, 1)?;
+                raw.serialize_field(RAW_TOKEN, &f.to_string())?;
+                raw.end()
+            }
+            (Value::Array(vec), _) => {
+                let mut seq = serializer.serialize_seq(Some(vec.len()))?;
+                for element in vec {
+                    seq.serialize_element(&wrap(element))?;
+                }
+                seq.end()
+            }
+            (Value::Object(map), _) => {
+                let mut out = serializer.serialize_map(Some(map.len()))?;
+                for (key, member) in map {
+                    out.serialize_entry(key, &wrap(member))?;
+                }
+                out.end()
+            }
+            (value, _) => value.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{NonFinite, NonFinitePolicy};
+
+    #[test]
+    fn non_finite_policies() {
+        let value = Value::Array(vec![Value::from_f64(1.5), Value::from_f64(f64::NAN), Value::from_f64(f64::NEG_INFINITY)]);
+        assert_eq!(value, Value::Array(vec![Value::from_f64(1.5), Value::NonFinite(NonFinite::NaN), Value::NonFinite(NonFinite::NegInfinity)]));
+        assert_eq!(serde_json_nostr::to_string(&value).unwrap(), "[1.5,null,null]");
+        assert_eq!(serde_json_nostr::to_string(&value.non_finite(NonFinitePolicy::Null)).unwrap(), "[1.5,null,null]");
+        assert_eq!(serde_json_nostr::to_string(&value.non_finite(NonFinitePolicy::Literal)).unwrap(), "[1.5,NaN,-Infinity]");
+        assert_eq!(serde_json::to_string(&value.non_finite(NonFinitePolicy::Literal)).unwrap(), "[1.5,NaN,-Infinity]");
+        let err = serde_json_nostr::to_string(&value.non_finite(NonFinitePolicy::Error)).unwrap_err();
+        assert_eq!(err.to_string(), "NaN is not a JSON number");
+    }
+
+    #[test]
+    fn non_finite_from_sources() {
+        let value: Value = serde_json_nostr::from_str(r#"{"nested":[{"n":1}]}"#).unwrap();
+        let infinite = Value::Object([("sum".into(), Value::from_f64(f64::INFINITY)), ("rest".into(), value)].into_iter().collect());
+        assert_eq!(
+            serde_json_nostr::to_string(&infinite.non_finite(NonFinitePolicy::Literal)).unwrap(),
+            r#"{"rest":{"nested":[{"n":1}]},"sum":Infinity}"#
+        );
+        match Value::from_f64(f64::INFINITY) {
+            Value::NonFinite(f) => assert_eq!(f.as_f64(), f64::INFINITY),
+            other => panic!("{:?}", other),
+        }
+    }
+}
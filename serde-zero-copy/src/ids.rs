@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use crate::schema::as_str;
+use crate::{Value, Violation};
+
+/// What an id field holds, `"uuid"` or e.g. `{"hex": 16}` for 16 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdFormat {
+    Uuid,
+    Hex(usize),
+}
+
+fn hex(s: &str) -> Option<u128> {
+    match s.len() <= 32 && s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        true => u128::from_str_radix(s, 16).ok(),
+        false => None,
+    }
+}
+
+fn uuid(s: &str) -> Option<u128> {
+    let b = s.as_bytes();
+    match b.len() {
+        32 => hex(s),
+        36 if [8, 13, 18, 23].iter().all(|&i| b[i] == b'-') => {
+            hex(&[&s[..8], &s[9..13], &s[14..18], &s[19..23], &s[24..]].concat())
+        }
+        _ => None,
+    }
+}
+
+impl IdFormat {
+    pub fn matches(self, s: &str) -> bool {
+        match self {
+            IdFormat::Uuid => uuid(s).is_some(),
+            IdFormat::Hex(len) => s.len() == len && s.bytes().all(|b| b.is_ascii_hexdigit()),
+        }
+    }
+
+    fn expected(self) -> String {
+        match self {
+            IdFormat::Uuid => "expected a UUID".to_string(),
+            IdFormat::Hex(len) => format!("expected {} hex digits", len),
+        }
+    }
+}
+
+impl<'a> Value<'a> {
+    /// A string holding a UUID, hyphenated or as 32 hex digits in either case, as its 128 bits.
+    pub fn as_uuid(&self) -> Option<u128> {
+        uuid(as_str(self)?)
+    }
+
+    pub fn is_hex_id(&self, len: usize) -> bool {
+        as_str(self).is_some_and(|s| IdFormat::Hex(len).matches(s))
+    }
+}
+
+/// Checks the id at each pointer of `fields`, missing ones are left to the schema. A field
+/// that isn't a string of its format is a violation at its pointer.
+pub fn validate_ids(value: &Value, fields: &[(&str, IdFormat)]) -> Vec<Violation> {
+    fields
+        .iter()
+        .filter_map(|&(pointer, format)| {
+            let found = value.pointer(pointer)?;
+            match as_str(found).is_some_and(|s| format.matches(s)) {
+                true => None,
+                false => Some(Violation { pointer: pointer.to_string(), message: format.expected() }),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Value, Violation};
+    use super::{validate_ids, IdFormat};
+
+    #[test]
+    fn uuid_accessor() {
+        let value: Value = serde_json_nostr::from_str(
+            r#"["67e55044-10b1-426f-9247-bb680e5fe0c8","67E5504410B1426F9247BB680E5FE0C8","67e55044-10b1-426f-9247-bb680e5fe0c","67e55044_10b1_426f_9247_bb680e5fe0c8",1]"#,
+        ).unwrap();
+        let uuids: Vec<_> = (0..5).map(|i| value.pointer(&format!("/{}", i)).and_then(Value::as_uuid)).collect();
+        assert_eq!(uuids, [Some(0x67e5504410b1426f9247bb680e5fe0c8), Some(0x67e5504410b1426f9247bb680e5fe0c8), None, None, None]);
+        assert!(Value::Str("00ff").is_hex_id(4));
+        assert!(!Value::Str("00fg").is_hex_id(4));
+        assert_eq!(serde_json::from_str::<IdFormat>(r#"{"hex":16}"#).unwrap(), IdFormat::Hex(16));
+    }
+
+    #[test]
+    fn ids_violations_by_pointer() {
+        let value: Value = serde_json_nostr::from_str(
+            r#"{"id":"67e55044-10b1-426f-9247-bb680e5fe0c8","trace":"0af7651916cd43dd","span":"xyz","owner":{"id":42}}"#,
+        ).unwrap();
+        let fields = [
+            ("/id", IdFormat::Uuid),
+            ("/trace", IdFormat::Hex(16)),
+            ("/span", IdFormat::Hex(16)),
+            ("/owner/id", IdFormat::Uuid),
+            ("/missing", IdFormat::Uuid),
+        ];
+        assert_eq!(
+            validate_ids(&value, &fields),
+            [
+                Violation { pointer: "/span".to_string(), message: "expected 16 hex digits".to_string() },
+                Violation { pointer: "/owner/id".to_string(), message: "expected a UUID".to_string() },
+            ]
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use core::fmt;
+use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
 use std::marker::PhantomData;
 use serde::de::{DeserializeSeed, MapAccess, SeqAccess, Visitor};
@@ -7,6 +8,96 @@ use serde::ser::Error;
 use serde_json::Number;
 use yoke_derive::Yokeable;
 
+pub mod aggregate;
+#[cfg(feature = "rkyv")]
+pub mod archive;
+pub mod array;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod avro;
+pub mod binary;
+pub mod builder;
+pub mod cancel;
+pub mod cmp;
+pub mod codec;
+pub mod coerce;
+pub mod compiled;
+pub mod content;
+pub mod csv;
+#[cfg(feature = "chrono")]
+pub mod datetime;
+pub mod entry;
+pub mod escapes;
+pub mod flatten;
+pub mod float;
+pub mod ids;
+pub mod mask;
+pub mod multimap;
+pub mod normalize;
+pub mod nulls;
+pub mod parse;
+#[cfg(feature = "postcard")]
+pub mod postcard;
+pub mod range;
+pub mod rename;
+pub mod rules;
+pub mod schema;
+pub mod slice;
+pub mod snapshot;
+pub mod splice;
+pub mod stats;
+#[cfg(any(test, feature = "proptest"))]
+pub mod strategy;
+pub mod symbols;
+pub mod tape;
+pub mod template;
+#[cfg(feature = "toml")]
+pub mod toml;
+pub mod urlencoded;
+pub mod visit;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "xml")]
+pub mod xml;
+#[cfg(feature = "yaml")]
+pub mod yaml;
+pub mod yielding;
+
+#[cfg(feature = "arrow")]
+pub use crate::arrow::{to_record_batches, ArrowOptions};
+pub use avro::{from_avro_slice, to_avro, AvroSchema};
+pub use binary::BytesPolicy;
+pub use builder::ValueBuilder;
+pub use codec::Codec;
+pub use compiled::{CompiledPath, CompiledPointer};
+pub use content::{flatten_borrowed, from_value, BorrowedContent};
+pub use csv::{from_csv, CsvOptions};
+pub use entry::KeyInterner;
+pub use float::{NonFinite, NonFinitePolicy};
+pub use ids::{validate_ids, IdFormat};
+pub use mask::{project, FieldMask};
+pub use multimap::MultiValue;
+pub use nulls::NullPolicy;
+pub use parse::{from_slice_with, from_str_with, ParseOptions, Preamble, Trailing};
+#[cfg(feature = "postcard")]
+pub use crate::postcard::{from_postcard_slice, to_postcard};
+pub use rename::{Case, KeyRenamer};
+pub use rules::{evaluate, Rule};
+pub use schema::{validate, Violation};
+pub use splice::RawJson;
+pub use stats::Stats;
+pub use symbols::Symbols;
+pub use tape::StructuralIndex;
+pub use template::Template;
+#[cfg(feature = "toml")]
+pub use crate::toml::from_toml_str;
+pub use urlencoded::{from_urlencoded, to_urlencoded};
+pub use visit::{Fold, Segment, Visit};
+#[cfg(feature = "xml")]
+pub use xml::{from_xml_str, XmlConvention};
+#[cfg(feature = "yaml")]
+pub use yaml::from_yaml_str;
+
 macro_rules! tri {
     ($e:expr $(,)?) => {
         match $e {
@@ -16,10 +107,27 @@ macro_rules! tri {
     };
 }
 
+// the key serde_json's deserializer gives numbers under, with `arbitrary_precision`
+#[cfg(feature = "decimal")]
+pub(crate) const NUMBER_TOKEN: &str = "$serde_json::private::Number";
+
+// the numeral as written, so `0.10` and `12345678901234567890123` serialize back as they came
+#[cfg(feature = "decimal")]
+pub(crate) fn next_number<'de, V>(visitor: &mut V) -> Result<Number, V::Error>
+    where
+        V: MapAccess<'de>,
+{
+    let numeral: String = visitor.next_value()?;
+    numeral.parse().map_err(de::Error::custom)
+}
+
 struct KeyClassifier;
 
 enum KeyClass<'a> {
-    Map(&'a str),
+    Map(Cow<'a, str>),
+    BigInt,
+    #[cfg(feature = "decimal")]
+    Number,
 }
 
 impl<'de> DeserializeSeed<'de> for KeyClassifier {
@@ -44,21 +152,153 @@ impl<'de> Visitor<'de> for KeyClassifier {
         where
             E: de::Error,
     {
-        Ok(KeyClass::Map(s))
+        match s {
+            #[cfg(feature = "decimal")]
+            NUMBER_TOKEN => Ok(KeyClass::Number),
+            serde_json_nostr::de::BIG_INT_TOKEN => Ok(KeyClass::BigInt),
+            _ => Ok(KeyClass::Map(Cow::Borrowed(s))),
+        }
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+    {
+        Ok(KeyClass::Map(Cow::Owned(s.to_string())))
+    }
+}
+
+/// An object key, borrowed unless it had escapes.
+pub(crate) struct KeySeed;
+
+impl<'de> DeserializeSeed<'de> for KeySeed {
+    type Value = Cow<'de, str>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for KeySeed {
+    type Value = Cow<'de, str>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string key")
+    }
+
+    fn visit_borrowed_str<E>(self, s: &'de str) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+    {
+        Ok(Cow::Borrowed(s))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+    {
+        Ok(Cow::Owned(s.to_string()))
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+    {
+        Ok(Cow::Owned(s))
     }
 }
 
-#[derive(Yokeable, Clone, Eq, PartialEq, Debug)]
+#[derive(Yokeable, Clone, Debug, Default)]
 pub enum Value<'a> {
+    #[default]
     Null,
     Bool(bool),
     Number(Number),
+    /// An integer too wide for a [`Number`], as written. Borrowed when parsed by
+    /// `serde_json_nostr`, owned when it came as an `i128` or `u128`.
+    BigInt(Cow<'a, str>),
+    /// NaN or an infinity, made by [`Value::from_f64`]. Serialized as null, see
+    /// [`Value::non_finite`] for other ways.
+    NonFinite(NonFinite),
+    /// What `serde_json_nostr` parses strings as. Serialized by `serialize_bytes`, see
+    /// [`Value::bytes`] for other ways.
     Bytes(&'a [u8]),
     Str(&'a str),
     String(String),
     Array(Vec<Value<'a>>),
     // Object(HashMap<&'a str, Value<'a>>),
-    Object(BTreeMap<&'a str, Value<'a>>),
+    /// Keys borrow from the input, those with escapes in them are owned.
+    Object(BTreeMap<Cow<'a, str>, Value<'a>>),
+}
+
+impl<'a> Value<'a> {
+    /// Looks up a JSON pointer (RFC 6901) like `/items/0/name`, the empty pointer is the value
+    /// itself.
+    pub fn pointer(&self, pointer: &str) -> Option<&Value<'a>> {
+        if pointer.is_empty() {
+            return Some(self);
+        }
+        pointer.strip_prefix('/')?.split('/').try_fold(self, |value, token| {
+            let token = token.replace("~1", "/").replace("~0", "~");
+            match value {
+                Value::Object(map) => map.get(token.as_str()),
+                Value::Array(vec) => vec.get(token.parse::<usize>().ok()?),
+                _ => None,
+            }
+        })
+    }
+
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Value<'a>> {
+        if pointer.is_empty() {
+            return Some(self);
+        }
+        pointer.strip_prefix('/')?.split('/').try_fold(self, |value, token| {
+            let token = token.replace("~1", "/").replace("~0", "~");
+            match value {
+                Value::Object(map) => map.get_mut(token.as_str()),
+                Value::Array(vec) => vec.get_mut(token.parse::<usize>().ok()?),
+                _ => None,
+            }
+        })
+    }
+
+    /// Moves the value at `pointer` out, leaving null, e.g. to re-parent a subtree without
+    /// cloning it. `std::mem::take` does the same for a value at hand.
+    pub fn take_pointer(&mut self, pointer: &str) -> Option<Value<'a>> {
+        self.pointer_mut(pointer).map(std::mem::take)
+    }
+}
+
+// a number unless it's wider than 64 bits
+pub(crate) fn from_i128<'a>(n: i128) -> Value<'a> {
+    Number::from_i128(n).map_or_else(|| Value::BigInt(Cow::Owned(n.to_string())), Value::Number)
+}
+
+pub(crate) fn from_u128<'a>(n: u128) -> Value<'a> {
+    Number::from_u128(n).map_or_else(|| Value::BigInt(Cow::Owned(n.to_string())), Value::Number)
+}
+
+// serde_json's token for a value written out as it is
+pub(crate) const RAW_TOKEN: &str = "$serde_json::private::RawValue";
+
+// as a bare numeral, through `i128`/`u128` when it fits and as a raw JSON value beyond
+fn serialize_big_int<S>(numeral: &str, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+{
+    if let Ok(n) = numeral.parse::<i128>() {
+        return serializer.serialize_i128(n);
+    }
+    if let Ok(n) = numeral.parse::<u128>() {
+        return serializer.serialize_u128(n);
+    }
+    use serde::ser::SerializeStruct;
+    let mut raw = tri!(serializer.serialize_struct(RAW_TOKEN, 1));
+    tri!(raw.serialize_field(RAW_TOKEN, numeral));
+    raw.end()
 }
 
 impl<'a> Serialize for Value<'a> {
@@ -67,6 +307,8 @@ impl<'a> Serialize for Value<'a> {
             Value::Null => serializer.serialize_unit(),
             Value::Bool(b) => serializer.serialize_bool(*b),
             Value::Number(n) => n.serialize(serializer),
+            Value::BigInt(n) => serialize_big_int(n, serializer),
+            Value::NonFinite(_) => serializer.serialize_unit(),
             Value::Bytes(b) => serializer.serialize_bytes(b),
             Value::Str(s) => s.serialize(serializer),
             Value::Array(v) => v.serialize(serializer),
@@ -83,9 +325,10 @@ impl<'a> Serialize for Value<'a> {
     }
 }
 
-impl<'de> Deserialize<'de> for Value<'de> {
+// `'de: 'a` rather than `Value<'de>`, so that a value can be a field of a borrowed struct
+impl<'de: 'a, 'a> Deserialize<'de> for Value<'a> {
     #[inline]
-    fn deserialize<D>(deserializer: D) -> Result<Value<'de>, D::Error>
+    fn deserialize<D>(deserializer: D) -> Result<Value<'a>, D::Error>
         where
             D: serde::Deserializer<'de>,
     {
@@ -98,7 +341,6 @@ impl<'de> Deserialize<'de> for Value<'de> {
             type Value = Value<'de>;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                panic!();
                 formatter.write_str("any valid JSON value")
             }
 
@@ -117,6 +359,16 @@ impl<'de> Deserialize<'de> for Value<'de> {
                 Ok(Value::Number(value.into()))
             }
 
+            #[inline]
+            fn visit_i128<E>(self, value: i128) -> Result<Value<'de>, E> {
+                Ok(from_i128(value))
+            }
+
+            #[inline]
+            fn visit_u128<E>(self, value: u128) -> Result<Value<'de>, E> {
+                Ok(from_u128(value))
+            }
+
             #[inline]
             fn visit_f64<E>(self, value: f64) -> Result<Value<'de>, E> {
                 Ok(Number::from_f64(value).map_or(Value::Null, Value::Number))
@@ -128,10 +380,26 @@ impl<'de> Deserialize<'de> for Value<'de> {
                 where
                     E: serde::de::Error,
             {
-                println!("using visit_borrowed_str");
                 Ok(Value::Str(value))
             }
 
+            // strings a deserializer can't lend, e.g. ones with escapes buffered by serde
+            #[inline]
+            fn visit_str<E>(self, value: &str) -> Result<Value<'de>, E>
+                where
+                    E: serde::de::Error,
+            {
+                Ok(Value::String(value.to_string()))
+            }
+
+            #[inline]
+            fn visit_string<E>(self, value: String) -> Result<Value<'de>, E>
+                where
+                    E: serde::de::Error,
+            {
+                Ok(Value::String(value))
+            }
+
             #[inline]
             fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<Value<'de>, E> where
                 E: serde::de::Error,
@@ -143,7 +411,6 @@ impl<'de> Deserialize<'de> for Value<'de> {
             fn visit_bytes<E>(self, v: &[u8]) -> Result<Value<'de>, E> where
                 E: serde::de::Error,
             {
-                println!("using visit_bytes");
                 Ok(Value::String(String::from_utf8_lossy(v).into_owned()))
             }
 
@@ -165,6 +432,15 @@ impl<'de> Deserialize<'de> for Value<'de> {
                 Ok(Value::Null)
             }
 
+            // from deserializers that don't know the big integer hint
+            #[inline]
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Value<'de>, D::Error>
+                where
+                    D: serde::Deserializer<'de>,
+            {
+                deserializer.deserialize_any(self)
+            }
+
             #[inline]
             fn visit_seq<V>(self, mut visitor: V) -> Result<Value<'de>, V::Error>
                 where
@@ -189,18 +465,23 @@ impl<'de> Deserialize<'de> for Value<'de> {
                         let mut values = BTreeMap::new();
 
                         values.insert(first_key, tri!(visitor.next_value()));
-                        while let Some((key, value)) = tri!(visitor.next_entry()) {
-                            values.insert(key, value);
+                        while let Some(key) = tri!(visitor.next_key_seed(KeySeed)) {
+                            values.insert(key, tri!(visitor.next_value()));
                         }
 
                         Ok(Value::Object(values))
                     }
+                    #[cfg(feature = "decimal")]
+                    Some(KeyClass::Number) => crate::next_number(&mut visitor).map(Value::Number),
+                    Some(KeyClass::BigInt) => visitor.next_value().map(|numeral| Value::BigInt(Cow::Borrowed(numeral))),
                     None => Ok(Value::Object(BTreeMap::new())),
                 }
             }
         }
 
-        deserializer.deserialize_any(ValueVisitor { marker: PhantomData::<Value<'de>>, lifetime: PhantomData })
+        // asks serde_json_nostr for integers wider than 64 bits as written
+        let visitor = ValueVisitor { marker: PhantomData::<Value<'de>>, lifetime: PhantomData };
+        deserializer.deserialize_newtype_struct(serde_json_nostr::de::BIG_INT_TOKEN, visitor)
     }
 }
 
@@ -783,6 +1064,7 @@ mod tests {
 
 
     #[test]
+    #[cfg(not(target_arch = "wasm32"))]
     fn serde_zero_copy_large_value_simd_json() {
         let mut file = std::fs::File::open("src/sample.json").unwrap();
         let mut contents = Vec::new();
@@ -840,6 +1122,76 @@ mod tests {
             serde_json::from_str::<serde_json::Value>(json_str).unwrap());
     }
 
+    #[test]
+    fn serde_zero_copy_float_roundtrip() {
+        let json_str = r#"[22E23,6e46,0.1]"#;
+        let result: super::Value = serde_json_nostr::from_str(json_str).unwrap();
+        let serialized = serde_json_nostr::to_vec(&result).unwrap();
+        let reparsed: super::Value = serde_json_nostr::from_slice(&serialized).unwrap();
+        assert_eq!(result, reparsed);
+        #[cfg(not(feature = "decimal"))]
+        assert_eq!(serde_json_nostr::to_string(&result).unwrap(), "[2.2e24,6e46,0.1]");
+    }
+
+    #[test]
+    fn serde_zero_copy_escaped_keys() {
+        use std::borrow::Cow;
+        let json_str = r#"{"plain":1,"tab\tkey":{"ü":2}}"#;
+        let result: super::Value = serde_json_nostr::from_str(json_str).unwrap();
+        let map = match &result {
+            super::Value::Object(map) => map,
+            other => panic!("{:?}", other),
+        };
+        assert!(matches!(map.get_key_value("plain"), Some((Cow::Borrowed(key), _)) if json_str.as_bytes().as_ptr_range().contains(&key.as_ptr())));
+        assert!(matches!(map.get_key_value("tab\tkey"), Some((Cow::Owned(_), _))));
+        assert!(matches!(result.pointer("/tab\tkey/ü"), Some(super::Value::Number(_))));
+        assert_eq!(serde_json_nostr::to_string(&result).unwrap(), r#"{"plain":1,"tab\tkey":{"ü":2}}"#);
+        assert_eq!(serde_json::from_str::<super::Value>(json_str).unwrap(), result);
+    }
+
+    #[cfg(not(feature = "decimal"))]
+    #[test]
+    fn serde_zero_copy_big_integers() {
+        use serde::Deserialize;
+        use serde::de::value::{Error, U128Deserializer};
+        let json_str = r#"{"id":123456789012345678901234,"neg":-9223372036854775809,"huge":1234567890123456789012345678901234567890,"max":18446744073709551615}"#;
+        let result: super::Value = serde_json_nostr::from_str(json_str).unwrap();
+        let expected = r#"{"huge":1234567890123456789012345678901234567890,"id":123456789012345678901234,"max":18446744073709551615,"neg":-9223372036854775809}"#;
+        assert_eq!(serde_json_nostr::to_string(&result).unwrap(), expected);
+        assert_eq!(serde_json::to_string(&result).unwrap(), expected);
+        match result.pointer("/id") {
+            Some(super::Value::BigInt(std::borrow::Cow::Borrowed(id))) => assert!(json_str.as_bytes().as_ptr_range().contains(&id.as_ptr())),
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(result.pointer("/max"), Some(super::Value::Number(_))));
+        let wide = super::Value::deserialize(U128Deserializer::<Error>::new(u128::MAX)).unwrap();
+        assert_eq!(serde_json::to_string(&wide).unwrap(), u128::MAX.to_string());
+    }
+
+    // `arbitrary_precision` keeps them for everything
+    #[cfg(not(feature = "decimal"))]
+    #[test]
+    fn big_integers_are_only_kept_for_values() {
+        let json_str = r#"[123456789012345678901234,{"id":-123456789012345678901234}]"#;
+        let other: serde_json_nostr::Value = serde_json_nostr::from_str(json_str).unwrap();
+        assert_eq!(other[0].as_f64(), Some(1.2345678901234568e23));
+        assert_eq!(other[1]["id"].as_f64(), Some(-1.2345678901234568e23));
+        let floats: (f64, std::collections::HashMap<&str, super::Value>) = serde_json_nostr::from_str(json_str).unwrap();
+        assert_eq!(floats.0, 1.2345678901234568e23);
+        assert!(matches!(floats.1["id"], super::Value::BigInt(_)));
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn serde_zero_copy_decimal_exact() {
+        let json_str = r#"{"price":19.990000000000000001,"total":0.10,"id":123456789012345678901234567890,"n":-7}"#;
+        let result: super::Value = serde_json_nostr::from_str(json_str).unwrap();
+        let expected = r#"{"id":123456789012345678901234567890,"n":-7,"price":19.990000000000000001,"total":0.10}"#;
+        assert_eq!(serde_json_nostr::to_string(&result).unwrap(), expected);
+        assert_eq!(serde_json::to_string(&result).unwrap(), expected);
+        assert_eq!(result.pointer("/n").and_then(|n| match n { super::Value::Number(n) => n.as_i64(), _ => None }), Some(-7));
+    }
+
     #[test]
     fn serde_bytes_test_json() {
         let json_str = r#"{"id":123,"name":"John Doe","screen_name":"Unidentified","location":"Fringe","nested":{"id":123,"name":"John Doe","screen_name":"Unidentified","location":"Fringe"}}"#;
@@ -0,0 +1,103 @@
+use std::borrow::Cow;
+use crate::Value;
+
+/// Which members of a value to keep, like a GraphQL selection set. A field with an empty mask
+/// keeps its whole value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldMask {
+    pub fields: Vec<MaskField>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaskField {
+    pub name: String,
+    /// The key the member is written under, `name` if not given.
+    pub alias: Option<String>,
+    pub mask: FieldMask,
+}
+
+impl MaskField {
+    pub fn key(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+}
+
+impl FieldMask {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Adds `name`, keeping its whole value.
+    pub fn field(self, name: &str) -> Self {
+        self.nested(name, FieldMask::default())
+    }
+
+    /// Adds `name` with its own selection.
+    pub fn nested(mut self, name: &str, mask: FieldMask) -> Self {
+        self.fields.push(MaskField { name: name.to_string(), alias: None, mask });
+        self
+    }
+}
+
+/// Keeps the masked members of objects, applying the mask to each element of arrays. Missing
+/// members and members of scalars come out as null. Strings and bytes stay borrowed, keys
+/// borrow from the mask. Only what's selected is copied out of `value`.
+pub fn project<'v, 'a: 'v>(value: &'v Value<'a>, mask: &'v FieldMask) -> Value<'v> {
+    if mask.is_empty() {
+        return value.clone();
+    }
+    match value {
+        Value::Array(vec) => Value::Array(vec.iter().map(|item| project(item, mask)).collect()),
+        // a member selected twice is there under each key
+        Value::Object(map) => Value::Object(
+            mask.fields
+                .iter()
+                .map(|field| {
+                    let member = map.get(field.name.as_str()).map_or(Value::Null, |member| project(member, &field.mask));
+                    (Cow::Borrowed(field.key()), member)
+                })
+                .collect(),
+        ),
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{project, FieldMask, MaskField};
+
+    #[test]
+    fn mask_projects_nested_members() {
+        let input = br#"{"id":1,"name":"Jane","posts":[{"title":"a","body":"x"},{"title":"b"}],"address":{"city":"Oslo","zip":"0150"}}"#;
+        let value: Value = serde_json_nostr::from_slice(input).unwrap();
+        let mask = FieldMask::default()
+            .field("name")
+            .nested("posts", FieldMask::default().field("title").field("missing"))
+            .nested("address", FieldMask::default().field("city"));
+        assert_eq!(
+            serde_json_nostr::to_string(&project(&value, &mask)).unwrap(),
+            r#"{"address":{"city":"Oslo"},"name":"Jane","posts":[{"missing":null,"title":"a"},{"missing":null,"title":"b"}]}"#
+        );
+    }
+
+    #[test]
+    fn mask_aliases_and_borrows() {
+        let input = br#"{"name":"Jane","id":{"nested":true}}"#;
+        let value: Value = serde_json_nostr::from_slice(input).unwrap();
+        let mut mask = FieldMask::default().nested("id", FieldMask::default().field("nope"));
+        mask.fields.push(MaskField { name: "name".to_string(), alias: Some("fullName".to_string()), mask: FieldMask::default() });
+        let projected = project(&value, &mask);
+        match &projected {
+            Value::Object(map) => match map["fullName"] {
+                Value::Bytes(name) => assert!(input.as_ptr_range().contains(&name.as_ptr())),
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+        assert_eq!(serde_json_nostr::to_string(&projected).unwrap(), r#"{"fullName":"Jane","id":{"nope":null}}"#);
+        assert_eq!(project(&Value::Bool(true), &mask), Value::Null);
+        mask.fields.push(MaskField { name: "name".to_string(), alias: Some("again".to_string()), mask: FieldMask::default() });
+        assert_eq!(serde_json_nostr::to_string(&project(&value, &mask)).unwrap(), r#"{"again":"Jane","fullName":"Jane","id":{"nope":null}}"#);
+    }
+}
@@ -0,0 +1,241 @@
+use core::fmt;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Number;
+use yoke_derive::Yokeable;
+use crate::{NonFinite, Value};
+
+/// Like [`Value`] but objects keep their members in document order, duplicate keys included,
+/// for payloads where repeating a key means something. Parse into it instead of `Value` to
+/// select the mode, serializing writes the members back as they were read.
+#[derive(Yokeable, Clone, PartialEq, Debug)]
+pub enum MultiValue<'a> {
+    Null,
+    Bool(bool),
+    Number(Number),
+    BigInt(Cow<'a, str>),
+    NonFinite(NonFinite),
+    Bytes(&'a [u8]),
+    Str(&'a str),
+    String(String),
+    Array(Vec<MultiValue<'a>>),
+    Object(Vec<(Cow<'a, str>, MultiValue<'a>)>),
+}
+
+impl<'a> MultiValue<'a> {
+    fn members(&self) -> &[(Cow<'a, str>, MultiValue<'a>)] {
+        match self {
+            MultiValue::Object(members) => members,
+            _ => &[],
+        }
+    }
+
+    /// Every member named `key` in order, empty for anything but an object.
+    pub fn get_all<'s>(&'s self, key: &'s str) -> impl Iterator<Item = &'s MultiValue<'a>> + 's {
+        self.members().iter().filter(move |(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    /// The last member named `key`, the one a `Value` would have kept.
+    pub fn get(&self, key: &str) -> Option<&MultiValue<'a>> {
+        self.members().iter().rev().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    /// Collapses objects into maps, later duplicates replacing earlier ones as when parsing
+    /// into a `Value` directly.
+    pub fn into_value(self) -> Value<'a> {
+        match self {
+            MultiValue::Null => Value::Null,
+            MultiValue::Bool(b) => Value::Bool(b),
+            MultiValue::Number(n) => Value::Number(n),
+            MultiValue::BigInt(n) => Value::BigInt(n),
+            MultiValue::NonFinite(f) => Value::NonFinite(f),
+            MultiValue::Bytes(b) => Value::Bytes(b),
+            MultiValue::Str(s) => Value::Str(s),
+            MultiValue::String(s) => Value::String(s),
+            MultiValue::Array(vec) => Value::Array(vec.into_iter().map(MultiValue::into_value).collect()),
+            MultiValue::Object(members) => {
+                Value::Object(members.into_iter().map(|(k, v)| (k, v.into_value())).collect::<BTreeMap<_, _>>())
+            }
+        }
+    }
+}
+
+impl<'a> From<Value<'a>> for MultiValue<'a> {
+    fn from(value: Value<'a>) -> Self {
+        match value {
+            Value::Null => MultiValue::Null,
+            Value::Bool(b) => MultiValue::Bool(b),
+            Value::Number(n) => MultiValue::Number(n),
+            Value::BigInt(n) => MultiValue::BigInt(n),
+            Value::NonFinite(f) => MultiValue::NonFinite(f),
+            Value::Bytes(b) => MultiValue::Bytes(b),
+            Value::Str(s) => MultiValue::Str(s),
+            Value::String(s) => MultiValue::String(s),
+            Value::Array(vec) => MultiValue::Array(vec.into_iter().map(MultiValue::from).collect()),
+            Value::Object(map) => MultiValue::Object(map.into_iter().map(|(k, v)| (k, v.into())).collect()),
+        }
+    }
+}
+
+impl<'a> Serialize for MultiValue<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        match self {
+            MultiValue::Null => serializer.serialize_unit(),
+            MultiValue::Bool(b) => serializer.serialize_bool(*b),
+            MultiValue::Number(n) => n.serialize(serializer),
+            MultiValue::BigInt(n) => Value::BigInt(Cow::Borrowed(n)).serialize(serializer),
+            MultiValue::NonFinite(f) => Value::NonFinite(*f).serialize(serializer),
+            MultiValue::Bytes(b) => serializer.serialize_bytes(b),
+            MultiValue::Str(s) => s.serialize(serializer),
+            MultiValue::String(s) => s.serialize(serializer),
+            MultiValue::Array(v) => v.serialize(serializer),
+            MultiValue::Object(members) => {
+                use serde::ser::SerializeMap;
+                let mut map = serializer.serialize_map(Some(members.len()))?;
+                for (k, v) in members {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MultiValue<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<MultiValue<'de>, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        struct MultiValueVisitor;
+
+        impl<'de> Visitor<'de> for MultiValueVisitor {
+            type Value = MultiValue<'de>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("any valid JSON value")
+            }
+
+            fn visit_bool<E>(self, value: bool) -> Result<MultiValue<'de>, E> {
+                Ok(MultiValue::Bool(value))
+            }
+
+            fn visit_i64<E>(self, value: i64) -> Result<MultiValue<'de>, E> {
+                Ok(MultiValue::Number(value.into()))
+            }
+
+            fn visit_u64<E>(self, value: u64) -> Result<MultiValue<'de>, E> {
+                Ok(MultiValue::Number(value.into()))
+            }
+
+            fn visit_i128<E>(self, value: i128) -> Result<MultiValue<'de>, E> {
+                Ok(crate::from_i128(value).into())
+            }
+
+            fn visit_u128<E>(self, value: u128) -> Result<MultiValue<'de>, E> {
+                Ok(crate::from_u128(value).into())
+            }
+
+            fn visit_f64<E>(self, value: f64) -> Result<MultiValue<'de>, E> {
+                Ok(Number::from_f64(value).map_or(MultiValue::Null, MultiValue::Number))
+            }
+
+            fn visit_borrowed_str<E>(self, value: &'de str) -> Result<MultiValue<'de>, E> {
+                Ok(MultiValue::Str(value))
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<MultiValue<'de>, E> {
+                Ok(MultiValue::String(value.to_string()))
+            }
+
+            fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<MultiValue<'de>, E> {
+                Ok(MultiValue::Bytes(v))
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<MultiValue<'de>, E> {
+                Ok(MultiValue::String(String::from_utf8_lossy(v).into_owned()))
+            }
+
+            fn visit_none<E>(self) -> Result<MultiValue<'de>, E> {
+                Ok(MultiValue::Null)
+            }
+
+            fn visit_some<D>(self, deserializer: D) -> Result<MultiValue<'de>, D::Error>
+                where
+                    D: serde::Deserializer<'de>,
+            {
+                Deserialize::deserialize(deserializer)
+            }
+
+            fn visit_unit<E>(self) -> Result<MultiValue<'de>, E> {
+                Ok(MultiValue::Null)
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<MultiValue<'de>, D::Error>
+                where
+                    D: serde::Deserializer<'de>,
+            {
+                deserializer.deserialize_any(self)
+            }
+
+            fn visit_seq<V>(self, mut visitor: V) -> Result<MultiValue<'de>, V::Error>
+                where
+                    V: SeqAccess<'de>,
+            {
+                let mut vec = Vec::new();
+                while let Some(elem) = visitor.next_element()? {
+                    vec.push(elem);
+                }
+                Ok(MultiValue::Array(vec))
+            }
+
+            fn visit_map<V>(self, mut visitor: V) -> Result<MultiValue<'de>, V::Error>
+                where
+                    V: MapAccess<'de>,
+            {
+                let mut members = Vec::new();
+                while let Some(key) = visitor.next_key_seed(crate::KeySeed)? {
+                    #[cfg(feature = "decimal")]
+                    if key == crate::NUMBER_TOKEN {
+                        return crate::next_number(&mut visitor).map(MultiValue::Number);
+                    }
+                    if key == serde_json_nostr::de::BIG_INT_TOKEN {
+                        return visitor.next_value().map(|numeral| MultiValue::BigInt(Cow::Borrowed(numeral)));
+                    }
+                    members.push((key, visitor.next_value()?));
+                }
+                Ok(MultiValue::Object(members))
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(serde_json_nostr::de::BIG_INT_TOKEN, MultiValueVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::MultiValue;
+
+    #[test]
+    fn multimap_keeps_duplicates_in_order() {
+        let input = r#"{"z":1,"filter":"a","nested":{"x":[1],"x":[2]},"filter":"b"}"#;
+        let value: MultiValue = serde_json_nostr::from_str(input).unwrap();
+        assert_eq!(serde_json_nostr::to_string(&value).unwrap(), input);
+        let filters: Vec<_> = value.get_all("filter").collect();
+        assert_eq!(filters, vec![&MultiValue::Bytes(b"a"), &MultiValue::Bytes(b"b")]);
+        assert_eq!(value.get("nested").unwrap().get_all("x").count(), 2);
+        assert_eq!(value.get("missing"), None);
+    }
+
+    #[test]
+    fn multimap_collapses_like_value() {
+        let input = r#"{"k":1,"a":[{"k":true,"k":false}],"k":2}"#;
+        let multi: MultiValue = serde_json_nostr::from_str(input).unwrap();
+        let direct: Value = serde_json_nostr::from_str(input).unwrap();
+        assert_eq!(multi.into_value(), direct);
+        let back = MultiValue::from(direct);
+        assert_eq!(serde_json_nostr::to_string(&back).unwrap(), r#"{"a":[{"k":false}],"k":2}"#);
+    }
+}
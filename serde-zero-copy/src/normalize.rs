@@ -0,0 +1,113 @@
+use std::borrow::Cow;
+use crate::visit::{Fold, Segment};
+use crate::Value;
+
+impl<'a> Value<'a> {
+    /// Replaces every string, object keys excluded, with `f` of it. Strings `f` hands back
+    /// borrowed stay borrowed from the original input, so no-ops and slicing such as
+    /// [`trim`] don't allocate. Bytes that aren't utf-8 are left alone.
+    pub fn map_strings<F>(self, f: F) -> Value<'a>
+        where
+            F: for<'s> FnMut(&'s str) -> Cow<'s, str>,
+    {
+        self.fold(&mut MapStrings(f))
+    }
+}
+
+struct MapStrings<F>(F);
+
+impl<'a, F> Fold<'a> for MapStrings<F>
+    where
+        F: for<'s> FnMut(&'s str) -> Cow<'s, str>,
+{
+    fn exit(&mut self, _: &[Segment<'a>], value: Value<'a>) -> Option<Value<'a>> {
+        let f = &mut self.0;
+        Some(match value {
+            Value::Str(s) => match f(s) {
+                Cow::Borrowed(s) => Value::Str(s),
+                Cow::Owned(s) => Value::String(s),
+            },
+            Value::Bytes(b) => match std::str::from_utf8(b) {
+                Ok(s) => match f(s) {
+                    Cow::Borrowed(s) => Value::Bytes(s.as_bytes()),
+                    Cow::Owned(s) => Value::String(s),
+                },
+                Err(_) => Value::Bytes(b),
+            },
+            Value::String(owned) => match f(&owned) {
+                Cow::Borrowed(s) if s.len() == owned.len() => Value::String(owned),
+                s => Value::String(s.into_owned()),
+            },
+            other => other,
+        })
+    }
+}
+
+pub fn trim(s: &str) -> Cow<'_, str> {
+    Cow::Borrowed(s.trim())
+}
+
+pub fn lowercase(s: &str) -> Cow<'_, str> {
+    if s.chars().any(char::is_uppercase) { Cow::Owned(s.to_lowercase()) } else { Cow::Borrowed(s) }
+}
+
+pub fn uppercase(s: &str) -> Cow<'_, str> {
+    if s.chars().any(char::is_lowercase) { Cow::Owned(s.to_uppercase()) } else { Cow::Borrowed(s) }
+}
+
+/// Trims and turns every run of whitespace into a single space.
+pub fn collapse_whitespace(s: &str) -> Cow<'_, str> {
+    let trimmed = s.trim();
+    let mut previous_space = false;
+    let collapsed = trimmed.chars().all(|c| {
+        let ok = (c == ' ' && !previous_space) || !c.is_whitespace();
+        previous_space = c.is_whitespace();
+        ok
+    });
+    if collapsed {
+        return Cow::Borrowed(trimmed);
+    }
+    Cow::Owned(trimmed.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use crate::Value;
+    use super::{collapse_whitespace, lowercase, trim};
+
+    #[test]
+    fn map_strings_keeps_borrows() {
+        let input = br#"{"Name":"  jane ","tags":["Admin","ops"],"bio":"tab\tand\nnewline","n":1}"#;
+        let value: Value = serde_json_nostr::from_slice(input).unwrap();
+        let value = value.map_strings(trim).map_strings(lowercase);
+        let borrowed = |v: &Value| match v {
+            Value::Bytes(b) => input.as_ptr_range().contains(&b.as_ptr()),
+            _ => false,
+        };
+        match &value {
+            Value::Object(map) => {
+                assert!(map.contains_key("Name"));
+                assert!(borrowed(&map["Name"]));
+                match &map["tags"] {
+                    Value::Array(tags) => assert_eq!((borrowed(&tags[0]), borrowed(&tags[1])), (false, true)),
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+        assert_eq!(
+            serde_json_nostr::to_string(&value).unwrap(),
+            r#"{"Name":"jane","bio":"tab\tand\nnewline","n":1,"tags":["admin","ops"]}"#
+        );
+    }
+
+    #[test]
+    fn normalizers_borrow_when_unchanged() {
+        assert!(matches!(collapse_whitespace(" a b "), Cow::Borrowed("a b")));
+        assert_eq!(collapse_whitespace("a \t\n b  c"), "a b c");
+        assert!(matches!(lowercase("straße"), Cow::Borrowed(_)));
+        assert_eq!(lowercase("ÀB"), "àb");
+        assert!(Value::String(" x ".to_string()).map_strings(trim).identical(&Value::String("x".to_string())));
+    }
+}
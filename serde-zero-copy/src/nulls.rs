@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use crate::schema::resolve;
+use crate::visit::{Fold, Segment};
+use crate::Value;
+
+/// What becomes of nulls and empty containers, for clients that don't want them. The default
+/// keeps everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullPolicy<'s> {
+    /// Replace nulls with the default of the JSON schema at their path: its `default`, or else
+    /// the empty value of its `type`. Nulls without a schema stay.
+    pub defaults: Option<&'s serde_json::Value>,
+    /// Drop members that are null. Elements of arrays stay, so the others keep their index.
+    pub strip_nulls: bool,
+    /// Drop members that are empty arrays or objects, including ones emptied by this.
+    pub collapse_empty: bool,
+}
+
+// the schema of the node at `path`, through `properties`, `additionalProperties` and `items`
+fn schema_at<'s>(root: &'s serde_json::Value, path: &[Segment]) -> Option<&'s serde_json::Value> {
+    let mut schema = resolve(root, root).ok()?;
+    for segment in path {
+        let next = match segment {
+            Segment::Key(key) => schema
+                .get("properties")
+                .and_then(|properties| properties.get(key.as_ref()))
+                .or_else(|| schema.get("additionalProperties").filter(|s| s.is_object())),
+            Segment::Index(_) => schema.get("items").filter(|s| s.is_object()),
+        };
+        schema = resolve(next?, root).ok()?;
+    }
+    Some(schema)
+}
+
+// objects other than `{}` can't be made, their keys would have to borrow from the schema
+fn from_json(json: &serde_json::Value) -> Option<Value<'static>> {
+    Some(match json {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Bool(*b),
+        serde_json::Value::Number(n) => Value::Number(n.clone()),
+        serde_json::Value::String(s) if s.is_empty() => Value::Str(""),
+        serde_json::Value::String(s) => Value::String(s.clone()),
+        serde_json::Value::Array(vec) => Value::Array(vec.iter().map(from_json).collect::<Option<_>>()?),
+        serde_json::Value::Object(map) if map.is_empty() => Value::Object(BTreeMap::new()),
+        serde_json::Value::Object(_) => return None,
+    })
+}
+
+fn default_of(schema: &serde_json::Value) -> Option<Value<'static>> {
+    if let Some(default) = schema.get("default") {
+        return from_json(default);
+    }
+    let name = match schema.get("type")? {
+        serde_json::Value::String(name) => name.as_str(),
+        serde_json::Value::Array(names) => names.iter().filter_map(serde_json::Value::as_str).find(|&name| name != "null")?,
+        _ => return None,
+    };
+    Some(match name {
+        "string" => Value::Str(""),
+        "number" | "integer" => Value::Number(0.into()),
+        "boolean" => Value::Bool(false),
+        "array" => Value::Array(Vec::new()),
+        "object" => Value::Object(BTreeMap::new()),
+        _ => return None,
+    })
+}
+
+impl<'a> Fold<'a> for NullPolicy<'_> {
+    fn exit(&mut self, path: &[Segment<'a>], value: Value<'a>) -> Option<Value<'a>> {
+        let value = match (value, self.defaults) {
+            (Value::Null, Some(schema)) => schema_at(schema, path).and_then(default_of).unwrap_or(Value::Null),
+            (value, _) => value,
+        };
+        let member = matches!(path.last(), Some(Segment::Key(_)));
+        let drop = match &value {
+            Value::Null => self.strip_nulls,
+            Value::Array(vec) => self.collapse_empty && vec.is_empty(),
+            Value::Object(map) => self.collapse_empty && map.is_empty(),
+            _ => false,
+        };
+        if member && drop { None } else { Some(value) }
+    }
+}
+
+impl<'a> Value<'a> {
+    pub fn apply_nulls(self, mut policy: NullPolicy) -> Value<'a> {
+        self.fold(&mut policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::NullPolicy;
+
+    const INPUT: &str = r#"{"name":null,"age":null,"tags":[null,"a"],"meta":{"note":null,"extra":[]},"items":[{"price":null}],"other":null}"#;
+
+    fn json(value: Value) -> String {
+        serde_json_nostr::to_string(&value).unwrap()
+    }
+
+    #[test]
+    fn nulls_strip_and_collapse() {
+        let value = || serde_json_nostr::from_str::<Value>(INPUT).unwrap();
+        assert_eq!(json(value().apply_nulls(NullPolicy::default())), json(value()));
+        let strip = NullPolicy { strip_nulls: true, ..NullPolicy::default() };
+        assert_eq!(json(value().apply_nulls(strip)), r#"{"items":[{}],"meta":{"extra":[]},"tags":[null,"a"]}"#);
+        let both = NullPolicy { strip_nulls: true, collapse_empty: true, ..NullPolicy::default() };
+        assert_eq!(json(value().apply_nulls(both)), r#"{"items":[{}],"tags":[null,"a"]}"#);
+    }
+
+    #[test]
+    fn nulls_default_from_schema() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": ["null", "string"]},
+                "age": {"$ref": "#/$defs/age"},
+                "tags": {"items": {"type": "string", "default": "none"}},
+                "items": {"type": "array", "items": {"properties": {"price": {"type": "number"}}}}
+            },
+            "$defs": {"age": {"type": "integer", "default": 18}}
+        });
+        let value: Value = serde_json_nostr::from_str(INPUT).unwrap();
+        let policy = NullPolicy { defaults: Some(&schema), strip_nulls: true, ..NullPolicy::default() };
+        assert_eq!(
+            json(value.apply_nulls(policy)),
+            r#"{"age":18,"items":[{"price":0}],"meta":{"extra":[]},"name":"","tags":["none","a"]}"#
+        );
+    }
+}
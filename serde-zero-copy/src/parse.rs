@@ -0,0 +1,133 @@
+use serde::de::Error;
+use serde::Deserialize;
+use serde_json_nostr::Deserializer;
+use crate::Value;
+
+/// What's done with what follows the document, other than whitespace.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Trailing {
+    /// Fail like `serde_json_nostr::from_slice` does.
+    #[default]
+    Error,
+    Ignore,
+    /// Hand it back, from just after the document, e.g. to parse the next of several.
+    Return,
+}
+
+/// What's done with a UTF-8 byte order mark and control characters before the document, as
+/// some legacy upstreams send.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Preamble {
+    /// Fail, saying which it was.
+    #[default]
+    Error,
+    Strip,
+}
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// How many bytes of `input` come before its document: byte order marks, control characters
+/// and whitespace, which JSON allows there anyway.
+pub fn preamble_len(input: &[u8]) -> usize {
+    let mut at = 0;
+    loop {
+        match &input[at..] {
+            [0xef, 0xbb, 0xbf, ..] => at += BOM.len(),
+            [b, ..] if b.is_ascii_control() || *b == b' ' => at += 1,
+            _ => return at,
+        }
+    }
+}
+
+/// How [`from_slice_with`] and [`from_str_with`] take their input.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    pub trailing: Trailing,
+    pub preamble: Preamble,
+}
+
+fn preamble(input: &[u8], options: ParseOptions) -> Result<&[u8], serde_json_nostr::Error> {
+    let len = preamble_len(input);
+    if options.preamble == Preamble::Strip {
+        return Ok(&input[len..]);
+    }
+    let found = input[..len].iter().position(|b| !b" \t\n\r".contains(b));
+    match found.map(|at| &input[at..]) {
+        Some([0xef, ..]) => Err(serde_json_nostr::Error::custom("byte order mark before the document")),
+        Some([b, ..]) => Err(serde_json_nostr::Error::custom(format!("control character {:#04x} before the document", b))),
+        _ => Ok(input),
+    }
+}
+
+/// Parses the document `input` starts with, after its [`Preamble`], and what follows it,
+/// empty unless [`Trailing::Return`]. A document that's a number, `true`, `false` or `null`
+/// is only over at whitespace or one of `"[]{},:`, so that `12x` isn't taken for `12`.
+pub fn from_slice_with(input: &[u8], options: ParseOptions) -> Result<(Value<'_>, &[u8]), serde_json_nostr::Error> {
+    let input = preamble(input, options)?;
+    if options.trailing == Trailing::Error {
+        return serde_json_nostr::from_slice(input).map(|value| (value, &input[input.len()..]));
+    }
+    let mut stream = Deserializer::from_slice(input).into_iter();
+    let value = match stream.next() {
+        Some(parsed) => parsed?,
+        // nothing but whitespace, for the error a parse of it gives
+        None => Value::deserialize(&mut Deserializer::from_slice(input))?,
+    };
+    let rest = match options.trailing {
+        Trailing::Return => &input[stream.byte_offset()..],
+        _ => &input[input.len()..],
+    };
+    Ok((value, rest))
+}
+
+/// [`from_slice_with`] for a `str`, the rest of which is one too.
+pub fn from_str_with(input: &str, options: ParseOptions) -> Result<(Value<'_>, &str), serde_json_nostr::Error> {
+    let (value, rest) = from_slice_with(input.as_bytes(), options)?;
+    // the document ends at an ASCII byte, or at the end
+    Ok((value, &input[input.len() - rest.len()..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{from_slice_with, from_str_with, preamble_len, ParseOptions, Preamble, Trailing};
+
+    #[test]
+    fn trailing_data() {
+        let input = "{\"a\":1} {\"a\":2}\n[3]";
+        let strict = serde_json_nostr::from_str::<Value>(input).unwrap_err().to_string();
+        assert_eq!(from_str_with(input, ParseOptions::default()).unwrap_err().to_string(), strict);
+        let (value, rest) = from_str_with(input, ParseOptions { trailing: Trailing::Ignore, ..ParseOptions::default() }).unwrap();
+        assert_eq!((value.pointer("/a").and_then(|a| a.as_i64_coerced()), rest), (Some(1), ""));
+
+        let mut documents = Vec::new();
+        let mut rest = input;
+        while !rest.trim().is_empty() {
+            let (value, after) = from_str_with(rest, ParseOptions { trailing: Trailing::Return, ..ParseOptions::default() }).unwrap();
+            documents.push(serde_json_nostr::to_string(&value).unwrap());
+            rest = after;
+        }
+        assert_eq!(documents, [r#"{"a":1}"#, r#"{"a":2}"#, "[3]"]);
+
+        assert!(from_slice_with(b"12x", ParseOptions { trailing: Trailing::Return, ..ParseOptions::default() }).is_err());
+        assert_eq!(from_slice_with(b"12 x", ParseOptions { trailing: Trailing::Return, ..ParseOptions::default() }).unwrap().1, b" x");
+        assert!(from_slice_with(b"  ", ParseOptions { trailing: Trailing::Ignore, ..ParseOptions::default() }).unwrap_err().is_eof());
+    }
+
+    #[test]
+    fn byte_order_marks_and_control_characters() {
+        let input = b"\xef\xbb\xbf\x00 \n{\"a\":\"b\"}";
+        assert_eq!(preamble_len(input), 6);
+        let strip = ParseOptions { preamble: Preamble::Strip, ..ParseOptions::default() };
+        let (value, _) = from_slice_with(input, strip).unwrap();
+        assert_eq!(serde_json_nostr::to_string(&value).unwrap(), r#"{"a":"b"}"#);
+        // still borrowed from the input
+        assert!(matches!(value.pointer("/a"), Some(Value::Bytes(b)) if input.as_ptr_range().contains(&b.as_ptr())));
+
+        let err = |input: &[u8]| from_slice_with(input, ParseOptions::default()).unwrap_err().to_string();
+        assert_eq!(err(input), "byte order mark before the document");
+        assert_eq!(err(b" \x01[]"), "control character 0x01 before the document");
+        assert_eq!(from_str_with("\u{feff} [1]", strip).unwrap().0, Value::Array(vec![Value::Number(1.into())]));
+        assert!(from_slice_with(b"\n [1]", ParseOptions::default()).is_ok());
+    }
+}
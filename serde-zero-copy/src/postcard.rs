@@ -0,0 +1,141 @@
+use std::borrow::Cow;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use serde_json::Number;
+use crate::{NonFinite, Value};
+
+#[derive(Debug)]
+pub enum Error {
+    Postcard(::postcard::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Postcard(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<::postcard::Error> for Error {
+    fn from(err: ::postcard::Error) -> Self {
+        Error::Postcard(err)
+    }
+}
+
+// postcard isn't self-describing, so values go as this rather than through `Value`'s own
+// `Serialize`
+#[derive(Serialize, Deserialize)]
+enum Node<'a> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    /// A number as written, with `decimal`.
+    Numeral(#[serde(borrow)] Cow<'a, str>),
+    BigInt(#[serde(borrow)] Cow<'a, str>),
+    NonFinite(u8),
+    String(#[serde(borrow)] Cow<'a, str>),
+    Array(#[serde(borrow)] Vec<Node<'a>>),
+    Object(#[serde(borrow)] Vec<(Cow<'a, str>, Node<'a>)>),
+}
+
+fn number(n: &Number) -> Node<'static> {
+    if cfg!(feature = "decimal") {
+        return Node::Numeral(Cow::Owned(n.to_string()));
+    }
+    match (n.as_i64(), n.as_u64(), n.as_f64()) {
+        (Some(i), _, _) => Node::Int(i),
+        (_, Some(u), _) => Node::Uint(u),
+        (_, _, Some(f)) => Node::Float(f),
+        _ => Node::Numeral(Cow::Owned(n.to_string())),
+    }
+}
+
+fn node<'v>(value: &'v Value) -> Node<'v> {
+    match value {
+        Value::Null => Node::Null,
+        Value::Bool(b) => Node::Bool(*b),
+        Value::Number(n) => number(n),
+        Value::BigInt(n) => Node::BigInt(Cow::Borrowed(n)),
+        Value::NonFinite(f) => Node::NonFinite(*f as u8),
+        // what `serde_json_nostr` left escaped goes as the text it stands for
+        Value::Bytes(b) if b.contains(&b'\\') => {
+            let text = std::str::from_utf8(b).ok().and_then(|s| serde_json::from_str(&format!("\"{}\"", s)).ok());
+            Node::String(Cow::Owned(text.unwrap_or_else(|| String::from_utf8_lossy(b).into_owned())))
+        }
+        Value::Bytes(b) => Node::String(String::from_utf8_lossy(b)),
+        Value::Str(s) => Node::String(Cow::Borrowed(s)),
+        Value::String(s) => Node::String(Cow::Borrowed(s)),
+        Value::Array(vec) => Node::Array(vec.iter().map(node).collect()),
+        Value::Object(map) => Node::Object(map.iter().map(|(key, member)| (Cow::Borrowed(key.as_ref()), node(member))).collect()),
+    }
+}
+
+fn value(node: Node) -> Value {
+    match node {
+        Node::Null => Value::Null,
+        Node::Bool(b) => Value::Bool(b),
+        Node::Int(i) => Value::Number(i.into()),
+        Node::Uint(u) => Value::Number(u.into()),
+        Node::Float(f) => Value::from_f64(f),
+        Node::Numeral(n) => n.parse().map_or(Value::Null, Value::Number),
+        Node::BigInt(n) => Value::BigInt(n),
+        Node::NonFinite(f) => Value::NonFinite(match f {
+            0 => NonFinite::NaN,
+            1 => NonFinite::Infinity,
+            _ => NonFinite::NegInfinity,
+        }),
+        Node::String(Cow::Borrowed(s)) => Value::Str(s),
+        Node::String(Cow::Owned(s)) => Value::String(s),
+        Node::Array(vec) => Value::Array(vec.into_iter().map(value).collect()),
+        Node::Object(members) => Value::Object(members.into_iter().map(|(key, member)| (key, value(member))).collect()),
+    }
+}
+
+/// `value` in postcard, for another instance to take up with [`from_postcard_slice`] rather
+/// than parse it again as JSON.
+pub fn to_postcard(value: &Value) -> Result<Vec<u8>, Error> {
+    Ok(::postcard::to_allocvec(&node(value))?)
+}
+
+/// What [`to_postcard`] wrote, its strings and keys borrowed from `input`. Nesting isn't
+/// limited as it is for JSON, so `input` is to come from an instance that's trusted.
+pub fn from_postcard_slice(input: &[u8]) -> Result<Value<'_>, Error> {
+    let (node, rest) = ::postcard::take_from_bytes(input)?;
+    if !rest.is_empty() {
+        return Err(Error::Postcard(::postcard::Error::DeserializeBadEncoding));
+    }
+    Ok(value(node))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{from_postcard_slice, to_postcard};
+
+    #[test]
+    fn round_trip_borrows_from_input() {
+        let input = br#"{"name":"Jane \"J\"","tags":["a",null,true],"n":-3,"u":18446744073709551615,"f":1.5,"big":123456789012345678901234}"#;
+        let parsed: Value = serde_json_nostr::from_slice(input).unwrap();
+        let encoded = to_postcard(&parsed).unwrap();
+        assert!(encoded.len() < input.len());
+        let decoded = from_postcard_slice(&encoded).unwrap();
+        assert_eq!(serde_json_nostr::to_string(&decoded).unwrap(), serde_json_nostr::to_string(&parsed).unwrap());
+        match decoded.pointer("/name") {
+            Some(Value::Str(name)) => {
+                assert_eq!(*name, "Jane \"J\"");
+                assert!(encoded.as_ptr_range().contains(&name.as_ptr()));
+            }
+            other => panic!("{:?}", other),
+        }
+
+        assert!(from_postcard_slice(&encoded[..encoded.len() - 3]).is_err());
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(from_postcard_slice(&trailing).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+use std::ops::{Bound, RangeBounds};
+use crate::Value;
+
+/// Members of an object by the order of their keys, found without looking at the others.
+/// Anything but an object has none.
+impl<'a> Value<'a> {
+    /// The members whose keys are in `keys`, e.g. `"a".."m"`.
+    pub fn range<'v, 'k, R>(&'v self, keys: R) -> impl Iterator<Item = (&'v str, &'v Value<'a>)> + 'v
+        where
+            R: RangeBounds<&'k str>,
+    {
+        let bounds = (keys.start_bound().map(|key| *key), keys.end_bound().map(|key| *key));
+        let range = match self {
+            Value::Object(map) => Some(map.range::<str, _>(bounds)),
+            _ => None,
+        };
+        range.into_iter().flatten().map(|(key, member)| (key.as_ref(), member))
+    }
+
+    /// The members whose keys start with `prefix`, e.g. `ext_` for `ext_source` and `ext_id`.
+    pub fn keys_with_prefix<'v>(&'v self, prefix: &'v str) -> impl Iterator<Item = (&'v str, &'v Value<'a>)> + 'v {
+        self.range(prefix..).take_while(move |(key, _)| key.starts_with(prefix))
+    }
+
+    pub fn keys_with_prefix_mut<'v>(&'v mut self, prefix: &'v str) -> impl Iterator<Item = (&'v str, &'v mut Value<'a>)> + 'v {
+        let range = match self {
+            Value::Object(map) => Some(map.range_mut::<str, _>((Bound::Included(prefix), Bound::Unbounded))),
+            _ => None,
+        };
+        range.into_iter().flatten().map(|(key, member)| (key.as_ref(), member)).take_while(move |(key, _)| key.starts_with(prefix))
+    }
+
+    /// Drops the members whose keys start with `prefix`, how many there were.
+    pub fn remove_with_prefix(&mut self, prefix: &str) -> usize {
+        let Value::Object(map) = self else {
+            return 0;
+        };
+        let mut removed = map.split_off(prefix);
+        if let Some(after) = removed.keys().find(|key| !key.starts_with(prefix)).cloned() {
+            map.append(&mut removed.split_off(&after));
+        }
+        removed.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+
+    #[test]
+    fn ranges_of_keys() {
+        let input = r#"{"a":1,"ext_id":2,"ext_source":"x","extra":3,"m":4,"z":5}"#;
+        let mut value: Value = serde_json_nostr::from_str(input).unwrap();
+        let keys = |value: &Value, range: std::ops::Range<&str>| value.range(range).map(|(key, _)| key.to_string()).collect::<Vec<_>>();
+        assert_eq!(keys(&value, "b".."m"), ["ext_id", "ext_source", "extra"]);
+        assert_eq!(value.keys_with_prefix("ext_").map(|(key, _)| key).collect::<Vec<_>>(), ["ext_id", "ext_source"]);
+        // borrowed from the input
+        let (_, source) = value.keys_with_prefix("ext_s").next().unwrap();
+        assert!(matches!(source, Value::Bytes(b) if input.as_bytes().as_ptr_range().contains(&b.as_ptr())));
+
+        value.keys_with_prefix_mut("ext_").for_each(|(_, member)| *member = Value::Null);
+        assert_eq!(value.pointer("/ext_id"), Some(&Value::Null));
+        assert_eq!(value.remove_with_prefix("ext_"), 2);
+        assert_eq!(serde_json_nostr::to_string(&value).unwrap(), r#"{"a":1,"extra":3,"m":4,"z":5}"#);
+        assert_eq!(value.remove_with_prefix("q"), 0);
+        assert_eq!(Value::Null.keys_with_prefix("").count(), 0);
+    }
+}
@@ -0,0 +1,187 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use crate::visit::{Fold, Segment};
+use crate::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    /// `user_id`
+    Snake,
+    /// `userId`
+    Camel,
+}
+
+/// `userId` and `UserID` to `user_id`, a run of capitals is one word unless it's followed by
+/// a lowercase letter, so `HTTPServer` is `http_server`.
+pub fn snake_case(key: &str) -> Cow<'_, str> {
+    if !key.chars().any(|c| c.is_ascii_uppercase() || c == '-') {
+        return Cow::Borrowed(key);
+    }
+    let chars: Vec<char> = key.chars().collect();
+    let mut out = String::with_capacity(key.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '-' {
+            out.push('_');
+            continue;
+        }
+        if c.is_ascii_uppercase() && i > 0 {
+            let previous = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+            if previous.is_ascii_lowercase() || previous.is_ascii_digit() || (previous.is_ascii_uppercase() && next_lower) {
+                out.push('_');
+            }
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    Cow::Owned(out)
+}
+
+/// `user_id` to `userId`, leading underscores are kept.
+pub fn camel_case(key: &str) -> Cow<'_, str> {
+    let body = key.trim_start_matches('_');
+    if !body.contains(['_', '-']) {
+        return Cow::Borrowed(key);
+    }
+    let prefix = key.len() - body.len();
+    let mut out = String::with_capacity(key.len());
+    out.push_str(&key[..prefix]);
+    let mut upper = false;
+    for c in body.chars() {
+        match c {
+            '_' | '-' => upper = out.len() > prefix,
+            c if upper => {
+                out.push(c.to_ascii_uppercase());
+                upper = false;
+            }
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Renames object keys at any depth: those in `renames` to what they map to, others by
+/// `case` if given.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyRenamer {
+    pub renames: HashMap<String, String>,
+    pub case: Option<Case>,
+}
+
+impl KeyRenamer {
+    pub fn rename(mut self, from: &str, to: &str) -> Self {
+        self.renames.insert(from.to_string(), to.to_string());
+        self
+    }
+
+    pub fn case(mut self, case: Case) -> Self {
+        self.case = Some(case);
+        self
+    }
+
+    pub fn new_name<'k>(&self, key: &'k str) -> Cow<'k, str> {
+        if let Some(to) = self.renames.get(key) {
+            return Cow::Owned(to.clone());
+        }
+        match self.case {
+            Some(Case::Snake) => snake_case(key),
+            Some(Case::Camel) => camel_case(key),
+            None => Cow::Borrowed(key),
+        }
+    }
+
+    /// The new name of every key in `value` that changes, once each.
+    pub fn names<'a>(&self, value: &Value<'a>) -> RenamedKeys<'a> {
+        fn collect<'a>(renamer: &KeyRenamer, value: &Value<'a>, names: &mut HashMap<Cow<'a, str>, String>) {
+            match value {
+                Value::Array(vec) => vec.iter().for_each(|v| collect(renamer, v, names)),
+                Value::Object(map) => {
+                    for (key, member) in map {
+                        if !names.contains_key(key) {
+                            if let Cow::Owned(name) = renamer.new_name(key) {
+                                if name != *key {
+                                    names.insert(key.clone(), name);
+                                }
+                            }
+                        }
+                        collect(renamer, member, names);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut names = HashMap::new();
+        collect(self, value, &mut names);
+        RenamedKeys(names)
+    }
+}
+
+/// New key names made by [`KeyRenamer::names`], which renamed values borrow.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenamedKeys<'a>(pub HashMap<Cow<'a, str>, String>);
+
+impl<'a> RenamedKeys<'a> {
+    /// Renames the keys of `value`, the ones that don't change stay borrowed from the input.
+    /// When two keys of an object end up the same, the one later in key order is kept.
+    pub fn apply<'n>(&'n self, value: Value<'a>) -> Value<'n>
+        where
+            'a: 'n,
+    {
+        let value: Value<'n> = value;
+        value.fold(&mut Rename(&self.0))
+    }
+}
+
+struct Rename<'m, 'a>(&'m HashMap<Cow<'a, str>, String>);
+
+impl<'n, 'a: 'n> Fold<'n> for Rename<'n, 'a> {
+    fn exit(&mut self, _: &[Segment<'n>], value: Value<'n>) -> Option<Value<'n>> {
+        Some(match value {
+            Value::Object(map) => Value::Object(
+                map.into_iter()
+                    .map(|(key, member)| (self.0.get(key.as_ref()).map_or(key, |name| Cow::Borrowed(name.as_str())), member))
+                    .collect(),
+            ),
+            other => other,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use crate::Value;
+    use super::{camel_case, snake_case, Case, KeyRenamer};
+
+    #[test]
+    fn rename_case_conversions() {
+        for (from, to) in [("userId", "user_id"), ("UserID", "user_id"), ("HTTPServer", "http_server"), ("page2Url", "page2_url"), ("x-request-id", "x_request_id")] {
+            assert_eq!(snake_case(from), to);
+        }
+        for (from, to) in [("user_id", "userId"), ("_private_key", "_privateKey"), ("a__b", "aB"), ("x-request-id", "xRequestId")] {
+            assert_eq!(camel_case(from), to);
+        }
+        assert!(matches!(snake_case("already_snake"), Cow::Borrowed(_)));
+        assert!(matches!(camel_case("alreadyCamel"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn rename_keys_borrows_unrenamed() {
+        let input = br#"{"user_id":1,"id":2,"items":[{"item_name":"a","sku":"x"},{"item_name":"b"}],"legacy":true}"#;
+        let value: Value = serde_json_nostr::from_slice(input).unwrap();
+        let renamer = KeyRenamer::default().case(Case::Camel).rename("legacy", "isLegacy");
+        let names = renamer.names(&value);
+        assert_eq!(names.0.len(), 3);
+        let renamed = names.apply(value);
+        assert_eq!(
+            serde_json_nostr::to_string(&renamed).unwrap(),
+            r#"{"id":2,"isLegacy":true,"items":[{"itemName":"a","sku":"x"},{"itemName":"b"}],"userId":1}"#
+        );
+        match &renamed {
+            Value::Object(map) => {
+                let (id, _) = map.get_key_value("id").unwrap();
+                assert!(input.as_ptr_range().contains(&id.as_ptr()));
+            }
+            _ => panic!(),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::cmp::Ordering;
+use serde::{Deserialize, Serialize};
+use crate::schema::{as_str, equals};
+use crate::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op {
+    #[serde(rename = "==")]
+    Eq,
+    #[serde(rename = "!=")]
+    Ne,
+    #[serde(rename = "<")]
+    Lt,
+    #[serde(rename = "<=")]
+    Le,
+    #[serde(rename = ">")]
+    Gt,
+    #[serde(rename = ">=")]
+    Ge,
+    /// Equal to one of the elements of `value`.
+    #[serde(rename = "in")]
+    In,
+    /// A string containing `value`, or an array with an element equal to it.
+    #[serde(rename = "contains")]
+    Contains,
+    #[serde(rename = "exists")]
+    Exists,
+    #[serde(rename = "missing")]
+    Missing,
+}
+
+/// A check of the value at `pointer`, e.g. `{"pointer": "/age", "op": ">=", "value": 18}`.
+/// Ordering compares numbers with numbers and strings with strings, anything else fails.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub pointer: String,
+    pub op: Op,
+    #[serde(default)]
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuleResult {
+    pub pointer: String,
+    pub op: Op,
+    pub passed: bool,
+}
+
+fn order(value: &Value, expected: &serde_json::Value) -> Option<Ordering> {
+    match (value, expected) {
+        (Value::Number(a), serde_json::Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        },
+        (value, serde_json::Value::String(b)) => Some(as_str(value)?.cmp(b.as_str())),
+        _ => None,
+    }
+}
+
+impl Rule {
+    pub fn new(pointer: &str, op: Op, value: serde_json::Value) -> Self {
+        Rule { pointer: pointer.to_string(), op, value }
+    }
+
+    pub fn test(&self, value: &Value) -> bool {
+        let Some(found) = value.pointer(&self.pointer) else {
+            return self.op == Op::Missing || self.op == Op::Ne;
+        };
+        let ordered = |accept: fn(Ordering) -> bool| order(found, &self.value).is_some_and(accept);
+        match self.op {
+            Op::Eq => equals(found, &self.value),
+            Op::Ne => !equals(found, &self.value),
+            Op::Lt => ordered(Ordering::is_lt),
+            Op::Le => ordered(Ordering::is_le),
+            Op::Gt => ordered(Ordering::is_gt),
+            Op::Ge => ordered(Ordering::is_ge),
+            Op::In => self.value.as_array().is_some_and(|options| options.iter().any(|option| equals(found, option))),
+            Op::Contains => match (found, &self.value) {
+                (Value::Array(vec), expected) => vec.iter().any(|element| equals(element, expected)),
+                (found, serde_json::Value::String(needle)) => as_str(found).is_some_and(|s| s.contains(needle.as_str())),
+                _ => false,
+            },
+            Op::Exists => true,
+            Op::Missing => false,
+        }
+    }
+}
+
+/// Every rule's outcome, in order.
+pub fn evaluate(rules: &[Rule], value: &Value) -> Vec<RuleResult> {
+    rules
+        .iter()
+        .map(|rule| RuleResult { pointer: rule.pointer.clone(), op: rule.op, passed: rule.test(value) })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::Value;
+    use super::{evaluate, Op, Rule};
+
+    const INPUT: &str = r#"{"age":21,"name":"Jane Doe","plan":"premium","tags":["a","b"],"score":2.5}"#;
+
+    #[test]
+    fn rules_ops() {
+        let value: Value = serde_json_nostr::from_str(INPUT).unwrap();
+        let passes = |op, pointer: &str, expected| Rule::new(pointer, op, expected).test(&value);
+        assert!(passes(Op::Ge, "/age", json!(18)));
+        assert!(!passes(Op::Lt, "/age", json!(21)));
+        assert!(passes(Op::Le, "/score", json!(2.5)));
+        assert!(passes(Op::Gt, "/name", json!("Jane")));
+        assert!(!passes(Op::Gt, "/name", json!(1)));
+        assert!(passes(Op::Eq, "/plan", json!("premium")));
+        assert!(passes(Op::Ne, "/missing", json!(1)));
+        assert!(passes(Op::In, "/plan", json!(["basic", "premium"])));
+        assert!(passes(Op::Contains, "/tags", json!("b")));
+        assert!(passes(Op::Contains, "/name", json!("Doe")));
+        assert!(passes(Op::Exists, "/tags/1", json!(null)));
+        assert!(passes(Op::Missing, "/tags/2", json!(null)));
+        assert!(!passes(Op::Ge, "/missing", json!(0)));
+    }
+
+    #[test]
+    fn rules_from_json_report_each() {
+        let rules: Vec<Rule> = serde_json::from_str(r#"[{"pointer":"/age","op":">=","value":18},{"pointer":"/plan","op":"in","value":["basic"]},{"pointer":"/name","op":"exists"}]"#).unwrap();
+        let value: Value = serde_json_nostr::from_str(INPUT).unwrap();
+        assert_eq!(
+            serde_json::to_string(&evaluate(&rules, &value)).unwrap(),
+            r#"[{"pointer":"/age","op":">=","passed":true},{"pointer":"/plan","op":"in","passed":false},{"pointer":"/name","op":"exists","passed":true}]"#
+        );
+    }
+}
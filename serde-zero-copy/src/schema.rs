@@ -0,0 +1,313 @@
+use std::fmt;
+use crate::Value;
+
+/// Where a value broke its schema, `pointer` follows RFC 6901.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", if self.pointer.is_empty() { "/" } else { &self.pointer }, self.message)
+    }
+}
+
+/// Validates `value` against a JSON Schema, covering the keywords OpenAPI 3 documents use:
+/// `type` (plus OpenAPI's `nullable`), `enum`, `const`, numeric and length bounds, `items`,
+/// `properties`, `required`, `additionalProperties` and the `allOf`/`anyOf`/`oneOf`/`not`
+/// combinators. `$ref`s must be local and are resolved against `root`. Unknown keywords,
+/// `pattern` and `format` included, are ignored.
+pub fn validate(value: &Value, schema: &serde_json::Value, root: &serde_json::Value) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    Validator { root, violations: &mut violations }.check(value, schema, &mut String::new());
+    violations
+}
+
+struct Validator<'s, 'v> {
+    root: &'s serde_json::Value,
+    violations: &'v mut Vec<Violation>,
+}
+
+pub(crate) fn as_str<'v>(value: &'v Value) -> Option<&'v str> {
+    match value {
+        Value::Str(s) => Some(s),
+        Value::String(s) => Some(s),
+        Value::Bytes(b) => std::str::from_utf8(b).ok(),
+        _ => None,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) | Value::BigInt(_) | Value::NonFinite(_) => "number",
+        Value::Bytes(_) | Value::Str(_) | Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn is_type(value: &Value, name: &str) -> bool {
+    match (name, value) {
+        ("integer", Value::BigInt(_)) => true,
+        ("integer", Value::Number(n)) => n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|f| f.fract() == 0.0),
+        ("string", value) => as_str(value).is_some(),
+        (name, value) => type_name(value) == name,
+    }
+}
+
+// equality across the two value models, numbers compare by value
+pub(crate) fn equals(value: &Value, expected: &serde_json::Value) -> bool {
+    match (value, expected) {
+        (Value::Null, serde_json::Value::Null) => true,
+        (Value::Bool(a), serde_json::Value::Bool(b)) => a == b,
+        (Value::Number(a), serde_json::Value::Number(b)) => a == b || a.as_f64() == b.as_f64(),
+        (Value::Array(a), serde_json::Value::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equals(a, b)),
+        (Value::Object(a), serde_json::Value::Object(b)) => {
+            a.len() == b.len() && a.iter().all(|(k, v)| b.get(k.as_ref()).is_some_and(|e| equals(v, e)))
+        }
+        (value, serde_json::Value::String(b)) => as_str(value) == Some(b.as_str()),
+        _ => false,
+    }
+}
+
+fn push_token(pointer: &mut String, token: &str) {
+    pointer.push('/');
+    pointer.push_str(&token.replace('~', "~0").replace('/', "~1"));
+}
+
+/// Follows local `$ref`s from `schema` to the schema they point at within `root`.
+pub(crate) fn resolve<'s>(mut schema: &'s serde_json::Value, root: &'s serde_json::Value) -> Result<&'s serde_json::Value, String> {
+    // bounded so that a reference cycle can't hang validation
+    for _ in 0..32 {
+        match schema.get("$ref").and_then(serde_json::Value::as_str) {
+            None => return Ok(schema),
+            Some(reference) => match reference.strip_prefix('#').and_then(|p| root.pointer(p)) {
+                Some(target) => schema = target,
+                None => return Err(format!("unresolvable $ref {}", reference)),
+            },
+        }
+    }
+    Err("$ref chain too long".to_string())
+}
+
+impl<'s, 'v> Validator<'s, 'v> {
+    fn fail(&mut self, pointer: &str, message: String) {
+        self.violations.push(Violation { pointer: pointer.to_string(), message });
+    }
+
+    fn resolve(&mut self, schema: &'s serde_json::Value, pointer: &str) -> Option<&'s serde_json::Value> {
+        match resolve(schema, self.root) {
+            Ok(schema) => Some(schema),
+            Err(message) => {
+                self.fail(pointer, message);
+                None
+            }
+        }
+    }
+
+    // whether `value` matches without recording violations
+    fn matches(&mut self, value: &Value, schema: &'s serde_json::Value, pointer: &mut String) -> bool {
+        let mut violations = Vec::new();
+        Validator { root: self.root, violations: &mut violations }.check(value, schema, pointer);
+        violations.is_empty()
+    }
+
+    fn check(&mut self, value: &Value, schema: &'s serde_json::Value, pointer: &mut String) {
+        let schema = match self.resolve(schema, pointer) {
+            Some(schema) => schema,
+            None => return,
+        };
+        let schema = match schema {
+            serde_json::Value::Bool(true) => return,
+            serde_json::Value::Bool(false) => return self.fail(pointer, "no value is allowed".to_string()),
+            serde_json::Value::Object(schema) => schema,
+            _ => return,
+        };
+        let nullable = schema.get("nullable").and_then(serde_json::Value::as_bool).unwrap_or(false);
+        if nullable && matches!(value, Value::Null) {
+            return;
+        }
+
+        if let Some(types) = schema.get("type") {
+            let allowed: Vec<&str> = match types {
+                serde_json::Value::String(t) => vec![t.as_str()],
+                serde_json::Value::Array(ts) => ts.iter().filter_map(serde_json::Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if !allowed.iter().any(|t| is_type(value, t)) {
+                return self.fail(pointer, format!("expected {}, found {}", allowed.join(" or "), type_name(value)));
+            }
+        }
+        if let Some(options) = schema.get("enum").and_then(serde_json::Value::as_array) {
+            if !options.iter().any(|option| equals(value, option)) {
+                self.fail(pointer, "not one of the enumerated values".to_string());
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if !equals(value, expected) {
+                self.fail(pointer, format!("expected {}", expected));
+            }
+        }
+        let bound = |keyword: &str| schema.get(keyword).and_then(serde_json::Value::as_f64);
+        let count = |keyword: &str| schema.get(keyword).and_then(serde_json::Value::as_u64).map(|n| n as usize);
+
+        match value {
+            Value::Number(n) => {
+                let n = n.as_f64().unwrap_or(f64::NAN);
+                // OpenAPI 3.0 spells exclusive bounds as booleans next to minimum/maximum
+                let exclusive = |keyword: &str| schema.get(keyword).and_then(serde_json::Value::as_bool).unwrap_or(false);
+                if let Some(min) = bound("minimum") {
+                    if n < min || (exclusive("exclusiveMinimum") && n == min) {
+                        self.fail(pointer, format!("less than the minimum {}", min));
+                    }
+                }
+                if let Some(max) = bound("maximum") {
+                    if n > max || (exclusive("exclusiveMaximum") && n == max) {
+                        self.fail(pointer, format!("greater than the maximum {}", max));
+                    }
+                }
+                if bound("exclusiveMinimum").is_some_and(|min| n <= min) {
+                    self.fail(pointer, "not above the exclusive minimum".to_string());
+                }
+                if bound("exclusiveMaximum").is_some_and(|max| n >= max) {
+                    self.fail(pointer, "not below the exclusive maximum".to_string());
+                }
+                if bound("multipleOf").is_some_and(|m| m > 0.0 && (n / m).fract() != 0.0) {
+                    self.fail(pointer, "not a multiple of multipleOf".to_string());
+                }
+            }
+            Value::Array(items) => {
+                if count("minItems").is_some_and(|min| items.len() < min) {
+                    self.fail(pointer, format!("fewer than {} items", count("minItems").unwrap()));
+                }
+                if count("maxItems").is_some_and(|max| items.len() > max) {
+                    self.fail(pointer, format!("more than {} items", count("maxItems").unwrap()));
+                }
+                if let Some(item_schema) = schema.get("items") {
+                    for (i, item) in items.iter().enumerate() {
+                        let len = pointer.len();
+                        push_token(pointer, &i.to_string());
+                        self.check(item, item_schema, pointer);
+                        pointer.truncate(len);
+                    }
+                }
+            }
+            Value::Object(members) => {
+                if let Some(required) = schema.get("required").and_then(serde_json::Value::as_array) {
+                    for name in required.iter().filter_map(serde_json::Value::as_str) {
+                        if !members.contains_key(name) {
+                            self.fail(pointer, format!("missing required property {}", name));
+                        }
+                    }
+                }
+                let properties = schema.get("properties").and_then(serde_json::Value::as_object);
+                let additional = schema.get("additionalProperties");
+                for (name, member) in members {
+                    let len = pointer.len();
+                    push_token(pointer, name);
+                    match (properties.and_then(|p| p.get(name.as_ref())), additional) {
+                        (Some(property), _) => self.check(member, property, pointer),
+                        (None, Some(serde_json::Value::Bool(false))) => self.fail(pointer, "unexpected property".to_string()),
+                        (None, Some(additional)) => self.check(member, additional, pointer),
+                        (None, None) => {}
+                    }
+                    pointer.truncate(len);
+                }
+            }
+            value => {
+                if let Some(s) = as_str(value) {
+                    let chars = s.chars().count();
+                    if count("minLength").is_some_and(|min| chars < min) {
+                        self.fail(pointer, format!("shorter than {} characters", count("minLength").unwrap()));
+                    }
+                    if count("maxLength").is_some_and(|max| chars > max) {
+                        self.fail(pointer, format!("longer than {} characters", count("maxLength").unwrap()));
+                    }
+                }
+            }
+        }
+
+        if let Some(all) = schema.get("allOf").and_then(serde_json::Value::as_array) {
+            for sub in all {
+                self.check(value, sub, pointer);
+            }
+        }
+        if let Some(any) = schema.get("anyOf").and_then(serde_json::Value::as_array) {
+            if !any.iter().any(|sub| self.matches(value, sub, pointer)) {
+                self.fail(pointer, "matches none of anyOf".to_string());
+            }
+        }
+        if let Some(one) = schema.get("oneOf").and_then(serde_json::Value::as_array) {
+            let matched = one.iter().filter(|sub| self.matches(value, sub, pointer)).count();
+            if matched != 1 {
+                self.fail(pointer, format!("matches {} of oneOf, expected exactly 1", matched));
+            }
+        }
+        if let Some(not) = schema.get("not") {
+            if self.matches(value, not, pointer) {
+                self.fail(pointer, "matches the not schema".to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{validate, Violation};
+
+    fn violations(input: &str, schema: serde_json::Value) -> Vec<String> {
+        let value: Value = serde_json_nostr::from_str(input).unwrap();
+        validate(&value, &schema, &schema).iter().map(Violation::to_string).collect()
+    }
+
+    #[test]
+    fn schema_reports_pointers() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "required": ["id", "name"],
+            "additionalProperties": false,
+            "properties": {
+                "id": {"type": "integer", "minimum": 1},
+                "name": {"type": "string", "maxLength": 3},
+                "tags": {"type": "array", "items": {"enum": ["a", "b"]}},
+                "a/b": {"type": "boolean"}
+            }
+        });
+        assert!(violations(r#"{"id":1,"name":"Jo","tags":["a"],"a/b":true}"#, schema.clone()).is_empty());
+        assert_eq!(
+            violations(r#"{"id":0,"tags":["a","c"],"a/b":1,"x":null}"#, schema.clone()),
+            vec![
+                "/: missing required property name",
+                "/a~1b: expected boolean, found number",
+                "/id: less than the minimum 1",
+                "/tags/1: not one of the enumerated values",
+                "/x: unexpected property",
+            ]
+        );
+        assert_eq!(violations(r#""Jane\n""#, serde_json::json!({"type": "string", "maxLength": 3})), vec!["/: longer than 3 characters"]);
+    }
+
+    #[test]
+    fn schema_refs_and_combinators() {
+        let root = serde_json::json!({
+            "components": {"schemas": {
+                "Id": {"oneOf": [{"type": "integer"}, {"type": "string", "minLength": 1}]},
+                "Pet": {"type": "object", "nullable": true, "properties": {"id": {"$ref": "#/components/schemas/Id"}}}
+            }},
+            "schema": {"type": "array", "items": {"$ref": "#/components/schemas/Pet"}}
+        });
+        let check = |input: &str| {
+            let value: Value = serde_json_nostr::from_str(input).unwrap();
+            validate(&value, &root["schema"], &root).iter().map(Violation::to_string).collect::<Vec<_>>()
+        };
+        assert!(check(r#"[{"id":1},{"id":"x"},null]"#).is_empty());
+        assert_eq!(check(r#"[{"id":""},{"id":1.5}]"#), vec!["/0/id: matches 0 of oneOf, expected exactly 1", "/1/id: matches 0 of oneOf, expected exactly 1"]);
+        let dangling = serde_json::json!({"$ref": "#/nowhere"});
+        assert_eq!(violations("1", dangling), vec!["/: unresolvable $ref #/nowhere"]);
+    }
+}
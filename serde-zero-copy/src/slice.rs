@@ -0,0 +1,55 @@
+#[cfg(feature = "regex")]
+use regex::Regex;
+use crate::Value;
+
+impl<'a> Value<'a> {
+    /// The string as a slice of the input it was parsed from, for what's cut out of it to
+    /// be too. `None` for an owned string, and for one `serde_json_nostr` left with its
+    /// escapes in, whose slices wouldn't be of the text it stands for.
+    pub fn borrowed_str(&self) -> Option<&'a str> {
+        match *self {
+            Value::Str(s) => Some(s),
+            Value::Bytes(b) if !b.contains(&b'\\') => std::str::from_utf8(b).ok(),
+            _ => None,
+        }
+    }
+
+    /// What follows `prefix`, e.g. the id of `https://host/users/42` after its base.
+    pub fn strip_prefix(&self, prefix: &str) -> Option<&'a str> {
+        self.borrowed_str()?.strip_prefix(prefix)
+    }
+
+    /// The parts between `separator`s, e.g. the path segments of a URL.
+    pub fn split<'p>(&self, separator: &'p str) -> Option<std::str::Split<'a, &'p str>> {
+        Some(self.borrowed_str()?.split(separator))
+    }
+
+    /// What `group` of `regex` matched, the whole match for 0.
+    #[cfg(feature = "regex")]
+    pub fn capture(&self, regex: &Regex, group: usize) -> Option<&'a str> {
+        let s = self.borrowed_str()?;
+        regex.captures(s)?.get(group).map(|m| &s[m.range()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+
+    #[test]
+    fn slices_stay_borrowed() {
+        let input = r#"{"href":"https://api.internal/users/42?expand=orders","quoted":"a\"b","owned":"x"}"#;
+        let value: Value = serde_json_nostr::from_str(input).unwrap();
+        let href = value.pointer("/href").unwrap();
+        let id = href.strip_prefix("https://api.internal/users/").and_then(|rest| rest.split('?').next()).unwrap();
+        assert_eq!(id, "42");
+        assert!(input.as_bytes().as_ptr_range().contains(&id.as_ptr()));
+        assert_eq!(href.split("/").unwrap().nth(2), Some("api.internal"));
+        #[cfg(feature = "regex")]
+        assert_eq!(href.capture(&regex::Regex::new(r"/users/(\d+)").unwrap(), 1), Some("42"));
+
+        assert_eq!(value.pointer("/quoted").unwrap().borrowed_str(), None);
+        assert_eq!(Value::String("x".to_string()).strip_prefix(""), None);
+        assert_eq!(Value::Str("x").strip_prefix("y"), None);
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use crate::Value;
+
+/// A snapshot that isn't what's on disk, or isn't on disk yet. What it is now was written
+/// next to it as `<name>.snap.new`, to review and rename over it to accept.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Mismatch { path: PathBuf, expected: Option<String>, actual: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "snapshot: {}", err),
+            Error::Mismatch { path, expected: None, actual } => {
+                write!(f, "no snapshot at {}, it would be:\n{}", path.display(), actual)
+            }
+            Error::Mismatch { path, expected: Some(expected), actual } => {
+                write!(f, "snapshot {} differs\n--- expected\n{}+++ actual\n{}", path.display(), expected, actual)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+// quoted with control characters escaped, anything else as it is
+fn quoted(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_default()
+}
+
+fn write(out: &mut String, value: &Value, depth: usize) {
+    let indent = "  ".repeat(depth + 1);
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(&format!("bool {}", b)),
+        Value::Number(n) => out.push_str(&format!("number {}", n)),
+        Value::BigInt(n) => out.push_str(&format!("bigint {}", n)),
+        Value::NonFinite(f) => out.push_str(&format!("nonfinite {}", f)),
+        Value::Str(s) => out.push_str(&format!("str {}", quoted(s))),
+        Value::String(s) => out.push_str(&format!("string {}", quoted(s))),
+        Value::Bytes(b) => match std::str::from_utf8(b) {
+            Ok(s) => out.push_str(&format!("bytes {}", quoted(s))),
+            Err(_) => out.push_str(&format!("bytes 0x{}", b.iter().map(|b| format!("{:02x}", b)).collect::<String>())),
+        },
+        Value::Array(vec) if vec.is_empty() => out.push_str("[]"),
+        Value::Array(vec) => {
+            out.push_str("[\n");
+            for element in vec {
+                out.push_str(&indent);
+                write(out, element, depth + 1);
+                out.push_str(",\n");
+            }
+            out.push_str(&indent[2..]);
+            out.push(']');
+        }
+        Value::Object(map) if map.is_empty() => out.push_str("{}"),
+        Value::Object(map) => {
+            out.push_str("{\n");
+            for (key, member) in map {
+                out.push_str(&format!("{}{}: ", indent, quoted(key)));
+                write(out, member, depth + 1);
+                out.push_str(",\n");
+            }
+            out.push_str(&indent[2..]);
+            out.push('}');
+        }
+    }
+}
+
+/// A stable text form of `value` to review in golden tests: one node per line, keys in
+/// order, control characters escaped and every scalar with its kind, so that a string that
+/// turns from borrowed `bytes` into an owned `string` shows up in the diff.
+pub fn to_snapshot(value: &Value) -> String {
+    let mut out = String::new();
+    write(&mut out, value, 0);
+    out.push('\n');
+    out
+}
+
+/// Compares `value` with the snapshot `<dir>/<name>.snap`, or with `update` writes it there.
+pub fn check_snapshot(dir: impl AsRef<Path>, name: &str, value: &Value, update: bool) -> Result<(), Error> {
+    let path = dir.as_ref().join(format!("{}.snap", name));
+    let actual = to_snapshot(value);
+    let pending = path.with_extension("snap.new");
+    if update {
+        std::fs::create_dir_all(dir.as_ref())?;
+        std::fs::write(&path, &actual)?;
+        let _ = std::fs::remove_file(pending);
+        return Ok(());
+    }
+    let expected = match std::fs::read_to_string(&path) {
+        Ok(expected) => Some(expected),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err.into()),
+    };
+    if expected.as_deref() == Some(actual.as_str()) {
+        let _ = std::fs::remove_file(pending);
+        return Ok(());
+    }
+    std::fs::create_dir_all(dir.as_ref())?;
+    std::fs::write(&pending, &actual)?;
+    Err(Error::Mismatch { path, expected, actual })
+}
+
+/// Asserts that a value matches its snapshot `snapshots/<name>.snap` in the calling crate,
+/// see [`snapshot::check_snapshot`](crate::snapshot::check_snapshot). Run with
+/// `UPDATE_SNAPSHOTS=1` to write them instead.
+#[macro_export]
+macro_rules! assert_value_snapshot {
+    ($name:expr, $value:expr) => {
+        if let Err(err) = $crate::snapshot::check_snapshot(
+            ::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots"),
+            $name,
+            &$value,
+            ::std::env::var("UPDATE_SNAPSHOTS").is_ok_and(|v| v == "1"),
+        ) {
+            panic!("{}", err)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{check_snapshot, to_snapshot, Error};
+
+    const INPUT: &str = r#"{"b":[1,"tab\there",{}],"a":"plain","c":{"d":null,"e":true},"f":[]}"#;
+
+    #[test]
+    fn snapshot_format() {
+        let mut value: Value = serde_json_nostr::from_str(INPUT).unwrap();
+        if let Value::Object(map) = &mut value {
+            map.insert("g".into(), Value::Bytes(&[0xff, 0x00]));
+            map.insert("h".into(), Value::Str("static\u{1}"));
+        }
+        assert_eq!(
+            to_snapshot(&value),
+            r#"{
+  "a": bytes "plain",
+  "b": [
+    number 1,
+    string "tab\there",
+    {},
+  ],
+  "c": {
+    "d": null,
+    "e": bool true,
+  },
+  "f": [],
+  "g": bytes 0xff00,
+  "h": str "static\u0001",
+}
+"#
+        );
+        crate::assert_value_snapshot!("snapshot_format", value);
+    }
+
+    #[test]
+    fn snapshot_review_workflow() {
+        let dir = std::env::temp_dir().join(format!("serde-zero-copy-snapshots-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let value: Value = serde_json_nostr::from_str(INPUT).unwrap();
+        assert!(matches!(check_snapshot(&dir, "doc", &value, false), Err(Error::Mismatch { expected: None, .. })));
+        assert_eq!(std::fs::read_to_string(dir.join("doc.snap.new")).unwrap(), to_snapshot(&value));
+        check_snapshot(&dir, "doc", &value, true).unwrap();
+        assert!(!dir.join("doc.snap.new").exists());
+        check_snapshot(&dir, "doc", &value, false).unwrap();
+        let changed: Value = serde_json_nostr::from_str(r#"{"a":"plain"}"#).unwrap();
+        assert!(matches!(check_snapshot(&dir, "doc", &changed, false), Err(Error::Mismatch { expected: Some(_), .. })));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+use serde::de::IgnoredAny;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use crate::RAW_TOKEN;
+
+#[derive(Debug)]
+pub enum Error {
+    Utf8(std::str::Utf8Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Utf8(err) => write!(f, "raw json: {}", err),
+            Error::Json(err) => write!(f, "raw json: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A span of JSON, e.g. an upstream body parsed before, that serializes as it is with
+/// `serde_json` and `serde_json_nostr`: put it in a struct beside other fields and its bytes
+/// are copied over once, never parsed or escaped again. Other serializers see a struct with
+/// the span as a string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawJson<'a>(&'a str);
+
+impl<'a> RawJson<'a> {
+    /// `json` once it's checked to be a single JSON value.
+    pub fn new(json: &'a [u8]) -> Result<Self, Error> {
+        let json = std::str::from_utf8(json).map_err(Error::Utf8)?;
+        serde_json::from_str::<IgnoredAny>(json).map_err(Error::Json)?;
+        Ok(RawJson(json))
+    }
+
+    /// For spans already known to be a JSON value, such as a body that parsed. Anything else
+    /// makes the output it's spliced into invalid.
+    pub fn new_unchecked(json: &'a str) -> Self {
+        RawJson(json)
+    }
+
+    pub fn get(&self) -> &'a str {
+        self.0
+    }
+}
+
+impl<'a> Serialize for RawJson<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+    {
+        let mut raw = serializer.serialize_struct(RAW_TOKEN, 1)?;
+        raw.serialize_field(RAW_TOKEN, self.0)?;
+        raw.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+    use crate::Value;
+    use super::{Error, RawJson};
+
+    #[test]
+    fn spliced_verbatim() {
+        #[derive(Serialize)]
+        struct Aggregate<'a> {
+            users: RawJson<'a>,
+            orders: Vec<RawJson<'a>>,
+            count: usize,
+            note: Value<'a>,
+        }
+        let users = r#"[{"id":1, "name":"aé"}]"#.as_bytes();
+        let orders = [&b"{\"total\": 1.50}"[..], b" null "];
+        let aggregate = Aggregate {
+            users: RawJson::new(users).unwrap(),
+            orders: orders.iter().map(|order| RawJson::new(order).unwrap()).collect(),
+            count: 2,
+            note: Value::Str("ok"),
+        };
+        let expected = r#"{"users":[{"id":1, "name":"aé"}],"orders":[{"total": 1.50}, null ],"count":2,"note":"ok"}"#;
+        assert_eq!(serde_json_nostr::to_string(&aggregate).unwrap(), expected);
+        assert_eq!(serde_json::to_string(&aggregate).unwrap(), expected);
+        assert!(std::ptr::eq(aggregate.users.get().as_bytes(), users));
+    }
+
+    #[test]
+    fn rejects_what_isnt_one_value() {
+        assert!(matches!(RawJson::new(b"{\"a\":"), Err(Error::Json(_))));
+        assert!(matches!(RawJson::new(b"1 2"), Err(Error::Json(_))));
+        assert!(matches!(RawJson::new(b"\"\xff\""), Err(Error::Utf8(_))));
+        assert_eq!(RawJson::new_unchecked("[]").get(), "[]");
+    }
+}
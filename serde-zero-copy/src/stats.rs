@@ -0,0 +1,59 @@
+use std::borrow::Cow;
+use serde::Serialize;
+use crate::Value;
+
+/// What a value is made of, see [`Value::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    /// Every value, containers and what's in them.
+    pub nodes: usize,
+    /// Strings and big integers that borrow from the input, keys aren't counted.
+    pub borrowed: usize,
+    /// Strings and big integers that had to be allocated, mostly for their escapes.
+    pub owned: usize,
+}
+
+impl Stats {
+    /// The share of strings that borrow, 1 for none at all: how well zero-copy does on a
+    /// document.
+    pub fn borrowed_ratio(&self) -> f64 {
+        match self.borrowed + self.owned {
+            0 => 1.0,
+            strings => self.borrowed as f64 / strings as f64,
+        }
+    }
+
+    fn add(&mut self, value: &Value) {
+        self.nodes += 1;
+        match value {
+            Value::Str(_) | Value::Bytes(_) | Value::BigInt(Cow::Borrowed(_)) => self.borrowed += 1,
+            Value::String(_) | Value::BigInt(Cow::Owned(_)) => self.owned += 1,
+            Value::Array(vec) => vec.iter().for_each(|element| self.add(element)),
+            Value::Object(map) => map.values().for_each(|member| self.add(member)),
+            _ => {}
+        }
+    }
+}
+
+impl<'a> Value<'a> {
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        stats.add(self);
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::Stats;
+
+    #[test]
+    fn stats_count_borrowed_strings() {
+        let value: Value = serde_json_nostr::from_str(r#"{"a":"x","b":["y\n",1,{"c":"z"}],"d":null}"#).unwrap();
+        let stats = value.stats();
+        assert_eq!(stats, Stats { nodes: 8, borrowed: 2, owned: 1 });
+        assert_eq!(stats.borrowed_ratio(), 2.0 / 3.0);
+        assert_eq!(Value::Null.stats().borrowed_ratio(), 1.0);
+    }
+}
@@ -0,0 +1,124 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use proptest::prelude::*;
+use serde_json::Number;
+use crate::Value;
+
+/// Keys are borrowed, so generated objects draw them from a fixed pool of awkward names.
+pub const KEYS: &[&str] = &[
+    "", "id", "name", "price", "Price", "a b", "_meta", "0", "ключ", "鍵", "emoji😀", "z", "zz",
+];
+
+/// Borrowed string values, `Value::String` covers arbitrary owned contents including escapes.
+pub const STRS: &[&str] = &["", "John Doe", "Atlantis", "ünïcödé", "😀", "1.5", "null", "x"];
+
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    pub depth: u32,
+    pub size: u32,
+    pub branch: u32,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Bounds { depth: 4, size: 64, branch: 8 }
+    }
+}
+
+pub fn number() -> impl Strategy<Value = Number> {
+    prop_oneof![
+        any::<i64>().prop_map(Number::from),
+        any::<u64>().prop_map(Number::from),
+        Just(Number::from(u64::MAX)),
+        Just(Number::from(i64::MIN)),
+        any::<f64>().prop_filter_map("non-finite floats have no JSON form", Number::from_f64),
+        prop::num::f64::NORMAL.prop_filter_map("non-finite floats have no JSON form", |f| Number::from_f64(f * 1e300)),
+    ]
+}
+
+/// Owned strings with control characters, quotes, backslashes and non-BMP characters,
+/// i.e. everything that needs escaping on the way out.
+pub fn owned_string() -> impl Strategy<Value = String> {
+    prop_oneof![
+        any::<String>(),
+        "[\\x00-\\x1f\"\\\\/a-z]{0,16}",
+        "[\\u{80}-\\u{10ffff}]{0,8}",
+    ]
+}
+
+pub fn leaf() -> impl Strategy<Value = Value<'static>> {
+    prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::Bool),
+        number().prop_map(Value::Number),
+        prop::sample::select(STRS).prop_map(Value::Str),
+        owned_string().prop_map(Value::String),
+    ]
+}
+
+pub fn value() -> impl Strategy<Value = Value<'static>> {
+    value_with(Bounds::default())
+}
+
+pub fn value_with(bounds: Bounds) -> impl Strategy<Value = Value<'static>> {
+    leaf().prop_recursive(bounds.depth, bounds.size, bounds.branch, move |inner| {
+        let branch = bounds.branch as usize;
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..branch).prop_map(Value::Array),
+            prop::collection::btree_map(prop::sample::select(KEYS), inner, 0..branch)
+                .prop_map(|map: BTreeMap<&'static str, Value<'static>>| {
+                    Value::Object(map.into_iter().map(|(k, v)| (Cow::Borrowed(k), v)).collect())
+                }),
+        ]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use crate::Value;
+
+    // Writes objects with their members in descending key order, scalars as usual.
+    fn to_string_reversed(value: &Value) -> String {
+        match value {
+            Value::Array(vec) => {
+                let items: Vec<String> = vec.iter().map(to_string_reversed).collect();
+                format!("[{}]", items.join(","))
+            }
+            Value::Object(map) => {
+                let members: Vec<String> = map
+                    .iter()
+                    .rev()
+                    .map(|(k, v)| format!("{}:{}", serde_json::to_string(k).unwrap(), to_string_reversed(v)))
+                    .collect();
+                format!("{{{}}}", members.join(","))
+            }
+            scalar => serde_json_nostr::to_string(scalar).unwrap(),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn serialize_parse_is_fixpoint(value in super::value()) {
+            let serialized = serde_json_nostr::to_vec(&value).unwrap();
+            let parsed: Value = serde_json_nostr::from_slice(&serialized).unwrap();
+            prop_assert_eq!(&serialized, &serde_json_nostr::to_vec(&parsed).unwrap());
+        }
+
+        #[test]
+        fn roundtrip_preserves_json_semantics(value in super::value()) {
+            let serialized = serde_json_nostr::to_vec(&value).unwrap();
+            let expected: serde_json::Value = serde_json::from_slice(&serialized).unwrap();
+            let parsed: Value = serde_json_nostr::from_slice(&serialized).unwrap();
+            let actual: serde_json::Value = serde_json::from_slice(&serde_json_nostr::to_vec(&parsed).unwrap()).unwrap();
+            prop_assert_eq!(actual, expected);
+        }
+
+        #[test]
+        fn member_order_does_not_matter(value in super::value()) {
+            let reversed = to_string_reversed(&value);
+            let parsed: Value = serde_json_nostr::from_str(&reversed).unwrap();
+            prop_assert_eq!(serde_json_nostr::to_vec(&parsed).unwrap(), serde_json_nostr::to_vec(&value).unwrap());
+        }
+    }
+}
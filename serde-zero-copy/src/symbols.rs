@@ -0,0 +1,135 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{OnceLock, RwLock};
+use crate::Value;
+
+/// Keys kept once for good and shared by every document interned with them, whatever those
+/// borrow from: a symbol is `'static`, so it outlives the input of any document that holds it
+/// and doesn't keep that input alive either. Unlike a [`crate::KeyInterner`] it's filled as
+/// documents go through it, up to `max_symbols` keys of at most `max_len` bytes; other keys
+/// stay as they are, so that keys made up per document don't grow it without end.
+#[derive(Debug)]
+pub struct Symbols {
+    table: RwLock<HashSet<&'static str>>,
+    max_symbols: usize,
+    max_len: usize,
+    bytes: AtomicUsize,
+}
+
+impl Symbols {
+    pub fn new(max_symbols: usize, max_len: usize) -> Self {
+        Symbols { table: RwLock::default(), max_symbols, max_len, bytes: AtomicUsize::new(0) }
+    }
+
+    /// The table shared by the whole program, of up to 4096 keys of up to 64 bytes.
+    pub fn global() -> &'static Symbols {
+        static GLOBAL: OnceLock<Symbols> = OnceLock::new();
+        GLOBAL.get_or_init(|| Symbols::new(4096, 64))
+    }
+
+    /// The symbol for `key`, `None` when it's too long or the table is full without it.
+    pub fn intern(&self, key: &str) -> Option<&'static str> {
+        if key.len() > self.max_len {
+            return None;
+        }
+        if let Some(symbol) = self.table.read().unwrap().get(key) {
+            return Some(symbol);
+        }
+        let mut table = self.table.write().unwrap();
+        if let Some(symbol) = table.get(key) {
+            return Some(symbol);
+        }
+        if table.len() >= self.max_symbols {
+            return None;
+        }
+        let symbol: &'static str = Box::leak(key.into());
+        table.insert(symbol);
+        self.bytes.fetch_add(symbol.len(), Ordering::Relaxed);
+        Some(symbol)
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Of the keys kept.
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+impl<'a> Value<'a> {
+    /// Whether an object in here has a key of its own rather than borrowed, e.g. one that had
+    /// escapes in the input.
+    pub fn has_owned_keys(&self) -> bool {
+        match self {
+            Value::Object(map) => map.iter().any(|(key, value)| matches!(key, Cow::Owned(_)) || value.has_owned_keys()),
+            Value::Array(vec) => vec.iter().any(Value::has_owned_keys),
+            _ => false,
+        }
+    }
+
+    /// Replaces the keys objects own with their symbols, how many were. Borrowed keys stay,
+    /// they cost nothing but the input that's held anyway.
+    pub fn intern_keys(&mut self, symbols: &Symbols) -> usize {
+        match self {
+            Value::Object(map) => {
+                let mut interned = map.values_mut().map(|value| value.intern_keys(symbols)).sum();
+                if map.keys().any(|key| matches!(key, Cow::Owned(_))) {
+                    *map = std::mem::take(map)
+                        .into_iter()
+                        .map(|(key, value)| match key {
+                            Cow::Owned(owned) => match symbols.intern(&owned) {
+                                Some(symbol) => {
+                                    interned += 1;
+                                    (Cow::Borrowed(symbol), value)
+                                }
+                                None => (Cow::Owned(owned), value),
+                            },
+                            borrowed => (borrowed, value),
+                        })
+                        .collect();
+                }
+                interned
+            }
+            Value::Array(vec) => vec.iter_mut().map(|value| value.intern_keys(symbols)).sum(),
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use crate::Value;
+    use super::Symbols;
+
+    #[test]
+    fn owned_keys_become_shared_symbols() {
+        let symbols = Symbols::new(2, 8);
+        let input = r#"[{"a\"b":1,"plain":2},{"a\"b":3,"c\nd":4,"long\tkey and more":5}]"#;
+        let mut value: Value = serde_json_nostr::from_str(input).unwrap();
+        assert!(value.has_owned_keys());
+        // the last key is over `max_len`
+        assert_eq!(value.intern_keys(&symbols), 3);
+        assert_eq!((symbols.len(), symbols.bytes()), (2, 6));
+        assert_eq!(serde_json_nostr::to_string(&value).unwrap(), input);
+
+        let key = |item: &str, key: &str| match value.pointer(item) {
+            Some(Value::Object(map)) => map.keys().find(|k| *k == key).cloned().unwrap(),
+            _ => panic!(),
+        };
+        let (first, second) = (key("/0", "a\"b"), key("/1", "a\"b"));
+        assert!(matches!((&first, &second), (Cow::Borrowed(a), Cow::Borrowed(b)) if a.as_ptr() == b.as_ptr()));
+        assert!(matches!(key("/0", "plain"), Cow::Borrowed(k) if input.as_bytes().as_ptr_range().contains(&k.as_ptr())));
+        assert!(matches!(key("/1", "long\tkey and more"), Cow::Owned(_)));
+        // full, what isn't in there yet stays owned
+        assert_eq!(symbols.intern("other"), None);
+        assert_eq!(symbols.intern("c\nd"), Some("c\nd"));
+    }
+}
@@ -0,0 +1,315 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::Value;
+
+#[derive(Debug)]
+pub enum Error {
+    UnterminatedString { offset: usize },
+    /// The index was built from an input of another length.
+    WrongLength { indexed: usize, input: usize },
+    /// Offsets are `u32`, an input of 4GiB or more can't be indexed.
+    TooLong { len: usize },
+    Syntax { offset: usize, expected: &'static str },
+    /// A scalar or escaped string starting at `offset` didn't parse.
+    Json { offset: usize, error: serde_json_nostr::Error },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnterminatedString { offset } => write!(f, "unterminated string at {}", offset),
+            Error::WrongLength { indexed, input } => write!(f, "index of {} bytes used for {}", indexed, input),
+            Error::TooLong { len } => write!(f, "{} bytes is too long to index", len),
+            Error::Syntax { offset, expected } => write!(f, "expected {} at {}", expected, offset),
+            Error::Json { offset, error } => write!(f, "at {}: {}", offset, error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Where a JSON document's structure is: the offsets of its brackets, braces, colons and
+/// commas and of the quotes around its strings, in order. Building it is the only pass that
+/// looks at every byte, [`StructuralIndex::parse`] only reads what's between the offsets, so
+/// one index can parse many documents laid out alike, e.g. a cached body revalidated
+/// unchanged. An index that doesn't fit a document fails the parse, it never parses something
+/// the document doesn't say.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructuralIndex {
+    positions: Vec<u32>,
+    len: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Token<'a> {
+    Byte(u8, usize),
+    /// With its quotes.
+    Str(&'a [u8], usize),
+    Scalar(&'a [u8], usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Expect {
+    Value,
+    /// A value or the end of the array just opened.
+    FirstValue,
+    Key,
+    FirstKey,
+    Colon,
+    /// A comma or the end of the container.
+    Next,
+}
+
+impl Expect {
+    fn describe(self) -> &'static str {
+        match self {
+            Expect::Value => "a value",
+            Expect::FirstValue => "a value or ']'",
+            Expect::Key => "a key",
+            Expect::FirstKey => "a key or '}'",
+            Expect::Colon => "':'",
+            Expect::Next => "',' or the end of a container",
+        }
+    }
+}
+
+enum Frame<'a> {
+    Array(Vec<Value<'a>>),
+    Object(BTreeMap<Cow<'a, str>, Value<'a>>, Option<Cow<'a, str>>),
+}
+
+fn trim(bytes: &[u8], mut offset: usize) -> Option<(&[u8], usize)> {
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace())?;
+    let end = bytes.iter().rposition(|b| !b.is_ascii_whitespace())? + 1;
+    offset += start;
+    Some((&bytes[start..end], offset))
+}
+
+fn json<'a>(bytes: &'a [u8], offset: usize) -> Result<Value<'a>, Error> {
+    serde_json_nostr::from_slice(bytes).map_err(|error| Error::Json { offset, error })
+}
+
+impl StructuralIndex {
+    pub fn build(input: &[u8]) -> Result<Self, Error> {
+        let at = |i: usize| u32::try_from(i).map_err(|_| Error::TooLong { len: input.len() });
+        let mut positions = Vec::new();
+        let mut string = None;
+        let mut i = 0;
+        while i < input.len() {
+            match (input[i], string) {
+                (b'\\', Some(_)) => i += 1,
+                (b'"', Some(_)) => {
+                    positions.push(at(i)?);
+                    string = None;
+                }
+                (b'"', None) => {
+                    positions.push(at(i)?);
+                    string = Some(i);
+                }
+                (b'{' | b'}' | b'[' | b']' | b':' | b',', None) => positions.push(at(i)?),
+                _ => {}
+            }
+            i += 1;
+        }
+        match string {
+            Some(offset) => Err(Error::UnterminatedString { offset }),
+            None => Ok(StructuralIndex { positions, len: input.len() }),
+        }
+    }
+
+    /// Offsets of the structural bytes, ascending.
+    pub fn positions(&self) -> &[u32] {
+        &self.positions
+    }
+
+    /// Length of the document the index was built from.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn tokens<'s, 'a: 's>(&'s self, input: &'a [u8]) -> impl Iterator<Item = Result<Token<'a>, Error>> + 's {
+        let mut positions = self.positions.iter().map(|&p| p as usize);
+        let mut prev = 0;
+        let mut pending = None;
+        std::iter::from_fn(move || {
+            if let Some(token) = pending.take() {
+                return Some(Ok(token));
+            }
+            let (byte, at) = match positions.next() {
+                Some(at) => (*input.get(at)?, at),
+                None => {
+                    let gap = trim(&input[prev..], prev);
+                    prev = input.len();
+                    return gap.map(|(scalar, offset)| Ok(Token::Scalar(scalar, offset)));
+                }
+            };
+            let gap = trim(&input[prev..at], prev);
+            let token = match byte {
+                b'"' => match positions.next() {
+                    Some(close) if input.get(close) == Some(&b'"') => {
+                        prev = close + 1;
+                        Token::Str(&input[at..=close], at)
+                    }
+                    _ => return Some(Err(Error::UnterminatedString { offset: at })),
+                },
+                b'{' | b'}' | b'[' | b']' | b':' | b',' => {
+                    prev = at + 1;
+                    Token::Byte(byte, at)
+                }
+                _ => return Some(Err(Error::Syntax { offset: at, expected: "a structural character" })),
+            };
+            match gap {
+                Some((scalar, offset)) => {
+                    pending = Some(token);
+                    Some(Ok(Token::Scalar(scalar, offset)))
+                }
+                None => Some(Ok(token)),
+            }
+        })
+    }
+
+    /// Parses `input` along the index, giving the same [`Value`] as
+    /// `serde_json_nostr::from_slice` would.
+    pub fn parse<'a>(&self, input: &'a [u8]) -> Result<Value<'a>, Error> {
+        if input.len() != self.len {
+            return Err(Error::WrongLength { indexed: self.len, input: input.len() });
+        }
+        let mut stack: Vec<Frame<'a>> = Vec::new();
+        let mut root = None;
+        let mut expect = Expect::Value;
+        for token in self.tokens(input) {
+            let token = token?;
+            let offset = match token {
+                Token::Byte(_, offset) | Token::Str(_, offset) | Token::Scalar(_, offset) => offset,
+            };
+            if root.is_some() {
+                return Err(Error::Syntax { offset, expected: "the end of the document" });
+            }
+            let value = match (expect, token) {
+                (Expect::Value | Expect::FirstValue, Token::Byte(b'[', _)) => {
+                    stack.push(Frame::Array(Vec::new()));
+                    expect = Expect::FirstValue;
+                    continue;
+                }
+                (Expect::Value | Expect::FirstValue, Token::Byte(b'{', _)) => {
+                    stack.push(Frame::Object(BTreeMap::new(), None));
+                    expect = Expect::FirstKey;
+                    continue;
+                }
+                (Expect::FirstValue | Expect::Next, Token::Byte(b']', _)) => match stack.pop() {
+                    Some(Frame::Array(vec)) => Value::Array(vec),
+                    _ => return Err(Error::Syntax { offset, expected: "'}'" }),
+                },
+                (Expect::FirstKey | Expect::Next, Token::Byte(b'}', _)) => match stack.pop() {
+                    Some(Frame::Object(map, _)) => Value::Object(map),
+                    _ => return Err(Error::Syntax { offset, expected: "']'" }),
+                },
+                (Expect::Key | Expect::FirstKey, Token::Str(raw, offset)) => {
+                    if let Some(Frame::Object(_, key)) = stack.last_mut() {
+                        *key = Some(string_key(raw, offset)?);
+                    }
+                    expect = Expect::Colon;
+                    continue;
+                }
+                (Expect::Colon, Token::Byte(b':', _)) => {
+                    expect = Expect::Value;
+                    continue;
+                }
+                (Expect::Next, Token::Byte(b',', _)) => {
+                    expect = match stack.last() {
+                        Some(Frame::Object(..)) => Expect::Key,
+                        _ => Expect::Value,
+                    };
+                    continue;
+                }
+                (Expect::Value | Expect::FirstValue, Token::Str(raw, offset)) => string_value(raw, offset)?,
+                (Expect::Value | Expect::FirstValue, Token::Scalar(raw, offset)) => json(raw, offset)?,
+                (expect, _) => return Err(Error::Syntax { offset, expected: expect.describe() }),
+            };
+            match stack.last_mut() {
+                None => root = Some(value),
+                Some(Frame::Array(vec)) => vec.push(value),
+                Some(Frame::Object(map, key)) => {
+                    // a value in an object always follows its key
+                    if let Some(key) = key.take() {
+                        map.insert(key, value);
+                    }
+                }
+            }
+            expect = Expect::Next;
+        }
+        match root {
+            Some(value) => Ok(value),
+            None => Err(Error::Syntax { offset: input.len(), expected: expect.describe() }),
+        }
+    }
+}
+
+fn plain(content: &[u8]) -> bool {
+    !content.iter().any(|&b| b == b'"' || b == b'\\' || b < 0x20)
+}
+
+// strings without escapes are borrowed as is, the same as `serde_json_nostr` does
+fn string_value(raw: &[u8], offset: usize) -> Result<Value<'_>, Error> {
+    let content = &raw[1..raw.len() - 1];
+    if plain(content) {
+        return Ok(Value::Bytes(content));
+    }
+    json(raw, offset)
+}
+
+fn string_key(raw: &[u8], offset: usize) -> Result<Cow<'_, str>, Error> {
+    let content = &raw[1..raw.len() - 1];
+    match std::str::from_utf8(content) {
+        Ok(key) if plain(content) => Ok(Cow::Borrowed(key)),
+        _ => match json(raw, offset)? {
+            Value::String(key) => Ok(Cow::Owned(key)),
+            _ => Err(Error::Syntax { offset, expected: "a utf-8 key" }),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{Error, StructuralIndex};
+
+    const DOCUMENT: &str = r#"{"items": [{"id": 1, "name": "a\nb", "tags": []}, {"id": -2.5e3, "ok": true, "meta": {}}], "next": null}"#;
+
+    #[test]
+    fn tape_parse_matches_from_slice() {
+        let index = StructuralIndex::build(DOCUMENT.as_bytes()).unwrap();
+        assert_eq!(index.parse(DOCUMENT.as_bytes()).unwrap(), serde_json_nostr::from_str::<Value>(DOCUMENT).unwrap());
+        for document in ["42", r#" "top" "#, "[]", "[[1,[2]],{}]", r#"{"a\nb":1}"#] {
+            let index = StructuralIndex::build(document.as_bytes()).unwrap();
+            assert_eq!(index.parse(document.as_bytes()).unwrap(), serde_json_nostr::from_str::<Value>(document).unwrap(), "{}", document);
+        }
+        for document in ["[1 2]", "[1,]", r#"{"a" 1}"#, "{1:2}", "[}", "[1]]", "[", ""] {
+            let index = StructuralIndex::build(document.as_bytes()).unwrap();
+            assert!(index.parse(document.as_bytes()).is_err(), "{}", document);
+        }
+        assert!(matches!(StructuralIndex::build(br#"["a\"]"#), Err(Error::UnterminatedString { offset: 1 })));
+    }
+
+    #[test]
+    fn tape_reused_for_alike_documents() {
+        let first = br#"{"id":17,"name":"alice","tags":["x","y"]}"#;
+        let second = br#"{"id":42,"name":"carol","tags":["z","w"]}"#;
+        let index = StructuralIndex::build(first).unwrap();
+        assert_eq!(index, StructuralIndex::build(second).unwrap());
+        assert_eq!(index.parse(second).unwrap(), serde_json_nostr::from_slice::<Value>(second).unwrap());
+
+        // what's between the offsets is parsed in full
+        let comma = br#"{"id":17,"name":"al,ce","tags":["x","y"]}"#;
+        assert!(index.parse(comma).unwrap().pointer("/name").is_some_and(|name| name.identical(&Value::Bytes(b"al,ce"))));
+        // same length, other structure
+        assert!(index.parse(br#"{"id":17,"name":"alice","tags":["xy"]   }"#).is_err());
+        assert!(index.parse(br#"{"id":17,"name":"al"ce","tags":["x","y"]}"#).is_err());
+        assert!(matches!(index.parse(b"[]"), Err(Error::WrongLength { .. })));
+    }
+}
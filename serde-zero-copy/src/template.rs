@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::Value;
+
+#[derive(Debug)]
+pub enum Error {
+    Json(serde_json_nostr::Error),
+    /// A `${` without its `}`, at this byte of the string.
+    Unclosed { offset: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Json(err) => write!(f, "template: {}", err),
+            Error::Unclosed { offset } => write!(f, "template: unclosed `${{` at {}", offset),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A JSON document to build responses from, e.g. `{"greeting": "Hello ${/user/name}!", "user":
+/// "${/user}"}`. A string that is a single `${pointer}` becomes the value there, as it is;
+/// other strings with `${pointer}` in them become new strings, the values written in, strings
+/// without their quotes and anything else as JSON, missing ones as nothing. Keys and strings
+/// without `${` stay borrowed from the template.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template<'t>(Value<'t>);
+
+fn text<'t>(value: &Value<'t>) -> Option<&'t str> {
+    match value {
+        Value::Bytes(b) => std::str::from_utf8(b).ok(),
+        Value::Str(s) => Some(s),
+        _ => None,
+    }
+}
+
+fn check(value: &Value) -> Result<(), Error> {
+    match value {
+        Value::Array(vec) => vec.iter().try_for_each(check),
+        Value::Object(map) => map.values().try_for_each(check),
+        Value::String(s) => holes(s),
+        value => text(value).map_or(Ok(()), holes),
+    }
+}
+
+fn holes(s: &str) -> Result<(), Error> {
+    let mut rest = 0;
+    while let Some(start) = s[rest..].find("${") {
+        let start = rest + start;
+        rest = start + s[start..].find('}').ok_or(Error::Unclosed { offset: start })? + 1;
+    }
+    Ok(())
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match (text(value), value) {
+        (Some(s), _) => out.push_str(s),
+        (_, Value::String(s)) => out.push_str(s),
+        (_, Value::Number(n)) => out.push_str(&n.to_string()),
+        (_, value) => out.push_str(&serde_json_nostr::to_string(value).unwrap_or_default()),
+    }
+}
+
+fn interpolate<'o>(s: &str, value: &Value<'o>) -> Value<'o> {
+    if let Some(pointer) = s.strip_prefix("${").and_then(|s| s.strip_suffix('}')).filter(|p| !p.contains('}')) {
+        return value.pointer(pointer).cloned().unwrap_or(Value::Null);
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else { break };
+        if let Some(found) = value.pointer(&rest[start + 2..start + end]) {
+            write_value(&mut out, found);
+        }
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    Value::String(out)
+}
+
+fn render<'o>(node: &Value<'o>, value: &Value<'o>) -> Value<'o> {
+    match node {
+        Value::Array(vec) => Value::Array(vec.iter().map(|node| render(node, value)).collect()),
+        Value::Object(map) => Value::Object(map.iter().map(|(key, node)| (key.clone(), render(node, value))).collect::<BTreeMap<_, _>>()),
+        Value::String(s) if s.contains("${") => interpolate(s, value),
+        node => match text(node) {
+            Some(s) if s.contains("${") => interpolate(s, value),
+            Some(s) => Value::Str(s),
+            None => node.clone(),
+        },
+    }
+}
+
+impl<'t> Template<'t> {
+    pub fn parse(template: &'t str) -> Result<Self, Error> {
+        let template: Value<'t> = serde_json_nostr::from_str(template).map_err(Error::Json)?;
+        check(&template)?;
+        Ok(Template(template))
+    }
+
+    /// The template filled in from `value`. Only strings with `${` in them allocate, the rest
+    /// borrows from the template or from `value`.
+    pub fn render<'o>(&self, value: &Value<'o>) -> Value<'o>
+        where
+            't: 'o,
+    {
+        render(&self.0, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{Error, Template};
+
+    const INPUT: &str = r#"{"user":{"name":"Jane","age":30,"tags":["a"]},"items":[1,2]}"#;
+
+    #[test]
+    fn template_interpolates() {
+        let template = Template::parse(
+            r#"{"greeting":"Hello ${/user/name}!","user":"${/user}","line":"${/user/name} is ${/user/age}, ${/user/tags} ${/missing}.","static":"plain","count":2}"#,
+        ).unwrap();
+        let value: Value = serde_json_nostr::from_str(INPUT).unwrap();
+        assert_eq!(
+            serde_json_nostr::to_string(&template.render(&value)).unwrap(),
+            r#"{"count":2,"greeting":"Hello Jane!","line":"Jane is 30, [\"a\"] .","static":"plain","user":{"age":30,"name":"Jane","tags":["a"]}}"#
+        );
+        assert!(matches!(Template::parse(r#"["${/user"]"#), Err(Error::Unclosed { offset: 0 })));
+    }
+
+    #[test]
+    fn template_static_strings_borrow() {
+        let source = r#"{"kind":"static","name":"${/user/name}"}"#;
+        let template = Template::parse(source).unwrap();
+        let value: Value = serde_json_nostr::from_str(INPUT).unwrap();
+        match template.render(&value) {
+            Value::Object(map) => {
+                assert!(matches!(map["kind"], Value::Str(s) if source.as_bytes().as_ptr_range().contains(&s.as_ptr())));
+                assert!(matches!(map["name"], Value::Bytes(b) if INPUT.as_bytes().as_ptr_range().contains(&b.as_ptr())));
+            }
+            _ => panic!(),
+        }
+    }
+}
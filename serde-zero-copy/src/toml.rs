@@ -0,0 +1,133 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use ::toml::de::{DeTable, DeValue};
+use serde_json::Number;
+use crate::Value;
+
+#[derive(Debug)]
+pub enum Error {
+    Toml(::toml::de::Error),
+    /// An integer of another radix than 10 that's wider than 128 bits.
+    Integer(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Toml(err) => err.fmt(f),
+            Error::Integer(integer) => write!(f, "integer {} is out of range", integer),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn string<'a>(s: &Cow<'a, str>) -> Value<'a> {
+    match s {
+        Cow::Borrowed(s) => Value::Str(s),
+        Cow::Owned(s) => Value::String(s.clone()),
+    }
+}
+
+fn table<'a>(input: &'a str, table: &DeTable<'a>) -> Result<Value<'a>, Error> {
+    let mut map = BTreeMap::new();
+    for (key, value) in table {
+        map.insert(key.get_ref().clone(), convert(input, value.get_ref(), value.span())?);
+    }
+    Ok(Value::Object(map))
+}
+
+fn convert<'a>(input: &'a str, value: &DeValue<'a>, span: std::ops::Range<usize>) -> Result<Value<'a>, Error> {
+    Ok(match value {
+        DeValue::String(s) => string(s),
+        DeValue::Integer(i) => {
+            let n = i64::from_str_radix(i.as_str(), i.radix()).map(Number::from).ok()
+                .or_else(|| u64::from_str_radix(i.as_str(), i.radix()).map(Number::from).ok());
+            match n {
+                Some(n) => Value::Number(n),
+                // wider ones as big integers, as JSON's are, rather than rounded
+                None => match i128::from_str_radix(i.as_str(), i.radix()) {
+                    Ok(n) => crate::from_i128(n),
+                    Err(_) if i.radix() == 10 => Value::BigInt(Cow::Owned(i.as_str().trim_start_matches('+').to_string())),
+                    Err(_) => return Err(Error::Integer(input[span].to_string())),
+                },
+            }
+        }
+        // `nan` and `inf` are TOML floats too
+        DeValue::Float(f) => f.as_str().parse().map_or(Value::Null, Value::from_f64),
+        DeValue::Boolean(b) => Value::Bool(*b),
+        DeValue::Datetime(_) => Value::Str(&input[span]),
+        DeValue::Array(array) => {
+            let mut vec = Vec::with_capacity(array.len());
+            for item in array.iter() {
+                vec.push(convert(input, item.get_ref(), item.span())?);
+            }
+            Value::Array(vec)
+        }
+        DeValue::Table(t) => table(input, t)?,
+    })
+}
+
+/// Parses a TOML document into an object, borrowing keys and strings without escapes and the
+/// source text of datetimes. Non-finite floats become nulls.
+pub fn from_toml_str(input: &str) -> Result<Value<'_>, Error> {
+    let root = DeTable::parse(input).map_err(Error::Toml)?;
+    table(input, root.get_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{from_toml_str, Error};
+
+    #[test]
+    fn toml_borrows_scalars() {
+        let input = r#"
+            name = "proxy"
+            escaped = "tab\there"
+            port = 2_000
+            ratio = 0.5
+            started = 1979-05-27T07:32:00Z
+
+            [upstream]
+            hosts = ["a", 'b']
+            hex = 0xff
+
+            [[routes]]
+            path = "/zc"
+        "#;
+        let value = from_toml_str(input).unwrap();
+        assert_eq!(
+            serde_json_nostr::to_string(&value).unwrap(),
+            r#"{"escaped":"tab\there","name":"proxy","port":2000,"ratio":0.5,"routes":[{"path":"/zc"}],"started":"1979-05-27T07:32:00Z","upstream":{"hex":255,"hosts":["a","b"]}}"#
+        );
+        match &value {
+            Value::Object(map) => {
+                let (k, v) = map.get_key_value("name").unwrap();
+                assert!(input.as_bytes().as_ptr_range().contains(&k.as_ptr()));
+                assert!(matches!(v, Value::Str(s) if input.as_bytes().as_ptr_range().contains(&s.as_ptr())));
+                assert!(map["escaped"].identical(&Value::String("tab\there".to_string())));
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn toml_errors() {
+        assert!(matches!(from_toml_str("a = "), Err(Error::Toml(_))));
+        let escaped = from_toml_str(r#""a\tb" = 1"#).unwrap();
+        assert_eq!(serde_json_nostr::to_string(&escaped).unwrap(), r#"{"a\tb":1}"#);
+        assert!(matches!(from_toml_str(&format!("a = 0x{}", "f".repeat(40))), Err(Error::Integer(_))));
+    }
+
+    #[test]
+    fn toml_wide_integers_stay_numbers() {
+        let value = from_toml_str("big = 18_446_744_073_709_551_616\nhuge = -1000000000000000000000000000000000000000000\nhex = 0xffffffffffffffffff").unwrap();
+        assert!(matches!(value.pointer("/big"), Some(Value::BigInt(_))));
+        assert_eq!(
+            serde_json_nostr::to_string(&value).unwrap(),
+            r#"{"big":18446744073709551616,"hex":4722366482869645213695,"huge":-1000000000000000000000000000000000000000000}"#
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::Value;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Malformed bracket syntax, e.g. `a[b=1`.
+    InvalidKey(String),
+    /// The same path was used both as a scalar and as a container, e.g. `a=1&a[b]=2`.
+    /// Repeating a scalar is fine, the last one wins.
+    Conflict(String),
+    /// An index more than [`MAX_INDEX_GAP`] past the end of its array so far, e.g.
+    /// `a[100]=1` on its own.
+    IndexOutOfRange(String),
+    /// Only objects can be written as `key=value` pairs.
+    NotAnObject,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidKey(key) => write!(f, "invalid key: {}", key),
+            Error::Conflict(key) => write!(f, "conflicting values for key: {}", key),
+            Error::IndexOutOfRange(key) => write!(f, "index out of range in key: {}", key),
+            Error::NotAnObject => f.write_str("only objects can be urlencoded"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// How many nulls an index can leave before it in an array, so that a key can't make one of
+/// any length.
+pub const MAX_INDEX_GAP: usize = 64;
+
+enum Segment<'a> {
+    Key(Cow<'a, str>),
+    Index(usize),
+    Append,
+}
+
+impl<'a> Segment<'a> {
+    fn into_owned<'b>(self) -> Segment<'b> {
+        match self {
+            Segment::Key(k) => Segment::Key(Cow::Owned(k.into_owned())),
+            Segment::Index(i) => Segment::Index(i),
+            Segment::Append => Segment::Append,
+        }
+    }
+}
+
+// `b[0][name]` -> ["b", 0, "name"], `b[]` -> ["b", append]
+fn segments(key: &str) -> Result<Vec<Segment<'_>>, Error> {
+    let (head, mut rest) = match key.find('[') {
+        Some(i) => (&key[..i], &key[i..]),
+        None => (key, ""),
+    };
+    let mut segments = vec![Segment::Key(Cow::Borrowed(head))];
+    while !rest.is_empty() {
+        let end = match (rest.starts_with('['), rest.find(']')) {
+            (true, Some(end)) => end,
+            _ => return Err(Error::InvalidKey(key.to_string())),
+        };
+        let inner = &rest[1..end];
+        segments.push(if inner.is_empty() {
+            Segment::Append
+        } else if inner.bytes().all(|b| b.is_ascii_digit()) {
+            match inner.parse() {
+                Ok(i) => Segment::Index(i),
+                Err(_) => return Err(Error::InvalidKey(key.to_string())),
+            }
+        } else {
+            Segment::Key(Cow::Borrowed(inner))
+        });
+        rest = &rest[end + 1..];
+    }
+    Ok(segments)
+}
+
+fn insert<'a>(target: &mut Value<'a>, path: &[Segment<'a>], value: Value<'a>, key: &str) -> Result<(), Error> {
+    let (segment, rest) = match path.split_first() {
+        Some(split) => split,
+        None => {
+            *target = value;
+            return Ok(());
+        }
+    };
+    let placeholder = || if rest.is_empty() { Value::Null } else if matches!(rest[0], Segment::Key(_)) {
+        Value::Object(BTreeMap::new())
+    } else {
+        Value::Array(Vec::new())
+    };
+    if let Value::Null = target {
+        *target = match segment {
+            Segment::Key(_) => Value::Object(BTreeMap::new()),
+            _ => Value::Array(Vec::new()),
+        };
+    }
+    let child = match (segment, target) {
+        (Segment::Key(k), Value::Object(map)) => map.entry(k.clone()).or_insert_with(placeholder),
+        (Segment::Index(i), Value::Array(vec)) => {
+            if *i > vec.len() + MAX_INDEX_GAP {
+                return Err(Error::IndexOutOfRange(key.to_string()));
+            }
+            if vec.len() <= *i {
+                vec.resize(*i + 1, Value::Null);
+            }
+            &mut vec[*i]
+        }
+        (Segment::Append, Value::Array(vec)) => {
+            vec.push(placeholder());
+            vec.last_mut().unwrap()
+        }
+        _ => return Err(Error::Conflict(key.to_string())),
+    };
+    // repeated scalars replace each other, like `a=1&a=2`, but never a nested container
+    if rest.is_empty() && matches!(child, Value::Array(_) | Value::Object(_)) {
+        return Err(Error::Conflict(key.to_string()));
+    }
+    insert(child, rest, value, key)
+}
+
+/// Parses `a=1&b[0]=x&c[d]=y` into an object, borrowing every key and value that doesn't
+/// need decoding. Values stay strings, `a=1` gives `{"a": "1"}`.
+pub fn from_urlencoded(input: &str) -> Result<Value<'_>, Error> {
+    let mut root = Value::Object(BTreeMap::new());
+    for (key, value) in form_urlencoded::parse(input.as_bytes()) {
+        let path = match &key {
+            Cow::Borrowed(key) => segments(key)?,
+            Cow::Owned(key) => segments(key)?.into_iter().map(Segment::into_owned).collect(),
+        };
+        let value = match value {
+            Cow::Borrowed(value) => Value::Str(value),
+            Cow::Owned(value) => Value::String(value),
+        };
+        insert(&mut root, &path, value, &key)?;
+    }
+    Ok(root)
+}
+
+fn encode(s: &str) -> impl Iterator<Item = &str> {
+    form_urlencoded::byte_serialize(s.as_bytes())
+}
+
+// `prefix` is already encoded, brackets are kept literal so the output parses back borrowed
+fn write_pairs(out: &mut String, prefix: &str, value: &Value) {
+    let scalar: Cow<str> = match value {
+        Value::Null => Cow::Borrowed(""),
+        Value::Bool(b) => Cow::Borrowed(if *b { "true" } else { "false" }),
+        Value::Number(n) => Cow::Owned(n.to_string()),
+        Value::BigInt(n) => Cow::Borrowed(n),
+        Value::NonFinite(f) => Cow::Owned(f.to_string()),
+        Value::Bytes(b) => String::from_utf8_lossy(b),
+        Value::Str(s) => Cow::Borrowed(s),
+        Value::String(s) => Cow::Borrowed(s),
+        Value::Array(vec) => {
+            for (i, item) in vec.iter().enumerate() {
+                write_pairs(out, &format!("{}[{}]", prefix, i), item);
+            }
+            return;
+        }
+        Value::Object(map) => {
+            for (k, v) in map {
+                write_pairs(out, &format!("{}[{}]", prefix, encode(k).collect::<String>()), v);
+            }
+            return;
+        }
+    };
+    if !out.is_empty() {
+        out.push('&');
+    }
+    out.push_str(prefix);
+    out.push('=');
+    out.extend(encode(&scalar));
+}
+
+/// The reverse of [`from_urlencoded`], nested values use the same bracket notation.
+pub fn to_urlencoded(value: &Value) -> Result<String, Error> {
+    let map = match value {
+        Value::Object(map) => map,
+        _ => return Err(Error::NotAnObject),
+    };
+    let mut out = String::new();
+    for (k, v) in map {
+        write_pairs(&mut out, &encode(k).collect::<String>(), v);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{from_urlencoded, to_urlencoded, Error};
+
+    #[test]
+    fn urlencoded_borrows_plain_components() {
+        let input = "a=1&b[0]=x&b[1]=y&c[d]=hello+world&e[]=p&e[]=q";
+        let value = from_urlencoded(input).unwrap();
+        let map = match &value {
+            Value::Object(map) => map,
+            _ => panic!(),
+        };
+        let (k, v) = map.get_key_value("a").unwrap();
+        assert_eq!(k.as_ptr(), input.as_ptr());
+        assert!(v.identical(&Value::Str("1")));
+        assert!(map["b"].identical(&Value::Array(vec![Value::Str("x"), Value::Str("y")])));
+        assert!(map["e"].identical(&Value::Array(vec![Value::Str("p"), Value::Str("q")])));
+        match &map["c"] {
+            Value::Object(c) => assert!(c["d"].identical(&Value::String("hello world".to_string()))),
+            _ => panic!(),
+        }
+        assert_eq!(
+            serde_json_nostr::to_string(&value).unwrap(),
+            r#"{"a":"1","b":["x","y"],"c":{"d":"hello world"},"e":["p","q"]}"#
+        );
+    }
+
+    #[test]
+    fn urlencoded_round_trip() {
+        let input = "a=1&b[0]=x%26y&b[1]=&c[d]=%C3%BC+!&c[e]=true&f%20g[h]=2";
+        let value = from_urlencoded(input).unwrap();
+        let encoded = to_urlencoded(&value).unwrap();
+        assert_eq!(encoded, "a=1&b[0]=x%26y&b[1]=&c[d]=%C3%BC+%21&c[e]=true&f+g[h]=2");
+        assert_eq!(from_urlencoded(&encoded).unwrap(), value);
+    }
+
+    #[test]
+    fn urlencoded_errors() {
+        assert_eq!(from_urlencoded("a=1&a[b]=2"), Err(Error::Conflict("a[b]".to_string())));
+        assert_eq!(from_urlencoded("a[b]=2&a=1"), Err(Error::Conflict("a".to_string())));
+        assert_eq!(from_urlencoded("a[b=1"), Err(Error::InvalidKey("a[b".to_string())));
+        assert_eq!(from_urlencoded("a[18446744073709551615]=1"), Err(Error::IndexOutOfRange("a[18446744073709551615]".to_string())));
+        assert_eq!(from_urlencoded("a[64]=1&a[129]=2").map(|v| matches!(&v.pointer("/a/129"), Some(Value::Str("2")))), Ok(true));
+        assert_eq!(to_urlencoded(&Value::Null), Err(Error::NotAnObject));
+    }
+}
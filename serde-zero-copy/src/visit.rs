@@ -0,0 +1,182 @@
+use std::borrow::Cow;
+use crate::Value;
+
+/// One step from the root to a node: a member's key or an element's index. Indices are those
+/// in the input, before any element was removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment<'a> {
+    Key(Cow<'a, str>),
+    Index(usize),
+}
+
+/// What [`Fold::enter`] wants done with a node.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Visit<'a> {
+    /// Fold its members or elements, then [`Fold::exit`] it.
+    Descend,
+    /// Keep it as it is, without visiting what's inside or exiting it.
+    Skip,
+    Replace(Value<'a>),
+    /// Drop it from its container, the root becomes null.
+    Remove,
+}
+
+/// A rewrite of a value node by node, depth first, see [`Value::fold`]. `enter` sees each node
+/// before what's inside it and may change it in place, `exit` after, with everything inside
+/// already folded; both get the path to the node.
+pub trait Fold<'a> {
+    fn enter(&mut self, path: &[Segment<'a>], value: &mut Value<'a>) -> Visit<'a> {
+        let _ = (path, value);
+        Visit::Descend
+    }
+
+    /// The node to keep in its place, `None` to drop it.
+    fn exit(&mut self, path: &[Segment<'a>], value: Value<'a>) -> Option<Value<'a>> {
+        let _ = path;
+        Some(value)
+    }
+}
+
+fn fold_node<'a, F>(mut value: Value<'a>, folder: &mut F, path: &mut Vec<Segment<'a>>) -> Option<Value<'a>>
+    where
+        F: Fold<'a> + ?Sized,
+{
+    match folder.enter(path, &mut value) {
+        Visit::Descend => {}
+        Visit::Skip => return Some(value),
+        Visit::Replace(value) => return Some(value),
+        Visit::Remove => return None,
+    }
+    let value = match value {
+        Value::Array(vec) => Value::Array(
+            vec.into_iter()
+                .enumerate()
+                .filter_map(|(index, element)| {
+                    path.push(Segment::Index(index));
+                    let folded = fold_node(element, folder, path);
+                    path.pop();
+                    folded
+                })
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter_map(|(key, member)| {
+                    // the key itself is lent to the path, an owned one isn't copied
+                    path.push(Segment::Key(key));
+                    let folded = fold_node(member, folder, path);
+                    let Some(Segment::Key(key)) = path.pop() else {
+                        unreachable!("the path is as it was pushed");
+                    };
+                    folded.map(|member| (key, member))
+                })
+                .collect(),
+        ),
+        leaf => leaf,
+    };
+    folder.exit(path, value)
+}
+
+impl<'a> Value<'a> {
+    /// Rewrites the value with `folder`, members of objects in key order and elements of
+    /// arrays in order. What the folder keeps stays borrowed.
+    pub fn fold<F>(self, folder: &mut F) -> Value<'a>
+        where
+            F: Fold<'a> + ?Sized,
+    {
+        fold_node(self, folder, &mut Vec::new()).unwrap_or(Value::Null)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Number;
+    use crate::Value;
+    use super::{Fold, Segment, Visit};
+
+    // every "price", at any depth, from cents to euros
+    struct Euros;
+
+    impl<'a> Fold<'a> for Euros {
+        fn exit(&mut self, path: &[Segment<'a>], value: Value<'a>) -> Option<Value<'a>> {
+            match (path.last(), &value) {
+                (Some(Segment::Key(key)), Value::Number(cents)) if key == "price" => {
+                    Some(Number::from_f64(cents.as_f64()? / 100.0).map_or(Value::Null, Value::Number))
+                }
+                _ => Some(value),
+            }
+        }
+    }
+
+    #[test]
+    fn fold_converts_prices() {
+        let input = br#"{"price":250,"items":[{"name":"a","price":100},{"price":"n/a"}],"meta":{"price":5}}"#;
+        let value: Value = serde_json_nostr::from_slice(input).unwrap();
+        let folded = value.fold(&mut Euros);
+        assert_eq!(
+            serde_json_nostr::to_string(&folded).unwrap(),
+            r#"{"items":[{"name":"a","price":1.0},{"price":"n/a"}],"meta":{"price":0.05},"price":2.5}"#
+        );
+        match folded.pointer("/items/0/name") {
+            Some(Value::Bytes(name)) => assert!(input.as_ptr_range().contains(&name.as_ptr())),
+            _ => panic!(),
+        }
+    }
+
+    // drops `internal` members and records the paths it entered
+    #[derive(Default)]
+    struct Paths(Vec<String>);
+
+    impl<'a> Fold<'a> for Paths {
+        fn enter(&mut self, path: &[Segment<'a>], value: &mut Value<'a>) -> Visit<'a> {
+            self.0.push(path.iter().map(|s| match s {
+                Segment::Key(k) => format!("/{}", k),
+                Segment::Index(i) => format!("/{}", i),
+            }).collect());
+            match (path.last(), value) {
+                (Some(Segment::Key(key)), _) if key == "internal" => Visit::Remove,
+                (_, Value::Object(map)) if map.contains_key("opaque") => Visit::Skip,
+                (_, Value::Bool(b)) => {
+                    *b = !*b;
+                    Visit::Descend
+                }
+                _ => Visit::Descend,
+            }
+        }
+    }
+
+    #[test]
+    fn fold_paths_and_control() {
+        let value: Value = serde_json_nostr::from_str(r#"[{"internal":1,"ok":true},{"opaque":{"internal":2}},false]"#).unwrap();
+        let mut paths = Paths::default();
+        let folded = value.fold(&mut paths);
+        assert_eq!(serde_json_nostr::to_string(&folded).unwrap(), r#"[{"ok":false},{"opaque":{"internal":2}},true]"#);
+        assert_eq!(paths.0, vec!["", "/0", "/0/internal", "/0/ok", "/1", "/2"]);
+        assert_eq!(Value::Bool(true).fold(&mut Euros), Value::Bool(true));
+    }
+
+    // where the keys of the path are
+    #[derive(Default)]
+    struct Keys(Vec<*const u8>);
+
+    impl<'a> Fold<'a> for Keys {
+        fn enter(&mut self, path: &[Segment<'a>], _: &mut Value<'a>) -> Visit<'a> {
+            if let Some(Segment::Key(key)) = path.last() {
+                self.0.push(key.as_ptr());
+            }
+            Visit::Descend
+        }
+    }
+
+    #[test]
+    fn fold_lends_keys_to_the_path() {
+        let value: Value = serde_json_nostr::from_str(r#"{"a\"b":1}"#).unwrap();
+        let mut keys = Keys::default();
+        let folded = value.fold(&mut keys);
+        // an owned key is the same string in the path and after
+        match folded {
+            Value::Object(map) => assert_eq!(keys.0, [map.keys().next().unwrap().as_ptr()]),
+            _ => panic!(),
+        }
+    }
+}
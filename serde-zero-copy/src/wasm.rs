@@ -0,0 +1,117 @@
+use std::fmt;
+use wasm_bindgen::prelude::*;
+use crate::mask::MaskField;
+use crate::rename::{Case, KeyRenamer};
+use crate::{project, FieldMask, NullPolicy, Value};
+
+#[derive(Debug)]
+pub enum Error {
+    Json(serde_json_nostr::Error),
+    UnknownCase(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Json(err) => err.fmt(f),
+            Error::UnknownCase(case) => write!(f, "unknown case {:?}, expected \"snake\" or \"camel\"", case),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<serde_json_nostr::Error> for Error {
+    fn from(err: serde_json_nostr::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+// thrown as an `Error` in JS
+impl From<Error> for JsValue {
+    fn from(err: Error) -> Self {
+        JsError::new(&err.to_string()).into()
+    }
+}
+
+fn parse(input: &str) -> Result<Value<'_>, Error> {
+    Ok(serde_json_nostr::from_str(input)?)
+}
+
+fn write(value: &Value) -> Result<String, Error> {
+    Ok(serde_json_nostr::to_string(value)?)
+}
+
+/// The JSON at `pointer` in `input`, `undefined` when there's none.
+#[wasm_bindgen]
+pub fn pointer(input: &str, pointer: &str) -> Result<Option<String>, Error> {
+    parse(input)?.pointer(pointer).map(write).transpose()
+}
+
+// `a.b.c` into `mask`, next to what's there already under `a`
+fn select(mask: &mut FieldMask, path: &str) {
+    let (name, rest) = path.split_once('.').map_or((path, None), |(name, rest)| (name, Some(rest)));
+    let at = match mask.fields.iter().position(|field| field.name == name) {
+        Some(at) => at,
+        None => {
+            mask.fields.push(MaskField { name: name.to_string(), alias: None, mask: FieldMask::default() });
+            mask.fields.len() - 1
+        }
+    };
+    if let Some(rest) = rest {
+        select(&mut mask.fields[at].mask, rest);
+    }
+}
+
+/// `input` with only the members at `fields`, dotted paths such as `user.name`.
+#[wasm_bindgen(js_name = selectFields)]
+pub fn select_fields(input: &str, fields: Vec<String>) -> Result<String, Error> {
+    let mut mask = FieldMask::default();
+    fields.iter().for_each(|field| select(&mut mask, field));
+    write(&project(&parse(input)?, &mask))
+}
+
+/// `input` with its keys in `case`, `"snake"` or `"camel"`, at any depth.
+#[wasm_bindgen(js_name = renameKeys)]
+pub fn rename_keys(input: &str, case: &str) -> Result<String, Error> {
+    let case = match case {
+        "snake" => Case::Snake,
+        "camel" => Case::Camel,
+        case => return Err(Error::UnknownCase(case.to_string())),
+    };
+    let renamer = KeyRenamer::default().case(case);
+    let value = parse(input)?;
+    let names = renamer.names(&value);
+    write(&names.apply(value))
+}
+
+/// `input` without null members, nor empty arrays and objects when `collapse_empty`.
+#[wasm_bindgen(js_name = stripNulls)]
+pub fn strip_nulls(input: &str, collapse_empty: bool) -> Result<String, Error> {
+    let policy = NullPolicy { strip_nulls: true, collapse_empty, ..NullPolicy::default() };
+    write(&parse(input)?.apply_nulls(policy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pointer, rename_keys, select_fields, strip_nulls, Error};
+
+    #[test]
+    fn transforms_json_text() {
+        let input = r#"{"userId":1,"profile":{"displayName":"A","avatarUrl":null,"tags":[]},"orders":[{"id":2,"total":3}]}"#;
+        assert_eq!(pointer(input, "/orders/0/id").unwrap().as_deref(), Some("2"));
+        assert_eq!(pointer(input, "/missing").unwrap(), None);
+        assert_eq!(
+            select_fields(input, vec!["userId".to_string(), "profile.displayName".to_string(), "orders.total".to_string()]).unwrap(),
+            r#"{"orders":[{"total":3}],"profile":{"displayName":"A"},"userId":1}"#
+        );
+        assert_eq!(
+            rename_keys(input, "snake").unwrap(),
+            r#"{"orders":[{"id":2,"total":3}],"profile":{"avatar_url":null,"display_name":"A","tags":[]},"user_id":1}"#
+        );
+        assert_eq!(strip_nulls(input, true).unwrap(), r#"{"orders":[{"id":2,"total":3}],"profile":{"displayName":"A"},"userId":1}"#);
+
+        assert!(matches!(rename_keys(input, "kebab"), Err(Error::UnknownCase(_))));
+        assert!(matches!(strip_nulls("{", false), Err(Error::Json(_))));
+    }
+}
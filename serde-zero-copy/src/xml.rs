@@ -0,0 +1,276 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use crate::Value;
+
+/// How elements, attributes and text map onto objects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XmlConvention {
+    /// Every element is an object, text goes under `"$"` and attributes under `"@"`,
+    /// `<a id="1">x</a>` is `{"a": {"@": {"id": "1"}, "$": "x"}}`.
+    BadgerFish,
+    /// Attributes sit next to child elements, text goes under `"#text"` and elements with
+    /// nothing but text collapse to a string, `<a id="1"><b>x</b></a>` is `{"a": {"id": "1", "b": "x"}}`.
+    /// An attribute named like a child element is an [`Error::Collision`].
+    Compact,
+    /// Like `Compact` with attributes under their name prefixed with `@`, so that they can't
+    /// collide with elements, `<a id="1"><id>2</id></a>` is `{"a": {"@id": "1", "id": "2"}}`.
+    Prefixed,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Xml(quick_xml::Error),
+    /// A name or text that couldn't be borrowed from the input as utf-8.
+    Utf8,
+    NoRoot,
+    MultipleRoots,
+    /// An attribute and a child element of the same name, of `element`, under `Compact`.
+    Collision { element: String, name: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Xml(err) => err.fmt(f),
+            Error::Utf8 => f.write_str("invalid utf-8 in xml document"),
+            Error::NoRoot => f.write_str("xml document has no root element"),
+            Error::MultipleRoots => f.write_str("xml document has more than one root element"),
+            Error::Collision { element, name } => write!(f, "<{}> has both an attribute and an element named {:?}", element, name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<quick_xml::Error> for Error {
+    fn from(err: quick_xml::Error) -> Self {
+        Error::Xml(err)
+    }
+}
+
+// The reader hands out names and attributes borrowed from the event rather than the input,
+// but for a `&str` source they are slices of the input, which gives them back their lifetime.
+fn reborrow<'a>(input: &'a str, part: &[u8]) -> Result<&'a str, Error> {
+    let start = (part.as_ptr() as usize).wrapping_sub(input.as_ptr() as usize);
+    input.get(start..start + part.len()).ok_or(Error::Utf8)
+}
+
+fn text<'a>(text: Cow<'a, str>) -> Value<'a> {
+    match text {
+        Cow::Borrowed(s) => Value::Str(s),
+        Cow::Owned(s) => Value::String(s),
+    }
+}
+
+struct Element<'a> {
+    name: &'a str,
+    attributes: BTreeMap<Cow<'a, str>, Value<'a>>,
+    children: BTreeMap<Cow<'a, str>, Value<'a>>,
+    text: Option<Value<'a>>,
+}
+
+impl<'a> Element<'a> {
+    fn start(input: &'a str, start: &BytesStart) -> Result<Element<'a>, Error> {
+        let mut attributes = BTreeMap::new();
+        for attribute in start.attributes() {
+            let attribute = attribute.map_err(quick_xml::Error::from)?;
+            let key = reborrow(input, attribute.key.as_ref())?;
+            let value = match attribute.unescape_value()? {
+                Cow::Borrowed(_) => Value::Str(reborrow(input, &attribute.value)?),
+                Cow::Owned(s) => Value::String(s),
+            };
+            attributes.insert(Cow::Borrowed(key), value);
+        }
+        Ok(Element {
+            name: reborrow(input, start.name().as_ref())?,
+            attributes,
+            children: BTreeMap::new(),
+            text: None,
+        })
+    }
+
+    fn push_text(&mut self, value: Value<'a>) {
+        self.text = Some(match self.text.take() {
+            None => value,
+            // mixed content, `<a>x<b/>y</a>`, concatenates the text pieces
+            Some(existing) => Value::String(format!("{}{}", as_str(&existing), as_str(&value))),
+        });
+    }
+
+    fn push_child(&mut self, name: &'a str, value: Value<'a>) {
+        match self.children.get_mut(name) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => {
+                let first = std::mem::replace(existing, Value::Null);
+                *existing = Value::Array(vec![first, value]);
+            }
+            None => {
+                self.children.insert(Cow::Borrowed(name), value);
+            }
+        }
+    }
+
+    fn finish(self, convention: XmlConvention) -> Result<Value<'a>, Error> {
+        let mut map = self.children;
+        if convention == XmlConvention::BadgerFish {
+            if !self.attributes.is_empty() {
+                map.insert(Cow::Borrowed("@"), Value::Object(self.attributes));
+            }
+            if let Some(text) = self.text {
+                map.insert(Cow::Borrowed("$"), text);
+            }
+            return Ok(Value::Object(map));
+        }
+        if map.is_empty() && self.attributes.is_empty() {
+            return Ok(self.text.unwrap_or(Value::Null));
+        }
+        for (name, value) in self.attributes {
+            let key = match convention {
+                XmlConvention::Prefixed => Cow::Owned(format!("@{}", name)),
+                _ => name,
+            };
+            if map.contains_key(&key) {
+                return Err(Error::Collision { element: self.name.to_string(), name: key.into_owned() });
+            }
+            map.insert(key, value);
+        }
+        if let Some(text) = self.text {
+            map.insert(Cow::Borrowed("#text"), text);
+        }
+        Ok(Value::Object(map))
+    }
+}
+
+fn as_str<'v>(value: &'v Value) -> &'v str {
+    match value {
+        Value::Str(s) => s,
+        Value::String(s) => s,
+        _ => "",
+    }
+}
+
+/// Converts an xml document into `{"root": ...}`. Element and attribute names always borrow
+/// from the input, text and attribute values do unless they contain entity references.
+pub fn from_xml_str(input: &str, convention: XmlConvention) -> Result<Value<'_>, Error> {
+    let mut reader = Reader::from_str(input);
+    reader.config_mut().trim_text(true);
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root: Option<(&str, Value)> = None;
+
+    loop {
+        let finished = match reader.read_event()? {
+            Event::Start(start) => {
+                stack.push(Element::start(input, &start)?);
+                None
+            }
+            Event::Empty(start) => Some(Element::start(input, &start)?),
+            Event::End(_) => stack.pop(),
+            Event::Text(t) => {
+                if let Some(element) = stack.last_mut() {
+                    element.push_text(text(t.unescape()?));
+                }
+                None
+            }
+            Event::CData(cdata) => {
+                if let Some(element) = stack.last_mut() {
+                    let raw = match cdata.into_inner() {
+                        Cow::Borrowed(raw) => Value::Str(std::str::from_utf8(raw).map_err(|_| Error::Utf8)?),
+                        Cow::Owned(raw) => Value::String(String::from_utf8(raw).map_err(|_| Error::Utf8)?),
+                    };
+                    element.push_text(raw);
+                }
+                None
+            }
+            Event::Eof => break,
+            _ => None,
+        };
+        if let Some(element) = finished {
+            let name = element.name;
+            let value = element.finish(convention)?;
+            match stack.last_mut() {
+                Some(parent) => parent.push_child(name, value),
+                None if root.is_some() => return Err(Error::MultipleRoots),
+                None => root = Some((name, value)),
+            }
+        }
+    }
+
+    let (name, value) = root.ok_or(Error::NoRoot)?;
+    let mut map = BTreeMap::new();
+    map.insert(Cow::Borrowed(name), value);
+    Ok(Value::Object(map))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{from_xml_str, Error, XmlConvention};
+
+    const SOAP: &str = r#"<?xml version="1.0"?>
+        <order id="42" status="open">
+            <item sku="A1">Widget</item>
+            <item sku="B2">Gadget &amp; co</item>
+            <note><![CDATA[<fragile>]]></note>
+            <empty/>
+        </order>"#;
+
+    #[test]
+    fn xml_badgerfish() {
+        let value = from_xml_str(SOAP, XmlConvention::BadgerFish).unwrap();
+        assert_eq!(
+            serde_json_nostr::to_string(&value).unwrap(),
+            r#"{"order":{"@":{"id":"42","status":"open"},"empty":{},"item":[{"$":"Widget","@":{"sku":"A1"}},{"$":"Gadget & co","@":{"sku":"B2"}}],"note":{"$":"<fragile>"}}}"#
+        );
+    }
+
+    #[test]
+    fn xml_compact_borrows_text() {
+        let value = from_xml_str(SOAP, XmlConvention::Compact).unwrap();
+        assert_eq!(
+            serde_json_nostr::to_string(&value).unwrap(),
+            r##"{"order":{"empty":null,"id":"42","item":[{"#text":"Widget","sku":"A1"},{"#text":"Gadget & co","sku":"B2"}],"note":"<fragile>","status":"open"}}"##
+        );
+        let order = match &value {
+            Value::Object(map) => &map["order"],
+            _ => panic!(),
+        };
+        let source = SOAP.as_bytes().as_ptr_range();
+        match order {
+            Value::Object(order) => match (&order["id"], &order["item"]) {
+                (Value::Str(id), Value::Array(items)) => {
+                    assert!(source.contains(&id.as_ptr()));
+                    match &items[1] {
+                        Value::Object(item) => assert!(item["#text"].identical(&Value::String("Gadget & co".to_string()))),
+                        _ => panic!(),
+                    }
+                }
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn xml_attributes_named_like_elements() {
+        let order = r#"<order id="42"><id>A-42</id></order>"#;
+        let collision = from_xml_str(order, XmlConvention::Compact);
+        assert!(matches!(collision, Err(Error::Collision { element, name }) if element == "order" && name == "id"));
+        let value = from_xml_str(order, XmlConvention::Prefixed).unwrap();
+        assert_eq!(serde_json_nostr::to_string(&value).unwrap(), r#"{"order":{"@id":"42","id":"A-42"}}"#);
+        let value = from_xml_str(SOAP, XmlConvention::Prefixed).unwrap();
+        assert_eq!(
+            serde_json_nostr::to_string(&value).unwrap(),
+            r##"{"order":{"@id":"42","@status":"open","empty":null,"item":[{"#text":"Widget","@sku":"A1"},{"#text":"Gadget & co","@sku":"B2"}],"note":"<fragile>"}}"##
+        );
+    }
+
+    #[test]
+    fn xml_errors() {
+        assert!(matches!(from_xml_str("<a></b>", XmlConvention::Compact), Err(Error::Xml(_))));
+        assert!(matches!(from_xml_str("<a/><b/>", XmlConvention::Compact), Err(Error::MultipleRoots)));
+        assert!(matches!(from_xml_str("<!-- nothing -->", XmlConvention::Compact), Err(Error::NoRoot)));
+    }
+}
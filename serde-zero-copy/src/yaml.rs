@@ -0,0 +1,289 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use serde_json::Number;
+use yaml_rust2::parser::{MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::{Marker, TScalarStyle};
+use yaml_rust2::{Event, ScanError};
+use crate::Value;
+
+#[derive(Debug)]
+pub enum Error {
+    Yaml(ScanError),
+    /// Only string keys are supported, e.g. `[1, 2]: x` isn't.
+    NonScalarKey,
+    /// A `<<` merge key of something other than a mapping or a sequence of them.
+    Merge,
+    NoDocument,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Yaml(err) => err.fmt(f),
+            Error::NonScalarKey => f.write_str("only scalar keys are supported"),
+            Error::Merge => f.write_str("only mappings can be merged with <<"),
+            Error::NoDocument => f.write_str("yaml stream contains no document"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+enum Key<'a> {
+    Named(Cow<'a, str>),
+    // a plain `<<`, whose mappings go into the one it's in
+    Merge,
+}
+
+enum Frame<'a> {
+    Seq(Vec<Value<'a>>, usize),
+    Map(BTreeMap<Cow<'a, str>, Value<'a>>, Option<Key<'a>>, usize),
+}
+
+// keys the mapping has of its own win, as do those of mappings merged before
+fn merge<'a>(map: &mut BTreeMap<Cow<'a, str>, Value<'a>>, merged: Value<'a>) -> Result<(), Error> {
+    match merged {
+        Value::Object(merged) => {
+            for (key, value) in merged {
+                map.entry(key).or_insert(value);
+            }
+            Ok(())
+        }
+        Value::Array(merged) => merged.into_iter().try_for_each(|merged| match merged {
+            Value::Object(_) => merge(map, merged),
+            _ => Err(Error::Merge),
+        }),
+        _ => Err(Error::Merge),
+    }
+}
+
+struct Builder<'a> {
+    input: &'a str,
+    // the parser marks positions in chars, this caches the last (char, byte) offset pair
+    cursor: (usize, usize),
+    stack: Vec<Frame<'a>>,
+    anchors: HashMap<usize, Value<'a>>,
+    root: Option<Value<'a>>,
+    error: Option<Error>,
+}
+
+impl<'a> Builder<'a> {
+    fn byte_offset(&mut self, char_index: usize) -> usize {
+        if char_index < self.cursor.0 {
+            self.cursor = (0, 0);
+        }
+        let (chars, bytes) = self.cursor;
+        let offset = self.input[bytes..]
+            .char_indices()
+            .nth(char_index - chars)
+            .map_or(self.input.len(), |(i, _)| bytes + i);
+        self.cursor = (char_index, offset);
+        offset
+    }
+
+    // The scalar's text as a slice of the input, if it appears there verbatim.
+    fn borrow(&mut self, value: &str, style: TScalarStyle, mark: Marker) -> Option<&'a str> {
+        let start = self.byte_offset(mark.index());
+        let start = match style {
+            TScalarStyle::Plain => start,
+            TScalarStyle::SingleQuoted | TScalarStyle::DoubleQuoted => start + 1,
+            TScalarStyle::Literal | TScalarStyle::Folded => return None,
+        };
+        let candidate = self.input.get(start..start + value.len())?;
+        let closing = self.input.as_bytes().get(start + value.len()).copied();
+        let verbatim = candidate == value && match style {
+            TScalarStyle::SingleQuoted => closing == Some(b'\''),
+            TScalarStyle::DoubleQuoted => closing == Some(b'"'),
+            _ => true,
+        };
+        verbatim.then_some(candidate)
+    }
+
+    fn scalar(&mut self, value: String, style: TScalarStyle, mark: Marker) -> Value<'a> {
+        if let TScalarStyle::Plain = style {
+            match value.as_str() {
+                "" | "~" | "null" | "Null" | "NULL" => return Value::Null,
+                "true" | "True" | "TRUE" => return Value::Bool(true),
+                "false" | "False" | "FALSE" => return Value::Bool(false),
+                _ => {}
+            }
+            if let Ok(n) = value.parse::<i64>() {
+                return Value::Number(n.into());
+            }
+            if let Ok(n) = value.parse::<u64>() {
+                return Value::Number(n.into());
+            }
+            let looks_numeric = value.bytes().all(|b| b.is_ascii_digit() || matches!(b, b'-' | b'+' | b'.' | b'e' | b'E'));
+            if let Some(n) = value.parse::<f64>().ok().filter(|_| looks_numeric).and_then(Number::from_f64) {
+                return Value::Number(n);
+            }
+        }
+        match self.borrow(&value, style, mark) {
+            Some(s) => Value::Str(s),
+            None => Value::String(value),
+        }
+    }
+
+    fn push(&mut self, value: Value<'a>, anchor: usize) {
+        if anchor != 0 {
+            self.anchors.insert(anchor, value.clone());
+        }
+        match self.stack.last_mut() {
+            Some(Frame::Seq(vec, _)) => vec.push(value),
+            Some(Frame::Map(map, key, _)) => match key.take() {
+                Some(Key::Named(key)) => {
+                    map.insert(key, value);
+                }
+                Some(Key::Merge) => {
+                    if let Err(err) = merge(map, value) {
+                        self.error = Some(err);
+                    }
+                }
+                None => self.error = Some(Error::NonScalarKey),
+            },
+            None => {
+                if self.root.is_none() {
+                    self.root = Some(value);
+                }
+            }
+        }
+    }
+
+    fn key(&mut self, key: Key<'a>) {
+        if let Some(Frame::Map(_, pending, _)) = self.stack.last_mut() {
+            *pending = Some(key);
+        }
+    }
+
+    fn expects_key(&self) -> bool {
+        matches!(self.stack.last(), Some(Frame::Map(_, None, _)))
+    }
+}
+
+impl<'a> MarkedEventReceiver for Builder<'a> {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        if self.error.is_some() {
+            return;
+        }
+        match event {
+            Event::Scalar(value, TScalarStyle::Plain, 0, _) if value == "<<" && self.expects_key() => self.key(Key::Merge),
+            Event::Scalar(value, style, anchor, _) if self.expects_key() => {
+                let key = match self.borrow(&value, style, mark) {
+                    Some(key) => Cow::Borrowed(key),
+                    None => Cow::Owned(value),
+                };
+                if anchor != 0 {
+                    let key = match &key {
+                        Cow::Borrowed(key) => Value::Str(key),
+                        Cow::Owned(key) => Value::String(key.clone()),
+                    };
+                    self.anchors.insert(anchor, key);
+                }
+                self.key(Key::Named(key));
+            }
+            Event::Scalar(value, style, anchor, _) => {
+                let value = self.scalar(value, style, mark);
+                self.push(value, anchor);
+            }
+            Event::Alias(anchor) => {
+                let value = self.anchors.get(&anchor).cloned().unwrap_or(Value::Null);
+                match (self.expects_key(), value) {
+                    (true, Value::Str(key)) => self.key(Key::Named(Cow::Borrowed(key))),
+                    (true, Value::String(key)) => self.key(Key::Named(Cow::Owned(key))),
+                    (true, _) => self.error = Some(Error::NonScalarKey),
+                    (false, value) => self.push(value, 0),
+                }
+            }
+            Event::SequenceStart(..) | Event::MappingStart(..) if self.expects_key() => {
+                self.error = Some(Error::NonScalarKey);
+            }
+            Event::SequenceStart(anchor, _) => self.stack.push(Frame::Seq(Vec::new(), anchor)),
+            Event::MappingStart(anchor, _) => self.stack.push(Frame::Map(BTreeMap::new(), None, anchor)),
+            Event::SequenceEnd | Event::MappingEnd => match self.stack.pop() {
+                Some(Frame::Seq(vec, anchor)) => self.push(Value::Array(vec), anchor),
+                Some(Frame::Map(map, _, anchor)) => self.push(Value::Object(map), anchor),
+                None => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Parses the first document of a YAML stream. Keys and scalars borrow from the input when
+/// they appear there verbatim, i.e. plain or quoted without escapes. Plain scalars resolve
+/// with the core schema, so `on` or `yes` stay strings. A plain `<<` key merges the mappings
+/// it's given, a quoted one is a key like any other.
+pub fn from_yaml_str(input: &str) -> Result<Value<'_>, Error> {
+    let mut builder = Builder {
+        input,
+        cursor: (0, 0),
+        stack: Vec::new(),
+        anchors: HashMap::new(),
+        root: None,
+        error: None,
+    };
+    Parser::new_from_str(input).load(&mut builder, false).map_err(Error::Yaml)?;
+    match (builder.error, builder.root) {
+        (Some(err), _) => Err(err),
+        (None, Some(root)) => Ok(root),
+        (None, None) => Err(Error::NoDocument),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use super::{from_yaml_str, Error};
+
+    #[test]
+    fn yaml_borrows_scalars() {
+        let input = "\
+name: prøxy
+port: 2000
+ratio: 0.5
+enabled: yes
+nothing: ~
+quoted: 'single'
+escaped: \"tab\\there\"
+defaults: &defaults
+  timeout: 30
+upstreams:
+  - host: a
+    <<: *defaults
+  - host: b
+    timeout: 5
+    <<: *defaults
+  - c
+note: |
+  literal
+";
+        let value = from_yaml_str(input).unwrap();
+        assert_eq!(
+            serde_json_nostr::to_string(&value).unwrap(),
+            r#"{"defaults":{"timeout":30},"enabled":"yes","escaped":"tab\there","name":"prøxy","note":"literal\n","nothing":null,"port":2000,"quoted":"single","ratio":0.5,"upstreams":[{"host":"a","timeout":30},{"host":"b","timeout":5},"c"]}"#
+        );
+        let source = input.as_bytes().as_ptr_range();
+        match &value {
+            Value::Object(map) => {
+                assert!(map.keys().all(|k| source.contains(&k.as_ptr())));
+                assert!(matches!(map["name"], Value::Str(s) if source.contains(&s.as_ptr())));
+                assert!(matches!(map["quoted"], Value::Str(s) if source.contains(&s.as_ptr())));
+                assert!(map["escaped"].identical(&Value::String("tab\there".to_string())));
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn yaml_errors() {
+        assert!(matches!(from_yaml_str("a: [1"), Err(Error::Yaml(_))));
+        assert!(matches!(from_yaml_str("? [1, 2]\n: x"), Err(Error::NonScalarKey)));
+        let escaped = from_yaml_str("&k \"a\\tb\": 1\nb: *k").unwrap();
+        assert_eq!(serde_json_nostr::to_string(&escaped).unwrap(), r#"{"a\tb":1,"b":"a\tb"}"#);
+        assert!(matches!(from_yaml_str(""), Err(Error::NoDocument)));
+        assert!(matches!(from_yaml_str("a: &a 1\nb:\n  <<: *a"), Err(Error::Merge)));
+        let merged = from_yaml_str("a: &a {x: 1, y: 1}\nb: &b {y: 2, z: 2}\nc:\n  <<: [*a, *b]\n'<<': quoted").unwrap();
+        assert_eq!(serde_json_nostr::to_string(&merged).unwrap(), r#"{"<<":"quoted","a":{"x":1,"y":1},"b":{"y":2,"z":2},"c":{"x":1,"y":1,"z":2}}"#);
+    }
+}
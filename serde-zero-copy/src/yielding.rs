@@ -0,0 +1,330 @@
+use std::borrow::Cow;
+use std::collections::{btree_map, BTreeMap};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use serde::de::DeserializeSeed;
+use yoke_derive::Yokeable;
+use crate::Value;
+
+#[derive(Debug)]
+pub enum Error {
+    Syntax { offset: usize, expected: &'static str },
+    /// A scalar starting at `offset` didn't parse.
+    Json { offset: usize, error: serde_json_nostr::Error },
+    TrailingCharacters { offset: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Syntax { offset, expected } => write!(f, "expected {} at {}", expected, offset),
+            Error::Json { offset, error } => write!(f, "at {}: {}", offset, error),
+            Error::TrailingCharacters { offset } => write!(f, "trailing characters at {}", offset),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Gives other tasks a turn, whichever executor this runs on.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+enum Frame<'a> {
+    Array(Vec<Value<'a>>),
+    /// Members so far and the key of the one being parsed.
+    Object(BTreeMap<Cow<'a, str>, Value<'a>>, Cow<'a, str>),
+}
+
+// borrowed unless it has escapes, like the keys of a `Value`
+struct Key<'a>(Cow<'a, str>);
+
+impl<'de> serde::Deserialize<'de> for Key<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        crate::KeySeed.deserialize(deserializer).map(Key)
+    }
+}
+
+/// A parse that can be stopped and resumed between values, producing the same [`Value`] as
+/// `serde_json_nostr::from_slice`. Containers are walked here, scalars and keys are handed to
+/// `serde_json_nostr` one by one. Yokeable, so a parse borrowing a cart can be stepped from
+/// async code holding the yoke.
+#[derive(Yokeable)]
+pub struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    stack: Vec<Frame<'a>>,
+    done: Option<Result<Value<'a>, Error>>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Parser { input, pos: 0, stack: Vec::new(), done: None }
+    }
+
+    /// Parses up to `nodes` more values, true once the document is complete or failed.
+    pub fn step(&mut self, nodes: usize) -> bool {
+        for _ in 0..nodes.max(1) {
+            if self.done.is_some() {
+                break;
+            }
+            match self.node() {
+                Ok(Some(value)) => self.done = Some(Ok(value)),
+                Ok(None) => {}
+                Err(err) => self.done = Some(Err(err)),
+            }
+        }
+        self.done.is_some()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done.is_some()
+    }
+
+    /// The parsed document, stepping through whatever is left.
+    pub fn finish(mut self) -> Result<Value<'a>, Error> {
+        while !self.step(usize::MAX) {}
+        self.done.take().unwrap()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.input.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.input.get(self.pos) == Some(&byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn syntax(&self, expected: &'static str) -> Error {
+        Error::Syntax { offset: self.pos, expected }
+    }
+
+    fn scalar<T: serde::Deserialize<'a>>(&mut self) -> Result<T, Error> {
+        let offset = self.pos;
+        let mut stream = serde_json_nostr::Deserializer::from_slice(&self.input[offset..]).into_iter::<T>();
+        match stream.next() {
+            Some(Ok(value)) => {
+                self.pos += stream.byte_offset();
+                Ok(value)
+            }
+            Some(Err(error)) => Err(Error::Json { offset, error }),
+            None => Err(self.syntax("a value")),
+        }
+    }
+
+    // `"key":`
+    fn key(&mut self) -> Result<Cow<'a, str>, Error> {
+        self.skip_whitespace();
+        if self.input.get(self.pos) != Some(&b'"') {
+            return Err(self.syntax("a key"));
+        }
+        let Key(key) = self.scalar()?;
+        if !self.eat(b':') {
+            return Err(self.syntax("':'"));
+        }
+        Ok(key)
+    }
+
+    // one scalar or empty container, or the opening of a container, closing every container
+    // it completes; the root once there's nothing left to close
+    fn node(&mut self) -> Result<Option<Value<'a>>, Error> {
+        let mut value = if self.eat(b'[') {
+            if !self.eat(b']') {
+                self.stack.push(Frame::Array(Vec::new()));
+                return Ok(None);
+            }
+            Value::Array(Vec::new())
+        } else if self.eat(b'{') {
+            if !self.eat(b'}') {
+                let key = self.key()?;
+                self.stack.push(Frame::Object(BTreeMap::new(), key));
+                return Ok(None);
+            }
+            Value::Object(BTreeMap::new())
+        } else {
+            self.scalar::<Value<'a>>()?
+        };
+        loop {
+            match self.stack.last_mut() {
+                None => {
+                    self.skip_whitespace();
+                    if self.pos < self.input.len() {
+                        return Err(Error::TrailingCharacters { offset: self.pos });
+                    }
+                    return Ok(Some(value));
+                }
+                Some(Frame::Array(vec)) => vec.push(value),
+                Some(Frame::Object(map, key)) => {
+                    map.insert(std::mem::take(key), value);
+                }
+            }
+            let array = matches!(self.stack.last(), Some(Frame::Array(_)));
+            if self.eat(b',') {
+                if !array {
+                    let next = self.key()?;
+                    if let Some(Frame::Object(_, key)) = self.stack.last_mut() {
+                        *key = next;
+                    }
+                }
+                return Ok(None);
+            }
+            if !self.eat(if array { b']' } else { b'}' }) {
+                return Err(self.syntax(if array { "',' or ']'" } else { "',' or '}'" }));
+            }
+            value = match self.stack.pop() {
+                Some(Frame::Array(vec)) => Value::Array(vec),
+                Some(Frame::Object(map, _)) => Value::Object(map),
+                None => unreachable!(),
+            };
+        }
+    }
+}
+
+/// Parses `input`, yielding to other tasks after every `every` values so a multi-megabyte
+/// document doesn't hold up the thread it's parsed on.
+pub async fn parse_yielding(input: &[u8], every: usize) -> Result<Value<'_>, Error> {
+    let mut parser = Parser::new(input);
+    while !parser.step(every) {
+        YieldNow(false).await;
+    }
+    parser.finish()
+}
+
+enum Level<'v, 'a> {
+    Array(std::slice::Iter<'v, Value<'a>>, bool),
+    Object(btree_map::Iter<'v, Cow<'a, str>, Value<'a>>, bool),
+}
+
+/// Serializes like `serde_json_nostr::to_writer`, yielding to other tasks after every
+/// `every` values.
+pub async fn serialize_yielding<W: io::Write>(mut writer: W, value: &Value<'_>, every: usize) -> Result<(), serde_json_nostr::Error> {
+    fn open<'v, 'a, W: io::Write>(writer: &mut W, value: &'v Value<'a>, stack: &mut Vec<Level<'v, 'a>>) -> Result<(), serde_json_nostr::Error> {
+        match value {
+            Value::Array(vec) => {
+                writer.write_all(b"[").map_err(serde_json_nostr::Error::io)?;
+                stack.push(Level::Array(vec.iter(), true));
+            }
+            Value::Object(map) => {
+                writer.write_all(b"{").map_err(serde_json_nostr::Error::io)?;
+                stack.push(Level::Object(map.iter(), true));
+            }
+            scalar => serde_json_nostr::to_writer(&mut *writer, scalar)?,
+        }
+        Ok(())
+    }
+
+    let mut stack = Vec::new();
+    open(&mut writer, value, &mut stack)?;
+    let mut nodes = 0usize;
+    while let Some(level) = stack.last_mut() {
+        let (next, first, close) = match level {
+            Level::Array(iter, first) => (iter.next().map(|v| (None, v)), first, b"]"),
+            Level::Object(iter, first) => (iter.next().map(|(k, v)| (Some(k.as_ref()), v)), first, b"}"),
+        };
+        match next {
+            Some((key, value)) => {
+                if !std::mem::replace(first, false) {
+                    writer.write_all(b",").map_err(serde_json_nostr::Error::io)?;
+                }
+                if let Some(key) = key {
+                    serde_json_nostr::to_writer(&mut writer, key)?;
+                    writer.write_all(b":").map_err(serde_json_nostr::Error::io)?;
+                }
+                open(&mut writer, value, &mut stack)?;
+            }
+            None => {
+                writer.write_all(close).map_err(serde_json_nostr::Error::io)?;
+                stack.pop();
+            }
+        }
+        nodes += 1;
+        if nodes.is_multiple_of(every.max(1)) {
+            YieldNow(false).await;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use crate::Value;
+    use super::{parse_yielding, serialize_yielding, Error, Parser};
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    // drives `future` to completion, counting how often it yielded
+    fn run<T>(future: impl Future<Output = T>) -> (T, usize) {
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = pin!(future);
+        let mut yields = 0;
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(value) => return (value, yields),
+                Poll::Pending => yields += 1,
+            }
+        }
+    }
+
+    const DOCUMENT: &str = r#" {"items":[{"id":1,"name":"a\nb","tags":[]},{"id":2.5,"name":"c","meta":{}}],"next":null,"ok":true} "#;
+
+    #[test]
+    fn parse_yielding_matches_from_slice() {
+        let expected: Value = serde_json_nostr::from_str(DOCUMENT).unwrap();
+        let (parsed, yields) = run(parse_yielding(DOCUMENT.as_bytes(), 3));
+        assert_eq!(parsed.unwrap(), expected);
+        assert!(yields > 2);
+        assert_eq!(Parser::new(b"[1,[2,[]],{}]").finish().unwrap(), serde_json_nostr::from_str::<Value>("[1,[2,[]],{}]").unwrap());
+
+        for (input, offset) in [("[1 2]", 3), (r#"{"a" 1}"#, 5), ("[1,]", 3), ("{1:2}", 1)] {
+            match Parser::new(input.as_bytes()).finish() {
+                Err(Error::Syntax { offset: at, .. }) | Err(Error::Json { offset: at, .. }) => assert_eq!(at, offset, "{}", input),
+                other => panic!("{}: {:?}", input, other.map(|_| ())),
+            }
+        }
+        assert!(matches!(Parser::new(b"[] []").finish(), Err(Error::TrailingCharacters { offset: 3 })));
+    }
+
+    #[test]
+    fn serialize_yielding_matches_to_writer() {
+        let value: Value = serde_json_nostr::from_str(DOCUMENT).unwrap();
+        let mut out = Vec::new();
+        let (result, yields) = run(serialize_yielding(&mut out, &value, 2));
+        result.unwrap();
+        assert_eq!(out, serde_json_nostr::to_vec(&value).unwrap());
+        assert!(yields > 2);
+    }
+}
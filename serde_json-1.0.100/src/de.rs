@@ -35,6 +35,8 @@ pub struct Deserializer<R> {
     single_precision: bool,
     #[cfg(feature = "unbounded_depth")]
     disable_recursion_limit: bool,
+    // asked for by `deserialize_newtype_struct(BIG_INT_TOKEN, ..)`, for the next value only
+    big_int: bool,
 }
 
 impl<'de, R> Deserializer<R>
@@ -58,6 +60,7 @@ where
             single_precision: false,
             #[cfg(feature = "unbounded_depth")]
             disable_recursion_limit: false,
+            big_int: false,
         }
     }
 }
@@ -132,6 +135,40 @@ impl ParserNumber {
     }
 }
 
+/// A visitor that's deserialized by `deserialize_newtype_struct` with this name is given an
+/// integer too wide for 64 bits in a map under this key, with its numeral borrowed from the
+/// input as the only value, and everything else as by `deserialize_any`. For any other
+/// visitor such an integer is an `f64` as in serde_json. Only without `arbitrary_precision`,
+/// which keeps every number exact.
+pub const BIG_INT_TOKEN: &str = "$serde_json_nostr::private::BigInt";
+
+#[cfg(not(feature = "arbitrary_precision"))]
+struct BigIntDeserializer<'de> {
+    numeral: Option<&'de str>,
+}
+
+#[cfg(not(feature = "arbitrary_precision"))]
+impl<'de> de::MapAccess<'de> for BigIntDeserializer<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        if self.numeral.is_none() {
+            return Ok(None);
+        }
+        seed.deserialize(de::value::BorrowedStrDeserializer::new(BIG_INT_TOKEN)).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(de::value::BorrowedStrDeserializer::new(self.numeral.take().unwrap()))
+    }
+}
+
 impl<'de, R: Read<'de>> Deserializer<R> {
     /// The `Deserializer::end` method should be called after a value has been fully deserialized.
     /// This allows the `Deserializer` to validate that the input stream is at the end or that it
@@ -854,6 +891,39 @@ impl<'de, R: Read<'de>> Deserializer<R> {
         self.parse_integer(positive)
     }
 
+    /// Like `parse_any_number(positive).visit(visitor)`, except that with `big_int` an integer
+    /// too wide for 64 bits is given as written when the input can be borrowed from, as a map
+    /// of [`BIG_INT_TOKEN`] to its numeral, rather than rounded to an `f64`.
+    #[cfg(not(feature = "arbitrary_precision"))]
+    fn visit_any_number<V>(&mut self, positive: bool, big_int: bool, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        let start = self.read.byte_offset() - !positive as usize;
+        let number = tri!(self.parse_any_number(positive));
+        if let (true, ParserNumber::F64(_)) = (big_int, &number) {
+            let numeral = self.read.borrow_range(start, self.read.byte_offset());
+            let integer = |n: &[u8]| {
+                let digits = if n.first() == Some(&b'-') { &n[1..] } else { n };
+                digits.iter().all(u8::is_ascii_digit)
+            };
+            if let Some(numeral) = numeral.filter(|&n| integer(n) && n != b"-0") {
+                // only ascii digits and a sign
+                let numeral = unsafe { core::str::from_utf8_unchecked(numeral) };
+                return visitor.visit_map(BigIntDeserializer { numeral: Some(numeral) });
+            }
+        }
+        number.visit(visitor)
+    }
+
+    #[cfg(feature = "arbitrary_precision")]
+    fn visit_any_number<V>(&mut self, positive: bool, _big_int: bool, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        tri!(self.parse_any_number(positive)).visit(visitor)
+    }
+
     #[cfg(feature = "arbitrary_precision")]
     fn parse_any_number(&mut self, positive: bool) -> Result<ParserNumber> {
         let mut buf = String::with_capacity(16);
@@ -1308,6 +1378,7 @@ impl<'de, 'a, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
     where
         V: de::Visitor<'de>,
     {
+        let big_int = core::mem::take(&mut self.big_int);
         let peek = match tri!(self.parse_whitespace()) {
             Some(b) => b,
             None => {
@@ -1333,9 +1404,9 @@ impl<'de, 'a, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
             }
             b'-' => {
                 self.eat_char();
-                tri!(self.parse_any_number(false)).visit(visitor)
+                self.visit_any_number(false, big_int, visitor)
             }
-            b'0'..=b'9' => tri!(self.parse_any_number(true)).visit(visitor),
+            b'0'..=b'9' => self.visit_any_number(true, big_int, visitor),
             b'"' => {
                 self.eat_char();
                 self.scratch.clear();
@@ -1709,7 +1780,11 @@ impl<'de, 'a, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
             }
         }
 
-        let _ = name;
+        if name == BIG_INT_TOKEN {
+            self.big_int = true;
+            return self.deserialize_any(visitor);
+        }
+
         visitor.visit_newtype_struct(self)
     }
 
@@ -58,6 +58,13 @@ pub trait Read<'de>: private::Sealed {
     #[doc(hidden)]
     fn byte_offset(&self) -> usize;
 
+    /// The input between two byte offsets, if it outlives the deserializer.
+    #[doc(hidden)]
+    fn borrow_range(&self, start: usize, end: usize) -> Option<&'de [u8]> {
+        let _ = (start, end);
+        None
+    }
+
     /// Assumes the previous byte was a quotation mark. Parses a JSON-escaped
     /// string until the next quotation mark using the given scratch space if
     /// necessary. The scratch space is initially empty.
@@ -528,6 +535,10 @@ impl<'a> Read<'a> for SliceRead<'a> {
         self.index
     }
 
+    fn borrow_range(&self, start: usize, end: usize) -> Option<&'a [u8]> {
+        self.slice.get(start..end)
+    }
+
     fn parse_str<'s>(&'s mut self, scratch: &'s mut Vec<u8>) -> Result<Reference<'a, 's, str>> {
         self.parse_str_bytes(scratch, true, as_str)
     }
@@ -655,6 +666,10 @@ impl<'a> Read<'a> for StrRead<'a> {
         self.delegate.byte_offset()
     }
 
+    fn borrow_range(&self, start: usize, end: usize) -> Option<&'a [u8]> {
+        self.delegate.borrow_range(start, end)
+    }
+
     fn parse_str<'s>(&'s mut self, scratch: &'s mut Vec<u8>) -> Result<Reference<'a, 's, str>> {
         self.delegate.parse_str_bytes(scratch, true, |_, bytes| {
             // The deserialization input came in as &str with a UTF-8 guarantee,
@@ -736,6 +751,10 @@ where
         R::byte_offset(self)
     }
 
+    fn borrow_range(&self, start: usize, end: usize) -> Option<&'de [u8]> {
+        R::borrow_range(self, start, end)
+    }
+
     fn parse_str<'s>(&'s mut self, scratch: &'s mut Vec<u8>) -> Result<Reference<'de, 's, str>> {
         R::parse_str(self, scratch)
     }